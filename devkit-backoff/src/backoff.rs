@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use crate::{jitter::SplitMix64, Jitter};
+
+/// An exponential backoff schedule: the delays to wait between attempts.
+///
+/// The `k`-th delay is `initial * multiplier^k`, capped at `max_delay`, and
+/// randomized by the [`Jitter`] strategy. The schedule is an endless iterator, to
+/// be bounded with [`Iterator::take`], or with [`ExponentialBackoff::with_max_elapsed`].
+/// It does not sleep: see [`IntoStream`](crate::IntoStream) for a stream that does.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_backoff::{ExponentialBackoff, Jitter};
+///
+/// let delays: Vec<_> = ExponentialBackoff::new(Duration::from_millis(100))
+///     .with_jitter(Jitter::None)
+///     .take(4)
+///     .collect();
+///
+/// assert_eq!(delays, [100, 200, 400, 800].map(Duration::from_millis));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ExponentialBackoff {
+    initial: Duration,
+    multiplier: f64,
+    max_delay: Duration,
+    max_elapsed: Option<Duration>,
+    jitter: Jitter,
+    /// The delay before jitter of the next attempt.
+    next: Duration,
+    /// The previous delay with decorrelated jitter.
+    previous: Duration,
+    /// The sum of the delays returned.
+    elapsed: Duration,
+    rng: SplitMix64,
+}
+
+impl Default for ExponentialBackoff {
+    /// Starts at 100ms, doubles up to 10s, with full jitter.
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100))
+    }
+}
+
+impl ExponentialBackoff {
+    /// Creates a new `ExponentialBackoff` starting at `initial`, doubling up to 10
+    /// seconds, with full jitter.
+    pub fn new(initial: Duration) -> Self {
+        Self {
+            initial,
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(10),
+            max_elapsed: None,
+            jitter: Jitter::Full,
+            next: initial,
+            previous: initial,
+            elapsed: Duration::ZERO,
+            rng: SplitMix64::from_entropy(),
+        }
+    }
+
+    /// Multiplies the delay by `multiplier` after every attempt. Multipliers below
+    /// 1 are raised to 1.
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Caps every delay at `max_delay`.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self.next = self.next.min(max_delay);
+        self
+    }
+
+    /// Ends the schedule once the delays add up to `max_elapsed`. The last delay is
+    /// shortened to end the schedule on time.
+    pub fn with_max_elapsed(mut self, max_elapsed: Duration) -> Self {
+        self.max_elapsed = Some(max_elapsed);
+        self
+    }
+
+    /// Randomizes the delays with `jitter`.
+    pub fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Draws the jitter from a generator seeded with `seed`, making the schedule
+    /// reproducible, e.g. in tests.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = SplitMix64::new(seed);
+        self
+    }
+
+    /// Starts the schedule over, e.g. after a successful attempt.
+    pub fn reset(&mut self) {
+        self.next = self.initial.min(self.max_delay);
+        self.previous = self.initial;
+        self.elapsed = Duration::ZERO;
+    }
+}
+
+impl Iterator for ExponentialBackoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let base = self.next;
+        self.next = Duration::try_from_secs_f64(base.as_secs_f64() * self.multiplier)
+            .unwrap_or(Duration::MAX)
+            .min(self.max_delay);
+
+        let delay = match self.jitter {
+            Jitter::None => base,
+            Jitter::Full => self.rng.between(Duration::ZERO, base),
+            Jitter::Equal => base / 2 + self.rng.between(Duration::ZERO, base - base / 2),
+            Jitter::Decorrelated => {
+                let high = self.previous.saturating_mul(3).min(self.max_delay);
+                self.previous = self.rng.between(self.initial.min(high), high);
+                self.previous
+            }
+        };
+
+        let delay = match self.max_elapsed {
+            Some(max) if self.elapsed >= max => return None,
+            Some(max) => delay.min(max - self.elapsed),
+            None => delay,
+        };
+        self.elapsed = self.elapsed.saturating_add(delay);
+        Some(delay)
+    }
+}
+
+/// A backoff schedule waiting the same delay between all attempts.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_backoff::ConstantBackoff;
+///
+/// let delays: Vec<_> = ConstantBackoff::new(Duration::from_secs(1)).take(3).collect();
+///
+/// assert_eq!(delays, [Duration::from_secs(1); 3]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConstantBackoff {
+    delay: Duration,
+}
+
+impl ConstantBackoff {
+    /// Creates a new `ConstantBackoff` waiting `delay` between attempts.
+    pub fn new(delay: Duration) -> Self {
+        Self { delay }
+    }
+}
+
+impl Iterator for ConstantBackoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        Some(self.delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn exponential_backoff_should_grow_up_to_the_cap() {
+        let backoff = ExponentialBackoff::new(MS * 100)
+            .with_multiplier(3.0)
+            .with_max_delay(MS * 1000)
+            .with_jitter(Jitter::None);
+        let delays: Vec<_> = backoff.clone().take(5).collect();
+        assert_eq!(delays, [100, 300, 900, 1000, 1000].map(|ms| MS * ms));
+
+        let mut bounded = backoff.with_max_elapsed(MS * 1500);
+        let delays: Vec<_> = bounded.by_ref().collect();
+        assert_eq!(delays, [100, 300, 900, 200].map(|ms| MS * ms));
+        bounded.reset();
+        assert_eq!(bounded.next(), Some(MS * 100));
+
+        // large delays saturate instead of overflowing
+        let mut huge = ExponentialBackoff::new(Duration::MAX)
+            .with_max_delay(Duration::MAX)
+            .with_jitter(Jitter::None);
+        assert_eq!(huge.nth(3), Some(Duration::MAX));
+    }
+
+    #[test]
+    fn exponential_backoff_should_jitter_within_bounds() {
+        let backoff = ExponentialBackoff::new(MS * 100)
+            .with_max_delay(MS * 1000)
+            .with_seed(42);
+        let schedule: Vec<_> = backoff.clone().with_jitter(Jitter::None).take(8).collect();
+
+        let full: Vec<_> = backoff.clone().with_jitter(Jitter::Full).take(8).collect();
+        let equal: Vec<_> = backoff.clone().with_jitter(Jitter::Equal).take(8).collect();
+        for ((full, equal), base) in full.iter().zip(&equal).zip(&schedule) {
+            assert!(*full <= *base);
+            assert!((*base / 2..=*base).contains(equal));
+        }
+        assert_ne!(full, schedule);
+
+        let mut previous = MS * 100;
+        for delay in backoff.clone().with_jitter(Jitter::Decorrelated).take(8) {
+            assert!((MS * 100..=(previous * 3).min(MS * 1000)).contains(&delay));
+            previous = delay;
+        }
+
+        // seeded schedules are reproducible
+        let again: Vec<_> = backoff.with_jitter(Jitter::Full).take(8).collect();
+        assert_eq!(again, full);
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+/// How a backoff randomizes its delays, so that clients failing together do not
+/// retry together.
+///
+/// The strategies are those compared in "Exponential Backoff And Jitter" on the AWS
+/// Architecture Blog.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Jitter {
+    /// The delays are used as they are.
+    None,
+    /// A random delay between zero and the delay.
+    #[default]
+    Full,
+    /// Half the delay, plus a random delay between zero and the other half.
+    Equal,
+    /// A random delay between the initial delay and three times the previous
+    /// random delay, capped at the maximum delay. The delays do not follow the
+    /// exponential schedule, but grow on average at the same pace.
+    Decorrelated,
+}
+
+/// A small, seedable pseudo-random generator, enough for jitter.
+#[derive(Debug, Clone)]
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Creates a generator seeded differently on every call.
+    pub(crate) fn from_entropy() -> Self {
+        use std::hash::BuildHasher;
+
+        // every `RandomState` is seeded differently
+        Self(std::collections::hash_map::RandomState::new().hash_one(0u64))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random duration between `low` and `high`, both included.
+    pub(crate) fn between(&mut self, low: Duration, high: Duration) -> Duration {
+        if high <= low {
+            return low;
+        }
+        let span = u64::try_from((high - low).as_nanos()).unwrap_or(u64::MAX);
+        let offset = match span.checked_add(1) {
+            Some(modulus) => self.next() % modulus,
+            None => self.next(),
+        };
+        low.saturating_add(Duration::from_nanos(offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_mix_should_stay_in_bounds() {
+        let mut rng = SplitMix64::new(7);
+        let (low, high) = (Duration::from_millis(10), Duration::from_millis(20));
+        for _ in 0..1000 {
+            assert!((low..=high).contains(&rng.between(low, high)));
+        }
+        assert_eq!(rng.between(high, low), high);
+        assert!(rng.between(Duration::ZERO, Duration::MAX) <= Duration::MAX);
+    }
+}
@@ -0,0 +1,15 @@
+mod backoff;
+mod jitter;
+#[cfg(feature = "tower")]
+mod policy;
+mod retry;
+#[cfg(feature = "tokio")]
+mod stream;
+
+pub use backoff::{ConstantBackoff, ExponentialBackoff};
+pub use jitter::Jitter;
+#[cfg(feature = "tower")]
+pub use policy::RetryPolicy;
+pub use retry::{GiveUp, Retry, RetryError};
+#[cfg(feature = "tokio")]
+pub use stream::{BackoffStream, IntoStream};
@@ -0,0 +1,165 @@
+use std::{fmt, time::Duration};
+
+use tokio::time::{sleep, Sleep};
+use tower::retry::Policy;
+
+use crate::{retry::Attempts, Retry};
+
+/// A [`Retry`] as a tower retry [`Policy`], to retry the calls of a tower
+/// service, e.g. a hyper or tonic client, with a [`tower::retry::RetryLayer`].
+///
+/// The policy retries the results for which `retryable` returns `true`, waiting
+/// the delays of the schedule of the `Retry`, within its maximum attempts, retry
+/// budget and rate limiter. Tower makes the first attempt before consulting the
+/// policy, so the rate limiter only gates the retries, which it is asked for
+/// before waiting their delay. Every call deposits into the budget once.
+///
+/// Requests are cloned for every attempt, so they have to implement [`Clone`].
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_backoff::{ConstantBackoff, Retry, RetryPolicy};
+/// use tower::{retry::RetryLayer, service_fn, Service, ServiceBuilder, ServiceExt};
+///
+/// # #[tokio::main(flavor = "current_thread", start_paused = true)]
+/// # async fn main() {
+/// let retry = Retry::new(ConstantBackoff::new(Duration::from_millis(100))).with_max_attempts(3);
+/// let policy = RetryPolicy::new(retry, |result: &Result<u32, &str>| result.is_err());
+///
+/// let mut calls = 0;
+/// let mut client = ServiceBuilder::new()
+///     .layer(RetryLayer::new(policy))
+///     .service(service_fn(move |request: u32| {
+///         calls += 1;
+///         let result = if calls < 3 { Err("unavailable") } else { Ok(request * 2) };
+///         async move { result }
+///     }));
+///
+/// assert_eq!(client.ready().await.unwrap().call(21).await, Ok(42));
+/// # }
+/// ```
+pub struct RetryPolicy<B, F> {
+    retry: Retry<B>,
+    retryable: F,
+    /// The attempts of the current call, once the first one has returned.
+    attempts: Option<Attempts<B>>,
+}
+
+impl<B: Iterator<Item = Duration> + Clone, F> RetryPolicy<B, F> {
+    /// Creates a new `RetryPolicy` retrying the results of the calls for which
+    /// `retryable` returns `true`, as `retry` would.
+    pub fn new(retry: Retry<B>, retryable: F) -> Self {
+        Self {
+            retry,
+            retryable,
+            attempts: None,
+        }
+    }
+}
+
+impl<B, F, Req, Res, E> Policy<Req, Res, E> for RetryPolicy<B, F>
+where
+    B: Iterator<Item = Duration> + Clone,
+    F: Fn(&Result<Res, E>) -> bool,
+    Req: Clone,
+{
+    type Future = Sleep;
+
+    fn retry(&mut self, _req: &mut Req, result: &mut Result<Res, E>) -> Option<Sleep> {
+        let attempts = self.attempts.get_or_insert_with(|| {
+            let mut attempts = Attempts::new(&self.retry);
+            attempts.started_unchecked();
+            attempts
+        });
+        let delay = attempts
+            .next_delay(&self.retry, (self.retryable)(result))
+            .ok()?;
+        attempts.start(&self.retry).ok()?;
+        Some(sleep(delay))
+    }
+
+    fn clone_request(&mut self, req: &Req) -> Option<Req> {
+        Some(req.clone())
+    }
+}
+
+/// Clones the policy for a new call, without the attempts of the current one.
+impl<B: Clone, F: Clone> Clone for RetryPolicy<B, F> {
+    fn clone(&self) -> Self {
+        Self {
+            retry: self.retry.clone(),
+            retryable: self.retryable.clone(),
+            attempts: None,
+        }
+    }
+}
+
+impl<B: fmt::Debug, F> fmt::Debug for RetryPolicy<B, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("retry", &self.retry)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    use devkit_rl::{RetryBudget, TokenBucket};
+    use tower::{retry::RetryLayer, service_fn, Service, ServiceBuilder, ServiceExt};
+
+    use super::*;
+    use crate::ConstantBackoff;
+
+    fn always(_: &Result<(), u32>) -> bool {
+        true
+    }
+
+    async fn call<F>(policy: RetryPolicy<ConstantBackoff, F>) -> u32
+    where
+        F: Fn(&Result<(), u32>) -> bool + Clone,
+    {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counted = calls.clone();
+        let mut service = ServiceBuilder::new()
+            .layer(RetryLayer::new(policy))
+            .service(service_fn(move |()| {
+                let call = counted.fetch_add(1, Ordering::Relaxed) + 1;
+                async move { Err::<(), _>(call) }
+            }));
+        let result = service.ready().await.unwrap().call(()).await;
+        assert!(result.is_err());
+        calls.load(Ordering::Relaxed)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_policy_should_retry_tower_calls_like_retry() {
+        let start = tokio::time::Instant::now();
+        let retry = Retry::new(ConstantBackoff::new(Duration::from_secs(1))).with_max_attempts(4);
+
+        // the schedule between attempts, within the maximum attempts
+        assert_eq!(call(RetryPolicy::new(retry.clone(), always)).await, 4);
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+
+        // the errors that are not retryable
+        let policy = RetryPolicy::new(retry.clone(), |r: &Result<(), u32>| r != &Err(2));
+        assert_eq!(call(policy).await, 2);
+
+        // the rate limiter, gating the retries only
+        let limiter = TokenBucket::new(1, 1, Some(Duration::from_secs(3600)));
+        let limited = retry.clone().with_limiter(limiter);
+        assert_eq!(call(RetryPolicy::new(limited.clone(), always)).await, 2);
+        assert_eq!(call(RetryPolicy::new(limited, always)).await, 1);
+
+        // the budget, deposited into once per call
+        let budget = RetryBudget::new(Duration::from_secs(10), 0, 1.0);
+        let budgeted = retry.with_budget(budget);
+        assert_eq!(call(RetryPolicy::new(budgeted, always)).await, 2);
+    }
+}
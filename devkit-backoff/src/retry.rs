@@ -0,0 +1,380 @@
+use std::{fmt, sync::Arc, thread, time::Duration};
+
+use devkit_rl::{RateLimiter, RetryBudget};
+
+/// Runs an operation until it succeeds, waiting the delays of a backoff schedule
+/// between attempts.
+///
+/// Before every retry, the [`RetryBudget`] given to [`Retry::with_budget`] is
+/// asked for a withdrawal, and every run deposits into it once, so retries stay a
+/// share of the runs. Before every attempt, the first one included, the rate
+/// limiter given to [`Retry::with_limiter`] is asked to allow it. When either
+/// refuses, the retries give up rather than wait, as they would only add load to
+/// an overloaded service: the [`RetryError`] tells why.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_backoff::{ConstantBackoff, GiveUp, Retry};
+///
+/// let retry = Retry::new(ConstantBackoff::new(Duration::from_millis(1))).with_max_attempts(3);
+///
+/// let mut calls = 0;
+/// let result = retry.run(|| {
+///     calls += 1;
+///     if calls < 3 { Err("unavailable") } else { Ok(calls) }
+/// });
+/// assert_eq!(result.unwrap(), 3);
+///
+/// let error = retry.run(|| Err::<(), _>("unavailable")).unwrap_err();
+/// assert_eq!(error.reason(), GiveUp::MaxAttempts);
+/// assert_eq!(error.attempts(), 3);
+/// ```
+#[derive(Clone)]
+pub struct Retry<B> {
+    schedule: B,
+    max_attempts: u32,
+    budget: Option<RetryBudget>,
+    limiter: Option<Arc<dyn RateLimiter>>,
+}
+
+/// Why a [`Retry`] gave up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum GiveUp {
+    /// The error is not worth retrying.
+    NonRetryable,
+    /// The maximum number of attempts was reached, or the backoff schedule ended.
+    MaxAttempts,
+    /// The retry budget refused a retry.
+    BudgetExhausted,
+    /// The rate limiter denied an attempt.
+    RateLimited,
+}
+
+/// The error of a [`Retry`] that gave up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryError<E> {
+    reason: GiveUp,
+    attempts: u32,
+    error: Option<E>,
+}
+
+impl<B: Iterator<Item = Duration> + Clone> Retry<B> {
+    /// Creates a new `Retry` waiting the delays of `schedule` between attempts, and
+    /// giving up when it ends.
+    ///
+    /// The schedule is cloned for every run, so every run starts it over.
+    pub fn new(schedule: B) -> Self {
+        Self {
+            schedule,
+            max_attempts: u32::MAX,
+            budget: None,
+            limiter: None,
+        }
+    }
+
+    /// Sets the maximum number of attempts, the first one included, at least 1.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Limits the retries to `budget`, which may be shared with other clients.
+    pub fn with_budget(mut self, budget: RetryBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Asks `limiter` to allow every attempt, the first one included.
+    pub fn with_limiter(mut self, limiter: impl RateLimiter + 'static) -> Self {
+        self.limiter = Some(Arc::new(limiter));
+        self
+    }
+
+    /// Runs `op` until it succeeds, retrying all its errors.
+    ///
+    /// # Errors
+    ///
+    /// The [`RetryError`] holding the last error of `op`, once the retries give up.
+    pub fn run<T, E>(&self, op: impl FnMut() -> Result<T, E>) -> Result<T, RetryError<E>> {
+        self.run_if(op, |_| true)
+    }
+
+    /// Runs `op` until it succeeds, retrying the errors for which `retryable`
+    /// returns `true`.
+    ///
+    /// The thread sleeps between attempts.
+    ///
+    /// # Errors
+    ///
+    /// The [`RetryError`] holding the last error of `op`, once the retries give up.
+    pub fn run_if<T, E>(
+        &self,
+        mut op: impl FnMut() -> Result<T, E>,
+        retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, RetryError<E>> {
+        let mut attempts = Attempts::new(self);
+        let mut last_error = None;
+        loop {
+            if let Err(reason) = attempts.start(self) {
+                return Err(attempts.give_up(reason, last_error));
+            }
+            let error = match op() {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            match attempts.next_delay(self, retryable(&error)) {
+                Ok(delay) => thread::sleep(delay),
+                Err(reason) => return Err(attempts.give_up(reason, Some(error))),
+            }
+            last_error = Some(error);
+        }
+    }
+
+    /// Runs the futures returned by `op` until one succeeds, retrying the errors
+    /// for which `retryable` returns `true`.
+    ///
+    /// The delays are waited with [`tokio::time::sleep`].
+    ///
+    /// # Errors
+    ///
+    /// The [`RetryError`] holding the last error of `op`, once the retries give up.
+    #[cfg(feature = "tokio")]
+    pub async fn run_async<T, E, F>(
+        &self,
+        mut op: impl FnMut() -> F,
+        retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, RetryError<E>>
+    where
+        F: std::future::Future<Output = Result<T, E>>,
+    {
+        let mut attempts = Attempts::new(self);
+        let mut last_error = None;
+        loop {
+            if let Err(reason) = attempts.start(self) {
+                return Err(attempts.give_up(reason, last_error));
+            }
+            let error = match op().await {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            match attempts.next_delay(self, retryable(&error)) {
+                Ok(delay) => tokio::time::sleep(delay).await,
+                Err(reason) => return Err(attempts.give_up(reason, Some(error))),
+            }
+            last_error = Some(error);
+        }
+    }
+}
+
+/// The attempts of a single run of a [`Retry`].
+pub(crate) struct Attempts<B> {
+    schedule: B,
+    /// The attempts started.
+    started: u32,
+}
+
+impl<B: Iterator<Item = Duration> + Clone> Attempts<B> {
+    /// Starts a run of `retry`, depositing into its budget.
+    pub(crate) fn new(retry: &Retry<B>) -> Self {
+        if let Some(budget) = &retry.budget {
+            budget.deposit();
+        }
+        Self {
+            schedule: retry.schedule.clone(),
+            started: 0,
+        }
+    }
+
+    /// Counts an attempt started without asking the limiter, e.g. the first one of
+    /// a tower [`RetryPolicy`](crate::RetryPolicy), made before it is consulted.
+    #[cfg(feature = "tower")]
+    pub(crate) fn started_unchecked(&mut self) {
+        self.started += 1;
+    }
+
+    /// Asks the limiter of `retry` for the next attempt.
+    pub(crate) fn start(&mut self, retry: &Retry<B>) -> Result<(), GiveUp> {
+        if retry.limiter.as_ref().is_some_and(|l| !l.allow()) {
+            return Err(GiveUp::RateLimited);
+        }
+        self.started += 1;
+        Ok(())
+    }
+
+    /// Returns the delay to wait before retrying an error, `retryable` or not, or
+    /// why not to retry.
+    pub(crate) fn next_delay(
+        &mut self,
+        retry: &Retry<B>,
+        retryable: bool,
+    ) -> Result<Duration, GiveUp> {
+        if !retryable {
+            return Err(GiveUp::NonRetryable);
+        }
+        if self.started >= retry.max_attempts {
+            return Err(GiveUp::MaxAttempts);
+        }
+        let delay = self.schedule.next().ok_or(GiveUp::MaxAttempts)?;
+        if retry.budget.as_ref().is_some_and(|b| !b.withdraw()) {
+            return Err(GiveUp::BudgetExhausted);
+        }
+        Ok(delay)
+    }
+
+    fn give_up<E>(&self, reason: GiveUp, error: Option<E>) -> RetryError<E> {
+        RetryError {
+            reason,
+            attempts: self.started,
+            error,
+        }
+    }
+}
+
+impl<E> RetryError<E> {
+    /// Returns why the retries gave up.
+    pub fn reason(&self) -> GiveUp {
+        self.reason
+    }
+
+    /// Returns the attempts made.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Returns the error of the last attempt, or `None` if the rate limiter denied
+    /// the first one.
+    pub fn error(&self) -> Option<&E> {
+        self.error.as_ref()
+    }
+
+    /// Returns the error of the last attempt, see [`RetryError::error`].
+    pub fn into_error(self) -> Option<E> {
+        self.error
+    }
+}
+
+impl fmt::Display for GiveUp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GiveUp::NonRetryable => "non-retryable error",
+            GiveUp::MaxAttempts => "max attempts reached",
+            GiveUp::BudgetExhausted => "retry budget exhausted",
+            GiveUp::RateLimited => "rate limited",
+        })
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for RetryError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "gave up after {} attempts: {}",
+            self.attempts, self.reason
+        )?;
+        match &self.error {
+            Some(error) => write!(f, ": {error}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for RetryError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.as_ref().map(|e| e as _)
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for Retry<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Retry")
+            .field("schedule", &self.schedule)
+            .field("max_attempts", &self.max_attempts)
+            .field("budget", &self.budget)
+            .field("limiter", &self.limiter.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use devkit_rl::TokenBucket;
+
+    use super::*;
+    use crate::ConstantBackoff;
+
+    fn retry() -> Retry<ConstantBackoff> {
+        Retry::new(ConstantBackoff::new(Duration::ZERO)).with_max_attempts(5)
+    }
+
+    #[test]
+    fn retry_should_tell_why_it_gave_up() {
+        let calls = Cell::new(0);
+        let fail = || {
+            calls.set(calls.get() + 1);
+            Err::<(), _>(calls.get())
+        };
+
+        let error = retry().run_if(fail, |&n| n < 2).unwrap_err();
+        assert_eq!(
+            (error.reason(), error.attempts()),
+            (GiveUp::NonRetryable, 2)
+        );
+        assert_eq!(error.into_error(), Some(2));
+
+        calls.set(0);
+        let error = retry().run(fail).unwrap_err();
+        assert_eq!((error.reason(), error.attempts()), (GiveUp::MaxAttempts, 5));
+
+        // the schedule ending ends the retries too
+        let error = Retry::new(ConstantBackoff::new(Duration::ZERO).take(1))
+            .run(fail)
+            .unwrap_err();
+        assert_eq!((error.reason(), error.attempts()), (GiveUp::MaxAttempts, 2));
+
+        // the run deposits a single retry into the budget
+        let budget = RetryBudget::new(Duration::from_secs(10), 0, 1.0);
+        let error = retry().with_budget(budget).run(fail).unwrap_err();
+        assert_eq!(
+            (error.reason(), error.attempts()),
+            (GiveUp::BudgetExhausted, 2)
+        );
+
+        let limiter = TokenBucket::new(3, 1, Some(Duration::from_secs(3600)));
+        let retry = retry().with_limiter(limiter);
+        let error = retry.run(fail).unwrap_err();
+        assert_eq!((error.reason(), error.attempts()), (GiveUp::RateLimited, 3));
+        assert_eq!(
+            error.to_string(),
+            "gave up after 3 attempts: rate limited: 12"
+        );
+
+        let error = retry.run(|| Ok::<_, ()>(())).unwrap_err();
+        assert_eq!((error.attempts(), error.error()), (0, None));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn retry_should_wait_the_schedule_between_async_attempts() {
+        let start = tokio::time::Instant::now();
+        let calls = Cell::new(0);
+        let retry = Retry::new(ConstantBackoff::new(Duration::from_secs(1))).with_max_attempts(3);
+
+        let error = retry
+            .run_async(
+                || {
+                    calls.set(calls.get() + 1);
+                    async { Err::<(), _>("unavailable") }
+                },
+                |_| true,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!((error.reason(), error.attempts()), (GiveUp::MaxAttempts, 3));
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_core::Stream;
+use tokio::time::{sleep, Sleep};
+
+/// Turns a backoff schedule into a [`BackoffStream`].
+pub trait IntoStream: Iterator<Item = Duration> + Sized {
+    /// Returns a stream waiting each delay of the schedule before yielding it.
+    fn into_stream(self) -> BackoffStream<Self> {
+        BackoffStream {
+            schedule: self,
+            sleep: None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Duration>> IntoStream for I {}
+
+/// A stream waiting each delay of a backoff schedule before yielding it, and ending
+/// with the schedule.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_backoff::{ExponentialBackoff, IntoStream};
+/// use futures::StreamExt;
+///
+/// # #[tokio::main(flavor = "current_thread", start_paused = true)]
+/// # async fn main() {
+/// let mut retries = ExponentialBackoff::default().take(3).into_stream();
+/// while let Some(_delay) = retries.next().await {
+///     // retry the operation
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct BackoffStream<I> {
+    schedule: I,
+    sleep: Option<(Pin<Box<Sleep>>, Duration)>,
+}
+
+impl<I> BackoffStream<I> {
+    /// Returns the schedule, e.g. to reset it.
+    pub fn schedule_mut(&mut self) -> &mut I {
+        &mut self.schedule
+    }
+}
+
+impl<I: Iterator<Item = Duration> + Unpin> Stream for BackoffStream<I> {
+    type Item = Duration;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Duration>> {
+        let this = self.get_mut();
+        if this.sleep.is_none() {
+            let Some(delay) = this.schedule.next() else {
+                return Poll::Ready(None);
+            };
+            this.sleep = Some((Box::pin(sleep(delay)), delay));
+        }
+        let (sleep, delay) = this.sleep.as_mut().expect("sleep was just set");
+        let delay = *delay;
+        match sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                this.sleep = None;
+                Poll::Ready(Some(delay))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let pending = usize::from(self.sleep.is_some());
+        let (low, high) = self.schedule.size_hint();
+        (
+            low.saturating_add(pending),
+            high.and_then(|h| h.checked_add(pending)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::{ExponentialBackoff, Jitter};
+
+    #[tokio::test(start_paused = true)]
+    async fn backoff_stream_should_sleep_between_items() {
+        let start = Instant::now();
+        let delays: Vec<_> = ExponentialBackoff::new(Duration::from_millis(100))
+            .with_jitter(Jitter::None)
+            .take(3)
+            .into_stream()
+            .collect()
+            .await;
+        assert_eq!(delays, [100, 200, 400].map(Duration::from_millis));
+        assert_eq!(start.elapsed(), Duration::from_millis(700));
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::Error;
+
+/// Buffers items and hands them over in batches to a flush function.
+///
+/// A batch is flushed as soon as it holds `max_size` items, or once its first item
+/// has waited for `max_linger`, whichever comes first. This paces writes to a
+/// database or a remote service: one round trip per batch instead of per item,
+/// with a bounded delay when the traffic is low.
+///
+/// The flush function runs on a background thread. While it runs, items queue up
+/// to `capacity`; once the queue is full, [`Batcher::add`] blocks and
+/// [`Batcher::try_add`] fails, pushing back on producers instead of buffering
+/// without bound. Dropping the batcher flushes the remaining items.
+///
+/// # Example
+///
+/// ```
+/// use std::{sync::mpsc, time::Duration};
+/// use devkit_batch::Batcher;
+///
+/// let (tx, rx) = mpsc::channel();
+/// let batcher = Batcher::new(3, Some(Duration::from_secs(60)), None, move |batch: Vec<u32>| {
+///     tx.send(batch).unwrap();
+/// });
+///
+/// for i in 0..4 {
+///     batcher.add(i).unwrap();
+/// }
+/// assert_eq!(rx.recv().unwrap(), [0, 1, 2]);
+///
+/// drop(batcher);
+/// assert_eq!(rx.recv().unwrap(), [3]);
+/// ```
+#[derive(Debug)]
+pub struct Batcher<T> {
+    sender: Option<SyncSender<T>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> Batcher<T> {
+    /// Creates a new `Batcher` and spawns its flush worker.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - The maximum number of items of a batch.
+    /// * `max_linger` - Optional maximum time an item waits for its batch to fill up. Defaults to 1 second if not provided.
+    /// * `capacity` - Optional number of items queued while a batch is being flushed. Defaults to `max_size` if not provided.
+    /// * `flush` - The function receiving the batches.
+    pub fn new<F>(
+        max_size: usize,
+        max_linger: Option<Duration>,
+        capacity: Option<usize>,
+        flush: F,
+    ) -> Self
+    where
+        F: FnMut(Vec<T>) + Send + 'static,
+    {
+        let max_size = max_size.max(1);
+        let max_linger = max_linger.unwrap_or(Duration::from_secs(1));
+        let (sender, receiver) = mpsc::sync_channel(capacity.unwrap_or(max_size));
+
+        let worker = thread::spawn(move || {
+            let mut flush = flush;
+            // wait for the first item of each batch, until every sender is gone
+            while let Ok(first) = receiver.recv() {
+                let deadline = Instant::now() + max_linger;
+                let mut batch = Vec::with_capacity(max_size);
+                batch.push(first);
+
+                let mut closed = false;
+                while batch.len() < max_size {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    match receiver.recv_timeout(timeout) {
+                        Ok(item) => batch.push(item),
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => {
+                            closed = true;
+                            break;
+                        }
+                    }
+                }
+
+                flush(batch);
+                if closed {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            sender: Some(sender),
+            worker: Some(worker),
+        }
+    }
+
+    /// Adds `item` to the current batch, blocking while the queue is full.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Closed`] if the flush worker has stopped.
+    pub fn add(&self, item: T) -> Result<(), Error<T>> {
+        self.sender()
+            .send(item)
+            .map_err(|mpsc::SendError(item)| Error::Closed(item))
+    }
+
+    /// Adds `item` to the current batch, without blocking.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Full`] if the queue is full, [`Error::Closed`] if the flush worker
+    /// has stopped.
+    pub fn try_add(&self, item: T) -> Result<(), Error<T>> {
+        self.sender().try_send(item).map_err(|e| match e {
+            TrySendError::Full(item) => Error::Full(item),
+            TrySendError::Disconnected(item) => Error::Closed(item),
+        })
+    }
+
+    /// Flushes the remaining items and waits for the flush worker to finish.
+    ///
+    /// This is what dropping the batcher does, made explicit.
+    pub fn close(self) {}
+
+    fn sender(&self) -> &SyncSender<T> {
+        self.sender.as_ref().expect("sender is only taken on drop")
+    }
+}
+
+impl<T> Drop for Batcher<T> {
+    fn drop(&mut self) {
+        // disconnecting the channel makes the worker flush and exit
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[test]
+    fn batcher_should_flush_on_size_and_linger() {
+        const LINGER: Duration = Duration::from_millis(50);
+
+        let (tx, rx) = mpsc::channel();
+        let batcher = Batcher::new(2, Some(LINGER), None, move |batch| tx.send(batch).unwrap());
+
+        batcher.add(1).unwrap();
+        batcher.add(2).unwrap();
+        assert_eq!(rx.recv_timeout(LINGER / 2), Ok(vec![1, 2]));
+
+        // a lone item is flushed once it has lingered
+        let start = Instant::now();
+        batcher.add(3).unwrap();
+        assert_eq!(rx.recv_timeout(LINGER * 4), Ok(vec![3]));
+        assert!(start.elapsed() >= LINGER);
+    }
+
+    #[test]
+    fn batcher_should_push_back_when_flush_is_slow() {
+        let flushed = Arc::new(AtomicUsize::new(0));
+        let counter = flushed.clone();
+        let (entered_tx, entered) = mpsc::channel();
+        let (gate, blocked) = mpsc::channel::<()>();
+        let batcher = Batcher::new(1, None, Some(2), move |batch: Vec<u32>| {
+            let _ = entered_tx.send(());
+            let _ = blocked.recv();
+            counter.fetch_add(batch.len(), Ordering::SeqCst);
+        });
+
+        // one item in the stuck flush, two in the queue, then the queue is full
+        batcher.add(0).unwrap();
+        entered.recv().unwrap();
+        assert_eq!(batcher.try_add(1), Ok(()));
+        assert_eq!(batcher.try_add(2), Ok(()));
+        assert_eq!(batcher.try_add(3), Err(Error::Full(3)));
+
+        drop(gate);
+        batcher.close();
+        assert_eq!(flushed.load(Ordering::SeqCst), 3);
+    }
+}
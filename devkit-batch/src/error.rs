@@ -0,0 +1,42 @@
+use std::fmt;
+
+/// Errors returned when an item cannot be added to a batcher.
+///
+/// The rejected item is handed back, so it is not lost.
+#[derive(PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error<T> {
+    /// The queue is full because the flush function cannot keep up.
+    Full(T),
+    /// The flush worker has stopped, after the flush function panicked.
+    Closed(T),
+}
+
+impl<T> Error<T> {
+    /// Returns the rejected item.
+    pub fn into_inner(self) -> T {
+        match self {
+            Error::Full(item) | Error::Closed(item) => item,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Error<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Full(_) => write!(f, "Full(..)"),
+            Error::Closed(_) => write!(f, "Closed(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for Error<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Full(_) => write!(f, "batch queue is full"),
+            Error::Closed(_) => write!(f, "batch worker has stopped"),
+        }
+    }
+}
+
+impl<T> std::error::Error for Error<T> {}
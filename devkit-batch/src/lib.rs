@@ -0,0 +1,5 @@
+mod batcher;
+mod error;
+
+pub use batcher::Batcher;
+pub use error::Error;
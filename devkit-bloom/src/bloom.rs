@@ -0,0 +1,226 @@
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hash::{optimal_params, positions};
+
+/// A standard Bloom filter.
+///
+/// A Bloom filter answers set membership queries in constant space: `contains`
+/// never returns `false` for an inserted item, but may return `true` for an item
+/// that was never inserted, with a probability bounded by the configured false
+/// positive rate as long as the filter holds at most its expected number of items.
+///
+/// The filter can be serialized with serde to take snapshots and restore them.
+/// Restoring a snapshot whose bit array does not match its number of bits fails.
+///
+/// # Example
+///
+/// ```
+/// use devkit_bloom::BloomFilter;
+///
+/// let mut filter = BloomFilter::new(1000, 0.01);
+/// filter.insert("request-42");
+///
+/// assert!(filter.contains("request-42"));
+/// assert!(!filter.contains("request-43"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawBloomFilter")]
+pub struct BloomFilter {
+    /// The bit array, packed in words.
+    bits: Vec<u64>,
+    /// The number of usable bits in `bits`.
+    num_bits: u64,
+    /// The number of hash functions.
+    num_hashes: u32,
+    /// The number of items inserted so far.
+    len: u64,
+}
+
+/// A deserialized [`BloomFilter`] that was not validated yet.
+#[derive(Deserialize)]
+struct RawBloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+    len: u64,
+}
+
+impl TryFrom<RawBloomFilter> for BloomFilter {
+    type Error = &'static str;
+
+    fn try_from(raw: RawBloomFilter) -> Result<Self, Self::Error> {
+        if raw.num_bits == 0 {
+            return Err("a bloom filter needs at least one bit");
+        }
+        if raw.num_hashes == 0 {
+            return Err("a bloom filter needs at least one hash function");
+        }
+        if raw.bits.len() as u64 != raw.num_bits.div_ceil(64) {
+            return Err("the bit array of a bloom filter must hold exactly `num_bits` bits");
+        }
+        Ok(Self {
+            bits: raw.bits,
+            num_bits: raw.num_bits,
+            num_hashes: raw.num_hashes,
+            len: raw.len,
+        })
+    }
+}
+
+impl BloomFilter {
+    /// Creates a new `BloomFilter` sized for `expected_items` items with a false
+    /// positive rate of `fp_rate`.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected_items` - The number of items the filter is expected to hold.
+    /// * `fp_rate` - The acceptable false positive rate, e.g. `0.01` for 1%.
+    pub fn new(expected_items: u64, fp_rate: f64) -> Self {
+        let (num_bits, num_hashes) = optimal_params(expected_items, fp_rate);
+        Self::with_params(num_bits, num_hashes)
+    }
+
+    /// Creates a new `BloomFilter` with explicit parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_bits` - The number of bits of the filter.
+    /// * `num_hashes` - The number of hash functions.
+    pub fn with_params(num_bits: u64, num_hashes: u32) -> Self {
+        let num_bits = num_bits.max(1);
+        Self {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes: num_hashes.max(1),
+            len: 0,
+        }
+    }
+
+    /// Inserts `item` into the filter.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the item was not in the filter before, `false` if it was, or
+    /// if it collided with other items on every bit.
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        let mut new = false;
+        for position in positions(item, self.num_hashes, self.num_bits) {
+            let (word, mask) = (position / 64, 1 << (position % 64));
+            new |= self.bits[word] & mask == 0;
+            self.bits[word] |= mask;
+        }
+        if new {
+            self.len += 1;
+        }
+        new
+    }
+
+    /// Checks whether `item` may be in the filter.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the item is definitely not in the filter, `true` if it probably is.
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        positions(item, self.num_hashes, self.num_bits)
+            .all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+
+    /// Returns the number of distinct items inserted so far.
+    ///
+    /// Items colliding on all bits with earlier items are not counted, so this may
+    /// slightly underestimate the true number.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if no item was inserted.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bits of the filter.
+    pub fn num_bits(&self) -> u64 {
+        self.num_bits
+    }
+
+    /// Returns the number of hash functions of the filter.
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// Estimates the current false positive rate from the fraction of set bits.
+    pub fn false_positive_rate(&self) -> f64 {
+        let set: u64 = self.bits.iter().map(|w| u64::from(w.count_ones())).sum();
+        (set as f64 / self.num_bits as f64).powi(self.num_hashes as i32)
+    }
+
+    /// Removes every item from the filter.
+    pub fn clear(&mut self) {
+        self.bits.fill(0);
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bloom_filter_should_work() {
+        const ITEMS: u64 = 1000;
+        const FP_RATE: f64 = 0.01;
+
+        let mut filter = BloomFilter::new(ITEMS, FP_RATE);
+        let inserted = (0..ITEMS).filter(|i| filter.insert(i)).count() as u64;
+        // an item colliding with earlier ones on every bit is reported as known
+        assert!(inserted > ITEMS * 98 / 100);
+        assert_eq!(filter.len(), inserted);
+
+        // no false negatives
+        assert!((0..ITEMS).all(|i| filter.contains(&i)));
+
+        // false positives stay around the configured rate
+        let false_positives = (ITEMS..ITEMS * 11).filter(|i| filter.contains(i)).count();
+        assert!(false_positives < (ITEMS as f64 * 10.0 * FP_RATE * 2.0) as usize);
+
+        filter.clear();
+        assert!(filter.is_empty());
+        assert!(!filter.contains(&0));
+    }
+
+    #[test]
+    fn bloom_filter_snapshot_should_roundtrip() {
+        let mut filter = BloomFilter::new(100, 0.01);
+        filter.insert("a");
+        filter.insert("b");
+
+        let snapshot = serde_json::to_string(&filter).unwrap();
+        let restored: BloomFilter = serde_json::from_str(&snapshot).unwrap();
+
+        assert_eq!(restored, filter);
+        assert!(restored.contains("a"));
+        assert!(restored.contains("b"));
+    }
+
+    #[test]
+    fn bloom_filter_snapshot_should_reject_invalid_shapes() {
+        for snapshot in [
+            r#"{"bits":[],"num_bits":0,"num_hashes":3,"len":0}"#,
+            r#"{"bits":[0],"num_bits":64,"num_hashes":0,"len":0}"#,
+            r#"{"bits":[0],"num_bits":65,"num_hashes":3,"len":0}"#,
+            r#"{"bits":[],"num_bits":1,"num_hashes":3,"len":0}"#,
+            r#"{"bits":[0,0],"num_bits":64,"num_hashes":3,"len":0}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<BloomFilter>(snapshot).is_err(),
+                "{snapshot}"
+            );
+        }
+        assert!(serde_json::from_str::<BloomFilter>(
+            r#"{"bits":[0,0],"num_bits":65,"num_hashes":3,"len":0}"#
+        )
+        .is_ok());
+    }
+}
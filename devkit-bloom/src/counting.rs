@@ -0,0 +1,185 @@
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hash::{optimal_params, positions};
+
+/// A counting Bloom filter, which supports removing items.
+///
+/// Every slot holds a small counter instead of a bit, so an item can be removed
+/// by decrementing its counters, and the number of times an item was inserted can
+/// be estimated. Counters saturate at `u8::MAX` and then stay there, so that
+/// removals can never introduce false negatives.
+///
+/// Only remove items that were inserted: removing an item that was never inserted
+/// may decrement counters shared with other items and cause false negatives.
+///
+/// Restoring a serde snapshot without any counter or hash function fails.
+///
+/// # Example
+///
+/// ```
+/// use devkit_bloom::CountingBloomFilter;
+///
+/// let mut filter = CountingBloomFilter::new(1000, 0.01);
+/// filter.insert("10.0.0.1");
+/// filter.insert("10.0.0.1");
+/// assert_eq!(filter.count("10.0.0.1"), 2);
+///
+/// filter.remove("10.0.0.1");
+/// filter.remove("10.0.0.1");
+/// assert!(!filter.contains("10.0.0.1"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawCountingBloomFilter")]
+pub struct CountingBloomFilter {
+    /// The counter of every slot.
+    counters: Vec<u8>,
+    /// The number of hash functions.
+    num_hashes: u32,
+}
+
+/// A deserialized [`CountingBloomFilter`] that was not validated yet.
+#[derive(Deserialize)]
+struct RawCountingBloomFilter {
+    counters: Vec<u8>,
+    num_hashes: u32,
+}
+
+impl TryFrom<RawCountingBloomFilter> for CountingBloomFilter {
+    type Error = &'static str;
+
+    fn try_from(raw: RawCountingBloomFilter) -> Result<Self, Self::Error> {
+        if raw.counters.is_empty() {
+            return Err("a counting bloom filter needs at least one counter");
+        }
+        if raw.num_hashes == 0 {
+            return Err("a counting bloom filter needs at least one hash function");
+        }
+        Ok(Self {
+            counters: raw.counters,
+            num_hashes: raw.num_hashes,
+        })
+    }
+}
+
+impl CountingBloomFilter {
+    /// Creates a new `CountingBloomFilter` sized for `expected_items` distinct items
+    /// with a false positive rate of `fp_rate`.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected_items` - The number of distinct items the filter is expected to hold.
+    /// * `fp_rate` - The acceptable false positive rate, e.g. `0.01` for 1%.
+    pub fn new(expected_items: u64, fp_rate: f64) -> Self {
+        let (num_slots, num_hashes) = optimal_params(expected_items, fp_rate);
+        Self::with_params(num_slots, num_hashes)
+    }
+
+    /// Creates a new `CountingBloomFilter` with explicit parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_slots` - The number of counters of the filter.
+    /// * `num_hashes` - The number of hash functions.
+    pub fn with_params(num_slots: u64, num_hashes: u32) -> Self {
+        Self {
+            counters: vec![0; num_slots.max(1) as usize],
+            num_hashes: num_hashes.max(1),
+        }
+    }
+
+    /// Inserts `item` into the filter, incrementing its counters.
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) {
+        for position in positions(item, self.num_hashes, self.counters.len() as u64) {
+            let counter = &mut self.counters[position];
+            *counter = counter.saturating_add(1);
+        }
+    }
+
+    /// Removes one insertion of `item` from the filter, decrementing its counters.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the item may have been in the filter, `false` if it definitely
+    /// was not, in which case nothing is changed.
+    pub fn remove<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        if !self.contains(item) {
+            return false;
+        }
+        for position in positions(item, self.num_hashes, self.counters.len() as u64) {
+            let counter = &mut self.counters[position];
+            // a saturated counter has lost track of its true value
+            if *counter != u8::MAX {
+                *counter -= 1;
+            }
+        }
+        true
+    }
+
+    /// Checks whether `item` may be in the filter.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the item is definitely not in the filter, `true` if it probably is.
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.count(item) > 0
+    }
+
+    /// Estimates how many times `item` was inserted.
+    ///
+    /// The estimate never undercounts, unless items that were never inserted have
+    /// been removed, but may overcount because of collisions.
+    pub fn count<T: Hash + ?Sized>(&self, item: &T) -> u8 {
+        positions(item, self.num_hashes, self.counters.len() as u64)
+            .map(|position| self.counters[position])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Removes every item from the filter.
+    pub fn clear(&mut self) {
+        self.counters.fill(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counting_bloom_filter_should_support_removal() {
+        let mut filter = CountingBloomFilter::new(100, 0.01);
+
+        for i in 0..100 {
+            filter.insert(&i);
+        }
+        assert!((0..100).all(|i| filter.contains(&i)));
+
+        for i in 0..50 {
+            assert!(filter.remove(&i));
+        }
+        // removing never causes false negatives for the remaining items
+        assert!((50..100).all(|i| filter.contains(&i)));
+        assert!((0..50).filter(|i| filter.contains(i)).count() < 5);
+
+        let snapshot = serde_json::to_string(&filter).unwrap();
+        assert_eq!(
+            serde_json::from_str::<CountingBloomFilter>(&snapshot).unwrap(),
+            filter
+        );
+    }
+
+    #[test]
+    fn counting_bloom_filter_snapshot_should_reject_invalid_shapes() {
+        for snapshot in [
+            r#"{"counters":[],"num_hashes":3}"#,
+            r#"{"counters":[0,0],"num_hashes":0}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<CountingBloomFilter>(snapshot).is_err(),
+                "{snapshot}"
+            );
+        }
+    }
+}
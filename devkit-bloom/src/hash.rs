@@ -0,0 +1,83 @@
+use std::hash::{Hash, Hasher};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A 64-bit FNV-1a hasher.
+///
+/// The standard library makes no promise that its hashers produce the same
+/// output across releases, which would silently corrupt serialized filters.
+/// FNV-1a is fully specified, so snapshots stay valid across Rust versions.
+struct Fnv1a(u64);
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+/// Finalizer of SplitMix64, spreading the entropy of `x` over all bits.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Returns the `num_hashes` positions of `item` in a filter of `len` slots.
+///
+/// The positions are derived from two base hashes with the enhanced double
+/// hashing scheme of Kirsch and Mitzenmacher, which performs as well as
+/// `num_hashes` independent hash functions.
+pub(crate) fn positions<T: Hash + ?Sized>(
+    item: &T,
+    num_hashes: u32,
+    len: u64,
+) -> impl Iterator<Item = usize> {
+    let mut hasher = Fnv1a(FNV_OFFSET);
+    item.hash(&mut hasher);
+    let hash = hasher.finish();
+
+    let mut h1 = mix(hash);
+    let mut h2 = mix(hash ^ FNV_PRIME) | 1;
+    (0..num_hashes).map(move |i| {
+        let position = h1 % len;
+        h1 = h1.wrapping_add(h2);
+        h2 = h2.wrapping_add(u64::from(i));
+        position as usize
+    })
+}
+
+/// Returns the optimal number of bits and hash functions for a filter holding
+/// `expected_items` items with a false positive rate of `fp_rate`.
+pub(crate) fn optimal_params(expected_items: u64, fp_rate: f64) -> (u64, u32) {
+    let n = expected_items.max(1) as f64;
+    let p = fp_rate.clamp(f64::MIN_POSITIVE, 0.5);
+    let ln2 = std::f64::consts::LN_2;
+
+    let num_bits = (-n * p.ln() / (ln2 * ln2)).ceil().max(1.0);
+    let num_hashes = (num_bits / n * ln2).round().max(1.0);
+    (num_bits as u64, num_hashes as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_should_be_stable() {
+        let a: Vec<usize> = positions("devkit", 4, 1024).collect();
+        let b: Vec<usize> = positions("devkit", 4, 1024).collect();
+        assert_eq!(a, b);
+        assert!(a.iter().all(|p| *p < 1024));
+
+        // 1% false positives for 1000 items needs ~9585 bits and 7 hashes
+        assert_eq!(optimal_params(1000, 0.01), (9586, 7));
+    }
+}
@@ -0,0 +1,8 @@
+mod bloom;
+mod counting;
+mod hash;
+mod scalable;
+
+pub use bloom::BloomFilter;
+pub use counting::CountingBloomFilter;
+pub use scalable::ScalableBloomFilter;
@@ -0,0 +1,174 @@
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use crate::BloomFilter;
+
+/// The factor by which each new filter is larger than the previous one.
+const GROWTH_FACTOR: u64 = 2;
+/// The factor by which the false positive rate of each new filter is tightened.
+const TIGHTENING_RATIO: f64 = 0.5;
+
+/// A Bloom filter that grows to hold any number of items.
+///
+/// A scalable Bloom filter (Almeida et al.) is a series of Bloom filters. Once the
+/// newest filter holds as many items as it was sized for, a larger one with a
+/// tighter false positive rate is added, so the overall false positive rate stays
+/// below the configured one no matter how many items are inserted.
+///
+/// # Example
+///
+/// ```
+/// use devkit_bloom::ScalableBloomFilter;
+///
+/// let mut filter = ScalableBloomFilter::new(10, 0.01);
+/// for i in 0..1000 {
+///     filter.insert(&i);
+/// }
+///
+/// assert!(filter.contains(&999));
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawScalableBloomFilter")]
+pub struct ScalableBloomFilter {
+    /// The filters, oldest first.
+    filters: Vec<BloomFilter>,
+    /// The number of items the newest filter was sized for.
+    capacity: u64,
+    /// The false positive rate of the newest filter.
+    fp_rate: f64,
+}
+
+/// A deserialized [`ScalableBloomFilter`] that was not validated yet.
+#[derive(Deserialize)]
+struct RawScalableBloomFilter {
+    filters: Vec<BloomFilter>,
+    capacity: u64,
+    fp_rate: f64,
+}
+
+impl TryFrom<RawScalableBloomFilter> for ScalableBloomFilter {
+    type Error = &'static str;
+
+    fn try_from(raw: RawScalableBloomFilter) -> Result<Self, Self::Error> {
+        if raw.filters.is_empty() {
+            return Err("a scalable bloom filter needs at least one filter");
+        }
+        if raw.capacity == 0 {
+            return Err("a scalable bloom filter needs a capacity of at least one item");
+        }
+        Ok(Self {
+            filters: raw.filters,
+            capacity: raw.capacity,
+            fp_rate: raw.fp_rate,
+        })
+    }
+}
+
+impl ScalableBloomFilter {
+    /// Creates a new `ScalableBloomFilter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial_capacity` - The number of items the first filter is sized for.
+    /// * `fp_rate` - The maximum overall false positive rate, e.g. `0.01` for 1%.
+    pub fn new(initial_capacity: u64, fp_rate: f64) -> Self {
+        // the rates of the filters form a geometric series summing up to `fp_rate`
+        let fp_rate = fp_rate * (1.0 - TIGHTENING_RATIO);
+        let capacity = initial_capacity.max(1);
+        Self {
+            filters: vec![BloomFilter::new(capacity, fp_rate)],
+            capacity,
+            fp_rate,
+        }
+    }
+
+    /// Inserts `item` into the filter, growing it if needed.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the item was not in the filter before, `false` if it probably was.
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        if self.contains(item) {
+            return false;
+        }
+
+        if self.newest().len() >= self.capacity {
+            self.capacity *= GROWTH_FACTOR;
+            self.fp_rate *= TIGHTENING_RATIO;
+            self.filters
+                .push(BloomFilter::new(self.capacity, self.fp_rate));
+        }
+
+        let newest = self.filters.len() - 1;
+        self.filters[newest].insert(item)
+    }
+
+    /// Checks whether `item` may be in the filter.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the item is definitely not in the filter, `true` if it probably is.
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.filters.iter().any(|f| f.contains(item))
+    }
+
+    /// Returns the number of distinct items inserted so far.
+    pub fn len(&self) -> u64 {
+        self.filters.iter().map(BloomFilter::len).sum()
+    }
+
+    /// Returns `true` if no item was inserted.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of filters the scalable filter is made of.
+    pub fn num_filters(&self) -> usize {
+        self.filters.len()
+    }
+
+    fn newest(&self) -> &BloomFilter {
+        self.filters.last().expect("always at least one filter")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scalable_bloom_filter_should_grow() {
+        const FP_RATE: f64 = 0.01;
+
+        let mut filter = ScalableBloomFilter::new(16, FP_RATE);
+        for i in 0..10_000u64 {
+            filter.insert(&i);
+        }
+        assert!(filter.num_filters() > 1);
+        assert!((0..10_000u64).all(|i| filter.contains(&i)));
+
+        let false_positives = (10_000..110_000u64).filter(|i| filter.contains(i)).count();
+        assert!((false_positives as f64) < 100_000.0 * FP_RATE);
+
+        let snapshot = serde_json::to_string(&filter).unwrap();
+        let restored: ScalableBloomFilter = serde_json::from_str(&snapshot).unwrap();
+        assert!(restored.contains(&42u64));
+    }
+
+    #[test]
+    fn scalable_bloom_filter_snapshot_should_reject_invalid_shapes() {
+        let valid = r#"{"bits":[0],"num_bits":64,"num_hashes":3,"len":0}"#;
+        let invalid = r#"{"bits":[],"num_bits":64,"num_hashes":3,"len":0}"#;
+        for snapshot in [
+            r#"{"filters":[],"capacity":16,"fp_rate":0.005}"#.to_string(),
+            format!(r#"{{"filters":[{valid}],"capacity":0,"fp_rate":0.005}}"#),
+            format!(r#"{{"filters":[{invalid}],"capacity":16,"fp_rate":0.005}}"#),
+        ] {
+            assert!(
+                serde_json::from_str::<ScalableBloomFilter>(&snapshot).is_err(),
+                "{snapshot}"
+            );
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::Hash,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+/// A map whose entries expire after a time to live.
+///
+/// Expired entries are dropped lazily, when they are looked up, or all at once by
+/// [`Cache::purge_expired`].
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_cache::Cache;
+///
+/// let cache = Cache::new();
+/// cache.insert("user:1", "alice", Duration::from_secs(60));
+///
+/// assert_eq!(cache.get("user:1"), Some("alice"));
+/// assert_eq!(cache.get("user:2"), None);
+/// ```
+#[derive(Debug)]
+pub struct Cache<K, V> {
+    entries: Mutex<HashMap<K, Entry<V>>>,
+}
+
+#[derive(Debug)]
+struct Entry<V> {
+    value: V,
+    /// The time after which the value is stale.
+    expires_at: Instant,
+    /// The time after which the value is dropped, at or after `expires_at`.
+    stale_until: Instant,
+}
+
+/// A value found in a [`Cache`], fresh or stale.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Lookup<V> {
+    Fresh(V),
+    Stale(V),
+}
+
+impl<K, V> Default for Cache<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V: Clone> Cache<K, V> {
+    /// Creates a new empty `Cache`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts `value` for `key`, replacing any previous value, until `ttl` elapses.
+    pub fn insert(&self, key: K, value: V, ttl: Duration) {
+        self.insert_stale(key, value, ttl, Duration::ZERO);
+    }
+
+    /// Returns the value of `key`, unless it is missing or expired.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.lookup(key)? {
+            Lookup::Fresh(value) => Some(value),
+            Lookup::Stale(_) => None,
+        }
+    }
+
+    /// Removes `key`, returning its value if it was cached, even expired.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lock().remove(key).map(|e| e.value)
+    }
+
+    /// Drops the expired entries.
+    pub fn purge_expired(&self) {
+        let now = Instant::now();
+        self.lock().retain(|_, e| e.stale_until > now);
+    }
+
+    /// Removes all entries.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Returns the number of entries, including the expired entries not dropped yet.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Inserts `value` for `key`, fresh until `ttl` elapses, and kept `stale` longer.
+    pub(crate) fn insert_stale(&self, key: K, value: V, ttl: Duration, stale: Duration) {
+        let now = Instant::now();
+        let expires_at = now.checked_add(ttl).unwrap_or_else(far_future);
+        let stale_until = expires_at.checked_add(stale).unwrap_or_else(far_future);
+        self.lock().insert(
+            key,
+            Entry {
+                value,
+                expires_at,
+                stale_until,
+            },
+        );
+    }
+
+    /// Returns the value of `key`, fresh or stale, dropping it if it is too old.
+    pub(crate) fn lookup<Q>(&self, key: &Q) -> Option<Lookup<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lookup_ttl(key).map(|(value, _)| value)
+    }
+
+    /// Like [`Cache::lookup`], also returning the time left until the value expires,
+    /// zero if it is stale.
+    pub(crate) fn lookup_ttl<Q>(&self, key: &Q) -> Option<(Lookup<V>, Duration)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        let mut entries = self.lock();
+        let entry = entries.get(key)?;
+        if now < entry.expires_at {
+            Some((Lookup::Fresh(entry.value.clone()), entry.expires_at - now))
+        } else if now < entry.stale_until {
+            Some((Lookup::Stale(entry.value.clone()), Duration::ZERO))
+        } else {
+            entries.remove(key);
+            None
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<K, Entry<V>>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Returns an instant later than any time to live, about 30 years from now.
+fn far_future() -> Instant {
+    Instant::now() + Duration::from_secs(86_400 * 365 * 30)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn cache_should_expire_entries() {
+        let cache = Cache::new();
+        cache.insert("a", 1, Duration::from_secs(10));
+        cache.insert("b", 2, Duration::from_secs(20));
+        cache.insert_stale("c", 3, Duration::from_secs(10), Duration::from_secs(10));
+        cache.insert("forever", 4, Duration::MAX);
+        assert_eq!(cache.get("a"), Some(1));
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), Some(2));
+        assert_eq!(cache.lookup("c"), Some(Lookup::Stale(3)));
+        assert_eq!(cache.len(), 3);
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        cache.purge_expired();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.remove("forever"), Some(4));
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,226 @@
+use std::{future::Future, hash::Hash, sync::Arc, time::Duration};
+
+use crate::{cache::Lookup, Cache, SingleFlight};
+
+/// A [`Cache`] filled by coalesced calls, i.e. a cached [`SingleFlight`].
+///
+/// [`CachedSingleFlight::get_or_compute`] returns the cached value of a key, and on
+/// a miss computes it once for all concurrent callers and caches it. This is the
+/// usual shape of a cache in front of a slow service or database, without the
+/// stampede of identical queries when a popular entry expires.
+///
+/// With [stale-while-revalidate](CachedSingleFlight::with_stale_while_revalidate),
+/// an expired value is still returned right away for a while, and recomputed in the
+/// background, so that callers do not wait for popular entries to be recomputed.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_cache::CachedSingleFlight;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let users = CachedSingleFlight::new()
+///     .with_stale_while_revalidate(Duration::from_secs(30));
+///
+/// let name = users
+///     .get_or_compute(1, Duration::from_secs(60), || async { String::from("alice") })
+///     .await;
+/// assert_eq!(name, "alice");
+///
+/// // cached: the function is not called
+/// let name = users
+///     .get_or_compute(1, Duration::from_secs(60), || async { unreachable!() })
+///     .await;
+/// assert_eq!(name, "alice");
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CachedSingleFlight<K, V> {
+    inner: Arc<Inner<K, V>>,
+    stale_while_revalidate: Duration,
+}
+
+#[derive(Debug)]
+struct Inner<K, V> {
+    cache: Cache<K, V>,
+    flight: SingleFlight<K, V>,
+}
+
+impl<K, V> Default for CachedSingleFlight<K, V> {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                cache: Cache::default(),
+                flight: SingleFlight::default(),
+            }),
+            stale_while_revalidate: Duration::ZERO,
+        }
+    }
+}
+
+impl<K, V> CachedSingleFlight<K, V>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Creates a new empty `CachedSingleFlight`, without stale-while-revalidate.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps returning expired values for `window` after they expire, while they
+    /// are recomputed in the background.
+    pub fn with_stale_while_revalidate(mut self, window: Duration) -> Self {
+        self.stale_while_revalidate = window;
+        self
+    }
+
+    /// Returns the cached value of `key`, or computes it with `f` and caches it for
+    /// `ttl`.
+    ///
+    /// Concurrent callers missing the same key wait for a single call of `f`. A
+    /// stale value is returned right away, and recomputed on the tokio runtime, so
+    /// the function must be called within a tokio runtime.
+    pub async fn get_or_compute<F, Fut>(&self, key: K, ttl: Duration, f: F) -> V
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = V> + Send + 'static,
+    {
+        let f = || async { Ok::<_, std::convert::Infallible>(f().await) };
+        match self.try_get_or_compute(key, ttl, f).await {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Returns the cached value of `key`, or computes it with the fallible `f` and
+    /// caches it for `ttl`.
+    ///
+    /// Errors are not cached. A caller waiting for a call of `f` that fails calls its
+    /// own `f`, and a failed recomputation of a stale value keeps the stale value
+    /// until it is dropped.
+    pub async fn try_get_or_compute<F, Fut, E>(&self, key: K, ttl: Duration, f: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<V, E>> + Send + 'static,
+        E: Send + 'static,
+    {
+        match self.inner.cache.lookup(&key) {
+            Some(Lookup::Fresh(value)) => Ok(value),
+            Some(Lookup::Stale(value)) => {
+                let inner = self.inner.clone();
+                let stale = self.stale_while_revalidate;
+                tokio::spawn(async move {
+                    let _ = inner.compute(key, ttl, stale, f).await;
+                });
+                Ok(value)
+            }
+            None => {
+                let stale = self.stale_while_revalidate;
+                self.inner.compute(key, ttl, stale, f).await
+            }
+        }
+    }
+
+    /// Removes the cached value of `key`.
+    pub fn invalidate(&self, key: &K) {
+        self.inner.cache.remove(key);
+    }
+
+    /// Returns the cache holding the computed values.
+    pub fn cache(&self) -> &Cache<K, V> {
+        &self.inner.cache
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Inner<K, V> {
+    /// Computes the value of `key` once for all concurrent callers, and caches it.
+    async fn compute<F, Fut, E>(&self, key: K, ttl: Duration, stale: Duration, f: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        self.flight
+            .try_work(key.clone(), || async {
+                // a call that completed since the lookup already cached the value
+                if let Some(Lookup::Fresh(value)) = self.cache.lookup(&key) {
+                    return Ok(value);
+                }
+                let value = f().await?;
+                self.cache
+                    .insert_stale(key.clone(), value.clone(), ttl, stale);
+                Ok(value)
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::time::{advance, sleep};
+
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[tokio::test(start_paused = true)]
+    async fn cached_single_flight_should_compute_misses_once() {
+        let cached = CachedSingleFlight::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let query = |value: u32| {
+            let calls = calls.clone();
+            move || async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                sleep(Duration::from_millis(10)).await;
+                value
+            }
+        };
+
+        let (a, b) = tokio::join!(
+            cached.get_or_compute("a", TTL, query(1)),
+            cached.get_or_compute("a", TTL, query(2)),
+        );
+        assert_eq!((a, b), (1, 1));
+        assert_eq!(cached.get_or_compute("a", TTL, query(3)).await, 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // expired without stale-while-revalidate: the caller waits for the new value
+        advance(TTL).await;
+        assert_eq!(cached.get_or_compute("a", TTL, query(4)).await, 4);
+
+        // errors are not cached
+        let failed = cached
+            .try_get_or_compute("b", TTL, || async { Err("unavailable") })
+            .await;
+        assert_eq!(failed, Err("unavailable"));
+        assert_eq!(cached.get_or_compute("b", TTL, query(5)).await, 5);
+
+        cached.invalidate(&"b");
+        assert_eq!(cached.get_or_compute("b", TTL, query(6)).await, 6);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cached_single_flight_should_revalidate_stale_values() {
+        let cached = CachedSingleFlight::new().with_stale_while_revalidate(TTL);
+        assert_eq!(cached.get_or_compute("a", TTL, || async { 1 }).await, 1);
+
+        // the stale value is returned while the new one is computed
+        advance(TTL).await;
+        let slow = || async {
+            sleep(Duration::from_millis(10)).await;
+            2
+        };
+        assert_eq!(cached.get_or_compute("a", TTL, slow).await, 1);
+        assert_eq!(cached.get_or_compute("a", TTL, slow).await, 1);
+        sleep(Duration::from_millis(20)).await;
+        assert_eq!(cached.cache().get("a"), Some(2));
+
+        // too old to be returned
+        advance(TTL * 2).await;
+        assert_eq!(cached.get_or_compute("a", TTL, || async { 3 }).await, 3);
+    }
+}
@@ -0,0 +1,9 @@
+mod cache;
+mod cached;
+mod loading;
+mod singleflight;
+
+pub use cache::Cache;
+pub use cached::CachedSingleFlight;
+pub use loading::LoadingCache;
+pub use singleflight::SingleFlight;
@@ -0,0 +1,262 @@
+use std::{future::Future, hash::Hash, sync::Arc, time::Duration};
+
+use crate::{cache::Lookup, Cache, SingleFlight};
+
+/// A [`Cache`] loading its missing values itself, with the features of the
+/// heavyweight cache crates: coalesced loads, negative caching and refresh-ahead.
+///
+/// [`LoadingCache::get_with`] returns the cached value of a key, and on a miss
+/// loads it once for all concurrent callers, so an expired popular entry does not
+/// send a stampede of identical queries to the backend.
+///
+/// A loader may find that the key has no value, e.g. a user that does not exist.
+/// With [negative caching](LoadingCache::with_negative_ttl), that answer is cached
+/// too, usually for a shorter time, so lookups of missing keys do not all reach the
+/// backend either.
+///
+/// With [refresh-ahead](LoadingCache::with_refresh_ahead), a value read shortly
+/// before it expires is reloaded in the background, while the callers keep getting
+/// the current value, so popular entries never expire and no caller waits for them.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_cache::LoadingCache;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let users = LoadingCache::new(Duration::from_secs(60))
+///     .with_negative_ttl(Duration::from_secs(5))
+///     .with_refresh_ahead(Duration::from_secs(10));
+///
+/// let name = users.get_with(1, || async { Some(String::from("alice")) }).await;
+/// assert_eq!(name.as_deref(), Some("alice"));
+///
+/// // the missing user is cached: the loader is not called again
+/// assert_eq!(users.get_with(2, || async { None }).await, None);
+/// assert_eq!(users.get_with(2, || async { unreachable!() }).await, None);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LoadingCache<K, V> {
+    inner: Arc<Inner<K, V>>,
+    policy: Policy,
+}
+
+#[derive(Debug)]
+struct Inner<K, V> {
+    /// The loaded values, `None` for the keys without a value.
+    cache: Cache<K, Option<V>>,
+    flight: SingleFlight<K, Option<V>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Policy {
+    /// How long a value is cached.
+    ttl: Duration,
+    /// How long the absence of a value is cached.
+    negative_ttl: Duration,
+    /// How long before a value expires it is reloaded when read.
+    refresh_ahead: Duration,
+}
+
+impl<K, V> LoadingCache<K, V>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Creates a new empty `LoadingCache` caching values for `ttl`, without
+    /// negative caching or refresh-ahead.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                cache: Cache::default(),
+                flight: SingleFlight::default(),
+            }),
+            policy: Policy {
+                ttl,
+                negative_ttl: Duration::ZERO,
+                refresh_ahead: Duration::ZERO,
+            },
+        }
+    }
+
+    /// Caches the absence of a value for `ttl`, instead of loading it again on
+    /// every lookup.
+    pub fn with_negative_ttl(mut self, ttl: Duration) -> Self {
+        self.policy.negative_ttl = ttl;
+        self
+    }
+
+    /// Reloads a value in the background when it is read less than `window` before
+    /// it expires.
+    ///
+    /// The reload runs on the tokio runtime, so the lookups must then be made within
+    /// a tokio runtime.
+    pub fn with_refresh_ahead(mut self, window: Duration) -> Self {
+        self.policy.refresh_ahead = window;
+        self
+    }
+
+    /// Returns the cached value of `key`, or loads it with `loader` and caches it.
+    ///
+    /// Concurrent callers missing the same key wait for a single call of `loader`.
+    /// A `None` from the loader is cached for the negative TTL.
+    pub async fn get_with<F, Fut>(&self, key: K, loader: F) -> Option<V>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Option<V>> + Send + 'static,
+    {
+        let loader = || async { Ok::<_, std::convert::Infallible>(loader().await) };
+        match self.try_get_with(key, loader).await {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Returns the cached value of `key`, or loads it with the fallible `loader`
+    /// and caches it.
+    ///
+    /// Errors are not cached. A caller waiting for a load that fails calls its own
+    /// `loader`, and a failed refresh-ahead keeps the current value until it
+    /// expires.
+    pub async fn try_get_with<F, Fut, E>(&self, key: K, loader: F) -> Result<Option<V>, E>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Option<V>, E>> + Send + 'static,
+        E: Send + 'static,
+    {
+        let policy = self.policy;
+        match self.inner.cache.lookup_ttl(&key) {
+            Some((Lookup::Fresh(value), left)) => {
+                if value.is_some() && left <= policy.refresh_ahead {
+                    let inner = self.inner.clone();
+                    tokio::spawn(async move {
+                        let _ = inner.load(key, policy, loader).await;
+                    });
+                }
+                Ok(value)
+            }
+            // the cache never keeps stale values
+            Some((Lookup::Stale(_), _)) | None => self.inner.load(key, policy, loader).await,
+        }
+    }
+
+    /// Removes the cached value of `key`, or its cached absence.
+    pub fn invalidate(&self, key: &K) {
+        self.inner.cache.remove(key);
+    }
+
+    /// Returns the cache holding the loaded values, `None` for the keys without a
+    /// value.
+    pub fn cache(&self) -> &Cache<K, Option<V>> {
+        &self.inner.cache
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Inner<K, V> {
+    /// Loads the value of `key` once for all concurrent callers, and caches it.
+    async fn load<F, Fut, E>(&self, key: K, policy: Policy, loader: F) -> Result<Option<V>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Option<V>, E>>,
+    {
+        self.flight
+            .try_work(key.clone(), || async {
+                // a load that completed since the lookup already cached the value
+                if let Some((Lookup::Fresh(value), left)) = self.cache.lookup_ttl(&key) {
+                    if value.is_none() || left > policy.refresh_ahead {
+                        return Ok(value);
+                    }
+                }
+                let value = loader().await?;
+                let ttl = match value {
+                    Some(_) => policy.ttl,
+                    None => policy.negative_ttl,
+                };
+                if !ttl.is_zero() {
+                    self.cache.insert(key.clone(), value.clone(), ttl);
+                }
+                Ok(value)
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::time::{advance, sleep};
+
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[tokio::test(start_paused = true)]
+    async fn loading_cache_should_coalesce_loads_and_cache_absence() {
+        let cache = LoadingCache::new(TTL).with_negative_ttl(TTL / 10);
+        let loads = Arc::new(AtomicUsize::new(0));
+        let loader = |value: Option<u32>| {
+            let loads = loads.clone();
+            move || async move {
+                loads.fetch_add(1, Ordering::SeqCst);
+                sleep(Duration::from_millis(10)).await;
+                value
+            }
+        };
+
+        let (a, b) = tokio::join!(
+            cache.get_with("a", loader(Some(1))),
+            cache.get_with("a", loader(Some(2))),
+        );
+        assert_eq!((a, b), (Some(1), Some(1)));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        // the absence of a value is cached for the negative TTL
+        assert_eq!(cache.get_with("b", loader(None)).await, None);
+        assert_eq!(cache.get_with("b", loader(Some(3))).await, None);
+        advance(TTL / 10).await;
+        assert_eq!(cache.get_with("b", loader(Some(3))).await, Some(3));
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+
+        // errors are not cached
+        let failed = cache
+            .try_get_with("c", || async { Err("unavailable") })
+            .await;
+        assert_eq!(failed, Err("unavailable"));
+        assert_eq!(cache.get_with("c", loader(Some(4))).await, Some(4));
+
+        // without a negative TTL, the absence of a value is not cached
+        let cache = LoadingCache::new(TTL);
+        assert_eq!(cache.get_with("a", || async { None }).await, None);
+        assert_eq!(cache.get_with("a", || async { Some(1) }).await, Some(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn loading_cache_should_refresh_ahead_of_expiry() {
+        let cache = LoadingCache::new(TTL).with_refresh_ahead(TTL / 4);
+        assert_eq!(cache.get_with("a", || async { Some(1) }).await, Some(1));
+
+        // not close enough to expiry
+        assert_eq!(cache.get_with("a", || async { Some(2) }).await, Some(1));
+        sleep(Duration::from_millis(1)).await;
+        assert_eq!(cache.cache().get("a"), Some(Some(1)));
+
+        // the current value is returned while the new one is loaded
+        advance(TTL - TTL / 4).await;
+        let slow = || async {
+            sleep(Duration::from_millis(10)).await;
+            Some(3)
+        };
+        assert_eq!(cache.get_with("a", slow).await, Some(1));
+        assert_eq!(cache.get_with("a", slow).await, Some(1));
+        sleep(Duration::from_millis(20)).await;
+        assert_eq!(cache.cache().get("a"), Some(Some(3)));
+
+        // the value never expired
+        advance(TTL / 2).await;
+        assert_eq!(cache.get_with("a", || async { Some(4) }).await, Some(3));
+    }
+}
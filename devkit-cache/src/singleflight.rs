@@ -0,0 +1,186 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use tokio::sync::OnceCell;
+
+/// Coalesces concurrent calls for the same key into one.
+///
+/// The first caller for a key runs its function, and the callers arriving while it
+/// runs wait for its result instead of running their own. This keeps a burst of
+/// identical requests, e.g. for the same missing cache entry, from reaching the
+/// backend more than once.
+///
+/// Calls are cancellation safe: if the caller running the function is dropped, one
+/// of the waiting callers runs its own function instead.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use devkit_cache::SingleFlight;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let flight = SingleFlight::new();
+/// let queries = AtomicUsize::new(0);
+/// let query = || async {
+///     queries.fetch_add(1, Ordering::SeqCst);
+///     tokio::task::yield_now().await;
+///     "alice"
+/// };
+///
+/// let (a, b) = tokio::join!(flight.work("user:1", query), flight.work("user:1", query));
+/// assert_eq!((a, b), ("alice", "alice"));
+/// assert_eq!(queries.load(Ordering::SeqCst), 1);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SingleFlight<K, V> {
+    calls: Mutex<HashMap<K, Arc<OnceCell<V>>>>,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> SingleFlight<K, V> {
+    /// Creates a new `SingleFlight` without calls in flight.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` for `key`, unless a call for `key` is in flight, in which case its
+    /// result is awaited instead.
+    pub async fn work<F, Fut>(&self, key: K, f: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let call = self.join(key);
+        call.cell.get_or_init(f).await.clone()
+    }
+
+    /// Runs the fallible `f` for `key`, unless a call for `key` is in flight, in
+    /// which case its result is awaited instead.
+    ///
+    /// Errors are not shared: when the call in flight fails, the error is returned
+    /// to its caller only, and one of the waiting callers runs its own `f`.
+    pub async fn try_work<F, Fut, E>(&self, key: K, f: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        let call = self.join(key);
+        call.cell.get_or_try_init(f).await.cloned()
+    }
+
+    /// Returns the number of keys with a call in flight.
+    pub fn in_flight(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Joins the call in flight for `key`, or starts one.
+    fn join(&self, key: K) -> Call<'_, K, V> {
+        let cell = self.lock().entry(key.clone()).or_default().clone();
+        Call {
+            flight: self,
+            key,
+            cell,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<K, Arc<OnceCell<V>>>> {
+        self.calls.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A caller of a call in flight, ending the call once it completed or has no
+/// caller left.
+struct Call<'a, K: Hash + Eq + Clone, V: Clone> {
+    flight: &'a SingleFlight<K, V>,
+    key: K,
+    cell: Arc<OnceCell<V>>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Drop for Call<'_, K, V> {
+    fn drop(&mut self) {
+        let mut calls = self.flight.lock();
+        let Some(cell) = calls.get(&self.key) else {
+            return;
+        };
+        // the map and this caller are the last holders of an unfinished call
+        if Arc::ptr_eq(cell, &self.cell)
+            && (self.cell.initialized() || Arc::strong_count(&self.cell) == 2)
+        {
+            calls.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use tokio::time::sleep;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn single_flight_should_coalesce_concurrent_calls() {
+        let flight = SingleFlight::new();
+        let calls = AtomicUsize::new(0);
+        let query = |value| {
+            let calls = &calls;
+            move || async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                sleep(Duration::from_millis(10)).await;
+                value
+            }
+        };
+
+        let (a, b, c) = tokio::join!(
+            flight.work("a", query(1)),
+            flight.work("a", query(2)),
+            flight.work("b", query(3)),
+        );
+        assert_eq!((a, b, c), (1, 1, 3));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(flight.in_flight(), 0);
+
+        // a call after the first one completed runs again
+        assert_eq!(flight.work("a", query(4)).await, 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn single_flight_should_survive_failed_and_cancelled_calls() {
+        let flight = SingleFlight::new();
+        let fail = || async {
+            sleep(Duration::from_millis(10)).await;
+            Err("unavailable")
+        };
+        let succeed = || async { Ok::<_, &str>(2) };
+        let (a, b) = tokio::join!(flight.try_work("a", fail), flight.try_work("a", succeed));
+        assert_eq!((a, b), (Err("unavailable"), Ok(2)));
+
+        // the caller running the call is dropped: the call does not leak
+        let slow = flight.work("b", || async {
+            sleep(Duration::from_secs(60)).await;
+            3
+        });
+        assert!(tokio::time::timeout(Duration::from_millis(10), slow)
+            .await
+            .is_err());
+        assert_eq!(flight.in_flight(), 0);
+    }
+}
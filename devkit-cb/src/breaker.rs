@@ -0,0 +1,548 @@
+use std::{
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use devkit_rl::{Clock, StdClock};
+
+use crate::{trip::RollingStats, CallStats, HalfOpenPolicy, TripPolicy};
+
+/// When a [`CircuitBreaker`] opens, and how it closes again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BreakerPolicy {
+    /// When the circuit opens.
+    pub trip: TripPolicy,
+    /// How long the circuit stays open before letting calls through again.
+    pub open_for: Duration,
+    /// Which calls are let through once the circuit is half-open.
+    pub half_open: HalfOpenPolicy,
+    /// The calls that may run at once while the circuit is half-open, at least 1.
+    pub max_half_open_calls: usize,
+}
+
+impl Default for BreakerPolicy {
+    /// Opens after 5 consecutive failures for 30s, then lets a single probe through.
+    fn default() -> Self {
+        Self {
+            trip: TripPolicy::ConsecutiveFailures { failures: 5 },
+            open_for: Duration::from_secs(30),
+            half_open: HalfOpenPolicy::Probes { probes: 1 },
+            max_half_open_calls: 1,
+        }
+    }
+}
+
+/// The state of a [`CircuitBreaker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// All calls are let through.
+    Closed,
+    /// All calls are rejected.
+    Open,
+    /// Some calls are let through, according to the [`HalfOpenPolicy`], to find
+    /// out whether the service has recovered.
+    HalfOpen,
+}
+
+/// A circuit breaker, rejecting the calls to a failing service for a while
+/// instead of piling more load onto it.
+///
+/// A call first asks for a [`CallPermit`], then reports its result on it. The
+/// circuit opens according to the [`TripPolicy`], after consecutive failures or
+/// when the rate of failed or slow calls gets too high, and rejects every call for
+/// [`BreakerPolicy::open_for`]. It is then half-open: the [`HalfOpenPolicy`]
+/// decides which calls are let through, a failed or slow call opens the circuit
+/// again, and enough successes close it. A single probe is rarely enough
+/// for a service under heavy traffic, which may handle one call fine and fall over
+/// again once all of them come back; a percentage or a ramp-up lets the traffic
+/// back in gradually instead.
+///
+/// While half-open, at most [`BreakerPolicy::max_half_open_calls`] permits are out
+/// at once, whatever the policy, so a burst of slow calls cannot swamp a service
+/// that is still recovering.
+///
+/// The results of calls let through in a previous state are ignored: a slow call
+/// failing after the circuit opened does not count towards the next opening.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_cb::{BreakerPolicy, CircuitBreaker, HalfOpenPolicy, RampCurve, State, TripPolicy};
+///
+/// let breaker = CircuitBreaker::new(BreakerPolicy {
+///     trip: TripPolicy::ConsecutiveFailures { failures: 2 },
+///     open_for: Duration::from_secs(10),
+///     half_open: HalfOpenPolicy::RampUp {
+///         start_percent: 10,
+///         duration: Duration::from_secs(60),
+///         curve: RampCurve::Linear,
+///     },
+///     max_half_open_calls: 8,
+/// });
+///
+/// for _ in 0..2 {
+///     let permit = breaker.try_acquire().unwrap();
+///     permit.failure();
+/// }
+/// assert_eq!(breaker.state(), State::Open);
+/// assert!(breaker.try_acquire().is_none());
+/// ```
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    policy: BreakerPolicy,
+    state: State,
+    /// Incremented on every change of state, to tell the permits of the previous
+    /// states apart.
+    generation: u64,
+    /// When the current state was entered.
+    since: Duration,
+    /// The consecutive failures while closed.
+    failures: u32,
+    /// The calls of the sliding window while closed, if the trip policy needs it.
+    stats: Option<RollingStats>,
+    /// The calls asking for a permit while half-open, and not rejected for
+    /// concurrency.
+    offered: u64,
+    /// The calls let through while half-open, less those given back.
+    admitted: u64,
+    /// The half-open permits out.
+    in_flight: usize,
+    /// The successful calls while half-open.
+    successes: u32,
+    /// The source of time.
+    clock: Arc<dyn Clock>,
+}
+
+/// The permission for a call to go through a [`CircuitBreaker`].
+///
+/// The result of the call is reported with [`CallPermit::success`] or
+/// [`CallPermit::failure`]. Dropping the permit without a result gives it back
+/// without counting the call, e.g. when the call is cancelled before reaching the
+/// service: a half-open probe may then be made by another call.
+#[derive(Debug)]
+#[must_use = "the call is not counted if the permit is dropped"]
+pub struct CallPermit {
+    inner: Arc<Mutex<Inner>>,
+    /// The generation of the breaker when the permit was given.
+    generation: u64,
+    /// Whether the permit was given while half-open.
+    half_open: bool,
+    /// When the permit was given.
+    started: Duration,
+    /// Whether the result has been reported.
+    done: bool,
+}
+
+impl CircuitBreaker {
+    /// Creates a new closed `CircuitBreaker`.
+    pub fn new(policy: BreakerPolicy) -> Self {
+        Self::with_clock(policy, Arc::new(StdClock::new()))
+    }
+
+    /// Creates a new closed `CircuitBreaker` reading the time from `clock`.
+    pub fn with_clock(policy: BreakerPolicy, clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                policy,
+                state: State::Closed,
+                generation: 0,
+                since: now,
+                failures: 0,
+                stats: policy.trip.rolling_stats(now),
+                offered: 0,
+                admitted: 0,
+                in_flight: 0,
+                successes: 0,
+                clock,
+            })),
+        }
+    }
+
+    /// Asks for a call to go through.
+    ///
+    /// # Returns
+    ///
+    /// The permit to report the result of the call on, or `None` if the call is
+    /// rejected: the circuit is open, or half-open and the call is not let through
+    /// by the policy, or too many half-open calls are running.
+    pub fn try_acquire(&self) -> Option<CallPermit> {
+        let mut inner = lock(&self.inner);
+        let half_open = inner.admit()?;
+        Some(CallPermit {
+            inner: self.inner.clone(),
+            generation: inner.generation,
+            half_open,
+            started: inner.clock.now(),
+            done: false,
+        })
+    }
+
+    /// Returns the state of the circuit.
+    pub fn state(&self) -> State {
+        let mut inner = lock(&self.inner);
+        inner.refresh();
+        inner.state
+    }
+
+    /// Returns how long the circuit stays open, or `None` if it is not open.
+    pub fn retry_after(&self) -> Option<Duration> {
+        let mut inner = lock(&self.inner);
+        inner.refresh();
+        (inner.state == State::Open).then(|| {
+            let elapsed = inner.clock.now().saturating_sub(inner.since);
+            inner.policy.open_for.saturating_sub(elapsed)
+        })
+    }
+
+    /// Returns the calls counted over the sliding window since the circuit last
+    /// closed, or `None` if the trip policy does not count them.
+    pub fn stats(&self) -> Option<CallStats> {
+        let mut inner = lock(&self.inner);
+        let now = inner.clock.now();
+        inner.stats.as_mut().map(|stats| stats.total(now))
+    }
+
+    /// Returns the half-open permits out.
+    pub fn half_open_in_flight(&self) -> usize {
+        lock(&self.inner).in_flight
+    }
+}
+
+impl CallPermit {
+    /// Reports the call as successful.
+    pub fn success(mut self) {
+        self.finish(Some(true));
+    }
+
+    /// Reports the call as failed.
+    pub fn failure(mut self) {
+        self.finish(Some(false));
+    }
+
+    fn finish(&mut self, success: Option<bool>) {
+        self.done = true;
+        lock(&self.inner).finish(self, success);
+    }
+}
+
+impl Drop for CallPermit {
+    fn drop(&mut self) {
+        if !self.done {
+            self.finish(None);
+        }
+    }
+}
+
+impl Inner {
+    /// Lets a call through, if it may go.
+    ///
+    /// # Returns
+    ///
+    /// Whether the call is let through while half-open, or `None` if it is rejected.
+    fn admit(&mut self) -> Option<bool> {
+        self.refresh();
+        match self.state {
+            State::Closed => Some(false),
+            State::Open => None,
+            State::HalfOpen => {
+                if self.in_flight >= self.policy.max_half_open_calls.max(1) {
+                    return None;
+                }
+                let policy = self.policy.half_open;
+                if policy.max_calls().is_some_and(|max| self.admitted >= max) {
+                    return None;
+                }
+
+                // lets through the share of the calls offered so far, spreading
+                // them evenly rather than at random
+                self.offered += 1;
+                let elapsed = self.clock.now().saturating_sub(self.since);
+                if self.admitted as f64 >= self.offered as f64 * policy.share(elapsed) {
+                    return None;
+                }
+                self.admitted += 1;
+                self.in_flight += 1;
+                Some(true)
+            }
+        }
+    }
+
+    /// Records the result of the call of `permit`, `None` if it was given back.
+    fn finish(&mut self, permit: &CallPermit, success: Option<bool>) {
+        if permit.generation != self.generation {
+            return;
+        }
+        if permit.half_open {
+            self.in_flight -= 1;
+        }
+
+        let Some(success) = success else {
+            // gives the probe back, the share of calls let through stays as it was
+            if self.state == State::HalfOpen && self.policy.half_open.max_calls().is_some() {
+                self.admitted -= 1;
+            }
+            return;
+        };
+        let now = self.clock.now();
+        let slow = self.policy.trip.is_slow(now.saturating_sub(permit.started));
+
+        match self.state {
+            State::Closed => {
+                self.failures = if success { 0 } else { self.failures + 1 };
+                let stats = self.stats.as_mut().map(|stats| {
+                    stats.record(now, success, slow);
+                    stats.total(now)
+                });
+                if self.policy.trip.should_open(self.failures, stats) {
+                    self.enter(State::Open);
+                }
+            }
+            State::HalfOpen if success && !slow => {
+                self.successes += 1;
+                self.refresh();
+            }
+            State::HalfOpen => self.enter(State::Open),
+            State::Open => {}
+        }
+    }
+
+    /// Moves on to the next state if the current one is over.
+    fn refresh(&mut self) {
+        let elapsed = self.clock.now().saturating_sub(self.since);
+        match self.state {
+            State::Open if elapsed >= self.policy.open_for => self.enter(State::HalfOpen),
+            State::HalfOpen if self.policy.half_open.should_close(self.successes, elapsed) => {
+                self.enter(State::Closed)
+            }
+            _ => {}
+        }
+    }
+
+    fn enter(&mut self, state: State) {
+        self.state = state;
+        self.generation += 1;
+        self.since = self.clock.now();
+        self.failures = 0;
+        self.stats = self.policy.trip.rolling_stats(self.since);
+        self.offered = 0;
+        self.admitted = 0;
+        self.in_flight = 0;
+        self.successes = 0;
+    }
+}
+
+impl std::fmt::Debug for CircuitBreaker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&*lock(&self.inner), f)
+    }
+}
+
+impl std::fmt::Debug for Inner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("policy", &self.policy)
+            .field("state", &self.state)
+            .field("failures", &self.failures)
+            .field("in_flight", &self.in_flight)
+            .finish_non_exhaustive()
+    }
+}
+
+fn lock(inner: &Mutex<Inner>) -> MutexGuard<'_, Inner> {
+    inner.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use devkit_rl::ManualClock;
+
+    use super::*;
+
+    const OPEN_FOR: Duration = Duration::from_secs(10);
+
+    fn breaker(
+        half_open: HalfOpenPolicy,
+        max_half_open_calls: usize,
+    ) -> (CircuitBreaker, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new());
+        let breaker = CircuitBreaker::with_clock(
+            BreakerPolicy {
+                trip: TripPolicy::ConsecutiveFailures { failures: 2 },
+                open_for: OPEN_FOR,
+                half_open,
+                max_half_open_calls,
+            },
+            clock.clone(),
+        );
+        (breaker, clock)
+    }
+
+    fn trip(breaker: &CircuitBreaker) {
+        for _ in 0..2 {
+            breaker.try_acquire().unwrap().failure();
+        }
+        assert_eq!(breaker.state(), State::Open);
+    }
+
+    #[test]
+    fn circuit_breaker_should_bound_half_open_probes() {
+        let (breaker, clock) = breaker(HalfOpenPolicy::Probes { probes: 2 }, 1);
+
+        // a success resets the consecutive failures
+        breaker.try_acquire().unwrap().failure();
+        breaker.try_acquire().unwrap().success();
+        breaker.try_acquire().unwrap().failure();
+        assert_eq!(breaker.state(), State::Closed);
+
+        // a call let through before the circuit opened does not count afterwards
+        let slow = breaker.try_acquire().unwrap();
+        breaker.try_acquire().unwrap().failure();
+        assert_eq!(breaker.state(), State::Open);
+        slow.failure();
+        assert_eq!(breaker.retry_after(), Some(OPEN_FOR));
+        assert!(breaker.try_acquire().is_none());
+
+        // a single probe runs at once, and a dropped one is given back
+        clock.advance(OPEN_FOR);
+        assert_eq!(breaker.state(), State::HalfOpen);
+        let probe = breaker.try_acquire().unwrap();
+        assert!(breaker.try_acquire().is_none());
+        drop(probe);
+        breaker.try_acquire().unwrap().success();
+        assert_eq!(breaker.half_open_in_flight(), 0);
+
+        // a failed probe opens the circuit again
+        breaker.try_acquire().unwrap().failure();
+        assert_eq!(breaker.state(), State::Open);
+
+        clock.advance(OPEN_FOR);
+        breaker.try_acquire().unwrap().success();
+        assert_eq!(breaker.state(), State::HalfOpen);
+        breaker.try_acquire().unwrap().success();
+        assert_eq!(breaker.state(), State::Closed);
+    }
+
+    #[test]
+    fn circuit_breaker_should_let_a_share_of_calls_through_when_half_open() {
+        let (breaker, clock) = breaker(
+            HalfOpenPolicy::Percentage {
+                percent: 25,
+                successes: 3,
+            },
+            2,
+        );
+        trip(&breaker);
+        clock.advance(OPEN_FOR);
+
+        let permits: Vec<_> = (0..8).map(|_| breaker.try_acquire()).collect();
+        let admitted: Vec<_> = permits.iter().map(Option::is_some).collect();
+        assert_eq!(
+            admitted,
+            [true, false, false, false, true, false, false, false]
+        );
+
+        // the calls rejected for concurrency do not count as offered
+        assert!((0..4).all(|_| breaker.try_acquire().is_none()));
+        permits.into_iter().flatten().for_each(CallPermit::success);
+        let next = (0..4).find_map(|_| breaker.try_acquire()).unwrap();
+        next.success();
+        assert_eq!(breaker.state(), State::Closed);
+    }
+
+    #[test]
+    fn circuit_breaker_should_close_at_the_end_of_the_ramp() {
+        const RAMP: Duration = Duration::from_secs(100);
+
+        let (breaker, clock) = breaker(
+            HalfOpenPolicy::RampUp {
+                start_percent: 10,
+                duration: RAMP,
+                curve: crate::RampCurve::Linear,
+            },
+            usize::MAX,
+        );
+        trip(&breaker);
+        clock.advance(OPEN_FOR);
+
+        let through = |n| (0..n).filter_map(|_| breaker.try_acquire()).count();
+        assert_eq!(through(100), 10);
+        clock.advance(RAMP / 2);
+        // 55% of the 200 calls offered so far
+        assert_eq!(through(100), 100);
+
+        clock.advance(RAMP / 2);
+        assert_eq!(breaker.state(), State::Closed);
+    }
+
+    #[test]
+    fn circuit_breaker_should_open_on_failure_rate_over_the_window() {
+        const WINDOW: Duration = Duration::from_secs(10);
+        const SLOW_CALL: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(ManualClock::new());
+        let breaker = CircuitBreaker::with_clock(
+            BreakerPolicy {
+                trip: TripPolicy::FailureRate {
+                    window: WINDOW,
+                    buckets: 5,
+                    min_calls: 4,
+                    failure_rate: 0.5,
+                    slow_call: SLOW_CALL,
+                    slow_call_rate: 0.5,
+                },
+                open_for: OPEN_FOR,
+                half_open: HalfOpenPolicy::Probes { probes: 1 },
+                max_half_open_calls: 1,
+            },
+            clock.clone(),
+        );
+
+        // too few calls for the rates to count
+        breaker.try_acquire().unwrap().failure();
+        breaker.try_acquire().unwrap().failure();
+        breaker.try_acquire().unwrap().success();
+        assert_eq!(breaker.state(), State::Closed);
+
+        // the calls slide out of the window
+        clock.advance(WINDOW);
+        breaker.try_acquire().unwrap().success();
+        let stats = CallStats {
+            successes: 1,
+            failures: 0,
+            slow: 0,
+        };
+        assert_eq!(breaker.stats(), Some(stats));
+        breaker.try_acquire().unwrap().success();
+        breaker.try_acquire().unwrap().failure();
+        assert_eq!(breaker.state(), State::Closed);
+        breaker.try_acquire().unwrap().failure();
+        assert_eq!(breaker.state(), State::Open);
+
+        // a slow probe fails
+        clock.advance(OPEN_FOR);
+        let probe = breaker.try_acquire().unwrap();
+        clock.advance(SLOW_CALL);
+        probe.success();
+        assert_eq!(breaker.state(), State::Open);
+
+        // the window starts over once the circuit closes
+        clock.advance(OPEN_FOR);
+        breaker.try_acquire().unwrap().success();
+        assert_eq!(breaker.state(), State::Closed);
+        assert_eq!(breaker.stats(), Some(CallStats::default()));
+
+        // too many slow calls open the circuit, even successful
+        breaker.try_acquire().unwrap().success();
+        breaker.try_acquire().unwrap().success();
+        let slow: Vec<_> = (0..2).map(|_| breaker.try_acquire().unwrap()).collect();
+        clock.advance(SLOW_CALL * 2);
+        slow.into_iter().for_each(CallPermit::success);
+        assert_eq!(breaker.state(), State::Open);
+    }
+}
@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+/// How a [`CircuitBreaker`](crate::CircuitBreaker) lets traffic back in once its
+/// open period is over.
+///
+/// Whatever the policy, a failure during the half-open state opens the circuit
+/// again, and at most [`BreakerPolicy::max_half_open_calls`](crate::BreakerPolicy::max_half_open_calls)
+/// calls run at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HalfOpenPolicy {
+    /// Lets `probes` calls through, and closes the circuit once all of them
+    /// succeeded. The other calls are rejected meanwhile.
+    Probes {
+        /// The calls to let through, at least 1.
+        probes: u32,
+    },
+    /// Lets `percent` of the calls through, and closes the circuit after
+    /// `successes` of them succeeded.
+    Percentage {
+        /// The share of the calls let through, from 1 to 100.
+        percent: u8,
+        /// The successful calls needed to close the circuit, at least 1.
+        successes: u32,
+    },
+    /// Lets a share of the calls through growing from `start_percent` to all of
+    /// them over `duration`, following `curve`, and closes the circuit at the end
+    /// of the ramp.
+    RampUp {
+        /// The share of the calls let through at the start of the ramp, from 1 to 100.
+        start_percent: u8,
+        /// How long the ramp lasts.
+        duration: Duration,
+        /// The shape of the ramp.
+        curve: RampCurve,
+    },
+}
+
+/// The shape of a [`HalfOpenPolicy::RampUp`], mapping the progress of the ramp
+/// to the progress of the share of calls let through, both from 0.0 to 1.0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RampCurve {
+    /// Grows the share at a constant pace.
+    #[default]
+    Linear,
+    /// Grows the share slowly at first, then faster, which keeps the load low
+    /// while the service is most likely to fail again.
+    Quadratic,
+    /// Grows the share in `steps` equal steps, holding each one for the same time.
+    Steps(u32),
+}
+
+impl RampCurve {
+    /// Returns the progress of the share of calls let through at `progress` of
+    /// the ramp.
+    pub fn at(&self, progress: f64) -> f64 {
+        let progress = progress.clamp(0.0, 1.0);
+        match *self {
+            RampCurve::Linear => progress,
+            RampCurve::Quadratic => progress * progress,
+            RampCurve::Steps(steps) => {
+                let steps = f64::from(steps.max(1));
+                (progress * steps).floor() / steps
+            }
+        }
+    }
+}
+
+impl HalfOpenPolicy {
+    /// Returns the share of the calls let through after `elapsed` in the
+    /// half-open state, from 0.0 to 1.0.
+    pub(crate) fn share(&self, elapsed: Duration) -> f64 {
+        match *self {
+            HalfOpenPolicy::Probes { .. } => 1.0,
+            HalfOpenPolicy::Percentage { percent, .. } => percent_share(percent),
+            HalfOpenPolicy::RampUp {
+                start_percent,
+                duration,
+                curve,
+            } => {
+                let progress = if duration.is_zero() {
+                    1.0
+                } else {
+                    elapsed.as_secs_f64() / duration.as_secs_f64()
+                };
+                let start = percent_share(start_percent);
+                start + (1.0 - start) * curve.at(progress)
+            }
+        }
+    }
+
+    /// Returns the calls that may be let through in total, if they are limited.
+    pub(crate) fn max_calls(&self) -> Option<u64> {
+        match *self {
+            HalfOpenPolicy::Probes { probes } => Some(u64::from(probes.max(1))),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the circuit closes after `successes` successful calls and
+    /// `elapsed` in the half-open state.
+    pub(crate) fn should_close(&self, successes: u32, elapsed: Duration) -> bool {
+        match *self {
+            HalfOpenPolicy::Probes { probes } => successes >= probes.max(1),
+            HalfOpenPolicy::Percentage { successes: n, .. } => successes >= n.max(1),
+            HalfOpenPolicy::RampUp { duration, .. } => elapsed >= duration,
+        }
+    }
+}
+
+fn percent_share(percent: u8) -> f64 {
+    f64::from(percent.clamp(1, 100)) / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramp_up_should_follow_its_curve() {
+        const RAMP: Duration = Duration::from_secs(10);
+
+        let ramp = |curve| HalfOpenPolicy::RampUp {
+            start_percent: 10,
+            duration: RAMP,
+            curve,
+        };
+
+        let linear = ramp(RampCurve::Linear);
+        assert!((linear.share(Duration::ZERO) - 0.1).abs() < 1e-9);
+        assert!((linear.share(RAMP / 2) - 0.55).abs() < 1e-9);
+        assert!((linear.share(RAMP * 2) - 1.0).abs() < 1e-9);
+
+        let quadratic = ramp(RampCurve::Quadratic);
+        assert!((quadratic.share(RAMP / 2) - 0.325).abs() < 1e-9);
+
+        let steps = ramp(RampCurve::Steps(4));
+        assert!((steps.share(RAMP / 5) - 0.1).abs() < 1e-9);
+        assert!((steps.share(RAMP / 4) - 0.325).abs() < 1e-9);
+        assert!((steps.share(RAMP) - 1.0).abs() < 1e-9);
+
+        assert!(!linear.should_close(100, RAMP / 2));
+        assert!(linear.should_close(0, RAMP));
+    }
+}
@@ -0,0 +1,7 @@
+mod breaker;
+mod half_open;
+mod trip;
+
+pub use breaker::{BreakerPolicy, CallPermit, CircuitBreaker, State};
+pub use half_open::{HalfOpenPolicy, RampCurve};
+pub use trip::{CallStats, TripPolicy};
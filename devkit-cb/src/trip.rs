@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use devkit_rl::BucketRing;
+
+/// When a [`CircuitBreaker`](crate::CircuitBreaker) opens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TripPolicy {
+    /// Opens after `failures` consecutive failures, at least 1.
+    ConsecutiveFailures {
+        /// The consecutive failures opening the circuit.
+        failures: u32,
+    },
+    /// Opens when too many of the calls of a sliding window fail, or are slow.
+    ///
+    /// The window is divided into buckets like a
+    /// [`SlidingWindowCount`](devkit_rl::SlidingWindowCount): the calls of the
+    /// oldest bucket stop counting all at once as it slides out of the window.
+    FailureRate {
+        /// The duration of the window.
+        window: Duration,
+        /// The number of buckets dividing the window.
+        buckets: u64,
+        /// The calls the window must hold before the rates are considered, so a
+        /// few calls after a quiet period do not open the circuit.
+        min_calls: u64,
+        /// The share of failed calls opening the circuit, from 0.0 to 1.0.
+        failure_rate: f64,
+        /// The duration after which a call is slow, whatever its result.
+        slow_call: Duration,
+        /// The share of slow calls opening the circuit, from 0.0 to 1.0, or above
+        /// 1.0 to never open for slow calls.
+        slow_call_rate: f64,
+    },
+}
+
+/// The calls counted by a [`CircuitBreaker`](crate::CircuitBreaker) over its
+/// sliding window, see [`TripPolicy::FailureRate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CallStats {
+    /// The successful calls.
+    pub successes: u64,
+    /// The failed calls.
+    pub failures: u64,
+    /// The slow calls, successful or not.
+    pub slow: u64,
+}
+
+impl CallStats {
+    /// Returns the calls counted.
+    pub fn calls(&self) -> u64 {
+        self.successes + self.failures
+    }
+
+    /// Returns the share of failed calls, or 0.0 if there are none.
+    pub fn failure_rate(&self) -> f64 {
+        self.rate(self.failures)
+    }
+
+    /// Returns the share of slow calls, or 0.0 if there are none.
+    pub fn slow_call_rate(&self) -> f64 {
+        self.rate(self.slow)
+    }
+
+    fn rate(&self, n: u64) -> f64 {
+        match self.calls() {
+            0 => 0.0,
+            calls => n as f64 / calls as f64,
+        }
+    }
+
+    fn add(&mut self, other: &CallStats) {
+        self.successes += other.successes;
+        self.failures += other.failures;
+        self.slow += other.slow;
+    }
+
+    fn sub(&mut self, other: &CallStats) {
+        self.successes -= other.successes;
+        self.failures -= other.failures;
+        self.slow -= other.slow;
+    }
+}
+
+/// The calls of the sliding window of a [`TripPolicy::FailureRate`].
+#[derive(Debug)]
+pub(crate) struct RollingStats {
+    buckets: BucketRing<CallStats>,
+    /// The sum of all buckets, kept up to date incrementally.
+    total: CallStats,
+}
+
+impl RollingStats {
+    /// Creates an empty window starting at `now`.
+    pub(crate) fn new(window: Duration, buckets: u64, now: Duration) -> Self {
+        Self {
+            buckets: BucketRing::new(window, buckets, now),
+            total: CallStats::default(),
+        }
+    }
+
+    /// Counts a call ending at `now`.
+    pub(crate) fn record(&mut self, now: Duration, success: bool, slow: bool) {
+        let call = CallStats {
+            successes: u64::from(success),
+            failures: u64::from(!success),
+            slow: u64::from(slow),
+        };
+        self.advance(now);
+        self.buckets.current_mut().add(&call);
+        self.total.add(&call);
+    }
+
+    /// Returns the calls of the window at `now`.
+    pub(crate) fn total(&mut self, now: Duration) -> CallStats {
+        self.advance(now);
+        self.total
+    }
+
+    fn advance(&mut self, now: Duration) {
+        let total = &mut self.total;
+        self.buckets.advance(now, |expired| total.sub(&expired));
+    }
+}
+
+impl TripPolicy {
+    /// Returns the window of the calls, if the policy needs one.
+    pub(crate) fn rolling_stats(&self, now: Duration) -> Option<RollingStats> {
+        match *self {
+            TripPolicy::ConsecutiveFailures { .. } => None,
+            TripPolicy::FailureRate {
+                window, buckets, ..
+            } => Some(RollingStats::new(window, buckets, now)),
+        }
+    }
+
+    /// Returns whether a call taking `elapsed` is slow.
+    pub(crate) fn is_slow(&self, elapsed: Duration) -> bool {
+        match *self {
+            TripPolicy::ConsecutiveFailures { .. } => false,
+            TripPolicy::FailureRate { slow_call, .. } => elapsed >= slow_call,
+        }
+    }
+
+    /// Returns whether the circuit opens after `failures` consecutive failures,
+    /// with `stats` over the window.
+    pub(crate) fn should_open(&self, failures: u32, stats: Option<CallStats>) -> bool {
+        match *self {
+            TripPolicy::ConsecutiveFailures { failures: max } => failures >= max.max(1),
+            TripPolicy::FailureRate {
+                min_calls,
+                failure_rate,
+                slow_call_rate,
+                ..
+            } => stats.is_some_and(|stats| {
+                stats.calls() >= min_calls.max(1)
+                    && (stats.failure_rate() >= failure_rate
+                        || stats.slow_call_rate() >= slow_call_rate)
+            }),
+        }
+    }
+}
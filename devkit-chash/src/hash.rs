@@ -0,0 +1,38 @@
+use std::hash::{Hash, Hasher};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A 64-bit FNV-1a hasher.
+///
+/// The standard library makes no promise that its hashers produce the same
+/// output across releases or processes, while every instance of a service must
+/// route a key to the same node. FNV-1a is fully specified.
+struct Fnv1a(u64);
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+/// Returns the stable 64-bit hash of `item`.
+///
+/// The FNV-1a output is passed through the SplitMix64 finalizer, since FNV alone
+/// spreads short, similar keys such as `node-1`, `node-2` poorly over the ring.
+pub(crate) fn hash<T: Hash + ?Sized>(item: &T) -> u64 {
+    let mut hasher = Fnv1a(FNV_OFFSET);
+    item.hash(&mut hasher);
+
+    let mut x = hasher.finish();
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
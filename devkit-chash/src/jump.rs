@@ -0,0 +1,120 @@
+use std::hash::Hash;
+
+use crate::hash::hash;
+
+/// Maps `key` to a bucket in `0..buckets` with the jump consistent hash of
+/// Lamping and Veach.
+///
+/// Jump hash needs no memory and spreads keys perfectly evenly, but buckets can
+/// only be added or removed at the end of the range. Growing from `n` to `n + 1`
+/// buckets moves exactly `1 / (n + 1)` of the keys, all of them to the new bucket.
+///
+/// # Returns
+///
+/// The bucket of the key, or 0 if `buckets` is 0.
+///
+/// # Example
+///
+/// ```
+/// use devkit_chash::jump_hash;
+///
+/// let bucket = jump_hash(42, 10);
+/// assert!(bucket < 10);
+/// assert_eq!(jump_hash(42, 10), bucket);
+/// ```
+pub fn jump_hash(mut key: u64, buckets: u32) -> u32 {
+    let mut b: i64 = -1;
+    let mut j: i64 = 0;
+    while j < i64::from(buckets) {
+        b = j;
+        key = key.wrapping_mul(2_862_933_555_777_941_757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    b.max(0) as u32
+}
+
+/// A list of nodes addressed with [`jump_hash`].
+///
+/// This is the jump hash counterpart of [`HashRing`](crate::HashRing), for node
+/// lists that only grow or shrink at the end, such as numbered shards.
+///
+/// # Example
+///
+/// ```
+/// use devkit_chash::JumpHash;
+///
+/// let shards = JumpHash::new(vec!["shard-0", "shard-1", "shard-2"]);
+/// assert!(shards.get("user:42").is_some());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct JumpHash<N> {
+    nodes: Vec<N>,
+}
+
+impl<N> JumpHash<N> {
+    /// Creates a new `JumpHash` over `nodes`.
+    pub fn new(nodes: Vec<N>) -> Self {
+        Self { nodes }
+    }
+
+    /// Returns the node `key` is routed to, or `None` if there is no node.
+    pub fn get<K: Hash + ?Sized>(&self, key: &K) -> Option<&N> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let bucket = jump_hash(hash(key), self.nodes.len() as u32);
+        self.nodes.get(bucket as usize)
+    }
+
+    /// Appends `node`, moving only the keys that now route to it.
+    pub fn push(&mut self, node: N) {
+        self.nodes.push(node);
+    }
+
+    /// Removes the last node, moving only the keys it owned.
+    pub fn pop(&mut self) -> Option<N> {
+        self.nodes.pop()
+    }
+
+    /// Returns the nodes.
+    pub fn nodes(&self) -> &[N] {
+        &self.nodes
+    }
+
+    /// Returns the number of nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if there is no node.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jump_hash_should_only_move_keys_to_new_bucket() {
+        const KEYS: u64 = 10_000;
+
+        // a single bucket takes every key
+        assert_eq!(jump_hash(0, 1), 0);
+        assert_eq!(jump_hash(u64::MAX, 1), 0);
+
+        let mut moved = 0;
+        for key in 0..KEYS {
+            let before = jump_hash(key, 10);
+            let after = jump_hash(key, 11);
+            assert!(before < 10);
+            if before != after {
+                assert_eq!(after, 10);
+                moved += 1;
+            }
+        }
+        // about 1/11 of the keys move
+        assert!((700..1_100).contains(&moved));
+    }
+}
@@ -0,0 +1,6 @@
+mod hash;
+mod jump;
+mod ring;
+
+pub use jump::{jump_hash, JumpHash};
+pub use ring::HashRing;
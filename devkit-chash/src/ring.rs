@@ -0,0 +1,284 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap, HashMap},
+    hash::Hash,
+};
+
+use crate::hash::hash;
+
+/// The default number of virtual nodes per unit of weight.
+const DEFAULT_REPLICAS: u32 = 160;
+/// The maximum number of virtual nodes of a single node.
+const MAX_POINTS: u32 = 1 << 20;
+
+/// A consistent hash ring with virtual nodes and weighted members.
+///
+/// Every node is placed on the ring `replicas * weight` times, capped at 2^20
+/// virtual nodes per node, and a key is routed to the first virtual node following
+/// its hash. Adding or removing a node only moves the keys of its own virtual
+/// nodes, about `1 / nodes` of all keys, which makes the ring a good fit for
+/// routing keys to limiter shards or backends whose membership changes at runtime.
+///
+/// # Example
+///
+/// ```
+/// use devkit_chash::HashRing;
+///
+/// let mut ring = HashRing::new();
+/// ring.add("shard-a", 1);
+/// ring.add("shard-b", 2);
+///
+/// let shard = ring.get("user:42").unwrap();
+/// assert!(*shard == "shard-a" || *shard == "shard-b");
+/// ```
+#[derive(Debug, Clone)]
+pub struct HashRing<N> {
+    /// The number of virtual nodes per unit of weight.
+    replicas: u32,
+    /// The virtual nodes, by their position on the ring.
+    ring: BTreeMap<u64, N>,
+    /// The other nodes placed on a position already taken, rare with 64-bit hashes,
+    /// which take it over when its owner is removed.
+    collisions: HashMap<u64, Vec<N>>,
+    /// The members and their weights.
+    nodes: Vec<(N, u32)>,
+}
+
+impl<N: Hash + Eq + Clone> Default for HashRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N: Hash + Eq + Clone> HashRing<N> {
+    /// Creates a new, empty `HashRing` with 160 virtual nodes per unit of weight.
+    pub fn new() -> Self {
+        Self::with_replicas(DEFAULT_REPLICAS)
+    }
+
+    /// Creates a new, empty `HashRing`.
+    ///
+    /// # Arguments
+    ///
+    /// * `replicas` - The number of virtual nodes per unit of weight. More virtual
+    ///   nodes spread keys more evenly at the cost of memory.
+    pub fn with_replicas(replicas: u32) -> Self {
+        Self {
+            replicas: replicas.max(1),
+            ring: BTreeMap::new(),
+            collisions: HashMap::new(),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Adds `node` to the ring, or updates its weight if it is already a member.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node to add.
+    /// * `weight` - The relative share of keys routed to the node. A weight of 0
+    ///   removes the node. Weights above `2^20 / replicas` all get the share of
+    ///   that weight, since a node has at most 2^20 virtual nodes.
+    pub fn add(&mut self, node: N, weight: u32) {
+        self.remove(&node);
+        if weight == 0 {
+            return;
+        }
+
+        for i in 0..self.points(weight) {
+            self.place(hash(&(&node, i)), node.clone());
+        }
+        self.nodes.push((node, weight));
+    }
+
+    /// Removes `node` from the ring.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the node was a member.
+    pub fn remove(&mut self, node: &N) -> bool {
+        let Some(index) = self.nodes.iter().position(|(n, _)| n == node) else {
+            return false;
+        };
+
+        let (_, weight) = self.nodes.swap_remove(index);
+        self.collisions.retain(|_, nodes| {
+            nodes.retain(|n| n != node);
+            !nodes.is_empty()
+        });
+        for i in 0..self.points(weight) {
+            self.unplace(hash(&(node, i)), node);
+        }
+        true
+    }
+
+    /// Returns the node `key` is routed to, or `None` if the ring is empty.
+    pub fn get<K: Hash + ?Sized>(&self, key: &K) -> Option<&N> {
+        let point = hash(key);
+        self.ring
+            .range(point..)
+            .chain(self.ring.iter())
+            .next()
+            .map(|(_, node)| node)
+    }
+
+    /// Returns up to `n` distinct nodes for `key`, in ring order.
+    ///
+    /// The first node is the one returned by [`HashRing::get`]; the others are the
+    /// natural fallbacks or replicas for the key.
+    pub fn get_n<K: Hash + ?Sized>(&self, key: &K, n: usize) -> Vec<&N> {
+        let n = n.min(self.nodes.len());
+        let point = hash(key);
+
+        let mut found: Vec<&N> = Vec::with_capacity(n);
+        for (_, node) in self.ring.range(point..).chain(self.ring.range(..point)) {
+            if found.len() == n {
+                break;
+            }
+            if !found.contains(&node) {
+                found.push(node);
+            }
+        }
+        found
+    }
+
+    /// Returns the members of the ring and their weights.
+    pub fn nodes(&self) -> impl Iterator<Item = (&N, u32)> {
+        self.nodes.iter().map(|(node, weight)| (node, *weight))
+    }
+
+    /// Returns the number of members of the ring.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if the ring has no members.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the number of virtual nodes of a node of `weight`.
+    fn points(&self, weight: u32) -> u32 {
+        self.replicas.saturating_mul(weight).min(MAX_POINTS)
+    }
+
+    /// Places `node` at `point` on the ring.
+    ///
+    /// On a collision the point goes to the node with the lowest hash, whatever
+    /// the order the nodes were added in, and the other one waits in `collisions`.
+    fn place(&mut self, point: u64, node: N) {
+        match self.ring.entry(point) {
+            Entry::Vacant(entry) => {
+                entry.insert(node);
+            }
+            Entry::Occupied(mut entry) => {
+                let other = if hash(&node) < hash(entry.get()) {
+                    std::mem::replace(entry.get_mut(), node)
+                } else {
+                    node
+                };
+                self.collisions.entry(point).or_default().push(other);
+            }
+        }
+    }
+
+    /// Takes `point` away from `node`, handing it over to the node with the lowest
+    /// hash among those that collided on it, if any.
+    fn unplace(&mut self, point: u64, node: &N) {
+        if self.ring.get(&point) != Some(node) {
+            return;
+        }
+        self.ring.remove(&point);
+        let Some(others) = self.collisions.get_mut(&point) else {
+            return;
+        };
+        if let Some(next) = (0..others.len()).min_by_key(|i| hash(&others[*i])) {
+            self.ring.insert(point, others.swap_remove(next));
+        }
+        if others.is_empty() {
+            self.collisions.remove(&point);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn hash_ring_should_balance_and_move_few_keys() {
+        const KEYS: u64 = 10_000;
+
+        let mut ring = HashRing::new();
+        ring.add("a", 1);
+        ring.add("b", 1);
+        ring.add("c", 2);
+
+        let before: Vec<&str> = (0..KEYS).map(|k| *ring.get(&k).unwrap()).collect();
+        let mut counts = HashMap::new();
+        for node in &before {
+            *counts.entry(*node).or_insert(0) += 1;
+        }
+        // "c" has twice the weight, so roughly half of the keys
+        assert!((4_000..6_000).contains(&counts["c"]));
+        assert!((1_800..3_200).contains(&counts["a"]));
+
+        // removing a node only moves the keys it owned
+        assert!(ring.remove(&"a"));
+        for (k, node) in before.iter().enumerate() {
+            if *node != "a" {
+                assert_eq!(ring.get(&(k as u64)), Some(node));
+            }
+        }
+
+        let replicas = ring.get_n("key", 5);
+        assert_eq!(replicas.len(), 2);
+        assert_eq!(replicas[0], ring.get("key").unwrap());
+        assert_ne!(replicas[0], replicas[1]);
+    }
+
+    #[test]
+    fn hash_ring_should_resolve_collisions_independently_of_order() {
+        let (low, high) = if hash(&"a") < hash(&"b") {
+            ("a", "b")
+        } else {
+            ("b", "a")
+        };
+        for (first, second) in [(low, high), (high, low)] {
+            let mut ring = HashRing::new();
+            ring.place(7, first);
+            ring.place(7, second);
+            assert_eq!(ring.ring[&7], low);
+
+            // the point goes back to the other node, rather than being lost
+            ring.unplace(7, &low);
+            assert_eq!(ring.ring[&7], high);
+            assert!(ring.collisions.is_empty());
+            ring.unplace(7, &high);
+            assert!(ring.ring.is_empty());
+        }
+    }
+
+    #[test]
+    fn hash_ring_should_not_overflow_the_virtual_nodes() {
+        let ring = HashRing::<&str>::with_replicas(160);
+        assert_eq!(ring.points(u32::MAX / 100), MAX_POINTS);
+        assert_eq!(ring.points(MAX_POINTS / 160 + 1), MAX_POINTS);
+        assert_eq!(ring.points(2), 320);
+
+        let mut ring = HashRing::with_replicas(1);
+        ring.add("a", 3);
+        assert_eq!(ring.ring.len(), 3);
+        assert!(ring.remove(&"a"));
+        assert!(ring.ring.is_empty());
+
+        // a huge weight places a bounded number of virtual nodes
+        let mut ring = HashRing::new();
+        ring.add("a", u32::MAX);
+        ring.add("b", 1);
+        assert_eq!(ring.ring.len(), (MAX_POINTS + DEFAULT_REPLICAS) as usize);
+        assert!(ring.remove(&"a"));
+        assert_eq!(ring.ring.len(), DEFAULT_REPLICAS as usize);
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::scheduler::Scheduler;
+use crate::Edge;
+
+/// Coalesces bursts of calls into a single invocation of an async function.
+///
+/// This is the async counterpart of [`crate::Debouncer`]: leading invocations are
+/// spawned as tokio tasks, trailing invocations run on a task owned by the
+/// debouncer. Pending invocations are dropped with the debouncer.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_debounce::{asynchronous::Debouncer, Edge};
+/// use tokio::sync::mpsc;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let (tx, mut rx) = mpsc::unbounded_channel();
+/// let debouncer = Debouncer::new(Duration::from_millis(20), Edge::Trailing, move |path| {
+///     let tx = tx.clone();
+///     async move { tx.send(path).unwrap() }
+/// });
+///
+/// debouncer.call("a.txt");
+/// debouncer.call("b.txt");
+///
+/// assert_eq!(rx.recv().await, Some("b.txt"));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Debouncer<T, F> {
+    wait: Duration,
+    edge: Edge,
+    f: Arc<F>,
+    scheduler: Scheduler<T>,
+}
+
+impl<T, F, Fut> Debouncer<T, F>
+where
+    T: Send + 'static,
+    F: Fn(T) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    /// Creates a new `Debouncer`. Must be called within a tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// * `wait` - The quiet period that ends a burst.
+    /// * `edge` - The edges of a burst on which `f` is invoked.
+    /// * `f` - The async function to debounce.
+    pub fn new(wait: Duration, edge: Edge, f: F) -> Self {
+        let f = Arc::new(f);
+        Self {
+            wait,
+            edge,
+            scheduler: Scheduler::new(f.clone(), None),
+            f,
+        }
+    }
+
+    /// Records a call with `arg`, extending the current burst.
+    pub fn call(&self, arg: T) {
+        let mut state = self.scheduler.lock();
+        let idle = state.deadline.is_none();
+        state.deadline = Some(Instant::now() + self.wait);
+
+        if idle && self.edge.leading() {
+            state.pending = None;
+            drop(state);
+            self.scheduler.wake();
+            tokio::spawn((self.f)(arg));
+            return;
+        }
+
+        if self.edge.trailing() {
+            state.pending = Some(arg);
+        }
+        drop(state);
+        self.scheduler.wake();
+    }
+
+    /// Drops the pending trailing invocation and ends the current burst.
+    pub fn cancel(&self) {
+        let mut state = self.scheduler.lock();
+        state.pending = None;
+        state.deadline = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{sync::mpsc, time::timeout};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn async_debouncer_should_coalesce_bursts() {
+        const WAIT: Duration = Duration::from_millis(50);
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let debouncer = Debouncer::new(WAIT, Edge::Both, move |i| {
+            let tx = tx.clone();
+            async move { tx.send(i).unwrap() }
+        });
+
+        for i in 0..5 {
+            debouncer.call(i);
+            tokio::time::sleep(WAIT / 5).await;
+        }
+        assert_eq!(timeout(WAIT * 4, rx.recv()).await, Ok(Some(0)));
+        assert_eq!(timeout(WAIT * 4, rx.recv()).await, Ok(Some(4)));
+
+        debouncer.cancel();
+        debouncer.call(5);
+        assert_eq!(timeout(WAIT * 4, rx.recv()).await, Ok(Some(5)));
+        assert!(timeout(WAIT * 2, rx.recv()).await.is_err());
+    }
+}
@@ -0,0 +1,12 @@
+//! Debouncer and throttler for async code, running on the tokio runtime.
+//!
+//! They mirror [`crate::Debouncer`] and [`crate::Throttler`], but invoke async
+//! functions on tokio tasks instead of plain functions on threads. They must be
+//! created within a tokio runtime.
+
+mod debouncer;
+mod scheduler;
+mod throttler;
+
+pub use debouncer::Debouncer;
+pub use throttler::Throttler;
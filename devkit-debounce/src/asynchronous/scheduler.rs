@@ -0,0 +1,82 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+use crate::scheduler::State;
+
+/// Runs the trailing invocations of an async debouncer or throttler on a tokio task.
+#[derive(Debug)]
+pub(crate) struct Scheduler<T> {
+    shared: Arc<(Mutex<State<T>>, Notify)>,
+}
+
+impl<T: Send + 'static> Scheduler<T> {
+    /// Creates a new `Scheduler` and spawns its worker task.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The function invoked with the pending argument once the deadline passes.
+    /// * `restart` - If set, a trailing invocation starts a new window of this length,
+    ///   as a throttler does; otherwise the deadline is cleared, as a debouncer does.
+    pub(crate) fn new<F, Fut>(f: Arc<F>, restart: Option<Duration>) -> Self
+    where
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let shared = Arc::new((Mutex::new(State::default()), Notify::new()));
+
+        let worker = shared.clone();
+        tokio::spawn(async move {
+            let (state, notify) = &*worker;
+            loop {
+                let (deadline, arg) = {
+                    let mut guard = state.lock().unwrap_or_else(|e| e.into_inner());
+                    if guard.closed {
+                        return;
+                    }
+                    let now = Instant::now();
+                    match guard.deadline {
+                        Some(deadline) if now >= deadline => (None, guard.fire(now, restart)),
+                        deadline => (deadline, None),
+                    }
+                };
+
+                if let Some(arg) = arg {
+                    f(arg).await;
+                    continue;
+                }
+                match deadline {
+                    None => notify.notified().await,
+                    Some(deadline) => {
+                        let wait = deadline.saturating_duration_since(Instant::now());
+                        let _ = tokio::time::timeout(wait, notify.notified()).await;
+                    }
+                }
+            }
+        });
+
+        Self { shared }
+    }
+
+    /// Locks the shared state. Call [`Scheduler::wake`] after moving the deadline.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.shared.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wakes the worker up so it picks up a new deadline.
+    pub(crate) fn wake(&self) {
+        self.shared.1.notify_one();
+    }
+}
+
+impl<T> Drop for Scheduler<T> {
+    fn drop(&mut self) {
+        let (state, notify) = &*self.shared;
+        state.lock().unwrap_or_else(|e| e.into_inner()).closed = true;
+        notify.notify_one();
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::scheduler::Scheduler;
+use crate::Edge;
+
+/// Invokes an async function at most once per interval, however often it is called.
+///
+/// This is the async counterpart of [`crate::Throttler`]: leading invocations are
+/// spawned as tokio tasks, trailing invocations run on a task owned by the
+/// throttler. Pending invocations are dropped with the throttler.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_debounce::{asynchronous::Throttler, Edge};
+/// use tokio::sync::mpsc;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let (tx, mut rx) = mpsc::unbounded_channel();
+/// let throttler = Throttler::new(Duration::from_millis(20), Edge::Trailing, move |event| {
+///     let tx = tx.clone();
+///     async move { tx.send(event).unwrap() }
+/// });
+///
+/// for event in 0..10 {
+///     throttler.call(event);
+/// }
+///
+/// assert_eq!(rx.recv().await, Some(9));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Throttler<T, F> {
+    interval: Duration,
+    edge: Edge,
+    f: Arc<F>,
+    scheduler: Scheduler<T>,
+}
+
+impl<T, F, Fut> Throttler<T, F>
+where
+    T: Send + 'static,
+    F: Fn(T) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    /// Creates a new `Throttler`. Must be called within a tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The minimum time between two invocations.
+    /// * `edge` - The edges of a window on which `f` is invoked.
+    /// * `f` - The async function to throttle.
+    pub fn new(interval: Duration, edge: Edge, f: F) -> Self {
+        let f = Arc::new(f);
+        Self {
+            interval,
+            edge,
+            scheduler: Scheduler::new(f.clone(), Some(interval)),
+            f,
+        }
+    }
+
+    /// Records a call with `arg`.
+    pub fn call(&self, arg: T) {
+        let mut state = self.scheduler.lock();
+        if state.deadline.is_none() {
+            state.deadline = Some(Instant::now() + self.interval);
+            if self.edge.leading() {
+                drop(state);
+                self.scheduler.wake();
+                tokio::spawn((self.f)(arg));
+                return;
+            }
+            state.pending = Some(arg);
+        } else if self.edge.trailing() {
+            state.pending = Some(arg);
+        }
+        drop(state);
+        self.scheduler.wake();
+    }
+
+    /// Drops the pending trailing invocation and ends the current window.
+    pub fn cancel(&self) {
+        let mut state = self.scheduler.lock();
+        state.pending = None;
+        state.deadline = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{sync::mpsc, time::timeout};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn async_throttler_should_invoke_on_the_edges() {
+        const INTERVAL: Duration = Duration::from_millis(50);
+
+        for (edge, expected) in [
+            (Edge::Leading, &[0][..]),
+            (Edge::Trailing, &[4]),
+            (Edge::Both, &[0, 4]),
+        ] {
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let throttler = Throttler::new(INTERVAL, edge, move |i| {
+                let tx = tx.clone();
+                async move { tx.send(i).unwrap() }
+            });
+
+            // a burst within a single window
+            for i in 0..5 {
+                throttler.call(i);
+                tokio::time::sleep(INTERVAL / 10).await;
+            }
+            for i in expected {
+                assert_eq!(timeout(INTERVAL * 4, rx.recv()).await, Ok(Some(*i)));
+            }
+            assert!(timeout(INTERVAL * 2, rx.recv()).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn async_throttler_should_cancel_the_pending_invocation() {
+        const INTERVAL: Duration = Duration::from_millis(50);
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let throttler = Throttler::new(INTERVAL, Edge::Both, move |i| {
+            let tx = tx.clone();
+            async move { tx.send(i).unwrap() }
+        });
+
+        throttler.call(0);
+        throttler.call(1);
+        assert_eq!(timeout(INTERVAL * 4, rx.recv()).await, Ok(Some(0)));
+        throttler.cancel();
+        assert!(timeout(INTERVAL * 2, rx.recv()).await.is_err());
+
+        // the window ended with the cancellation, so the next call leads a new one
+        throttler.call(2);
+        assert_eq!(timeout(INTERVAL / 2, rx.recv()).await, Ok(Some(2)));
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{scheduler::Scheduler, Edge};
+
+/// Coalesces bursts of calls into a single invocation of a function.
+///
+/// A burst is a series of calls less than `wait` apart. With the trailing edge,
+/// the function is invoked with the argument of the last call once no call was
+/// made for `wait`; with the leading edge, it is invoked with the argument of the
+/// first call, immediately. This suits event-driven code such as file watchers,
+/// where one save fires a flurry of events that should trigger a single reload.
+///
+/// Leading invocations run on the calling thread, trailing invocations run on a
+/// background thread owned by the debouncer. Pending invocations are dropped with
+/// the debouncer.
+///
+/// # Example
+///
+/// ```
+/// use std::{sync::mpsc, thread, time::Duration};
+/// use devkit_debounce::{Debouncer, Edge};
+///
+/// let (tx, rx) = mpsc::channel();
+/// let debouncer = Debouncer::new(Duration::from_millis(20), Edge::Trailing, move |path| {
+///     tx.send(path).unwrap();
+/// });
+///
+/// debouncer.call("a.txt");
+/// debouncer.call("b.txt");
+///
+/// assert_eq!(rx.recv().unwrap(), "b.txt");
+/// ```
+#[derive(Debug)]
+pub struct Debouncer<T, F> {
+    wait: Duration,
+    edge: Edge,
+    f: Arc<F>,
+    scheduler: Scheduler<T>,
+}
+
+impl<T, F> Debouncer<T, F>
+where
+    T: Send + 'static,
+    F: Fn(T) + Send + Sync + 'static,
+{
+    /// Creates a new `Debouncer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `wait` - The quiet period that ends a burst.
+    /// * `edge` - The edges of a burst on which `f` is invoked.
+    /// * `f` - The function to debounce.
+    pub fn new(wait: Duration, edge: Edge, f: F) -> Self {
+        let f = Arc::new(f);
+        Self {
+            wait,
+            edge,
+            scheduler: Scheduler::new(f.clone(), None),
+            f,
+        }
+    }
+
+    /// Records a call with `arg`, extending the current burst.
+    pub fn call(&self, arg: T) {
+        let mut state = self.scheduler.lock();
+        let idle = state.deadline.is_none();
+        state.deadline = Some(Instant::now() + self.wait);
+
+        if idle && self.edge.leading() {
+            state.pending = None;
+            drop(state);
+            self.scheduler.wake();
+            (self.f)(arg);
+            return;
+        }
+
+        if self.edge.trailing() {
+            state.pending = Some(arg);
+        }
+        drop(state);
+        self.scheduler.wake();
+    }
+
+    /// Drops the pending trailing invocation and ends the current burst.
+    pub fn cancel(&self) {
+        let mut state = self.scheduler.lock();
+        state.pending = None;
+        state.deadline = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread};
+
+    use super::*;
+
+    #[test]
+    fn debouncer_should_coalesce_bursts() {
+        const WAIT: Duration = Duration::from_millis(50);
+
+        let (tx, rx) = mpsc::channel();
+        let debouncer = Debouncer::new(WAIT, Edge::Both, move |i| tx.send(i).unwrap());
+
+        for i in 0..5 {
+            debouncer.call(i);
+            thread::sleep(WAIT / 5);
+        }
+        // the first call on the leading edge, the last one on the trailing edge
+        assert_eq!(rx.recv_timeout(WAIT * 4), Ok(0));
+        assert_eq!(rx.recv_timeout(WAIT * 4), Ok(4));
+
+        // a single call only fires once
+        debouncer.call(5);
+        assert_eq!(rx.recv_timeout(WAIT * 4), Ok(5));
+        assert!(rx.recv_timeout(WAIT * 2).is_err());
+    }
+}
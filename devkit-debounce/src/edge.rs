@@ -0,0 +1,21 @@
+/// The edges of a burst of calls on which the wrapped function is invoked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Edge {
+    /// Invoke with the first call of a burst, immediately.
+    Leading,
+    /// Invoke with the last call of a burst, once the burst is over.
+    #[default]
+    Trailing,
+    /// Invoke on both edges. A burst of a single call only invokes once.
+    Both,
+}
+
+impl Edge {
+    pub(crate) fn leading(self) -> bool {
+        matches!(self, Edge::Leading | Edge::Both)
+    }
+
+    pub(crate) fn trailing(self) -> bool {
+        matches!(self, Edge::Trailing | Edge::Both)
+    }
+}
@@ -0,0 +1,10 @@
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+mod debouncer;
+mod edge;
+mod scheduler;
+mod throttler;
+
+pub use debouncer::Debouncer;
+pub use edge::Edge;
+pub use throttler::Throttler;
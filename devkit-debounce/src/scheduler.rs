@@ -0,0 +1,108 @@
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The state shared by a debouncer or throttler and its worker thread.
+#[derive(Debug)]
+pub(crate) struct State<T> {
+    /// The argument of the pending trailing invocation.
+    pub(crate) pending: Option<T>,
+    /// The end of the current burst or window, if one is in progress.
+    pub(crate) deadline: Option<Instant>,
+    /// Set when the owner is dropped, to stop the worker.
+    pub(crate) closed: bool,
+}
+
+impl<T> Default for State<T> {
+    fn default() -> Self {
+        Self {
+            pending: None,
+            deadline: None,
+            closed: false,
+        }
+    }
+}
+
+impl<T> State<T> {
+    /// Takes the argument due at the deadline, and moves the deadline to the end
+    /// of the next window if `restart` is set and an invocation is due.
+    pub(crate) fn fire(&mut self, now: Instant, restart: Option<Duration>) -> Option<T> {
+        let pending = self.pending.take();
+        self.deadline = match (&pending, restart) {
+            (Some(_), Some(window)) => Some(now + window),
+            _ => None,
+        };
+        pending
+    }
+}
+
+/// Runs the trailing invocations of a debouncer or throttler on a worker thread.
+#[derive(Debug)]
+pub(crate) struct Scheduler<T> {
+    shared: Arc<(Mutex<State<T>>, Condvar)>,
+}
+
+impl<T: Send + 'static> Scheduler<T> {
+    /// Creates a new `Scheduler` and spawns its worker thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The function invoked with the pending argument once the deadline passes.
+    /// * `restart` - If set, a trailing invocation starts a new window of this length,
+    ///   as a throttler does; otherwise the deadline is cleared, as a debouncer does.
+    pub(crate) fn new<F>(f: Arc<F>, restart: Option<Duration>) -> Self
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        let shared = Arc::new((Mutex::new(State::default()), Condvar::new()));
+
+        let worker = shared.clone();
+        thread::spawn(move || {
+            let (state, cond) = &*worker;
+            let mut guard = state.lock().unwrap_or_else(|e| e.into_inner());
+            while !guard.closed {
+                let now = Instant::now();
+                match guard.deadline {
+                    None => {
+                        guard = cond.wait(guard).unwrap_or_else(|e| e.into_inner());
+                    }
+                    Some(deadline) if now < deadline => {
+                        guard = cond
+                            .wait_timeout(guard, deadline - now)
+                            .unwrap_or_else(|e| e.into_inner())
+                            .0;
+                    }
+                    Some(_) => {
+                        if let Some(arg) = guard.fire(now, restart) {
+                            drop(guard);
+                            f(arg);
+                            guard = state.lock().unwrap_or_else(|e| e.into_inner());
+                        }
+                    }
+                }
+            }
+        });
+
+        Self { shared }
+    }
+
+    /// Locks the shared state. Call [`Scheduler::wake`] after moving the deadline.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.shared.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wakes the worker up so it picks up a new deadline.
+    pub(crate) fn wake(&self) {
+        self.shared.1.notify_one();
+    }
+}
+
+impl<T> Drop for Scheduler<T> {
+    fn drop(&mut self) {
+        let (state, cond) = &*self.shared;
+        state.lock().unwrap_or_else(|e| e.into_inner()).closed = true;
+        cond.notify_one();
+    }
+}
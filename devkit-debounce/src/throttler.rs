@@ -0,0 +1,121 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{scheduler::Scheduler, Edge};
+
+/// Invokes a function at most once per interval, however often it is called.
+///
+/// With the leading edge, the first call of a window invokes the function
+/// immediately. With the trailing edge, the last call of a window is invoked when
+/// the window ends, and starts the next window. Calls in between are dropped. This
+/// suits floods of events, such as webhooks, where only the latest one matters but
+/// the consumer should keep seeing updates while the flood lasts.
+///
+/// Leading invocations run on the calling thread, trailing invocations run on a
+/// background thread owned by the throttler. Pending invocations are dropped with
+/// the throttler.
+///
+/// # Example
+///
+/// ```
+/// use std::{sync::mpsc, time::Duration};
+/// use devkit_debounce::{Edge, Throttler};
+///
+/// let (tx, rx) = mpsc::channel();
+/// let throttler = Throttler::new(Duration::from_millis(20), Edge::Both, move |event| {
+///     tx.send(event).unwrap();
+/// });
+///
+/// for event in 0..10 {
+///     throttler.call(event);
+/// }
+///
+/// assert_eq!(rx.recv().unwrap(), 0);
+/// assert_eq!(rx.recv().unwrap(), 9);
+/// ```
+#[derive(Debug)]
+pub struct Throttler<T, F> {
+    interval: Duration,
+    edge: Edge,
+    f: Arc<F>,
+    scheduler: Scheduler<T>,
+}
+
+impl<T, F> Throttler<T, F>
+where
+    T: Send + 'static,
+    F: Fn(T) + Send + Sync + 'static,
+{
+    /// Creates a new `Throttler`.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The minimum time between two invocations.
+    /// * `edge` - The edges of a window on which `f` is invoked.
+    /// * `f` - The function to throttle.
+    pub fn new(interval: Duration, edge: Edge, f: F) -> Self {
+        let f = Arc::new(f);
+        Self {
+            interval,
+            edge,
+            scheduler: Scheduler::new(f.clone(), Some(interval)),
+            f,
+        }
+    }
+
+    /// Records a call with `arg`.
+    pub fn call(&self, arg: T) {
+        let mut state = self.scheduler.lock();
+        if state.deadline.is_none() {
+            state.deadline = Some(Instant::now() + self.interval);
+            if self.edge.leading() {
+                drop(state);
+                self.scheduler.wake();
+                (self.f)(arg);
+                return;
+            }
+            state.pending = Some(arg);
+        } else if self.edge.trailing() {
+            state.pending = Some(arg);
+        }
+        drop(state);
+        self.scheduler.wake();
+    }
+
+    /// Drops the pending trailing invocation and ends the current window.
+    pub fn cancel(&self) {
+        let mut state = self.scheduler.lock();
+        state.pending = None;
+        state.deadline = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread};
+
+    use super::*;
+
+    #[test]
+    fn throttler_should_invoke_once_per_interval() {
+        const INTERVAL: Duration = Duration::from_millis(100);
+
+        let (tx, rx) = mpsc::channel();
+        let throttler = Throttler::new(INTERVAL, Edge::Leading, move |i| tx.send(i).unwrap());
+
+        let start = Instant::now();
+        let mut i = 0;
+        while start.elapsed() < INTERVAL * 5 / 2 {
+            throttler.call(i);
+            i += 1;
+            thread::sleep(INTERVAL / 20);
+        }
+
+        // one invocation at the start of each of the 3 windows
+        let invoked: Vec<_> = rx.try_iter().collect();
+        assert_eq!(invoked.len(), 3);
+        assert_eq!(invoked[0], 0);
+    }
+}
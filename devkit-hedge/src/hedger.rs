@@ -0,0 +1,311 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+    time::Duration,
+};
+
+use devkit_rl::{Quota, TokenBucket};
+use tokio::time::Instant;
+
+use crate::LatencyTracker;
+
+/// When a [`Hedger`] sends a backup request, and how many of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HedgePolicy {
+    /// The percentile of the recent latencies after which a backup request is
+    /// sent, between 0.0 and 1.0. With 0.95, about 5% of the requests are hedged.
+    pub percentile: f64,
+    /// The number of recent latencies the percentile is computed from.
+    pub samples: usize,
+    /// The number of latencies to record before trusting the percentile.
+    pub min_samples: usize,
+    /// The delay before a backup request while fewer than `min_samples` latencies
+    /// are recorded.
+    pub initial_delay: Duration,
+    /// The shortest delay before a backup request, so that a fast upstream does not
+    /// receive every request twice.
+    pub min_delay: Duration,
+    /// The most backup requests sent, which bounds the extra load hedging puts on
+    /// the upstream when it slows down as a whole.
+    pub max_hedges: Quota,
+}
+
+impl Default for HedgePolicy {
+    /// Hedges after the p95 of the last 1000 requests, at most 10 times per second.
+    fn default() -> Self {
+        Self {
+            percentile: 0.95,
+            samples: 1000,
+            min_samples: 20,
+            initial_delay: Duration::from_millis(100),
+            min_delay: Duration::from_millis(1),
+            max_hedges: Quota::per_second(10),
+        }
+    }
+}
+
+/// Counters of the requests run by a [`Hedger`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HedgeStats {
+    /// The requests run.
+    pub requests: u64,
+    /// The backup requests sent.
+    pub hedges: u64,
+    /// The backup requests that completed before the original one.
+    pub hedge_wins: u64,
+    /// The backup requests not sent because `max_hedges` was exceeded.
+    pub throttled: u64,
+}
+
+/// Cuts tail latency by sending a backup request when a request is slow.
+///
+/// A request that has not completed after the configured percentile of the recent
+/// latencies is hedged: the same request is sent again, and whichever completes
+/// first wins. The other one is cancelled by dropping its future. The backup
+/// requests are rate limited with a token bucket, so that a slow upstream does not
+/// get twice the load.
+///
+/// Hedging is meant for idempotent requests, e.g. reads.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_hedge::{HedgePolicy, Hedger};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let hedger = Hedger::new(HedgePolicy {
+///     initial_delay: Duration::from_millis(10),
+///     ..HedgePolicy::default()
+/// });
+///
+/// let mut attempt = 0;
+/// let replica = hedger
+///     .run(|| {
+///         attempt += 1;
+///         // the first replica is stuck, the backup request goes to another one
+///         let delay = if attempt == 1 { 60_000 } else { 1 };
+///         async move {
+///             tokio::time::sleep(Duration::from_millis(delay)).await;
+///             attempt
+///         }
+///     })
+///     .await;
+///
+/// assert_eq!(replica, 2);
+/// assert_eq!(hedger.stats().hedge_wins, 1);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Hedger {
+    policy: HedgePolicy,
+    latencies: Mutex<LatencyTracker>,
+    budget: TokenBucket,
+    requests: AtomicU64,
+    hedges: AtomicU64,
+    hedge_wins: AtomicU64,
+    throttled: AtomicU64,
+}
+
+impl Hedger {
+    /// Creates a new `Hedger`.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - When backup requests are sent, and how many of them.
+    pub fn new(policy: HedgePolicy) -> Self {
+        Self {
+            latencies: Mutex::new(LatencyTracker::new(policy.samples)),
+            budget: TokenBucket::from_quota(policy.max_hedges),
+            policy,
+            requests: AtomicU64::new(0),
+            hedges: AtomicU64::new(0),
+            hedge_wins: AtomicU64::new(0),
+            throttled: AtomicU64::new(0),
+        }
+    }
+
+    /// Runs a request, and hedges it if it is slow.
+    ///
+    /// `request` is called once to send the request, and a second time to send the
+    /// backup request if the first one has not completed after [`Hedger::delay`]
+    /// and the hedge budget allows it. The output of the request that completes
+    /// first is returned, and the other request is dropped.
+    ///
+    /// The time the caller waited is recorded as the latency of the request.
+    pub async fn run<F, Fut, T>(&self, mut request: F) -> T
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let start = Instant::now();
+        let primary = request();
+        tokio::pin!(primary);
+
+        tokio::select! {
+            output = &mut primary => {
+                self.record(start.elapsed());
+                return output;
+            }
+            _ = tokio::time::sleep(self.delay()) => {}
+        }
+
+        if !self.budget.allow() {
+            self.throttled.fetch_add(1, Ordering::Relaxed);
+            let output = primary.await;
+            self.record(start.elapsed());
+            return output;
+        }
+
+        self.hedges.fetch_add(1, Ordering::Relaxed);
+        let backup = request();
+        let output = tokio::select! {
+            biased;
+            output = &mut primary => output,
+            output = backup => {
+                self.hedge_wins.fetch_add(1, Ordering::Relaxed);
+                output
+            }
+        };
+        self.record(start.elapsed());
+        output
+    }
+
+    /// Returns how long a request currently runs before it is hedged.
+    pub fn delay(&self) -> Duration {
+        let latencies = self
+            .latencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let delay = if latencies.len() < self.policy.min_samples {
+            self.policy.initial_delay
+        } else {
+            latencies
+                .percentile(self.policy.percentile)
+                .unwrap_or(self.policy.initial_delay)
+        };
+        delay.max(self.policy.min_delay)
+    }
+
+    /// Returns the counters of the requests run so far.
+    pub fn stats(&self) -> HedgeStats {
+        HedgeStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            hedges: self.hedges.load(Ordering::Relaxed),
+            hedge_wins: self.hedge_wins.load(Ordering::Relaxed),
+            throttled: self.throttled.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record(&self, latency: Duration) {
+        self.latencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(latency);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use tokio::time::sleep;
+
+    use super::*;
+
+    /// Counts the requests dropped before completing.
+    struct DropGuard(Arc<AtomicUsize>);
+
+    impl Drop for DropGuard {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn policy() -> HedgePolicy {
+        HedgePolicy {
+            percentile: 0.5,
+            samples: 10,
+            min_samples: 4,
+            initial_delay: Duration::from_millis(100),
+            min_delay: Duration::from_millis(5),
+            max_hedges: Quota::per_minute(1),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hedger_should_hedge_slow_requests_and_cancel_the_loser() {
+        let hedger = Hedger::new(policy());
+        let dropped = Arc::new(AtomicUsize::new(0));
+
+        let mut attempt = 0;
+        let start = Instant::now();
+        let output = hedger
+            .run(|| {
+                attempt += 1;
+                let (attempt, guard) = (attempt, DropGuard(dropped.clone()));
+                async move {
+                    sleep(Duration::from_millis(if attempt == 1 { 1_000 } else { 20 })).await;
+                    std::mem::forget(guard);
+                    attempt
+                }
+            })
+            .await;
+
+        assert_eq!(output, 2);
+        assert_eq!(start.elapsed(), Duration::from_millis(120));
+        assert_eq!(dropped.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            hedger.stats(),
+            HedgeStats {
+                requests: 1,
+                hedges: 1,
+                hedge_wins: 1,
+                throttled: 0,
+            }
+        );
+
+        // the budget of one hedge per minute is spent: the slow request is awaited
+        let output = hedger
+            .run(|| async {
+                sleep(Duration::from_millis(300)).await;
+                "slow"
+            })
+            .await;
+        assert_eq!(output, "slow");
+        assert_eq!(hedger.stats().hedges, 1);
+        assert_eq!(hedger.stats().throttled, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hedger_should_adapt_delay_to_recent_latencies() {
+        let hedger = Hedger::new(policy());
+        assert_eq!(hedger.delay(), Duration::from_millis(100));
+
+        for ms in [10, 20, 30, 40] {
+            let output = hedger
+                .run(|| async move {
+                    sleep(Duration::from_millis(ms)).await;
+                    ms
+                })
+                .await;
+            assert_eq!(output, ms);
+        }
+        assert_eq!(hedger.stats().hedges, 0);
+        assert_eq!(hedger.delay(), Duration::from_millis(20));
+
+        // fast requests do not make the delay shorter than the minimum
+        for _ in 0..10 {
+            hedger.run(|| async {}).await;
+        }
+        assert_eq!(hedger.delay(), Duration::from_millis(5));
+    }
+}
@@ -0,0 +1,106 @@
+use std::{collections::VecDeque, time::Duration};
+
+/// Keeps the latencies of the most recent requests, to estimate their percentiles.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_hedge::LatencyTracker;
+///
+/// let mut tracker = LatencyTracker::new(100);
+/// for ms in 1..=100 {
+///     tracker.record(Duration::from_millis(ms));
+/// }
+/// assert_eq!(tracker.percentile(0.95), Some(Duration::from_millis(95)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct LatencyTracker {
+    capacity: usize,
+    samples: VecDeque<Duration>,
+}
+
+impl LatencyTracker {
+    /// Creates a new `LatencyTracker`.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of recent latencies kept, at least 1. Older ones are forgotten.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Records the latency of a request, forgetting the oldest one if the tracker is full.
+    pub fn record(&mut self, latency: Duration) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    /// Returns the number of latencies kept.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns `true` if no latency was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Estimates the `p` percentile of the recent latencies, with the nearest-rank method.
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - The percentile, between 0.0 and 1.0, e.g. 0.95 for the p95.
+    ///
+    /// # Returns
+    ///
+    /// The smallest recorded latency that at least `p` of the latencies do not
+    /// exceed, or `None` if no latency was recorded.
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<_> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+
+        let p = if p.is_nan() { 1.0 } else { p.clamp(0.0, 1.0) };
+        let rank = (p * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.clamp(1, sorted.len()) - 1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_tracker_should_estimate_recent_percentiles() {
+        let mut tracker = LatencyTracker::new(4);
+        assert_eq!(tracker.percentile(0.5), None);
+
+        for ms in [40, 10, 30, 20] {
+            tracker.record(Duration::from_millis(ms));
+        }
+        assert_eq!(tracker.percentile(0.0), Some(Duration::from_millis(10)));
+        assert_eq!(tracker.percentile(0.5), Some(Duration::from_millis(20)));
+        assert_eq!(tracker.percentile(0.75), Some(Duration::from_millis(30)));
+        assert_eq!(tracker.percentile(0.99), Some(Duration::from_millis(40)));
+        assert_eq!(
+            tracker.percentile(f64::NAN),
+            Some(Duration::from_millis(40))
+        );
+
+        // the oldest latencies are forgotten
+        tracker.record(Duration::from_millis(1));
+        tracker.record(Duration::from_millis(2));
+        assert_eq!(tracker.len(), 4);
+        assert_eq!(tracker.percentile(0.5), Some(Duration::from_millis(2)));
+        assert_eq!(tracker.percentile(1.0), Some(Duration::from_millis(30)));
+    }
+}
@@ -0,0 +1,5 @@
+mod hedger;
+mod latency;
+
+pub use hedger::{HedgePolicy, HedgeStats, Hedger};
+pub use latency::LatencyTracker;
@@ -0,0 +1,28 @@
+use std::fmt;
+
+/// The error of parsing the text of an ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseIdError {
+    /// The text does not have the length of the ID.
+    InvalidLength,
+    /// The text has a character outside of the alphabet of the ID.
+    InvalidCharacter,
+    /// The text encodes a value wider than 128 bits.
+    Overflow,
+    /// The text is a UUID of another version or variant.
+    InvalidVersion,
+}
+
+impl fmt::Display for ParseIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParseIdError::InvalidLength => "invalid length",
+            ParseIdError::InvalidCharacter => "invalid character",
+            ParseIdError::Overflow => "value overflows 128 bits",
+            ParseIdError::InvalidVersion => "not a version 7 UUID",
+        })
+    }
+}
+
+impl std::error::Error for ParseIdError {}
@@ -0,0 +1,8 @@
+mod error;
+mod monotonic;
+mod ulid;
+mod uuid;
+
+pub use error::ParseIdError;
+pub use ulid::{Ulid, UlidGenerator};
+pub use uuid::{UuidV7, UuidV7Generator};
@@ -0,0 +1,137 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::BuildHasher,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The milliseconds since the unix epoch, as read by a generator.
+pub(crate) type Clock = Arc<dyn Fn() -> u64 + Send + Sync>;
+
+/// The state shared by the time-ordered generators: the timestamp of the last ID,
+/// and its random bits, incremented for the next ID of the same millisecond.
+pub(crate) struct Monotonic {
+    /// The number of random bits of an ID.
+    bits: u32,
+    clock: Clock,
+    state: Mutex<State>,
+}
+
+struct State {
+    /// The timestamp of the last ID, in milliseconds since the unix epoch.
+    last_ms: u64,
+    /// The random bits of the last ID.
+    random: u128,
+    rng: SplitMix64,
+}
+
+impl Monotonic {
+    pub(crate) fn new(bits: u32, clock: Clock) -> Self {
+        Self {
+            bits,
+            clock,
+            state: Mutex::new(State {
+                last_ms: 0,
+                random: 0,
+                rng: SplitMix64::from_entropy(),
+            }),
+        }
+    }
+
+    /// Returns the timestamp and the random bits of the next `n` IDs, in order.
+    ///
+    /// A millisecond later than the last ID starts from fresh random bits. Within
+    /// the same millisecond, or if the clock went backwards, the random bits of the
+    /// last ID are incremented; once they overflow, the IDs borrow the next
+    /// millisecond, so that they keep increasing.
+    pub(crate) fn next_n(&self, n: usize, mut f: impl FnMut(u64, u128)) {
+        let mask = (1u128 << self.bits) - 1;
+        let now = (self.clock)();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        for _ in 0..n {
+            if now > state.last_ms {
+                state.last_ms = now;
+                // the top random bit is left clear, so that a millisecond takes at
+                // least 2^(bits - 1) IDs to overflow
+                state.random = state.rng.next_u128() & (mask >> 1);
+            } else if state.random == mask {
+                state.last_ms += 1;
+                state.random = state.rng.next_u128() & (mask >> 1);
+            } else {
+                state.random += 1;
+            }
+            f(state.last_ms, state.random);
+        }
+    }
+}
+
+impl fmt::Debug for Monotonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Monotonic")
+            .field("bits", &self.bits)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reads the system clock, in milliseconds since the unix epoch.
+pub(crate) fn system_clock() -> Clock {
+    Arc::new(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+    })
+}
+
+/// A small pseudo-random generator, seeded differently for every generator.
+///
+/// The IDs are unique and time-ordered, not unguessable: do not use them as
+/// secrets.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn from_entropy() -> Self {
+        // every `RandomState` is seeded differently
+        Self(RandomState::new().hash_one(0u64))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn next_u128(&mut self) -> u128 {
+        (u128::from(self.next()) << 64) | u128::from(self.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monotonic_should_increase_within_and_across_milliseconds() {
+        let now = Arc::new(Mutex::new(1_000u64));
+        let clock = now.clone();
+        let monotonic = Monotonic::new(4, Arc::new(move || *clock.lock().unwrap()));
+
+        let mut ids = Vec::new();
+        monotonic.next_n(20, |ms, random| ids.push((ms, random)));
+        // 4 random bits overflow within the batch, which borrows the next milliseconds
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(ids[0].0, 1_000);
+        assert!(ids[0].1 < 8);
+        assert!(ids[19].0 > 1_000);
+
+        // the clock going backwards does not make the IDs go backwards
+        let last = ids[19];
+        *now.lock().unwrap() = 900;
+        monotonic.next_n(1, |ms, random| assert!((ms, random) > last));
+
+        *now.lock().unwrap() = 2_000;
+        monotonic.next_n(1, |ms, _| assert_eq!(ms, 2_000));
+    }
+}
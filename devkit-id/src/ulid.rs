@@ -0,0 +1,200 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use crate::{
+    monotonic::{system_clock, Monotonic},
+    ParseIdError,
+};
+
+/// The Crockford base32 alphabet of ULIDs.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// The length of the text of a ULID.
+const LEN: usize = 26;
+
+/// The number of random bits of a ULID.
+const RANDOM_BITS: u32 = 80;
+
+/// A ULID: a 48-bit unix timestamp in milliseconds followed by 80 random bits,
+/// written as 26 characters of Crockford base32.
+///
+/// ULIDs sort by time, in binary and in text, see <https://github.com/ulid/spec>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ulid(u128);
+
+/// A generator of [`Ulid`]s increasing monotonically, even within a millisecond.
+///
+/// The ULIDs of the same millisecond increment the random bits of the previous
+/// one, as the monotonic variant of the spec does, so the IDs of a generator are
+/// strictly increasing and keep their order as database keys. Once the random bits
+/// of a millisecond are exhausted, which takes at least 2^79 IDs, the IDs borrow
+/// the next millisecond instead of failing. A clock going backwards is handled the
+/// same way.
+///
+/// A generator is shared by reference between threads.
+///
+/// # Example
+///
+/// ```
+/// use devkit_id::UlidGenerator;
+///
+/// let ids = UlidGenerator::new();
+/// let first = ids.next();
+/// let batch = ids.next_batch(100);
+///
+/// assert!(batch.windows(2).all(|w| w[0] < w[1]));
+/// assert!(first < batch[0]);
+/// assert_eq!(first.to_string().len(), 26);
+/// assert_eq!(first.to_string().parse(), Ok(first));
+/// ```
+#[derive(Debug)]
+pub struct UlidGenerator {
+    monotonic: Monotonic,
+}
+
+impl Ulid {
+    /// Creates a `Ulid` from its timestamp, in milliseconds since the unix epoch,
+    /// and its random bits, both truncated to their width.
+    pub fn from_parts(timestamp_ms: u64, random: u128) -> Self {
+        let timestamp = u128::from(timestamp_ms & 0xffff_ffff_ffff);
+        Self((timestamp << RANDOM_BITS) | (random & ((1 << RANDOM_BITS) - 1)))
+    }
+
+    /// Creates a `Ulid` from its 128 bits.
+    pub fn from_u128(value: u128) -> Self {
+        Self(value)
+    }
+
+    /// Returns the 128 bits of the ULID.
+    pub fn to_u128(self) -> u128 {
+        self.0
+    }
+
+    /// Returns the 16 bytes of the ULID, big-endian.
+    pub fn to_bytes(self) -> [u8; 16] {
+        self.0.to_be_bytes()
+    }
+
+    /// Returns the timestamp of the ULID, in milliseconds since the unix epoch.
+    pub fn timestamp_ms(self) -> u64 {
+        (self.0 >> RANDOM_BITS) as u64
+    }
+
+    /// Returns the 80 random bits of the ULID.
+    pub fn random(self) -> u128 {
+        self.0 & ((1 << RANDOM_BITS) - 1)
+    }
+}
+
+impl fmt::Display for Ulid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut text = [0u8; LEN];
+        for (i, c) in text.iter_mut().enumerate() {
+            let shift = 5 * (LEN - 1 - i);
+            *c = ALPHABET[((self.0 >> shift) & 0x1f) as usize];
+        }
+        // the alphabet is ASCII
+        f.write_str(std::str::from_utf8(&text).map_err(|_| fmt::Error)?)
+    }
+}
+
+impl FromStr for Ulid {
+    type Err = ParseIdError;
+
+    /// Parses the 26 characters of a ULID, in any case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != LEN {
+            return Err(ParseIdError::InvalidLength);
+        }
+        // 26 characters of 5 bits hold 130 bits, the first one only 3
+        if !matches!(s.as_bytes()[0], b'0'..=b'7') {
+            return Err(ParseIdError::Overflow);
+        }
+        s.bytes()
+            .try_fold(0u128, |value, c| {
+                let digit = ALPHABET
+                    .iter()
+                    .position(|&a| a == c.to_ascii_uppercase())
+                    .ok_or(ParseIdError::InvalidCharacter)?;
+                Ok((value << 5) | digit as u128)
+            })
+            .map(Self)
+    }
+}
+
+impl UlidGenerator {
+    /// Creates a new `UlidGenerator` reading the system clock.
+    pub fn new() -> Self {
+        Self {
+            monotonic: Monotonic::new(RANDOM_BITS, system_clock()),
+        }
+    }
+
+    /// Creates a new `UlidGenerator` reading the time from `clock`, in
+    /// milliseconds since the unix epoch.
+    pub fn with_clock(clock: impl Fn() -> u64 + Send + Sync + 'static) -> Self {
+        Self {
+            monotonic: Monotonic::new(RANDOM_BITS, Arc::new(clock)),
+        }
+    }
+
+    /// Returns a new ULID, greater than every ULID returned before.
+    pub fn next(&self) -> Ulid {
+        let mut ulid = Ulid(0);
+        self.monotonic
+            .next_n(1, |ms, random| ulid = Ulid::from_parts(ms, random));
+        ulid
+    }
+
+    /// Returns `n` new ULIDs in increasing order, greater than every ULID returned
+    /// before, reading the clock and taking the lock of the generator once.
+    pub fn next_batch(&self, n: usize) -> Vec<Ulid> {
+        let mut ulids = Vec::with_capacity(n);
+        self.monotonic
+            .next_n(n, |ms, random| ulids.push(Ulid::from_parts(ms, random)));
+        ulids
+    }
+}
+
+impl Default for UlidGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ulid_should_round_trip_through_text() {
+        let ulid = Ulid::from_parts(1_469_918_176_385, 0x1234);
+        assert_eq!(ulid.to_string(), "01ARYZ6S4100000000000004HM");
+        assert_eq!("01aryz6s4100000000000004hm".parse(), Ok(ulid));
+        assert_eq!(ulid.timestamp_ms(), 1_469_918_176_385);
+        assert_eq!(ulid.random(), 0x1234);
+        assert_eq!(
+            Ulid::from_u128(u128::MAX).to_string(),
+            "7ZZZZZZZZZZZZZZZZZZZZZZZZZ"
+        );
+
+        assert_eq!("01ARYZ".parse::<Ulid>(), Err(ParseIdError::InvalidLength));
+        assert_eq!(
+            "01ARYZ6S41000000000000004U".parse::<Ulid>(),
+            Err(ParseIdError::InvalidCharacter)
+        );
+        assert_eq!(
+            "81ARYZ6S410000000000000000".parse::<Ulid>(),
+            Err(ParseIdError::Overflow)
+        );
+    }
+
+    #[test]
+    fn ulid_generator_should_order_ids_by_time() {
+        let ids = UlidGenerator::with_clock(|| 1_000);
+        let batch = ids.next_batch(3);
+        assert!(batch.iter().all(|u| u.timestamp_ms() == 1_000));
+        assert_eq!(batch[1].random(), batch[0].random() + 1);
+        assert_eq!(ids.next().random(), batch[2].random() + 1);
+        assert!(UlidGenerator::new().next().timestamp_ms() > 1_000);
+    }
+}
@@ -0,0 +1,201 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use crate::{
+    monotonic::{system_clock, Monotonic},
+    ParseIdError,
+};
+
+/// The number of random bits of a UUIDv7, `rand_a` and `rand_b` together.
+const RANDOM_BITS: u32 = 74;
+
+/// The number of bits of `rand_b`.
+const RAND_B_BITS: u32 = 62;
+
+/// A version 7 UUID, as defined by RFC 9562: a 48-bit unix timestamp in
+/// milliseconds, the version and variant bits, and 74 random bits.
+///
+/// UUIDv7s sort by time, in binary and in text, and fit the UUID columns of
+/// databases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UuidV7(u128);
+
+/// A generator of [`UuidV7`]s increasing monotonically, even within a
+/// millisecond.
+///
+/// The UUIDs of the same millisecond increment the 74 random bits of the previous
+/// one, which RFC 9562 describes as a monotonic random counter, so the IDs of a
+/// generator are strictly increasing. Once the random bits of a millisecond are
+/// exhausted, the IDs borrow the next millisecond instead of failing. A clock going
+/// backwards is handled the same way.
+///
+/// A generator is shared by reference between threads.
+///
+/// # Example
+///
+/// ```
+/// use devkit_id::UuidV7Generator;
+///
+/// let ids = UuidV7Generator::new();
+/// let batch = ids.next_batch(100);
+///
+/// assert!(batch.windows(2).all(|w| w[0] < w[1]));
+/// let text = batch[0].to_string();
+/// assert_eq!(&text[14..15], "7");
+/// assert_eq!(text.parse(), Ok(batch[0]));
+/// ```
+#[derive(Debug)]
+pub struct UuidV7Generator {
+    monotonic: Monotonic,
+}
+
+impl UuidV7 {
+    /// Creates a `UuidV7` from its timestamp, in milliseconds since the unix epoch,
+    /// and its 74 random bits, both truncated to their width.
+    pub fn from_parts(timestamp_ms: u64, random: u128) -> Self {
+        let timestamp = u128::from(timestamp_ms & 0xffff_ffff_ffff);
+        let rand_a = (random >> RAND_B_BITS) & 0xfff;
+        let rand_b = random & ((1 << RAND_B_BITS) - 1);
+        Self((timestamp << 80) | (0x7 << 76) | (rand_a << 64) | (0b10 << 62) | rand_b)
+    }
+
+    /// Returns the 128 bits of the UUID.
+    pub fn to_u128(self) -> u128 {
+        self.0
+    }
+
+    /// Returns the 16 bytes of the UUID, big-endian.
+    pub fn to_bytes(self) -> [u8; 16] {
+        self.0.to_be_bytes()
+    }
+
+    /// Returns the timestamp of the UUID, in milliseconds since the unix epoch.
+    pub fn timestamp_ms(self) -> u64 {
+        (self.0 >> 80) as u64
+    }
+
+    /// Returns the 74 random bits of the UUID.
+    pub fn random(self) -> u128 {
+        let rand_a = (self.0 >> 64) & 0xfff;
+        (rand_a << RAND_B_BITS) | (self.0 & ((1 << RAND_B_BITS) - 1))
+    }
+}
+
+impl fmt::Display for UuidV7 {
+    /// Writes the UUID in the hyphenated lowercase form.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let v = self.0;
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            v >> 96,
+            (v >> 80) & 0xffff,
+            (v >> 64) & 0xffff,
+            (v >> 48) & 0xffff,
+            v & 0xffff_ffff_ffff
+        )
+    }
+}
+
+impl FromStr for UuidV7 {
+    type Err = ParseIdError;
+
+    /// Parses the hyphenated form of a version 7 UUID, in any case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 36 {
+            return Err(ParseIdError::InvalidLength);
+        }
+        let mut value = 0u128;
+        for (i, c) in s.bytes().enumerate() {
+            if matches!(i, 8 | 13 | 18 | 23) {
+                if c != b'-' {
+                    return Err(ParseIdError::InvalidCharacter);
+                }
+                continue;
+            }
+            let digit = (c as char)
+                .to_digit(16)
+                .ok_or(ParseIdError::InvalidCharacter)?;
+            value = (value << 4) | u128::from(digit);
+        }
+        if (value >> 76) & 0xf != 0x7 || (value >> 62) & 0b11 != 0b10 {
+            return Err(ParseIdError::InvalidVersion);
+        }
+        Ok(Self(value))
+    }
+}
+
+impl UuidV7Generator {
+    /// Creates a new `UuidV7Generator` reading the system clock.
+    pub fn new() -> Self {
+        Self {
+            monotonic: Monotonic::new(RANDOM_BITS, system_clock()),
+        }
+    }
+
+    /// Creates a new `UuidV7Generator` reading the time from `clock`, in
+    /// milliseconds since the unix epoch.
+    pub fn with_clock(clock: impl Fn() -> u64 + Send + Sync + 'static) -> Self {
+        Self {
+            monotonic: Monotonic::new(RANDOM_BITS, Arc::new(clock)),
+        }
+    }
+
+    /// Returns a new UUID, greater than every UUID returned before.
+    pub fn next(&self) -> UuidV7 {
+        let mut uuid = UuidV7(0);
+        self.monotonic
+            .next_n(1, |ms, random| uuid = UuidV7::from_parts(ms, random));
+        uuid
+    }
+
+    /// Returns `n` new UUIDs in increasing order, greater than every UUID returned
+    /// before, reading the clock and taking the lock of the generator once.
+    pub fn next_batch(&self, n: usize) -> Vec<UuidV7> {
+        let mut uuids = Vec::with_capacity(n);
+        self.monotonic
+            .next_n(n, |ms, random| uuids.push(UuidV7::from_parts(ms, random)));
+        uuids
+    }
+}
+
+impl Default for UuidV7Generator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuid_v7_should_round_trip_through_text() {
+        // the example of RFC 9562, appendix A.6
+        let uuid: UuidV7 = "017F22E2-79B0-7CC3-98C4-DC0C0C07398F".parse().unwrap();
+        assert_eq!(uuid.timestamp_ms(), 0x017f_22e2_79b0);
+        assert_eq!(uuid.to_string(), "017f22e2-79b0-7cc3-98c4-dc0c0c07398f");
+        assert_eq!(UuidV7::from_parts(uuid.timestamp_ms(), uuid.random()), uuid);
+
+        assert_eq!(
+            "017f22e2-79b0-4cc3-98c4-dc0c0c07398f".parse::<UuidV7>(),
+            Err(ParseIdError::InvalidVersion)
+        );
+        assert_eq!(
+            "017f22e2-79b0-7cc3-98c4_dc0c0c07398f".parse::<UuidV7>(),
+            Err(ParseIdError::InvalidCharacter)
+        );
+        assert_eq!(
+            "017f22e2".parse::<UuidV7>(),
+            Err(ParseIdError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn uuid_v7_generator_should_count_within_a_millisecond() {
+        let ids = UuidV7Generator::with_clock(|| 1_000);
+        let batch = ids.next_batch(3);
+        assert!(batch.iter().all(|u| u.timestamp_ms() == 1_000));
+        assert_eq!(batch[1].random(), batch[0].random() + 1);
+        assert!(ids.next() > batch[2]);
+    }
+}
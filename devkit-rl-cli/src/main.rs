@@ -0,0 +1,220 @@
+//! `devkit-rl`, a command line tool around the devkit-rl rate limiters.
+//!
+//! - `devkit-rl serve` runs a local rate-limit server for the limiters of a
+//!   registry config file, see [`server`] for the protocol.
+//! - `devkit-rl pace` copies stdin to stdout, one line at a time, at the
+//!   configured rate.
+//! - `devkit-rl run` executes a command repeatedly at the configured rate.
+//!
+//! ```sh
+//! # at most 5 requests per second against an API
+//! cat urls.txt | devkit-rl pace --rate 5 | xargs -n1 curl -s
+//!
+//! # ping a host 3 times, one every 2 seconds
+//! devkit-rl run --rate 1 --interval-ms 2000 --times 3 -- ping -c1 example.com
+//! ```
+
+mod pace;
+mod server;
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use devkit_rl::{LimiterConfig, LimiterRegistry, LogOverflow};
+
+#[derive(Debug, Parser)]
+#[command(name = "devkit-rl", version, about = "Rate limiting from the shell")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Runs a rate-limit server for the limiters of a config file.
+    Serve {
+        /// The registry config, in JSON, TOML or YAML depending on its extension.
+        #[arg(short, long)]
+        config: PathBuf,
+        /// The address to listen on.
+        #[arg(short, long, default_value = "127.0.0.1:7878")]
+        listen: String,
+    },
+    /// Copies stdin lines to stdout at the configured rate.
+    Pace {
+        #[command(flatten)]
+        limiter: LimiterArgs,
+    },
+    /// Runs a command repeatedly at the configured rate.
+    Run {
+        #[command(flatten)]
+        limiter: LimiterArgs,
+        /// How many times to run the command, forever if omitted.
+        #[arg(short = 'n', long)]
+        times: Option<u64>,
+        /// The command and its arguments.
+        #[arg(required = true, last = true)]
+        command: Vec<String>,
+    },
+}
+
+/// The rate limiting algorithms that can be selected on the command line.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Algorithm {
+    TokenBucket,
+    LeakyBucket,
+    FixedWindow,
+    SlidingWindowLog,
+    SlidingWindowCount,
+}
+
+/// The limiter used to pace `pace` and `run`.
+#[derive(Debug, Args)]
+struct LimiterArgs {
+    /// The rate limiting algorithm.
+    #[arg(short, long, value_enum, default_value_t = Algorithm::TokenBucket)]
+    algorithm: Algorithm,
+    /// How many lines or runs are allowed per interval.
+    #[arg(short, long)]
+    rate: u64,
+    /// The interval, in milliseconds.
+    #[arg(short, long, default_value_t = 1000)]
+    interval_ms: u64,
+    /// How many lines or runs may burst at once, defaults to the rate.
+    #[arg(long)]
+    burst: Option<u64>,
+    /// The number of buckets of the sliding window count algorithm.
+    #[arg(long, default_value_t = 10)]
+    buckets: u64,
+}
+
+impl LimiterArgs {
+    /// Describes the selected limiter as a [`LimiterConfig`].
+    fn config(&self) -> LimiterConfig {
+        let interval_ms = Some(self.interval_ms);
+        let burst = self.burst.unwrap_or(self.rate);
+        match self.algorithm {
+            Algorithm::TokenBucket => LimiterConfig::TokenBucket {
+                capacity: burst,
+                refill_rate: self.rate,
+                refill_interval_ms: interval_ms,
+                initial_tokens: None,
+            },
+            Algorithm::LeakyBucket => LimiterConfig::LeakyBucket {
+                leak_rate: self.rate,
+                capacity: burst,
+                leak_interval_ms: interval_ms,
+                meter: false,
+            },
+            Algorithm::FixedWindow => LimiterConfig::FixedWindow {
+                size: self.rate,
+                interval_ms,
+                smoothing: false,
+            },
+            Algorithm::SlidingWindowLog => LimiterConfig::SlidingWindowLog {
+                size: self.rate,
+                interval_ms,
+                max_entries: None,
+                overflow: LogOverflow::default(),
+            },
+            Algorithm::SlidingWindowCount => LimiterConfig::SlidingWindowCount {
+                size: self.rate,
+                interval_ms,
+                bucket_count: self.buckets,
+            },
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Serve { config, listen } => {
+            load_registry(&config).and_then(|registry| server::serve(&listen, registry))
+        }
+        Command::Pace { limiter } => {
+            pace::pace_lines(&limiter.config().build(), io::stdin().lock(), io::stdout())
+        }
+        Command::Run {
+            limiter,
+            times,
+            command,
+        } => pace::run_command(&limiter.config().build(), &command, times),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        // the reader of our output went away, e.g. `devkit-rl pace | head`
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("devkit-rl: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Loads a registry from a config file, picking the format from its extension.
+fn load_registry(path: &Path) -> io::Result<LimiterRegistry> {
+    let s = fs::read_to_string(path)?;
+    let registry = match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => LimiterRegistry::from_json(&s),
+        Some("toml") => LimiterRegistry::from_toml(&s),
+        Some("yaml" | "yml") => LimiterRegistry::from_yaml(&s),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: expected a .json, .toml or .yaml file", path.display()),
+            ))
+        }
+    };
+    registry.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn cli_should_parse_limiter_args() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::parse_from([
+            "devkit-rl",
+            "run",
+            "-a",
+            "fixed-window",
+            "-r",
+            "3",
+            "-n",
+            "2",
+            "--",
+            "echo",
+            "-n",
+        ]);
+        let Command::Run {
+            limiter,
+            times,
+            command,
+        } = cli.command
+        else {
+            panic!("expected the run command");
+        };
+
+        assert_eq!(times, Some(2));
+        assert_eq!(command, ["echo", "-n"]);
+        assert_eq!(
+            limiter.config(),
+            LimiterConfig::FixedWindow {
+                size: 3,
+                interval_ms: Some(1000),
+                smoothing: false,
+            }
+        );
+    }
+}
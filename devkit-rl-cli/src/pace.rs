@@ -0,0 +1,104 @@
+use std::{
+    io::{self, BufRead, Write},
+    process, thread,
+    time::Duration,
+};
+
+use devkit_rl::{Limiter, RateLimiter};
+
+/// Blocks until `limiter` allows one request.
+///
+/// # Returns
+///
+/// An error if the limiter will never allow a request, e.g. because its rate is 0.
+fn wait(limiter: &Limiter) -> io::Result<()> {
+    while !limiter.allow() {
+        let wait = limiter.next_available(1);
+        if wait == Duration::MAX {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the limiter never allows a request",
+            ));
+        }
+        // other holders of the limiter may take the request first, never spin
+        thread::sleep(wait.max(Duration::from_millis(1)));
+    }
+    Ok(())
+}
+
+/// Copies `input` to `output` line by line, waiting for `limiter` before each line.
+///
+/// Every line is flushed as soon as it is written, so that the next command of a
+/// pipeline sees it at the paced time.
+pub fn pace_lines(
+    limiter: &Limiter,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        wait(limiter)?;
+        writeln!(output, "{line}")?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// Runs `command` `times` times, or forever if `None`, waiting for `limiter`
+/// before each run.
+///
+/// A run that fails to start is an error, while a run that exits unsuccessfully is
+/// only reported, so that a flaky command keeps being paced.
+pub fn run_command(limiter: &Limiter, command: &[String], times: Option<u64>) -> io::Result<()> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no command given"))?;
+
+    let mut run = 0;
+    while times.is_none_or(|times| run < times) {
+        wait(limiter)?;
+        let status = process::Command::new(program).args(args).status()?;
+        if !status.success() {
+            eprintln!("devkit-rl: {program} exited with {status}");
+        }
+        run += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use devkit_rl::LimiterConfig;
+
+    use super::*;
+
+    #[test]
+    fn pace_lines_should_wait_for_the_limiter() {
+        const INTERVAL_MS: u64 = 20;
+
+        let limiter = LimiterConfig::FixedWindow {
+            size: 2,
+            interval_ms: Some(INTERVAL_MS),
+            smoothing: false,
+        }
+        .build();
+
+        let start = Instant::now();
+        let mut output = Vec::new();
+        pace_lines(&limiter, "a\nb\nc\nd\ne\n".as_bytes(), &mut output).unwrap();
+
+        // 2 lines per window, so the 5th line needs a third window
+        assert_eq!(output, b"a\nb\nc\nd\ne\n");
+        assert!(start.elapsed() >= Duration::from_millis(INTERVAL_MS));
+
+        let never = LimiterConfig::FixedWindow {
+            size: 0,
+            interval_ms: None,
+            smoothing: false,
+        }
+        .build();
+        assert!(pace_lines(&never, "a\n".as_bytes(), io::sink()).is_err());
+    }
+}
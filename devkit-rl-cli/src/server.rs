@@ -0,0 +1,281 @@
+//! A local rate-limit server for the limiters of a [`LimiterRegistry`].
+//!
+//! Clients connect over TCP and send one command per line:
+//!
+//! - `ALLOW <name> [n]` attempts to allow `n` (default 1) requests, and replies
+//!   `OK`, or `LIMITED <ms>` with the milliseconds to wait before retrying.
+//! - `NEXT <name> [n]` replies `WAIT <ms>`, the milliseconds to wait until `n`
+//!   requests would be allowed, without taking them.
+//!
+//! A wait that never ends is sent as `never`, and errors are replied as
+//! `ERR <message>`.
+//!
+//! The same commands are served over HTTP, as `GET /allow/<name>?n=<n>` and
+//! `GET /next/<name>?n=<n>`. A limited request is answered with
+//! `429 Too Many Requests` and a `Retry-After` header, so `curl --fail` works in
+//! scripts.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use devkit_rl::{LimiterRegistry, RateLimiter};
+
+/// A command sent by a client.
+#[derive(Debug, PartialEq, Eq)]
+enum Request {
+    Allow { name: String, n: u64 },
+    Next { name: String, n: u64 },
+}
+
+/// The reply to a [`Request`].
+#[derive(Debug, PartialEq, Eq)]
+enum Response {
+    Allowed,
+    Limited(Duration),
+    Wait(Duration),
+    UnknownLimiter(String),
+    BadRequest(String),
+}
+
+/// Serves the limiters of `registry` on `addr` until the process is stopped.
+///
+/// Every connection is handled on its own thread.
+pub fn serve(addr: &str, registry: LimiterRegistry) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("devkit-rl: serving {:?} on {addr}", registry.names());
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let registry = registry.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(&registry, stream) {
+                eprintln!("devkit-rl: connection failed: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Answers the commands of one client, over the line protocol or HTTP depending
+/// on the first line it sends.
+fn handle_connection(registry: &LimiterRegistry, stream: TcpStream) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut lines = BufReader::new(stream).lines();
+
+    let Some(first) = lines.next().transpose()? else {
+        return Ok(());
+    };
+    if let Some(target) = first.strip_prefix("GET ") {
+        // skip the headers, the request has no body
+        for line in lines.by_ref() {
+            if line?.is_empty() {
+                break;
+            }
+        }
+        let target = target.split(' ').next().unwrap_or_default();
+        let response = parse_http_target(target)
+            .map_or_else(Response::BadRequest, |request| handle(registry, request));
+        return response.write_http(&mut writer);
+    }
+
+    for line in std::iter::once(Ok(first)).chain(lines) {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = parse_line(&line)
+            .map_or_else(Response::BadRequest, |request| handle(registry, request));
+        writeln!(writer, "{}", response.to_line())?;
+    }
+    Ok(())
+}
+
+/// Runs `request` against the limiters of `registry`.
+fn handle(registry: &LimiterRegistry, request: Request) -> Response {
+    let (Request::Allow { name, .. } | Request::Next { name, .. }) = &request;
+    let Some(limiter) = registry.get(name) else {
+        return Response::UnknownLimiter(name.clone());
+    };
+
+    match request {
+        Request::Allow { n, .. } if limiter.allow_n(n) => Response::Allowed,
+        Request::Allow { n, .. } => Response::Limited(limiter.next_available(n)),
+        Request::Next { n, .. } => Response::Wait(limiter.next_available(n)),
+    }
+}
+
+/// Parses a command of the line protocol, e.g. `ALLOW api 2`.
+fn parse_line(line: &str) -> Result<Request, String> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let name = words
+        .next()
+        .ok_or_else(|| format!("missing limiter name in {line:?}"))?
+        .to_string();
+    let n = words.next().map_or(Ok(1), parse_n)?;
+    if words.next().is_some() {
+        return Err(format!("unexpected arguments in {line:?}"));
+    }
+
+    match command.to_ascii_uppercase().as_str() {
+        "ALLOW" => Ok(Request::Allow { name, n }),
+        "NEXT" => Ok(Request::Next { name, n }),
+        _ => Err(format!("unknown command {command:?}")),
+    }
+}
+
+/// Parses the target of an HTTP request, e.g. `/allow/api?n=2`.
+fn parse_http_target(target: &str) -> Result<Request, String> {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let n = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("n="))
+        .map_or(Ok(1), parse_n)?;
+
+    match path.trim_start_matches('/').split_once('/') {
+        Some(("allow", name)) if !name.is_empty() => Ok(Request::Allow {
+            name: name.to_string(),
+            n,
+        }),
+        Some(("next", name)) if !name.is_empty() => Ok(Request::Next {
+            name: name.to_string(),
+            n,
+        }),
+        _ => Err(format!("unknown path {path:?}")),
+    }
+}
+
+fn parse_n(s: &str) -> Result<u64, String> {
+    s.parse().map_err(|_| format!("invalid count {s:?}"))
+}
+
+/// Formats a wait in milliseconds, rounding up so that a client retrying after
+/// it is not early.
+fn millis(wait: Duration) -> String {
+    if wait == Duration::MAX {
+        "never".to_string()
+    } else {
+        wait.as_nanos().div_ceil(1_000_000).to_string()
+    }
+}
+
+impl Response {
+    /// Formats the response for the line protocol.
+    fn to_line(&self) -> String {
+        match self {
+            Response::Allowed => "OK".to_string(),
+            Response::Limited(wait) => format!("LIMITED {}", millis(*wait)),
+            Response::Wait(wait) => format!("WAIT {}", millis(*wait)),
+            Response::UnknownLimiter(name) => format!("ERR unknown limiter {name:?}"),
+            Response::BadRequest(message) => format!("ERR {message}"),
+        }
+    }
+
+    /// Writes the response as an HTTP/1.1 response, closing the connection.
+    fn write_http(&self, w: &mut impl Write) -> io::Result<()> {
+        let status = match self {
+            Response::Allowed | Response::Wait(_) => "200 OK",
+            Response::Limited(_) => "429 Too Many Requests",
+            Response::UnknownLimiter(_) => "404 Not Found",
+            Response::BadRequest(_) => "400 Bad Request",
+        };
+        let body = self.to_line();
+
+        write!(w, "HTTP/1.1 {status}\r\n")?;
+        if let Response::Limited(wait) = self {
+            if *wait != Duration::MAX {
+                write!(w, "Retry-After: {}\r\n", wait.as_secs_f64().ceil() as u64)?;
+            }
+        }
+        write!(
+            w,
+            "Content-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}\n",
+            body.len() + 1
+        )?;
+        w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use devkit_rl::LimiterConfig;
+
+    use super::*;
+
+    fn start(registry: LimiterRegistry) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let registry = registry.clone();
+                let stream = stream.unwrap();
+                thread::spawn(move || handle_connection(&registry, stream));
+            }
+        });
+        addr
+    }
+
+    fn registry() -> LimiterRegistry {
+        let registry = LimiterRegistry::new();
+        registry.insert(
+            "api",
+            LimiterConfig::FixedWindow {
+                size: 2,
+                interval_ms: Some(60_000),
+                smoothing: false,
+            }
+            .build(),
+        );
+        registry
+    }
+
+    #[test]
+    fn server_should_answer_line_commands() {
+        let addr = start(registry());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"ALLOW api\nnext api 1\nALLOW api 2\nALLOW api 3\nALLOW nope\nPING\n")
+            .unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+
+        let replies: Vec<String> = BufReader::new(stream).lines().map(Result::unwrap).collect();
+        assert_eq!(replies[0], "OK");
+        assert_eq!(replies[1], "WAIT 0");
+        assert!(replies[2].starts_with("LIMITED "));
+        assert_ne!(replies[2], "LIMITED 0");
+        assert_eq!(replies[3], "LIMITED never");
+        assert_eq!(replies[4], "ERR unknown limiter \"nope\"");
+        assert!(replies[5].starts_with("ERR "));
+        assert_eq!(replies.len(), 6);
+    }
+
+    #[test]
+    fn server_should_answer_http_requests() {
+        let addr = start(registry());
+
+        let get = |target: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        assert!(get("/allow/api?n=2").starts_with("HTTP/1.1 200 OK\r\n"));
+        let limited = get("/allow/api");
+        assert!(limited.starts_with("HTTP/1.1 429 Too Many Requests\r\n"));
+        assert!(limited.contains("\r\nRetry-After: "));
+        assert!(get("/allow/nope").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(get("/allow/api?n=x").starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        let next = get("/next/api");
+        assert!(next.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(!next.ends_with("\r\n\r\nWAIT 0\n"));
+    }
+}
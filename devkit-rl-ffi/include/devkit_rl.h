@@ -0,0 +1,90 @@
+/*
+ * Thread safety: a handle may be used from any number of threads at the same
+ * time, without external locking. Only devkit_rl_free requires exclusive access,
+ * and must be called exactly once, after every other thread stopped using the
+ * handle.
+ */
+
+#ifndef DEVKIT_RL_H
+#define DEVKIT_RL_H
+
+/* Generated by cbindgen from devkit-rl-ffi, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// An opaque handle on a rate limiter.
+typedef struct DevkitRateLimiter DevkitRateLimiter;
+
+// Creates a token bucket holding up to `capacity` tokens, refilled with
+// `refill_rate` tokens every `refill_interval_ms` milliseconds (0 for 1 second).
+struct DevkitRateLimiter *devkit_rl_token_bucket_new(uint64_t capacity,
+                                                     uint64_t refill_rate,
+                                                     uint64_t refill_interval_ms);
+
+// Creates a leaky bucket holding up to `capacity` events, leaking `leak_rate`
+// events every `leak_interval_ms` milliseconds (0 for 1 second).
+//
+// `devkit_rl_allow` and `devkit_rl_allow_n` block on a leaky bucket until the
+// events have leaked out.
+struct DevkitRateLimiter *devkit_rl_leaky_bucket_new(uint64_t leak_rate,
+                                                     uint64_t capacity,
+                                                     uint64_t leak_interval_ms);
+
+// Creates a fixed window allowing `size` requests every `interval_ms`
+// milliseconds (0 for 1 second), smoothing window boundaries if `smoothing` is set.
+struct DevkitRateLimiter *devkit_rl_fixed_window_new(uint64_t size,
+                                                     uint64_t interval_ms,
+                                                     bool smoothing);
+
+// Creates a sliding window log allowing `size` requests in any window of
+// `interval_ms` milliseconds (0 for 1 second).
+struct DevkitRateLimiter *devkit_rl_sliding_window_log_new(uint64_t size, uint64_t interval_ms);
+
+// Creates a sliding window counter allowing `size` requests in any window of
+// `interval_ms` milliseconds (0 for 1 second), counted in `bucket_count` buckets.
+//
+// Returns NULL if `bucket_count` is 0.
+struct DevkitRateLimiter *devkit_rl_sliding_window_count_new(uint64_t size,
+                                                             uint64_t interval_ms,
+                                                             uint64_t bucket_count);
+
+// Attempts to allow a single request. Returns false if `limiter` is NULL.
+//
+// # Safety
+//
+// `limiter` must be NULL or a handle returned by a `devkit_rl_*_new` function
+// that has not been freed.
+bool devkit_rl_allow(const struct DevkitRateLimiter *limiter);
+
+// Attempts to allow `n` requests at once. Returns false if `limiter` is NULL.
+//
+// # Safety
+//
+// `limiter` must be NULL or a handle returned by a `devkit_rl_*_new` function
+// that has not been freed.
+bool devkit_rl_allow_n(const struct DevkitRateLimiter *limiter, uint64_t n);
+
+// Estimates how many milliseconds to wait until `n` requests would be allowed.
+//
+// Returns 0 if they would be allowed now, and `UINT64_MAX` if they never will be
+// or if `limiter` is NULL.
+//
+// # Safety
+//
+// `limiter` must be NULL or a handle returned by a `devkit_rl_*_new` function
+// that has not been freed.
+uint64_t devkit_rl_next_available_ms(const struct DevkitRateLimiter *limiter, uint64_t n);
+
+// Releases a limiter. Does nothing if `limiter` is NULL.
+//
+// # Safety
+//
+// `limiter` must be NULL or a handle returned by a `devkit_rl_*_new` function
+// that has not been freed, and no other thread may use it any more.
+void devkit_rl_free(struct DevkitRateLimiter *limiter);
+
+#endif  /* DEVKIT_RL_H */
@@ -0,0 +1,238 @@
+//! C bindings for the devkit-rl rate limiters.
+//!
+//! Every limiter is created by one of the `devkit_rl_*_new` functions, which
+//! returns an opaque handle, and must be released with [`devkit_rl_free`]. The
+//! C header `include/devkit_rl.h` is generated from this file with cbindgen.
+//!
+//! # Thread safety
+//!
+//! A handle may be used from any number of threads at the same time, without
+//! external locking: every limiter synchronizes its state internally. Only
+//! [`devkit_rl_free`] requires exclusive access, and must be called exactly once,
+//! after every other thread has stopped using the handle.
+
+use std::time::Duration;
+
+use devkit_rl::{
+    FixedWindow, LeakyBucket, Limiter, RateLimiter, SlidingWindowCount, SlidingWindowLog,
+    TokenBucket,
+};
+
+/// An opaque handle on a rate limiter.
+pub struct DevkitRateLimiter {
+    limiter: Limiter,
+}
+
+/// Converts an interval in milliseconds to a duration, 0 meaning the default of 1 second.
+fn interval(ms: u64) -> Option<Duration> {
+    (ms != 0).then(|| Duration::from_millis(ms))
+}
+
+fn into_handle(limiter: Limiter) -> *mut DevkitRateLimiter {
+    Box::into_raw(Box::new(DevkitRateLimiter { limiter }))
+}
+
+/// Creates a token bucket holding up to `capacity` tokens, refilled with
+/// `refill_rate` tokens every `refill_interval_ms` milliseconds (0 for 1 second).
+#[no_mangle]
+pub extern "C" fn devkit_rl_token_bucket_new(
+    capacity: u64,
+    refill_rate: u64,
+    refill_interval_ms: u64,
+) -> *mut DevkitRateLimiter {
+    into_handle(Limiter::TokenBucket(TokenBucket::new(
+        capacity,
+        refill_rate,
+        interval(refill_interval_ms),
+    )))
+}
+
+/// Creates a leaky bucket holding up to `capacity` events, leaking `leak_rate`
+/// events every `leak_interval_ms` milliseconds (0 for 1 second).
+///
+/// `devkit_rl_allow` and `devkit_rl_allow_n` block on a leaky bucket until the
+/// events have leaked out.
+#[no_mangle]
+pub extern "C" fn devkit_rl_leaky_bucket_new(
+    leak_rate: u64,
+    capacity: u64,
+    leak_interval_ms: u64,
+) -> *mut DevkitRateLimiter {
+    into_handle(Limiter::LeakyBucket(LeakyBucket::new(
+        leak_rate,
+        capacity,
+        interval(leak_interval_ms),
+    )))
+}
+
+/// Creates a fixed window allowing `size` requests every `interval_ms`
+/// milliseconds (0 for 1 second), smoothing window boundaries if `smoothing` is set.
+#[no_mangle]
+pub extern "C" fn devkit_rl_fixed_window_new(
+    size: u64,
+    interval_ms: u64,
+    smoothing: bool,
+) -> *mut DevkitRateLimiter {
+    into_handle(Limiter::FixedWindow(FixedWindow::with_smoothing(
+        size,
+        interval(interval_ms),
+        smoothing,
+    )))
+}
+
+/// Creates a sliding window log allowing `size` requests in any window of
+/// `interval_ms` milliseconds (0 for 1 second).
+#[no_mangle]
+pub extern "C" fn devkit_rl_sliding_window_log_new(
+    size: u64,
+    interval_ms: u64,
+) -> *mut DevkitRateLimiter {
+    into_handle(Limiter::SlidingWindowLog(SlidingWindowLog::new(
+        size,
+        interval(interval_ms),
+    )))
+}
+
+/// Creates a sliding window counter allowing `size` requests in any window of
+/// `interval_ms` milliseconds (0 for 1 second), counted in `bucket_count` buckets.
+///
+/// Returns NULL if `bucket_count` is 0.
+#[no_mangle]
+pub extern "C" fn devkit_rl_sliding_window_count_new(
+    size: u64,
+    interval_ms: u64,
+    bucket_count: u64,
+) -> *mut DevkitRateLimiter {
+    if bucket_count == 0 {
+        return std::ptr::null_mut();
+    }
+    into_handle(Limiter::SlidingWindowCount(SlidingWindowCount::new(
+        size,
+        interval(interval_ms).unwrap_or(Duration::from_secs(1)),
+        bucket_count,
+    )))
+}
+
+/// Attempts to allow a single request. Returns false if `limiter` is NULL.
+///
+/// # Safety
+///
+/// `limiter` must be NULL or a handle returned by a `devkit_rl_*_new` function
+/// that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn devkit_rl_allow(limiter: *const DevkitRateLimiter) -> bool {
+    devkit_rl_allow_n(limiter, 1)
+}
+
+/// Attempts to allow `n` requests at once. Returns false if `limiter` is NULL.
+///
+/// # Safety
+///
+/// `limiter` must be NULL or a handle returned by a `devkit_rl_*_new` function
+/// that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn devkit_rl_allow_n(limiter: *const DevkitRateLimiter, n: u64) -> bool {
+    // SAFETY: guaranteed by the caller.
+    match unsafe { limiter.as_ref() } {
+        Some(handle) => handle.limiter.allow_n(n),
+        None => false,
+    }
+}
+
+/// Estimates how many milliseconds to wait until `n` requests would be allowed.
+///
+/// Returns 0 if they would be allowed now, and `UINT64_MAX` if they never will be
+/// or if `limiter` is NULL.
+///
+/// # Safety
+///
+/// `limiter` must be NULL or a handle returned by a `devkit_rl_*_new` function
+/// that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn devkit_rl_next_available_ms(
+    limiter: *const DevkitRateLimiter,
+    n: u64,
+) -> u64 {
+    // SAFETY: guaranteed by the caller.
+    match unsafe { limiter.as_ref() } {
+        Some(handle) => {
+            let wait = handle.limiter.next_available(n);
+            // round up, so that retrying after the wait is not too early
+            u64::try_from(wait.as_nanos().div_ceil(1_000_000)).unwrap_or(u64::MAX)
+        }
+        None => u64::MAX,
+    }
+}
+
+/// Releases a limiter. Does nothing if `limiter` is NULL.
+///
+/// # Safety
+///
+/// `limiter` must be NULL or a handle returned by a `devkit_rl_*_new` function
+/// that has not been freed, and no other thread may use it any more.
+#[no_mangle]
+pub unsafe extern "C" fn devkit_rl_free(limiter: *mut DevkitRateLimiter) {
+    if !limiter.is_null() {
+        // SAFETY: guaranteed by the caller.
+        drop(unsafe { Box::from_raw(limiter) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, thread};
+
+    use super::*;
+
+    #[test]
+    fn ffi_limiter_should_work_across_threads() {
+        let limiter = devkit_rl_fixed_window_new(100, 60_000, false);
+        let shared = limiter as usize;
+
+        let allowed: u64 = thread::scope(|s| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(move || {
+                        let limiter = shared as *const DevkitRateLimiter;
+                        (0..50)
+                            .filter(|_| unsafe { devkit_rl_allow(limiter) })
+                            .count() as u64
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).sum()
+        });
+        assert_eq!(allowed, 100);
+
+        unsafe {
+            assert!(devkit_rl_next_available_ms(limiter, 1) > 0);
+            assert_eq!(devkit_rl_next_available_ms(limiter, 101), u64::MAX);
+            devkit_rl_free(limiter);
+
+            assert!(!devkit_rl_allow(std::ptr::null()));
+            devkit_rl_free(std::ptr::null_mut());
+        }
+        assert!(devkit_rl_sliding_window_count_new(1, 0, 0).is_null());
+    }
+
+    #[test]
+    fn header_should_be_up_to_date() {
+        let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+
+        let mut generated = Vec::new();
+        cbindgen::generate_with_config(crate_dir, config)
+            .unwrap()
+            .write(&mut generated);
+
+        let header = crate_dir.join("include/devkit_rl.h");
+        if std::env::var_os("DEVKIT_RL_BLESS").is_some() {
+            std::fs::write(&header, &generated).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(generated).unwrap(),
+            std::fs::read_to_string(header).unwrap_or_default(),
+            "include/devkit_rl.h is stale, rerun the test with DEVKIT_RL_BLESS=1"
+        );
+    }
+}
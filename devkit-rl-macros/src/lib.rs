@@ -0,0 +1,180 @@
+//! The `#[rate_limited]` attribute, re-exported by `devkit-rl` with its `macros` feature.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input, parse_quote, Expr, ItemFn, LitStr, ReturnType, Token,
+};
+
+/// Rate limits a function with a limiter of the process-wide registry.
+///
+/// Every call looks up the limiter registered under the given name with
+/// `devkit_rl::limiter`, and takes `cost` requests from it before running the body
+/// of the function. What happens when the limiter denies the call depends on the
+/// mode:
+///
+/// * `error`, the default - the function returns
+///   `Err(devkit_rl::Error::RateLimited.into())`, so its error type must implement
+///   `From<devkit_rl::Error>`.
+/// * `block` - the thread blocks until the limiter allows the call, with
+///   `Limiter::wait`.
+/// * `wait` - the `async` function waits until the limiter allows the call, with
+///   `Limiter::wait_async`, which needs the `tokio` feature of `devkit-rl`.
+///
+/// `cost` defaults to 1, and may be any expression of type `u64`, including one
+/// reading the arguments of the function.
+///
+/// Calls are not limited while no limiter is registered under the name, so that a
+/// library can be rate limited by the application using it.
+///
+/// # Panics
+///
+/// In the `block` and `wait` modes, the function panics if the limiter can never
+/// allow the call, e.g. because `cost` exceeds its capacity.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{Error, LimiterConfig, LimiterRegistry};
+/// use devkit_rl_macros::rate_limited;
+///
+/// #[rate_limited("search-api")]
+/// fn search(query: &str) -> Result<usize, Error> {
+///     Ok(query.len())
+/// }
+///
+/// #[rate_limited("search-api", block, cost = bytes.len() as u64)]
+/// fn upload(bytes: &[u8]) {}
+///
+/// LimiterRegistry::global().register(
+///     "search-api",
+///     LimiterConfig::TokenBucket {
+///         capacity: 1,
+///         refill_rate: 1,
+///         refill_interval_ms: Some(60_000),
+///         initial_tokens: None,
+///     },
+/// );
+///
+/// assert_eq!(search("rust").unwrap(), 4);
+/// assert!(matches!(search("rust"), Err(Error::RateLimited)));
+/// ```
+#[proc_macro_attribute]
+pub fn rate_limited(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as Args);
+    let mut item = parse_macro_input!(item as ItemFn);
+    match expand(&args, &mut item) {
+        Ok(()) => quote!(#item).into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// What a rate limited function does when its limiter denies a call.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Error,
+    Block,
+    Wait,
+}
+
+/// The arguments of `#[rate_limited]`.
+struct Args {
+    name: LitStr,
+    mode: Mode,
+    cost: Expr,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        let mut mode = None;
+        let mut cost = None;
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let ident: Ident = input.parse()?;
+            match ident.to_string().as_str() {
+                "error" if mode.is_none() => mode = Some(Mode::Error),
+                "block" if mode.is_none() => mode = Some(Mode::Block),
+                "wait" if mode.is_none() => mode = Some(Mode::Wait),
+                "cost" if cost.is_none() => {
+                    input.parse::<Token![=]>()?;
+                    cost = Some(input.parse()?);
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        ident.span(),
+                        "expected one of `error`, `block`, `wait` or `cost = ...`",
+                    ))
+                }
+            }
+        }
+        Ok(Self {
+            name,
+            mode: mode.unwrap_or(Mode::Error),
+            cost: cost.unwrap_or_else(|| parse_quote!(1)),
+        })
+    }
+}
+
+/// Prepends the rate limiting of `args` to the body of `item`.
+fn expand(args: &Args, item: &mut ItemFn) -> syn::Result<()> {
+    let is_async = item.sig.asyncness.is_some();
+    match args.mode {
+        Mode::Error if matches!(item.sig.output, ReturnType::Default) => {
+            return Err(syn::Error::new_spanned(
+                &item.sig,
+                "`#[rate_limited]` returns an error when rate limited, the function must return a `Result`",
+            ));
+        }
+        Mode::Block if is_async => {
+            return Err(syn::Error::new_spanned(
+                &item.sig,
+                "an `async fn` must not block its thread, use `wait` instead of `block`",
+            ));
+        }
+        Mode::Wait if !is_async => {
+            return Err(syn::Error::new_spanned(
+                &item.sig,
+                "only an `async fn` can wait, use `block` instead of `wait`",
+            ));
+        }
+        _ => {}
+    }
+
+    let Args { name, cost, .. } = args;
+    // not visible to the cost expression nor the body
+    let limiter = Ident::new("limiter", Span::mixed_site());
+    let check: TokenStream2 = match args.mode {
+        Mode::Error => quote! {
+            if !::devkit_rl::RateLimiter::allow_n(&#limiter, #cost) {
+                return ::core::result::Result::Err(::core::convert::From::from(
+                    ::devkit_rl::Error::RateLimited,
+                ));
+            }
+        },
+        Mode::Block => quote! {
+            if let ::core::result::Result::Err(e) = #limiter.wait(#cost) {
+                ::core::panic!("rate limiter {:?} cannot allow the call: {}", #name, e);
+            }
+        },
+        Mode::Wait => quote! {
+            if let ::core::result::Result::Err(e) = #limiter.wait_async(#cost).await {
+                ::core::panic!("rate limiter {:?} cannot allow the call: {}", #name, e);
+            }
+        },
+    };
+
+    let body = &item.block;
+    item.block = parse_quote!({
+        if let ::core::option::Option::Some(#limiter) = ::devkit_rl::limiter(#name) {
+            #check
+        }
+        #body
+    });
+    Ok(())
+}
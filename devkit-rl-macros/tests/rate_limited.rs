@@ -0,0 +1,94 @@
+use std::{sync::Arc, time::Duration};
+
+use devkit_rl::{Error, Limiter, LimiterRegistry, TokenBucket};
+use devkit_rl_macros::rate_limited;
+
+/// Registers a token bucket of `capacity` requests refilled once per `interval`.
+fn register(name: &str, capacity: u64, interval: Duration) {
+    LimiterRegistry::global().insert(
+        name,
+        Limiter::TokenBucket(TokenBucket::new(capacity, capacity, Some(interval))),
+    );
+}
+
+#[derive(Debug, PartialEq)]
+enum ApiError {
+    RateLimited,
+}
+
+impl From<Error> for ApiError {
+    fn from(_: Error) -> Self {
+        ApiError::RateLimited
+    }
+}
+
+#[rate_limited("tests-error")]
+fn search(query: &str) -> Result<usize, ApiError> {
+    Ok(query.len())
+}
+
+#[rate_limited("tests-cost", error, cost = batch.len() as u64)]
+fn send(batch: &[u8]) -> Result<(), Error> {
+    Ok(())
+}
+
+struct Client;
+
+impl Client {
+    #[rate_limited("tests-block", block)]
+    fn get(&self, limiter: u32) -> u32 {
+        limiter
+    }
+}
+
+#[rate_limited("tests-wait", wait, cost = 2)]
+async fn fetch(id: u32) -> u32 {
+    id
+}
+
+#[test]
+fn rate_limited_should_return_an_error() {
+    // nothing is registered yet: calls are not limited
+    assert_eq!(search("a"), Ok(1));
+    assert_eq!(search("a"), Ok(1));
+
+    register("tests-error", 1, Duration::from_secs(60));
+    assert_eq!(search("rust"), Ok(4));
+    assert_eq!(search("rust"), Err(ApiError::RateLimited));
+
+    register("tests-cost", 4, Duration::from_secs(60));
+    assert!(send(&[0; 3]).is_ok());
+    assert!(matches!(send(&[0; 2]), Err(Error::RateLimited)));
+    assert!(send(&[0]).is_ok());
+}
+
+#[test]
+fn rate_limited_should_block_until_allowed() {
+    const INTERVAL: Duration = Duration::from_millis(20);
+    register("tests-block", 1, INTERVAL);
+
+    let start = std::time::Instant::now();
+    // the argument named like the limiter of the expansion is not shadowed
+    assert_eq!(Client.get(1), 1);
+    assert_eq!(Client.get(2), 2);
+    assert!(start.elapsed() >= INTERVAL / 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn rate_limited_should_wait_until_allowed() {
+    let start = tokio::time::Instant::now();
+    let clock = Arc::new(move || start.elapsed());
+    LimiterRegistry::global().insert(
+        "tests-wait",
+        Limiter::TokenBucket(TokenBucket::with_clock(
+            2,
+            2,
+            Some(Duration::from_secs(1)),
+            clock,
+        )),
+    );
+
+    assert_eq!(fetch(1).await, 1);
+    assert_eq!(fetch(2).await, 2);
+    assert_eq!(start.elapsed(), Duration::from_secs(1));
+}
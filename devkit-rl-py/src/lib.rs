@@ -0,0 +1,235 @@
+//! Python bindings for the devkit-rl rate limiters.
+//!
+//! The module is built with maturin and imported as `devkit_rl`:
+//!
+//! ```python
+//! from devkit_rl import TokenBucket
+//!
+//! bucket = TokenBucket(capacity=10, refill_rate=5, refill_interval=1.0)
+//! for row in rows:
+//!     bucket.acquire()  # blocks without holding the GIL
+//!     call_api(row)
+//! ```
+//!
+//! Durations are given and returned in seconds, as floats. Every limiter is
+//! thread-safe and can be shared between Python threads.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use devkit_rl::{FixedWindow, RateLimiter, SlidingWindowCount, SlidingWindowLog, TokenBucket};
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+/// The longest a blocking acquire waits without the GIL before checking for
+/// signals, so that Ctrl-C interrupts it promptly.
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Converts a duration in seconds from Python.
+fn seconds(secs: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(secs)
+        .map_err(|e| PyValueError::new_err(format!("invalid duration {secs}: {e}")))
+}
+
+/// Waits for `n` requests to be allowed, without the GIL.
+///
+/// # Returns
+///
+/// `true` once the requests are allowed, `false` if they cannot be allowed before
+/// `timeout` seconds have passed.
+fn acquire(
+    py: Python<'_>,
+    limiter: &dyn RateLimiter,
+    n: u64,
+    timeout: Option<f64>,
+) -> PyResult<bool> {
+    let deadline = timeout
+        .map(seconds)
+        .transpose()?
+        .map(|t| Instant::now() + t);
+    loop {
+        if let Some(allowed) = py.allow_threads(|| acquire_step(limiter, n, deadline)) {
+            return Ok(allowed);
+        }
+        py.check_signals()?;
+    }
+}
+
+/// Tries to allow `n` requests, and sleeps for at most [`SIGNAL_CHECK_INTERVAL`]
+/// towards the time they would be allowed if they are not.
+///
+/// # Returns
+///
+/// `Some(true)` if the requests were allowed, `Some(false)` if they cannot be
+/// allowed before `deadline`, or `None` if the caller should try again.
+fn acquire_step(limiter: &dyn RateLimiter, n: u64, deadline: Option<Instant>) -> Option<bool> {
+    if limiter.allow_n(n) {
+        return Some(true);
+    }
+
+    let wait = limiter.next_available(n);
+    if wait == Duration::MAX {
+        return Some(false);
+    }
+
+    // other callers may take the requests first, so never spin without sleeping
+    let mut sleep = wait.clamp(Duration::from_millis(1), SIGNAL_CHECK_INTERVAL);
+    if let Some(deadline) = deadline {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left < wait {
+            return Some(false);
+        }
+        sleep = sleep.min(left);
+    }
+    thread::sleep(sleep);
+    None
+}
+
+/// Converts a wait to seconds for Python, `inf` standing for never.
+fn wait_seconds(wait: Duration) -> f64 {
+    if wait == Duration::MAX {
+        f64::INFINITY
+    } else {
+        wait.as_secs_f64()
+    }
+}
+
+/// Generates the methods of a limiter class, its constructor followed by the
+/// methods shared by every limiter.
+macro_rules! limiter_methods {
+    ($class:ident { $($ctor:tt)* }) => {
+        #[pymethods]
+        impl $class {
+            $($ctor)*
+
+            /// Attempts to allow `n` requests, without blocking.
+            #[pyo3(signature = (n = 1))]
+            fn allow(&self, py: Python<'_>, n: u64) -> bool {
+                py.allow_threads(|| self.inner.allow_n(n))
+            }
+
+            /// Waits until `n` requests are allowed, releasing the GIL meanwhile.
+            ///
+            /// Returns `False` if they cannot be allowed within `timeout` seconds, or
+            /// ever, because `n` exceeds the limit.
+            #[pyo3(signature = (n = 1, timeout = None))]
+            fn acquire(&self, py: Python<'_>, n: u64, timeout: Option<f64>) -> PyResult<bool> {
+                acquire(py, &self.inner, n, timeout)
+            }
+
+            /// Estimates the seconds to wait until `n` requests are allowed, `inf` if never.
+            #[pyo3(signature = (n = 1))]
+            fn next_available(&self, n: u64) -> f64 {
+                wait_seconds(self.inner.next_available(n))
+            }
+        }
+    };
+}
+
+/// A token bucket holding up to `capacity` tokens, refilled with `refill_rate`
+/// tokens every `refill_interval` seconds.
+#[pyclass(frozen, name = "TokenBucket", module = "devkit_rl")]
+struct PyTokenBucket {
+    inner: TokenBucket,
+}
+
+limiter_methods!(PyTokenBucket {
+    #[new]
+    #[pyo3(signature = (capacity, refill_rate, refill_interval = 1.0))]
+    fn new(capacity: u64, refill_rate: u64, refill_interval: f64) -> PyResult<Self> {
+        Ok(Self {
+            inner: TokenBucket::new(capacity, refill_rate, Some(seconds(refill_interval)?)),
+        })
+    }
+});
+
+/// A fixed window allowing `size` requests every `interval` seconds, optionally
+/// smoothing window boundaries.
+#[pyclass(frozen, name = "FixedWindow", module = "devkit_rl")]
+struct PyFixedWindow {
+    inner: FixedWindow,
+}
+
+limiter_methods!(PyFixedWindow {
+    #[new]
+    #[pyo3(signature = (size, interval = 1.0, smoothing = false))]
+    fn new(size: u64, interval: f64, smoothing: bool) -> PyResult<Self> {
+        Ok(Self {
+            inner: FixedWindow::with_smoothing(size, Some(seconds(interval)?), smoothing),
+        })
+    }
+});
+
+/// A sliding window allowing `size` requests in any `interval` seconds, logging
+/// every request.
+#[pyclass(frozen, name = "SlidingWindowLog", module = "devkit_rl")]
+struct PySlidingWindowLog {
+    inner: SlidingWindowLog,
+}
+
+limiter_methods!(PySlidingWindowLog {
+    #[new]
+    #[pyo3(signature = (size, interval = 1.0))]
+    fn new(size: u64, interval: f64) -> PyResult<Self> {
+        Ok(Self {
+            inner: SlidingWindowLog::new(size, Some(seconds(interval)?)),
+        })
+    }
+});
+
+/// A sliding window allowing `size` requests in any `interval` seconds, counting
+/// requests in `bucket_count` buckets.
+#[pyclass(frozen, name = "SlidingWindowCount", module = "devkit_rl")]
+struct PySlidingWindowCount {
+    inner: SlidingWindowCount,
+}
+
+limiter_methods!(PySlidingWindowCount {
+    #[new]
+    #[pyo3(signature = (size, interval = 1.0, bucket_count = 10))]
+    fn new(size: u64, interval: f64, bucket_count: u64) -> PyResult<Self> {
+        if bucket_count == 0 {
+            return Err(PyValueError::new_err("bucket_count must be positive"));
+        }
+        Ok(Self {
+            inner: SlidingWindowCount::new(size, seconds(interval)?, bucket_count),
+        })
+    }
+});
+
+/// Rate limiters from devkit-rs.
+#[pymodule]
+#[pyo3(name = "devkit_rl")]
+fn devkit_rl_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyTokenBucket>()?;
+    m.add_class::<PyFixedWindow>()?;
+    m.add_class::<PySlidingWindowLog>()?;
+    m.add_class::<PySlidingWindowCount>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acquire_step_should_wait_for_next_available() {
+        const INTERVAL: Duration = Duration::from_millis(50);
+
+        let bucket = TokenBucket::new(1, 1, Some(INTERVAL));
+        assert_eq!(acquire_step(&bucket, 1, None), Some(true));
+
+        // the token is back after one interval, each step sleeps towards it
+        let start = Instant::now();
+        while acquire_step(&bucket, 1, None).is_none() {}
+        assert!(start.elapsed() >= INTERVAL / 2);
+
+        // a deadline before the next token gives up right away
+        let deadline = Some(Instant::now() + INTERVAL / 10);
+        assert_eq!(acquire_step(&bucket, 1, deadline), Some(false));
+
+        // more than the capacity never succeeds
+        assert_eq!(acquire_step(&bucket, 2, None), Some(false));
+    }
+}
@@ -0,0 +1,23 @@
+use tonic_build::manual::{Builder, Method, Service};
+
+/// Generates the gRPC server of Envoy's rate limit service.
+///
+/// The messages are written by hand in `src/proto.rs`, which keeps `protoc` and the
+/// Envoy proto tree out of the build.
+fn main() {
+    let service = Service::builder()
+        .name("RateLimitService")
+        .package("envoy.service.ratelimit.v3")
+        .method(
+            Method::builder()
+                .name("should_rate_limit")
+                .route_name("ShouldRateLimit")
+                .input_type("crate::proto::RateLimitRequest")
+                .output_type("crate::proto::RateLimitResponse")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .build();
+
+    Builder::new().build_client(false).compile(&[service]);
+}
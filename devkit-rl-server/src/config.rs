@@ -0,0 +1,196 @@
+use std::{fs, io, path::Path, time::Duration};
+
+use serde::Deserialize;
+
+use crate::proto::{Entry, Unit};
+
+/// The limits served, in the descriptor format of Envoy's reference rate limit
+/// service, with several domains in one document.
+///
+/// ```yaml
+/// domains:
+///   - domain: edge
+///     descriptors:
+///       # every client address gets its own 10 requests per second
+///       - key: remote_address
+///         rate_limit:
+///           unit: second
+///           requests_per_unit: 10
+///       # nested descriptors match the following entries of a descriptor
+///       - key: path
+///         value: /login
+///         descriptors:
+///           - key: remote_address
+///             rate_limit:
+///               unit: minute
+///               requests_per_unit: 5
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct ServerConfig {
+    #[serde(default)]
+    pub domains: Vec<DomainConfig>,
+}
+
+/// The limits of one domain.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DomainConfig {
+    pub domain: String,
+    #[serde(default)]
+    pub descriptors: Vec<DescriptorConfig>,
+}
+
+/// A node of the descriptor tree of a domain.
+///
+/// A node matches a descriptor entry with the same key, and, if `value` is set,
+/// the same value. Nodes with a value take precedence over nodes without.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DescriptorConfig {
+    pub key: String,
+    #[serde(default)]
+    pub value: Option<String>,
+    /// The limit of descriptors ending at this node, unlimited if unset.
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+    #[serde(default)]
+    pub descriptors: Vec<DescriptorConfig>,
+}
+
+/// A limit of `requests_per_unit` requests per `unit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct RateLimitConfig {
+    pub unit: Unit,
+    pub requests_per_unit: u32,
+}
+
+impl ServerConfig {
+    /// Loads the configuration from a YAML or JSON file, depending on its extension.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let s = fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&s).map_err(io::Error::other),
+            Some("yaml" | "yml") => serde_yaml::from_str(&s).map_err(io::Error::other),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: expected a .yaml or .json file", path.display()),
+            )),
+        }
+    }
+
+    /// Finds the limit of the descriptor made of `entries` in `domain`.
+    ///
+    /// # Returns
+    ///
+    /// The limit of the node matching the last entry, or `None` if the descriptor
+    /// does not match the tree or its node has no limit.
+    pub fn find(&self, domain: &str, entries: &[Entry]) -> Option<RateLimitConfig> {
+        let domain = self.domains.iter().find(|d| d.domain == domain)?;
+
+        let mut nodes = &domain.descriptors;
+        let mut limit = None;
+        for entry in entries {
+            let node = nodes
+                .iter()
+                .find(|n| n.key == entry.key && n.value.as_ref() == Some(&entry.value))
+                .or_else(|| {
+                    nodes
+                        .iter()
+                        .find(|n| n.key == entry.key && n.value.is_none())
+                })?;
+            nodes = &node.descriptors;
+            limit = node.rate_limit;
+        }
+        limit
+    }
+}
+
+impl RateLimitConfig {
+    /// Returns the interval the limit applies to, or `None` for an unknown unit.
+    pub fn interval(&self) -> Option<Duration> {
+        let secs = match self.unit {
+            Unit::Unknown => return None,
+            Unit::Second => 1,
+            Unit::Minute => 60,
+            Unit::Hour => 60 * 60,
+            Unit::Day => 60 * 60 * 24,
+        };
+        Some(Duration::from_secs(secs))
+    }
+}
+
+impl<'de> Deserialize<'de> for Unit {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        // Envoy's configurations use both cases
+        Unit::from_str_name(&name.to_ascii_uppercase())
+            .filter(|unit| *unit != Unit::Unknown)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown unit {name:?}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(pairs: &[(&str, &str)]) -> Vec<Entry> {
+        pairs
+            .iter()
+            .map(|(key, value)| Entry {
+                key: key.to_string(),
+                value: value.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn server_config_should_match_descriptor_tree() {
+        let config: ServerConfig = serde_yaml::from_str(
+            r#"
+            domains:
+              - domain: edge
+                descriptors:
+                  - key: remote_address
+                    rate_limit: { unit: second, requests_per_unit: 10 }
+                  - key: path
+                    descriptors:
+                      - key: remote_address
+                        rate_limit: { unit: MINUTE, requests_per_unit: 1 }
+                  - key: path
+                    value: /login
+                    descriptors:
+                      - key: remote_address
+                        rate_limit: { unit: minute, requests_per_unit: 5 }
+            "#,
+        )
+        .unwrap();
+
+        let find = |domain, pairs| config.find(domain, &entries(pairs));
+        let limit = |unit, requests_per_unit| {
+            Some(RateLimitConfig {
+                unit,
+                requests_per_unit,
+            })
+        };
+
+        assert_eq!(
+            find("edge", &[("remote_address", "a")]),
+            limit(Unit::Second, 10)
+        );
+        assert_eq!(
+            find("edge", &[("path", "/login"), ("remote_address", "a")]),
+            limit(Unit::Minute, 5)
+        );
+        assert_eq!(
+            find("edge", &[("path", "/"), ("remote_address", "a")]),
+            limit(Unit::Minute, 1)
+        );
+        // the node of the last entry has no limit, or there is no such node
+        assert_eq!(find("edge", &[("path", "/")]), None);
+        assert_eq!(find("edge", &[("user", "a")]), None);
+        assert_eq!(find("other", &[("remote_address", "a")]), None);
+
+        assert!(
+            serde_yaml::from_str::<RateLimitConfig>("{ unit: week, requests_per_unit: 1 }")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! The HTTP API of the server, compatible with the `/json` endpoint of Envoy's
+//! reference rate limit service.
+//!
+//! ```sh
+//! curl -d '{"domain": "edge", "descriptors": [{"entries": [{"key": "remote_address", "value": "10.0.0.1"}]}]}' \
+//!     localhost:8080/json
+//! ```
+//!
+//! The response is the JSON mapping of `RateLimitResponse`, with the status
+//! `429 Too Many Requests` when the request is over the limit.
+
+use axum::{body::Bytes, extract::State, http::StatusCode, routing, Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    proto::{Code, Entry, RateLimitDescriptor, RateLimitRequest, RateLimitResponse, Unit},
+    service::RateLimitServer,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonRequest {
+    domain: String,
+    #[serde(default)]
+    descriptors: Vec<JsonDescriptor>,
+    #[serde(default)]
+    hits_addend: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonDescriptor {
+    #[serde(default)]
+    entries: Vec<JsonEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonEntry {
+    key: String,
+    value: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonResponse {
+    overall_code: &'static str,
+    statuses: Vec<JsonStatus>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonStatus {
+    code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current_limit: Option<JsonLimit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_until_reset: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonLimit {
+    requests_per_unit: u32,
+    unit: &'static str,
+}
+
+/// Creates the routes of the HTTP API.
+pub fn router(server: RateLimitServer) -> Router {
+    Router::new()
+        .route("/json", routing::post(json))
+        .route("/healthcheck", routing::get(|| async { "OK" }))
+        .with_state(server)
+}
+
+async fn json(
+    State(server): State<RateLimitServer>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<JsonResponse>), (StatusCode, String)> {
+    // like the reference implementation, accept the body whatever its content type
+    let request: JsonRequest =
+        serde_json::from_slice(&body).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let request = RateLimitRequest::from(request);
+    let response = tokio::task::spawn_blocking(move || server.should_rate_limit(&request))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let status = if response.overall_code == Code::OverLimit as i32 {
+        StatusCode::TOO_MANY_REQUESTS
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(JsonResponse::from(response))))
+}
+
+impl From<JsonRequest> for RateLimitRequest {
+    fn from(request: JsonRequest) -> Self {
+        RateLimitRequest {
+            domain: request.domain,
+            descriptors: request
+                .descriptors
+                .into_iter()
+                .map(|d| RateLimitDescriptor {
+                    entries: d
+                        .entries
+                        .into_iter()
+                        .map(|e| Entry {
+                            key: e.key,
+                            value: e.value,
+                        })
+                        .collect(),
+                    limit: None,
+                })
+                .collect(),
+            hits_addend: request.hits_addend,
+        }
+    }
+}
+
+impl From<RateLimitResponse> for JsonResponse {
+    fn from(response: RateLimitResponse) -> Self {
+        let code_name = |code| Code::try_from(code).unwrap_or(Code::Unknown).as_str_name();
+        JsonResponse {
+            overall_code: code_name(response.overall_code),
+            statuses: response
+                .statuses
+                .into_iter()
+                .map(|s| JsonStatus {
+                    code: code_name(s.code),
+                    current_limit: s.current_limit.map(|l| JsonLimit {
+                        requests_per_unit: l.requests_per_unit,
+                        unit: Unit::try_from(l.unit)
+                            .unwrap_or(Unit::Unknown)
+                            .as_str_name(),
+                    }),
+                    // the JSON mapping of a protobuf duration
+                    duration_until_reset: s
+                        .duration_until_reset
+                        .map(|d| format!("{}.{:09}s", d.seconds, d.nanos)),
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! `devkit-rl-server`, a standalone rate limit service speaking Envoy's Rate
+//! Limit Service protocol, backed by the devkit-rl distributed windows.
+//!
+//! - gRPC: `envoy.service.ratelimit.v3.RateLimitService/ShouldRateLimit`, which
+//!   Envoy's `ratelimit` filters call.
+//! - HTTP: `POST /json` with the same request as JSON, see [`http`], and
+//!   `GET /healthcheck`.
+//!
+//! Counters live in memory by default. With `--redis`, they live in Redis, so
+//! that several instances of the server enforce one quota.
+//!
+//! ```sh
+//! devkit-rl-server --config limits.yaml --redis 127.0.0.1:6379
+//! ```
+
+mod config;
+mod http;
+mod proto;
+mod service;
+
+use std::{io, path::PathBuf, sync::Arc};
+
+use clap::Parser;
+use devkit_rl::distributed::{DistributedStore, InMemoryStore, RedisStore};
+use tokio::net::TcpListener;
+
+use crate::{
+    config::ServerConfig, proto::rate_limit_service_server::RateLimitServiceServer,
+    service::RateLimitServer,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "devkit-rl-server",
+    version,
+    about = "Envoy compatible rate limit service"
+)]
+struct Args {
+    /// The limits, in YAML or JSON depending on the extension.
+    #[arg(short, long)]
+    config: PathBuf,
+    /// The address of the gRPC API.
+    #[arg(long, default_value = "0.0.0.0:8081")]
+    grpc_listen: String,
+    /// The address of the HTTP API.
+    #[arg(long, default_value = "0.0.0.0:8080")]
+    http_listen: String,
+    /// The address of a Redis server to keep the counters in, instead of memory.
+    #[arg(long)]
+    redis: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let args = Args::parse();
+
+    let config = ServerConfig::load(&args.config)?;
+    let store: Arc<dyn DistributedStore> = match &args.redis {
+        Some(addr) => Arc::new(RedisStore::connect(addr.as_str()).map_err(io::Error::other)?),
+        None => Arc::new(InMemoryStore::new()),
+    };
+    let server = RateLimitServer::new(config, store);
+
+    let grpc_addr = args
+        .grpc_listen
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let grpc = tonic::transport::Server::builder()
+        .add_service(RateLimitServiceServer::new(server.clone()))
+        .serve(grpc_addr);
+
+    let http_listener = TcpListener::bind(&args.http_listen).await?;
+    let http = axum::serve(http_listener, http::router(server));
+
+    eprintln!(
+        "devkit-rl-server: gRPC on {}, HTTP on {}",
+        args.grpc_listen, args.http_listen
+    );
+    tokio::select! {
+        res = grpc => res.map_err(io::Error::other),
+        res = http => res,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::IntoFuture,
+        io::{Read, Write},
+    };
+
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::{client::Grpc, codec::ProstCodec, codegen::http::uri::PathAndQuery};
+
+    use super::*;
+    use crate::proto::{Code, Entry, RateLimitDescriptor, RateLimitRequest, RateLimitResponse};
+
+    #[tokio::test]
+    async fn server_should_speak_grpc_and_http() {
+        let config: ServerConfig = serde_yaml::from_str(
+            r#"
+            domains:
+              - domain: edge
+                descriptors:
+                  - key: remote_address
+                    rate_limit: { unit: hour, requests_per_unit: 1 }
+            "#,
+        )
+        .unwrap();
+        let server = RateLimitServer::new(config, Arc::new(InMemoryStore::new()));
+
+        let grpc_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let grpc_addr = grpc_listener.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(RateLimitServiceServer::new(server.clone()))
+                .serve_with_incoming(TcpListenerStream::new(grpc_listener)),
+        );
+        let http_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let http_addr = http_listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(http_listener, http::router(server)).into_future());
+
+        // call the route Envoy calls, with nothing but the message types
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{grpc_addr}"))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut client = Grpc::new(channel);
+        client.ready().await.unwrap();
+        let request = RateLimitRequest {
+            domain: "edge".to_string(),
+            descriptors: vec![RateLimitDescriptor {
+                entries: vec![Entry {
+                    key: "remote_address".to_string(),
+                    value: "10.0.0.1".to_string(),
+                }],
+                limit: None,
+            }],
+            hits_addend: 0,
+        };
+        let response: tonic::Response<RateLimitResponse> = client
+            .unary(
+                tonic::Request::new(request),
+                PathAndQuery::from_static(
+                    "/envoy.service.ratelimit.v3.RateLimitService/ShouldRateLimit",
+                ),
+                ProstCodec::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.into_inner().overall_code, Code::Ok as i32);
+
+        // the same descriptor is now over the limit over HTTP as well
+        let response = tokio::task::spawn_blocking(move || {
+            let body = r#"{"domain":"edge","descriptors":[{"entries":[{"key":"remote_address","value":"10.0.0.1"}]}]}"#;
+            let mut stream = std::net::TcpStream::connect(http_addr).unwrap();
+            write!(
+                stream,
+                "POST /json HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+        .await
+        .unwrap();
+        assert!(response.starts_with("HTTP/1.1 429 Too Many Requests\r\n"));
+        assert!(response.contains(r#""overallCode":"OVER_LIMIT""#));
+        assert!(response.contains(r#""currentLimit":{"requestsPerUnit":1,"unit":"HOUR"}"#));
+    }
+}
@@ -0,0 +1,145 @@
+//! The messages of Envoy's rate limit service, `envoy.service.ratelimit.v3`.
+//!
+//! Only the fields this server reads or writes are declared. Protobuf skips
+//! unknown fields, so the messages stay wire compatible with Envoy, which may send
+//! or expect more of them.
+
+/// A request to check the rate limits of a set of descriptors.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RateLimitRequest {
+    /// The namespace of the descriptors, selecting the domain of the configuration.
+    #[prost(string, tag = "1")]
+    pub domain: String,
+    /// The descriptors to check, each one limited independently.
+    #[prost(message, repeated, tag = "2")]
+    pub descriptors: Vec<RateLimitDescriptor>,
+    /// The number of hits to add to each descriptor, 0 meaning 1.
+    #[prost(uint32, tag = "3")]
+    pub hits_addend: u32,
+}
+
+/// A list of key/value entries identifying what is rate limited, e.g. a client
+/// address and the path it requests.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RateLimitDescriptor {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<Entry>,
+    /// A limit overriding the configured one.
+    #[prost(message, optional, tag = "2")]
+    pub limit: Option<RateLimitOverride>,
+}
+
+/// One key/value entry of a [`RateLimitDescriptor`].
+#[derive(Clone, PartialEq, Eq, Hash, prost::Message)]
+pub struct Entry {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+/// A limit sent along with a descriptor.
+#[derive(Clone, Copy, PartialEq, Eq, prost::Message)]
+pub struct RateLimitOverride {
+    #[prost(uint32, tag = "1")]
+    pub requests_per_unit: u32,
+    #[prost(enumeration = "Unit", tag = "2")]
+    pub unit: i32,
+}
+
+/// The decision for a [`RateLimitRequest`].
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RateLimitResponse {
+    /// `OverLimit` if any descriptor is over its limit.
+    #[prost(enumeration = "Code", tag = "1")]
+    pub overall_code: i32,
+    /// The decision for every descriptor of the request, in order.
+    #[prost(message, repeated, tag = "2")]
+    pub statuses: Vec<DescriptorStatus>,
+}
+
+/// The decision for one [`RateLimitDescriptor`].
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DescriptorStatus {
+    #[prost(enumeration = "Code", tag = "1")]
+    pub code: i32,
+    /// The limit applied to the descriptor, unset if it is not limited.
+    #[prost(message, optional, tag = "2")]
+    pub current_limit: Option<RateLimit>,
+    /// How long until the descriptor is allowed again, set when it is over the limit.
+    #[prost(message, optional, tag = "4")]
+    pub duration_until_reset: Option<prost_types::Duration>,
+}
+
+/// A limit of `requests_per_unit` requests per `unit`.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct RateLimit {
+    #[prost(string, tag = "3")]
+    pub name: String,
+    #[prost(uint32, tag = "1")]
+    pub requests_per_unit: u32,
+    #[prost(enumeration = "Unit", tag = "2")]
+    pub unit: i32,
+}
+
+/// The decision of a rate limit check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Code {
+    Unknown = 0,
+    Ok = 1,
+    OverLimit = 2,
+}
+
+/// The time unit of a [`RateLimit`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Unit {
+    Unknown = 0,
+    Second = 1,
+    Minute = 2,
+    Hour = 3,
+    Day = 4,
+}
+
+impl Code {
+    /// Returns the name of the value in the proto file.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Code::Unknown => "UNKNOWN",
+            Code::Ok => "OK",
+            Code::OverLimit => "OVER_LIMIT",
+        }
+    }
+}
+
+impl Unit {
+    /// Returns the name of the value in the proto file.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Unit::Unknown => "UNKNOWN",
+            Unit::Second => "SECOND",
+            Unit::Minute => "MINUTE",
+            Unit::Hour => "HOUR",
+            Unit::Day => "DAY",
+        }
+    }
+
+    /// Returns the value named `name` in the proto file.
+    pub fn from_str_name(name: &str) -> Option<Self> {
+        [
+            Unit::Unknown,
+            Unit::Second,
+            Unit::Minute,
+            Unit::Hour,
+            Unit::Day,
+        ]
+        .into_iter()
+        .find(|unit| unit.as_str_name() == name)
+    }
+}
+
+include!(concat!(
+    env!("OUT_DIR"),
+    "/envoy.service.ratelimit.v3.RateLimitService.rs"
+));
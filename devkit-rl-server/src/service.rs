@@ -0,0 +1,200 @@
+use std::{sync::Arc, time::Duration};
+
+use devkit_rl::distributed::{DistributedFixedWindow, DistributedStore};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    config::{RateLimitConfig, ServerConfig},
+    proto::{
+        rate_limit_service_server, Code, DescriptorStatus, Entry, RateLimit, RateLimitDescriptor,
+        RateLimitRequest, RateLimitResponse, Unit,
+    },
+};
+
+/// Decides rate limits for Envoy's rate limit service protocol.
+///
+/// Every descriptor gets a fixed window in `store`, so that all instances of the
+/// server sharing a store, e.g. Redis, enforce one quota. Windows are aligned to
+/// the unix epoch, like the ones of Envoy's reference implementation.
+#[derive(Clone)]
+pub struct RateLimitServer {
+    config: Arc<ServerConfig>,
+    store: Arc<dyn DistributedStore>,
+}
+
+impl RateLimitServer {
+    /// Creates a new `RateLimitServer` serving the limits of `config` from `store`.
+    pub fn new(config: ServerConfig, store: Arc<dyn DistributedStore>) -> Self {
+        Self {
+            config: Arc::new(config),
+            store,
+        }
+    }
+
+    /// Checks the descriptors of `request` against their limits, counting the hits.
+    ///
+    /// Descriptors without a limit are allowed. This blocks on the store, so call
+    /// it outside of the async runtime.
+    pub fn should_rate_limit(&self, request: &RateLimitRequest) -> RateLimitResponse {
+        let hits = u64::from(request.hits_addend.max(1));
+        let statuses: Vec<_> = request
+            .descriptors
+            .iter()
+            .map(|descriptor| self.check(&request.domain, descriptor, hits))
+            .collect();
+
+        let over_limit = statuses.iter().any(|s| s.code == Code::OverLimit as i32);
+        RateLimitResponse {
+            overall_code: if over_limit {
+                Code::OverLimit
+            } else {
+                Code::Ok
+            } as i32,
+            statuses,
+        }
+    }
+
+    /// Counts `hits` against the limit of `descriptor`.
+    fn check(&self, domain: &str, descriptor: &RateLimitDescriptor, hits: u64) -> DescriptorStatus {
+        let limit = match descriptor.limit {
+            Some(limit) => Unit::try_from(limit.unit).ok().map(|unit| RateLimitConfig {
+                unit,
+                requests_per_unit: limit.requests_per_unit,
+            }),
+            None => self.config.find(domain, &descriptor.entries),
+        };
+        let Some((limit, interval)) = limit.and_then(|l| Some((l, l.interval()?))) else {
+            return DescriptorStatus {
+                code: Code::Ok as i32,
+                current_limit: None,
+                duration_until_reset: None,
+            };
+        };
+
+        let window = DistributedFixedWindow::new(
+            Arc::clone(&self.store),
+            cache_key(domain, &descriptor.entries),
+            u64::from(limit.requests_per_unit),
+            Some(interval),
+        );
+        let (code, duration_until_reset) = if window.allow_n(hits) {
+            (Code::Ok, None)
+        } else {
+            let wait = window.next_available(hits);
+            (
+                Code::OverLimit,
+                (wait != Duration::MAX).then(|| to_proto(wait)),
+            )
+        };
+
+        DescriptorStatus {
+            code: code as i32,
+            current_limit: Some(RateLimit {
+                name: String::new(),
+                requests_per_unit: limit.requests_per_unit,
+                unit: limit.unit as i32,
+            }),
+            duration_until_reset,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl rate_limit_service_server::RateLimitService for RateLimitServer {
+    async fn should_rate_limit(
+        &self,
+        request: Request<RateLimitRequest>,
+    ) -> Result<Response<RateLimitResponse>, Status> {
+        let server = self.clone();
+        let request = request.into_inner();
+        tokio::task::spawn_blocking(move || server.should_rate_limit(&request))
+            .await
+            .map(Response::new)
+            .map_err(|e| Status::internal(e.to_string()))
+    }
+}
+
+/// Returns the store key of the window of a descriptor, in the format of Envoy's
+/// reference implementation.
+fn cache_key(domain: &str, entries: &[Entry]) -> String {
+    let mut key = format!("{domain}_");
+    for entry in entries {
+        key.push_str(&format!("{}_{}_", entry.key, entry.value));
+    }
+    key
+}
+
+fn to_proto(d: Duration) -> prost_types::Duration {
+    prost_types::Duration {
+        seconds: i64::try_from(d.as_secs()).unwrap_or(i64::MAX),
+        nanos: d.subsec_nanos() as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use devkit_rl::distributed::InMemoryStore;
+
+    use super::*;
+    use crate::{config::DescriptorConfig, config::DomainConfig, proto::RateLimitOverride};
+
+    fn descriptor(key: &str, value: &str) -> RateLimitDescriptor {
+        RateLimitDescriptor {
+            entries: vec![Entry {
+                key: key.to_string(),
+                value: value.to_string(),
+            }],
+            limit: None,
+        }
+    }
+
+    #[test]
+    fn should_rate_limit_should_count_each_descriptor() {
+        let config = ServerConfig {
+            domains: vec![DomainConfig {
+                domain: "edge".to_string(),
+                descriptors: vec![DescriptorConfig {
+                    key: "remote_address".to_string(),
+                    value: None,
+                    rate_limit: Some(RateLimitConfig {
+                        unit: Unit::Hour,
+                        requests_per_unit: 2,
+                    }),
+                    descriptors: vec![],
+                }],
+            }],
+        };
+        let server = RateLimitServer::new(config, Arc::new(InMemoryStore::new()));
+        let request = |descriptors, hits_addend| RateLimitRequest {
+            domain: "edge".to_string(),
+            descriptors,
+            hits_addend,
+        };
+
+        let a = descriptor("remote_address", "a");
+        let b = descriptor("remote_address", "b");
+        let ok = server.should_rate_limit(&request(vec![a.clone(), b.clone()], 2));
+        assert_eq!(ok.overall_code, Code::Ok as i32);
+        assert_eq!(
+            ok.statuses[0].current_limit.as_ref().unwrap().unit,
+            Unit::Hour as i32
+        );
+
+        // a is over its limit, unlimited descriptors are allowed regardless
+        let over = server.should_rate_limit(&request(vec![a, descriptor("user", "x")], 0));
+        assert_eq!(over.overall_code, Code::OverLimit as i32);
+        assert_eq!(over.statuses[0].code, Code::OverLimit as i32);
+        assert!(over.statuses[0].duration_until_reset.is_some());
+        assert_eq!(over.statuses[1].code, Code::Ok as i32);
+        assert_eq!(over.statuses[1].current_limit, None);
+
+        // the limit sent with a descriptor overrides the configured one
+        let mut b = b;
+        b.limit = Some(RateLimitOverride {
+            requests_per_unit: 3,
+            unit: Unit::Hour as i32,
+        });
+        let ok = server.should_rate_limit(&request(vec![b], 1));
+        assert_eq!(ok.overall_code, Code::Ok as i32);
+    }
+}
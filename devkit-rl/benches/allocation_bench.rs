@@ -0,0 +1,78 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use devkit_rl::{
+    FixedWindow, LeakyBucket, RateLimiter, SlidingWindowCount, SlidingWindowLog, TokenBucket,
+};
+
+/// Counts the allocations of the whole process.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const CALLS: usize = 100_000;
+
+/// Measures `allow_n(4)` of every algorithm, and prints the allocations it makes.
+fn allocation_benchmark(c: &mut Criterion) {
+    let interval = Some(Duration::from_millis(1));
+    let limiters: [(&str, Box<dyn RateLimiter>); 5] = [
+        (
+            "token_bucket",
+            Box::new(TokenBucket::new(100, 10, interval)),
+        ),
+        (
+            "leaky_bucket_meter",
+            Box::new(LeakyBucket::meter(10, 100, interval)),
+        ),
+        ("fixed_window", Box::new(FixedWindow::new(100, interval))),
+        (
+            "sliding_window_log",
+            Box::new(SlidingWindowLog::new(100, interval)),
+        ),
+        (
+            "sliding_window_count",
+            Box::new(SlidingWindowCount::new(100, Duration::from_millis(1), 10)),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("allow_n");
+    for (name, limiter) in &limiters {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        for _ in 0..CALLS {
+            limiter.allow_n(4);
+        }
+        let allocs = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!("{name}: {allocs} allocations in {CALLS} calls to allow_n");
+
+        group.bench_with_input(BenchmarkId::from_parameter(name), limiter, |b, limiter| {
+            b.iter(|| limiter.allow_n(4))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, allocation_benchmark);
+criterion_main!(benches);
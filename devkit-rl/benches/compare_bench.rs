@@ -0,0 +1,152 @@
+//! Compares the algorithms configured for the same limit under the same load.
+//!
+//! Every algorithm allows 100 requests per second, with bursts of up to 100 where
+//! the algorithm has a notion of burst, and is run against:
+//!
+//! - `constant`: twice the limit, evenly spaced;
+//! - `bursty`: 3 times the limit arriving at once, every second;
+//! - `boundary`: the limit right before and again right after every second, the
+//!   adversarial traffic of fixed windows.
+//!
+//! The accuracy columns come from a deterministic simulation (see
+//! `devkit_rl::simulate`): `admitted` is the rate admitted over the whole trace
+//! and `peak` the most admitted in any sliding second, both relative to the limit,
+//! so 1.00 is exact. The speed columns time `allow` on the real clock, on one
+//! thread: the throughput of back to back calls and the latency percentiles of
+//! single calls, which include the cost of reading the time around them.
+//!
+//! Run with `cargo bench -p devkit-rl --bench compare_bench`.
+
+use std::{
+    hint::black_box,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use devkit_rl::{
+    simulate::{self, Trace},
+    Clock, FixedWindow, LeakyBucket, Limiter, RateLimiter, SlidingWindowCount, SlidingWindowLog,
+    StdClock, TokenBucket,
+};
+
+const LIMIT: u64 = 100;
+const INTERVAL: Duration = Duration::from_secs(1);
+const DURATION: Duration = Duration::from_secs(10);
+const RESOLUTION: Duration = Duration::from_millis(10);
+const CALLS: usize = 200_000;
+
+/// Builds a limiter on the given clock.
+type Build = fn(Arc<dyn Clock>) -> Limiter;
+
+/// Builds the limiters compared, allowing [`LIMIT`] requests per [`INTERVAL`].
+const ALGORITHMS: [(&str, Build); 6] = [
+    ("token_bucket", |clock| {
+        Limiter::TokenBucket(TokenBucket::with_clock(
+            LIMIT,
+            1,
+            Some(INTERVAL / LIMIT as u32),
+            clock,
+        ))
+    }),
+    ("leaky_bucket_meter", |clock| {
+        Limiter::LeakyBucket(LeakyBucket::meter_with_clock(
+            1,
+            LIMIT,
+            Some(INTERVAL / LIMIT as u32),
+            clock,
+        ))
+    }),
+    ("fixed_window", |clock| {
+        Limiter::FixedWindow(FixedWindow::with_clock(LIMIT, Some(INTERVAL), false, clock))
+    }),
+    ("fixed_window_smoothed", |clock| {
+        Limiter::FixedWindow(FixedWindow::with_clock(LIMIT, Some(INTERVAL), true, clock))
+    }),
+    ("sliding_window_log", |clock| {
+        Limiter::SlidingWindowLog(SlidingWindowLog::with_clock(LIMIT, Some(INTERVAL), clock))
+    }),
+    ("sliding_window_count", |clock| {
+        Limiter::SlidingWindowCount(SlidingWindowCount::with_clock(LIMIT, INTERVAL, 10, clock))
+    }),
+];
+
+fn profiles() -> [(&'static str, Trace); 3] {
+    let seconds = 1..=DURATION.as_secs();
+    let boundary = seconds.flat_map(|s| {
+        let edge = Duration::from_secs(s);
+        let before = (0..LIMIT).map(move |_| edge - Duration::from_millis(1));
+        let after = (0..LIMIT).map(move |_| edge);
+        before.chain(after)
+    });
+
+    [
+        ("constant", Trace::constant(2.0 * LIMIT as f64, DURATION)),
+        ("bursty", Trace::bursts(3 * LIMIT, INTERVAL, DURATION)),
+        ("boundary", Trace::from_timestamps(boundary)),
+    ]
+}
+
+/// Returns the most requests admitted in any [`INTERVAL`] of `report`.
+fn sliding_peak(report: &simulate::Report) -> u64 {
+    let width = (INTERVAL.as_nanos() / RESOLUTION.as_nanos()) as usize;
+    let admitted: Vec<u64> = report.series.iter().map(|i| i.admitted).collect();
+    if admitted.len() <= width {
+        return admitted.iter().sum();
+    }
+    admitted
+        .windows(width)
+        .map(|w| w.iter().sum())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Returns the throughput of back to back calls, in millions per second, and the
+/// latency percentiles of single calls, in nanoseconds.
+fn speed(limiter: &Limiter) -> (f64, [u128; 3]) {
+    let start = Instant::now();
+    for _ in 0..CALLS {
+        black_box(limiter.allow());
+    }
+    let throughput = CALLS as f64 / start.elapsed().as_secs_f64() / 1e6;
+
+    let mut latencies: Vec<u128> = (0..CALLS)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(limiter.allow());
+            start.elapsed().as_nanos()
+        })
+        .collect();
+    latencies.sort_unstable();
+    let percentile = |p: f64| latencies[((CALLS as f64 * p) as usize).min(CALLS - 1)];
+    (
+        throughput,
+        [percentile(0.5), percentile(0.99), percentile(0.999)],
+    )
+}
+
+fn main() {
+    let profiles = profiles();
+
+    print!("{:<22}", "algorithm");
+    for (name, _) in &profiles {
+        print!(" {:>18}", format!("{name} adm/peak"));
+    }
+    println!(
+        " {:>8} {:>7} {:>7} {:>8}",
+        "Mops/s", "p50 ns", "p99 ns", "p999 ns"
+    );
+
+    for (name, build) in ALGORITHMS {
+        print!("{name:<22}");
+        for (_, trace) in &profiles {
+            let report = simulate::run(trace, RESOLUTION, build);
+            let expected = LIMIT as f64 * DURATION.as_secs_f64();
+            let admitted = report.admitted as f64 / expected;
+            let peak = sliding_peak(&report) as f64 / LIMIT as f64;
+            print!(" {:>18}", format!("{admitted:.2} / {peak:.2}"));
+        }
+
+        let (throughput, [p50, p99, p999]) = speed(&build(Arc::new(StdClock::new())));
+        println!(" {throughput:>8.1} {p50:>7} {p99:>7} {p999:>8}");
+    }
+}
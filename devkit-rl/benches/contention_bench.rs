@@ -0,0 +1,66 @@
+use std::{
+    sync::Barrier,
+    thread,
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use devkit_rl::{FixedWindow, RateLimiter, SlidingWindowCount, SlidingWindowLog, TokenBucket};
+
+const THREADS: [usize; 3] = [8, 32, 128];
+
+/// Measures `iters` calls to `allow` spread over `threads` threads hammering one limiter.
+fn hammer(limiter: &dyn RateLimiter, threads: usize, iters: u64) -> Duration {
+    let per_thread = iters.div_ceil(threads as u64);
+    let barrier = Barrier::new(threads + 1);
+
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                barrier.wait();
+                for _ in 0..per_thread {
+                    limiter.allow();
+                }
+            });
+        }
+
+        // start the clock once every thread is ready, the scope joins them all
+        barrier.wait();
+        Instant::now()
+    })
+    .elapsed()
+}
+
+fn contention_benchmark(c: &mut Criterion) {
+    let limiters: [(&str, Box<dyn RateLimiter>); 4] = [
+        (
+            "token_bucket",
+            Box::new(TokenBucket::new(10, 100, Some(Duration::from_millis(1)))),
+        ),
+        (
+            "fixed_window",
+            Box::new(FixedWindow::new(100, Some(Duration::from_millis(1)))),
+        ),
+        (
+            "sliding_window_log",
+            Box::new(SlidingWindowLog::new(100, Some(Duration::from_millis(1)))),
+        ),
+        (
+            "sliding_window_count",
+            Box::new(SlidingWindowCount::new(100, Duration::from_millis(1), 10)),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("contention");
+    for (name, limiter) in &limiters {
+        for threads in THREADS {
+            group.bench_with_input(BenchmarkId::new(*name, threads), &threads, |b, &threads| {
+                b.iter_custom(|iters| hammer(limiter.as_ref(), threads, iters))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, contention_benchmark);
+criterion_main!(benches);
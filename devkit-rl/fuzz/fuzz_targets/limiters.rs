@@ -0,0 +1,74 @@
+//! Feeds random sequences of operations to every algorithm, and checks that the
+//! estimates of the limiters agree with their decisions.
+//!
+//! Run with `cargo +nightly fuzz run limiters` from `devkit-rl`.
+
+#![no_main]
+
+use std::{sync::Arc, time::Duration};
+
+use devkit_rl::{
+    FixedWindow, LeakyBucket, Limiter, ManualClock, RateLimiter, Refill, SlidingWindowCount,
+    SlidingWindowLog, TokenBucket,
+};
+use libfuzzer_sys::fuzz_target;
+
+const LIMIT: u64 = 10;
+const PERIOD: Duration = Duration::from_secs(1);
+
+fn limiters(clock: &Arc<ManualClock>) -> Vec<Limiter> {
+    vec![
+        Limiter::TokenBucket(TokenBucket::with_clock(LIMIT, LIMIT, Some(PERIOD), clock.clone())),
+        Limiter::TokenBucket(
+            TokenBucket::with_clock(LIMIT, LIMIT, Some(PERIOD), clock.clone())
+                .with_refill(Refill::Continuous),
+        ),
+        Limiter::LeakyBucket(LeakyBucket::meter_with_clock(
+            LIMIT,
+            LIMIT,
+            Some(PERIOD),
+            clock.clone(),
+        )),
+        Limiter::FixedWindow(FixedWindow::with_clock(LIMIT, Some(PERIOD), false, clock.clone())),
+        Limiter::FixedWindow(FixedWindow::with_clock(LIMIT, Some(PERIOD), true, clock.clone())),
+        Limiter::SlidingWindowLog(SlidingWindowLog::with_clock(LIMIT, Some(PERIOD), clock.clone())),
+        Limiter::SlidingWindowCount(SlidingWindowCount::with_clock(
+            LIMIT,
+            PERIOD,
+            10,
+            clock.clone(),
+        )),
+    ]
+}
+
+fuzz_target!(|data: &[u8]| {
+    let clock = Arc::new(ManualClock::new());
+    let limiters = limiters(&clock);
+
+    // every two bytes are an operation: what to do, and its argument
+    for op in data.chunks_exact(2) {
+        match op[0] % 4 {
+            0 => clock.advance(Duration::from_millis(u64::from(op[1]) * 10)),
+            1 => clock.advance(Duration::from_micros(u64::from(op[1]))),
+            2 => {
+                let n = u64::from(op[1]) % (LIMIT + 3);
+                for limiter in &limiters {
+                    let estimate = limiter.next_available(n);
+                    let remaining = limiter.remaining();
+                    let allowed = limiter.allow_n(n);
+                    assert_eq!(allowed, estimate == Duration::ZERO);
+                    assert_eq!(n > LIMIT, estimate == Duration::MAX);
+                    assert!(remaining.is_none_or(|r| r <= LIMIT));
+                }
+            }
+            _ => {
+                // waiting out the estimate of a limiter gets the request through
+                let limiter = &limiters[usize::from(op[1]) % limiters.len()];
+                let estimate = limiter.next_available(1);
+                assert!(estimate <= PERIOD * 2);
+                clock.advance(estimate);
+                assert!(limiter.allow());
+            }
+        }
+    }
+});
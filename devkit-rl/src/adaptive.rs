@@ -0,0 +1,356 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    clock::{whole_periods, SharedClock},
+    sync::{Mutex, MutexExt},
+    Clock, Error, LeakyBucket, RateLimiter, StdClock,
+};
+
+/// How an [`AdaptiveClientLimiter`] backs off and recovers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptivePolicy {
+    /// The lowest rate backing off goes down to, in requests per second.
+    pub min_rate: f64,
+    /// The factor the rate is multiplied by when the upstream throttles.
+    pub backoff: f64,
+    /// The requests per second the rate recovers by every `recovery_interval`
+    /// without throttling.
+    pub recovery_step: f64,
+    /// How often the rate recovers. Throttling reported within this long of the
+    /// previous back off counts once, as it is likely caused by the same burst.
+    pub recovery_interval: Duration,
+    /// The number of requests allowed at once.
+    pub burst: u64,
+}
+
+impl Default for AdaptivePolicy {
+    /// Halves the rate on throttling, down to one request every 10 seconds, and
+    /// recovers by one request per second every second.
+    fn default() -> Self {
+        Self {
+            min_rate: 0.1,
+            backoff: 0.5,
+            recovery_step: 1.0,
+            recovery_interval: Duration::from_secs(1),
+            burst: 1,
+        }
+    }
+}
+
+/// What the upstream answered to a request let through by an [`AdaptiveClientLimiter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feedback {
+    /// The request was served.
+    Accepted,
+    /// The request was throttled, optionally asking to wait `retry_after` before
+    /// sending any other request.
+    Throttled { retry_after: Option<Duration> },
+}
+
+impl Feedback {
+    /// Interprets an HTTP response.
+    ///
+    /// `429 Too Many Requests` and `503 Service Unavailable` are throttling. The
+    /// `Retry-After` header is honored when it is a number of seconds; an HTTP date
+    /// is ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::Feedback;
+    ///
+    /// assert_eq!(
+    ///     Feedback::from_http(429, Some("2")),
+    ///     Feedback::Throttled { retry_after: Some(Duration::from_secs(2)) },
+    /// );
+    /// assert_eq!(Feedback::from_http(200, None), Feedback::Accepted);
+    /// ```
+    pub fn from_http(status: u16, retry_after: Option<&str>) -> Self {
+        match status {
+            429 | 503 => Feedback::Throttled {
+                retry_after: retry_after
+                    .and_then(|v| v.trim().parse().ok())
+                    .map(Duration::from_secs),
+            },
+            _ => Feedback::Accepted,
+        }
+    }
+}
+
+/// A client-side rate limiter adapting to the throttling of the upstream.
+///
+/// Third-party APIs rarely publish their exact limits, and often share them with
+/// other clients. This limiter starts from a configured rate, and backs off
+/// multiplicatively whenever the upstream throttles a request, honoring its
+/// `Retry-After`. Without further throttling, the rate then recovers additively up
+/// to the configured rate, which makes it a congestion controller for the calls to
+/// the upstream.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{AdaptiveClientLimiter, AdaptivePolicy, Feedback};
+///
+/// let limiter = AdaptiveClientLimiter::new(10.0, AdaptivePolicy::default());
+///
+/// let response = limiter.call(|| {
+///     // send the request, e.g. with an HTTP client
+///     let (status, retry_after) = (429, Some("1"));
+///     ((), Feedback::from_http(status, retry_after))
+/// });
+///
+/// assert!(response.is_ok());
+/// assert_eq!(limiter.rate(), 5.0);
+/// assert!(!limiter.allow());
+/// ```
+#[derive(Debug, Clone)]
+pub struct AdaptiveClientLimiter {
+    inner: Arc<Mutex<AdaptiveInner>>,
+}
+
+#[derive(Debug)]
+struct AdaptiveInner {
+    policy: AdaptivePolicy,
+    /// The configured rate, which the rate recovers up to.
+    max_rate: f64,
+    /// The current rate, in requests per second.
+    rate: f64,
+    /// Paces the requests at the current rate.
+    meter: LeakyBucket,
+    /// No request is allowed before this time, as asked by `Retry-After`.
+    paused_until: Duration,
+    /// The time of the last back off.
+    backed_off_at: Option<Duration>,
+    /// The time the rate last recovered, or stopped recovering.
+    recovered_at: Duration,
+    /// The source of time.
+    clock: SharedClock,
+}
+
+impl AdaptiveClientLimiter {
+    /// Creates a new `AdaptiveClientLimiter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - The configured rate, in requests per second, which is also the highest.
+    /// * `policy` - How the rate backs off and recovers.
+    pub fn new(rate: f64, policy: AdaptivePolicy) -> Self {
+        Self::with_clock(rate, policy, Arc::new(StdClock::new()))
+    }
+
+    /// Creates a new `AdaptiveClientLimiter` reading the time from `clock`.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - The configured rate, in requests per second, which is also the highest.
+    /// * `policy` - How the rate backs off and recovers.
+    /// * `clock` - The source of time of the limiter.
+    pub fn with_clock(rate: f64, policy: AdaptivePolicy, clock: Arc<dyn Clock>) -> Self {
+        let rate = rate.max(policy.min_rate);
+        let meter = LeakyBucket::meter_with_clock(
+            1,
+            policy.burst,
+            Some(emission_interval(rate)),
+            clock.clone(),
+        );
+        let mut clock = SharedClock::new(clock);
+        Self {
+            inner: Arc::new(Mutex::new(AdaptiveInner {
+                policy,
+                max_rate: rate,
+                rate,
+                meter,
+                paused_until: Duration::ZERO,
+                backed_off_at: None,
+                recovered_at: clock.now(),
+                clock,
+            })),
+        }
+    }
+
+    /// Attempts to allow a single request.
+    ///
+    /// This is a convenience method that is equivalent to calling `allow_n(1)`.
+    pub fn allow(&self) -> bool {
+        self.allow_n(1)
+    }
+
+    /// Attempts to allow `n` requests at the current rate.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the requests are allowed, `false` if they exceed the current rate,
+    /// or the upstream asked to wait.
+    pub fn allow_n(&self, n: u64) -> bool {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.update();
+        now >= inner.paused_until && inner.meter.allow_n(n)
+    }
+
+    /// Estimates how long to wait until `n` requests are allowed at the current rate.
+    ///
+    /// See [`RateLimiter::next_available`].
+    pub fn next_available(&self, n: u64) -> Duration {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.update();
+        inner
+            .paused_until
+            .saturating_sub(now)
+            .max(inner.meter.next_available(n))
+    }
+
+    /// Runs `op` if a request is allowed, and adapts the rate to the feedback it returns.
+    ///
+    /// # Returns
+    ///
+    /// The output of `op`, or [`Error::RateLimited`] if the request was not allowed,
+    /// in which case `op` is not run.
+    pub fn call<T>(&self, op: impl FnOnce() -> (T, Feedback)) -> Result<T, Error> {
+        if !self.allow() {
+            return Err(Error::RateLimited);
+        }
+        let (output, feedback) = op();
+        self.record(feedback);
+        Ok(output)
+    }
+
+    /// Adapts the rate to the feedback of the upstream on a request.
+    ///
+    /// Throttling backs off the rate, unless it already backed off within the
+    /// recovery interval, and pauses all requests for `retry_after`.
+    pub fn record(&self, feedback: Feedback) {
+        let Feedback::Throttled { retry_after } = feedback else {
+            return;
+        };
+
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.update();
+        if let Some(retry_after) = retry_after {
+            inner.paused_until = inner.paused_until.max(now.saturating_add(retry_after));
+        }
+
+        let interval = inner.policy.recovery_interval;
+        if inner
+            .backed_off_at
+            .is_some_and(|at| now.saturating_sub(at) < interval)
+        {
+            return;
+        }
+        let rate = (inner.rate * inner.policy.backoff).max(inner.policy.min_rate);
+        inner.set_rate(rate);
+        inner.backed_off_at = Some(now);
+        inner.recovered_at = now;
+    }
+
+    /// Returns the current rate, in requests per second.
+    pub fn rate(&self) -> f64 {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update();
+        inner.rate
+    }
+}
+
+impl AdaptiveInner {
+    /// Recovers the rate for the recovery intervals that have passed.
+    ///
+    /// # Returns
+    ///
+    /// The current timestamp.
+    fn update(&mut self) -> Duration {
+        let now = self.clock.now();
+        if self.rate >= self.max_rate {
+            self.recovered_at = now;
+            return now;
+        }
+
+        let elapsed = now.saturating_sub(self.recovered_at);
+        let (steps, into_step) = whole_periods(elapsed, self.policy.recovery_interval);
+        if steps > 0 {
+            let rate = self.rate + steps as f64 * self.policy.recovery_step;
+            self.set_rate(rate.min(self.max_rate));
+            self.recovered_at = now - into_step;
+        }
+        now
+    }
+
+    /// Paces the requests at `rate`.
+    fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+        self.meter
+            .reconfigure(1, self.policy.burst, Some(emission_interval(rate)));
+    }
+}
+
+impl RateLimiter for AdaptiveClientLimiter {
+    fn allow_n(&self, n: u64) -> bool {
+        AdaptiveClientLimiter::allow_n(self, n)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        AdaptiveClientLimiter::next_available(self, n)
+    }
+}
+
+/// Returns the time between two requests at `rate` requests per second.
+fn emission_interval(rate: f64) -> Duration {
+    if rate > 0.0 {
+        Duration::try_from_secs_f64(1.0 / rate).unwrap_or(Duration::MAX)
+    } else {
+        Duration::MAX
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+
+    #[test]
+    fn adaptive_limiter_should_back_off_and_recover() {
+        let clock = Arc::new(ManualClock::new());
+        let policy = AdaptivePolicy {
+            min_rate: 1.0,
+            backoff: 0.5,
+            recovery_step: 2.0,
+            recovery_interval: Duration::from_secs(10),
+            burst: 1,
+        };
+        let limiter = AdaptiveClientLimiter::with_clock(8.0, policy, clock.clone());
+
+        assert!(limiter.allow());
+        assert_eq!(limiter.next_available(1), Duration::from_millis(125));
+
+        // a burst of throttled requests backs off once, and pauses for Retry-After
+        limiter.record(Feedback::from_http(429, Some("3")));
+        limiter.record(Feedback::from_http(429, None));
+        assert_eq!(limiter.rate(), 4.0);
+        assert_eq!(limiter.next_available(1), Duration::from_secs(3));
+
+        clock.advance(Duration::from_secs(3));
+        assert!(limiter.allow());
+        assert!(!limiter.allow());
+        assert_eq!(limiter.next_available(1), Duration::from_millis(250));
+
+        // throttling after the recovery interval backs off from the recovered rate
+        clock.advance(Duration::from_secs(7));
+        limiter.record(Feedback::Throttled { retry_after: None });
+        assert_eq!(limiter.rate(), 3.0);
+        clock.advance(Duration::from_secs(10));
+        limiter.record(Feedback::Throttled { retry_after: None });
+        assert_eq!(limiter.rate(), 2.5);
+
+        // then the rate recovers up to the configured one
+        clock.advance(Duration::from_secs(25));
+        assert_eq!(limiter.rate(), 6.5);
+        clock.advance(Duration::from_secs(100));
+        assert_eq!(limiter.rate(), 8.0);
+
+        let output = limiter.call(|| (42, Feedback::Accepted));
+        assert!(matches!(output, Ok(42)));
+        assert!(matches!(
+            limiter.call(|| ((), Feedback::Accepted)),
+            Err(Error::RateLimited)
+        ));
+    }
+}
@@ -0,0 +1,524 @@
+use std::{collections::HashMap, hash::Hash, sync::Arc, time::Duration};
+
+use crate::{
+    clock::{whole_periods, SharedClock},
+    sync::{Mutex, MutexExt},
+    Clock, Observer,
+};
+
+/// The weight of the newest window in the moving averages.
+const ALPHA: f64 = 0.3;
+
+/// The windows without requests after which the rates of a key are forgotten.
+const IDLE_WINDOWS: u64 = 16;
+
+/// A rate tracked by an [`AnomalyDetector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Metric {
+    /// The share of the requests denied, from 0 to 1.
+    DenyRatio,
+    /// The requests made per second, allowed or not.
+    ArrivalRate,
+}
+
+/// The smoothed rates of a key, or of all the keys of an [`AnomalyDetector`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rates {
+    /// The share of the requests denied, from 0 to 1.
+    pub deny_ratio: f64,
+    /// The requests made per second, allowed or not.
+    pub arrival_rate: f64,
+}
+
+impl Rates {
+    /// Returns the value of `metric`.
+    pub fn get(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::DenyRatio => self.deny_ratio,
+            Metric::ArrivalRate => self.arrival_rate,
+        }
+    }
+}
+
+/// A threshold crossing, reported to the subscribers of an [`AnomalyDetector`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum AnomalyEvent {
+    /// `metric` has risen above the threshold, to `value`.
+    Exceeded { metric: Metric, value: f64 },
+    /// `metric` has fallen back to the threshold or below, to `value`. This is
+    /// also reported with the last value when an idle key is forgotten.
+    Recovered { metric: Metric, value: f64 },
+}
+
+type TotalHook = Arc<dyn Fn(&AnomalyEvent) + Send + Sync>;
+type KeyHook<K> = Arc<dyn Fn(&K, &AnomalyEvent) + Send + Sync>;
+
+/// An [`Observer`] tracking moving averages of the deny ratio and the arrival rate
+/// of a limiter, for all its keys and for each of them, and reporting when they
+/// cross thresholds.
+///
+/// Abuse detection can subscribe to a client suddenly making many more requests,
+/// or getting most of them denied, and react to it, e.g. with a
+/// [`PenaltyBox`](crate::PenaltyBox), without scraping metrics. The rates are
+/// measured over windows of `window`, and smoothed over the last windows; they are
+/// checked against the thresholds at the end of every window, on the next request.
+/// Keys without requests for 16 windows are forgotten.
+///
+/// The detector is attached like any observer, and clones of it share their state.
+///
+/// # Example
+///
+/// ```
+/// use std::{
+///     sync::{Arc, Mutex},
+///     time::Duration,
+/// };
+/// use devkit_rl::{
+///     AnomalyDetector, AnomalyEvent, KeyedLimiter, LimiterConfig, ManualClock, Metric,
+/// };
+///
+/// let clock = Arc::new(ManualClock::new());
+/// let detector = AnomalyDetector::with_clock(Duration::from_secs(1), clock.clone());
+/// let alerts = Arc::new(Mutex::new(Vec::new()));
+/// detector.subscribe_keys(Metric::DenyRatio, 0.5, {
+///     let alerts = alerts.clone();
+///     move |key: &&str, event| {
+///         if let AnomalyEvent::Exceeded { .. } = event {
+///             alerts.lock().unwrap().push(key.to_string());
+///         }
+///     }
+/// });
+///
+/// let limiter = KeyedLimiter::new(LimiterConfig::TokenBucket {
+///     capacity: 1,
+///     refill_rate: 1,
+///     refill_interval_ms: Some(60_000),
+///     initial_tokens: None,
+/// })
+/// .with_observer(Arc::new(detector.clone()));
+///
+/// for _ in 0..10 {
+///     limiter.allow(&"10.0.0.1");
+/// }
+/// clock.advance(Duration::from_secs(1));
+/// limiter.allow(&"10.0.0.1");
+/// assert_eq!(*alerts.lock().unwrap(), ["10.0.0.1"]);
+/// assert_eq!(detector.rates(&"10.0.0.1").unwrap().deny_ratio, 0.9);
+/// ```
+pub struct AnomalyDetector<K = ()> {
+    inner: Arc<Mutex<AnomalyDetectorInner<K>>>,
+}
+
+struct AnomalyDetectorInner<K> {
+    /// The duration of a measurement.
+    window: Duration,
+    /// The rates of all the keys.
+    total: Tracker,
+    /// The rates of every key with recent requests.
+    keys: HashMap<K, Tracker>,
+    subscriptions: Vec<Subscription<K>>,
+    /// When the idle keys are next dropped.
+    next_purge: Duration,
+    /// The source of time.
+    clock: SharedClock,
+}
+
+struct Subscription<K> {
+    metric: Metric,
+    threshold: f64,
+    hook: Hook<K>,
+}
+
+enum Hook<K> {
+    Total(TotalHook),
+    Keys(KeyHook<K>),
+}
+
+/// The moving averages of a key, and the requests of its current window.
+#[derive(Debug)]
+struct Tracker {
+    /// The averages, once a window has ended.
+    rates: Option<Rates>,
+    /// The time when the current window started.
+    window_start: Duration,
+    /// The requests made in the current window.
+    arrived: u64,
+    /// The requests denied in the current window.
+    denied: u64,
+    /// Whether each subscription is exceeded, by index of subscription.
+    exceeded: Vec<bool>,
+}
+
+/// A hook to call with an event, outside of the lock.
+enum Call<K> {
+    Total(TotalHook, AnomalyEvent),
+    Key(KeyHook<K>, K, AnomalyEvent),
+}
+
+impl<K: Hash + Eq + Clone> AnomalyDetector<K> {
+    /// Creates a new `AnomalyDetector` measuring the rates over windows of `window`,
+    /// at least a nanosecond.
+    pub fn new(window: Duration) -> Self {
+        Self::from_clock(window, SharedClock::std())
+    }
+
+    /// Creates a new `AnomalyDetector` reading the time from `clock`.
+    pub fn with_clock(window: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self::from_clock(window, SharedClock::new(clock))
+    }
+
+    fn from_clock(window: Duration, mut clock: SharedClock) -> Self {
+        let now = clock.now();
+        Self {
+            inner: Arc::new(Mutex::new(AnomalyDetectorInner {
+                window: window.max(Duration::from_nanos(1)),
+                total: Tracker::new(now),
+                keys: HashMap::new(),
+                subscriptions: Vec::new(),
+                next_purge: now,
+                clock,
+            })),
+        }
+    }
+
+    /// Calls `f` whenever `metric`, over all the keys, rises above `threshold` or
+    /// falls back.
+    ///
+    /// `f` is called on the thread making the request, outside of any lock, so it
+    /// may call back into the detector.
+    pub fn subscribe(
+        &self,
+        metric: Metric,
+        threshold: f64,
+        f: impl Fn(&AnomalyEvent) + Send + Sync + 'static,
+    ) {
+        self.add(metric, threshold, Hook::Total(Arc::new(f)));
+    }
+
+    /// Calls `f` whenever `metric` of a key rises above `threshold` or falls back.
+    ///
+    /// `f` is called on the thread making the request, outside of any lock, so it
+    /// may call back into the detector.
+    pub fn subscribe_keys(
+        &self,
+        metric: Metric,
+        threshold: f64,
+        f: impl Fn(&K, &AnomalyEvent) + Send + Sync + 'static,
+    ) {
+        self.add(metric, threshold, Hook::Keys(Arc::new(f)));
+    }
+
+    fn add(&self, metric: Metric, threshold: f64, hook: Hook<K>) {
+        self.inner
+            .lock_unpoisoned()
+            .subscriptions
+            .push(Subscription {
+                metric,
+                threshold,
+                hook,
+            });
+    }
+
+    /// Returns the rates over all the keys, once a window has ended.
+    pub fn total(&self) -> Option<Rates> {
+        self.inner.lock_unpoisoned().total.rates
+    }
+
+    /// Returns the rates of `key`, once a window with requests for it has ended.
+    pub fn rates(&self, key: &K) -> Option<Rates> {
+        self.inner.lock_unpoisoned().keys.get(key)?.rates
+    }
+
+    /// Records `n` requests for `key`, `denied` of which were denied, and reports
+    /// the thresholds crossed.
+    fn record(&self, key: &K, n: u64, denied: u64) {
+        let calls = self.inner.lock_unpoisoned().record(key, n, denied);
+        for call in calls {
+            match call {
+                Call::Total(hook, event) => hook(&event),
+                Call::Key(hook, key, event) => hook(&key, &event),
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> AnomalyDetectorInner<K> {
+    /// Records the requests, returning the hooks to call.
+    fn record(&mut self, key: &K, n: u64, denied: u64) -> Vec<Call<K>> {
+        let now = self.clock.now();
+        let window = self.window;
+        let mut calls = Vec::new();
+
+        if self.total.advance_to(now, window) {
+            for (i, subscription) in self.subscriptions.iter().enumerate() {
+                if let Hook::Total(hook) = &subscription.hook {
+                    if let Some(event) = self.total.check(i, subscription) {
+                        calls.push(Call::Total(hook.clone(), event));
+                    }
+                }
+            }
+        }
+        self.total.add(n, denied);
+
+        if now >= self.next_purge {
+            self.purge(now, &mut calls);
+        }
+        let tracker = self
+            .keys
+            .entry(key.clone())
+            .or_insert_with(|| Tracker::new(now));
+        if tracker.advance_to(now, window) {
+            for (i, subscription) in self.subscriptions.iter().enumerate() {
+                if let Hook::Keys(hook) = &subscription.hook {
+                    if let Some(event) = tracker.check(i, subscription) {
+                        calls.push(Call::Key(hook.clone(), key.clone(), event));
+                    }
+                }
+            }
+        }
+        tracker.add(n, denied);
+        calls
+    }
+
+    /// Forgets the keys idle for [`IDLE_WINDOWS`], reporting their exceeded
+    /// thresholds as recovered.
+    fn purge(&mut self, now: Duration, calls: &mut Vec<Call<K>>) {
+        let idle = self.window.saturating_mul(IDLE_WINDOWS as u32);
+        let subscriptions = &self.subscriptions;
+        self.keys.retain(|key, tracker| {
+            if now.saturating_sub(tracker.window_start) < idle {
+                return true;
+            }
+            let rates = tracker.rates.unwrap_or_default();
+            for (i, &exceeded) in tracker.exceeded.iter().enumerate() {
+                if let (true, Hook::Keys(hook)) = (exceeded, &subscriptions[i].hook) {
+                    let metric = subscriptions[i].metric;
+                    let value = rates.get(metric);
+                    let event = AnomalyEvent::Recovered { metric, value };
+                    calls.push(Call::Key(hook.clone(), key.clone(), event));
+                }
+            }
+            false
+        });
+        self.next_purge = now.saturating_add(self.window);
+    }
+}
+
+impl Tracker {
+    fn new(now: Duration) -> Self {
+        Self {
+            rates: None,
+            window_start: now,
+            arrived: 0,
+            denied: 0,
+            exceeded: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, n: u64, denied: u64) {
+        self.arrived = self.arrived.saturating_add(n);
+        self.denied = self.denied.saturating_add(denied);
+    }
+
+    /// Ends the windows elapsed until `now`, folding them into the averages.
+    ///
+    /// # Returns
+    ///
+    /// `true` if a window has ended.
+    fn advance_to(&mut self, now: Duration, window: Duration) -> bool {
+        let elapsed = now.saturating_sub(self.window_start);
+        if elapsed < window {
+            return false;
+        }
+        let (windows, into_window) = whole_periods(elapsed, window);
+
+        let arrival = self.arrived as f64 / window.as_secs_f64();
+        let deny = (self.arrived > 0).then(|| self.denied as f64 / self.arrived as f64);
+        let rates = match self.rates {
+            None => Rates {
+                deny_ratio: deny.unwrap_or(0.0),
+                arrival_rate: arrival,
+            },
+            Some(rates) => Rates {
+                deny_ratio: deny.map_or(rates.deny_ratio, |d| ewma(rates.deny_ratio, d)),
+                arrival_rate: ewma(rates.arrival_rate, arrival),
+            },
+        };
+        // the windows after the first one saw no requests
+        let idle = i32::try_from(windows - 1).unwrap_or(i32::MAX);
+        self.rates = Some(Rates {
+            arrival_rate: rates.arrival_rate * (1.0 - ALPHA).powi(idle),
+            ..rates
+        });
+
+        self.arrived = 0;
+        self.denied = 0;
+        self.window_start = now - into_window;
+        true
+    }
+
+    /// Checks the subscription numbered `i` against the rates.
+    ///
+    /// # Returns
+    ///
+    /// The event to report, if the threshold has been crossed.
+    fn check<K>(&mut self, i: usize, subscription: &Subscription<K>) -> Option<AnomalyEvent> {
+        let metric = subscription.metric;
+        let value = self.rates?.get(metric);
+        if self.exceeded.len() <= i {
+            self.exceeded.resize(i + 1, false);
+        }
+
+        let exceeded = value > subscription.threshold;
+        if exceeded == self.exceeded[i] {
+            return None;
+        }
+        self.exceeded[i] = exceeded;
+        Some(if exceeded {
+            AnomalyEvent::Exceeded { metric, value }
+        } else {
+            AnomalyEvent::Recovered { metric, value }
+        })
+    }
+}
+
+/// Folds `sample` into the moving average `average`.
+fn ewma(average: f64, sample: f64) -> f64 {
+    average + ALPHA * (sample - average)
+}
+
+impl<K: Hash + Eq + Clone + Send + Sync> Observer<K> for AnomalyDetector<K> {
+    fn on_allowed(&self, key: &K, n: u64) {
+        self.record(key, n, 0);
+    }
+
+    fn on_denied(&self, key: &K, n: u64) {
+        self.record(key, n, n);
+    }
+}
+
+impl<K> Clone for AnomalyDetector<K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K> std::fmt::Debug for AnomalyDetector<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inner = self.inner.lock_unpoisoned();
+        f.debug_struct("AnomalyDetector")
+            .field("window", &inner.window)
+            .field("total", &inner.total.rates)
+            .field("keys", &inner.keys.len())
+            .field("subscriptions", &inner.subscriptions.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex as StdMutex;
+
+    use super::*;
+    use crate::ManualClock;
+    #[cfg(feature = "fixed-window")]
+    use crate::{FixedWindow, Observed, RateLimiter};
+
+    #[test]
+    fn anomaly_detector_should_report_threshold_crossings() {
+        const WINDOW: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(ManualClock::new());
+        let detector = AnomalyDetector::with_clock(WINDOW, clock.clone());
+        let events = Arc::new(StdMutex::new(Vec::new()));
+        detector.subscribe_keys(Metric::DenyRatio, 0.5, {
+            let events = events.clone();
+            move |key: &&str, event| events.lock().unwrap().push((*key, *event))
+        });
+        let totals = Arc::new(StdMutex::new(Vec::new()));
+        detector.subscribe(Metric::ArrivalRate, 15.0, {
+            let totals = totals.clone();
+            move |event| totals.lock().unwrap().push(*event)
+        });
+
+        // "a" gets 3 of its 4 requests denied, "b" none of its 16
+        for _ in 0..3 {
+            detector.on_denied(&"a", 1);
+        }
+        detector.on_allowed(&"a", 1);
+        detector.on_allowed(&"b", 16);
+        assert_eq!(detector.rates(&"a"), None);
+
+        clock.advance(WINDOW);
+        detector.on_allowed(&"a", 1);
+        detector.on_allowed(&"b", 1);
+        let a = detector.rates(&"a").unwrap();
+        assert_eq!(a.deny_ratio, 0.75);
+        assert_eq!(a.arrival_rate, 4.0);
+        assert_eq!(detector.rates(&"b").unwrap().deny_ratio, 0.0);
+        assert_eq!(detector.total().unwrap().arrival_rate, 20.0);
+
+        // a window of allowed requests brings "a" back under the threshold
+        detector.on_allowed(&"a", 3);
+        clock.advance(WINDOW);
+        detector.on_allowed(&"a", 1);
+        assert!((detector.rates(&"a").unwrap().deny_ratio - 0.525).abs() < 1e-9);
+        clock.advance(WINDOW);
+        detector.on_allowed(&"a", 1);
+
+        // an idle detector decays the arrival rates, and forgets idle keys
+        clock.advance(WINDOW * 20);
+        detector.on_allowed(&"c", 1);
+        assert!(detector.total().unwrap().arrival_rate < 0.1);
+        assert_eq!(detector.rates(&"b"), None);
+
+        let deny = |value| (Metric::DenyRatio, value);
+        let events: Vec<_> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|&(key, event)| match event {
+                AnomalyEvent::Exceeded { metric, value } => (key, true, metric, value),
+                AnomalyEvent::Recovered { metric, value } => (key, false, metric, value),
+            })
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].0, events[0].1), ("a", true));
+        assert_eq!((events[0].2, events[0].3), deny(0.75));
+        assert_eq!(
+            (events[1].0, events[1].1, events[1].2),
+            ("a", false, Metric::DenyRatio)
+        );
+        assert!(matches!(
+            totals.lock().unwrap()[..],
+            [
+                AnomalyEvent::Exceeded {
+                    metric: Metric::ArrivalRate,
+                    value: 20.0
+                },
+                AnomalyEvent::Recovered { .. }
+            ]
+        ));
+    }
+
+    #[cfg(feature = "fixed-window")]
+    #[test]
+    fn anomaly_detector_should_observe_limiters_without_keys() {
+        let clock = Arc::new(ManualClock::new());
+        let detector = AnomalyDetector::with_clock(Duration::from_secs(1), clock.clone());
+        let limiter = Observed::new(
+            FixedWindow::with_clock(1, Some(Duration::from_secs(60)), false, clock.clone()),
+            Arc::new(detector.clone()),
+        );
+
+        assert!(limiter.allow());
+        assert!(!limiter.allow());
+        clock.advance(Duration::from_secs(1));
+        assert!(!limiter.allow());
+        assert_eq!(detector.total().unwrap().deny_ratio, 0.5);
+        assert_eq!(detector.rates(&()), detector.total());
+    }
+}
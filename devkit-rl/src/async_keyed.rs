@@ -0,0 +1,347 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    hash::Hash,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    limiter::retry_after,
+    runtime::timeout,
+    sync::{Mutex, MutexExt},
+    AsyncRuntime, DefaultRuntime, Error, HashMapStore, KeyStore, KeyedLimiter,
+};
+
+/// Async waiters for the keys of a [`KeyedLimiter`], with a bound on the waiters.
+///
+/// Waiting for a key with [`AsyncKeyedLimiter::wait`] queues the caller behind the
+/// other waiters of the key, and admits the waiters of a key in order as its quota
+/// frees. Every waiter is a future held by the limiter, so a client flooding keys
+/// with requests would hold an unbounded number of them: the limiter bounds the
+/// waiters of all the keys together to `max_pending`, and those of a single key to
+/// [`max_pending_per_key`](AsyncKeyedLimiter::with_max_pending_per_key), so that a
+/// few hot keys cannot take the whole budget. A caller over either bound is
+/// rejected with [`Error::QueueFull`] right away.
+///
+/// A request allowed by the limiter of a key without waiters is admitted right
+/// away, whatever the number of waiters of the other keys.
+///
+/// Waiters schedule themselves: there is no background task, and every waiter
+/// admits as many waiters of its key as the limiter allows, in order, whenever it
+/// wakes up. The waits run on the [`DefaultRuntime`] unless another one is given.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{AsyncKeyedLimiter, Error, KeyedLimiter, LimiterConfig};
+///
+/// let keyed = KeyedLimiter::new(LimiterConfig::TokenBucket {
+///     capacity: 1,
+///     refill_rate: 1,
+///     refill_interval_ms: Some(10),
+///     initial_tokens: None,
+/// });
+/// let limiter = AsyncKeyedLimiter::new(keyed, 1000).with_max_pending_per_key(10);
+///
+/// futures::executor::block_on(async {
+///     assert!(limiter.wait("10.0.0.1", 1).await.is_ok());
+///     // waits for the bucket of the key to refill
+///     assert!(limiter.wait("10.0.0.1", 1).await.is_ok());
+///     // the bucket can never hold 2 tokens
+///     assert!(matches!(limiter.wait("10.0.0.1", 2).await, Err(Error::TooLarge)));
+/// });
+/// ```
+pub struct AsyncKeyedLimiter<K, S = HashMapStore<K>> {
+    keyed: KeyedLimiter<K, S>,
+    max_pending: usize,
+    max_pending_per_key: usize,
+    runtime: Arc<dyn AsyncRuntime>,
+    state: Arc<Mutex<State<K>>>,
+}
+
+/// The waiters of an [`AsyncKeyedLimiter`].
+struct State<K> {
+    /// The waiters of every key with waiters, in order.
+    queues: HashMap<K, VecDeque<Waiter>>,
+    /// The number of waiters of all the keys.
+    pending: usize,
+    next_id: u64,
+}
+
+/// The receiver of the admission of a waiter.
+type Admission = oneshot::Receiver<Result<(), Error>>;
+
+/// A caller waiting for `n` requests.
+struct Waiter {
+    id: u64,
+    n: u64,
+    tx: oneshot::Sender<Result<(), Error>>,
+}
+
+impl<K: Hash + Eq + Clone, S: KeyStore<K>> AsyncKeyedLimiter<K, S> {
+    /// Creates a new `AsyncKeyedLimiter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `keyed` - The limiters of the keys.
+    /// * `max_pending` - The most callers waiting at once, all keys together. A
+    ///   single key may take them all, see
+    ///   [`with_max_pending_per_key`](AsyncKeyedLimiter::with_max_pending_per_key).
+    pub fn new(keyed: KeyedLimiter<K, S>, max_pending: usize) -> Self {
+        Self {
+            keyed,
+            max_pending,
+            max_pending_per_key: max_pending,
+            runtime: Arc::new(DefaultRuntime),
+            state: Arc::new(Mutex::new(State {
+                queues: HashMap::new(),
+                pending: 0,
+                next_id: 0,
+            })),
+        }
+    }
+
+    /// Sets the most callers waiting at once for a single key.
+    pub fn with_max_pending_per_key(mut self, max_pending_per_key: usize) -> Self {
+        self.max_pending_per_key = max_pending_per_key;
+        self
+    }
+
+    /// Waits on the timer of `runtime` instead of the [`DefaultRuntime`].
+    pub fn with_runtime(mut self, runtime: Arc<dyn AsyncRuntime>) -> Self {
+        self.runtime = runtime;
+        self
+    }
+
+    /// Waits for `n` requests for `key`.
+    ///
+    /// Dropping the returned future takes the waiter out of the queue of its key;
+    /// a waiter dropped right after being admitted still counts against the limiter.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::QueueFull`] if the requests are not allowed right away and
+    ///   `max_pending` callers wait already, or `max_pending_per_key` for `key`.
+    /// * [`Error::TooLarge`] if the limiter of `key` can never allow them, e.g.
+    ///   because `n` exceeds its capacity.
+    pub async fn wait(&self, key: K, n: u64) -> Result<(), Error> {
+        let Some((mut pending, mut rx, mut retry)) = self.enqueue(key, n)? else {
+            return Ok(());
+        };
+        loop {
+            let admission = if retry == Duration::MAX {
+                // the waiter is no longer queued, its admission is already sent
+                Some((&mut rx).await)
+            } else {
+                timeout(&*self.runtime, retry, &mut rx).await
+            };
+            match admission {
+                Some(result) => {
+                    pending.done = true;
+                    return result.unwrap_or(Err(Error::Disconnected));
+                }
+                None => retry = self.dispatch(&mut self.state.lock_unpoisoned(), &pending.key),
+            }
+        }
+    }
+
+    /// Returns the number of callers waiting, all keys together.
+    pub fn pending(&self) -> usize {
+        self.state.lock_unpoisoned().pending
+    }
+
+    /// Returns the limiters of the keys.
+    pub fn keyed(&self) -> &KeyedLimiter<K, S> {
+        &self.keyed
+    }
+
+    /// Admits `n` requests for `key` if no caller waits for it and the limiter
+    /// allows them, or queues a waiter for them and admits the waiters of `key`
+    /// the limiter allows.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the requests are admitted, or the guard taking the waiter out of
+    /// the queue if it is dropped, the receiver of its admission, and how long to
+    /// wait before trying the limiter again.
+    fn enqueue(&self, key: K, n: u64) -> Result<Option<(Pending<K>, Admission, Duration)>, Error> {
+        let mut state = self.state.lock_unpoisoned();
+        let queued = state.queues.get(&key).map_or(0, VecDeque::len);
+        if queued == 0 && self.keyed.allow_n(&key, n) {
+            return Ok(None);
+        }
+        if state.pending >= self.max_pending || queued >= self.max_pending_per_key {
+            return Err(Error::QueueFull);
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.pending += 1;
+        state
+            .queues
+            .entry(key.clone())
+            .or_default()
+            .push_back(Waiter { id, n, tx });
+
+        let retry = self.dispatch(&mut state, &key);
+        let pending = Pending {
+            state: self.state.clone(),
+            key,
+            id,
+            done: false,
+        };
+        Ok(Some((pending, rx, retry)))
+    }
+
+    /// Admits the waiters of `key` in order while its limiter allows them.
+    ///
+    /// # Returns
+    ///
+    /// How long to wait before trying the limiter again, or [`Duration::MAX`] once
+    /// `key` has no waiters.
+    fn dispatch(&self, state: &mut State<K>, key: &K) -> Duration {
+        loop {
+            let Some(queue) = state.queues.get_mut(key) else {
+                return Duration::MAX;
+            };
+            let n = queue.front().expect("queues are not empty").n;
+
+            let result = if self.keyed.allow_n(key, n) {
+                Ok(())
+            } else {
+                match retry_after(self.keyed.next_available(key, n)) {
+                    Ok(wait) => return wait,
+                    Err(e) => Err(e),
+                }
+            };
+            let waiter = queue.pop_front().expect("queues are not empty");
+            if queue.is_empty() {
+                state.queues.remove(key);
+            }
+            state.pending -= 1;
+            // the caller may be gone, e.g. a dropped future
+            let _ = waiter.tx.send(result);
+        }
+    }
+}
+
+impl<K: fmt::Debug, S: fmt::Debug> fmt::Debug for AsyncKeyedLimiter<K, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncKeyedLimiter")
+            .field("keyed", &self.keyed)
+            .field("max_pending", &self.max_pending)
+            .field("max_pending_per_key", &self.max_pending_per_key)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Takes a waiter out of the queue of its key unless it was admitted.
+struct Pending<K: Hash + Eq> {
+    state: Arc<Mutex<State<K>>>,
+    key: K,
+    id: u64,
+    done: bool,
+}
+
+impl<K: Hash + Eq> Drop for Pending<K> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let mut state = self.state.lock_unpoisoned();
+        let Some(queue) = state.queues.get_mut(&self.key) else {
+            return;
+        };
+        let queued = queue.len();
+        queue.retain(|w| w.id != self.id);
+        let removed = queued - queue.len();
+        if queue.is_empty() {
+            state.queues.remove(&self.key);
+        }
+        state.pending -= removed;
+    }
+}
+
+#[cfg(all(test, feature = "token-bucket"))]
+mod tests {
+    use std::sync::Mutex as StdMutex;
+
+    use futures::{executor::block_on, future::join_all, FutureExt};
+
+    use super::*;
+    use crate::{LimiterConfig, Sleep};
+
+    fn keyed(refill_interval_ms: u64) -> KeyedLimiter<&'static str> {
+        KeyedLimiter::new(LimiterConfig::TokenBucket {
+            capacity: 1,
+            refill_rate: 1,
+            refill_interval_ms: Some(refill_interval_ms),
+            initial_tokens: None,
+        })
+    }
+
+    #[test]
+    fn async_keyed_limiter_should_bound_pending_waiters() {
+        // the waiters never wake up on their own
+        let never = |_: Duration| -> Sleep { Box::pin(core::future::pending()) };
+        let limiter = AsyncKeyedLimiter::new(keyed(3_600_000), 3)
+            .with_max_pending_per_key(2)
+            .with_runtime(Arc::new(never));
+
+        assert!(matches!(limiter.wait("a", 1).now_or_never(), Some(Ok(()))));
+        // requests the limiter can never allow are denied right away
+        assert!(matches!(
+            limiter.wait("a", 2).now_or_never(),
+            Some(Err(Error::TooLarge))
+        ));
+        let mut a1 = Box::pin(limiter.wait("a", 1));
+        let mut a2 = Box::pin(limiter.wait("a", 1));
+        assert!((&mut a1).now_or_never().is_none());
+        assert!((&mut a2).now_or_never().is_none());
+        assert!(matches!(
+            limiter.wait("a", 1).now_or_never(),
+            Some(Err(Error::QueueFull))
+        ));
+
+        assert!(matches!(limiter.wait("b", 1).now_or_never(), Some(Ok(()))));
+        let mut b1 = Box::pin(limiter.wait("b", 1));
+        assert!((&mut b1).now_or_never().is_none());
+        assert_eq!(limiter.pending(), 3);
+
+        // allowed requests are admitted even when the budget is taken
+        assert!(matches!(limiter.wait("c", 1).now_or_never(), Some(Ok(()))));
+        assert!(matches!(
+            limiter.wait("c", 1).now_or_never(),
+            Some(Err(Error::QueueFull))
+        ));
+
+        // dropped waiters free their place
+        drop(a1);
+        assert_eq!(limiter.pending(), 2);
+        let mut c1 = Box::pin(limiter.wait("c", 1));
+        assert!((&mut c1).now_or_never().is_none());
+        drop((a2, b1, c1));
+        assert_eq!(limiter.pending(), 0);
+    }
+
+    #[test]
+    fn async_keyed_limiter_should_admit_waiters_in_order_as_quota_frees() {
+        let limiter = AsyncKeyedLimiter::new(keyed(10), 10);
+        let admitted = StdMutex::new(Vec::new());
+
+        block_on(join_all((0..4).map(|i| {
+            let (limiter, admitted) = (&limiter, &admitted);
+            async move {
+                let key = if i == 3 { "b" } else { "a" };
+                assert!(limiter.wait(key, 1).await.is_ok());
+                admitted.lock().unwrap().push(i);
+            }
+        })));
+
+        // the other key does not wait behind the hot one
+        assert_eq!(*admitted.lock().unwrap(), [0, 3, 1, 2]);
+        assert_eq!(limiter.pending(), 0);
+    }
+}
@@ -0,0 +1,425 @@
+use alloc::sync::Arc;
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+use crate::sync::arc_size;
+use crate::{
+    clock::{At, SharedClock},
+    sync::{Mutex, MutexExt},
+    Clock, Error,
+};
+
+const SECS_PER_DAY: i64 = 86_400;
+
+/// The calendar period after which a [`CalendarWindow`] starts over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "std",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum CalendarPeriod {
+    /// Windows start at midnight.
+    #[default]
+    Daily,
+    /// Windows start at midnight on Monday.
+    Weekly,
+    /// Windows start at midnight on the first day of the month.
+    Monthly,
+}
+
+/// A fixed window rate limiter whose windows follow the calendar.
+///
+/// Most third-party API quotas reset at a calendar boundary, e.g. every day at
+/// midnight UTC or on the first of the month, which a [`FixedWindow`](crate::FixedWindow)
+/// counting from its creation cannot express. A `CalendarWindow` reads the wall
+/// clock, and its windows start at midnight UTC, or at midnight in a time zone
+/// given by its offset from UTC.
+///
+/// The offset is fixed: for a time zone with daylight saving time, the windows
+/// start an hour off for part of the year.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{CalendarPeriod, CalendarWindow};
+///
+/// // 1000 requests per day, reset at midnight in UTC+8
+/// let daily = CalendarWindow::with_utc_offset(1000, CalendarPeriod::Daily, 8 * 3600);
+///
+/// assert!(daily.allow());
+/// assert!(daily.window_end() > daily.window_start());
+/// ```
+#[derive(Debug, Clone)]
+pub struct CalendarWindow {
+    inner: Arc<Mutex<CalendarWindowInner>>,
+}
+
+#[derive(Debug)]
+struct CalendarWindowInner {
+    /// Maximum number of allowed requests within a window.
+    size: u64,
+    /// Current count of requests within the current window.
+    count: u64,
+    period: CalendarPeriod,
+    /// The offset of the time zone from UTC, in seconds.
+    utc_offset: i32,
+    /// The time when the current window started, since the unix epoch.
+    start: Duration,
+    /// The time when the next window starts, since the unix epoch.
+    end: Duration,
+    /// The source of time, counting from the unix epoch.
+    clock: SharedClock,
+}
+
+impl CalendarWindow {
+    /// Creates a new `CalendarWindow` whose windows start at midnight UTC.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The maximum number of requests allowed within each window.
+    /// * `period` - How long each window lasts.
+    #[cfg(feature = "std")]
+    pub fn new(size: u64, period: CalendarPeriod) -> Self {
+        Self::with_utc_offset(size, period, 0)
+    }
+
+    /// Creates a new `CalendarWindow` whose windows start at midnight in the time
+    /// zone `utc_offset` seconds east of UTC.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The maximum number of requests allowed within each window.
+    /// * `period` - How long each window lasts.
+    /// * `utc_offset` - The offset of the time zone from UTC, in seconds, e.g.
+    ///   `-5 * 3600` for UTC-5.
+    #[cfg(feature = "std")]
+    pub fn with_utc_offset(size: u64, period: CalendarPeriod, utc_offset: i32) -> Self {
+        Self::with_clock(
+            size,
+            period,
+            utc_offset,
+            Arc::new(crate::SystemClock::new()),
+        )
+    }
+
+    /// Creates a new `CalendarWindow` reading the time from `clock`.
+    ///
+    /// Unlike the clock of the other limiters, `clock` must count from the unix
+    /// epoch, like [`SystemClock`](crate::SystemClock), so that the windows follow
+    /// the calendar.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The maximum number of requests allowed within each window.
+    /// * `period` - How long each window lasts.
+    /// * `utc_offset` - The offset of the time zone from UTC, in seconds.
+    /// * `clock` - The wall clock, counting from the unix epoch.
+    pub fn with_clock(
+        size: u64,
+        period: CalendarPeriod,
+        utc_offset: i32,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let mut clock = SharedClock::new(clock);
+        let (start, end) = window_bounds(clock.now(), period, utc_offset);
+        Self {
+            inner: Arc::new(Mutex::new(CalendarWindowInner {
+                size,
+                count: 0,
+                period,
+                utc_offset,
+                start,
+                end,
+                clock,
+            })),
+        }
+    }
+
+    /// Updates the parameters of the window without losing its current state.
+    ///
+    /// Requests already counted in the current window keep counting against the new
+    /// size, and the current window is the window of the new period containing now.
+    pub fn reconfigure(&self, size: u64, period: CalendarPeriod, utc_offset: i32) {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.clock.now();
+        (inner.start, inner.end) = window_bounds(now, period, utc_offset);
+        inner.size = size;
+        inner.period = period;
+        inner.utc_offset = utc_offset;
+    }
+
+    /// Checks if a single request is allowed in the current window.
+    ///
+    /// This is a convenience method for `allow_n(1)`.
+    pub fn allow(&self) -> bool {
+        self.allow_n(1)
+    }
+
+    /// Checks if `n` requests are allowed in the current window.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the requests are allowed, `false` if they exceed the limit.
+    pub fn allow_n(&self, n: u64) -> bool {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.advance();
+
+        inner.try_accept(n)
+    }
+
+    /// Checks if a single request is allowed at the time `now`.
+    ///
+    /// This is a convenience method for `allow_n_at(1, now)`.
+    pub fn allow_at(&self, now: Duration) -> bool {
+        self.allow_n_at(1, now)
+    }
+
+    /// Checks if `n` requests are allowed at the time `now`, since the unix epoch,
+    /// instead of the time read from the clock of the window.
+    ///
+    /// A time older than the latest one seen by the window counts as that latest time.
+    pub fn allow_n_at(&self, n: u64, now: Duration) -> bool {
+        self.allow_n_at_checked(n, At::Clamped(now))
+            .unwrap_or(false)
+    }
+
+    /// Like [`allow_n_at`](Self::allow_n_at), but fails with
+    /// [`Error::ClockWentBackwards`] if `now` is older than the latest time seen by
+    /// the window, e.g. to detect the out-of-order entries of a replayed log.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::RateLimited`] if the requests are denied, or [`Error::ClockWentBackwards`].
+    pub fn try_allow_n_at(&self, n: u64, now: Duration) -> Result<(), Error> {
+        if self.allow_n_at_checked(n, At::Strict(now))? {
+            Ok(())
+        } else {
+            Err(Error::RateLimited)
+        }
+    }
+
+    /// Takes `n` requests at the time `at`.
+    fn allow_n_at_checked(&self, n: u64, at: At) -> Result<bool, Error> {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.clock.observe_at(at)?;
+        inner.advance_to(now);
+
+        Ok(inner.try_accept(n))
+    }
+
+    /// Estimates how long to wait until `n` requests are allowed.
+    ///
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if the requests are allowed now, the time until the next
+    /// window otherwise, or `Duration::MAX` if `n` exceeds the size of the window
+    /// and will never be allowed.
+    pub fn next_available(&self, n: u64) -> Duration {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.advance();
+
+        if inner.count.saturating_add(n) <= inner.size {
+            return Duration::ZERO;
+        }
+        if n > inner.size {
+            return Duration::MAX;
+        }
+        inner.end.saturating_sub(now)
+    }
+
+    /// Returns how many requests the current window still allows.
+    pub fn remaining(&self) -> u64 {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.advance();
+        inner.size.saturating_sub(inner.count)
+    }
+
+    /// Returns when the current window started, since the unix epoch.
+    pub fn window_start(&self) -> Duration {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.advance();
+        inner.start
+    }
+
+    /// Returns when the current window ends, since the unix epoch, e.g. for a
+    /// `X-RateLimit-Reset` header.
+    pub fn window_end(&self) -> Duration {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.advance();
+        inner.end
+    }
+
+    /// Gives back `n` requests previously allowed by [`CalendarWindow::allow_n`].
+    ///
+    /// Refunding more than was allowed never takes the count of the current window
+    /// below 0.
+    pub fn refund(&self, n: u64) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.count = inner.count.saturating_sub(n);
+    }
+
+    /// Estimates the memory held by this limiter, in bytes.
+    #[cfg(feature = "std")]
+    pub(crate) fn mem_size(&self) -> usize {
+        arc_size::<Mutex<CalendarWindowInner>>()
+    }
+}
+
+impl CalendarWindowInner {
+    /// Starts a new window if the current one has ended, and returns the time.
+    fn advance(&mut self) -> Duration {
+        let now = self.clock.now();
+        self.advance_to(now);
+        now
+    }
+
+    /// Starts a new window if the current one has ended at `now`.
+    fn advance_to(&mut self, now: Duration) {
+        if now >= self.end {
+            (self.start, self.end) = window_bounds(now, self.period, self.utc_offset);
+            self.count = 0;
+        }
+    }
+
+    /// Counts `n` requests if they fit in the window.
+    fn try_accept(&mut self, n: u64) -> bool {
+        if self.count.saturating_add(n) > self.size {
+            false
+        } else {
+            self.count += n;
+            true
+        }
+    }
+}
+
+/// Returns the start and the end of the window of `period` containing `now`, both
+/// since the unix epoch, in the time zone `utc_offset` seconds east of UTC.
+fn window_bounds(now: Duration, period: CalendarPeriod, utc_offset: i32) -> (Duration, Duration) {
+    let local = i64::try_from(now.as_secs())
+        .unwrap_or(i64::MAX)
+        .saturating_add(i64::from(utc_offset));
+    let day = local.div_euclid(SECS_PER_DAY);
+    let (first, last) = match period {
+        CalendarPeriod::Daily => (day, day + 1),
+        CalendarPeriod::Weekly => {
+            // the unix epoch is a Thursday
+            let monday = day - (day + 3).rem_euclid(7);
+            (monday, monday + 7)
+        }
+        CalendarPeriod::Monthly => {
+            let (year, month) = civil_from_days(day);
+            let next = if month == 12 {
+                (year + 1, 1)
+            } else {
+                (year, month + 1)
+            };
+            (
+                days_from_civil(year, month),
+                days_from_civil(next.0, next.1),
+            )
+        }
+    };
+    let to_epoch = |day: i64| {
+        let secs = day
+            .saturating_mul(SECS_PER_DAY)
+            .saturating_sub(i64::from(utc_offset));
+        Duration::from_secs(u64::try_from(secs).unwrap_or(0))
+    };
+    (to_epoch(first), to_epoch(last))
+}
+
+/// Returns the days since the unix epoch of the first day of `month` of `year`.
+///
+/// This is the `days_from_civil` algorithm of Howard Hinnant, for the proleptic
+/// Gregorian calendar.
+fn days_from_civil(year: i64, month: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the year and the month of `day`, in days since the unix epoch.
+///
+/// This is the `civil_from_days` algorithm of Howard Hinnant.
+fn civil_from_days(day: i64) -> (i64, i64) {
+    let day = day + 719_468;
+    let era = day.div_euclid(146_097);
+    let day_of_era = day - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+    const DAY: Duration = Duration::from_secs(86_400);
+
+    /// Returns a clock at `days` days and `hours` hours after the unix epoch.
+    fn clock_at(days: u32, hours: u32) -> Arc<ManualClock> {
+        let clock = Arc::new(ManualClock::new());
+        clock.advance(DAY * days + HOUR * hours);
+        clock
+    }
+
+    #[test]
+    fn calendar_window_should_reset_at_midnight() {
+        // 2024-03-10 22:00 UTC, which is 06:00 on 2024-03-11 in UTC+8
+        let clock = clock_at(19_792, 22);
+        let utc = CalendarWindow::with_clock(2, CalendarPeriod::Daily, 0, clock.clone());
+        let east = CalendarWindow::with_clock(2, CalendarPeriod::Daily, 8 * 3600, clock.clone());
+        assert_eq!(utc.window_start(), DAY * 19_792);
+        assert_eq!(east.window_start(), DAY * 19_793 - HOUR * 8);
+
+        assert!(utc.allow_n(2));
+        assert!(!utc.allow());
+        assert_eq!(utc.next_available(1), HOUR * 2);
+        assert_eq!(utc.next_available(3), Duration::MAX);
+        assert!(east.allow_n(2));
+
+        clock.advance(HOUR * 2);
+        assert!(utc.allow_n(2));
+        assert!(!east.allow());
+        assert_eq!(east.next_available(1), HOUR * 16);
+    }
+
+    #[test]
+    fn calendar_window_should_follow_weeks_and_months() {
+        // 2024-02-28 12:00 UTC, a Wednesday in a leap year
+        let clock = clock_at(19_781, 12);
+        let weekly = CalendarWindow::with_clock(1, CalendarPeriod::Weekly, 0, clock.clone());
+        let monthly = CalendarWindow::with_clock(1, CalendarPeriod::Monthly, 0, clock.clone());
+        // Monday 2024-02-26
+        assert_eq!(weekly.window_start(), DAY * 19_779);
+        assert_eq!(weekly.window_end(), DAY * 19_786);
+        // 2024-02-01 to 2024-03-01
+        assert_eq!(monthly.window_start(), DAY * 19_754);
+        assert_eq!(monthly.window_end(), DAY * 19_783);
+
+        assert!(monthly.allow());
+        clock.advance(DAY);
+        assert!(!monthly.allow());
+        clock.advance(DAY);
+        assert!(monthly.allow());
+
+        // the counted requests survive a change of period
+        monthly.reconfigure(1, CalendarPeriod::Daily, 0);
+        assert!(!monthly.allow());
+        assert_eq!(monthly.window_start(), DAY * 19_783);
+
+        // December rolls over into the next year
+        assert_eq!(civil_from_days(20_088), (2024, 12));
+        assert_eq!(days_from_civil(2025, 1), 20_089);
+    }
+}
@@ -0,0 +1,367 @@
+use core::time::Duration;
+
+use crate::Error;
+
+/// A source of monotonic time for the rate limiters.
+///
+/// The limiters only compare the times returned by their clock, so a clock may
+/// count from any origin, such as the boot of a device. With the `std` feature,
+/// the limiters use [`StdClock`] unless another clock is given; without it, a
+/// clock must always be provided, e.g. a closure reading a hardware timer.
+///
+/// # Example
+///
+/// ```
+/// use std::{sync::Arc, time::Duration};
+/// use devkit_rl::{Clock, ManualClock, TokenBucket};
+///
+/// let clock = Arc::new(ManualClock::new());
+/// let bucket = TokenBucket::with_clock(1, 1, Some(Duration::from_secs(1)), clock.clone());
+///
+/// assert!(bucket.allow());
+/// assert!(!bucket.allow());
+///
+/// clock.advance(Duration::from_secs(1));
+/// assert!(bucket.allow());
+/// ```
+pub trait Clock: Send + Sync {
+    /// Returns the time elapsed since the origin of the clock.
+    ///
+    /// Successive calls should never go backwards. If they do, the limiters see the
+    /// time as stopped until the clock catches up again.
+    fn now(&self) -> Duration;
+}
+
+impl<F> Clock for F
+where
+    F: Fn() -> Duration + Send + Sync,
+{
+    fn now(&self) -> Duration {
+        self()
+    }
+}
+
+/// The system's monotonic clock, counting from the creation of the clock.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct StdClock {
+    origin: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl StdClock {
+    /// Creates a new `StdClock` starting at zero now.
+    pub fn new() -> Self {
+        Self {
+            origin: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for StdClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// The system's wall clock, counting from the unix epoch.
+///
+/// The state of a limiter driven by this clock holds unix times, which are
+/// comparable across processes and hosts, and still meaningful after a restart,
+/// e.g. to share or persist it. See [`FixedWindow::epoch_aligned`](crate::FixedWindow::epoch_aligned)
+/// for windows that start at the same time in every process.
+///
+/// If the system time is set before the unix epoch, the clock falls back on the
+/// monotonic clock, counting from the unix time at which the clock was created.
+/// Like every clock, it appears stopped to the limiters while the system time is
+/// set back.
+///
+/// # Example
+///
+/// ```
+/// use std::{sync::Arc, time::Duration};
+/// use devkit_rl::{Clock, SystemClock, TokenBucket};
+///
+/// let clock = Arc::new(SystemClock::new());
+/// assert!(clock.now() > Duration::from_secs(1_700_000_000));
+///
+/// let bucket = TokenBucket::with_clock(1, 1, None, clock);
+/// assert!(bucket.allow());
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    /// The unix time when the clock was created.
+    unix_origin: Duration,
+    origin: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl SystemClock {
+    /// Creates a new `SystemClock`.
+    pub fn new() -> Self {
+        Self {
+            unix_origin: unix_time().unwrap_or_default(),
+            origin: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        unix_time().unwrap_or_else(|| self.unix_origin.saturating_add(self.origin.elapsed()))
+    }
+}
+
+/// Returns the time since the unix epoch, or `None` if the system time is before it.
+#[cfg(feature = "std")]
+fn unix_time() -> Option<Duration> {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+}
+
+/// A clock that only moves when told to.
+///
+/// It makes the limiters deterministic in tests, and can be driven from a timer
+/// interrupt on devices without a readable clock.
+#[cfg(target_has_atomic = "64")]
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    nanos: core::sync::atomic::AtomicU64,
+}
+
+#[cfg(target_has_atomic = "64")]
+impl ManualClock {
+    /// Creates a new `ManualClock` at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        let by = u64::try_from(by.as_nanos()).unwrap_or(u64::MAX);
+        let _ = self.nanos.fetch_update(
+            core::sync::atomic::Ordering::AcqRel,
+            core::sync::atomic::Ordering::Acquire,
+            |nanos| Some(nanos.saturating_add(by)),
+        );
+    }
+}
+
+#[cfg(target_has_atomic = "64")]
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(core::sync::atomic::Ordering::Acquire))
+    }
+}
+
+/// A shared handle on the clock of a limiter.
+///
+/// The handle never goes backwards, even if its clock does: a clock going
+/// backwards appears stopped until it catches up with the latest time read.
+#[derive(Clone)]
+pub(crate) struct SharedClock {
+    clock: alloc::sync::Arc<dyn Clock>,
+    /// The latest time read through this handle.
+    last: Duration,
+}
+
+impl SharedClock {
+    pub(crate) fn new(clock: alloc::sync::Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            last: Duration::ZERO,
+        }
+    }
+
+    /// Returns the default clock, [`StdClock`].
+    #[cfg(feature = "std")]
+    pub(crate) fn std() -> Self {
+        Self::new(alloc::sync::Arc::new(StdClock::new()))
+    }
+
+    pub(crate) fn now(&mut self) -> Duration {
+        let now = self.clock.now();
+        self.observe(now)
+    }
+
+    /// Takes in a time given by the caller instead of read from the clock, e.g.
+    /// replayed from a log, and returns it, or the latest time read if it is older.
+    pub(crate) fn observe(&mut self, now: Duration) -> Duration {
+        self.last = self.last.max(now);
+        self.last
+    }
+
+    /// Takes in a time given by the caller, see [`At`].
+    pub(crate) fn observe_at(&mut self, at: At) -> Result<Duration, Error> {
+        match at {
+            At::Strict(now) if now < self.last => Err(Error::ClockWentBackwards),
+            At::Clamped(now) | At::Strict(now) => Ok(self.observe(now)),
+        }
+    }
+}
+
+/// A time given to a limiter by the caller instead of read from its clock.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum At {
+    /// A time older than the latest one seen counts as that latest time.
+    Clamped(Duration),
+    /// A time older than the latest one seen is an [`Error::ClockWentBackwards`].
+    Strict(Duration),
+}
+
+/// Returns how many whole `period`s fit in `elapsed`, saturating at `u64::MAX`, and
+/// the time left over.
+///
+/// The count is computed on integer nanoseconds, so it stays exact for any uptime. A
+/// zero period fits any number of times and leaves nothing over.
+pub(crate) fn whole_periods(elapsed: Duration, period: Duration) -> (u64, Duration) {
+    let period = period.as_nanos();
+    if period == 0 {
+        return (u64::MAX, Duration::ZERO);
+    }
+    let elapsed = elapsed.as_nanos();
+    let count = u64::try_from(elapsed / period).unwrap_or(u64::MAX);
+    (count, nanos_to_duration(elapsed % period))
+}
+
+/// Converts nanoseconds to a `Duration`, saturating at `Duration::MAX`.
+pub(crate) fn nanos_to_duration(nanos: u128) -> Duration {
+    const NANOS_PER_SEC: u128 = 1_000_000_000;
+    match u64::try_from(nanos / NANOS_PER_SEC) {
+        Ok(secs) => Duration::new(secs, (nanos % NANOS_PER_SEC) as u32),
+        Err(_) => Duration::MAX,
+    }
+}
+
+impl core::fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("SharedClock").finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "fixed-window"))]
+mod tests {
+    use alloc::sync::Arc;
+
+    use super::*;
+    use crate::FixedWindow;
+    #[cfg(feature = "token-bucket")]
+    use crate::TokenBucket;
+
+    #[test]
+    fn manual_clock_should_drive_limiters() {
+        const INTERVAL: Duration = Duration::from_secs(60);
+
+        let clock = Arc::new(ManualClock::new());
+        let window = FixedWindow::with_clock(2, Some(INTERVAL), false, clock.clone());
+
+        assert!(window.allow_n(2));
+        assert!(!window.allow());
+        assert_eq!(window.next_available(1), INTERVAL);
+
+        clock.advance(INTERVAL / 2);
+        assert_eq!(window.next_available(1), INTERVAL / 2);
+
+        clock.advance(INTERVAL / 2);
+        assert!(window.allow_n(2));
+
+        // any function returning the time is a clock
+        let ticks = || Duration::from_millis(42);
+        assert_eq!(Clock::now(&ticks), Duration::from_millis(42));
+    }
+
+    #[cfg(all(
+        feature = "fixed-window",
+        feature = "sliding-log",
+        feature = "sliding-window",
+        feature = "token-bucket"
+    ))]
+    #[test]
+    fn limiters_should_survive_long_uptimes() {
+        use crate::{SlidingWindowCount, SlidingWindowLog};
+
+        const YEAR: Duration = Duration::from_secs(365 * 24 * 3600);
+
+        let clock = Arc::new(ManualClock::new());
+        let bucket = TokenBucket::with_clock(2, 1, Some(Duration::from_nanos(1)), clock.clone());
+        let window =
+            FixedWindow::with_clock(2, Some(Duration::from_nanos(3)), false, clock.clone());
+        let count = SlidingWindowCount::with_clock(2, Duration::from_nanos(5), 2, clock.clone());
+        let log = SlidingWindowLog::with_clock(2, Some(YEAR * 1000), clock.clone());
+
+        for _ in 0..3 {
+            clock.advance(YEAR * 100 + Duration::from_nanos(1));
+            assert!(bucket.allow_n(2));
+            assert!(window.allow_n(2));
+            assert!(!window.allow());
+            assert!(count.allow_n(2));
+            assert!(!count.allow());
+        }
+        assert!(log.allow_n(2));
+        assert_eq!(log.next_available(1), YEAR * 1000);
+
+        // the refills stay aligned to whole intervals
+        assert_eq!(
+            whole_periods(YEAR * 300, Duration::from_nanos(7)).1,
+            Duration::from_nanos(YEAR.as_nanos() as u64 * 300 % 7)
+        );
+        assert_eq!(
+            whole_periods(Duration::MAX, Duration::from_nanos(1)).0,
+            u64::MAX
+        );
+        assert_eq!(
+            whole_periods(YEAR, Duration::ZERO),
+            (u64::MAX, Duration::ZERO)
+        );
+    }
+
+    #[cfg(all(feature = "fixed-window", feature = "token-bucket"))]
+    #[test]
+    fn limiters_should_not_go_back_in_time() {
+        const INTERVAL: Duration = Duration::from_secs(1);
+
+        let nanos = Arc::new(core::sync::atomic::AtomicU64::new(10_000_000_000));
+        let clock = {
+            let nanos = nanos.clone();
+            Arc::new(move || {
+                Duration::from_nanos(nanos.load(core::sync::atomic::Ordering::Relaxed))
+            })
+        };
+        let set =
+            |secs: u64| nanos.store(secs * 1_000_000_000, core::sync::atomic::Ordering::Relaxed);
+
+        let bucket = TokenBucket::with_clock(1, 1, Some(INTERVAL), clock.clone());
+        let window = FixedWindow::with_clock(1, Some(INTERVAL), false, clock.clone());
+        assert!(bucket.allow());
+        assert!(window.allow());
+
+        // a clock stepping back appears stopped, neither refilling nor panicking
+        set(5);
+        assert!(!bucket.allow());
+        assert!(!window.allow());
+        assert_eq!(window.next_available(1), INTERVAL);
+
+        // and time moves on once the clock catches up
+        set(11);
+        assert!(bucket.allow());
+        assert!(window.allow());
+    }
+}
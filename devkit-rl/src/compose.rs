@@ -0,0 +1,171 @@
+use core::time::Duration;
+
+use crate::{Error, RateLimiter};
+
+/// A rate limiter allowing requests only if both of its limiters allow them, see
+/// [`RateLimiter::and`].
+///
+/// `first` is checked first, and `second` only if `first` allows the requests. If
+/// `second` then denies them, they are [refunded](RateLimiter::refund) to `first`,
+/// so that denied requests are accounted to neither.
+#[derive(Debug, Clone)]
+pub struct And<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> And<A, B> {
+    pub(crate) fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+
+    /// Returns the limiter checked first.
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    /// Returns the limiter checked second.
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+}
+
+impl<A: RateLimiter, B: RateLimiter> RateLimiter for And<A, B> {
+    fn allow_n(&self, n: u64) -> bool {
+        self.try_check(n).is_ok()
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        self.first
+            .next_available(n)
+            .max(self.second.next_available(n))
+    }
+
+    fn try_check(&self, n: u64) -> Result<(), Error> {
+        self.first.try_check(n)?;
+        self.second
+            .try_check(n)
+            .inspect_err(|_| self.first.refund(n))
+    }
+
+    fn refund(&self, n: u64) {
+        self.first.refund(n);
+        self.second.refund(n);
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        match (self.first.remaining(), self.second.remaining()) {
+            (Some(first), Some(second)) => Some(first.min(second)),
+            (first, second) => first.or(second),
+        }
+    }
+}
+
+/// A rate limiter allowing requests if either of its limiters allows them, see
+/// [`RateLimiter::or`].
+///
+/// `second` is only checked, and accounted to, if `first` denies the requests, so
+/// that it serves as an overflow for `first`, e.g. a burst allowance on top of a
+/// steady rate.
+#[derive(Debug, Clone)]
+pub struct Or<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Or<A, B> {
+    pub(crate) fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+
+    /// Returns the limiter checked first.
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    /// Returns the limiter checked if the first denies the requests.
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+}
+
+impl<A: RateLimiter, B: RateLimiter> RateLimiter for Or<A, B> {
+    fn allow_n(&self, n: u64) -> bool {
+        self.first.allow_n(n) || self.second.allow_n(n)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        self.first
+            .next_available(n)
+            .min(self.second.next_available(n))
+    }
+
+    fn try_check(&self, n: u64) -> Result<(), Error> {
+        self.first
+            .try_check(n)
+            .or_else(|_| self.second.try_check(n))
+    }
+
+    /// Gives back `n` requests to the first limiter, as which of the two allowed
+    /// them is not recorded.
+    fn refund(&self, n: u64) {
+        self.first.refund(n);
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        Some(
+            self.first
+                .remaining()?
+                .saturating_add(self.second.remaining()?),
+        )
+    }
+}
+
+#[cfg(all(test, feature = "fixed-window"))]
+mod tests {
+    use alloc::sync::Arc;
+
+    use super::*;
+    use crate::{FixedWindow, ManualClock};
+
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    fn window(size: u64, clock: &Arc<ManualClock>) -> FixedWindow {
+        FixedWindow::with_clock(size, Some(INTERVAL), false, clock.clone())
+    }
+
+    #[test]
+    fn and_should_roll_back_the_first_limiter_when_the_second_denies() {
+        let clock = Arc::new(ManualClock::new());
+        let (first, second) = (window(5, &clock), window(3, &clock));
+        let limiter = first.clone().and(second.clone());
+
+        assert!(limiter.allow_n(2));
+        assert!(!limiter.allow_n(2));
+        assert_eq!(first.remaining(), 3);
+        assert_eq!(RateLimiter::remaining(&limiter), Some(1));
+        assert!(matches!(limiter.try_check(4), Err(Error::RateLimited)));
+        assert_eq!(first.remaining(), 3);
+
+        limiter.refund(2);
+        assert_eq!((first.remaining(), second.remaining()), (5, 3));
+        assert!(limiter.allow_n(3));
+        assert_eq!(limiter.next_available(1), INTERVAL);
+    }
+
+    #[test]
+    fn or_should_overflow_into_the_second_limiter() {
+        let clock = Arc::new(ManualClock::new());
+        let (steady, burst) = (window(2, &clock), window(3, &clock));
+        let limiter = steady.clone().or(burst.clone());
+
+        assert_eq!(RateLimiter::remaining(&limiter), Some(5));
+        assert!(limiter.allow_n(2));
+        assert_eq!(burst.remaining(), 3);
+        assert!(limiter.allow_n(2));
+        assert_eq!(burst.remaining(), 1);
+        assert!(limiter.try_check(1).is_ok());
+        assert!(!limiter.allow());
+        assert_eq!(limiter.next_available(1), INTERVAL);
+    }
+}
@@ -0,0 +1,168 @@
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+
+use crate::Error;
+
+/// An async limiter on the number of operations in flight at once, rather than
+/// on their rate.
+///
+/// An operation holds a permit while it runs, and gives it back when the permit
+/// is dropped. [`ConcurrencyLimiter::acquire`] borrows the limiter, which suits
+/// an operation awaited in place; [`ConcurrencyLimiter::acquire_owned`] returns a
+/// `'static` permit that can be moved into a spawned task, like
+/// [`Semaphore::acquire_owned`].
+///
+/// Waiters get their permits in the order they asked for them.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::ConcurrencyLimiter;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let limiter = ConcurrencyLimiter::new(2);
+///
+/// let permit = limiter.acquire_owned().await;
+/// let task = tokio::spawn(async move {
+///     let _permit = permit; // held until the task is done
+/// });
+/// assert_eq!(limiter.in_flight(), 1);
+///
+/// task.await.unwrap();
+/// assert_eq!(limiter.in_flight(), 0);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimiter {
+    semaphore: Arc<Semaphore>,
+    max: usize,
+}
+
+/// A permit of a [`ConcurrencyLimiter`] borrowing it, given back when dropped.
+#[derive(Debug)]
+#[must_use = "the permit is given back as soon as it is dropped"]
+pub struct Permit<'a> {
+    _permit: SemaphorePermit<'a>,
+}
+
+/// A `'static` permit of a [`ConcurrencyLimiter`], given back when dropped.
+///
+/// It can be moved into a spawned task.
+#[derive(Debug)]
+#[must_use = "the permit is given back as soon as it is dropped"]
+pub struct OwnedPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl ConcurrencyLimiter {
+    /// Creates a new `ConcurrencyLimiter` allowing `max` operations in flight, at
+    /// most [`Semaphore::MAX_PERMITS`].
+    pub fn new(max: usize) -> Self {
+        let max = max.min(Semaphore::MAX_PERMITS);
+        Self {
+            semaphore: Arc::new(Semaphore::new(max)),
+            max,
+        }
+    }
+
+    /// Waits until an operation may start.
+    ///
+    /// The wait is cancellation safe: dropping it gives up the place in line.
+    pub async fn acquire(&self) -> Permit<'_> {
+        let permit = self
+            .semaphore
+            .acquire()
+            .await
+            .expect("the semaphore is never closed");
+        Permit { _permit: permit }
+    }
+
+    /// Waits until an operation may start, returning a permit that does not borrow
+    /// the limiter.
+    ///
+    /// The wait is cancellation safe: dropping it gives up the place in line.
+    pub async fn acquire_owned(&self) -> OwnedPermit {
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        OwnedPermit { _permit: permit }
+    }
+
+    /// Starts an operation if the limit allows it now.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::RateLimited`] if `max` operations are in flight.
+    pub fn try_acquire(&self) -> Result<Permit<'_>, Error> {
+        let permit = self
+            .semaphore
+            .try_acquire()
+            .map_err(|_| Error::RateLimited)?;
+        Ok(Permit { _permit: permit })
+    }
+
+    /// Starts an operation if the limit allows it now, returning a permit that does
+    /// not borrow the limiter.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::RateLimited`] if `max` operations are in flight.
+    pub fn try_acquire_owned(&self) -> Result<OwnedPermit, Error> {
+        let permit = self
+            .semaphore
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| Error::RateLimited)?;
+        Ok(OwnedPermit { _permit: permit })
+    }
+
+    /// Returns the number of operations in flight.
+    pub fn in_flight(&self) -> usize {
+        self.max - self.semaphore.available_permits()
+    }
+
+    /// Returns the maximum number of operations in flight.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn concurrency_limiter_should_move_owned_permits_into_tasks() {
+        let limiter = ConcurrencyLimiter::new(2);
+
+        let mut tasks = Vec::new();
+        for _ in 0..2 {
+            let permit = limiter.acquire_owned().await;
+            tasks.push(tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                drop(permit);
+            }));
+        }
+        assert_eq!(limiter.in_flight(), 2);
+        assert!(matches!(limiter.try_acquire(), Err(Error::RateLimited)));
+
+        // the next operation starts once a task gives its permit back
+        let start = tokio::time::Instant::now();
+        let permit = limiter.acquire().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        drop(permit);
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(limiter.in_flight(), 0);
+        assert!(limiter.try_acquire_owned().is_ok());
+    }
+}
@@ -0,0 +1,524 @@
+#[cfg(any(
+    feature = "fixed-window",
+    feature = "leaky-bucket",
+    feature = "sliding-log",
+    feature = "sliding-window",
+    feature = "token-bucket"
+))]
+use std::time::Duration;
+use std::{collections::HashMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "fixed-window")]
+use crate::FixedWindow;
+#[cfg(feature = "leaky-bucket")]
+use crate::LeakyBucket;
+#[cfg(feature = "sliding-window")]
+use crate::SlidingWindowCount;
+#[cfg(feature = "token-bucket")]
+use crate::TokenBucket;
+#[cfg(feature = "calendar-window")]
+use crate::{CalendarPeriod, CalendarWindow};
+use crate::{Error, Limiter, Unlimited};
+#[cfg(feature = "sliding-log")]
+use crate::{LogOverflow, SlidingWindowLog};
+
+/// The largest offset of a time zone from UTC, in seconds.
+#[cfg(feature = "calendar-window")]
+const MAX_UTC_OFFSET_SECS: i32 = 18 * 3600;
+
+/// Declarative description of a single rate limiter.
+///
+/// The `algorithm` field selects the rate limiting algorithm, the remaining fields
+/// are the parameters of that algorithm. Intervals are given in milliseconds and
+/// default to 1 second when omitted.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{LimiterConfig, RateLimiter};
+///
+/// let config = LimiterConfig::TokenBucket {
+///     capacity: 10,
+///     refill_rate: 10,
+///     refill_interval_ms: Some(1000),
+///     initial_tokens: None,
+/// };
+///
+/// assert!(config.build().allow());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "snake_case")]
+pub enum LimiterConfig {
+    /// Parameters of a [`TokenBucket`].
+    #[cfg(feature = "token-bucket")]
+    TokenBucket {
+        capacity: u64,
+        refill_rate: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        refill_interval_ms: Option<u64>,
+        /// The tokens the bucket starts with, see [`TokenBucket::with_initial_tokens`].
+        /// Defaults to a full bucket.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        initial_tokens: Option<u64>,
+    },
+    /// Parameters of a [`LeakyBucket`].
+    #[cfg(feature = "leaky-bucket")]
+    LeakyBucket {
+        leak_rate: u64,
+        capacity: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        leak_interval_ms: Option<u64>,
+        /// Whether to answer right away instead of queuing, see [`LeakyBucket::meter`].
+        #[serde(default)]
+        meter: bool,
+    },
+    /// Parameters of a [`FixedWindow`].
+    #[cfg(feature = "fixed-window")]
+    FixedWindow {
+        size: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interval_ms: Option<u64>,
+        /// Whether to weigh in the previous window, see [`FixedWindow::with_smoothing`].
+        #[serde(default)]
+        smoothing: bool,
+    },
+    /// Parameters of a [`SlidingWindowLog`].
+    #[cfg(feature = "sliding-log")]
+    SlidingWindowLog {
+        size: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interval_ms: Option<u64>,
+        /// The maximum number of log entries, see [`SlidingWindowLog::with_max_entries`].
+        /// Defaults to [`SlidingWindowLog::DEFAULT_MAX_ENTRIES`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_entries: Option<usize>,
+        /// What to do with requests once the log is full.
+        #[serde(default)]
+        overflow: LogOverflow,
+    },
+    /// Parameters of a [`SlidingWindowCount`].
+    #[cfg(feature = "sliding-window")]
+    SlidingWindowCount {
+        size: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interval_ms: Option<u64>,
+        bucket_count: u64,
+    },
+    /// Parameters of a [`CalendarWindow`].
+    #[cfg(feature = "calendar-window")]
+    CalendarWindow {
+        size: u64,
+        #[serde(default)]
+        period: CalendarPeriod,
+        /// The offset of the time zone from UTC, in seconds. Defaults to UTC.
+        #[serde(default)]
+        utc_offset_secs: i32,
+    },
+    /// An [`Unlimited`] limiter, allowing every request.
+    Unlimited,
+}
+
+/// Declarative description of a set of named rate limiters.
+///
+/// This is the document a [`LimiterRegistry`](crate::LimiterRegistry) is built from.
+/// It can be deserialized from any serde format; JSON, TOML and YAML helpers are
+/// available behind the `json`, `toml` and `yaml` features.
+///
+/// ```toml
+/// [limiters.search-api]
+/// algorithm = "token_bucket"
+/// capacity = 100
+/// refill_rate = 10
+///
+/// [limiters.login]
+/// algorithm = "sliding_window_log"
+/// size = 5
+/// interval_ms = 60000
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryConfig {
+    /// The limiters, keyed by name.
+    #[serde(default)]
+    pub limiters: HashMap<String, LimiterConfig>,
+}
+
+/// Errors that can occur while loading a limiter configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The JSON document could not be parsed.
+    #[cfg(feature = "json")]
+    Json(serde_json::Error),
+    /// The TOML document could not be parsed.
+    #[cfg(feature = "toml")]
+    Toml(toml::de::Error),
+    /// The YAML document could not be parsed.
+    #[cfg(feature = "yaml")]
+    Yaml(serde_yaml::Error),
+}
+
+impl LimiterConfig {
+    /// Checks that this configuration describes a working limiter.
+    ///
+    /// [`LimiterConfig::build`] accepts any configuration, and makes the best of the
+    /// invalid ones, e.g. by using 1 bucket instead of 0. This reports them instead.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidConfig`] if an interval, a bucket count or a maximum number of
+    /// log entries is zero, if the buckets of a sliding window are shorter than
+    /// [`SlidingWindowCount::MIN_BUCKET_INTERVAL`], or if a UTC offset exceeds 18
+    /// hours.
+    pub fn validate(&self) -> Result<(), Error> {
+        let interval_ms = match *self {
+            #[cfg(feature = "token-bucket")]
+            LimiterConfig::TokenBucket {
+                refill_interval_ms, ..
+            } => refill_interval_ms,
+            #[cfg(feature = "leaky-bucket")]
+            LimiterConfig::LeakyBucket {
+                leak_interval_ms, ..
+            } => leak_interval_ms,
+            #[cfg(feature = "fixed-window")]
+            LimiterConfig::FixedWindow { interval_ms, .. } => interval_ms,
+            #[cfg(feature = "sliding-log")]
+            LimiterConfig::SlidingWindowLog { interval_ms, .. } => interval_ms,
+            #[cfg(feature = "sliding-window")]
+            LimiterConfig::SlidingWindowCount { interval_ms, .. } => interval_ms,
+            #[cfg(feature = "calendar-window")]
+            LimiterConfig::CalendarWindow { .. } => None,
+            LimiterConfig::Unlimited => None,
+        };
+        if interval_ms == Some(0) {
+            return Err(Error::InvalidConfig("interval must not be zero"));
+        }
+        match *self {
+            #[cfg(feature = "sliding-log")]
+            LimiterConfig::SlidingWindowLog {
+                max_entries: Some(0),
+                ..
+            } => Err(Error::InvalidConfig("max_entries must not be zero")),
+            #[cfg(feature = "sliding-window")]
+            LimiterConfig::SlidingWindowCount {
+                interval_ms,
+                bucket_count,
+                ..
+            } => SlidingWindowCount::validate(
+                Duration::from_millis(interval_ms.unwrap_or(1000)),
+                bucket_count,
+            ),
+            #[cfg(feature = "calendar-window")]
+            LimiterConfig::CalendarWindow {
+                utc_offset_secs, ..
+            } if utc_offset_secs.abs() > MAX_UTC_OFFSET_SECS => Err(Error::InvalidConfig(
+                "utc_offset_secs must be within 18 hours of UTC",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Creates a new limiter from this configuration, once it is
+    /// [validated](LimiterConfig::validate).
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidConfig`] if the configuration is invalid.
+    pub fn try_build(&self) -> Result<Limiter, Error> {
+        self.validate().map(|()| self.build())
+    }
+
+    /// Creates a new limiter from this configuration.
+    ///
+    /// # Returns
+    ///
+    /// A new [`Limiter`] with fresh state.
+    pub fn build(&self) -> Limiter {
+        match *self {
+            #[cfg(feature = "token-bucket")]
+            LimiterConfig::TokenBucket {
+                capacity,
+                refill_rate,
+                refill_interval_ms,
+                initial_tokens,
+            } => {
+                let refill_interval = refill_interval_ms.map(Duration::from_millis);
+                #[cfg(feature = "token-bucket")]
+                Limiter::TokenBucket(match initial_tokens {
+                    Some(tokens) => TokenBucket::with_initial_tokens(
+                        capacity,
+                        refill_rate,
+                        refill_interval,
+                        tokens,
+                    ),
+                    None => TokenBucket::new(capacity, refill_rate, refill_interval),
+                })
+            }
+            #[cfg(feature = "leaky-bucket")]
+            LimiterConfig::LeakyBucket {
+                leak_rate,
+                capacity,
+                leak_interval_ms,
+                meter,
+            } => {
+                let leak_interval = leak_interval_ms.map(Duration::from_millis);
+                #[cfg(feature = "leaky-bucket")]
+                Limiter::LeakyBucket(if meter {
+                    LeakyBucket::meter(leak_rate, capacity, leak_interval)
+                } else {
+                    LeakyBucket::new(leak_rate, capacity, leak_interval)
+                })
+            }
+            #[cfg(feature = "fixed-window")]
+            LimiterConfig::FixedWindow {
+                size,
+                interval_ms,
+                smoothing,
+            } => Limiter::FixedWindow(FixedWindow::with_smoothing(
+                size,
+                interval_ms.map(Duration::from_millis),
+                smoothing,
+            )),
+            #[cfg(feature = "sliding-log")]
+            LimiterConfig::SlidingWindowLog {
+                size,
+                interval_ms,
+                max_entries,
+                overflow,
+            } => Limiter::SlidingWindowLog(SlidingWindowLog::with_max_entries(
+                size,
+                interval_ms.map(Duration::from_millis),
+                max_entries.unwrap_or(SlidingWindowLog::DEFAULT_MAX_ENTRIES),
+                overflow,
+            )),
+            #[cfg(feature = "sliding-window")]
+            LimiterConfig::SlidingWindowCount {
+                size,
+                interval_ms,
+                bucket_count,
+            } => Limiter::SlidingWindowCount(SlidingWindowCount::new(
+                size,
+                Duration::from_millis(interval_ms.unwrap_or(1000)),
+                bucket_count,
+            )),
+            #[cfg(feature = "calendar-window")]
+            LimiterConfig::CalendarWindow {
+                size,
+                period,
+                utc_offset_secs,
+            } => Limiter::CalendarWindow(CalendarWindow::with_utc_offset(
+                size,
+                period,
+                utc_offset_secs,
+            )),
+            LimiterConfig::Unlimited => Limiter::Unlimited(Unlimited::new()),
+        }
+    }
+}
+
+impl RegistryConfig {
+    /// Checks every limiter configuration, see [`LimiterConfig::validate`].
+    ///
+    /// # Errors
+    ///
+    /// The name of the first invalid limiter, in name order, with its error.
+    pub fn validate(&self) -> Result<(), (String, Error)> {
+        let mut names: Vec<_> = self.limiters.keys().collect();
+        names.sort();
+        for name in names {
+            self.limiters[name]
+                .validate()
+                .map_err(|e| (name.clone(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Parses a registry configuration from a JSON document.
+    #[cfg(feature = "json")]
+    pub fn from_json(s: &str) -> Result<Self, ConfigError> {
+        serde_json::from_str(s).map_err(ConfigError::Json)
+    }
+
+    /// Parses a registry configuration from a TOML document.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(s: &str) -> Result<Self, ConfigError> {
+        toml::from_str(s).map_err(ConfigError::Toml)
+    }
+
+    /// Parses a registry configuration from a YAML document.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(s: &str) -> Result<Self, ConfigError> {
+        serde_yaml::from_str(s).map_err(ConfigError::Yaml)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, _f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "json")]
+            ConfigError::Json(ref e) => write!(_f, "invalid JSON limiter config: {e}"),
+            #[cfg(feature = "toml")]
+            ConfigError::Toml(ref e) => write!(_f, "invalid TOML limiter config: {e}"),
+            #[cfg(feature = "yaml")]
+            ConfigError::Yaml(ref e) => write!(_f, "invalid YAML limiter config: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(all(test, feature = "algorithms"))]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn registry_config_from_json_should_work() {
+        use crate::RateLimiter;
+
+        let config = RegistryConfig::from_json(
+            r#"{
+                "limiters": {
+                    "api": { "algorithm": "token_bucket", "capacity": 10, "refill_rate": 1, "initial_tokens": 0 },
+                    "login": { "algorithm": "sliding_window_count", "size": 5, "bucket_count": 10 },
+                    "internal": { "algorithm": "unlimited" }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.limiters["api"],
+            LimiterConfig::TokenBucket {
+                capacity: 10,
+                refill_rate: 1,
+                refill_interval_ms: None,
+                initial_tokens: Some(0),
+            }
+        );
+        assert!(!config.limiters["api"].build().allow());
+        assert_eq!(
+            config.limiters["login"],
+            LimiterConfig::SlidingWindowCount {
+                size: 5,
+                interval_ms: None,
+                bucket_count: 10,
+            }
+        );
+        assert_eq!(config.limiters["internal"], LimiterConfig::Unlimited);
+
+        assert!(
+            RegistryConfig::from_json(r#"{ "limiters": { "x": { "algorithm": "nope" } } }"#)
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn registry_config_from_toml_should_work() {
+        let config = RegistryConfig::from_toml(
+            r#"
+            [limiters.search-api]
+            algorithm = "fixed_window"
+            size = 100
+            interval_ms = 500
+
+            [limiters.geocoding]
+            algorithm = "calendar_window"
+            size = 2500
+            period = "monthly"
+            utc_offset_secs = -18000
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.limiters["search-api"],
+            LimiterConfig::FixedWindow {
+                size: 100,
+                interval_ms: Some(500),
+                smoothing: false,
+            }
+        );
+        assert_eq!(
+            config.limiters["geocoding"],
+            LimiterConfig::CalendarWindow {
+                size: 2500,
+                period: CalendarPeriod::Monthly,
+                utc_offset_secs: -18000,
+            }
+        );
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn registry_config_from_yaml_should_work() {
+        let config = RegistryConfig::from_yaml(
+            r#"
+            limiters:
+              login:
+                algorithm: sliding_window_log
+                size: 5
+                interval_ms: 60000
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.limiters["login"],
+            LimiterConfig::SlidingWindowLog {
+                size: 5,
+                interval_ms: Some(60000),
+                max_entries: None,
+                overflow: LogOverflow::Degrade,
+            }
+        );
+    }
+
+    #[test]
+    fn limiter_config_validate_should_reject_invalid_parameters() {
+        let valid = LimiterConfig::SlidingWindowCount {
+            size: 5,
+            interval_ms: None,
+            bucket_count: 10,
+        };
+        assert!(valid.try_build().is_ok());
+
+        let invalid = [
+            LimiterConfig::FixedWindow {
+                size: 5,
+                interval_ms: Some(0),
+                smoothing: false,
+            },
+            LimiterConfig::SlidingWindowCount {
+                size: 5,
+                interval_ms: None,
+                bucket_count: 0,
+            },
+            LimiterConfig::SlidingWindowCount {
+                size: 5,
+                interval_ms: Some(1),
+                bucket_count: 10_000,
+            },
+            LimiterConfig::CalendarWindow {
+                size: 5,
+                period: CalendarPeriod::Daily,
+                utc_offset_secs: 24 * 3600,
+            },
+        ];
+        for config in invalid {
+            assert!(
+                matches!(config.try_build(), Err(Error::InvalidConfig(_))),
+                "{config:?}"
+            );
+        }
+
+        let mut registry = RegistryConfig::default();
+        registry.limiters.insert("ok".to_string(), valid);
+        registry.limiters.insert("bad".to_string(), invalid[1]);
+        assert!(matches!(
+            registry.validate(),
+            Err((name, Error::InvalidConfig(_))) if name == "bad"
+        ));
+    }
+}
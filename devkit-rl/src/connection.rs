@@ -0,0 +1,211 @@
+use std::{borrow::Borrow, collections::HashMap, hash::Hash, sync::Arc};
+
+use crate::{
+    sync::{Mutex, MutexExt},
+    Error, HashMapStore, KeyStore, KeyedLimiter, LimiterConfig,
+};
+
+/// A limiter for connection-oriented servers, e.g. of WebSockets, limiting both
+/// how fast and how many connections every key opens.
+///
+/// A long-lived connection costs little to rate-limit as a single request, and a
+/// lot to keep open. Every key, e.g. a client address or account, is limited in
+/// the rate at which it establishes connections, by a [`KeyedLimiter`], and in
+/// the number of connections it keeps open at once. A connection holds a
+/// [`ConnectionGuard`] for as long as it lives, and is closed when the guard is
+/// dropped.
+///
+/// Clones of a limiter share their state.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{ConnectionLimiter, LimiterConfig};
+///
+/// let limiter = ConnectionLimiter::new(
+///     LimiterConfig::TokenBucket {
+///         capacity: 10,
+///         refill_rate: 10,
+///         refill_interval_ms: None,
+///         initial_tokens: None,
+///     },
+///     2,
+/// );
+///
+/// let first = limiter.try_connect("10.0.0.1").unwrap();
+/// let second = limiter.try_connect("10.0.0.1").unwrap();
+/// assert!(limiter.try_connect("10.0.0.1").is_err());
+/// assert_eq!(limiter.open(&"10.0.0.1"), 2);
+///
+/// drop(first);
+/// assert!(limiter.try_connect("10.0.0.1").is_ok());
+/// ```
+#[derive(Debug)]
+pub struct ConnectionLimiter<K, S = HashMapStore<K>> {
+    /// The rate at which every key establishes connections.
+    rate: KeyedLimiter<K, S>,
+    /// The connections open per key, for the keys with any.
+    open: Arc<Mutex<HashMap<K, usize>>>,
+    /// The most connections a key keeps open at once.
+    max_open: usize,
+}
+
+/// A connection admitted by a [`ConnectionLimiter`], closed when dropped.
+#[derive(Debug)]
+#[must_use = "the connection is closed as soon as the guard is dropped"]
+pub struct ConnectionGuard<K: Hash + Eq> {
+    open: Arc<Mutex<HashMap<K, usize>>>,
+    key: K,
+}
+
+impl<K: Hash + Eq + Clone> ConnectionLimiter<K> {
+    /// Creates a new `ConnectionLimiter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - The configuration of the limiter of connection establishments
+    ///   created for each key.
+    /// * `max_open` - The most connections a key keeps open at once.
+    pub fn new(rate: LimiterConfig, max_open: usize) -> Self {
+        Self::with_rate_limiter(KeyedLimiter::new(rate), max_open)
+    }
+}
+
+impl<K: Hash + Eq + Clone, S: KeyStore<K>> ConnectionLimiter<K, S> {
+    /// Creates a new `ConnectionLimiter` limiting connection establishments with
+    /// `rate`, e.g. a keyed limiter with an idle TTL or a sharded store.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - The limiter of connection establishments per key.
+    /// * `max_open` - The most connections a key keeps open at once.
+    pub fn with_rate_limiter(rate: KeyedLimiter<K, S>, max_open: usize) -> Self {
+        Self {
+            rate,
+            open: Arc::new(Mutex::new(HashMap::new())),
+            max_open,
+        }
+    }
+
+    /// Attempts to open a connection for `key`.
+    ///
+    /// A connection refused for having too many open is not counted against the
+    /// establishment rate of the key.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::RateLimited`] if `key` has as many connections open as allowed, or
+    /// establishes connections too fast.
+    pub fn try_connect(&self, key: K) -> Result<ConnectionGuard<K>, Error> {
+        {
+            let mut open = self.open.lock_unpoisoned();
+            if open.get(&key).copied().unwrap_or(0) >= self.max_open {
+                return Err(Error::RateLimited);
+            }
+            // reserves the connection while its rate is checked
+            *open.entry(key.clone()).or_insert(0) += 1;
+        }
+        let guard = ConnectionGuard {
+            open: self.open.clone(),
+            key,
+        };
+        if !self.rate.allow(&guard.key) {
+            return Err(Error::RateLimited);
+        }
+        Ok(guard)
+    }
+
+    /// Returns the number of connections open for `key`.
+    pub fn open<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.open.lock_unpoisoned().get(key).copied().unwrap_or(0)
+    }
+
+    /// Returns the number of connections open for all the keys.
+    pub fn total_open(&self) -> usize {
+        self.open.lock_unpoisoned().values().sum()
+    }
+
+    /// Returns the most connections a key keeps open at once.
+    pub fn max_open(&self) -> usize {
+        self.max_open
+    }
+
+    /// Returns the limiter of connection establishments.
+    pub fn rate_limiter(&self) -> &KeyedLimiter<K, S> {
+        &self.rate
+    }
+}
+
+impl<K: Hash + Eq> ConnectionGuard<K> {
+    /// Returns the key of the connection.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+impl<K: Hash + Eq> Drop for ConnectionGuard<K> {
+    fn drop(&mut self) {
+        let mut open = self.open.lock_unpoisoned();
+        if let Some(count) = open.get_mut(&self.key) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                open.remove(&self.key);
+            }
+        }
+    }
+}
+
+impl<K, S> Clone for ConnectionLimiter<K, S> {
+    fn clone(&self) -> Self {
+        Self {
+            rate: self.rate.clone(),
+            open: self.open.clone(),
+            max_open: self.max_open,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "fixed-window"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn connection_limiter_should_limit_the_rate_and_the_open_connections() {
+        let limiter = ConnectionLimiter::new(
+            LimiterConfig::FixedWindow {
+                size: 3,
+                interval_ms: Some(60_000),
+                smoothing: false,
+            },
+            2,
+        );
+
+        let a = limiter.try_connect("a").unwrap();
+        let b = limiter.try_connect("a").unwrap();
+        // too many open, which does not count against the rate
+        assert!(matches!(limiter.try_connect("a"), Err(Error::RateLimited)));
+        assert_eq!(
+            limiter.rate_limiter().next_available(&"a", 1),
+            Duration::ZERO
+        );
+        let other = limiter.clone().try_connect("b").unwrap();
+        assert_eq!((limiter.open(&"a"), limiter.total_open()), (2, 3));
+
+        // a closed connection frees a slot, but the rate is spent after a third
+        drop(a);
+        let c = limiter.try_connect("a").unwrap();
+        drop(b);
+        assert!(matches!(limiter.try_connect("a"), Err(Error::RateLimited)));
+        assert_eq!(limiter.open(&"a"), 1);
+
+        drop((c, other));
+        assert_eq!(limiter.total_open(), 0);
+        assert!(limiter.open.lock_unpoisoned().is_empty());
+    }
+}
@@ -0,0 +1,35 @@
+/// A request carrying its own weight, to take from a limiter with
+/// [`RateLimiter::allow_for`](crate::RateLimiter::allow_for).
+///
+/// This lets the type of a request decide what it costs, e.g. a batch counting
+/// for its items or a query for the rows it may scan, rather than every caller
+/// working it out.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{RateLimiter, RequestCost, TokenBucket};
+///
+/// struct Batch(Vec<u32>);
+///
+/// impl RequestCost for Batch {
+///     fn cost(&self) -> u64 {
+///         self.0.len() as u64
+///     }
+/// }
+///
+/// let limiter = TokenBucket::new(5, 5, None);
+///
+/// assert!(limiter.allow_for(&Batch(vec![1, 2, 3])));
+/// assert!(!limiter.allow_for(&Batch(vec![4, 5, 6])));
+/// ```
+pub trait RequestCost {
+    /// Returns the number of requests this request counts for.
+    fn cost(&self) -> u64;
+}
+
+impl<R: RequestCost + ?Sized> RequestCost for &R {
+    fn cost(&self) -> u64 {
+        (**self).cost()
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    mem::size_of,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    clock::{whole_periods, SharedClock},
+    sync::{Mutex, MutexExt},
+    Clock,
+};
+
+/// The smallest error rate and failure probability of a [`CountMinLimiter`], so
+/// that a typo does not allocate the memory of the host.
+const MIN_BOUND: f64 = 1e-6;
+
+/// An approximate keyed limiter allowing `limit` requests per key over a sliding
+/// window, counting the requests of all the keys in a count-min sketch.
+///
+/// A [`KeyedLimiter`](crate::KeyedLimiter) keeps a limiter per key, so its memory
+/// grows with the number of keys: limiting every client IP of a CDN edge tracks
+/// millions of them. A `CountMinLimiter` instead counts the requests in a table of
+/// `depth` rows of `width` counters, whatever the number of keys: every key adds to
+/// one counter per row, and its count is the lowest of its counters. Keys sharing
+/// counters may make a count too high, never too low, so a key may be denied
+/// early, but is never allowed more than `limit` requests per window.
+///
+/// The table is sized from the error bounds: with probability `1 - delta`, the
+/// count of a key exceeds its requests in the window by at most `epsilon` times the
+/// requests of all the keys. The sketch takes `2 * ceil(e / epsilon) *
+/// ceil(ln(1 / delta))` counters, e.g. 5 rows of 2719 counters, about 200 KiB, for
+/// `epsilon = 0.001` and `delta = 0.01`.
+///
+/// The requests are aged like those of a [`SlidingWindowCount`](crate::SlidingWindowCount)
+/// of 2 buckets: the sketch of the previous window is weighted by the share of it
+/// still in the sliding window, and is cleared once it has left.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_rl::CountMinLimiter;
+///
+/// let limiter = CountMinLimiter::new(2, Some(Duration::from_secs(60)), 0.001, 0.01);
+///
+/// assert!(limiter.allow(&"10.0.0.1"));
+/// assert!(limiter.allow(&"10.0.0.1"));
+/// assert!(!limiter.allow(&"10.0.0.1"));
+/// assert!(limiter.allow(&"10.0.0.2"));
+/// assert_eq!((limiter.width(), limiter.depth()), (2719, 5));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CountMinLimiter {
+    inner: Arc<Mutex<CountMinLimiterInner>>,
+    hasher: RandomState,
+}
+
+#[derive(Debug)]
+struct CountMinLimiterInner {
+    /// The requests allowed per key and window.
+    limit: u64,
+    /// The duration of the window.
+    interval: Duration,
+    /// The number of counters per row.
+    width: usize,
+    /// The number of rows.
+    depth: usize,
+    /// The index of the current window, counted in intervals.
+    window: u64,
+    /// The counters of the current window, row after row.
+    current: Vec<u64>,
+    /// The counters of the previous window, row after row.
+    previous: Vec<u64>,
+    /// The requests of all the keys in the current and the previous window.
+    totals: (u64, u64),
+    /// The source of time.
+    clock: SharedClock,
+}
+
+impl CountMinLimiter {
+    /// Creates a new `CountMinLimiter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of requests per key within the window.
+    /// * `interval` - The duration of the window. Defaults to 1 second if not provided.
+    /// * `epsilon` - The over-count of a key, as a share of the requests of all the
+    ///   keys in the window, from 0.000001 to 1.
+    /// * `delta` - The probability of a key exceeding that over-count, from
+    ///   0.000001 to 1.
+    pub fn new(limit: u64, interval: Option<Duration>, epsilon: f64, delta: f64) -> Self {
+        Self::from_clock(limit, interval, epsilon, delta, SharedClock::std())
+    }
+
+    /// Creates a new `CountMinLimiter` reading the time from `clock`, see
+    /// [`CountMinLimiter::new`].
+    pub fn with_clock(
+        limit: u64,
+        interval: Option<Duration>,
+        epsilon: f64,
+        delta: f64,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self::from_clock(limit, interval, epsilon, delta, SharedClock::new(clock))
+    }
+
+    fn from_clock(
+        limit: u64,
+        interval: Option<Duration>,
+        epsilon: f64,
+        delta: f64,
+        mut clock: SharedClock,
+    ) -> Self {
+        let interval = interval
+            .unwrap_or(Duration::from_secs(1))
+            .max(Duration::from_nanos(1));
+        let epsilon = epsilon.clamp(MIN_BOUND, 1.0);
+        let delta = delta.clamp(MIN_BOUND, 1.0);
+        let width = (std::f64::consts::E / epsilon).ceil() as usize;
+        let depth = ((1.0 / delta).ln().ceil() as usize).max(1);
+        let window = whole_periods(clock.now(), interval).0;
+
+        Self {
+            inner: Arc::new(Mutex::new(CountMinLimiterInner {
+                limit,
+                interval,
+                width,
+                depth,
+                window,
+                current: vec![0; width * depth],
+                previous: vec![0; width * depth],
+                totals: (0, 0),
+                clock,
+            })),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Attempts to allow a single request for `key`.
+    pub fn allow<Q: Hash + ?Sized>(&self, key: &Q) -> bool {
+        self.allow_n(key, 1)
+    }
+
+    /// Attempts to allow `n` requests for `key`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the requests are allowed, `false` if the estimated count of `key`
+    /// would exceed the limit.
+    pub fn allow_n<Q: Hash + ?Sized>(&self, key: &Q, n: u64) -> bool {
+        let hash = self.hasher.hash_one(key);
+        let mut inner = self.inner.lock_unpoisoned();
+        let weight = inner.advance();
+
+        let (current, previous) = inner.counts(hash);
+        let estimate = current.saturating_add((previous as f64 * weight).ceil() as u64);
+        if estimate.saturating_add(n) > inner.limit {
+            return false;
+        }
+        inner.add(hash, current.saturating_add(n));
+        inner.totals.0 = inner.totals.0.saturating_add(n);
+        true
+    }
+
+    /// Estimates the requests of `key` in the sliding window, never fewer than it
+    /// made.
+    pub fn estimate<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        let hash = self.hasher.hash_one(key);
+        let mut inner = self.inner.lock_unpoisoned();
+        let weight = inner.advance();
+        let (current, previous) = inner.counts(hash);
+        current.saturating_add((previous as f64 * weight).ceil() as u64)
+    }
+
+    /// Returns the most requests the count of a key exceeds its requests by, with
+    /// probability `1 - delta`: `epsilon` times the requests of all the keys in the
+    /// sliding window.
+    pub fn error_bound(&self) -> u64 {
+        let mut inner = self.inner.lock_unpoisoned();
+        let weight = inner.advance();
+        let total = inner.totals.0 as f64 + inner.totals.1 as f64 * weight;
+        (total * std::f64::consts::E / inner.width as f64).ceil() as u64
+    }
+
+    /// Returns the number of counters per row.
+    pub fn width(&self) -> usize {
+        self.inner.lock_unpoisoned().width
+    }
+
+    /// Returns the number of rows.
+    pub fn depth(&self) -> usize {
+        self.inner.lock_unpoisoned().depth
+    }
+
+    /// Returns the memory held by the counters, in bytes, whatever the number of
+    /// keys.
+    pub fn memory_bytes(&self) -> usize {
+        let inner = self.inner.lock_unpoisoned();
+        (inner.current.len() + inner.previous.len()) * size_of::<u64>()
+    }
+}
+
+impl CountMinLimiterInner {
+    /// Moves the sketches to the current window.
+    ///
+    /// # Returns
+    ///
+    /// The weight of the previous window, the share of it still in the sliding window.
+    fn advance(&mut self) -> f64 {
+        let (window, into_window) = whole_periods(self.clock.now(), self.interval);
+        if window > self.window {
+            if window == self.window + 1 {
+                std::mem::swap(&mut self.current, &mut self.previous);
+                self.totals = (0, self.totals.0);
+            } else {
+                self.previous.fill(0);
+                self.totals = (0, 0);
+            }
+            self.current.fill(0);
+            self.window = window;
+        }
+        1.0 - into_window.as_secs_f64() / self.interval.as_secs_f64()
+    }
+
+    /// Returns the counts of the key hashed to `hash` in the current and the
+    /// previous window.
+    fn counts(&self, hash: u64) -> (u64, u64) {
+        cells(self.width, self.depth, hash).fold((u64::MAX, u64::MAX), |(c, p), i| {
+            (c.min(self.current[i]), p.min(self.previous[i]))
+        })
+    }
+
+    /// Raises the counters of the key hashed to `hash` in the current window to
+    /// `count`, if lower.
+    ///
+    /// Only raising the counters to the new count of the key, rather than adding to
+    /// all of them, keeps the counters shared with other keys from growing more than
+    /// needed.
+    fn add(&mut self, hash: u64, count: u64) {
+        for i in cells(self.width, self.depth, hash) {
+            self.current[i] = self.current[i].max(count);
+        }
+    }
+}
+
+/// Returns the indices of the counters of the key hashed to `hash` in a sketch of
+/// `depth` rows of `width` counters, one per row.
+fn cells(width: usize, depth: usize, hash: u64) -> impl Iterator<Item = usize> {
+    // double hashing: the rows probe `h1 + row * h2`, h2 odd
+    let (h1, h2) = (hash as u32 as u64, (hash >> 32) | 1);
+    let width = width as u64;
+    (0..depth as u64)
+        .map(move |row| (row * width + h1.wrapping_add(row.wrapping_mul(h2)) % width) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn count_min_limiter_should_limit_keys_over_a_sliding_window() {
+        let clock = Arc::new(ManualClock::new());
+        let limiter = CountMinLimiter::with_clock(10, Some(INTERVAL), 0.01, 0.01, clock.clone());
+        assert_eq!((limiter.width(), limiter.depth()), (272, 5));
+        assert_eq!(limiter.memory_bytes(), 2 * 272 * 5 * 8);
+
+        assert!(limiter.allow_n(&"a", 10));
+        assert!(!limiter.allow(&"a"));
+        assert!(limiter.allow_n(&"b", 4));
+        assert_eq!(limiter.estimate(&"b"), 4);
+
+        // 3/4 of the previous window are still in the sliding window
+        clock.advance(INTERVAL + INTERVAL / 4);
+        assert_eq!(limiter.estimate(&"a"), 8);
+        assert!(limiter.allow_n(&"a", 2));
+        assert!(!limiter.allow(&"a"));
+
+        // the requests age out
+        clock.advance(INTERVAL * 2);
+        assert_eq!(limiter.estimate(&"a"), 0);
+        assert_eq!(limiter.error_bound(), 0);
+        assert!(limiter.allow_n(&"a", 10));
+    }
+
+    #[test]
+    fn count_min_limiter_should_never_undercount() {
+        // a tiny sketch, shared by many more keys than it has counters
+        let clock = Arc::new(ManualClock::new());
+        let limiter = CountMinLimiter::with_clock(u64::MAX, Some(INTERVAL), 0.1, 0.1, clock);
+        assert_eq!((limiter.width(), limiter.depth()), (28, 3));
+
+        for key in 0..1_000u64 {
+            assert!(limiter.allow_n(&key, key % 7 + 1));
+        }
+        let bound = limiter.error_bound();
+        let mut over = 0;
+        for key in 0..1_000u64 {
+            let estimate = limiter.estimate(&key);
+            assert!(estimate > key % 7);
+            if estimate - (key % 7 + 1) > bound {
+                over += 1;
+            }
+        }
+        // within the bound but for about `delta` of the keys
+        assert!(over <= 100, "{over}");
+    }
+}
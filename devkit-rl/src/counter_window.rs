@@ -0,0 +1,258 @@
+#[cfg(feature = "fixed-window")]
+use core::convert::Infallible;
+use core::time::Duration;
+
+use crate::clock::whole_periods;
+
+/// The counters of the windows of a [`CounterWindow`], wherever they are kept.
+///
+/// Windows are identified by their index since the origin of the window. A backend
+/// only has to add to a counter and read it back in one step, e.g. one `INCR` and
+/// `EXPIRE` round trip to a store, rather than a read-modify-write, or override
+/// [`WindowCounter::incr_within`] to also check the limit in that step.
+pub(crate) trait WindowCounter {
+    /// The error reported by the backend.
+    type Error;
+
+    /// Adds `n` to the counter of `window`, creating it if needed so that it expires
+    /// after `ttl`, and returns its new value.
+    fn incr(&mut self, window: u64, n: u64, ttl: Duration) -> Result<u64, Self::Error>;
+
+    /// Subtracts `n` from the counter of `window`, saturating at 0. A backend that
+    /// has to rewrite the counter sets it to expire after `ttl`.
+    fn decr(&mut self, window: u64, n: u64, ttl: Duration) -> Result<(), Self::Error>;
+
+    /// Returns the counter of `window`, 0 if it does not exist.
+    fn get(&mut self, window: u64) -> Result<u64, Self::Error>;
+
+    /// Adds `n` to the counter of `window` if its new value does not exceed
+    /// `limit`, creating it like [`WindowCounter::incr`].
+    ///
+    /// By default the requests are added first and rolled back if they went over,
+    /// so that denied requests do not use up the room left for smaller ones. A
+    /// failed rollback over-counts the window, but still denies the requests.
+    fn incr_within(
+        &mut self,
+        window: u64,
+        n: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<bool, Self::Error> {
+        if self.incr(window, n, ttl)? <= limit {
+            return Ok(true);
+        }
+        let _ = self.decr(window, n, ttl);
+        Ok(false)
+    }
+}
+
+/// The logic of a fixed window, shared by the local [`FixedWindow`](crate::FixedWindow)
+/// and the [`DistributedFixedWindow`](crate::distributed::DistributedFixedWindow), so
+/// that both admit the same requests whatever keeps their counters.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CounterWindow {
+    /// The maximum number of requests allowed within each window.
+    pub(crate) size: u64,
+    /// The duration of a window.
+    pub(crate) interval: Duration,
+}
+
+impl CounterWindow {
+    pub(crate) fn new(size: u64, interval: Duration) -> Self {
+        Self { size, interval }
+    }
+
+    /// Returns the index of the window containing the time `elapsed` since the
+    /// origin of the windows, and how far into that window it is.
+    pub(crate) fn window(&self, elapsed: Duration) -> (u64, Duration) {
+        whole_periods(elapsed, self.interval)
+    }
+
+    /// Counts `n` requests in `window` if they fit, together with `reserved`
+    /// requests counted elsewhere, e.g. the weighted previous window of a smoothed
+    /// window.
+    ///
+    /// The check and the increment take a single [`WindowCounter::incr_within`], so
+    /// that an admission takes one round trip to a store.
+    pub(crate) fn try_accept<C: WindowCounter>(
+        &self,
+        counter: &mut C,
+        window: u64,
+        n: u64,
+        reserved: u64,
+    ) -> Result<bool, C::Error> {
+        if n > self.size || reserved > self.size {
+            return Ok(false);
+        }
+        counter.incr_within(window, n, self.size - reserved, self.interval)
+    }
+
+    /// Estimates how long to wait from `offset` into a window counting `count`
+    /// requests until `n` requests are allowed.
+    ///
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if the requests fit in the window, the time until the next
+    /// window otherwise, or `Duration::MAX` if `n` exceeds the size and will never be
+    /// allowed.
+    pub(crate) fn next_available(&self, count: u64, n: u64, offset: Duration) -> Duration {
+        if count.saturating_add(n) <= self.size {
+            Duration::ZERO
+        } else if n > self.size {
+            Duration::MAX
+        } else {
+            self.interval - offset.min(self.interval)
+        }
+    }
+}
+
+/// The counters of a window kept in memory: the current window, and the previous
+/// one for smoothing.
+#[cfg(feature = "fixed-window")]
+#[derive(Debug, Default)]
+pub(crate) struct LocalCounter {
+    /// The index of the current window.
+    window: u64,
+    /// The requests counted in the current window.
+    count: u64,
+    /// The requests counted in the window before the current one.
+    prev: u64,
+}
+
+#[cfg(feature = "fixed-window")]
+impl LocalCounter {
+    /// Returns the requests counted in the window before `window`.
+    pub(crate) fn prev(&mut self, window: u64) -> u64 {
+        self.roll(window);
+        self.prev
+    }
+
+    /// Returns the index of the current window and the requests counted in it.
+    pub(crate) fn current(&self) -> (u64, u64) {
+        (self.window, self.count)
+    }
+
+    /// Makes `window` the current window, keeping its state, e.g. when the windows
+    /// are re-indexed from a new origin.
+    pub(crate) fn rebase(&mut self, window: u64) {
+        self.window = window;
+    }
+
+    /// Moves on to `window`, if it is newer than the current one.
+    pub(crate) fn roll(&mut self, window: u64) {
+        if window <= self.window {
+            return;
+        }
+        // the current window becomes the previous one, unless more windows have passed
+        self.prev = if window - self.window == 1 {
+            self.count
+        } else {
+            0
+        };
+        self.count = 0;
+        self.window = window;
+    }
+}
+
+#[cfg(feature = "fixed-window")]
+impl WindowCounter for LocalCounter {
+    type Error = Infallible;
+
+    fn incr(&mut self, window: u64, n: u64, _ttl: Duration) -> Result<u64, Infallible> {
+        self.roll(window);
+        if window == self.window {
+            self.count = self.count.saturating_add(n);
+        }
+        Ok(self.count)
+    }
+
+    fn decr(&mut self, window: u64, n: u64, _ttl: Duration) -> Result<(), Infallible> {
+        self.roll(window);
+        if window == self.window {
+            self.count = self.count.saturating_sub(n);
+        }
+        Ok(())
+    }
+
+    fn get(&mut self, window: u64) -> Result<u64, Infallible> {
+        self.roll(window);
+        Ok(if window == self.window { self.count } else { 0 })
+    }
+}
+
+/// Runs the scenario every backend of a [`CounterWindow`] has to pass.
+#[cfg(test)]
+pub(crate) fn assert_counter_window_semantics<C>(counter: &mut C, first: u64)
+where
+    C: WindowCounter,
+    C::Error: core::fmt::Debug,
+{
+    let window = CounterWindow::new(3, Duration::from_secs(60));
+
+    assert!(window.try_accept(counter, first, 2, 0).unwrap());
+    // denied requests are rolled back, leaving room for a smaller one
+    assert!(!window.try_accept(counter, first, 2, 0).unwrap());
+    assert!(window.try_accept(counter, first, 1, 0).unwrap());
+    assert_eq!(counter.get(first).unwrap(), 3);
+    assert!(!window.try_accept(counter, first, 4, 0).unwrap());
+    assert_eq!(
+        window.next_available(3, 1, Duration::from_secs(20)),
+        Duration::from_secs(40)
+    );
+
+    // every window has its own counter
+    assert!(window.try_accept(counter, first + 1, 3, 0).unwrap());
+    assert!(!window.try_accept(counter, first + 1, 1, 0).unwrap());
+    counter.decr(first + 1, 1, window.interval).unwrap();
+    assert!(window.try_accept(counter, first + 1, 1, 0).unwrap());
+    assert!(window.try_accept(counter, first + 2, 1, 2).unwrap());
+    assert!(!window.try_accept(counter, first + 2, 1, 2).unwrap());
+}
+
+/// Computes how long `n` requests have to wait to fit in a window of `size`, when
+/// the previous window's `prev` requests are weighted by the part of the previous
+/// window a sliding window still covers, `offset` into the current window.
+///
+/// Used by the smoothed fixed window and the distributed sliding window, which share
+/// this estimation. `n` must not exceed `size`.
+pub(crate) fn smoothed_wait(
+    prev: u64,
+    count: u64,
+    n: u64,
+    size: u64,
+    interval: Duration,
+    offset: Duration,
+) -> Duration {
+    // the fraction of a window at which a decaying count of `weighted` leaves `room`
+    let decayed_at = |weighted: u64, room: u64| {
+        if weighted <= room {
+            0.0
+        } else {
+            1.0 - room as f64 / weighted as f64
+        }
+    };
+
+    match size.checked_sub(count.saturating_add(n)) {
+        // the current window has room, wait for the previous one to decay
+        Some(room) => interval
+            .mul_f64(decayed_at(prev, room))
+            .saturating_sub(offset),
+        // wait for the next window, in which the current one decays
+        None => (interval + interval.mul_f64(decayed_at(count, size - n))).saturating_sub(offset),
+    }
+}
+
+#[cfg(all(test, feature = "fixed-window"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_counter_should_follow_counter_window_semantics() {
+        let mut counter = LocalCounter::default();
+        assert_counter_window_semantics(&mut counter, 7);
+
+        // the previous window is kept for smoothing, older ones are forgotten
+        assert_eq!(counter.prev(10), 1);
+        assert_eq!(counter.prev(12), 0);
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+
+use super::{DistributedStore, StoreError, Versioned, MAX_CAS_ATTEMPTS};
+
+/// A [`DistributedStore`] backed by an etcd v3 cluster.
+///
+/// The store talks to the JSON gateway of etcd (`/v3/kv/*`, `/v3/lease/*`) over
+/// plain HTTP. The `mod_revision` of a key serves as its version, updates are
+/// transactions comparing it, and expirations are implemented with leases, which
+/// have a resolution of one second, so TTLs are rounded up to whole seconds.
+#[derive(Debug, Clone)]
+pub struct EtcdStore {
+    addr: SocketAddr,
+    host: String,
+    timeout: Duration,
+}
+
+impl EtcdStore {
+    /// Creates a store talking to the etcd endpoint at `addr`.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The client address of an etcd member, e.g. `"127.0.0.1:2379"`.
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, StoreError> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| StoreError::Protocol("no address to connect to".to_string()))?;
+
+        Ok(Self {
+            addr,
+            host: addr.to_string(),
+            timeout: Duration::from_secs(5),
+        })
+    }
+
+    /// Reads the key-value pair stored under `key`, with its `mod_revision`.
+    fn range(&self, key: &str) -> Result<Option<(u64, u64)>, StoreError> {
+        let response = self.post("/v3/kv/range", &json!({ "key": STANDARD.encode(key) }))?;
+        let Some(kv) = response["kvs"].get(0) else {
+            return Ok(None);
+        };
+
+        let value = STANDARD
+            .decode(kv["value"].as_str().unwrap_or_default())
+            .map_err(|e| StoreError::Protocol(e.to_string()))?;
+        let value = String::from_utf8_lossy(&value);
+        let value = value
+            .parse()
+            .map_err(|_| StoreError::Protocol(format!("unexpected value {value:?}")))?;
+        Ok(Some((value, number(&kv["mod_revision"])?)))
+    }
+
+    /// Grants a lease expiring after `ttl`, returning its ID.
+    fn grant(&self, ttl: Duration) -> Result<String, StoreError> {
+        let secs = (ttl.as_secs() + u64::from(ttl.subsec_nanos() > 0)).max(1);
+        let response = self.post("/v3/lease/grant", &json!({ "TTL": secs.to_string() }))?;
+        response["ID"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| StoreError::Protocol(response.to_string()))
+    }
+
+    /// Puts `value` under `key` if the `mod_revision` of the key is still `version`.
+    ///
+    /// If `lease` is `None`, the key keeps its current lease.
+    fn put_if(
+        &self,
+        key: &str,
+        version: Option<u64>,
+        value: u64,
+        lease: Option<String>,
+    ) -> Result<bool, StoreError> {
+        let key = STANDARD.encode(key);
+        let compare = match version {
+            Some(revision) => json!({
+                "key": key, "result": "EQUAL", "target": "MOD", "mod_revision": revision.to_string()
+            }),
+            None => json!({
+                "key": key, "result": "EQUAL", "target": "CREATE", "create_revision": "0"
+            }),
+        };
+        let put = match lease {
+            Some(lease) => {
+                json!({ "key": key, "value": STANDARD.encode(value.to_string()), "lease": lease })
+            }
+            None => {
+                json!({ "key": key, "value": STANDARD.encode(value.to_string()), "ignore_lease": true })
+            }
+        };
+
+        let response = self.post(
+            "/v3/kv/txn",
+            &json!({ "compare": [compare], "success": [{ "request_put": put }] }),
+        )?;
+        // proto3 JSON omits `false`
+        Ok(response["succeeded"].as_bool().unwrap_or(false))
+    }
+
+    /// Sends a JSON request to the gateway and parses the JSON response.
+    fn post(&self, path: &str, body: &Value) -> Result<Value, StoreError> {
+        let body = body.to_string();
+        let mut stream = TcpStream::connect_timeout(&self.addr, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        write!(
+            stream,
+            "POST {path} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            self.host,
+            body.len()
+        )?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+        let response = String::from_utf8_lossy(&response);
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| StoreError::Protocol("malformed HTTP response".to_string()))?;
+
+        let status = head.split_whitespace().nth(1).unwrap_or_default();
+        if status != "200" {
+            return Err(StoreError::Protocol(format!("HTTP {status}: {body}")));
+        }
+        let body = if head
+            .to_ascii_lowercase()
+            .contains("transfer-encoding: chunked")
+        {
+            dechunk(body)?
+        } else {
+            body.to_string()
+        };
+        serde_json::from_str(&body).map_err(|e| StoreError::Protocol(e.to_string()))
+    }
+}
+
+impl DistributedStore for EtcdStore {
+    fn get(&self, key: &str) -> Result<Option<Versioned>, StoreError> {
+        Ok(self
+            .range(key)?
+            .map(|(value, version)| Versioned { value, version }))
+    }
+
+    fn incr(&self, key: &str, delta: u64, ttl: Duration) -> Result<u64, StoreError> {
+        for _ in 0..MAX_CAS_ATTEMPTS {
+            let (value, stored) = match self.range(key)? {
+                Some((value, revision)) => {
+                    let value = value.saturating_add(delta);
+                    (value, self.put_if(key, Some(revision), value, None)?)
+                }
+                None => (
+                    delta,
+                    self.put_if(key, None, delta, Some(self.grant(ttl)?))?,
+                ),
+            };
+            if stored {
+                return Ok(value);
+            }
+        }
+        Err(StoreError::Conflict)
+    }
+
+    fn expire(&self, key: &str, ttl: Duration) -> Result<(), StoreError> {
+        for _ in 0..MAX_CAS_ATTEMPTS {
+            let Some((value, revision)) = self.range(key)? else {
+                return Ok(());
+            };
+            if self.put_if(key, Some(revision), value, Some(self.grant(ttl)?))? {
+                return Ok(());
+            }
+        }
+        Err(StoreError::Conflict)
+    }
+
+    fn decr(&self, key: &str, delta: u64, _ttl: Duration) -> Result<(), StoreError> {
+        // keeps the lease of the key, rather than granting a new one per attempt
+        for _ in 0..MAX_CAS_ATTEMPTS {
+            let Some((value, revision)) = self.range(key)? else {
+                return Ok(());
+            };
+            if self.put_if(key, Some(revision), value.saturating_sub(delta), None)? {
+                return Ok(());
+            }
+        }
+        Err(StoreError::Conflict)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        version: Option<u64>,
+        value: u64,
+        ttl: Duration,
+    ) -> Result<bool, StoreError> {
+        self.put_if(key, version, value, Some(self.grant(ttl)?))
+    }
+}
+
+/// Parses a number that proto3 JSON may encode either as a string or as a number.
+fn number(value: &Value) -> Result<u64, StoreError> {
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+        .ok_or_else(|| StoreError::Protocol(format!("unexpected value {value}")))
+}
+
+/// Decodes a body sent with `Transfer-Encoding: chunked`.
+fn dechunk(mut body: &str) -> Result<String, StoreError> {
+    let mut decoded = String::new();
+    loop {
+        let (size, rest) = body
+            .split_once("\r\n")
+            .ok_or_else(|| StoreError::Protocol("malformed chunk".to_string()))?;
+        let size = usize::from_str_radix(size.trim(), 16)
+            .map_err(|_| StoreError::Protocol(format!("malformed chunk size {size:?}")))?;
+        if size == 0 {
+            return Ok(decoded);
+        }
+        let chunk = rest
+            .get(..size)
+            .ok_or_else(|| StoreError::Protocol("truncated chunk".to_string()))?;
+        decoded.push_str(chunk);
+        body = rest[size..].trim_start_matches("\r\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::BufRead, io::BufReader, net::TcpListener, thread};
+
+    use super::*;
+
+    #[test]
+    fn etcd_store_should_speak_json_gateway() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let responses = [
+                r#"{"kvs":[{"key":"aw==","value":"Nw==","mod_revision":"12"}]}"#,
+                r#"{"header":{}}"#,
+            ];
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                let mut length = 0;
+                while line != "\r\n" {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.strip_prefix("Content-Length: ") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                reader.read_exact(&mut vec![0; length]).unwrap();
+                let mut stream = stream;
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{response}\r\n0\r\n\r\n",
+                    response.len()
+                )
+                .unwrap();
+            }
+        });
+
+        let store = EtcdStore::connect(addr).unwrap();
+        assert_eq!(
+            store.get("k").unwrap(),
+            Some(Versioned {
+                value: 7,
+                version: 12
+            })
+        );
+        // `succeeded` is omitted when the comparison failed
+        assert!(!store.put_if("k", Some(11), 8, None).unwrap());
+    }
+}
@@ -0,0 +1,284 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{DistributedStore, StoreError, Versioned};
+use crate::sync::{Mutex, MutexExt};
+
+/// A [`DistributedStore`] persisted to a local file, so that quotas survive restarts.
+///
+/// This is meant for CLI tools and desktop apps that must respect e.g. a daily API
+/// quota: the counters of the distributed windows are written to the file after
+/// every change, and read back when the store is opened. Windows are aligned to
+/// the unix epoch, so a restarted process resumes the window it left. Each limiter
+/// keeps its counters under its own key prefix, e.g. its name.
+///
+/// The file is replaced atomically, by writing a temporary file next to it and
+/// renaming it, so a crash never leaves it half written. It must only be opened by
+/// one process at a time.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::{sync::Arc, time::Duration};
+/// use devkit_rl::distributed::{DistributedFixedWindow, FileStore};
+///
+/// let store = Arc::new(FileStore::open("quota.db")?);
+/// let daily = DistributedFixedWindow::new(store, "github", 5000, Some(Duration::from_secs(86_400)));
+///
+/// if daily.allow() {
+///     // call the API
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    value: u64,
+    version: u64,
+    /// When the entry expires, in milliseconds since the unix epoch.
+    expires_at: u64,
+}
+
+impl FileStore {
+    /// Opens the store persisted to `path`, creating it on the first write if it
+    /// does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read, or is not a file written by a `FileStore`.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let entries = match fs::read_to_string(&path) {
+            Ok(content) => parse(&content)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Runs `f` on the entries, after dropping the expired ones, and writes them to
+    /// the file if `f` changed them.
+    fn with_entries<T>(
+        &self,
+        f: impl FnOnce(&mut HashMap<String, Entry>) -> (T, bool),
+    ) -> Result<T, StoreError> {
+        let mut entries = self.entries.lock_unpoisoned();
+        let now = now_millis();
+        entries.retain(|_, e| e.expires_at > now);
+        let (output, changed) = f(&mut entries);
+        if changed {
+            self.persist(&entries)?;
+        }
+        Ok(output)
+    }
+
+    /// Replaces the file with `entries`.
+    fn persist(&self, entries: &HashMap<String, Entry>) -> io::Result<()> {
+        let mut content = String::new();
+        for (key, e) in entries {
+            content.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                e.value,
+                e.version,
+                e.expires_at,
+                escape(key)
+            ));
+        }
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+impl DistributedStore for FileStore {
+    fn get(&self, key: &str) -> Result<Option<Versioned>, StoreError> {
+        self.with_entries(|entries| {
+            let entry = entries.get(key).map(|e| Versioned {
+                value: e.value,
+                version: e.version,
+            });
+            (entry, false)
+        })
+    }
+
+    fn incr(&self, key: &str, delta: u64, ttl: Duration) -> Result<u64, StoreError> {
+        self.with_entries(|entries| {
+            let entry = entries.entry(key.to_string()).or_insert_with(|| Entry {
+                value: 0,
+                version: 0,
+                expires_at: expiry(ttl),
+            });
+            entry.value = entry.value.saturating_add(delta);
+            entry.version += 1;
+            (entry.value, true)
+        })
+    }
+
+    fn expire(&self, key: &str, ttl: Duration) -> Result<(), StoreError> {
+        self.with_entries(|entries| match entries.get_mut(key) {
+            Some(entry) => {
+                entry.expires_at = expiry(ttl);
+                ((), true)
+            }
+            None => ((), false),
+        })
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        version: Option<u64>,
+        value: u64,
+        ttl: Duration,
+    ) -> Result<bool, StoreError> {
+        self.with_entries(|entries| {
+            let current = entries.get(key).map(|e| e.version);
+            if current != version {
+                return (false, false);
+            }
+            entries.insert(
+                key.to_string(),
+                Entry {
+                    value,
+                    version: current.map_or(1, |v| v + 1),
+                    expires_at: expiry(ttl),
+                },
+            );
+            (true, true)
+        })
+    }
+}
+
+/// Returns the current time, in milliseconds since the unix epoch.
+fn now_millis() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    u64::try_from(now.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Returns when an entry written now with `ttl` expires.
+fn expiry(ttl: Duration) -> u64 {
+    let ttl = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+    now_millis().saturating_add(ttl)
+}
+
+/// Escapes the characters of `key` that separate the fields and the entries.
+fn escape(key: &str) -> String {
+    key.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+/// Reverses [`escape`].
+fn unescape(key: &str) -> Option<String> {
+    let mut out = String::with_capacity(key.len());
+    let mut chars = key.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => out.push('\\'),
+            't' => out.push('\t'),
+            'n' => out.push('\n'),
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+/// Parses the content of a file written by [`FileStore::persist`].
+fn parse(content: &str) -> io::Result<HashMap<String, Entry>> {
+    let invalid = |line: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid rate limiter store entry: {line:?}"),
+        )
+    };
+    content
+        .lines()
+        .map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            let mut number = || fields.next().and_then(|f| f.parse().ok());
+            let (Some(value), Some(version), Some(expires_at)) = (number(), number(), number())
+            else {
+                return Err(invalid(line));
+            };
+            let key = fields
+                .next()
+                .and_then(unescape)
+                .ok_or_else(|| invalid(line))?;
+            let entry = Entry {
+                value,
+                version,
+                expires_at,
+            };
+            Ok((key, entry))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::distributed::DistributedFixedWindow;
+
+    /// Returns a path in the temporary directory, removing what it points to.
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("devkit-rl-{}-{name}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn file_store_should_persist_counters_across_restarts() {
+        const DAY: Duration = Duration::from_secs(86_400);
+        let path = temp_path("restart");
+
+        let store = Arc::new(FileStore::open(&path).unwrap());
+        let limiter = DistributedFixedWindow::new(store.clone(), "api", 3, Some(DAY));
+        assert!(limiter.allow_n(2));
+        store.incr("odd\tkey\\\n", 7, DAY).unwrap();
+        store.incr("expired", 1, Duration::ZERO).unwrap();
+        drop((limiter, store));
+
+        // a restarted process resumes the window
+        let store = Arc::new(FileStore::open(&path).unwrap());
+        let limiter = DistributedFixedWindow::new(store.clone(), "api", 3, Some(DAY));
+        assert!(limiter.allow());
+        assert!(!limiter.allow());
+        assert_eq!(store.get("odd\tkey\\\n").unwrap().unwrap().value, 7);
+        assert_eq!(store.get("expired").unwrap(), None);
+
+        fs::write(&path, "1\tnot a version\t0\tkey\n").unwrap();
+        let err = FileStore::open(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+use std::{sync::Arc, time::Duration};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{DistributedStore, StoreError};
+use crate::{
+    counter_window::{CounterWindow, WindowCounter},
+    Error, RateLimiter,
+};
+
+/// A fixed window rate limiter whose counter lives in a [`DistributedStore`].
+///
+/// Every instance created with the same store and key shares one quota of `size`
+/// requests per window. Windows are aligned to the unix epoch, so all instances
+/// agree on when a window starts as long as their clocks are synchronized.
+///
+/// An admission takes a single [`DistributedStore::incr_within`], e.g. one script
+/// checking and incrementing the counter on a Redis server. The window admits the
+/// same requests as a [`FixedWindow`](crate::FixedWindow), which runs the same
+/// logic on counters kept in memory.
+///
+/// # Example
+///
+/// ```
+/// use std::{sync::Arc, time::Duration};
+/// use devkit_rl::distributed::{DistributedFixedWindow, InMemoryStore};
+///
+/// let store = Arc::new(InMemoryStore::new());
+/// let a = DistributedFixedWindow::new(store.clone(), "api", 1, Some(Duration::from_secs(60)));
+/// let b = DistributedFixedWindow::new(store, "api", 1, Some(Duration::from_secs(60)));
+///
+/// assert!(a.allow());
+/// assert!(!b.allow());
+/// ```
+#[derive(Clone)]
+pub struct DistributedFixedWindow {
+    store: Arc<dyn DistributedStore>,
+    key: String,
+    window: CounterWindow,
+}
+
+/// The counters of the windows of a [`DistributedFixedWindow`], kept in a store
+/// under `<key>:<window index>`.
+struct StoreCounter<'a> {
+    store: &'a dyn DistributedStore,
+    key: &'a str,
+}
+
+impl DistributedFixedWindow {
+    /// Creates a new `DistributedFixedWindow` rate limiter.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store holding the shared counters.
+    /// * `key` - The prefix of the keys used in the store; instances with the same key share a quota.
+    /// * `size` - The maximum number of requests allowed within each time window.
+    /// * `interval` - Optional duration of the time window. Defaults to 1 second if not provided.
+    pub fn new(
+        store: Arc<dyn DistributedStore>,
+        key: impl Into<String>,
+        size: u64,
+        interval: Option<Duration>,
+    ) -> Self {
+        Self {
+            store,
+            key: key.into(),
+            window: CounterWindow::new(size, interval.unwrap_or(Duration::from_secs(1))),
+        }
+    }
+
+    /// Checks if a single request is allowed in the current time window.
+    ///
+    /// Store failures are treated as admissions, see [`DistributedFixedWindow::try_allow_n`]
+    /// to handle them explicitly.
+    pub fn allow(&self) -> bool {
+        self.allow_n(1)
+    }
+
+    /// Checks if `n` requests are allowed in the current time window.
+    ///
+    /// Store failures are treated as admissions, so an unavailable store does not
+    /// take the service down with it. See [`DistributedFixedWindow::try_allow_n`] to
+    /// handle them explicitly.
+    pub fn allow_n(&self, n: u64) -> bool {
+        self.try_allow_n(n).unwrap_or(true)
+    }
+
+    /// Checks if `n` requests are allowed in the current time window.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the requests are allowed, `Ok(false)` if they exceed the limit,
+    /// or the error reported by the store.
+    pub fn try_allow_n(&self, n: u64) -> Result<bool, StoreError> {
+        let (index, _) = self.current();
+        self.window.try_accept(&mut self.counter(), index, n, 0)
+    }
+
+    /// Estimates how long to wait until `n` requests are allowed.
+    ///
+    /// Store failures are treated as admissions, like in [`DistributedFixedWindow::allow_n`].
+    ///
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if the requests are allowed now, the time until the next window
+    /// otherwise, or `Duration::MAX` if `n` exceeds the size and will never be allowed.
+    pub fn next_available(&self, n: u64) -> Duration {
+        if n > self.window.size {
+            return Duration::MAX;
+        }
+
+        let (index, offset) = self.current();
+        let Ok(count) = self.counter().get(index) else {
+            return Duration::ZERO;
+        };
+        self.window.next_available(count, n, offset)
+    }
+
+    /// Returns the index of the epoch-aligned window containing the current time,
+    /// together with how far into that window the current time is.
+    fn current(&self) -> (u64, Duration) {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.window.window(since_epoch)
+    }
+
+    fn counter(&self) -> StoreCounter<'_> {
+        StoreCounter {
+            store: self.store.as_ref(),
+            key: &self.key,
+        }
+    }
+}
+
+impl WindowCounter for StoreCounter<'_> {
+    type Error = StoreError;
+
+    fn incr(&mut self, window: u64, n: u64, ttl: Duration) -> Result<u64, StoreError> {
+        self.store.incr(&format!("{}:{}", self.key, window), n, ttl)
+    }
+
+    fn decr(&mut self, window: u64, n: u64, ttl: Duration) -> Result<(), StoreError> {
+        self.store.decr(&format!("{}:{}", self.key, window), n, ttl)
+    }
+
+    fn get(&mut self, window: u64) -> Result<u64, StoreError> {
+        let current = self.store.get(&format!("{}:{}", self.key, window))?;
+        Ok(current.map_or(0, |c| c.value))
+    }
+
+    fn incr_within(
+        &mut self,
+        window: u64,
+        n: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<bool, StoreError> {
+        self.store
+            .incr_within(&format!("{}:{}", self.key, window), n, limit, ttl)
+    }
+}
+
+impl std::fmt::Debug for DistributedFixedWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DistributedFixedWindow")
+            .field("key", &self.key)
+            .field("size", &self.window.size)
+            .field("interval", &self.window.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RateLimiter for DistributedFixedWindow {
+    fn allow_n(&self, n: u64) -> bool {
+        DistributedFixedWindow::allow_n(self, n)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        DistributedFixedWindow::next_available(self, n)
+    }
+
+    fn try_check(&self, n: u64) -> Result<(), Error> {
+        if n > self.window.size {
+            return Err(Error::TooLarge);
+        }
+        if self.try_allow_n(n)? {
+            Ok(())
+        } else {
+            Err(Error::RateLimited)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        counter_window::assert_counter_window_semantics,
+        distributed::{InMemoryStore, Versioned},
+    };
+
+    #[test]
+    fn store_counter_should_follow_counter_window_semantics() {
+        let store = InMemoryStore::new();
+        let mut counter = StoreCounter {
+            store: &store,
+            key: "test",
+        };
+        assert_counter_window_semantics(&mut counter, 7);
+        assert_eq!(store.get("test:8").unwrap().unwrap().value, 3);
+    }
+
+    /// A store whose rollbacks always lose against concurrent writers.
+    struct ConflictingStore(InMemoryStore);
+
+    impl DistributedStore for ConflictingStore {
+        fn get(&self, key: &str) -> Result<Option<Versioned>, StoreError> {
+            self.0.get(key)
+        }
+
+        fn incr(&self, key: &str, delta: u64, ttl: Duration) -> Result<u64, StoreError> {
+            self.0.incr(key, delta, ttl)
+        }
+
+        fn expire(&self, key: &str, ttl: Duration) -> Result<(), StoreError> {
+            self.0.expire(key, ttl)
+        }
+
+        fn decr(&self, _key: &str, _delta: u64, _ttl: Duration) -> Result<(), StoreError> {
+            Err(StoreError::Conflict)
+        }
+
+        fn compare_and_swap(
+            &self,
+            key: &str,
+            version: Option<u64>,
+            value: u64,
+            ttl: Duration,
+        ) -> Result<bool, StoreError> {
+            self.0.compare_and_swap(key, version, value, ttl)
+        }
+    }
+
+    #[test]
+    fn distributed_fixed_window_should_deny_when_the_rollback_conflicts() {
+        let store = Arc::new(ConflictingStore(InMemoryStore::new()));
+        let limiter = DistributedFixedWindow::new(store, "test", 2, Some(Duration::from_secs(60)));
+
+        assert!(limiter.allow_n(2));
+        // the denied request cannot be rolled back, and is still denied
+        assert!(!limiter.allow());
+        assert!(matches!(limiter.try_allow_n(1), Ok(false)));
+        assert!(matches!(limiter.try_check(1), Err(Error::RateLimited)));
+    }
+
+    #[test]
+    fn distributed_fixed_window_should_share_quota() {
+        const SIZE: u64 = 10;
+        const INTERVAL: Duration = Duration::from_millis(50);
+
+        // wait for the start of a window, so the test does not straddle a boundary
+        let (_, offset) = crate::distributed::current_window(INTERVAL);
+        std::thread::sleep(INTERVAL - offset);
+
+        let store = Arc::new(InMemoryStore::new());
+        let a = DistributedFixedWindow::new(store.clone(), "test", SIZE, Some(INTERVAL));
+        let b = DistributedFixedWindow::new(store, "test", SIZE, Some(INTERVAL));
+
+        for _ in 0..SIZE / 2 {
+            assert!(a.allow());
+            assert!(b.allow());
+        }
+        assert!(!a.allow());
+        assert!(!b.allow());
+        let wait = b.next_available(1);
+        assert!(wait > Duration::ZERO && wait <= INTERVAL);
+        assert_eq!(b.next_available(SIZE + 1), Duration::MAX);
+
+        // a new window brings a new quota
+        std::thread::sleep(INTERVAL);
+        assert!(b.allow_n(SIZE));
+    }
+}
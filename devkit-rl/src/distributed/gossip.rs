@@ -0,0 +1,562 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use crate::{
+    clock::whole_periods,
+    sync::{Mutex, MutexExt},
+    Clock, RateLimiter, SystemClock, Worker,
+};
+
+/// The count of a node in a window, as exchanged between the nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GossipMessage {
+    /// The id of the node sending the message.
+    pub node: String,
+    /// The index of the epoch-aligned window the count belongs to.
+    pub window: u64,
+    /// The number of requests the node admitted in the window.
+    pub count: u64,
+}
+
+impl GossipMessage {
+    /// Encodes the message as one line of text: `window count node`.
+    pub fn encode(&self) -> Vec<u8> {
+        format!("{} {} {}", self.window, self.count, self.node).into_bytes()
+    }
+
+    /// Decodes a message encoded by [`GossipMessage::encode`].
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(bytes).ok()?;
+        let mut fields = text.splitn(3, ' ');
+        Some(Self {
+            window: fields.next()?.parse().ok()?,
+            count: fields.next()?.parse().ok()?,
+            node: fields.next()?.to_string(),
+        })
+    }
+}
+
+/// How the nodes of a [`GossipLimiter`] exchange their counts.
+///
+/// Delivery may be lossy and unordered: a lost message only makes the estimates
+/// of the receivers older. [`UdpTransport`] is provided; a transport over TCP, a
+/// message bus or a service mesh only has to implement these two methods.
+pub trait GossipTransport: Send + Sync {
+    /// Sends `message` to the other nodes.
+    fn broadcast(&self, message: &GossipMessage) -> io::Result<()>;
+
+    /// Returns the messages received since the last call, without blocking.
+    fn receive(&self) -> io::Result<Vec<GossipMessage>>;
+}
+
+/// A [`GossipTransport`] sending datagrams to a fixed list of peers.
+///
+/// Each message fits in one datagram. Datagrams that are not messages are dropped.
+#[derive(Debug)]
+pub struct UdpTransport {
+    socket: UdpSocket,
+    peers: Mutex<Vec<SocketAddr>>,
+}
+
+impl UdpTransport {
+    /// Binds a socket to `addr` to gossip with `peers`.
+    ///
+    /// # Errors
+    ///
+    /// If the socket cannot be bound, or an address cannot be resolved.
+    pub fn bind<A: ToSocketAddrs>(
+        addr: impl ToSocketAddrs,
+        peers: impl IntoIterator<Item = A>,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        let mut addrs = Vec::new();
+        for peer in peers {
+            addrs.extend(peer.to_socket_addrs()?);
+        }
+        Ok(Self {
+            socket,
+            peers: Mutex::new(addrs),
+        })
+    }
+
+    /// Returns the address the socket is bound to.
+    ///
+    /// # Errors
+    ///
+    /// If the address of the socket cannot be read.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Adds `peer` to the nodes the messages are sent to.
+    pub fn add_peer(&self, peer: SocketAddr) {
+        let mut peers = self.peers.lock_unpoisoned();
+        if !peers.contains(&peer) {
+            peers.push(peer);
+        }
+    }
+}
+
+impl GossipTransport for UdpTransport {
+    fn broadcast(&self, message: &GossipMessage) -> io::Result<()> {
+        let bytes = message.encode();
+        let peers = self.peers.lock_unpoisoned().clone();
+        for peer in peers {
+            self.socket.send_to(&bytes, peer)?;
+        }
+        Ok(())
+    }
+
+    fn receive(&self) -> io::Result<Vec<GossipMessage>> {
+        let mut messages = Vec::new();
+        let mut buf = [0; 512];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, _)) => messages.extend(GossipMessage::decode(&buf[..len])),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(messages),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// An approximate cluster-wide rate limiter, for clusters without a shared store.
+///
+/// Every node counts the requests it admits in epoch-aligned windows, and the
+/// nodes periodically exchange their counts through a [`GossipTransport`]. On
+/// every exchange, a node estimates the number of active nodes from the peers it
+/// heard from recently, and grants itself its usage so far plus an equal part of
+/// the global headroom left, i.e. `limit / nodes` at the start of a window. A busy
+/// node next to idle ones thus picks up their unused capacity over a few rounds.
+///
+/// The limit is only enforced approximately:
+///
+/// - Nodes decide on counts one exchange old. When their views agree, they never
+///   exceed the limit together; when they do not, e.g. because messages were lost,
+///   they may exceed it by the requests admitted during one exchange period.
+/// - A node that has not heard from its peers yet, e.g. right after starting,
+///   counts itself alone and may admit up to the whole limit.
+/// - Peers silent for longer than the peer TTL (3 windows by default) are deemed
+///   gone, and their share is redistributed.
+///
+/// Exchanging more often, relative to the window, makes it more accurate. For an
+/// exact limit, use a [`DistributedStore`](super::DistributedStore) instead.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::{sync::Arc, time::Duration};
+/// use devkit_rl::distributed::{GossipLimiter, UdpTransport};
+///
+/// let transport = UdpTransport::bind("0.0.0.0:7946", ["10.0.0.2:7946", "10.0.0.3:7946"])?;
+/// let limiter = GossipLimiter::new("10.0.0.1", 3000, Some(Duration::from_secs(1)), Arc::new(transport));
+/// let gossip = limiter.spawn_gossip(Duration::from_millis(100));
+///
+/// if limiter.allow() {
+///     // handle the request
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct GossipLimiter {
+    inner: Arc<GossipLimiterInner>,
+}
+
+struct GossipLimiterInner {
+    node: String,
+    limit: u64,
+    interval: Duration,
+    transport: Arc<dyn GossipTransport>,
+    clock: Arc<dyn Clock>,
+    state: Mutex<GossipState>,
+}
+
+struct GossipState {
+    /// The window of `local` and `budget`.
+    window: u64,
+    /// The requests admitted by this node in the window.
+    local: u64,
+    /// The requests this node may admit in the window.
+    budget: u64,
+    peers: HashMap<String, Peer>,
+    peer_ttl: Duration,
+}
+
+struct Peer {
+    window: u64,
+    count: u64,
+    /// When the peer was last heard from.
+    seen: Duration,
+}
+
+impl GossipLimiter {
+    /// Creates a new `GossipLimiter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The id of this node, unique in the cluster.
+    /// * `limit` - The maximum number of requests allowed by all nodes within each time window.
+    /// * `interval` - Optional duration of the time window. Defaults to 1 second if not provided.
+    /// * `transport` - How the counts are exchanged with the other nodes.
+    pub fn new(
+        node: impl Into<String>,
+        limit: u64,
+        interval: Option<Duration>,
+        transport: Arc<dyn GossipTransport>,
+    ) -> Self {
+        Self::with_clock(
+            node,
+            limit,
+            interval,
+            transport,
+            Arc::new(SystemClock::new()),
+        )
+    }
+
+    /// Creates a new `GossipLimiter` reading the time from `clock`, which must count
+    /// the unix time for the windows of the nodes to line up.
+    pub fn with_clock(
+        node: impl Into<String>,
+        limit: u64,
+        interval: Option<Duration>,
+        transport: Arc<dyn GossipTransport>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let interval = interval.unwrap_or(Duration::from_secs(1));
+        let window = whole_periods(clock.now(), interval).0;
+        Self {
+            inner: Arc::new(GossipLimiterInner {
+                node: node.into(),
+                limit,
+                interval,
+                transport,
+                clock,
+                state: Mutex::new(GossipState {
+                    window,
+                    local: 0,
+                    budget: limit,
+                    peers: HashMap::new(),
+                    peer_ttl: interval.saturating_mul(3),
+                }),
+            }),
+        }
+    }
+
+    /// Sets how long a silent peer is still counted as active.
+    pub fn set_peer_ttl(&self, ttl: Duration) {
+        self.inner.state.lock_unpoisoned().peer_ttl = ttl;
+    }
+
+    /// Attempts to allow a single request.
+    pub fn allow(&self) -> bool {
+        self.allow_n(1)
+    }
+
+    /// Attempts to allow `n` requests against the budget of this node.
+    pub fn allow_n(&self, n: u64) -> bool {
+        let mut state = self.inner.lock_current();
+        match state.local.checked_add(n) {
+            Some(local) if local <= state.budget => {
+                state.local = local;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Estimates how long to wait until `n` requests are allowed.
+    ///
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if the requests fit in the budget of this node, the time
+    /// until the next window otherwise, or `Duration::MAX` if `n` exceeds the limit
+    /// and will never be allowed. The budget may also grow on the next exchange.
+    pub fn next_available(&self, n: u64) -> Duration {
+        if n > self.inner.limit {
+            return Duration::MAX;
+        }
+        let state = self.inner.lock_current();
+        if state.local.saturating_add(n) <= state.budget {
+            return Duration::ZERO;
+        }
+        let (_, offset) = whole_periods(self.inner.clock.now(), self.inner.interval);
+        self.inner.interval - offset
+    }
+
+    /// Exchanges counts with the other nodes once: broadcasts the count of this
+    /// node, then takes in the counts received and recomputes the budget.
+    ///
+    /// # Errors
+    ///
+    /// If the transport fails. The messages received before the failure are kept.
+    pub fn gossip(&self) -> io::Result<()> {
+        let inner = &self.inner;
+        let message = {
+            let state = inner.lock_current();
+            GossipMessage {
+                node: inner.node.clone(),
+                window: state.window,
+                count: state.local,
+            }
+        };
+        let sent = inner.transport.broadcast(&message);
+        let received = inner.transport.receive();
+
+        let mut state = inner.lock_current();
+        let now = inner.clock.now();
+        for message in received.as_ref().map_or(&[][..], Vec::as_slice) {
+            if message.node == inner.node {
+                continue;
+            }
+            let peer = state.peers.entry(message.node.clone()).or_insert(Peer {
+                window: message.window,
+                count: 0,
+                seen: now,
+            });
+            // messages may arrive out of order: keep the latest count
+            if message.window > peer.window {
+                peer.window = message.window;
+                peer.count = message.count;
+            } else if message.window == peer.window {
+                peer.count = peer.count.max(message.count);
+            }
+            peer.seen = now;
+        }
+        inner.rebudget(&mut state, now);
+        sent.and(received.map(drop))
+    }
+
+    /// Spawns a thread calling [`GossipLimiter::gossip`] every `period`, until the
+    /// returned [`Worker`] is shut down or dropped, or, once it is
+    /// [detached](Worker::detach), until all the clones of this limiter are dropped.
+    ///
+    /// Transport errors are ignored: the estimates only get older until the
+    /// transport recovers.
+    pub fn spawn_gossip(&self, period: Duration) -> Worker {
+        let inner: Weak<GossipLimiterInner> = Arc::downgrade(&self.inner);
+        Worker::spawn("gossip", move |stop| {
+            while let Some(inner) = inner.upgrade() {
+                let _ = GossipLimiter { inner }.gossip();
+                if !stop.sleep(period) {
+                    return;
+                }
+            }
+        })
+    }
+
+    /// Returns the number of active nodes, this one included, as estimated from the
+    /// peers heard from recently.
+    pub fn estimated_nodes(&self) -> usize {
+        self.inner.lock_current().peers.len() + 1
+    }
+
+    /// Returns the number of requests admitted by this node in the current window.
+    pub fn local_usage(&self) -> u64 {
+        self.inner.lock_current().local
+    }
+
+    /// Returns the number of requests admitted by the peers in the current window,
+    /// as last heard from them.
+    pub fn peer_usage(&self) -> u64 {
+        self.inner.lock_current().peer_usage()
+    }
+
+    /// Returns the number of requests this node may admit in the current window,
+    /// including the ones already admitted.
+    pub fn budget(&self) -> u64 {
+        self.inner.lock_current().budget
+    }
+}
+
+impl GossipLimiterInner {
+    /// Locks the state, rolled over to the current window.
+    fn lock_current(&self) -> crate::sync::MutexGuard<'_, GossipState> {
+        let mut state = self.state.lock_unpoisoned();
+        let now = self.clock.now();
+        let (window, _) = whole_periods(now, self.interval);
+        if window != state.window {
+            state.window = window;
+            state.local = 0;
+            self.rebudget(&mut state, now);
+        }
+        state
+    }
+
+    /// Drops the peers gone silent, and grants this node its usage plus an equal
+    /// part of the global headroom.
+    fn rebudget(&self, state: &mut GossipState, now: Duration) {
+        let ttl = state.peer_ttl;
+        state
+            .peers
+            .retain(|_, peer| now.saturating_sub(peer.seen) <= ttl);
+        let nodes = state.peers.len() as u64 + 1;
+        let headroom = self
+            .limit
+            .saturating_sub(state.local)
+            .saturating_sub(state.peer_usage());
+        state.budget = state.local + headroom / nodes;
+    }
+}
+
+impl GossipState {
+    fn peer_usage(&self) -> u64 {
+        self.peers
+            .values()
+            .filter(|peer| peer.window == self.window)
+            .fold(0, |sum, peer| sum.saturating_add(peer.count))
+    }
+}
+
+impl std::fmt::Debug for GossipLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GossipLimiter")
+            .field("node", &self.inner.node)
+            .field("limit", &self.inner.limit)
+            .field("interval", &self.inner.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RateLimiter for GossipLimiter {
+    fn allow_n(&self, n: u64) -> bool {
+        GossipLimiter::allow_n(self, n)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        GossipLimiter::next_available(self, n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::ManualClock;
+
+    /// An in-process transport delivering every message to every other node.
+    #[derive(Default)]
+    struct Hub {
+        inboxes: Mutex<HashMap<String, Vec<GossipMessage>>>,
+    }
+
+    struct HubTransport {
+        hub: Arc<Hub>,
+        node: String,
+    }
+
+    impl GossipTransport for HubTransport {
+        fn broadcast(&self, message: &GossipMessage) -> io::Result<()> {
+            let mut inboxes = self.hub.inboxes.lock_unpoisoned();
+            for (node, inbox) in inboxes.iter_mut() {
+                if *node != self.node {
+                    inbox.push(message.clone());
+                }
+            }
+            Ok(())
+        }
+
+        fn receive(&self) -> io::Result<Vec<GossipMessage>> {
+            let mut inboxes = self.hub.inboxes.lock_unpoisoned();
+            Ok(std::mem::take(inboxes.get_mut(&self.node).unwrap()))
+        }
+    }
+
+    fn cluster(nodes: &[&str], limit: u64, clock: &Arc<ManualClock>) -> Vec<GossipLimiter> {
+        let hub = Arc::new(Hub::default());
+        nodes
+            .iter()
+            .map(|node| {
+                hub.inboxes
+                    .lock_unpoisoned()
+                    .insert(node.to_string(), Vec::new());
+                let transport = Arc::new(HubTransport {
+                    hub: hub.clone(),
+                    node: node.to_string(),
+                });
+                GossipLimiter::with_clock(*node, limit, None, transport, clock.clone())
+            })
+            .collect()
+    }
+
+    fn round(nodes: &[GossipLimiter]) {
+        for node in nodes {
+            node.gossip().unwrap();
+        }
+    }
+
+    #[test]
+    fn gossip_limiter_should_share_the_limit_between_nodes() {
+        let clock = Arc::new(ManualClock::new());
+        clock.advance(Duration::from_secs(1_700_000_000));
+        let nodes = cluster(&["a", "b", "c"], 90, &clock);
+
+        // a node that heard from no peer counts itself alone
+        assert_eq!(nodes[0].budget(), 90);
+        round(&nodes);
+        round(&nodes);
+        assert!(nodes.iter().all(|node| node.estimated_nodes() == 3));
+        assert_eq!(nodes[0].budget(), 30);
+        assert!(nodes[0].allow_n(30));
+        assert!(!nodes[0].allow());
+        assert_eq!(nodes[0].next_available(1), Duration::from_secs(1));
+
+        // busy nodes keep picking up the headroom left
+        let mut admitted = 30;
+        for _ in 0..20 {
+            round(&nodes);
+            for node in &nodes[..2] {
+                while node.allow() {
+                    admitted += 1;
+                }
+            }
+        }
+        // the nodes gossip one after the other, so each decides on counts one
+        // exchange old, and they may slightly exceed the limit together
+        assert!((85..=95).contains(&admitted), "{admitted}");
+        assert_eq!(nodes[2].local_usage(), 0);
+        assert_eq!(nodes[2].peer_usage(), admitted);
+
+        // the budgets start over with the window
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(nodes[1].budget(), 30);
+        assert_eq!(nodes[1].peer_usage(), 0);
+
+        // silent peers are deemed gone
+        round(&nodes[..1]);
+        clock.advance(Duration::from_secs(4));
+        round(&nodes[..1]);
+        assert_eq!(nodes[0].estimated_nodes(), 1);
+        assert!(nodes[0].allow_n(90));
+    }
+
+    #[test]
+    fn gossip_over_udp_should_exchange_counts() {
+        let a = UdpTransport::bind("127.0.0.1:0", [] as [SocketAddr; 0]).unwrap();
+        let b = UdpTransport::bind("127.0.0.1:0", [a.local_addr().unwrap()]).unwrap();
+        a.add_peer(b.local_addr().unwrap());
+
+        let message = GossipMessage {
+            node: "node a".to_string(),
+            window: 7,
+            count: 3,
+        };
+        a.broadcast(&message).unwrap();
+        let mut received = Vec::new();
+        for _ in 0..100 {
+            received.extend(b.receive().unwrap());
+            if !received.is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(received, [message]);
+        assert_eq!(GossipMessage::decode(b"x 1 a"), None);
+    }
+}
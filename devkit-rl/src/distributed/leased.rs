@@ -0,0 +1,354 @@
+use std::{sync::Arc, time::Duration};
+
+use super::{current_window, DistributedStore};
+use crate::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex, MutexExt,
+    },
+    Error, RateLimiter, Worker,
+};
+
+/// A distributed rate limiter enforcing a global quota through local leases.
+///
+/// Instead of a round trip to the store for every request, each instance leases
+/// chunks of the global quota of the current window from the store and admits
+/// requests locally, at memory speed, until its chunk is used up. A new lease is
+/// requested in the background once the local tokens fall below a quarter of a
+/// lease, so the store is usually off the request path entirely.
+///
+/// The global limit is never exceeded, but it is enforced approximately: tokens
+/// leased by one instance and left unused at the end of a window are lost, so the
+/// instances together may admit fewer than `limit` requests per window. Smaller
+/// leases waste less quota at the cost of more round trips. If the store becomes
+/// unreachable, an instance keeps admitting requests from the tokens it holds and
+/// denies requests after that.
+///
+/// The renewals run on short-lived threads, waited for when the last clone of the
+/// limiter is dropped, or on [`LeasedLimiter::shutdown`].
+///
+/// # Example
+///
+/// ```
+/// use std::{sync::Arc, time::Duration};
+/// use devkit_rl::distributed::{InMemoryStore, LeasedLimiter};
+///
+/// let store = Arc::new(InMemoryStore::new());
+/// let rl = LeasedLimiter::new(store, "api", 1000, Some(Duration::from_secs(1)), 50);
+///
+/// assert!(rl.allow());
+/// ```
+#[derive(Clone)]
+pub struct LeasedLimiter {
+    inner: Arc<LeasedLimiterInner>,
+}
+
+struct LeasedLimiterInner {
+    store: Arc<dyn DistributedStore>,
+    key: String,
+    limit: u64,
+    interval: Duration,
+    lease_size: u64,
+    /// The window the local tokens belong to.
+    window: AtomicU64,
+    /// The tokens leased for the current window that are still available.
+    tokens: AtomicU64,
+    /// Whether a background lease renewal is in flight.
+    renewing: AtomicBool,
+    /// The thread of the last background renewal.
+    renewal: Mutex<Option<Worker>>,
+    /// Whether the background renewals are shut down.
+    shut_down: AtomicBool,
+    /// Serializes window roll-overs and lease grants.
+    lease_lock: Mutex<()>,
+}
+
+impl LeasedLimiter {
+    /// Creates a new `LeasedLimiter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store coordinating the leases.
+    /// * `key` - The prefix of the keys used in the store; instances with the same key share a quota.
+    /// * `limit` - The maximum number of requests allowed by all instances within each time window.
+    /// * `interval` - Optional duration of the time window. Defaults to 1 second if not provided.
+    /// * `lease_size` - The number of tokens leased from the store at once.
+    pub fn new(
+        store: Arc<dyn DistributedStore>,
+        key: impl Into<String>,
+        limit: u64,
+        interval: Option<Duration>,
+        lease_size: u64,
+    ) -> Self {
+        Self {
+            inner: Arc::new(LeasedLimiterInner {
+                store,
+                key: key.into(),
+                limit,
+                interval: interval.unwrap_or(Duration::from_secs(1)),
+                lease_size: lease_size.max(1),
+                window: AtomicU64::new(u64::MAX),
+                tokens: AtomicU64::new(0),
+                renewing: AtomicBool::new(false),
+                renewal: Mutex::new(None),
+                shut_down: AtomicBool::new(false),
+                lease_lock: Mutex::new(()),
+            }),
+        }
+    }
+
+    /// Attempts to allow a single request.
+    pub fn allow(&self) -> bool {
+        self.allow_n(1)
+    }
+
+    /// Attempts to allow `n` requests.
+    ///
+    /// This only talks to the store synchronously at the start of a window, or
+    /// when the background renewal could not keep up with the request rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of requests to allow.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the requests are allowed, `false` if they exceed the limit.
+    pub fn allow_n(&self, n: u64) -> bool {
+        let inner = &self.inner;
+        let (index, _) = current_window(inner.interval);
+        let index = index as u64;
+
+        if inner.window.load(Ordering::Acquire) != index {
+            inner.roll_over(index);
+        }
+
+        if inner.take(n) || (inner.lease(index, n) && inner.take(n)) {
+            if inner.tokens.load(Ordering::Relaxed) < inner.lease_size / 4 {
+                self.renew_in_background(index);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Estimates how long to wait until `n` requests are allowed.
+    ///
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if the requests can be served from the local tokens or a new
+    /// lease, the time until the next window otherwise, or `Duration::MAX` if `n`
+    /// exceeds the limit and will never be allowed.
+    pub fn next_available(&self, n: u64) -> Duration {
+        let inner = &self.inner;
+        if n > inner.limit {
+            return Duration::MAX;
+        }
+
+        let (index, offset) = current_window(inner.interval);
+        if inner.window.load(Ordering::Acquire) == index as u64
+            && inner.tokens.load(Ordering::Acquire) >= n
+        {
+            return Duration::ZERO;
+        }
+
+        // the quota not yet leased by any instance
+        let leased = inner
+            .store
+            .get(&format!("{}:{}", inner.key, index))
+            .map(|c| c.map_or(0, |c| c.value));
+        match leased {
+            Ok(leased) if inner.limit.saturating_sub(leased) >= n => Duration::ZERO,
+            _ => inner.interval - offset,
+        }
+    }
+
+    /// Stops renewing leases in the background, waiting at most `timeout` for a
+    /// renewal in flight to finish.
+    ///
+    /// The limiter keeps working: leases are then only requested when the local
+    /// tokens run out, on the request path.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Timeout`] if the renewal in flight has not finished within `timeout`.
+    pub fn shutdown(&self, timeout: Duration) -> Result<(), Error> {
+        let renewal = {
+            let mut renewal = self.inner.renewal.lock_unpoisoned();
+            self.inner.shut_down.store(true, Ordering::Release);
+            renewal.take()
+        };
+        renewal.map_or(Ok(()), |renewal| renewal.shutdown(timeout))
+    }
+
+    /// Requests a new lease for window `index` on a background thread, unless a
+    /// renewal is already in flight, or the renewals are shut down.
+    fn renew_in_background(&self, index: u64) {
+        if self.inner.renewing.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let mut renewal = self.inner.renewal.lock_unpoisoned();
+        if self.inner.shut_down.load(Ordering::Acquire) {
+            return;
+        }
+        // the thread only holds a weak reference, so that the last clone of the
+        // limiter is not dropped on it
+        let inner = Arc::downgrade(&self.inner);
+        *renewal = Some(Worker::spawn("lease-renewal", move |_| {
+            if let Some(inner) = inner.upgrade() {
+                inner.lease(index, 0);
+                inner.renewing.store(false, Ordering::Release);
+            }
+        }));
+    }
+}
+
+impl LeasedLimiterInner {
+    /// Takes `n` tokens from the local lease.
+    fn take(&self, n: u64) -> bool {
+        self.tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |tokens| {
+                tokens.checked_sub(n)
+            })
+            .is_ok()
+    }
+
+    /// Drops the tokens of the previous window and leases tokens for window `index`.
+    fn roll_over(&self, index: u64) {
+        let _guard = self.lease_lock.lock_unpoisoned();
+        if self.window.load(Ordering::Acquire) == index {
+            // another thread rolled over already
+            return;
+        }
+        self.tokens.store(0, Ordering::Release);
+        self.window.store(index, Ordering::Release);
+        self.grant(index, 0);
+    }
+
+    /// Leases tokens for window `index`, enough for at least `n` requests.
+    ///
+    /// # Returns
+    ///
+    /// `true` if any tokens were granted.
+    fn lease(&self, index: u64, n: u64) -> bool {
+        let _guard = self.lease_lock.lock_unpoisoned();
+        if self.window.load(Ordering::Acquire) != index {
+            // the window ended while waiting, its lease would be wasted
+            return false;
+        }
+        self.grant(index, n)
+    }
+
+    /// Claims a chunk of the global quota of window `index` from the store and adds
+    /// the granted part to the local tokens. Must be called with `lease_lock` held.
+    fn grant(&self, index: u64, n: u64) -> bool {
+        let chunk = self.lease_size.max(n);
+        let key = format!("{}:{}", self.key, index);
+        let Ok(total) = self.store.incr(&key, chunk, self.interval) else {
+            return false;
+        };
+
+        // the part of the chunk still below the global limit
+        let granted = self.limit.saturating_sub(total - chunk).min(chunk);
+        self.tokens.fetch_add(granted, Ordering::AcqRel);
+        granted > 0
+    }
+}
+
+impl std::fmt::Debug for LeasedLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LeasedLimiter")
+            .field("key", &self.inner.key)
+            .field("limit", &self.inner.limit)
+            .field("interval", &self.inner.interval)
+            .field("lease_size", &self.inner.lease_size)
+            .field("tokens", &self.inner.tokens.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl RateLimiter for LeasedLimiter {
+    fn allow_n(&self, n: u64) -> bool {
+        LeasedLimiter::allow_n(self, n)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        LeasedLimiter::next_available(self, n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::distributed::InMemoryStore;
+
+    use super::*;
+
+    #[cfg(loom)]
+    #[test]
+    fn loom_leased_limiter_should_not_overdraw_tokens() {
+        loom::model(|| {
+            let store = Arc::new(InMemoryStore::new());
+            let rl = LeasedLimiter::new(store, "loom", 3, Some(Duration::from_secs(60)), 3);
+
+            // a lease is granted while two requests take tokens
+            let granter = {
+                let inner = rl.inner.clone();
+                loom::thread::spawn(move || inner.grant(0, 0))
+            };
+            let taker = {
+                let inner = rl.inner.clone();
+                loom::thread::spawn(move || inner.take(2))
+            };
+            let took = rl.inner.take(2);
+
+            assert!(granter.join().unwrap());
+            let taken = 2 * (u64::from(took) + u64::from(taker.join().unwrap()));
+            assert!(taken <= 2);
+            assert_eq!(taken + rl.inner.tokens.load(Ordering::Acquire), 3);
+        });
+    }
+
+    #[test]
+    fn leased_limiter_should_stop_renewing_leases_on_shutdown() {
+        let store = Arc::new(InMemoryStore::new());
+        let rl = LeasedLimiter::new(store, "shutdown", 100, Some(Duration::from_secs(60)), 10);
+
+        assert!(rl.allow_n(9));
+        assert!(rl.shutdown(Duration::from_secs(1)).is_ok());
+        assert!(rl.inner.renewal.lock_unpoisoned().is_none());
+
+        // leases are still requested on the request path
+        assert!(rl.allow_n(10));
+        assert!(rl.inner.renewal.lock_unpoisoned().is_none());
+    }
+
+    #[test]
+    fn leased_limiter_should_never_exceed_global_limit() {
+        const LIMIT: u64 = 100;
+        const INTERVAL: Duration = Duration::from_secs(1);
+
+        // wait for the start of a window, so the test does not straddle a boundary
+        let (_, offset) = current_window(INTERVAL);
+        thread::sleep(INTERVAL - offset);
+
+        let store = Arc::new(InMemoryStore::new());
+        let a = LeasedLimiter::new(store.clone(), "test", LIMIT, Some(INTERVAL), 10);
+        let b = LeasedLimiter::new(store, "test", LIMIT, Some(INTERVAL), 10);
+
+        let mut admitted = 0;
+        loop {
+            let (x, y) = (a.allow(), b.allow());
+            if !x && !y {
+                break;
+            }
+            admitted += u64::from(x) + u64::from(y);
+        }
+
+        // both instances draw from the same global quota until it is exhausted
+        assert_eq!(admitted, LIMIT);
+    }
+}
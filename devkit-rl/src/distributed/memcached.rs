@@ -0,0 +1,227 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use super::{DistributedStore, StoreError, Versioned};
+use crate::sync::{Mutex, MutexExt};
+
+/// Memcached treats expiration times above 30 days as absolute unix timestamps.
+const MAX_RELATIVE_EXPIRY_SECS: u64 = 60 * 60 * 24 * 30;
+
+/// A [`DistributedStore`] backed by a memcached server.
+///
+/// The store speaks the memcached text protocol over a single TCP connection,
+/// using `gets`/`cas`/`add` for versioned updates, `incr` for increments and
+/// `touch` for expiration. Memcached expirations have a resolution of one second,
+/// so TTLs are rounded up to whole seconds.
+#[derive(Debug)]
+pub struct MemcachedStore {
+    conn: Mutex<BufReader<TcpStream>>,
+}
+
+impl MemcachedStore {
+    /// Connects to the memcached server at `addr`.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address of the memcached server, e.g. `"127.0.0.1:11211"`.
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, StoreError> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            conn: Mutex::new(BufReader::new(stream)),
+        })
+    }
+
+    /// Sends `request` and returns the first line of the response.
+    fn request(conn: &mut BufReader<TcpStream>, request: &str) -> Result<String, StoreError> {
+        conn.get_mut().write_all(request.as_bytes())?;
+        read_line(conn)
+    }
+}
+
+impl DistributedStore for MemcachedStore {
+    fn get(&self, key: &str) -> Result<Option<Versioned>, StoreError> {
+        let mut conn = self.conn.lock_unpoisoned();
+
+        let line = Self::request(&mut conn, &format!("gets {key}\r\n"))?;
+        if line == "END" {
+            return Ok(None);
+        }
+
+        // VALUE <key> <flags> <bytes> <cas unique>
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let [_, _, _, bytes, cas] = parts[..] else {
+            return Err(StoreError::Protocol(line));
+        };
+        let bytes: usize = parse(bytes)?;
+        let version = parse(cas)?;
+
+        let mut data = vec![0; bytes + 2];
+        conn.read_exact(&mut data)?;
+        let value = parse(String::from_utf8_lossy(&data[..bytes]).trim())?;
+
+        let end = read_line(&mut conn)?;
+        if end != "END" {
+            return Err(StoreError::Protocol(end));
+        }
+        Ok(Some(Versioned { value, version }))
+    }
+
+    fn incr(&self, key: &str, delta: u64, ttl: Duration) -> Result<u64, StoreError> {
+        let mut conn = self.conn.lock_unpoisoned();
+        loop {
+            let line = Self::request(&mut conn, &format!("incr {key} {delta}\r\n"))?;
+            if line != "NOT_FOUND" {
+                return parse(&line);
+            }
+
+            // the key does not exist yet, create it unless another client won the race
+            let line = Self::request(
+                &mut conn,
+                &format!(
+                    "add {key} 0 {} {}\r\n{delta}\r\n",
+                    expiry(ttl),
+                    digits(delta)
+                ),
+            )?;
+            match line.as_str() {
+                "STORED" => return Ok(delta),
+                "NOT_STORED" => continue,
+                _ => return Err(StoreError::Protocol(line)),
+            }
+        }
+    }
+
+    fn decr(&self, key: &str, delta: u64, _ttl: Duration) -> Result<(), StoreError> {
+        let mut conn = self.conn.lock_unpoisoned();
+        // memcached decrements saturate at 0 and keep the expiry
+        let line = Self::request(&mut conn, &format!("decr {key} {delta}\r\n"))?;
+        if line == "NOT_FOUND" {
+            return Ok(());
+        }
+        parse::<u64>(&line).map(drop)
+    }
+
+    fn expire(&self, key: &str, ttl: Duration) -> Result<(), StoreError> {
+        let mut conn = self.conn.lock_unpoisoned();
+        let line = Self::request(&mut conn, &format!("touch {key} {}\r\n", expiry(ttl)))?;
+        match line.as_str() {
+            "TOUCHED" | "NOT_FOUND" => Ok(()),
+            _ => Err(StoreError::Protocol(line)),
+        }
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        version: Option<u64>,
+        value: u64,
+        ttl: Duration,
+    ) -> Result<bool, StoreError> {
+        let mut conn = self.conn.lock_unpoisoned();
+        let header = match version {
+            Some(cas) => format!("cas {key} 0 {} {} {cas}", expiry(ttl), digits(value)),
+            None => format!("add {key} 0 {} {}", expiry(ttl), digits(value)),
+        };
+        let line = Self::request(&mut conn, &format!("{header}\r\n{value}\r\n"))?;
+        match line.as_str() {
+            "STORED" => Ok(true),
+            "EXISTS" | "NOT_FOUND" | "NOT_STORED" => Ok(false),
+            _ => Err(StoreError::Protocol(line)),
+        }
+    }
+}
+
+/// Reads a single `\r\n` terminated line, failing on server errors.
+fn read_line(conn: &mut BufReader<TcpStream>) -> Result<String, StoreError> {
+    let mut line = String::new();
+    if conn.read_line(&mut line)? == 0 {
+        return Err(StoreError::Protocol("connection closed".to_string()));
+    }
+    let line = line.trim_end().to_string();
+    if line == "ERROR" || line.starts_with("CLIENT_ERROR") || line.starts_with("SERVER_ERROR") {
+        return Err(StoreError::Protocol(line));
+    }
+    Ok(line)
+}
+
+/// Converts `ttl` to a memcached expiration time, rounding up to whole seconds.
+fn expiry(ttl: Duration) -> u64 {
+    let secs = ttl.as_secs() + u64::from(ttl.subsec_nanos() > 0);
+    secs.clamp(1, MAX_RELATIVE_EXPIRY_SECS)
+}
+
+/// Returns the number of bytes of the decimal representation of `n`.
+fn digits(n: u64) -> usize {
+    n.checked_ilog10().unwrap_or(0) as usize + 1
+}
+
+fn parse<T: std::str::FromStr>(s: &str) -> Result<T, StoreError> {
+    s.parse()
+        .map_err(|_| StoreError::Protocol(format!("unexpected value {s:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::BufRead, net::TcpListener, thread};
+
+    use super::*;
+
+    /// Serves one connection, answering each request in `script` with its response.
+    fn serve(script: &'static [(&'static str, &'static str)]) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            for (request, response) in script {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                assert_eq!(line.trim_end(), *request);
+                if request.starts_with("add") || request.starts_with("cas") {
+                    // the data block of a storage command
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                }
+                writer.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn memcached_store_should_speak_text_protocol() {
+        let addr = serve(&[
+            ("gets k", "END\r\n"),
+            ("add k 0 2 1", "STORED\r\n"),
+            ("gets k", "VALUE k 0 1 42\r\n3\r\nEND\r\n"),
+            ("cas k 0 2 1 42", "EXISTS\r\n"),
+            ("incr k 2", "NOT_FOUND\r\n"),
+            ("add k 0 1 1", "STORED\r\n"),
+            ("touch k 1", "TOUCHED\r\n"),
+            ("decr k 5", "0\r\n"),
+            ("decr j 1", "NOT_FOUND\r\n"),
+        ]);
+        let store = MemcachedStore::connect(addr).unwrap();
+        let ttl = Duration::from_millis(1500);
+
+        assert_eq!(store.get("k").unwrap(), None);
+        assert!(store.compare_and_swap("k", None, 3, ttl).unwrap());
+        assert_eq!(
+            store.get("k").unwrap(),
+            Some(Versioned {
+                value: 3,
+                version: 42
+            })
+        );
+        assert!(!store.compare_and_swap("k", Some(42), 4, ttl).unwrap());
+        assert_eq!(store.incr("k", 2, Duration::from_secs(1)).unwrap(), 2);
+        store.expire("k", Duration::from_millis(1)).unwrap();
+        store.decr("k", 5, ttl).unwrap();
+        store.decr("j", 1, ttl).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::{DistributedStore, StoreError, Versioned};
+use crate::sync::{Mutex, MutexExt};
+
+/// A [`DistributedStore`] kept in the memory of the current process.
+///
+/// It is mostly useful for tests, and for sharing one quota between limiters of
+/// the same process through the distributed windows.
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    value: u64,
+    version: u64,
+    expires_at: Instant,
+}
+
+impl InMemoryStore {
+    /// Creates a new, empty `InMemoryStore`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` on the entries, after dropping the expired ones.
+    fn with_entries<T>(&self, f: impl FnOnce(&mut HashMap<String, Entry>) -> T) -> T {
+        let mut entries = self.entries.lock_unpoisoned();
+        let now = Instant::now();
+        entries.retain(|_, e| e.expires_at > now);
+        f(&mut entries)
+    }
+}
+
+impl DistributedStore for InMemoryStore {
+    fn get(&self, key: &str) -> Result<Option<Versioned>, StoreError> {
+        Ok(self.with_entries(|entries| {
+            entries.get(key).map(|e| Versioned {
+                value: e.value,
+                version: e.version,
+            })
+        }))
+    }
+
+    fn incr(&self, key: &str, delta: u64, ttl: Duration) -> Result<u64, StoreError> {
+        Ok(self.with_entries(|entries| {
+            let entry = entries.entry(key.to_string()).or_insert_with(|| Entry {
+                value: 0,
+                version: 0,
+                expires_at: Instant::now() + ttl,
+            });
+            entry.value = entry.value.saturating_add(delta);
+            entry.version += 1;
+            entry.value
+        }))
+    }
+
+    fn decr(&self, key: &str, delta: u64, _ttl: Duration) -> Result<(), StoreError> {
+        self.with_entries(|entries| {
+            if let Some(entry) = entries.get_mut(key) {
+                entry.value = entry.value.saturating_sub(delta);
+                entry.version += 1;
+            }
+        });
+        Ok(())
+    }
+
+    fn expire(&self, key: &str, ttl: Duration) -> Result<(), StoreError> {
+        self.with_entries(|entries| {
+            if let Some(entry) = entries.get_mut(key) {
+                entry.expires_at = Instant::now() + ttl;
+            }
+        });
+        Ok(())
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        version: Option<u64>,
+        value: u64,
+        ttl: Duration,
+    ) -> Result<bool, StoreError> {
+        Ok(self.with_entries(|entries| {
+            let current = entries.get(key).map(|e| e.version);
+            if current != version {
+                return false;
+            }
+            entries.insert(
+                key.to_string(),
+                Entry {
+                    value,
+                    version: current.map_or(1, |v| v + 1),
+                    expires_at: Instant::now() + ttl,
+                },
+            );
+            true
+        }))
+    }
+}
@@ -0,0 +1,226 @@
+//! Rate limiters whose state lives in a store shared by many instances.
+//!
+//! The windows in this module keep their counters in a [`DistributedStore`], so
+//! every process talking to the same store enforces one shared quota. Windows are
+//! aligned to the unix epoch, which makes window boundaries agree across hosts.
+//!
+//! [`FileStore`] keeps the counters in a local file instead, so that the quota of a
+//! single process survives its restarts.
+//!
+//! [`ShmTokenBucket`] keeps a token bucket in shared memory instead, so that the
+//! processes of one host share a limit without a network hop (`shm` feature, unix).
+//!
+//! [`GossipLimiter`] needs no store at all: the nodes exchange their counts over a
+//! pluggable transport, e.g. UDP, and each enforces its part of the limit.
+//!
+//! [`LeasedLimiter`] trades exactness for speed: it leases chunks of the global
+//! quota and enforces them locally, keeping the store off the request path.
+
+#[cfg(feature = "etcd")]
+mod etcd;
+mod file;
+mod fixed_window;
+mod gossip;
+mod leased;
+#[cfg(feature = "memcached")]
+mod memcached;
+mod memory;
+#[cfg(feature = "redis")]
+mod redis;
+#[cfg(all(feature = "shm", unix))]
+mod shm;
+mod sliding_window;
+
+use std::{
+    fmt, io,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "etcd")]
+pub use etcd::EtcdStore;
+pub use file::FileStore;
+pub use fixed_window::DistributedFixedWindow;
+pub use gossip::{GossipLimiter, GossipMessage, GossipTransport, UdpTransport};
+pub use leased::LeasedLimiter;
+#[cfg(feature = "memcached")]
+pub use memcached::MemcachedStore;
+pub use memory::InMemoryStore;
+#[cfg(feature = "redis")]
+pub use redis::RedisStore;
+#[cfg(all(feature = "shm", unix))]
+pub use shm::ShmTokenBucket;
+pub use sliding_window::DistributedSlidingWindow;
+
+/// The maximum number of compare-and-swap attempts before giving up on an update.
+const MAX_CAS_ATTEMPTS: usize = 16;
+
+/// A counter value read from a [`DistributedStore`], together with its version.
+///
+/// The version is an opaque token that changes on every write, used for
+/// compare-and-swap updates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Versioned {
+    /// The value of the counter.
+    pub value: u64,
+    /// The version of the counter.
+    pub version: u64,
+}
+
+/// A key-value store holding counters shared between rate limiter instances.
+///
+/// Implementations must be safe to call from many threads and many processes at
+/// once: `incr` and `compare_and_swap` have to be atomic on the store side.
+pub trait DistributedStore: Send + Sync {
+    /// Reads the counter stored under `key`.
+    ///
+    /// # Returns
+    ///
+    /// The counter and its version, or `None` if the key does not exist or has expired.
+    fn get(&self, key: &str) -> Result<Option<Versioned>, StoreError>;
+
+    /// Atomically adds `delta` to the counter stored under `key`.
+    ///
+    /// If the key does not exist it is created with the value `delta` and expires
+    /// after `ttl`. The expiry of an existing key is left unchanged.
+    ///
+    /// # Returns
+    ///
+    /// The value of the counter after the increment.
+    fn incr(&self, key: &str, delta: u64, ttl: Duration) -> Result<u64, StoreError>;
+
+    /// Sets the counter stored under `key` to expire after `ttl`.
+    fn expire(&self, key: &str, ttl: Duration) -> Result<(), StoreError>;
+
+    /// Atomically subtracts `delta` from the counter stored under `key`, saturating
+    /// at 0, e.g. to roll back an increment that went over a limit.
+    ///
+    /// Nothing is done if the key does not exist. The default implementation retries
+    /// compare-and-swap updates, which set the counter to expire after `ttl`; stores
+    /// with a native decrement keep its expiry instead.
+    fn decr(&self, key: &str, delta: u64, ttl: Duration) -> Result<(), StoreError> {
+        for _ in 0..MAX_CAS_ATTEMPTS {
+            let Some(current) = self.get(key)? else {
+                return Ok(());
+            };
+            let value = current.value.saturating_sub(delta);
+            if self.compare_and_swap(key, Some(current.version), value, ttl)? {
+                return Ok(());
+            }
+        }
+        Err(StoreError::Conflict)
+    }
+
+    /// Atomically adds `delta` to the counter stored under `key` if its new value
+    /// does not exceed `limit`, creating the key like [`DistributedStore::incr`].
+    ///
+    /// The default implementation increments the counter and rolls the increment
+    /// back with [`DistributedStore::decr`] if it went over `limit`. A rollback that
+    /// fails leaves the counter over-counted until it expires, but never turns the
+    /// denial into an admission. Stores with server-side scripting check and
+    /// increment in a single round trip instead.
+    ///
+    /// # Returns
+    ///
+    /// `true` if `delta` was added, `false` if it would have exceeded `limit`.
+    fn incr_within(
+        &self,
+        key: &str,
+        delta: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<bool, StoreError> {
+        if self.incr(key, delta, ttl)? <= limit {
+            return Ok(true);
+        }
+        let _ = self.decr(key, delta, ttl);
+        Ok(false)
+    }
+
+    /// Stores `value` under `key` if the counter is still at `version`.
+    ///
+    /// A `version` of `None` means the key must not exist yet. The stored value
+    /// expires after `ttl`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the value was stored, `false` if the counter was changed concurrently.
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        version: Option<u64>,
+        value: u64,
+        ttl: Duration,
+    ) -> Result<bool, StoreError>;
+}
+
+/// Errors reported by a [`DistributedStore`].
+#[derive(Debug)]
+pub enum StoreError {
+    /// Communicating with the store failed.
+    Io(io::Error),
+    /// The store answered with something unexpected.
+    Protocol(String),
+    /// A compare-and-swap update kept losing against concurrent writers.
+    Conflict,
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io(e) => write!(f, "store I/O error: {e}"),
+            StoreError::Protocol(msg) => write!(f, "store protocol error: {msg}"),
+            StoreError::Conflict => write!(f, "too many concurrent updates"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoreError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+/// Returns the index of the epoch-aligned window containing the current time,
+/// together with how far into that window the current time is.
+fn current_window(interval: Duration) -> (u128, Duration) {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let interval_nanos = interval.as_nanos().max(1);
+    let index = since_epoch.as_nanos() / interval_nanos;
+    let offset = since_epoch.as_nanos() % interval_nanos;
+    (index, Duration::from_nanos(offset as u64))
+}
+
+/// Adds `n` to the counter under `key` if the result does not exceed `limit`.
+///
+/// `limit` is computed from the current counter value by the caller, which lets
+/// the sliding window account for the previous window as well.
+fn cas_add(
+    store: &dyn DistributedStore,
+    key: &str,
+    n: u64,
+    ttl: Duration,
+    limit: impl Fn(u64) -> bool,
+) -> Result<bool, StoreError> {
+    for _ in 0..MAX_CAS_ATTEMPTS {
+        let current = store.get(key)?;
+        let value = current.map_or(0, |c| c.value);
+        if !limit(value.saturating_add(n)) {
+            return Ok(false);
+        }
+        if store.compare_and_swap(key, current.map(|c| c.version), value + n, ttl)? {
+            return Ok(true);
+        }
+    }
+    Err(StoreError::Conflict)
+}
@@ -0,0 +1,297 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use super::{DistributedStore, StoreError, Versioned};
+use crate::sync::{Mutex, MutexExt};
+
+/// Increments the counter, setting its expiry only if it has none yet.
+///
+/// `KEYS[1]` is the counter, `ARGV[1]` the delta and `ARGV[2]` the TTL in milliseconds.
+const INCR_SCRIPT: &str = "\
+local v = redis.call('HINCRBY', KEYS[1], 'v', ARGV[1])
+redis.call('HINCRBY', KEYS[1], 'ver', 1)
+if redis.call('PTTL', KEYS[1]) < 0 then redis.call('PEXPIRE', KEYS[1], ARGV[2]) end
+return v";
+
+/// Increments the counter like `INCR_SCRIPT` if its new value does not exceed the
+/// limit, returning 1, or leaves it untouched and returns 0.
+///
+/// `KEYS[1]` is the counter, `ARGV[1]` the delta, `ARGV[2]` the limit and `ARGV[3]`
+/// the TTL in milliseconds.
+const INCR_WITHIN_SCRIPT: &str = "\
+local v = tonumber(redis.call('HGET', KEYS[1], 'v') or '0')
+if v + tonumber(ARGV[1]) > tonumber(ARGV[2]) then return 0 end
+redis.call('HINCRBY', KEYS[1], 'v', ARGV[1])
+redis.call('HINCRBY', KEYS[1], 'ver', 1)
+if redis.call('PTTL', KEYS[1]) < 0 then redis.call('PEXPIRE', KEYS[1], ARGV[3]) end
+return 1";
+
+/// Decrements the counter, saturating at 0, if it exists, keeping its expiry.
+///
+/// `KEYS[1]` is the counter and `ARGV[1]` the delta.
+const DECR_SCRIPT: &str = "\
+local v = redis.call('HGET', KEYS[1], 'v')
+if not v then return 0 end
+redis.call('HSET', KEYS[1], 'v', math.max(tonumber(v) - tonumber(ARGV[1]), 0))
+redis.call('HINCRBY', KEYS[1], 'ver', 1)
+return 1";
+
+/// Stores the counter if its version is still `ARGV[1]`, empty meaning absent.
+///
+/// `KEYS[1]` is the counter, `ARGV[2]` the new value and `ARGV[3]` the TTL in milliseconds.
+const CAS_SCRIPT: &str = "\
+local ver = redis.call('HGET', KEYS[1], 'ver')
+if (ARGV[1] == '' and ver) or (ARGV[1] ~= '' and ver ~= ARGV[1]) then return 0 end
+redis.call('HSET', KEYS[1], 'v', ARGV[2], 'ver', (tonumber(ver) or 0) + 1)
+redis.call('PEXPIRE', KEYS[1], ARGV[3])
+return 1";
+
+/// A [`DistributedStore`] backed by a Redis server.
+///
+/// The store speaks RESP over a single TCP connection. Every counter is a hash
+/// holding its value and version, updated atomically by small Lua scripts, so the
+/// server must allow `EVAL`. TTLs have a resolution of one millisecond.
+#[derive(Debug)]
+pub struct RedisStore {
+    conn: Mutex<BufReader<TcpStream>>,
+}
+
+/// A reply read from the Redis server.
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    Simple(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Reply>>),
+}
+
+impl RedisStore {
+    /// Connects to the Redis server at `addr`.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address of the Redis server, e.g. `"127.0.0.1:6379"`.
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, StoreError> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            conn: Mutex::new(BufReader::new(stream)),
+        })
+    }
+
+    /// Sends the command made of `args` and reads its reply.
+    fn request(&self, args: &[&str]) -> Result<Reply, StoreError> {
+        let mut command = format!("*{}\r\n", args.len());
+        for arg in args {
+            command.push_str(&format!("${}\r\n{arg}\r\n", arg.len()));
+        }
+
+        let mut conn = self.conn.lock_unpoisoned();
+        conn.get_mut().write_all(command.as_bytes())?;
+        read_reply(&mut conn)
+    }
+}
+
+impl DistributedStore for RedisStore {
+    fn get(&self, key: &str) -> Result<Option<Versioned>, StoreError> {
+        match self.request(&["HMGET", key, "v", "ver"])? {
+            Reply::Array(Some(fields)) => match &fields[..] {
+                [Reply::Bulk(Some(value)), Reply::Bulk(Some(version))] => Ok(Some(Versioned {
+                    value: parse(value)?,
+                    version: parse(version)?,
+                })),
+                [Reply::Bulk(None), Reply::Bulk(None)] => Ok(None),
+                _ => Err(unexpected(Reply::Array(Some(fields)))),
+            },
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    fn incr(&self, key: &str, delta: u64, ttl: Duration) -> Result<u64, StoreError> {
+        let delta = delta.to_string();
+        let ttl = millis(ttl).to_string();
+        match self.request(&["EVAL", INCR_SCRIPT, "1", key, &delta, &ttl])? {
+            Reply::Integer(v) => u64::try_from(v).map_err(|_| unexpected(Reply::Integer(v))),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    fn expire(&self, key: &str, ttl: Duration) -> Result<(), StoreError> {
+        match self.request(&["PEXPIRE", key, &millis(ttl).to_string()])? {
+            Reply::Integer(_) => Ok(()),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    fn decr(&self, key: &str, delta: u64, _ttl: Duration) -> Result<(), StoreError> {
+        match self.request(&["EVAL", DECR_SCRIPT, "1", key, &delta.to_string()])? {
+            Reply::Integer(_) => Ok(()),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    fn incr_within(
+        &self,
+        key: &str,
+        delta: u64,
+        limit: u64,
+        ttl: Duration,
+    ) -> Result<bool, StoreError> {
+        let delta = delta.to_string();
+        let limit = limit.to_string();
+        let ttl = millis(ttl).to_string();
+        match self.request(&["EVAL", INCR_WITHIN_SCRIPT, "1", key, &delta, &limit, &ttl])? {
+            Reply::Integer(stored) => Ok(stored == 1),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        version: Option<u64>,
+        value: u64,
+        ttl: Duration,
+    ) -> Result<bool, StoreError> {
+        let version = version.map(|v| v.to_string()).unwrap_or_default();
+        let value = value.to_string();
+        let ttl = millis(ttl).to_string();
+        match self.request(&["EVAL", CAS_SCRIPT, "1", key, &version, &value, &ttl])? {
+            Reply::Integer(stored) => Ok(stored == 1),
+            reply => Err(unexpected(reply)),
+        }
+    }
+}
+
+/// Reads one reply, failing on server errors.
+fn read_reply(conn: &mut BufReader<TcpStream>) -> Result<Reply, StoreError> {
+    let mut line = String::new();
+    if conn.read_line(&mut line)? == 0 {
+        return Err(StoreError::Protocol("connection closed".to_string()));
+    }
+    let line = line.trim_end();
+    let (kind, rest) = line.split_at(line.len().min(1));
+
+    match kind {
+        "+" => Ok(Reply::Simple(rest.to_string())),
+        "-" => Err(StoreError::Protocol(rest.to_string())),
+        ":" => parse(rest.as_bytes()).map(Reply::Integer),
+        "$" => {
+            let Ok(len) = usize::try_from(parse::<i64>(rest.as_bytes())?) else {
+                return Ok(Reply::Bulk(None));
+            };
+            let mut data = vec![0; len + 2];
+            conn.read_exact(&mut data)?;
+            data.truncate(len);
+            Ok(Reply::Bulk(Some(data)))
+        }
+        "*" => {
+            let Ok(len) = usize::try_from(parse::<i64>(rest.as_bytes())?) else {
+                return Ok(Reply::Array(None));
+            };
+            let items = (0..len)
+                .map(|_| read_reply(conn))
+                .collect::<Result<_, _>>()?;
+            Ok(Reply::Array(Some(items)))
+        }
+        _ => Err(StoreError::Protocol(line.to_string())),
+    }
+}
+
+/// Converts `ttl` to whole milliseconds, rounding up as Redis rejects a TTL of 0.
+fn millis(ttl: Duration) -> u128 {
+    ttl.as_nanos().div_ceil(1_000_000).max(1)
+}
+
+fn unexpected(reply: Reply) -> StoreError {
+    StoreError::Protocol(format!("unexpected reply {reply:?}"))
+}
+
+fn parse<T: std::str::FromStr>(s: &[u8]) -> Result<T, StoreError> {
+    let s = String::from_utf8_lossy(s);
+    s.parse()
+        .map_err(|_| StoreError::Protocol(format!("unexpected value {s:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+
+    /// Serves one connection, answering each command in `script` with its response.
+    ///
+    /// Commands are matched on their first two arguments, leaving out the scripts.
+    fn serve(script: &'static [(&'static str, &'static str)]) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            for (request, response) in script {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let len: usize = line.trim_end()[1..].parse().unwrap();
+
+                let mut args = Vec::new();
+                for _ in 0..len {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let mut arg = vec![0; line.trim_end()[1..].parse::<usize>().unwrap() + 2];
+                    reader.read_exact(&mut arg).unwrap();
+                    arg.truncate(arg.len() - 2);
+                    args.push(String::from_utf8(arg).unwrap());
+                }
+                if args[0] == "EVAL" {
+                    args.drain(1..3);
+                }
+                assert_eq!(args.join(" "), *request);
+                writer.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn redis_store_should_speak_resp() {
+        let addr = serve(&[
+            ("HMGET k v ver", "*2\r\n$-1\r\n$-1\r\n"),
+            ("EVAL k  3 1500", ":1\r\n"),
+            ("HMGET k v ver", "*2\r\n$1\r\n3\r\n$2\r\n42\r\n"),
+            ("EVAL k 42 4 1500", ":0\r\n"),
+            ("EVAL k 2 1000", ":5\r\n"),
+            ("PEXPIRE k 1", ":1\r\n"),
+            ("PEXPIRE k 1", "-ERR wrong\r\n"),
+            ("EVAL k 2 6 1000", ":1\r\n"),
+            ("EVAL k 2 6 1000", ":0\r\n"),
+            ("EVAL k 3", ":1\r\n"),
+        ]);
+        let store = RedisStore::connect(addr).unwrap();
+        let ttl = Duration::from_millis(1500);
+
+        assert_eq!(store.get("k").unwrap(), None);
+        assert!(store.compare_and_swap("k", None, 3, ttl).unwrap());
+        assert_eq!(
+            store.get("k").unwrap(),
+            Some(Versioned {
+                value: 3,
+                version: 42
+            })
+        );
+        assert!(!store.compare_and_swap("k", Some(42), 4, ttl).unwrap());
+        assert_eq!(store.incr("k", 2, Duration::from_secs(1)).unwrap(), 5);
+        store.expire("k", Duration::from_micros(1)).unwrap();
+        assert!(store.expire("k", Duration::from_micros(1)).is_err());
+        assert!(store
+            .incr_within("k", 2, 6, Duration::from_secs(1))
+            .unwrap());
+        assert!(!store
+            .incr_within("k", 2, 6, Duration::from_secs(1))
+            .unwrap());
+        store.decr("k", 3, Duration::from_secs(1)).unwrap();
+    }
+}
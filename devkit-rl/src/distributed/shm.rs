@@ -0,0 +1,390 @@
+use std::{
+    fs::OpenOptions,
+    io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{Clock, RateLimiter, SystemClock};
+
+/// Marks a segment whose configuration is written.
+const MAGIC: u64 = u64::from_le_bytes(*b"devkitrl");
+
+/// Marks a segment whose configuration is being written by another process.
+const INITIALIZING: u64 = 1;
+
+/// How long to wait for another process to write the configuration.
+const INIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The layout of the shared memory segment.
+#[repr(C)]
+struct SharedState {
+    magic: AtomicU64,
+    capacity: AtomicU64,
+    refill_rate: AtomicU64,
+    /// The refill interval, in nanoseconds.
+    refill_interval: AtomicU64,
+    /// The theoretical arrival time of the next request, in nanoseconds since the
+    /// unix epoch: the bucket is full once the time reaches it.
+    tat: AtomicU64,
+}
+
+const SIZE: usize = std::mem::size_of::<SharedState>();
+
+/// A token bucket whose state lives in shared memory, so that the processes of one
+/// host, e.g. preforked workers, share one limit without a network hop.
+///
+/// Every process opens the bucket from the same file, which is mapped in memory:
+/// a file in `/dev/shm` (or another tmpfs) never touches the disk. The state is a
+/// single atomic word, updated with compare-and-swap following the generic cell rate
+/// algorithm, so a crashed process never leaves the bucket locked. The time is the
+/// unix time, shared by all the processes of the host.
+///
+/// The first process to open the file writes the configuration of the bucket; the
+/// others must open it with the same one.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use devkit_rl::distributed::ShmTokenBucket;
+///
+/// // in every worker
+/// let bucket = ShmTokenBucket::open("/dev/shm/api.bucket", 100, 10, Some(Duration::from_secs(1)))?;
+/// if bucket.allow() {
+///     // handle the request
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct ShmTokenBucket {
+    path: PathBuf,
+    state: NonNull<SharedState>,
+    capacity: u64,
+    refill_rate: u64,
+    refill_interval: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+// SAFETY: the mapping is only accessed through atomics, and unmapped on drop.
+unsafe impl Send for ShmTokenBucket {}
+// SAFETY: the mapping is only accessed through atomics.
+unsafe impl Sync for ShmTokenBucket {}
+
+impl ShmTokenBucket {
+    /// Opens the bucket shared through the file at `path`, creating it if it does
+    /// not exist.
+    ///
+    /// - `capacity`: The maximum number of tokens the bucket can hold.
+    /// - `refill_rate`: The number of tokens to add during each refill interval.
+    /// - `refill_interval`: The time duration between each refill. If `None` is provided, the default is 1 second.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be opened or mapped, if it is not a bucket, if it was
+    /// opened with another configuration, or if `refill_rate` is zero.
+    pub fn open(
+        path: impl AsRef<Path>,
+        capacity: u64,
+        refill_rate: u64,
+        refill_interval: Option<Duration>,
+    ) -> io::Result<Self> {
+        Self::open_with_clock(
+            path,
+            capacity,
+            refill_rate,
+            refill_interval,
+            Arc::new(SystemClock::new()),
+        )
+    }
+
+    /// Opens the bucket shared through the file at `path`, reading the time from
+    /// `clock`, which must count the same time in every process.
+    ///
+    /// # Errors
+    ///
+    /// See [`ShmTokenBucket::open`].
+    pub fn open_with_clock(
+        path: impl AsRef<Path>,
+        capacity: u64,
+        refill_rate: u64,
+        refill_interval: Option<Duration>,
+        clock: Arc<dyn Clock>,
+    ) -> io::Result<Self> {
+        if refill_rate == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the refill rate of a shared memory token bucket must not be zero",
+            ));
+        }
+        let path = path.as_ref().to_path_buf();
+        let refill_interval = refill_interval.unwrap_or(Duration::from_secs(1));
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if file.metadata()?.len() < SIZE as u64 {
+            // the new bytes are zeroes, i.e. an uninitialized segment
+            file.set_len(SIZE as u64)?;
+        }
+        // SAFETY: the file is at least `SIZE` bytes long, and the mapping is checked
+        // for failure; it stays valid after the file is closed.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let state = NonNull::new(ptr.cast()).ok_or_else(io::Error::last_os_error)?;
+
+        // unmaps the segment if the configuration is rejected
+        let bucket = Self {
+            path,
+            state,
+            capacity,
+            refill_rate,
+            refill_interval,
+            clock,
+        };
+        bucket.initialize()?;
+        Ok(bucket)
+    }
+
+    /// Writes the configuration to a new segment, or checks it against the one
+    /// written by the process that created the segment.
+    fn initialize(&self) -> io::Result<()> {
+        let state = self.state();
+        let interval = u64::try_from(self.refill_interval.as_nanos()).unwrap_or(u64::MAX);
+        let config = [
+            (&state.capacity, self.capacity),
+            (&state.refill_rate, self.refill_rate),
+            (&state.refill_interval, interval),
+        ];
+
+        if state
+            .magic
+            .compare_exchange(0, INITIALIZING, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            for (field, value) in config {
+                field.store(value, Ordering::Relaxed);
+            }
+            state.magic.store(MAGIC, Ordering::Release);
+            return Ok(());
+        }
+
+        let started = Instant::now();
+        loop {
+            match state.magic.load(Ordering::Acquire) {
+                MAGIC => break,
+                INITIALIZING if started.elapsed() < INIT_TIMEOUT => std::thread::yield_now(),
+                INITIALIZING => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("{} is still being initialized", self.path.display()),
+                    ))
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{} is not a shared memory token bucket",
+                            self.path.display()
+                        ),
+                    ))
+                }
+            }
+        }
+        if config
+            .iter()
+            .any(|(field, value)| field.load(Ordering::Relaxed) != *value)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} was opened with another configuration",
+                    self.path.display()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> &SharedState {
+        // SAFETY: the mapping lives as long as `self`, and is only accessed through
+        // atomics.
+        unsafe { self.state.as_ref() }
+    }
+
+    /// Returns the path of the file shared by the processes.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the maximum number of tokens the bucket can hold.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Attempts to take a single token.
+    pub fn allow(&self) -> bool {
+        self.allow_n(1)
+    }
+
+    /// Attempts to take `n` tokens at once, in any of the processes sharing the
+    /// bucket.
+    pub fn allow_n(&self, n: u64) -> bool {
+        if n > self.capacity {
+            return false;
+        }
+        let (cost, tolerance) = (self.nanos_for(n), self.nanos_for(self.capacity));
+        let now = self.now();
+        self.state()
+            .tat
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |tat| {
+                let next = tat.max(now).saturating_add(cost);
+                (next - now <= tolerance).then_some(next)
+            })
+            .is_ok()
+    }
+
+    /// Estimates how long to wait until `n` tokens would be available.
+    ///
+    /// Returns `Duration::MAX` if `n` exceeds the capacity.
+    pub fn next_available(&self, n: u64) -> Duration {
+        if n > self.capacity {
+            return Duration::MAX;
+        }
+        let (cost, tolerance) = (self.nanos_for(n), self.nanos_for(self.capacity));
+        let now = self.now();
+        let tat = self.state().tat.load(Ordering::Acquire);
+        let next = tat.max(now).saturating_add(cost);
+        Duration::from_nanos((next - now).saturating_sub(tolerance))
+    }
+
+    /// Returns the number of tokens currently in the bucket.
+    pub fn available(&self) -> u64 {
+        let now = self.now();
+        let tat = self.state().tat.load(Ordering::Acquire);
+        let debt = u128::from(tat.saturating_sub(now));
+        let refilled = debt * u128::from(self.refill_rate) / self.refill_interval.as_nanos().max(1);
+        self.capacity
+            .saturating_sub(u64::try_from(refilled).unwrap_or(u64::MAX))
+    }
+
+    /// Returns how long the bucket takes to refill `n` tokens, in nanoseconds.
+    fn nanos_for(&self, n: u64) -> u64 {
+        let nanos = u128::from(n) * self.refill_interval.as_nanos() / u128::from(self.refill_rate);
+        u64::try_from(nanos).unwrap_or(u64::MAX)
+    }
+
+    /// Returns the time, in nanoseconds since the unix epoch.
+    fn now(&self) -> u64 {
+        u64::try_from(self.clock.now().as_nanos()).unwrap_or(u64::MAX)
+    }
+}
+
+impl Drop for ShmTokenBucket {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was created with `SIZE` bytes, and is not used anymore.
+        unsafe {
+            libc::munmap(self.state.as_ptr().cast(), SIZE);
+        }
+    }
+}
+
+impl std::fmt::Debug for ShmTokenBucket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShmTokenBucket")
+            .field("path", &self.path)
+            .field("capacity", &self.capacity)
+            .field("refill_rate", &self.refill_rate)
+            .field("refill_interval", &self.refill_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RateLimiter for ShmTokenBucket {
+    fn allow_n(&self, n: u64) -> bool {
+        ShmTokenBucket::allow_n(self, n)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        ShmTokenBucket::next_available(self, n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::ManualClock;
+
+    /// Returns a path in the temporary directory, removing what it points to.
+    fn temp_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("devkit-rl-{}-{name}.shm", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn shm_token_bucket_should_share_tokens_across_mappings() {
+        let path = temp_path("share");
+        let clock = Arc::new(ManualClock::new());
+        clock.advance(Duration::from_secs(1_700_000_000));
+        let open = || ShmTokenBucket::open_with_clock(&path, 3, 1, None, clock.clone());
+
+        // two mappings of the file, as two worker processes would have
+        let (a, b) = (open().unwrap(), open().unwrap());
+        assert!(a.allow_n(2));
+        assert!(b.allow());
+        assert!(!a.allow());
+        assert!(!b.allow());
+        assert_eq!(b.next_available(1), Duration::from_secs(1));
+        assert_eq!(a.next_available(4), Duration::MAX);
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(a.available(), 2);
+        assert!(b.allow_n(2));
+        assert!(!a.allow());
+
+        // the state survives the mappings
+        drop((a, b));
+        clock.advance(Duration::from_secs(1));
+        let c = open().unwrap();
+        assert_eq!(c.available(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn shm_token_bucket_should_reject_foreign_files_and_configurations() {
+        let path = temp_path("config");
+        let _bucket = ShmTokenBucket::open(&path, 3, 1, None).unwrap();
+        let err = ShmTokenBucket::open(&path, 5, 1, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = ShmTokenBucket::open(&path, 3, 0, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        fs::write(&path, [0xff; 64]).unwrap();
+        let err = ShmTokenBucket::open(&path, 3, 1, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+use std::{sync::Arc, time::Duration};
+
+use super::{cas_add, current_window, DistributedStore, StoreError};
+use crate::{counter_window::smoothed_wait, Error, RateLimiter};
+
+/// A sliding window rate limiter whose counters live in a [`DistributedStore`].
+///
+/// Only the counters of the current and the previous fixed window are stored. The
+/// number of requests in the sliding window is estimated by weighting the previous
+/// window's count with the part of it still covered by the sliding window, which
+/// avoids the 2x bursts a fixed window allows at window boundaries.
+///
+/// # Example
+///
+/// ```
+/// use std::{sync::Arc, time::Duration};
+/// use devkit_rl::distributed::{DistributedSlidingWindow, InMemoryStore};
+///
+/// let store = Arc::new(InMemoryStore::new());
+/// let rl = DistributedSlidingWindow::new(store, "api", 10, Some(Duration::from_secs(60)));
+///
+/// assert!(rl.allow());
+/// ```
+#[derive(Clone)]
+pub struct DistributedSlidingWindow {
+    store: Arc<dyn DistributedStore>,
+    key: String,
+    size: u64,
+    interval: Duration,
+}
+
+impl DistributedSlidingWindow {
+    /// Creates a new `DistributedSlidingWindow` rate limiter.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store holding the shared counters.
+    /// * `key` - The prefix of the keys used in the store; instances with the same key share a quota.
+    /// * `size` - The maximum number of requests allowed within the sliding window.
+    /// * `interval` - The duration of the sliding window. Defaults to 1 second if not provided.
+    pub fn new(
+        store: Arc<dyn DistributedStore>,
+        key: impl Into<String>,
+        size: u64,
+        interval: Option<Duration>,
+    ) -> Self {
+        Self {
+            store,
+            key: key.into(),
+            size,
+            interval: interval.unwrap_or(Duration::from_secs(1)),
+        }
+    }
+
+    /// Attempts to allow a single request.
+    ///
+    /// Store failures are treated as admissions, see [`DistributedSlidingWindow::try_allow_n`]
+    /// to handle them explicitly.
+    pub fn allow(&self) -> bool {
+        self.allow_n(1)
+    }
+
+    /// Attempts to allow `n` requests.
+    ///
+    /// Store failures are treated as admissions, so an unavailable store does not
+    /// take the service down with it. See [`DistributedSlidingWindow::try_allow_n`] to
+    /// handle them explicitly.
+    pub fn allow_n(&self, n: u64) -> bool {
+        self.try_allow_n(n).unwrap_or(true)
+    }
+
+    /// Attempts to allow `n` requests.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the requests are allowed, `Ok(false)` if they exceed the limit,
+    /// or the error reported by the store.
+    pub fn try_allow_n(&self, n: u64) -> Result<bool, StoreError> {
+        let (index, offset) = current_window(self.interval);
+        let previous = self
+            .store
+            .get(&format!("{}:{}", self.key, index.wrapping_sub(1)))?
+            .map_or(0, |c| c.value);
+
+        // the part of the previous window still covered by the sliding window
+        let weight = 1.0 - offset.div_duration_f64(self.interval);
+        let previous = (previous as f64 * weight) as u64;
+
+        // the current counter must outlive the next window, which still weighs it
+        let ttl = self.interval * 2;
+        let key = format!("{}:{}", self.key, index);
+        cas_add(self.store.as_ref(), &key, n, ttl, |count| {
+            previous.saturating_add(count) <= self.size
+        })
+    }
+
+    /// Estimates how long to wait until `n` requests are allowed.
+    ///
+    /// Store failures are treated as admissions, like in [`DistributedSlidingWindow::allow_n`].
+    ///
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if the requests are allowed now, the time until enough of the
+    /// earlier requests have slid out of the window otherwise, or `Duration::MAX` if
+    /// `n` exceeds the size and will never be allowed.
+    pub fn next_available(&self, n: u64) -> Duration {
+        if n > self.size {
+            return Duration::MAX;
+        }
+
+        let (index, offset) = current_window(self.interval);
+        let count = |index: u128| {
+            self.store
+                .get(&format!("{}:{}", self.key, index))
+                .map(|c| c.map_or(0, |c| c.value))
+        };
+        let (Ok(previous), Ok(current)) = (count(index.wrapping_sub(1)), count(index)) else {
+            return Duration::ZERO;
+        };
+        smoothed_wait(previous, current, n, self.size, self.interval, offset)
+    }
+}
+
+impl std::fmt::Debug for DistributedSlidingWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DistributedSlidingWindow")
+            .field("key", &self.key)
+            .field("size", &self.size)
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RateLimiter for DistributedSlidingWindow {
+    fn allow_n(&self, n: u64) -> bool {
+        DistributedSlidingWindow::allow_n(self, n)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        DistributedSlidingWindow::next_available(self, n)
+    }
+
+    fn try_check(&self, n: u64) -> Result<(), Error> {
+        if n > self.size {
+            return Err(Error::TooLarge);
+        }
+        if self.try_allow_n(n)? {
+            Ok(())
+        } else {
+            Err(Error::RateLimited)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::distributed::InMemoryStore;
+
+    use super::*;
+
+    #[test]
+    fn distributed_sliding_window_should_weigh_previous_window() {
+        const SIZE: u64 = 10;
+        const INTERVAL: Duration = Duration::from_millis(100);
+
+        // wait for the start of a window, so the test does not straddle a boundary
+        let (_, offset) = current_window(INTERVAL);
+        std::thread::sleep(INTERVAL - offset);
+
+        let store = Arc::new(InMemoryStore::new());
+        let rl = DistributedSlidingWindow::new(store, "test", SIZE, Some(INTERVAL));
+
+        assert!(rl.allow_n(SIZE));
+        assert!(!rl.allow());
+
+        // early in the next window, most of the previous window still counts
+        std::thread::sleep(INTERVAL + INTERVAL / 10);
+        assert!(!rl.allow_n(SIZE / 2));
+        assert!(rl.allow());
+    }
+}
@@ -0,0 +1,90 @@
+use core::fmt;
+
+#[cfg(feature = "std")]
+use crate::distributed::StoreError;
+
+/// Errors returned by the fallible APIs of the rate limiters.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The request was denied because it exceeds the rate limit.
+    RateLimited,
+    /// The request asks for more than the limiter can ever allow at once, e.g. more
+    /// tokens than the capacity of a token bucket, so it will be denied forever.
+    ///
+    /// Splitting the request in smaller ones, as [`Limiter::wait_all`](crate::Limiter::wait_all)
+    /// does, gets it through over several intervals.
+    TooLarge,
+    /// The background worker of the limiter has stopped, so the request cannot be served.
+    Disconnected,
+    /// The request was not served within the time the caller was willing to wait.
+    Timeout,
+    /// The request was denied right away because it would wait longer than the
+    /// latency budget of the caller, see [`Limiter::admit_if_wait_below`](crate::Limiter::admit_if_wait_below).
+    /// The estimated wait is given.
+    OverBudget(core::time::Duration),
+    /// The request was denied because the queue of a queuing limiter is full, or
+    /// because it would wait longer than the target of a [`QueueGuard`](crate::QueueGuard).
+    QueueFull,
+    /// The store backing a distributed limiter failed.
+    #[cfg(feature = "std")]
+    Backend(StoreError),
+    /// The configuration of the limiter is invalid; the message names the problem.
+    InvalidConfig(&'static str),
+    /// A time given to the limiter is older than the latest time it has seen, e.g.
+    /// because the entries of a replayed log are out of order.
+    ClockWentBackwards,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::RateLimited => write!(f, "rate limited"),
+            Error::TooLarge => write!(
+                f,
+                "request exceeds what the rate limiter ever allows at once"
+            ),
+            Error::Disconnected => write!(f, "rate limiter worker has stopped"),
+            Error::Timeout => write!(f, "timed out waiting for the rate limiter"),
+            Error::OverBudget(wait) => write!(
+                f,
+                "estimated wait of {wait:?} for the rate limiter exceeds the budget"
+            ),
+            Error::QueueFull => write!(f, "rate limiter queue is full"),
+            #[cfg(feature = "std")]
+            Error::Backend(e) => write!(f, "rate limiter backend failed: {e}"),
+            Error::InvalidConfig(reason) => write!(f, "invalid rate limiter config: {reason}"),
+            Error::ClockWentBackwards => write!(f, "time went backwards for the rate limiter"),
+        }
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            Error::Backend(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<StoreError> for Error {
+    fn from(e: StoreError) -> Self {
+        Error::Backend(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Timeout => std::io::Error::new(std::io::ErrorKind::TimedOut, e),
+            Error::InvalidConfig(_) | Error::TooLarge => {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, e)
+            }
+            _ => std::io::Error::other(e),
+        }
+    }
+}
@@ -0,0 +1,449 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    hash::Hash,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    limiter::retry_after,
+    sync::{Mutex, MutexExt},
+    Error, RateLimiter,
+};
+#[cfg(feature = "async")]
+use crate::{runtime::timeout, AsyncRuntime, DefaultRuntime};
+
+/// Waiters for a limiter shared by many keys, admitted fairly across the keys.
+///
+/// When callers wait for a shared limiter, e.g. the global limit of a
+/// [`TieredLimiter`](crate::TieredLimiter) or a limiter of the registry shared by
+/// tenants, the first to retry wins, so a hot key with many waiters starves the
+/// others. A `FairQueue` queues the waiters per key instead, and admits them with
+/// deficit round robin: the keys take turns, and each turn a key is credited with
+/// `quantum` requests, so that every waiting key gets the same share of the limiter
+/// whatever the number and the weight of its waiters. Waiters of the same key are
+/// admitted in order.
+///
+/// Waiters schedule themselves: there is no background thread, and every waiter
+/// admits as many waiters as the limiter allows, in order, whenever it wakes up.
+///
+/// # Example
+///
+/// ```
+/// use std::{sync::Arc, thread, time::Duration};
+/// use devkit_rl::{FairQueue, TokenBucket};
+///
+/// let queue = Arc::new(FairQueue::new(TokenBucket::new(1, 1, Some(Duration::from_millis(10)))));
+///
+/// let workers: Vec<_> = ["tenant-a", "tenant-a", "tenant-b"]
+///     .into_iter()
+///     .map(|tenant| {
+///         let queue = queue.clone();
+///         thread::spawn(move || queue.wait(tenant, 1))
+///     })
+///     .collect();
+/// for worker in workers {
+///     assert!(worker.join().unwrap().is_ok());
+/// }
+/// ```
+pub struct FairQueue<K, L> {
+    limiter: L,
+    quantum: u64,
+    state: Arc<Mutex<State<K>>>,
+}
+
+/// The waiters of a [`FairQueue`].
+struct State<K> {
+    /// The waiters of every key with waiters.
+    queues: HashMap<K, KeyQueue>,
+    /// The keys with waiters, the key whose turn it is first.
+    ring: VecDeque<K>,
+    /// Whether the key whose turn it is was credited for its turn.
+    credited: bool,
+    next_id: u64,
+}
+
+/// The waiters of a key.
+struct KeyQueue {
+    /// The requests the key may still be admitted during its turn.
+    deficit: u64,
+    waiters: VecDeque<Waiter>,
+}
+
+/// A caller waiting for `n` requests.
+struct Waiter {
+    id: u64,
+    n: u64,
+    tx: oneshot::Sender<Result<(), Error>>,
+}
+
+impl<K: Hash + Eq + Clone, L: RateLimiter> FairQueue<K, L> {
+    /// Creates a new `FairQueue` crediting every key with one request per turn.
+    ///
+    /// # Arguments
+    ///
+    /// * `limiter` - The limiter shared by the keys.
+    pub fn new(limiter: L) -> Self {
+        Self::with_quantum(limiter, 1)
+    }
+
+    /// Creates a new `FairQueue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `limiter` - The limiter shared by the keys.
+    /// * `quantum` - The requests a key is credited with per turn, at least 1. A
+    ///   quantum close to the usual weight of the requests keeps the turns short.
+    pub fn with_quantum(limiter: L, quantum: u64) -> Self {
+        Self {
+            limiter,
+            quantum: quantum.max(1),
+            state: Arc::new(Mutex::new(State {
+                queues: HashMap::new(),
+                ring: VecDeque::new(),
+                credited: false,
+                next_id: 0,
+            })),
+        }
+    }
+
+    /// Waits for `n` requests for `key`, blocking the thread until they are admitted.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the requests are admitted, or [`Error::TooLarge`] if the
+    /// limiter can never allow them, e.g. because `n` exceeds its capacity.
+    pub fn wait(&self, key: K, n: u64) -> Result<(), Error> {
+        let (mut pending, rx, mut retry) = self.enqueue(key, n);
+        loop {
+            match rx.recv_timeout(retry) {
+                Ok(result) => {
+                    pending.done = true;
+                    return result;
+                }
+                Err(oneshot::RecvTimeoutError::Timeout) => {
+                    retry = self.dispatch(&mut self.state.lock_unpoisoned());
+                }
+                Err(oneshot::RecvTimeoutError::Disconnected) => return Err(Error::Disconnected),
+            }
+        }
+    }
+
+    /// Waits for `n` requests for `key` without blocking the thread.
+    ///
+    /// This is the async counterpart of [`FairQueue::wait`]. Dropping the returned
+    /// future takes the waiter out of the queue; a waiter dropped right after being
+    /// admitted still counts against the limiter.
+    #[cfg(feature = "async")]
+    pub async fn wait_async(&self, key: K, n: u64) -> Result<(), Error> {
+        self.wait_async_with(key, n, &DefaultRuntime).await
+    }
+
+    /// Waits for `n` requests for `key` like [`FairQueue::wait_async`], on the timer
+    /// of `runtime`, see [`AsyncRuntime`].
+    #[cfg(feature = "async")]
+    pub async fn wait_async_with(
+        &self,
+        key: K,
+        n: u64,
+        runtime: &(impl AsyncRuntime + ?Sized),
+    ) -> Result<(), Error> {
+        let (mut pending, mut rx, mut retry) = self.enqueue(key, n);
+        loop {
+            match timeout(runtime, retry, &mut rx).await {
+                Some(result) => {
+                    pending.done = true;
+                    return result.unwrap_or(Err(Error::Disconnected));
+                }
+                None => retry = self.dispatch(&mut self.state.lock_unpoisoned()),
+            }
+        }
+    }
+
+    /// Returns the number of callers waiting.
+    pub fn waiting(&self) -> usize {
+        let state = self.state.lock_unpoisoned();
+        state.queues.values().map(|q| q.waiters.len()).sum()
+    }
+
+    /// Returns the limiter shared by the keys.
+    pub fn limiter(&self) -> &L {
+        &self.limiter
+    }
+
+    /// Queues a waiter for `n` requests for `key`, and admits the waiters the
+    /// limiter allows.
+    ///
+    /// # Returns
+    ///
+    /// The guard taking the waiter out of the queue if it is dropped, the receiver
+    /// of its admission, and how long to wait before trying the limiter again.
+    fn enqueue(
+        &self,
+        key: K,
+        n: u64,
+    ) -> (Pending<K>, oneshot::Receiver<Result<(), Error>>, Duration) {
+        let (tx, rx) = oneshot::channel();
+        let mut state = self.state.lock_unpoisoned();
+        let id = state.next_id;
+        state.next_id += 1;
+
+        let queue = state.queues.entry(key.clone()).or_insert_with(|| KeyQueue {
+            deficit: 0,
+            waiters: VecDeque::new(),
+        });
+        queue.waiters.push_back(Waiter { id, n, tx });
+        if queue.waiters.len() == 1 {
+            state.ring.push_back(key.clone());
+        }
+
+        let retry = self.dispatch(&mut state);
+        let pending = Pending {
+            state: self.state.clone(),
+            key,
+            id,
+            done: false,
+        };
+        (pending, rx, retry)
+    }
+
+    /// Admits waiters in turn while the limiter allows them.
+    ///
+    /// # Returns
+    ///
+    /// How long to wait before trying the limiter again.
+    fn dispatch(&self, state: &mut State<K>) -> Duration {
+        loop {
+            let Some(key) = state.ring.front() else {
+                return Duration::MAX;
+            };
+            let queue = state
+                .queues
+                .get_mut(key)
+                .expect("keys in the ring have waiters");
+            if !state.credited {
+                queue.deficit = queue.deficit.saturating_add(self.quantum);
+                state.credited = true;
+            }
+            let n = queue.waiters.front().expect("queues are not empty").n;
+
+            if n > queue.deficit {
+                // the turn of this key is over
+                self.skip_idle_rounds(state);
+                state.ring.rotate_left(1);
+                state.credited = false;
+                continue;
+            }
+
+            let result = if self.limiter.allow_n(n) {
+                queue.deficit -= n;
+                Ok(())
+            } else {
+                match retry_after(self.limiter.next_available(n)) {
+                    Ok(wait) => return wait,
+                    Err(e) => Err(e),
+                }
+            };
+            let waiter = queue.waiters.pop_front().expect("queues are not empty");
+            if queue.waiters.is_empty() {
+                let key = state.ring.pop_front().expect("the ring is not empty");
+                state.queues.remove(&key);
+                state.credited = false;
+            }
+            // the caller may be gone, e.g. a dropped future
+            let _ = waiter.tx.send(result);
+        }
+    }
+
+    /// Credits every key at once with the rounds of turns in which none of them
+    /// would be admitted, so that heavy requests and a small quantum do not make the
+    /// turns spin.
+    fn skip_idle_rounds(&self, state: &mut State<K>) {
+        let rounds = state
+            .queues
+            .values()
+            .map(|q| {
+                let n = q.waiters.front().map_or(0, |w| w.n);
+                n.saturating_sub(q.deficit).div_ceil(self.quantum)
+            })
+            .min()
+            .unwrap_or(0);
+        if rounds > 1 {
+            let credit = (rounds - 1).saturating_mul(self.quantum);
+            for queue in state.queues.values_mut() {
+                queue.deficit = queue.deficit.saturating_add(credit);
+            }
+        }
+    }
+}
+
+impl<K, L: fmt::Debug> fmt::Debug for FairQueue<K, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FairQueue")
+            .field("limiter", &self.limiter)
+            .field("quantum", &self.quantum)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Takes a waiter out of the queue of its key unless it was admitted.
+struct Pending<K: Hash + Eq> {
+    state: Arc<Mutex<State<K>>>,
+    key: K,
+    id: u64,
+    done: bool,
+}
+
+impl<K: Hash + Eq> Drop for Pending<K> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let mut state = self.state.lock_unpoisoned();
+        let Some(queue) = state.queues.get_mut(&self.key) else {
+            return;
+        };
+        queue.waiters.retain(|w| w.id != self.id);
+        if queue.waiters.is_empty() {
+            state.queues.remove(&self.key);
+            if state.ring.front() == Some(&self.key) {
+                state.credited = false;
+            }
+            state.ring.retain(|k| *k != self.key);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "token-bucket"))]
+mod tests {
+    use super::*;
+    use crate::{ManualClock, TokenBucket};
+
+    type Admission = oneshot::Receiver<Result<(), Error>>;
+
+    /// A queue on a bucket of 2 requests refilled with one request per second,
+    /// already spent.
+    fn queue(quantum: u64) -> (FairQueue<&'static str, TokenBucket>, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new());
+        let bucket = TokenBucket::with_clock(2, 1, Some(Duration::from_secs(1)), clock.clone());
+        assert!(bucket.allow_n(2));
+        (FairQueue::with_quantum(bucket, quantum), clock)
+    }
+
+    /// Lets one more request through the limiter, and returns the keys admitted.
+    fn tick(
+        queue: &FairQueue<&'static str, TokenBucket>,
+        clock: &ManualClock,
+        waiters: &mut Vec<(&'static str, Pending<&'static str>, Admission)>,
+    ) -> Vec<&'static str> {
+        clock.advance(Duration::from_secs(1));
+        queue.dispatch(&mut queue.state.lock_unpoisoned());
+        let mut admitted = Vec::new();
+        waiters.retain_mut(|(key, pending, rx)| match rx.try_recv() {
+            Ok(result) => {
+                assert!(result.is_ok());
+                pending.done = true;
+                admitted.push(*key);
+                false
+            }
+            Err(_) => true,
+        });
+        admitted
+    }
+
+    #[test]
+    fn fair_queue_should_take_turns_across_keys() {
+        let (queue, clock) = queue(1);
+        let mut waiters: Vec<_> = ["hot", "hot", "hot", "b", "c"]
+            .into_iter()
+            .map(|key| {
+                let (pending, rx, retry) = queue.enqueue(key, 1);
+                assert_eq!(retry, Duration::from_secs(1));
+                (key, pending, rx)
+            })
+            .collect();
+        assert_eq!(queue.waiting(), 5);
+
+        let order: Vec<_> = (0..5)
+            .flat_map(|_| tick(&queue, &clock, &mut waiters))
+            .collect();
+        assert_eq!(order, ["hot", "b", "c", "hot", "hot"]);
+        assert_eq!(queue.waiting(), 0);
+    }
+
+    #[test]
+    fn fair_queue_should_share_by_weight() {
+        // a key waiting for 2 requests at once gets its turn after 2 single requests
+        let (queue, clock) = queue(1);
+        let mut waiters: Vec<_> = [("light", 1), ("heavy", 2), ("light", 1), ("light", 1)]
+            .into_iter()
+            .map(|(key, n)| {
+                let (pending, rx, _) = queue.enqueue(key, n);
+                (key, pending, rx)
+            })
+            .collect();
+
+        let order: Vec<_> = (0..5)
+            .flat_map(|_| tick(&queue, &clock, &mut waiters))
+            .collect();
+        assert_eq!(order, ["light", "light", "heavy", "light"]);
+    }
+
+    #[test]
+    fn fair_queue_should_drop_cancelled_waiters() {
+        let (queue, clock) = queue(1);
+        let (hot, _hot_rx, _) = queue.enqueue("hot", 1);
+        let (cold, cold_rx, _) = queue.enqueue("cold", 1);
+
+        drop(hot);
+        assert_eq!(queue.waiting(), 1);
+        let mut waiters = vec![("cold", cold, cold_rx)];
+        assert_eq!(tick(&queue, &clock, &mut waiters), ["cold"]);
+
+        // requests the limiter can never allow are denied right away
+        assert!(matches!(queue.wait("cold", 3), Err(Error::TooLarge)));
+        assert_eq!(queue.waiting(), 0);
+    }
+
+    #[test]
+    fn fair_queue_should_block_until_admitted() {
+        let queue = Arc::new(FairQueue::new(TokenBucket::new(
+            1,
+            1,
+            Some(Duration::from_millis(10)),
+        )));
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let queue = queue.clone();
+                std::thread::spawn(move || queue.wait(i % 2, 1))
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap().is_ok());
+        }
+        assert_eq!(queue.waiting(), 0);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn fair_queue_should_wait_asynchronously() {
+        let start = tokio::time::Instant::now();
+        let clock: Arc<dyn crate::Clock> = Arc::new(move || start.elapsed());
+        let queue = FairQueue::new(TokenBucket::with_clock(
+            1,
+            1,
+            Some(Duration::from_secs(1)),
+            clock,
+        ));
+
+        assert!(queue.wait_async("a", 1).await.is_ok());
+        assert!(queue.wait_async("b", 1).await.is_ok());
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        // a cancelled waiter leaves the queue
+        let wait = tokio::time::timeout(Duration::from_millis(10), queue.wait_async("a", 1));
+        assert!(wait.await.is_err());
+        assert_eq!(queue.waiting(), 0);
+    }
+}
@@ -0,0 +1,370 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use crate::{
+    clock::{whole_periods, SharedClock},
+    sync::{Mutex, MutexExt},
+    Clock, RateLimiter,
+};
+
+/// A fixed window limit shared among dynamically registered flows with max-min
+/// fairness.
+///
+/// This is how several background jobs multiplex one API quota: every job
+/// registers a [`FairShareFlow`], and the limit is split among the flows at the
+/// start of each window by water filling. Flows asking for less than an equal
+/// split get all they asked for, and what they leave unused is split among the
+/// others, so that no flow can take more than its fair share while another wants
+/// it, and no share goes unused while a flow wants more.
+///
+/// The demand of a flow is the requests it asked for, allowed or not, in the
+/// previous window, so shares follow the demand from one window to the next. A new
+/// flow is assumed to want as much as it can get until its first window ends. The
+/// shares are split again whenever a flow is registered or dropped, and the
+/// requests allowed to all flows together never exceed the limit.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_rl::FairShareLimiter;
+///
+/// let limiter = FairShareLimiter::new(10, Some(Duration::from_secs(1)));
+/// let backfill = limiter.register();
+/// let reindex = limiter.register();
+///
+/// // the limit is split evenly until the demand of the flows is known
+/// assert_eq!(backfill.share(), 5);
+/// assert!(reindex.allow_n(5));
+/// assert!(!reindex.allow());
+///
+/// // the share of a dropped flow goes to the others
+/// drop(backfill);
+/// assert_eq!(reindex.share(), 10);
+/// assert!(reindex.allow_n(5));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FairShareLimiter {
+    inner: Arc<Mutex<FairShareInner>>,
+}
+
+#[derive(Debug)]
+struct FairShareInner {
+    /// The requests allowed per window for all flows together.
+    size: u64,
+    /// Duration of each window.
+    interval: Duration,
+    /// The time when the current window ends.
+    next_win_time: Duration,
+    /// The flows by id, in the order they were registered.
+    flows: BTreeMap<u64, Flow>,
+    /// The requests allowed in the current window to all flows together.
+    used: u64,
+    next_id: u64,
+    /// The source of time.
+    clock: SharedClock,
+}
+
+#[derive(Debug)]
+struct Flow {
+    /// The requests the flow may be allowed in the current window.
+    share: u64,
+    /// The requests allowed in the current window.
+    used: u64,
+    /// The requests asked for in the current window, allowed or not.
+    asked: u64,
+    /// The requests asked for in the previous window, or `u64::MAX` before the
+    /// first window of the flow has ended.
+    demand: u64,
+}
+
+impl FairShareLimiter {
+    /// Creates a new `FairShareLimiter` without flows.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The requests allowed per window for all flows together.
+    /// * `interval` - Optional duration of the time window. Defaults to 1 second if not provided.
+    pub fn new(size: u64, interval: Option<Duration>) -> Self {
+        Self::from_clock(size, interval, SharedClock::std())
+    }
+
+    /// Creates a new `FairShareLimiter` without flows, reading the time from `clock`.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The requests allowed per window for all flows together.
+    /// * `interval` - Optional duration of the time window. Defaults to 1 second if not provided.
+    /// * `clock` - The source of time of the window.
+    pub fn with_clock(size: u64, interval: Option<Duration>, clock: Arc<dyn Clock>) -> Self {
+        Self::from_clock(size, interval, SharedClock::new(clock))
+    }
+
+    fn from_clock(size: u64, interval: Option<Duration>, mut clock: SharedClock) -> Self {
+        let interval = interval.unwrap_or(Duration::from_secs(1));
+        Self {
+            inner: Arc::new(Mutex::new(FairShareInner {
+                size,
+                interval,
+                next_win_time: clock.now().saturating_add(interval),
+                flows: BTreeMap::new(),
+                used: 0,
+                next_id: 0,
+                clock,
+            })),
+        }
+    }
+
+    /// Registers a new flow, and splits the limit again among all flows.
+    ///
+    /// The flow is unregistered when the returned handle is dropped.
+    pub fn register(&self) -> FairShareFlow {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_window();
+
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.flows.insert(
+            id,
+            Flow {
+                share: 0,
+                used: 0,
+                asked: 0,
+                demand: u64::MAX,
+            },
+        );
+        inner.allocate();
+        FairShareFlow {
+            inner: self.inner.clone(),
+            id,
+        }
+    }
+
+    /// Returns the number of registered flows.
+    pub fn flows(&self) -> usize {
+        self.inner.lock_unpoisoned().flows.len()
+    }
+}
+
+/// A flow of a [`FairShareLimiter`], limited to its share of the limit.
+///
+/// Dropping the flow unregisters it, and splits its share among the other flows.
+#[derive(Debug)]
+pub struct FairShareFlow {
+    inner: Arc<Mutex<FairShareInner>>,
+    id: u64,
+}
+
+impl FairShareFlow {
+    /// Attempts to allow a single request.
+    ///
+    /// This is a convenience method that is equivalent to calling `allow_n(1)`.
+    pub fn allow(&self) -> bool {
+        self.allow_n(1)
+    }
+
+    /// Attempts to allow `n` requests.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the requests are within the share of the flow, `false` otherwise.
+    pub fn allow_n(&self, n: u64) -> bool {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_window();
+
+        let total = inner.used.saturating_add(n);
+        let size = inner.size;
+        let flow = inner.flow_mut(self.id);
+        flow.asked = flow.asked.saturating_add(n);
+        let used = flow.used.saturating_add(n);
+        if used > flow.share || total > size {
+            return false;
+        }
+        flow.used = used;
+        inner.used = total;
+        true
+    }
+
+    /// Estimates how long to wait until `n` requests would be allowed.
+    ///
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if the requests are allowed now, the time until the next
+    /// window otherwise, or `Duration::MAX` if `n` exceeds the limit shared by the
+    /// flows.
+    pub fn next_available(&self, n: u64) -> Duration {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_window();
+
+        let flow = inner.flow_mut(self.id);
+        if flow.used.saturating_add(n) <= flow.share && inner.used.saturating_add(n) <= inner.size {
+            return Duration::ZERO;
+        }
+        if n > inner.size {
+            return Duration::MAX;
+        }
+        inner.next_win_time.saturating_sub(inner.clock.now())
+    }
+
+    /// Returns the requests the flow may be allowed in the current window.
+    pub fn share(&self) -> u64 {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_window();
+        inner.flow_mut(self.id).share
+    }
+
+    /// Returns the requests allowed to the flow in the current window.
+    pub fn usage(&self) -> u64 {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_window();
+        inner.flow_mut(self.id).used
+    }
+
+    /// Returns when the current window started, by the clock of the limiter.
+    pub fn window_start(&self) -> Duration {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_window();
+        inner.next_win_time.saturating_sub(inner.interval)
+    }
+}
+
+impl Drop for FairShareFlow {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.flows.remove(&self.id);
+        inner.allocate();
+    }
+}
+
+impl RateLimiter for FairShareFlow {
+    fn allow_n(&self, n: u64) -> bool {
+        FairShareFlow::allow_n(self, n)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        FairShareFlow::next_available(self, n)
+    }
+
+    fn window_start(&self) -> Option<Duration> {
+        Some(FairShareFlow::window_start(self))
+    }
+}
+
+impl FairShareInner {
+    fn flow_mut(&mut self, id: u64) -> &mut Flow {
+        self.flows
+            .get_mut(&id)
+            .expect("flows are registered while they have a handle")
+    }
+
+    /// Starts a new window if the current one has ended.
+    fn update_window(&mut self) {
+        let now = self.clock.now();
+        if now < self.next_win_time {
+            return;
+        }
+
+        // the window containing `now` ends at the next whole interval
+        let (periods, into_window) = whole_periods(now - self.next_win_time, self.interval);
+        self.next_win_time = (now - into_window).saturating_add(self.interval);
+        self.used = 0;
+        for flow in self.flows.values_mut() {
+            // nothing was asked for in the windows that passed without requests
+            flow.demand = if periods == 0 { flow.asked } else { 0 };
+            flow.asked = 0;
+            flow.used = 0;
+        }
+        self.allocate();
+    }
+
+    /// Splits the limit among the flows by water filling on their demand.
+    fn allocate(&mut self) {
+        let mut flows: Vec<_> = self.flows.values_mut().collect();
+        flows.sort_by_key(|f| f.demand);
+
+        // the flows asking for less than an equal split of what is left get all
+        // they asked for, the others an equal split
+        let mut left = self.size;
+        let count = flows.len() as u64;
+        for (i, flow) in flows.iter_mut().enumerate() {
+            let rest = count - i as u64;
+            let split = left.div_ceil(rest);
+            flow.share = flow.demand.min(split);
+            left -= flow.share;
+        }
+
+        // the flows asking for little get room to grow into what nobody asked for
+        for (i, flow) in flows.iter_mut().enumerate() {
+            let rest = count - i as u64;
+            let extra = left.div_ceil(rest);
+            flow.share += extra;
+            left -= extra;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn fair_share_limiter_should_split_by_demand() {
+        let clock = Arc::new(ManualClock::new());
+        let limiter = FairShareLimiter::with_clock(12, Some(INTERVAL), clock.clone());
+        let light = limiter.register();
+        let heavy = limiter.register();
+        let other = limiter.register();
+        assert_eq!((light.share(), heavy.share(), other.share()), (4, 4, 4));
+
+        assert!(light.allow_n(2));
+        for _ in 0..10 {
+            heavy.allow();
+            other.allow();
+        }
+        assert_eq!(heavy.usage(), 4);
+        assert_eq!(heavy.next_available(1), INTERVAL);
+        assert_eq!(heavy.next_available(13), Duration::MAX);
+
+        // light asked for 2, and the others split what it left
+        clock.advance(INTERVAL);
+        assert_eq!((light.share(), heavy.share(), other.share()), (2, 5, 5));
+        assert!(heavy.allow_n(5));
+        assert!(!heavy.allow());
+
+        // the share of other goes to heavy right away
+        drop(other);
+        assert_eq!(limiter.flows(), 2);
+        assert_eq!((light.share(), heavy.share()), (2, 10));
+        assert!(heavy.allow_n(5));
+        assert_eq!(heavy.usage(), 10);
+    }
+
+    #[test]
+    fn fair_share_limiter_should_follow_demand_across_windows() {
+        let clock = Arc::new(ManualClock::new());
+        let limiter = FairShareLimiter::with_clock(10, Some(INTERVAL), clock.clone());
+        let a = limiter.register();
+        let b = limiter.register();
+
+        // nothing was asked for, the limit is split evenly
+        clock.advance(INTERVAL * 3);
+        assert_eq!((a.share(), b.share()), (5, 5));
+        assert!(a.allow_n(1));
+        // denied requests count in the demand
+        assert!(!b.allow_n(9));
+
+        clock.advance(INTERVAL);
+        assert_eq!((a.share(), b.share()), (1, 9));
+        assert!(b.allow_n(9));
+
+        // a new flow gets an equal split until its demand is known, and the total
+        // holds while a flow is over its new share
+        let c = limiter.register();
+        assert_eq!((a.share(), b.share(), c.share()), (1, 5, 4));
+        assert!(!c.allow_n(2));
+        assert!(c.allow());
+        assert_eq!(RateLimiter::window_start(&c), Some(INTERVAL * 4));
+    }
+}
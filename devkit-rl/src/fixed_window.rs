@@ -1,7 +1,14 @@
-use std::{
-    sync::{Arc, Mutex},
-    time::{Duration, Instant},
+use alloc::sync::Arc;
+use core::{fmt, time::Duration};
+
+use crate::{
+    clock::{At, SharedClock},
+    counter_window::{smoothed_wait, CounterWindow, LocalCounter, WindowCounter},
+    sync::{Mutex, MutexExt},
+    Clock, Error,
 };
+#[cfg(feature = "std")]
+use crate::{sync::arc_size, Quota};
 
 /// A fixed window rate limiter.
 ///
@@ -21,28 +28,42 @@ use std::{
 ///
 /// assert!(bucket.allow());
 /// ```
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct FixedWindow {
     inner: Arc<Mutex<FixedWindowInner>>,
+    on_close: Option<Arc<CloseHook>>,
+}
+
+type CloseHook = dyn Fn(&WindowStats) + Send + Sync;
+
+/// The usage of a window of a [`FixedWindow`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowStats {
+    /// When the window started, by the clock of the limiter.
+    pub start: Duration,
+    /// The duration of the window.
+    pub interval: Duration,
+    /// The requests allowed in the window, less those refunded.
+    pub count: u64,
 }
 
 /// Inner data for the fixed window rate limiter.
 ///
-/// This struct stores the configuration and state of the rate limiter, such as
-/// the size of the window, the current count of requests, the time interval of
-/// the window, and the last time the window was updated.
+/// This struct stores the configuration and state of the rate limiter: the size
+/// and the interval of the windows, and the counters of the current and previous
+/// windows, indexed from the start of the first window.
 #[derive(Debug)]
 struct FixedWindowInner {
-    /// Maximum number of allowed requests within the time window.
-    size: u64,
-    /// Current count of requests within the current window.
-    count: u64,
-    /// Duration of the time window.
-    interval: Duration,
-    /// The time when the window was last updated.
-    last_update: Instant,
-    /// The time when the next window starts.
-    next_win_time: Instant,
+    /// The size and the interval of the windows.
+    window: CounterWindow,
+    /// The counters of the current window and of the previous one, used for smoothing.
+    counter: LocalCounter,
+    /// Whether the previous window is taken into account at window boundaries.
+    smoothing: bool,
+    /// The time when the window of index 0 starts.
+    origin: Duration,
+    /// The source of time.
+    clock: SharedClock,
 }
 
 impl FixedWindow {
@@ -56,12 +77,197 @@ impl FixedWindow {
     /// # Returns
     ///
     /// A new `FixedWindow` instance.
+    #[cfg(feature = "std")]
     pub fn new(size: u64, interval: Option<Duration>) -> Self {
+        Self::with_smoothing(size, interval, false)
+    }
+
+    /// Creates a new `FixedWindow` rate limiter allowing `quota`.
+    ///
+    /// Each window allows [`Quota::burst`] requests and lasts [`Quota::burst_period`].
+    #[cfg(feature = "std")]
+    pub fn from_quota(quota: Quota) -> Self {
+        Self::new(quota.burst(), Some(quota.burst_period()))
+    }
+
+    /// Creates a new `FixedWindow` rate limiter, optionally smoothing window boundaries.
+    ///
+    /// A plain fixed window allows up to twice its size in a burst straddling a window
+    /// boundary. With smoothing enabled, the requests of the previous window are also
+    /// counted, weighted by the part of the previous window that a sliding window ending
+    /// now would still cover. This gives the boundary protection of a sliding window
+    /// while still storing only two counters.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The maximum number of requests allowed within each time window.
+    /// * `interval` - Optional duration of the time window. Defaults to 1 second if not provided.
+    /// * `smoothing` - Whether to weigh in the previous window.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::FixedWindow;
+    ///
+    /// let bucket = FixedWindow::with_smoothing(10, Some(Duration::from_secs(1)), true);
+    ///
+    /// assert!(bucket.allow());
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn with_smoothing(size: u64, interval: Option<Duration>, smoothing: bool) -> Self {
+        Self::from_clock(size, interval, smoothing, false, SharedClock::std())
+    }
+
+    /// Creates a new `FixedWindow` rate limiter whose windows are aligned to the unix
+    /// epoch.
+    ///
+    /// The window reads the time from a [`SystemClock`](crate::SystemClock), and its
+    /// windows start at whole multiples of `interval` since the unix epoch, so that
+    /// windows created in different processes start and end together, and
+    /// [`FixedWindow::window_start`] returns a unix time.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The maximum number of requests allowed within each time window.
+    /// * `interval` - Optional duration of the time window. Defaults to 1 second if not provided.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::FixedWindow;
+    ///
+    /// let window = FixedWindow::epoch_aligned(10, Some(Duration::from_secs(60)));
+    ///
+    /// assert!(window.allow());
+    /// assert_eq!(window.window_start().as_secs() % 60, 0);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn epoch_aligned(size: u64, interval: Option<Duration>) -> Self {
+        let clock = SharedClock::new(Arc::new(crate::SystemClock::new()));
+        Self::from_clock(size, interval, false, true, clock)
+    }
+
+    /// Creates a new `FixedWindow` rate limiter reading the time from `clock`.
+    ///
+    /// This is how a fixed window is created without the `std` feature, and how tests
+    /// control the passing of time.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The maximum number of requests allowed within each time window.
+    /// * `interval` - Optional duration of the time window. Defaults to 1 second if not provided.
+    /// * `smoothing` - Whether to weigh in the previous window, see [`FixedWindow::with_smoothing`].
+    /// * `clock` - The source of time of the window.
+    pub fn with_clock(
+        size: u64,
+        interval: Option<Duration>,
+        smoothing: bool,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self::from_clock(size, interval, smoothing, false, SharedClock::new(clock))
+    }
+
+    fn from_clock(
+        size: u64,
+        interval: Option<Duration>,
+        smoothing: bool,
+        aligned: bool,
+        clock: SharedClock,
+    ) -> Self {
         Self {
-            inner: Arc::new(Mutex::new(FixedWindowInner::new(size, interval))),
+            inner: Arc::new(Mutex::new(FixedWindowInner::new(
+                size, interval, smoothing, aligned, clock,
+            ))),
+            on_close: None,
         }
     }
 
+    /// Calls `f` with the usage of every window once it has ended, e.g. to bill or
+    /// record exact per-window counts.
+    ///
+    /// The end of a window is noticed by the first call to the limiter after it,
+    /// which calls `f` on its thread, outside of any lock. Windows that passed
+    /// without any call to the limiter are not reported, as they counted nothing.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use std::time::Duration;
+    /// use devkit_rl::{FixedWindow, ManualClock};
+    ///
+    /// let clock = Arc::new(ManualClock::new());
+    /// let closed = Arc::new(Mutex::new(Vec::new()));
+    /// let sink = closed.clone();
+    /// let window = FixedWindow::with_clock(10, Some(Duration::from_secs(1)), false, clock.clone())
+    ///     .on_window_close(move |stats| sink.lock().unwrap().push(stats.count));
+    ///
+    /// assert!(window.allow_n(3));
+    /// clock.advance(Duration::from_secs(1));
+    /// assert!(window.allow());
+    /// assert_eq!(*closed.lock().unwrap(), [3]);
+    /// assert_eq!(window.window_stats().count, 1);
+    /// ```
+    pub fn on_window_close(mut self, f: impl Fn(&WindowStats) + Send + Sync + 'static) -> Self {
+        self.on_close = Some(Arc::new(f));
+        self
+    }
+
+    /// Returns the usage of the current window so far.
+    pub fn window_stats(&self) -> WindowStats {
+        let (stats, closed) = {
+            let mut inner = self.inner.lock_unpoisoned();
+            let now = inner.clock.now();
+            let closed = inner.close(now);
+            let (index, _) = inner.current(now);
+            let Ok(count) = inner.counter.get(index);
+            (inner.stats(index, count), closed)
+        };
+        self.report(closed);
+        stats
+    }
+
+    /// Hands the usage of a window that has ended to the hook, if any.
+    fn report(&self, closed: Option<WindowStats>) {
+        if let (Some(on_close), Some(stats)) = (&self.on_close, closed) {
+            on_close(&stats);
+        }
+    }
+
+    /// Enables or disables smoothing of window boundaries.
+    ///
+    /// See [`FixedWindow::with_smoothing`] for details.
+    pub fn set_smoothing(&self, smoothing: bool) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.smoothing = smoothing;
+    }
+
+    /// Updates the parameters of the window without losing its current state.
+    ///
+    /// Requests already counted in the current window keep counting against the new size,
+    /// and the current window ends `interval` after it started.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The maximum number of requests allowed within each time window.
+    /// * `interval` - Optional duration of the time window. Defaults to 1 second if not provided.
+    pub fn reconfigure(&self, size: u64, interval: Option<Duration>) {
+        let closed = {
+            let mut inner = self.inner.lock_unpoisoned();
+            let now = inner.clock.now();
+            let closed = inner.close(now);
+            // the windows are indexed from the start of the current one from now on
+            let (_, offset) = inner.current(now);
+            inner.counter.rebase(0);
+            inner.origin = now - offset;
+            inner.window = CounterWindow::new(size, interval.unwrap_or(Duration::from_secs(1)));
+            closed
+        };
+        self.report(closed);
+    }
+
     /// Checks if a single request is allowed in the current time window.
     ///
     /// This is a convenience method for `allow_n(1)`.
@@ -83,27 +289,133 @@ impl FixedWindow {
     ///
     /// `true` if the requests are allowed, `false` if they exceed the limit.
     pub fn allow_n(&self, n: u64) -> bool {
-        let mut inner = self.inner.lock().expect("Failed to lock fixed window");
+        let (allowed, closed) = {
+            let mut inner = self.inner.lock_unpoisoned();
 
-        let now = Instant::now();
+            let now = inner.clock.now();
+            let closed = inner.close(now);
+            (inner.try_accept(n, now), closed)
+        };
+        self.report(closed);
+        allowed
+    }
 
-        // Check if the current time is beyond the next window time
-        if now >= inner.next_win_time {
-            // Calculate how many windows have passed
-            let pass_win_count = (now - inner.last_update).div_duration_f64(inner.interval) as u32;
-            inner.count = 0; // Reset count for the new window
-            inner.last_update = inner.last_update + inner.interval * pass_win_count;
-            inner.next_win_time = inner.last_update + inner.interval;
-        }
+    /// Checks if a single request is allowed at the time `now`.
+    ///
+    /// This is a convenience method for `allow_n_at(1, now)`.
+    pub fn allow_at(&self, now: Duration) -> bool {
+        self.allow_n_at(1, now)
+    }
 
-        // Check if the new requests exceed the window size
-        if inner.count + n > inner.size {
-            false
+    /// Checks if `n` requests are allowed at the time `now`, instead of the time read
+    /// from the clock of the window.
+    ///
+    /// `now` is on the timeline of the clock, see [`TokenBucket::allow_n_at`](crate::TokenBucket::allow_n_at).
+    pub fn allow_n_at(&self, n: u64, now: Duration) -> bool {
+        self.allow_n_at_checked(n, At::Clamped(now))
+            .unwrap_or(false)
+    }
+
+    /// Like [`allow_n_at`](Self::allow_n_at), but fails with
+    /// [`Error::ClockWentBackwards`] if `now` is older than the latest time seen by
+    /// the window, e.g. to detect the out-of-order entries of a replayed log.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::RateLimited`] if the requests are denied, or [`Error::ClockWentBackwards`].
+    pub fn try_allow_n_at(&self, n: u64, now: Duration) -> Result<(), Error> {
+        if self.allow_n_at_checked(n, At::Strict(now))? {
+            Ok(())
         } else {
-            inner.count += n;
-            true
+            Err(Error::RateLimited)
         }
     }
+
+    /// Takes `n` requests at the time `at`.
+    fn allow_n_at_checked(&self, n: u64, at: At) -> Result<bool, Error> {
+        let (allowed, closed) = {
+            let mut inner = self.inner.lock_unpoisoned();
+
+            let now = inner.clock.observe_at(at)?;
+            let closed = inner.close(now);
+            (inner.try_accept(n, now), closed)
+        };
+        self.report(closed);
+        Ok(allowed)
+    }
+
+    /// Estimates how long to wait until `n` requests are allowed.
+    ///
+    /// The estimate assumes no other request is made in the meantime.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of requests to allow.
+    ///
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if the requests are allowed now, the time until the window
+    /// that allows them otherwise, or `Duration::MAX` if `n` exceeds the size of the
+    /// window and will never be allowed.
+    pub fn next_available(&self, n: u64) -> Duration {
+        let (wait, closed) = {
+            let mut inner = self.inner.lock_unpoisoned();
+
+            let now = inner.clock.now();
+            let closed = inner.close(now);
+            (inner.next_available(n, now), closed)
+        };
+        self.report(closed);
+        wait
+    }
+
+    /// Returns how many requests the current window still allows.
+    ///
+    /// With smoothing, the weighted count of the previous window is taken off too.
+    pub fn remaining(&self) -> u64 {
+        let (remaining, closed) = {
+            let mut inner = self.inner.lock_unpoisoned();
+            let now = inner.clock.now();
+            let closed = inner.close(now);
+            let remaining = inner.window.size.saturating_sub(inner.estimated_count(now));
+            (remaining, closed)
+        };
+        self.report(closed);
+        remaining
+    }
+
+    /// Returns when the current window started, by the clock of the window.
+    pub fn window_start(&self) -> Duration {
+        self.window_stats().start
+    }
+
+    /// Gives back `n` requests previously allowed by [`FixedWindow::allow_n`].
+    ///
+    /// This undoes an admission that turned out not to be used, e.g. because
+    /// another limiter denied the same request. Refunding more than was allowed
+    /// never takes the count of the current window below 0.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of requests to give back.
+    pub fn refund(&self, n: u64) {
+        let closed = {
+            let mut inner = self.inner.lock_unpoisoned();
+            let now = inner.clock.now();
+            let closed = inner.close(now);
+            let (index, _) = inner.current(now);
+            let interval = inner.window.interval;
+            let Ok(()) = inner.counter.decr(index, n, interval);
+            closed
+        };
+        self.report(closed);
+    }
+
+    /// Estimates the memory held by this limiter, in bytes.
+    #[cfg(feature = "std")]
+    pub(crate) fn mem_size(&self) -> usize {
+        arc_size::<Mutex<FixedWindowInner>>()
+    }
 }
 
 impl FixedWindowInner {
@@ -113,26 +425,137 @@ impl FixedWindowInner {
     ///
     /// * `size` - The maximum number of requests allowed in each window.
     /// * `interval` - Optional duration of the time window. Defaults to 1 second if not provided.
+    /// * `smoothing` - Whether to weigh in the previous window.
+    /// * `aligned` - Whether windows start at whole multiples of `interval` since the
+    ///   origin of `clock`, rather than now.
+    /// * `clock` - The source of time.
     ///
     /// # Returns
     ///
     /// A new `FixedWindowInner` instance.
-    pub fn new(size: u64, interval: Option<Duration>) -> Self {
-        let now = Instant::now();
+    pub fn new(
+        size: u64,
+        interval: Option<Duration>,
+        smoothing: bool,
+        aligned: bool,
+        mut clock: SharedClock,
+    ) -> Self {
+        let now = clock.now();
         let interval = interval.unwrap_or(Duration::from_secs(1));
-        let next_win_time = now + interval;
+        let origin = if aligned { Duration::ZERO } else { now };
 
         Self {
-            size,
-            count: 0,
+            window: CounterWindow::new(size, interval),
+            counter: LocalCounter::default(),
+            smoothing,
+            origin,
+            clock,
+        }
+    }
+
+    /// Returns the index of the window containing `now`, and how far into that
+    /// window `now` is.
+    fn current(&self, now: Duration) -> (u64, Duration) {
+        self.window.window(now.saturating_sub(self.origin))
+    }
+
+    /// Moves on to the window containing `now`.
+    ///
+    /// # Returns
+    ///
+    /// The usage of the window that was current until now, if it has ended.
+    fn close(&mut self, now: Duration) -> Option<WindowStats> {
+        let (index, _) = self.current(now);
+        let (current, count) = self.counter.current();
+        if index <= current {
+            return None;
+        }
+        self.counter.roll(index);
+        Some(self.stats(current, count))
+    }
+
+    /// Returns the usage of the window of `index`, counting `count` requests.
+    fn stats(&self, index: u64, count: u64) -> WindowStats {
+        let interval = self.window.interval;
+        let since_origin = interval.as_nanos().saturating_mul(u128::from(index));
+        WindowStats {
+            start: self.origin
+                + Duration::from_nanos(u64::try_from(since_origin).unwrap_or(u64::MAX)),
             interval,
-            last_update: now,
-            next_win_time,
+            count,
         }
     }
+
+    /// Estimates how long to wait at `now` until `n` requests are allowed, see
+    /// [`FixedWindow::next_available`].
+    fn next_available(&mut self, n: u64, now: Duration) -> Duration {
+        let (index, offset) = self.current(now);
+        let Ok(count) = self.counter.get(index);
+        if !self.smoothing {
+            return self.window.next_available(count, n, offset);
+        }
+
+        if self.estimated_count(now).saturating_add(n) <= self.window.size {
+            return Duration::ZERO;
+        }
+        if n > self.window.size {
+            return Duration::MAX;
+        }
+        smoothed_wait(
+            self.counter.prev(index),
+            count,
+            n,
+            self.window.size,
+            self.window.interval,
+            offset,
+        )
+    }
+
+    /// Counts `n` requests at `now` if they fit in the window.
+    fn try_accept(&mut self, n: u64, now: Duration) -> bool {
+        let (index, _) = self.current(now);
+        let reserved = self.weighted_prev(now);
+        let Ok(allowed) = self
+            .window
+            .try_accept(&mut self.counter, index, n, reserved);
+        allowed
+    }
+
+    /// Returns the number of requests counting against the current window.
+    ///
+    /// Without smoothing this is the count of the current window. With smoothing,
+    /// the count of the previous window is added, weighted by the part of it that
+    /// is still covered by a sliding window ending at `now`.
+    fn estimated_count(&mut self, now: Duration) -> u64 {
+        let (index, _) = self.current(now);
+        let Ok(count) = self.counter.get(index);
+        count.saturating_add(self.weighted_prev(now))
+    }
+
+    /// Returns the count of the previous window still covered by a sliding window
+    /// ending at `now`, or 0 without smoothing.
+    fn weighted_prev(&mut self, now: Duration) -> u64 {
+        if !self.smoothing {
+            return 0;
+        }
+
+        let (index, offset) = self.current(now);
+        let elapsed = offset.div_duration_f64(self.window.interval);
+        let weight = (1.0 - elapsed).max(0.0);
+        (self.counter.prev(index) as f64 * weight) as u64
+    }
 }
 
-#[cfg(test)]
+impl fmt::Debug for FixedWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FixedWindow")
+            .field("inner", &self.inner)
+            .field("on_window_close", &self.on_close.is_some())
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
@@ -145,6 +568,88 @@ mod tests {
         assert!(!bucket.allow_n(SIZE + 1));
     }
 
+    #[test]
+    fn fixed_window_with_smoothing_should_limit_boundary_bursts() {
+        const SIZE: u64 = 10;
+        const INTERVAL: Duration = Duration::from_millis(50);
+
+        let plain = FixedWindow::new(SIZE, Some(INTERVAL));
+        let smoothed = FixedWindow::with_smoothing(SIZE, Some(INTERVAL), true);
+        assert!(plain.allow_n(SIZE));
+        assert!(smoothed.allow_n(SIZE));
+
+        // right after the boundary, a plain window allows a full new burst,
+        // while a smoothed one still counts most of the previous window
+        std::thread::sleep(INTERVAL + INTERVAL / 10);
+        assert!(plain.allow_n(SIZE));
+        assert!(!smoothed.allow_n(SIZE / 2));
+        assert!(smoothed.allow());
+
+        // once more windows have passed, the previous window is forgotten
+        std::thread::sleep(INTERVAL * 3);
+        assert!(smoothed.allow_n(SIZE));
+    }
+
+    #[test]
+    fn fixed_window_epoch_aligned_should_share_windows_across_instances() {
+        const INTERVAL: Duration = Duration::from_secs(3600);
+
+        let a = FixedWindow::epoch_aligned(1, Some(INTERVAL));
+        let b = FixedWindow::epoch_aligned(1, Some(INTERVAL));
+        let start = a.window_start();
+        assert_eq!(start.as_secs() % INTERVAL.as_secs(), 0);
+        assert!(start > Duration::from_secs(1_700_000_000));
+        assert_eq!(b.window_start(), start);
+        assert!(a.allow());
+        assert!(a.next_available(1) <= INTERVAL);
+    }
+
+    #[test]
+    fn fixed_window_should_report_the_usage_of_every_window() {
+        use std::sync::Mutex;
+
+        use crate::ManualClock;
+
+        const INTERVAL: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(ManualClock::new());
+        let closed = Arc::new(Mutex::new(Vec::new()));
+        let sink = closed.clone();
+        let window = FixedWindow::with_clock(10, Some(INTERVAL), false, clock.clone())
+            .on_window_close(move |stats| sink.lock().unwrap().push(*stats));
+
+        assert!(window.allow_n(4));
+        assert!(!window.allow_n(7));
+        window.refund(1);
+        assert_eq!(
+            window.window_stats(),
+            WindowStats {
+                start: Duration::ZERO,
+                interval: INTERVAL,
+                count: 3,
+            }
+        );
+        assert!(closed.lock().unwrap().is_empty());
+
+        // the end of a window is noticed by the next call, whichever it is
+        clock.advance(INTERVAL);
+        assert_eq!(window.remaining(), 10);
+        assert!(window.allow_n(2));
+
+        // windows passing without any call are not reported
+        clock.advance(INTERVAL * 5 + INTERVAL / 2);
+        assert_eq!(window.window_start(), INTERVAL * 6);
+        assert_eq!(window.window_stats().count, 0);
+
+        let counts: Vec<_> = closed
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|stats| (stats.start, stats.count))
+            .collect();
+        assert_eq!(counts, [(Duration::ZERO, 3), (INTERVAL, 2)]);
+    }
+
     #[test]
     fn fixed_window_should_work() {
         const SIZE: u64 = 10;
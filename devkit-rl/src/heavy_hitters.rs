@@ -0,0 +1,229 @@
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    hash::Hash,
+    sync::Arc,
+};
+
+use crate::{
+    sync::{Mutex, MutexExt},
+    Observer,
+};
+
+/// A key seen often by a [`HeavyHitters`], with an estimate of its requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeavyHitter<K> {
+    /// The key.
+    pub key: K,
+    /// The requests counted for the key, never fewer than it made since it is
+    /// tracked, and at most `error` more.
+    pub count: u64,
+    /// The most requests of other keys that may be counted in `count`. The key made
+    /// at least `count - error` requests.
+    pub error: u64,
+}
+
+/// A sketch tracking the most active keys in bounded memory, with the
+/// space-saving algorithm.
+///
+/// Operators want to know which clients consume the quota, but a limiter facing
+/// millions of keys cannot count the requests of each of them. A `HeavyHitters`
+/// counts the requests of at most `capacity` keys: a key seen while all the counters
+/// are taken replaces the key with the lowest count, and inherits its count. Every
+/// key making more than `1 / capacity` of the requests is then tracked, with a
+/// count overestimated by at most the lowest count, reported as its
+/// [`error`](HeavyHitter::error).
+///
+/// The sketch is an [`Observer`] counting the requests made, allowed or denied,
+/// or is attached to a [`KeyedLimiter`](crate::KeyedLimiter) with
+/// [`with_heavy_hitters`](crate::KeyedLimiter::with_heavy_hitters) to report the
+/// top keys in its stats. Clones of a sketch share their counters.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::HeavyHitters;
+///
+/// let hitters = HeavyHitters::new(2);
+/// for key in ["a", "b", "a", "c", "a", "b", "a"] {
+///     hitters.record(&key, 1);
+/// }
+///
+/// let top = hitters.top(1);
+/// assert_eq!((top[0].key, top[0].count), ("a", 4));
+/// // "b" took over the counter of "c", whose requests may be counted for it
+/// let b = &hitters.top(2)[1];
+/// assert_eq!((b.key, b.count, b.error), ("b", 3, 2));
+/// ```
+pub struct HeavyHitters<K> {
+    inner: Arc<Mutex<HeavyHittersInner<K>>>,
+}
+
+struct HeavyHittersInner<K> {
+    /// The most keys tracked.
+    capacity: usize,
+    /// The counter of every tracked key.
+    counters: HashMap<K, Counter>,
+    /// The tracked keys by count and age of their counter, lowest count first.
+    order: BTreeMap<(u64, u64), K>,
+    next_id: u64,
+}
+
+/// The count of a tracked key.
+#[derive(Debug, Clone, Copy)]
+struct Counter {
+    count: u64,
+    error: u64,
+    /// Tells apart the counters with the same count in the order.
+    id: u64,
+}
+
+impl<K: Hash + Eq + Clone> HeavyHitters<K> {
+    /// Creates a new `HeavyHitters` tracking at most `capacity` keys, at least 1.
+    ///
+    /// A few times the number of keys of interest keeps their counts accurate.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(HeavyHittersInner {
+                capacity: capacity.max(1),
+                counters: HashMap::new(),
+                order: BTreeMap::new(),
+                next_id: 0,
+            })),
+        }
+    }
+
+    /// Counts `n` requests for `key`.
+    ///
+    /// `key` may be any borrowed form of the key type, only converted to an owned
+    /// key when it starts being tracked.
+    pub fn record<Q>(&self, key: &Q, n: u64)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let mut inner = self.inner.lock_unpoisoned();
+        let inner = &mut *inner;
+        if let Some(counter) = inner.counters.get_mut(key) {
+            let key = inner
+                .order
+                .remove(&(counter.count, counter.id))
+                .expect("tracked keys are ordered");
+            counter.count = counter.count.saturating_add(n);
+            inner.order.insert((counter.count, counter.id), key);
+            return;
+        }
+
+        // the new key takes over the counter of the least active one
+        let floor = if inner.counters.len() < inner.capacity {
+            0
+        } else {
+            let ((count, _), evicted) = inner.order.pop_first().expect("the sketch is full");
+            inner.counters.remove::<K>(&evicted);
+            count
+        };
+        let counter = Counter {
+            count: floor.saturating_add(n),
+            error: floor,
+            id: inner.next_id,
+        };
+        inner.next_id += 1;
+        let key = key.to_owned();
+        inner.order.insert((counter.count, counter.id), key.clone());
+        inner.counters.insert(key, counter);
+    }
+
+    /// Returns the `k` keys with the highest counts, highest first.
+    pub fn top(&self, k: usize) -> Vec<HeavyHitter<K>> {
+        let inner = self.inner.lock_unpoisoned();
+        inner
+            .order
+            .values()
+            .rev()
+            .take(k)
+            .map(|key| {
+                let counter = inner.counters[key];
+                HeavyHitter {
+                    key: key.clone(),
+                    count: counter.count,
+                    error: counter.error,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the most keys tracked.
+    pub fn capacity(&self) -> usize {
+        self.inner.lock_unpoisoned().capacity
+    }
+
+    /// Forgets every key, e.g. to count the requests of a new period.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.counters.clear();
+        inner.order.clear();
+    }
+}
+
+impl<K: Hash + Eq + Clone + Send + Sync> Observer<K> for HeavyHitters<K> {
+    fn on_allowed(&self, key: &K, n: u64) {
+        self.record(key, n);
+    }
+
+    fn on_denied(&self, key: &K, n: u64) {
+        self.record(key, n);
+    }
+}
+
+impl<K> Clone for HeavyHitters<K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K> fmt::Debug for HeavyHitters<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.lock_unpoisoned();
+        f.debug_struct("HeavyHitters")
+            .field("capacity", &inner.capacity)
+            .field("tracked", &inner.counters.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heavy_hitters_should_keep_the_most_active_keys() {
+        let hitters = HeavyHitters::new(8);
+
+        // 3 hot keys among a long tail of keys seen once
+        for i in 0..1_000u64 {
+            hitters.record(
+                &["hot-1", "hot-2", "hot-3"][(i % 3) as usize].to_string(),
+                10,
+            );
+            hitters.record(&format!("cold-{i}"), 1);
+        }
+        hitters.record("hot-1", 100);
+
+        assert_eq!(hitters.top(10).len(), 8);
+        let top = hitters.top(3);
+        assert_eq!(top[0].key, "hot-1");
+        for hitter in &top {
+            // the counts are never underestimated, and overestimated by the error
+            let actual = if hitter.key == "hot-1" { 3_440 } else { 3_330 };
+            assert!(hitter.count >= actual, "{hitter:?}");
+            assert!(hitter.count - hitter.error <= actual, "{hitter:?}");
+        }
+
+        hitters.clear();
+        assert!(hitters.top(10).is_empty());
+        assert_eq!(hitters.capacity(), 8);
+    }
+}
@@ -0,0 +1,407 @@
+use std::{collections::VecDeque, fmt, sync::Arc, time::Duration};
+
+use crate::{
+    clock::SharedClock,
+    simulate::{Arrival, Trace},
+    sync::{Mutex, MutexExt},
+    Clock, Error, Observer, RateLimiter, SystemClock,
+};
+
+/// The outcome of a decision recorded in a [`Journal`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Outcome {
+    /// The requests were allowed.
+    Allowed,
+    /// The requests exceeded the limit.
+    RateLimited,
+    /// The requests exceeded the queue of a queuing limiter.
+    QueueFull,
+    /// The limiter could not make a decision, e.g. because its backend failed.
+    Failed,
+}
+
+impl Outcome {
+    /// Returns the outcome of a decision returning `result`.
+    pub fn of(result: &Result<(), Error>) -> Self {
+        match result {
+            Ok(()) => Outcome::Allowed,
+            Err(Error::RateLimited) => Outcome::RateLimited,
+            Err(Error::QueueFull) => Outcome::QueueFull,
+            Err(_) => Outcome::Failed,
+        }
+    }
+
+    /// Returns whether the requests were allowed.
+    pub fn is_allowed(&self) -> bool {
+        *self == Outcome::Allowed
+    }
+}
+
+/// A decision recorded in a [`Journal`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decision<K = ()> {
+    /// When the decision was made, by the clock of the journal.
+    pub at: Duration,
+    /// The key the requests were made for.
+    pub key: K,
+    /// The number of requests.
+    pub n: u64,
+    /// What was decided.
+    pub outcome: Outcome,
+    /// The requests the limiter still allowed right after the decision, if it can
+    /// tell, see [`RateLimiter::remaining`].
+    pub remaining: Option<u64>,
+}
+
+impl<K: fmt::Debug> fmt::Display for Decision<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.3}s {:?} n={} {:?}",
+            self.at.as_secs_f64(),
+            self.key,
+            self.n,
+            self.outcome
+        )?;
+        match self.remaining {
+            Some(remaining) => write!(f, " remaining={remaining}"),
+            None => Ok(()),
+        }
+    }
+}
+
+type RecordHook<K> = Box<dyn Fn(&Decision<K>) + Send + Sync>;
+
+/// A record of the decisions of a limiter, to find out after the fact why a
+/// request was denied.
+///
+/// The journal keeps the last `capacity` decisions in a ring buffer, and may also
+/// hand every decision to a callback, e.g. to ship it to a log. The decisions kept
+/// can be dumped with [`Journal::entries`], each printing as a line, or replayed
+/// into the [simulator](crate::simulate) with [`Journal::to_trace`] to try another
+/// configuration on the same traffic.
+///
+/// A journal records the decisions of a limiter wrapped in [`Journaled`], or of a
+/// [`KeyedLimiter`](crate::KeyedLimiter) as its [observer](crate::KeyedLimiter::with_observer),
+/// which records every denial as rate limited and does not know the remaining
+/// requests. By default, the decisions are timestamped by a [`SystemClock`], in
+/// unix time. Clones of a journal share their records.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{Journal, Journaled, Outcome, RateLimiter, TokenBucket};
+///
+/// let journal = Journal::new(100);
+/// let limiter = Journaled::new(TokenBucket::new(1, 1, None), journal.clone());
+///
+/// assert!(limiter.allow());
+/// assert!(!limiter.allow());
+///
+/// let entries = journal.entries();
+/// assert_eq!(entries[1].outcome, Outcome::RateLimited);
+/// assert_eq!(entries[1].remaining, Some(0));
+/// for entry in &entries {
+///     println!("{entry}"); // e.g. `1718000000.123s () n=1 RateLimited remaining=0`
+/// }
+/// ```
+pub struct Journal<K = ()> {
+    inner: Arc<Mutex<JournalInner<K>>>,
+    hook: Option<Arc<RecordHook<K>>>,
+}
+
+struct JournalInner<K> {
+    /// The maximum number of decisions kept.
+    capacity: usize,
+    /// The decisions kept, oldest first.
+    entries: VecDeque<Decision<K>>,
+    /// The source of time.
+    clock: SharedClock,
+}
+
+impl<K: Clone> Journal<K> {
+    /// Creates a new `Journal` keeping the last `capacity` decisions, timestamped in
+    /// unix time.
+    ///
+    /// A capacity of 0 keeps nothing, which suits a journal only calling
+    /// [`Journal::on_record`].
+    pub fn new(capacity: usize) -> Self {
+        Self::from_clock(capacity, SharedClock::new(Arc::new(SystemClock::new())))
+    }
+
+    /// Creates a new `Journal` timestamping the decisions by `clock`.
+    pub fn with_clock(capacity: usize, clock: Arc<dyn Clock>) -> Self {
+        Self::from_clock(capacity, SharedClock::new(clock))
+    }
+
+    fn from_clock(capacity: usize, clock: SharedClock) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(JournalInner {
+                capacity,
+                entries: VecDeque::new(),
+                clock,
+            })),
+            hook: None,
+        }
+    }
+
+    /// Calls `f` with every decision as it is recorded, whether it is kept or not.
+    ///
+    /// `f` is called on the thread making the request, outside of any lock.
+    pub fn on_record(mut self, f: impl Fn(&Decision<K>) + Send + Sync + 'static) -> Self {
+        self.hook = Some(Arc::new(Box::new(f)));
+        self
+    }
+
+    /// Records a decision on `n` requests for `key`, made now.
+    pub fn record(&self, key: &K, n: u64, outcome: Outcome, remaining: Option<u64>) {
+        let decision = {
+            let mut inner = self.inner.lock_unpoisoned();
+            let decision = Decision {
+                at: inner.clock.now(),
+                key: key.clone(),
+                n,
+                outcome,
+                remaining,
+            };
+            if inner.capacity > 0 {
+                if inner.entries.len() == inner.capacity {
+                    inner.entries.pop_front();
+                }
+                inner.entries.push_back(decision.clone());
+            }
+            decision
+        };
+        if let Some(hook) = &self.hook {
+            hook(&decision);
+        }
+    }
+
+    /// Returns the decisions kept, oldest first.
+    pub fn entries(&self) -> Vec<Decision<K>> {
+        self.inner
+            .lock_unpoisoned()
+            .entries
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the decisions kept made from `from`, inclusive, to `to`, exclusive.
+    pub fn between(&self, from: Duration, to: Duration) -> Vec<Decision<K>> {
+        let inner = self.inner.lock_unpoisoned();
+        inner
+            .entries
+            .iter()
+            .filter(|d| (from..to).contains(&d.at))
+            .cloned()
+            .collect()
+    }
+
+    /// Forgets the decisions kept.
+    pub fn clear(&self) {
+        self.inner.lock_unpoisoned().entries.clear();
+    }
+
+    /// Returns the requests of the decisions kept as a trace of the
+    /// [simulator](crate::simulate), starting at the first one.
+    ///
+    /// The trace holds the requests whatever was decided, so that replaying it
+    /// through another limiter shows what that one would have decided.
+    pub fn to_trace(&self) -> Trace {
+        self.trace_where(|_| true)
+    }
+
+    /// Like [`Journal::to_trace`], keeping only the requests for `key`.
+    pub fn trace_of(&self, key: &K) -> Trace
+    where
+        K: PartialEq,
+    {
+        self.trace_where(|d| d.key == *key)
+    }
+
+    fn trace_where(&self, keep: impl Fn(&Decision<K>) -> bool) -> Trace {
+        let inner = self.inner.lock_unpoisoned();
+        let kept = inner.entries.iter().filter(|d| keep(d));
+        let start = kept.clone().map(|d| d.at).min().unwrap_or_default();
+        Trace::new(
+            kept.map(|d| Arrival {
+                at: d.at - start,
+                weight: d.n,
+            })
+            .collect(),
+        )
+    }
+}
+
+impl<K: Clone + Send + Sync> Observer<K> for Journal<K> {
+    fn on_allowed(&self, key: &K, n: u64) {
+        self.record(key, n, Outcome::Allowed, None);
+    }
+
+    fn on_denied(&self, key: &K, n: u64) {
+        self.record(key, n, Outcome::RateLimited, None);
+    }
+}
+
+impl<K> Clone for Journal<K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            hook: self.hook.clone(),
+        }
+    }
+}
+
+impl<K> fmt::Debug for Journal<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.lock_unpoisoned();
+        f.debug_struct("Journal")
+            .field("capacity", &inner.capacity)
+            .field("entries", &inner.entries.len())
+            .field("on_record", &self.hook.is_some())
+            .finish()
+    }
+}
+
+/// A rate limiter recording its decisions in a [`Journal`].
+///
+/// `Journaled` wraps any [`RateLimiter`] and implements it in turn, recording the
+/// requests left after every decision. See [`Journal`] for an example.
+#[derive(Debug)]
+pub struct Journaled<L> {
+    limiter: L,
+    journal: Journal,
+}
+
+impl<L: RateLimiter> Journaled<L> {
+    /// Creates a new `Journaled` recording the decisions of `limiter` in `journal`.
+    pub fn new(limiter: L, journal: Journal) -> Self {
+        Self { limiter, journal }
+    }
+
+    /// Returns the wrapped limiter.
+    ///
+    /// Decisions made on it directly are not recorded.
+    pub fn limiter(&self) -> &L {
+        &self.limiter
+    }
+
+    /// Returns the journal the decisions are recorded in.
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+}
+
+impl<L: RateLimiter> RateLimiter for Journaled<L> {
+    fn allow_n(&self, n: u64) -> bool {
+        self.try_check(n).is_ok()
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        self.limiter.next_available(n)
+    }
+
+    fn try_check(&self, n: u64) -> Result<(), Error> {
+        let result = self.limiter.try_check(n);
+        let remaining = self.limiter.remaining();
+        self.journal.record(&(), n, Outcome::of(&result), remaining);
+        result
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        self.limiter.remaining()
+    }
+
+    fn window_start(&self) -> Option<Duration> {
+        self.limiter.window_start()
+    }
+
+    fn refund(&self, n: u64) {
+        self.limiter.refund(n);
+    }
+}
+
+#[cfg(all(test, any(feature = "token-bucket", feature = "fixed-window")))]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+    #[cfg(feature = "token-bucket")]
+    use crate::{simulate, TokenBucket};
+    #[cfg(feature = "fixed-window")]
+    use crate::{KeyedLimiter, LimiterConfig};
+
+    #[cfg(feature = "token-bucket")]
+    #[test]
+    fn journal_should_record_decisions_for_postmortems() {
+        let clock = Arc::new(ManualClock::new());
+        let journal = Journal::with_clock(3, clock.clone());
+        let limiter = Journaled::new(
+            TokenBucket::with_clock(2, 1, Some(Duration::from_secs(1)), clock.clone()),
+            journal.clone(),
+        );
+
+        assert!(limiter.allow());
+        clock.advance(Duration::from_millis(100));
+        assert!(limiter.allow());
+        assert!(!limiter.allow());
+        clock.advance(Duration::from_millis(900));
+        assert!(limiter.allow());
+
+        // the oldest decision is dropped
+        let entries = journal.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[1],
+            Decision {
+                at: Duration::from_millis(100),
+                key: (),
+                n: 1,
+                outcome: Outcome::RateLimited,
+                remaining: Some(0),
+            }
+        );
+        assert_eq!(
+            entries[1].to_string(),
+            "0.100s () n=1 RateLimited remaining=0"
+        );
+        let second = journal.between(Duration::from_secs(1), Duration::from_secs(2));
+        assert_eq!(second.len(), 1);
+        assert!(second[0].outcome.is_allowed());
+
+        // replaying the requests through a larger bucket admits them all
+        let report = simulate::run(&journal.to_trace(), Duration::from_secs(1), |clock| {
+            TokenBucket::with_clock(3, 1, Some(Duration::from_secs(1)), clock)
+        });
+        assert_eq!(report.admitted, 3);
+        journal.clear();
+        assert!(journal.entries().is_empty());
+    }
+
+    #[cfg(feature = "fixed-window")]
+    #[test]
+    fn journal_should_observe_keyed_limiters() {
+        let clock = Arc::new(ManualClock::new());
+        let shipped = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let journal = Journal::with_clock(0, clock.clone()).on_record({
+            let shipped = shipped.clone();
+            move |d: &Decision<String>| shipped.lock().unwrap().push(d.to_string())
+        });
+        let limiter = KeyedLimiter::new(LimiterConfig::FixedWindow {
+            size: 1,
+            interval_ms: Some(60_000),
+            smoothing: false,
+        })
+        .with_observer(Arc::new(journal.clone()));
+
+        assert!(limiter.allow("a"));
+        clock.advance(Duration::from_secs(1));
+        assert!(!limiter.allow("a"));
+        assert!(journal.entries().is_empty());
+        assert_eq!(
+            *shipped.lock().unwrap(),
+            ["0.000s \"a\" n=1 Allowed", "1.000s \"a\" n=1 RateLimited"]
+        );
+    }
+}
@@ -0,0 +1,494 @@
+use std::{
+    borrow::Borrow,
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hash},
+    mem::size_of,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    sync::{Mutex, MutexExt},
+    Limiter,
+};
+
+/// Where a [`KeyedLimiter`](crate::KeyedLimiter) keeps the limiters of its keys.
+///
+/// The store owns the entries and their synchronization: every method takes
+/// `&self`, so a store may lock a single map, a shard of it, or nothing at all. The
+/// keyed limiter calls the closures given to the store while the entry is locked,
+/// and they are short.
+///
+/// [`HashMapStore`] is the default, a map behind a single lock. [`ShardedStore`]
+/// splits the keys over several locks, for limiters shared by many threads.
+/// Implementing the trait plugs in another eviction scheme, e.g. a store bounded
+/// to its most recently seen keys, or persistence.
+///
+/// A store evicting entries on its own should keep those that are
+/// [held](KeyEntry::is_held), whose keys are expected to be tracked until their
+/// last [`KeyHandle`](crate::KeyHandle) is dropped.
+pub trait KeyStore<K> {
+    /// Calls `f` on the entry of `key`, inserting the entry made by `create` first
+    /// if there is none.
+    fn get_or_create<Q, R>(
+        &self,
+        key: &Q,
+        create: impl FnOnce() -> KeyEntry,
+        f: impl FnOnce(&mut KeyEntry) -> R,
+    ) -> R
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized;
+
+    /// Calls `f` on the entry of `key`, if there is one.
+    fn get<Q, R>(&self, key: &Q, f: impl FnOnce(&mut KeyEntry) -> R) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
+    /// Removes the entry of `key` if `f` returns `true` for it.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the entry was removed.
+    fn remove_if<Q>(&self, key: &Q, f: impl FnOnce(&mut KeyEntry) -> bool) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
+    /// Keeps only the entries for which `f` returns `true`.
+    fn retain(&self, f: impl FnMut(&K, &mut KeyEntry) -> bool);
+
+    /// Calls `f` on every entry.
+    fn for_each(&self, f: impl FnMut(&K, &KeyEntry));
+
+    /// Returns the number of entries.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if the store has no entries.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Estimates the memory held by the store for its entries, in bytes, not
+    /// counting the heap data of the limiters and of the keys.
+    fn memory_bytes(&self) -> usize {
+        self.len() * size_of::<(K, KeyEntry)>()
+    }
+}
+
+/// The state of a key of a [`KeyedLimiter`](crate::KeyedLimiter), kept by its
+/// [`KeyStore`].
+#[derive(Debug)]
+pub struct KeyEntry {
+    pub(crate) limiter: Limiter,
+    /// When the key was last seen.
+    pub(crate) last_seen: Instant,
+    /// The start of the window of the limiter seen by the last request, if observed.
+    pub(crate) window: Option<Duration>,
+    /// The number of live [`KeyHandle`](crate::KeyHandle)s of the key.
+    pub(crate) handles: usize,
+    /// Until when the requests of the key are denied without checking its limiter,
+    /// if a deny cache is set.
+    pub(crate) denied_until: Option<Instant>,
+}
+
+impl KeyEntry {
+    /// Returns the limiter of the key.
+    pub fn limiter(&self) -> &Limiter {
+        &self.limiter
+    }
+
+    /// Returns when the key was last seen.
+    pub fn last_seen(&self) -> Instant {
+        self.last_seen
+    }
+
+    /// Returns `true` if the key has live [`KeyHandle`](crate::KeyHandle)s.
+    pub fn is_held(&self) -> bool {
+        self.handles > 0
+    }
+}
+
+/// A [`KeyStore`] keeping its entries in a single map behind a lock, the default
+/// store of a [`KeyedLimiter`](crate::KeyedLimiter).
+///
+/// The keys are hashed with `S`, see [`KeyedLimiter::with_hasher`](crate::KeyedLimiter::with_hasher).
+#[derive(Debug)]
+pub struct HashMapStore<K, S = RandomState> {
+    map: Mutex<HashMap<K, KeyEntry, S>>,
+}
+
+impl<K> HashMapStore<K> {
+    /// Creates a new empty `HashMapStore`.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K> Default for HashMapStore<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, S> HashMapStore<K, S> {
+    /// Creates a new empty `HashMapStore` hashing its keys with `hasher`.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            map: Mutex::new(HashMap::with_hasher(hasher)),
+        }
+    }
+}
+
+impl<K: Hash + Eq, S: BuildHasher> KeyStore<K> for HashMapStore<K, S> {
+    fn get_or_create<Q, R>(
+        &self,
+        key: &Q,
+        create: impl FnOnce() -> KeyEntry,
+        f: impl FnOnce(&mut KeyEntry) -> R,
+    ) -> R
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        get_or_create(&mut self.map.lock_unpoisoned(), key, create, f)
+    }
+
+    fn get<Q, R>(&self, key: &Q, f: impl FnOnce(&mut KeyEntry) -> R) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.lock_unpoisoned().get_mut(key).map(f)
+    }
+
+    fn remove_if<Q>(&self, key: &Q, f: impl FnOnce(&mut KeyEntry) -> bool) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        remove_if(&mut self.map.lock_unpoisoned(), key, f)
+    }
+
+    fn retain(&self, f: impl FnMut(&K, &mut KeyEntry) -> bool) {
+        self.map.lock_unpoisoned().retain(f);
+    }
+
+    fn for_each(&self, mut f: impl FnMut(&K, &KeyEntry)) {
+        self.map.lock_unpoisoned().iter().for_each(|(k, e)| f(k, e));
+    }
+
+    fn len(&self) -> usize {
+        self.map.lock_unpoisoned().len()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        table_bytes(&self.map.lock_unpoisoned())
+    }
+}
+
+/// A [`KeyStore`] splitting its entries over several maps, each behind its own
+/// lock, so that threads looking up different keys rarely wait for each other.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{KeyedLimiter, LimiterConfig, ShardedStore};
+///
+/// let limiter = KeyedLimiter::with_store(
+///     LimiterConfig::TokenBucket {
+///         capacity: 1,
+///         refill_rate: 1,
+///         refill_interval_ms: None,
+///         initial_tokens: None,
+///     },
+///     None,
+///     ShardedStore::new(16),
+/// );
+///
+/// assert!(limiter.allow(&"10.0.0.1"));
+/// assert!(!limiter.allow(&"10.0.0.1"));
+/// ```
+#[derive(Debug)]
+pub struct ShardedStore<K, S = RandomState> {
+    shards: Box<[Mutex<HashMap<K, KeyEntry, S>>]>,
+    hasher: S,
+}
+
+impl<K> ShardedStore<K> {
+    /// Creates a new empty `ShardedStore` of `shards` maps, at least 1.
+    ///
+    /// A few times the number of threads sharing the limiter is a good start.
+    pub fn new(shards: usize) -> Self {
+        Self::with_hasher(shards, RandomState::new())
+    }
+}
+
+impl<K, S: Clone> ShardedStore<K, S> {
+    /// Creates a new empty `ShardedStore` of `shards` maps hashing their keys with
+    /// `hasher`.
+    pub fn with_hasher(shards: usize, hasher: S) -> Self {
+        Self {
+            shards: (0..shards.max(1))
+                .map(|_| Mutex::new(HashMap::with_hasher(hasher.clone())))
+                .collect(),
+            hasher,
+        }
+    }
+}
+
+impl<K: Hash + Eq, S: BuildHasher> ShardedStore<K, S> {
+    /// Returns the shard of `key`.
+    fn shard<Q: Hash + ?Sized>(&self, key: &Q) -> &Mutex<HashMap<K, KeyEntry, S>> {
+        let hash = self.hasher.hash_one(key);
+        &self.shards[(hash % self.shards.len() as u64) as usize]
+    }
+}
+
+impl<K: Hash + Eq, S: BuildHasher> KeyStore<K> for ShardedStore<K, S> {
+    fn get_or_create<Q, R>(
+        &self,
+        key: &Q,
+        create: impl FnOnce() -> KeyEntry,
+        f: impl FnOnce(&mut KeyEntry) -> R,
+    ) -> R
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        get_or_create(&mut self.shard(key).lock_unpoisoned(), key, create, f)
+    }
+
+    fn get<Q, R>(&self, key: &Q, f: impl FnOnce(&mut KeyEntry) -> R) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).lock_unpoisoned().get_mut(key).map(f)
+    }
+
+    fn remove_if<Q>(&self, key: &Q, f: impl FnOnce(&mut KeyEntry) -> bool) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        remove_if(&mut self.shard(key).lock_unpoisoned(), key, f)
+    }
+
+    fn retain(&self, mut f: impl FnMut(&K, &mut KeyEntry) -> bool) {
+        for shard in self.shards.iter() {
+            shard.lock_unpoisoned().retain(&mut f);
+        }
+    }
+
+    fn for_each(&self, mut f: impl FnMut(&K, &KeyEntry)) {
+        for shard in self.shards.iter() {
+            shard.lock_unpoisoned().iter().for_each(|(k, e)| f(k, e));
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.lock_unpoisoned().len()).sum()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|s| {
+                table_bytes(&s.lock_unpoisoned()) + size_of::<Mutex<HashMap<K, KeyEntry, S>>>()
+            })
+            .sum()
+    }
+}
+
+fn get_or_create<K, Q, S, R>(
+    map: &mut HashMap<K, KeyEntry, S>,
+    key: &Q,
+    create: impl FnOnce() -> KeyEntry,
+    f: impl FnOnce(&mut KeyEntry) -> R,
+) -> R
+where
+    K: Hash + Eq + Borrow<Q>,
+    Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    S: BuildHasher,
+{
+    if let Some(entry) = map.get_mut(key) {
+        return f(entry);
+    }
+    f(map.entry(key.to_owned()).or_insert_with(create))
+}
+
+fn remove_if<K, Q, S>(
+    map: &mut HashMap<K, KeyEntry, S>,
+    key: &Q,
+    f: impl FnOnce(&mut KeyEntry) -> bool,
+) -> bool
+where
+    K: Hash + Eq + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    S: BuildHasher,
+{
+    let remove = map.get_mut(key).is_some_and(f);
+    remove && map.remove(key).is_some()
+}
+
+/// Estimates the memory held by the table of `map`, one control byte per bucket
+/// included.
+fn table_bytes<K, S>(map: &HashMap<K, KeyEntry, S>) -> usize {
+    map.capacity() * (size_of::<(K, KeyEntry)>() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Unlimited;
+    #[cfg(feature = "token-bucket")]
+    use crate::{KeyedLimiter, LimiterConfig};
+
+    fn entry() -> KeyEntry {
+        KeyEntry {
+            limiter: Limiter::Unlimited(Unlimited::new()),
+            last_seen: Instant::now(),
+            window: None,
+            handles: 0,
+            denied_until: None,
+        }
+    }
+
+    /// Runs the scenario every store has to pass.
+    fn assert_key_store_semantics(store: &impl KeyStore<String>) {
+        assert!(store.is_empty());
+        let empty = store.memory_bytes();
+
+        // the entry is created once, and found again after
+        assert!(store.get_or_create("a", entry, |e| e.handles == 0));
+        store.get_or_create("a", || unreachable!(), |e| e.handles = 1);
+        store.get_or_create("b", entry, |_| {});
+        store.get_or_create("c", entry, |_| {});
+        assert_eq!(store.get("a", |e| e.is_held()), Some(true));
+        assert_eq!(store.get("unknown", |e| e.is_held()), None);
+        assert_eq!(store.len(), 3);
+        assert!(store.memory_bytes() >= empty + 3 * size_of::<(String, KeyEntry)>());
+
+        // only the entries matching the predicate are removed
+        assert!(!store.remove_if("a", |e| !e.is_held()));
+        assert!(store.remove_if("b", |e| !e.is_held()));
+        assert!(!store.remove_if("b", |_| true));
+        assert_eq!(store.len(), 2);
+
+        store.retain(|_, e| e.is_held());
+        let mut keys = Vec::new();
+        store.for_each(|k, _| keys.push(k.clone()));
+        assert_eq!(keys, ["a"]);
+        assert!(!store.is_empty());
+    }
+
+    #[test]
+    fn hash_map_store_should_follow_key_store_semantics() {
+        let store = HashMapStore::new();
+        assert_eq!(store.memory_bytes(), 0);
+        assert_key_store_semantics(&store);
+    }
+
+    #[test]
+    fn sharded_store_should_follow_key_store_semantics() {
+        let store = ShardedStore::new(4);
+        // the locks of the shards are counted, even when empty
+        assert_eq!(
+            store.memory_bytes(),
+            4 * size_of::<Mutex<HashMap<String, KeyEntry>>>()
+        );
+        assert_key_store_semantics(&store);
+        assert_key_store_semantics(&ShardedStore::new(0));
+    }
+
+    /// A store keeping only its `max` most recently seen keys that are not held.
+    #[cfg(feature = "token-bucket")]
+    struct RecentStore {
+        map: HashMapStore<&'static str>,
+        max: usize,
+    }
+
+    #[cfg(feature = "token-bucket")]
+    impl KeyStore<&'static str> for RecentStore {
+        fn get_or_create<Q, R>(
+            &self,
+            key: &Q,
+            create: impl FnOnce() -> KeyEntry,
+            f: impl FnOnce(&mut KeyEntry) -> R,
+        ) -> R
+        where
+            &'static str: Borrow<Q>,
+            Q: Hash + Eq + ToOwned<Owned = &'static str> + ?Sized,
+        {
+            if self.map.get(key, |_| ()).is_none() && self.map.len() >= self.max {
+                let mut oldest: Option<(&'static str, Instant)> = None;
+                self.map.for_each(|k, e| {
+                    if !e.is_held() && oldest.is_none_or(|(_, at)| e.last_seen < at) {
+                        oldest = Some((k, e.last_seen));
+                    }
+                });
+                if let Some((oldest, _)) = oldest {
+                    self.map.remove_if::<&str>(&oldest, |_| true);
+                }
+            }
+            self.map.get_or_create(key, create, f)
+        }
+
+        fn get<Q, R>(&self, key: &Q, f: impl FnOnce(&mut KeyEntry) -> R) -> Option<R>
+        where
+            &'static str: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            self.map.get(key, f)
+        }
+
+        fn remove_if<Q>(&self, key: &Q, f: impl FnOnce(&mut KeyEntry) -> bool) -> bool
+        where
+            &'static str: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            self.map.remove_if(key, f)
+        }
+
+        fn retain(&self, f: impl FnMut(&&'static str, &mut KeyEntry) -> bool) {
+            self.map.retain(f);
+        }
+
+        fn for_each(&self, f: impl FnMut(&&'static str, &KeyEntry)) {
+            self.map.for_each(f);
+        }
+
+        fn len(&self) -> usize {
+            self.map.len()
+        }
+    }
+
+    #[cfg(feature = "token-bucket")]
+    #[test]
+    fn keyed_limiter_should_work_behind_an_evicting_store() {
+        let limiter = KeyedLimiter::with_store(
+            LimiterConfig::TokenBucket {
+                capacity: 1,
+                refill_rate: 1,
+                refill_interval_ms: Some(60_000),
+                initial_tokens: None,
+            },
+            None,
+            RecentStore {
+                map: HashMapStore::new(),
+                max: 2,
+            },
+        );
+
+        assert!(limiter.allow(&"a"));
+        assert!(!limiter.allow(&"a"));
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(limiter.allow(&"b"));
+        // a third key evicts the least recently seen, whose quota starts over
+        assert!(limiter.allow(&"c"));
+        assert_eq!(limiter.len(), 2);
+        assert!(limiter.allow(&"a"));
+        assert!(!limiter.allow(&"c"));
+    }
+}
@@ -0,0 +1,1222 @@
+use std::{
+    borrow::Borrow,
+    hash::{BuildHasher, Hash},
+    mem::size_of,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    limiter::whole_cost,
+    observer::notify,
+    sync::atomic::{AtomicU64, Ordering},
+    Error, HashMapStore, HeavyHitter, HeavyHitters, KeyEntry, KeyStore, Limiter, LimiterConfig,
+    Observer, RateLimiter, RequestCost, Worker,
+};
+
+/// A rate limiter keeping a separate limit for every key.
+///
+/// Each key gets its own limiter, built from the same [`LimiterConfig`] the first
+/// time the key is seen. This is the usual building block for per-client limits,
+/// keyed by IP address, API token, route, and so on.
+///
+/// Since every key gets its own limiter, blocking algorithms such as the leaky
+/// bucket, which runs a thread per limiter, are a poor fit for large key spaces.
+///
+/// Keys are kept until they are removed, unless the limiter is created with
+/// [`KeyedLimiter::with_idle_ttl`], which evicts the keys that have not been seen
+/// for a while. [`KeyedLimiter::stats`] reports how many keys are tracked, so that
+/// a blow-up of the key space, e.g. from a client rotating addresses, is visible,
+/// and with [`KeyedLimiter::with_heavy_hitters`], which keys make the most requests.
+///
+/// Keys may be composite, e.g. `(tenant, route, method)` tuples, and are hashed
+/// with the SipHash of the standard library by default; see
+/// [`KeyedLimiter::with_hasher`] to plug a faster one such as ahash or fxhash. Keys
+/// are looked up by any borrowed form, so a limiter keyed by `String` checks a
+/// `&str` without allocating a `String` per request.
+///
+/// The limiters of the keys are kept in `S`, a [`KeyStore`]: a single map behind
+/// a lock by default, see [`KeyedLimiter::with_store`] for the others.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{KeyedLimiter, LimiterConfig};
+///
+/// let limiter = KeyedLimiter::new(LimiterConfig::TokenBucket {
+///     capacity: 1,
+///     refill_rate: 1,
+///     refill_interval_ms: None,
+///     initial_tokens: None,
+/// });
+///
+/// assert!(limiter.allow(&"10.0.0.1"));
+/// assert!(!limiter.allow(&"10.0.0.1"));
+/// assert!(limiter.allow(&"10.0.0.2"));
+/// ```
+#[derive(Debug)]
+pub struct KeyedLimiter<K, S = HashMapStore<K>> {
+    inner: Arc<KeyedLimiterInner<S>>,
+    observer: Option<Arc<dyn Observer<K>>>,
+    heavy_hitters: Option<HeavyHitters<K>>,
+    /// The shortest wait of a denied key for its denials to be cached, if cached.
+    deny_cache: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct KeyedLimiterInner<S> {
+    /// The configuration every per-key limiter is built from.
+    config: LimiterConfig,
+    /// The limiters of the keys seen so far.
+    store: S,
+    /// How long a key may stay unseen before it is evicted, if ever.
+    idle_ttl: Option<Duration>,
+    /// The time `next_sweep` is counted from.
+    origin: Instant,
+    /// When the keys are next swept on access, in nanoseconds since `origin`.
+    next_sweep: AtomicU64,
+    /// The number of keys evicted for being idle so far.
+    evictions: AtomicU64,
+}
+
+/// The limiter of a key, as looked up in the store.
+struct Lookup {
+    limiter: Limiter,
+    /// Whether a new window of the limiter has started since the last request for
+    /// the key, if observed.
+    reset: bool,
+    /// Whether the requests of the key are denied from the deny cache.
+    denied: bool,
+}
+
+/// A handle on the limiter of one key of a [`KeyedLimiter`], keeping the key alive.
+///
+/// A key with handles is never evicted for being idle, and is forgotten as soon as
+/// its last handle is dropped, see [`KeyedLimiter::handle`].
+#[derive(Debug)]
+pub struct KeyHandle<K: Hash + Eq, S: KeyStore<K> = HashMapStore<K>> {
+    keyed: KeyedLimiter<K, S>,
+    key: K,
+    limiter: Limiter,
+}
+
+/// The limit of one key of a [`KeyedLimiter`] as a [`RateLimiter`], see
+/// [`KeyedLimiter::keyed_by`].
+#[derive(Debug)]
+pub struct KeyedBy<K, S = HashMapStore<K>> {
+    keyed: KeyedLimiter<K, S>,
+    key: K,
+}
+
+/// A snapshot of the keys tracked by a [`KeyedLimiter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyedLimiterStats<K = ()> {
+    /// The number of keys currently tracked.
+    pub live_keys: usize,
+    /// The number of keys evicted for being idle since the limiter was created.
+    pub evictions: u64,
+    /// An estimate of the memory held by the tracked keys and their limiters, in
+    /// bytes. Heap data owned by the keys themselves, e.g. the bytes of a `String`
+    /// key, is not included.
+    pub memory_bytes: usize,
+    /// The keys making the most requests, most first, if tracked with
+    /// [`KeyedLimiter::with_heavy_hitters`].
+    pub top_keys: Vec<HeavyHitter<K>>,
+}
+
+impl<K: Hash + Eq + Clone> KeyedLimiter<K> {
+    /// Creates a new `KeyedLimiter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the limiter created for each key.
+    pub fn new(config: LimiterConfig) -> Self {
+        Self::with_store(config, None, HashMapStore::new())
+    }
+
+    /// Creates a new `KeyedLimiter` evicting the keys that have not been seen for
+    /// `idle_ttl`.
+    ///
+    /// Idle keys are swept on access, at most once per `idle_ttl`, so a limiter that
+    /// is no longer accessed keeps its keys; see [`KeyedLimiter::spawn_sweeper`]. An
+    /// evicted key starts from a fresh limit on its next request, so `idle_ttl`
+    /// should be longer than the interval of the limiter.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the limiter created for each key.
+    /// * `idle_ttl` - How long a key may stay unseen before it is evicted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::{thread, time::Duration};
+    /// use devkit_rl::{KeyedLimiter, LimiterConfig};
+    ///
+    /// let limiter = KeyedLimiter::with_idle_ttl(
+    ///     LimiterConfig::TokenBucket {
+    ///         capacity: 1,
+    ///         refill_rate: 1,
+    ///         refill_interval_ms: Some(10),
+    ///         initial_tokens: None,
+    ///     },
+    ///     Duration::from_millis(20),
+    /// );
+    ///
+    /// assert!(limiter.allow(&"10.0.0.1"));
+    /// thread::sleep(Duration::from_millis(30));
+    /// assert_eq!(limiter.sweep(), 1);
+    /// assert_eq!(limiter.stats().evictions, 1);
+    /// ```
+    pub fn with_idle_ttl(config: LimiterConfig, idle_ttl: Duration) -> Self {
+        Self::with_store(config, Some(idle_ttl), HashMapStore::new())
+    }
+}
+
+impl<K: Hash + Eq + Clone, H: BuildHasher> KeyedLimiter<K, HashMapStore<K, H>> {
+    /// Creates a new `KeyedLimiter` hashing its keys with `hasher`.
+    ///
+    /// The default SipHash resists hash flooding from keys chosen by clients. Keys
+    /// that are not, e.g. tenant IDs and routes, are looked up faster with hashers
+    /// such as `ahash::RandomState` or `fxhash::FxBuildHasher`.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the limiter created for each key.
+    /// * `idle_ttl` - Optional time a key may stay unseen before it is evicted, see
+    ///   [`KeyedLimiter::with_idle_ttl`]. Keys are kept forever if not provided.
+    /// * `hasher` - The builder of the hasher of the keys.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::{collections::hash_map::DefaultHasher, hash::BuildHasherDefault};
+    /// use devkit_rl::{KeyedLimiter, LimiterConfig};
+    ///
+    /// let limiter: KeyedLimiter<(String, String, &str), _> = KeyedLimiter::with_hasher(
+    ///     LimiterConfig::TokenBucket {
+    ///         capacity: 1,
+    ///         refill_rate: 1,
+    ///         refill_interval_ms: None,
+    ///         initial_tokens: None,
+    ///     },
+    ///     None,
+    ///     BuildHasherDefault::<DefaultHasher>::default(),
+    /// );
+    ///
+    /// let key = ("acme".to_string(), "/search".to_string(), "GET");
+    /// assert!(limiter.allow(&key));
+    /// assert!(!limiter.allow(&key));
+    /// ```
+    pub fn with_hasher(config: LimiterConfig, idle_ttl: Option<Duration>, hasher: H) -> Self {
+        Self::with_store(config, idle_ttl, HashMapStore::with_hasher(hasher))
+    }
+}
+
+impl<K: Hash + Eq + Clone, S: KeyStore<K>> KeyedLimiter<K, S> {
+    /// Creates a new `KeyedLimiter` keeping the limiters of its keys in `store`.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the limiter created for each key.
+    /// * `idle_ttl` - Optional time a key may stay unseen before it is evicted, see
+    ///   [`KeyedLimiter::with_idle_ttl`]. Keys are kept until the store evicts them
+    ///   if not provided.
+    /// * `store` - The store of the limiters, e.g. a [`ShardedStore`](crate::ShardedStore)
+    ///   for a limiter shared by many threads.
+    pub fn with_store(config: LimiterConfig, idle_ttl: Option<Duration>, store: S) -> Self {
+        Self {
+            inner: Arc::new(KeyedLimiterInner {
+                config,
+                store,
+                idle_ttl,
+                origin: Instant::now(),
+                next_sweep: AtomicU64::new(0),
+                evictions: AtomicU64::new(0),
+            }),
+            observer: None,
+            heavy_hitters: None,
+            deny_cache: None,
+        }
+    }
+
+    /// Reports the decisions made for every key to `observer`.
+    ///
+    /// Observers see the key of each decision, which makes them the place to log
+    /// abusive keys or to ban them, see [`Observer`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use devkit_rl::{Hooks, KeyedLimiter, LimiterConfig};
+    ///
+    /// let denied = Arc::new(Mutex::new(Vec::new()));
+    /// let limiter = KeyedLimiter::new(LimiterConfig::TokenBucket {
+    ///     capacity: 1,
+    ///     refill_rate: 1,
+    ///     refill_interval_ms: None,
+    ///     initial_tokens: None,
+    /// })
+    /// .with_observer(Arc::new(Hooks::new().on_denied({
+    ///     let denied = denied.clone();
+    ///     move |key: &&str, _| denied.lock().unwrap().push(key.to_string())
+    /// })));
+    ///
+    /// assert!(limiter.allow(&"10.0.0.1"));
+    /// assert!(!limiter.allow(&"10.0.0.1"));
+    /// assert_eq!(*denied.lock().unwrap(), ["10.0.0.1"]);
+    /// ```
+    pub fn with_observer(mut self, observer: Arc<dyn Observer<K>>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Counts the requests of every key, allowed or denied, in `heavy_hitters`, and
+    /// reports the most active keys in the [stats](KeyedLimiter::stats).
+    ///
+    /// The sketch tracks a bounded number of keys, so that the clients consuming the
+    /// quota are known without counting the requests of every key.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use devkit_rl::{HeavyHitters, KeyedLimiter, LimiterConfig};
+    ///
+    /// let limiter = KeyedLimiter::new(LimiterConfig::TokenBucket {
+    ///     capacity: 10,
+    ///     refill_rate: 10,
+    ///     refill_interval_ms: None,
+    ///     initial_tokens: None,
+    /// })
+    /// .with_heavy_hitters(HeavyHitters::new(100));
+    ///
+    /// for _ in 0..20 {
+    ///     limiter.allow(&"10.0.0.1");
+    /// }
+    /// limiter.allow(&"10.0.0.2");
+    ///
+    /// let top = limiter.stats().top_keys;
+    /// assert_eq!((top[0].key, top[0].count), ("10.0.0.1", 20));
+    /// ```
+    pub fn with_heavy_hitters(mut self, heavy_hitters: HeavyHitters<K>) -> Self {
+        self.heavy_hitters = Some(heavy_hitters);
+        self
+    }
+
+    /// Denies the requests of the keys deep in denial from a cache, without
+    /// checking their limiters.
+    ///
+    /// Once a key is denied and its limiter allows no request for at least
+    /// `min_retry_after`, as estimated by [`RateLimiter::next_available`], its
+    /// requests are denied until that estimate has passed by the key lookup alone.
+    /// Under attack traffic, this spares the limiter, and its lock, the requests
+    /// of the keys hugely over their limit, while the keys only briefly over it
+    /// are checked as usual.
+    ///
+    /// [`KeyedLimiter::refund`] clears the cache of a key. Requests made through a
+    /// [`KeyHandle`] skip the cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::{KeyedLimiter, LimiterConfig};
+    ///
+    /// let limiter = KeyedLimiter::new(LimiterConfig::TokenBucket {
+    ///     capacity: 1,
+    ///     refill_rate: 1,
+    ///     refill_interval_ms: Some(60_000),
+    ///     initial_tokens: None,
+    /// })
+    /// .with_deny_cache(Duration::from_secs(1));
+    ///
+    /// assert!(limiter.allow(&"10.0.0.1"));
+    /// // denied for a minute, from the cache after the first denial
+    /// assert!(!limiter.allow(&"10.0.0.1"));
+    /// assert!(!limiter.allow(&"10.0.0.1"));
+    /// ```
+    pub fn with_deny_cache(mut self, min_retry_after: Duration) -> Self {
+        self.deny_cache = Some(min_retry_after);
+        self
+    }
+
+    /// Attempts to allow a single request for `key`.
+    ///
+    /// `key` may be any borrowed form of the key type, e.g. a `&str` for `String`
+    /// keys, which is only converted to an owned key the first time it is seen, or
+    /// to report decisions to an observer.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the request is allowed, `false` otherwise.
+    pub fn allow<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.allow_n(key, 1)
+    }
+
+    /// Attempts to allow `n` requests for `key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key the requests are accounted to.
+    /// * `n` - The number of requests to allow.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the requests are allowed, `false` otherwise.
+    pub fn allow_n<Q>(&self, key: &Q, n: u64) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let lookup = self.lookup(key);
+        self.count(key, n);
+        self.decide_lookup(key, &lookup, n)
+    }
+
+    /// Attempts to allow `request` for `key`, taking its [cost](RequestCost::cost).
+    ///
+    /// See [`RateLimiter::allow_for`].
+    pub fn allow_for<Q, R>(&self, key: &Q, request: &R) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        R: RequestCost + ?Sized,
+    {
+        self.allow_n(key, request.cost())
+    }
+
+    /// Attempts to allow requests for `key` costing `cost` in total, which may be
+    /// fractional.
+    ///
+    /// See [`RateLimiter::allow_cost`].
+    pub fn allow_cost<Q>(&self, key: &Q, cost: f64) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let lookup = self.lookup(key);
+        let allowed = !lookup.denied && lookup.limiter.allow_cost(cost);
+        if !allowed && !lookup.denied {
+            self.cache_denial(key, &lookup.limiter);
+        }
+        self.count(key, whole_cost(cost));
+        if let Some(observer) = &self.observer {
+            let result = if allowed {
+                Ok(())
+            } else {
+                Err(Error::RateLimited)
+            };
+            notify(
+                observer.as_ref(),
+                &key.to_owned(),
+                whole_cost(cost),
+                &result,
+            );
+        }
+        allowed
+    }
+
+    /// Estimates how long to wait until `n` requests for `key` would be allowed.
+    ///
+    /// See [`RateLimiter::next_available`].
+    pub fn next_available<Q>(&self, key: &Q, n: u64) -> Duration
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.lookup(key).limiter.next_available(n)
+    }
+
+    /// Attempts to allow a batch of requests, each for its own key.
+    ///
+    /// This suits a proxy checking several dimensions of one request (IP, token,
+    /// route, ...). Each decision is made independently: a denied key does not
+    /// affect the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `requests` - The keys and the number of requests for each of them.
+    ///
+    /// # Returns
+    ///
+    /// The decision for each entry of `requests`, in the same order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use devkit_rl::{KeyedLimiter, LimiterConfig};
+    ///
+    /// let limiter = KeyedLimiter::new(LimiterConfig::TokenBucket {
+    ///     capacity: 2,
+    ///     refill_rate: 2,
+    ///     refill_interval_ms: None,
+    ///     initial_tokens: None,
+    /// });
+    ///
+    /// let decisions = limiter.allow_many(&[("ip:10.0.0.1", 1), ("route:/search", 3)]);
+    /// assert_eq!(decisions, [true, false]);
+    /// ```
+    pub fn allow_many(&self, requests: &[(K, u64)]) -> Vec<bool> {
+        requests
+            .iter()
+            .map(|(key, n)| {
+                let lookup = self
+                    .inner
+                    .get_or_create(key, self.observer.is_some(), false);
+                if lookup.reset {
+                    self.report_window_reset(key);
+                }
+                self.count(key, *n);
+                self.decide_lookup(key, &lookup, *n)
+            })
+            .collect()
+    }
+
+    /// Gives back `n` requests previously allowed for `key`, clearing its cached
+    /// denials.
+    ///
+    /// Nothing is done if `key` is not tracked, e.g. because it has been evicted
+    /// since. See [`Limiter::refund`].
+    pub fn refund<Q>(&self, key: &Q, n: u64)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let limiter = self.inner.store.get(key, |e| {
+            e.denied_until = None;
+            e.limiter.clone()
+        });
+        if let Some(limiter) = limiter {
+            limiter.refund(n);
+        }
+    }
+
+    /// Evicts the keys that have not been seen for the idle TTL of the limiter.
+    ///
+    /// This is done on access already; calling it is only needed to reclaim memory
+    /// from a limiter that is rarely accessed.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys evicted, always 0 without an idle TTL.
+    pub fn sweep(&self) -> usize {
+        self.inner.sweep(Instant::now())
+    }
+
+    /// Sweeps idle keys every `interval` on a background thread.
+    ///
+    /// The thread stops when the returned [`Worker`] is shut down or dropped. It
+    /// only holds a weak reference to the limiter, so once the worker is
+    /// [detached](Worker::detach), it stops after every handle of the limiter has
+    /// been dropped.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the background thread.
+    pub fn spawn_sweeper(&self, interval: Duration) -> Worker
+    where
+        K: Send + 'static,
+        S: Send + Sync + 'static,
+    {
+        let inner = Arc::downgrade(&self.inner);
+        Worker::spawn("sweeper", move |stop| {
+            while stop.sleep(interval) {
+                let Some(inner) = inner.upgrade() else {
+                    return;
+                };
+                inner.sweep(Instant::now());
+            }
+        })
+    }
+
+    /// Returns statistics about the keys tracked by the limiter.
+    pub fn stats(&self) -> KeyedLimiterStats<K> {
+        let store = &self.inner.store;
+        let mut live_keys = 0;
+        let mut limiters = 0;
+        store.for_each(|_, e| {
+            live_keys += 1;
+            limiters += e.limiter.mem_size();
+        });
+
+        KeyedLimiterStats {
+            live_keys,
+            evictions: self.inner.evictions.load(Ordering::Relaxed),
+            memory_bytes: size_of::<KeyedLimiterInner<S>>() + store.memory_bytes() + limiters,
+            top_keys: self
+                .heavy_hitters
+                .as_ref()
+                .map_or_else(Vec::new, |h| h.top(h.capacity())),
+        }
+    }
+
+    /// Forgets the limiter of `key`, so its next request starts from a fresh limit.
+    ///
+    /// # Returns
+    ///
+    /// `true` if a limiter existed for `key`.
+    pub fn remove<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.store.remove_if(key, |_| true)
+    }
+
+    /// Returns a handle on the limiter of `key`, which keeps the key alive until it
+    /// is dropped.
+    ///
+    /// This suits long-lived, connection-oriented servers: a connection holds the
+    /// handle of its client, which is tracked exactly as long as one of its
+    /// connections is open, without tuning an idle TTL and without the key map
+    /// growing forever. Once the last handle of a key is dropped, the key is
+    /// forgotten, even if it was also used through the limiter directly, and its
+    /// next request starts from a fresh limit.
+    ///
+    /// Requests made through a handle skip the key lookup.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use devkit_rl::{KeyedLimiter, LimiterConfig};
+    ///
+    /// let limiter = KeyedLimiter::new(LimiterConfig::TokenBucket {
+    ///     capacity: 2,
+    ///     refill_rate: 2,
+    ///     refill_interval_ms: None,
+    ///     initial_tokens: None,
+    /// });
+    ///
+    /// let connection = limiter.handle("10.0.0.1");
+    /// let other = connection.clone();
+    /// assert!(connection.allow());
+    /// assert!(limiter.allow(&"10.0.0.1"));
+    /// assert!(!other.allow());
+    ///
+    /// drop(connection);
+    /// assert_eq!(limiter.len(), 1);
+    /// drop(other);
+    /// assert!(limiter.is_empty());
+    /// ```
+    pub fn handle(&self, key: K) -> KeyHandle<K, S> {
+        let lookup = self.inner.get_or_create(&key, false, true);
+        KeyHandle {
+            keyed: self.clone(),
+            key,
+            limiter: lookup.limiter,
+        }
+    }
+
+    /// Returns the limit of `key` as a [`RateLimiter`], to combine it with other
+    /// limiters, see [`RateLimiter::and`].
+    ///
+    /// Unlike a [handle](KeyedLimiter::handle), it does not keep `key` alive: the
+    /// key is looked up on every request, and evicted as usual.
+    pub fn keyed_by(&self, key: K) -> KeyedBy<K, S> {
+        KeyedBy {
+            keyed: self.clone(),
+            key,
+        }
+    }
+
+    /// Returns the number of keys currently tracked.
+    pub fn len(&self) -> usize {
+        self.inner.store.len()
+    }
+
+    /// Returns `true` if no key is currently tracked.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the limiter of `key`, creating it if needed.
+    ///
+    /// The limiter is evaluated after the store is unlocked, so that a blocking
+    /// limiter does not hold up every other key.
+    fn lookup<Q>(&self, key: &Q) -> Lookup
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let lookup = self
+            .inner
+            .get_or_create(key, self.observer.is_some(), false);
+        if lookup.reset {
+            self.report_window_reset(&key.to_owned());
+        }
+        lookup
+    }
+
+    /// Attempts to allow `n` requests for `key` as looked up, denying them from the
+    /// deny cache if cached, and caching their denial otherwise.
+    fn decide_lookup<Q>(&self, key: &Q, lookup: &Lookup, n: u64) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if lookup.denied {
+            if let Some(observer) = &self.observer {
+                notify(
+                    observer.as_ref(),
+                    &key.to_owned(),
+                    n,
+                    &Err(Error::RateLimited),
+                );
+            }
+            return false;
+        }
+        let allowed = if self.observer.is_none() {
+            lookup.limiter.allow_n(n)
+        } else {
+            self.decide(&key.to_owned(), &lookup.limiter, n)
+        };
+        if !allowed {
+            self.cache_denial(key, &lookup.limiter);
+        }
+        allowed
+    }
+
+    /// Caches the denial of `key` until its `limiter` allows a request again, if
+    /// that is at least the minimum wait of the deny cache.
+    fn cache_denial<Q>(&self, key: &Q, limiter: &Limiter)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(min_retry_after) = self.deny_cache else {
+            return;
+        };
+        let retry_after = limiter.next_available(1);
+        if retry_after < min_retry_after {
+            return;
+        }
+        if let Some(until) = Instant::now().checked_add(retry_after) {
+            self.inner.store.get(key, |e| e.denied_until = Some(until));
+        }
+    }
+
+    /// Attempts to allow `n` requests for `key` on its `limiter`, reporting the
+    /// decision to the observer.
+    fn decide(&self, key: &K, limiter: &Limiter, n: u64) -> bool {
+        let Some(observer) = &self.observer else {
+            return limiter.allow_n(n);
+        };
+        let result = limiter.try_check(n);
+        notify(observer.as_ref(), key, n, &result);
+        result.is_ok()
+    }
+
+    /// Counts `n` requests for `key` in the heavy hitters, if tracked.
+    fn count<Q>(&self, key: &Q, n: u64)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if let Some(heavy_hitters) = &self.heavy_hitters {
+            heavy_hitters.record(key, n);
+        }
+    }
+
+    fn report_window_reset(&self, key: &K) {
+        if let Some(observer) = &self.observer {
+            observer.on_window_reset(key);
+        }
+    }
+}
+
+impl<S> KeyedLimiterInner<S> {
+    /// Returns the limiter of `key`, creating it from the configuration if needed.
+    ///
+    /// Idle keys are swept first if the next sweep is due. If `observed`, this also
+    /// returns whether a new window of the limiter has started since the last request
+    /// for `key`. If `hold`, the key gets one more handle.
+    fn get_or_create<K, Q>(&self, key: &Q, observed: bool, hold: bool) -> Lookup
+    where
+        S: KeyStore<K>,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let now = Instant::now();
+        self.sweep_if_due(now);
+
+        let create = || KeyEntry {
+            limiter: self.config.build(),
+            last_seen: now,
+            window: None,
+            handles: 0,
+            denied_until: None,
+        };
+        self.store.get_or_create(key, create, |entry| {
+            entry.last_seen = now;
+            entry.handles += usize::from(hold);
+            let mut reset = false;
+            if observed {
+                let window = entry.limiter.window_start();
+                reset = entry.window.is_some() && entry.window != window;
+                entry.window = window;
+            }
+            Lookup {
+                limiter: entry.limiter.clone(),
+                reset,
+                denied: entry.denied_until.is_some_and(|until| now < until),
+            }
+        })
+    }
+
+    /// Sweeps the idle keys if the next sweep is due as of `now`.
+    ///
+    /// A single caller sweeps, the others go on.
+    fn sweep_if_due<K>(&self, now: Instant)
+    where
+        S: KeyStore<K>,
+    {
+        let Some(idle_ttl) = self.idle_ttl else {
+            return;
+        };
+        let elapsed = self.nanos_since_origin(now);
+        let next_sweep = self.next_sweep.load(Ordering::Relaxed);
+        if elapsed < next_sweep {
+            return;
+        }
+        let after = elapsed.saturating_add(duration_nanos(idle_ttl));
+        if self
+            .next_sweep
+            .compare_exchange(next_sweep, after, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.evict_idle(now, idle_ttl);
+        }
+    }
+
+    /// Evicts the keys not seen for the idle TTL as of `now`.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys evicted.
+    fn sweep<K>(&self, now: Instant) -> usize
+    where
+        S: KeyStore<K>,
+    {
+        let Some(idle_ttl) = self.idle_ttl else {
+            return 0;
+        };
+        let after = self
+            .nanos_since_origin(now)
+            .saturating_add(duration_nanos(idle_ttl));
+        self.next_sweep.store(after, Ordering::Relaxed);
+        self.evict_idle(now, idle_ttl)
+    }
+
+    fn evict_idle<K>(&self, now: Instant, idle_ttl: Duration) -> usize
+    where
+        S: KeyStore<K>,
+    {
+        let mut evicted = 0;
+        self.store.retain(|_, e| {
+            let keep = e.handles > 0 || now.saturating_duration_since(e.last_seen) < idle_ttl;
+            evicted += usize::from(!keep);
+            keep
+        });
+        self.evictions.fetch_add(evicted as u64, Ordering::Relaxed);
+        evicted
+    }
+
+    fn nanos_since_origin(&self, now: Instant) -> u64 {
+        duration_nanos(now.saturating_duration_since(self.origin))
+    }
+}
+
+/// Returns `duration` in nanoseconds, saturating at `u64::MAX`, over 584 years.
+fn duration_nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+impl<K: Hash + Eq + Clone, S: KeyStore<K>> KeyHandle<K, S> {
+    /// Attempts to allow a single request for the key.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the request is allowed, `false` otherwise.
+    pub fn allow(&self) -> bool {
+        self.allow_n(1)
+    }
+
+    /// Attempts to allow `n` requests for the key.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the requests are allowed, `false` otherwise.
+    pub fn allow_n(&self, n: u64) -> bool {
+        self.keyed.count(&self.key, n);
+        self.keyed.decide(&self.key, &self.limiter, n)
+    }
+
+    /// Estimates how long to wait until `n` requests for the key would be allowed.
+    ///
+    /// See [`RateLimiter::next_available`].
+    pub fn next_available(&self, n: u64) -> Duration {
+        self.limiter.next_available(n)
+    }
+
+    /// Gives back `n` requests previously allowed for the key.
+    ///
+    /// See [`Limiter::refund`].
+    pub fn refund(&self, n: u64) {
+        self.limiter.refund(n);
+    }
+
+    /// Returns the key of the handle.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+impl<K: Hash + Eq + Clone, S: KeyStore<K>> Clone for KeyHandle<K, S> {
+    fn clone(&self) -> Self {
+        self.keyed.inner.store.get(&self.key, |e| e.handles += 1);
+        Self {
+            keyed: KeyedLimiter {
+                inner: self.keyed.inner.clone(),
+                observer: self.keyed.observer.clone(),
+                heavy_hitters: self.keyed.heavy_hitters.clone(),
+                deny_cache: self.keyed.deny_cache,
+            },
+            key: self.key.clone(),
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+impl<K: Hash + Eq, S: KeyStore<K>> Drop for KeyHandle<K, S> {
+    fn drop(&mut self) {
+        // nothing is done if the key was removed while the handle was alive
+        self.keyed.inner.store.remove_if(&self.key, |e| {
+            e.handles = e.handles.saturating_sub(1);
+            e.handles == 0
+        });
+    }
+}
+
+impl<K: Hash + Eq + Clone, S: KeyStore<K>> KeyedBy<K, S> {
+    /// Returns the key the requests are accounted to.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+impl<K, S> RateLimiter for KeyedBy<K, S>
+where
+    K: Hash + Eq + Clone + Send + Sync,
+    S: KeyStore<K> + Send + Sync,
+{
+    fn allow_n(&self, n: u64) -> bool {
+        self.keyed.allow_n(&self.key, n)
+    }
+
+    fn allow_cost(&self, cost: f64) -> bool {
+        self.keyed.allow_cost(&self.key, cost)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        self.keyed.next_available(&self.key, n)
+    }
+
+    fn refund(&self, n: u64) {
+        self.keyed.refund(&self.key, n);
+    }
+}
+
+impl<K: Clone, S> Clone for KeyedBy<K, S> {
+    fn clone(&self) -> Self {
+        Self {
+            keyed: self.keyed.clone(),
+            key: self.key.clone(),
+        }
+    }
+}
+
+impl<K, S> Clone for KeyedLimiter<K, S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            observer: self.observer.clone(),
+            heavy_hitters: self.heavy_hitters.clone(),
+            deny_cache: self.deny_cache,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "fixed-window"))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn keyed_limiter_allow_many_should_decide_per_key() {
+        let limiter = KeyedLimiter::new(LimiterConfig::FixedWindow {
+            size: 3,
+            interval_ms: Some(60_000),
+            smoothing: false,
+        });
+
+        assert_eq!(
+            limiter.allow_many(&[("ip", 1), ("token", 3), ("route", 4)]),
+            [true, true, false]
+        );
+        assert_eq!(limiter.len(), 3);
+
+        // the same key may appear several times, and is accounted cumulatively
+        assert_eq!(
+            limiter.allow_many(&[("ip", 1), ("ip", 1), ("ip", 1), ("token", 1)]),
+            [true, true, false, false]
+        );
+
+        assert!(limiter.remove(&"ip"));
+        assert!(limiter.allow_n(&"ip", 3));
+    }
+
+    #[test]
+    fn keyed_limiter_should_look_up_borrowed_and_composite_keys() {
+        let config = LimiterConfig::FixedWindow {
+            size: 2,
+            interval_ms: Some(60_000),
+            smoothing: false,
+        };
+
+        // `String` keys are checked with `&str`
+        let limiter: KeyedLimiter<String> = KeyedLimiter::new(config);
+        assert!(limiter.allow("tenant-a"));
+        assert!(limiter.allow_n(&"tenant-a".to_string(), 1));
+        assert!(!limiter.allow("tenant-a"));
+        assert!(limiter.next_available("tenant-a", 1) > Duration::ZERO);
+        limiter.refund("tenant-a", 1);
+        assert!(limiter.allow_cost("tenant-a", 0.5));
+        assert!(limiter.remove("tenant-a"));
+        assert!(!limiter.remove("tenant-a"));
+
+        // composite keys with a custom hasher
+        type Key = (u32, &'static str, &'static str);
+        let limiter: KeyedLimiter<Key, _> = KeyedLimiter::with_hasher(
+            config,
+            Some(Duration::from_secs(60)),
+            std::hash::BuildHasherDefault::<std::collections::hash_map::DefaultHasher>::default(),
+        );
+        assert!(limiter.allow_n(&(1, "/search", "GET"), 2));
+        assert!(!limiter.allow(&(1, "/search", "GET")));
+        assert!(limiter.allow(&(1, "/search", "POST")));
+        assert!(limiter.allow(&(2, "/search", "GET")));
+        assert_eq!(limiter.len(), 3);
+    }
+
+    #[test]
+    fn keyed_limiter_should_take_the_cost_of_typed_requests() {
+        struct Upload(u64);
+
+        impl RequestCost for Upload {
+            fn cost(&self) -> u64 {
+                self.0.div_ceil(1024)
+            }
+        }
+
+        let limiter = KeyedLimiter::new(LimiterConfig::FixedWindow {
+            size: 4,
+            interval_ms: Some(60_000),
+            smoothing: false,
+        });
+
+        assert!(limiter.allow_for(&"alice", &Upload(3000)));
+        assert!(!limiter.allow_for(&"alice", &Upload(2000)));
+        assert!(limiter.allow_for(&"alice", &Upload(1)));
+        assert!(limiter.allow_for(&"bob", &Upload(4096)));
+
+        let handle = limiter.keyed_by("bob");
+        assert!(matches!(
+            handle.try_check_for(&Upload(1)),
+            Err(Error::RateLimited)
+        ));
+    }
+
+    #[test]
+    fn keyed_limiter_should_evict_idle_keys() {
+        const TTL: Duration = Duration::from_millis(20);
+
+        let limiter = KeyedLimiter::with_idle_ttl(
+            LimiterConfig::FixedWindow {
+                size: 1,
+                interval_ms: Some(10),
+                smoothing: false,
+            },
+            TTL,
+        );
+        assert!(limiter.allow(&"a"));
+        assert!(limiter.allow(&"b"));
+        let stats = limiter.stats();
+        assert_eq!(stats.live_keys, 2);
+        assert_eq!(stats.evictions, 0);
+        assert!(stats.memory_bytes > 0);
+
+        // the next access after the TTL sweeps both keys, then tracks "b" afresh
+        std::thread::sleep(TTL + TTL / 2);
+        assert!(limiter.allow(&"b"));
+        assert_eq!(limiter.stats().live_keys, 1);
+        assert_eq!(limiter.stats().evictions, 2);
+
+        // a detached sweeper thread stops with the limiter
+        let sweeper = limiter.spawn_sweeper(TTL / 4);
+        std::thread::sleep(TTL * 2);
+        assert!(limiter.is_empty());
+        assert_eq!(limiter.stats().evictions, 3);
+        sweeper.detach();
+        drop(limiter);
+
+        // a sweeper is woken up to stop, without waiting for its next sweep
+        let limiter: KeyedLimiter<&str> =
+            KeyedLimiter::with_idle_ttl(LimiterConfig::Unlimited, TTL);
+        let start = Instant::now();
+        let sweeper = limiter.spawn_sweeper(Duration::from_secs(3600));
+        assert!(!sweeper.is_finished());
+        assert!(sweeper.shutdown(Duration::from_secs(1)).is_ok());
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // without an idle TTL, keys are kept
+        let limiter = KeyedLimiter::new(LimiterConfig::Unlimited);
+        assert!(limiter.allow(&"a"));
+        assert_eq!(limiter.sweep(), 0);
+        assert_eq!(limiter.len(), 1);
+    }
+
+    #[test]
+    fn keyed_limiter_should_report_its_heavy_hitters() {
+        let limiter = KeyedLimiter::new(LimiterConfig::FixedWindow {
+            size: 5,
+            interval_ms: Some(60_000),
+            smoothing: false,
+        });
+        assert!(limiter.stats().top_keys.is_empty());
+
+        let limiter = limiter.with_heavy_hitters(HeavyHitters::new(2));
+        // denied requests count too, from every way of making them
+        for _ in 0..8 {
+            limiter.allow(&"hot");
+        }
+        limiter.allow_many(&[("warm", 2), ("hot", 1)]);
+        assert!(limiter.handle("warm").allow_n(3));
+        limiter.allow_cost(&"cold", 0.5);
+
+        let top: Vec<_> = limiter
+            .stats()
+            .top_keys
+            .into_iter()
+            .map(|h| (h.key, h.count, h.error))
+            .collect();
+        // "cold" took over the counter of "warm"
+        assert_eq!(top, [("hot", 9, 0), ("cold", 6, 5)]);
+    }
+
+    #[test]
+    fn keyed_limiter_should_keep_keys_while_they_have_handles() {
+        const TTL: Duration = Duration::from_millis(20);
+
+        let limiter = KeyedLimiter::with_idle_ttl(
+            LimiterConfig::FixedWindow {
+                size: 2,
+                interval_ms: Some(60_000),
+                smoothing: false,
+            },
+            TTL,
+        );
+        let a = limiter.handle("a".to_string());
+        assert!(a.allow());
+        assert!(limiter.allow(&"b".to_string()));
+
+        // an idle key with a handle is not evicted, nor does it lose its state
+        std::thread::sleep(TTL + TTL / 2);
+        assert_eq!(limiter.sweep(), 1);
+        assert_eq!(limiter.len(), 1);
+        let other = a.clone();
+        assert!(other.allow());
+        assert!(!limiter.allow("a"));
+        assert!(a.next_available(1) > Duration::ZERO);
+
+        drop(a);
+        assert_eq!(limiter.len(), 1);
+        assert_eq!(other.key(), "a");
+        drop(other);
+        assert!(limiter.is_empty());
+        assert!(limiter.allow("a"));
+    }
+
+    #[test]
+    fn keyed_limiter_should_cache_the_denials_of_keys_deep_in_denial() {
+        let keyed = |min_retry_after| {
+            KeyedLimiter::new(LimiterConfig::FixedWindow {
+                size: 2,
+                interval_ms: Some(60_000),
+                smoothing: false,
+            })
+            .with_deny_cache(min_retry_after)
+        };
+
+        let limiter = keyed(Duration::from_secs(1));
+        let handle = limiter.handle("a");
+        assert!(limiter.allow_n(&"a", 2));
+        assert!(!limiter.allow(&"a"));
+
+        // the window has room again, but the key is denied from the cache
+        handle.refund(2);
+        assert!(!limiter.allow(&"a"));
+        assert!(!limiter.allow_cost(&"a", 0.5));
+        assert_eq!(limiter.allow_many(&[("a", 1)]), [false]);
+        assert!(handle.allow());
+        // a refund through the limiter clears the cache
+        limiter.refund(&"a", 1);
+        assert!(limiter.allow(&"a"));
+
+        // a key allowed again sooner than the minimum wait is checked as usual
+        let limiter = keyed(Duration::from_secs(120));
+        let handle = limiter.handle("a");
+        assert!(limiter.allow_n(&"a", 2));
+        assert!(!limiter.allow(&"a"));
+        handle.refund(1);
+        assert!(limiter.allow(&"a"));
+    }
+
+    #[test]
+    fn keyed_limiter_should_keep_keys_in_a_sharded_store() {
+        const TTL: Duration = Duration::from_millis(20);
+
+        let limiter = KeyedLimiter::with_store(
+            LimiterConfig::FixedWindow {
+                size: 1,
+                interval_ms: Some(60_000),
+                smoothing: false,
+            },
+            Some(TTL),
+            crate::ShardedStore::new(4),
+        );
+        let held = limiter.handle(0);
+        assert!(held.allow());
+        let threads: Vec<_> = (1..=8)
+            .map(|key| {
+                let limiter = limiter.clone();
+                thread::spawn(move || limiter.allow(&key) && !limiter.allow(&key))
+            })
+            .collect();
+        assert!(threads.into_iter().all(|t| t.join().unwrap()));
+        assert_eq!(limiter.stats().live_keys, 9);
+
+        // idle keys are swept from every shard, but the held one
+        thread::sleep(TTL + TTL / 2);
+        assert_eq!(limiter.sweep(), 8);
+        assert!(!held.allow());
+        drop(held);
+        assert!(limiter.is_empty());
+        assert_eq!(limiter.stats().evictions, 8);
+    }
+}
@@ -1,9 +1,16 @@
 use std::{
-    sync::{mpsc, Arc, Mutex},
-    thread,
+    sync::{mpsc, Arc, Weak},
     time::{Duration, Instant},
 };
 
+use crate::{
+    clock::{At, SharedClock},
+    limiter::{cost_units, whole_cost, COST_SCALE},
+    sync::{arc_size, Mutex, MutexExt},
+    worker::{Stop, Worker},
+    Clock, Error, Quota, WaitStrategy,
+};
+
 /// A leaky bucket rate limiter.
 ///
 /// This implementation allows you to control the rate of events through a leaky bucket algorithm.
@@ -22,19 +29,47 @@ use std::{
 /// // Attempt to allow an event through the bucket.
 /// assert!(bucket.allow());
 /// ```
+///
+/// By default, allowed events are queued until they leak out of the bucket, let
+/// out by a leak thread. The thread stops once the bucket is dropped, or on
+/// [`LeakyBucket::shutdown`]. A bucket created with [`LeakyBucket::meter`] answers
+/// right away instead, without a thread.
 #[derive(Debug, Clone)]
 pub struct LeakyBucket {
     inner: Arc<Mutex<LeakyBucketInner>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 struct LeakyBucketInner {
     capacity: u64,
     current_level: u64,
     leak_rate: u64,
     leak_interval: Duration,
-    last_leaktime: Instant,
-    queue: mpsc::Sender<oneshot::Sender<()>>,
+    /// How the leak thread waits for the next leak.
+    wait: WaitStrategy,
+    mode: Mode,
+}
+
+/// How a [`LeakyBucket`] lets events leak.
+#[derive(Debug)]
+enum Mode {
+    /// Events wait in the bucket until the leak thread lets them out.
+    Queue {
+        /// The queue of the events waiting to leak, `None` once shut down.
+        ///
+        /// Declared before the worker, so that it is dropped first, disconnecting the
+        /// leak thread waiting for events before the thread is waited for.
+        queue: Option<mpsc::Sender<oneshot::Sender<()>>>,
+        /// The leak thread, `None` once shut down.
+        worker: Option<Worker>,
+    },
+    /// Events are only metered against the level the bucket would have drained to.
+    Meter {
+        /// The time, in ticks (see [`ticks`]), when the bucket will have drained completely.
+        drained_at: u128,
+        /// The source of time.
+        clock: SharedClock,
+    },
 }
 
 impl LeakyBucket {
@@ -50,48 +85,494 @@ impl LeakyBucket {
     ///
     /// Returns a new `LeakyBucket` instance.
     pub fn new(leak_rate: u64, capacity: u64, leak_interval: Option<Duration>) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let inner = Arc::new(Mutex::new(LeakyBucketInner::new(
+            leak_rate,
+            capacity,
+            leak_interval,
+            Mode::Queue {
+                queue: Some(tx),
+                worker: None,
+            },
+        )));
+
+        // The leak thread only holds a weak reference, so it stops once the bucket is dropped.
+        let weak = Arc::downgrade(&inner);
+        let leak = Worker::spawn("leak", move |stop| LeakyBucketInner::start(weak, rx, stop));
+        if let Mode::Queue { worker, .. } = &mut inner.lock_unpoisoned().mode {
+            *worker = Some(leak);
+        }
+
+        Self { inner }
+    }
+
+    /// Creates a new `LeakyBucket` used as a meter.
+    ///
+    /// A meter neither queues events nor runs a leak thread: [`LeakyBucket::allow_n`]
+    /// answers right away whether the events fit below capacity, given the level the
+    /// bucket would have drained to by now. This is the classic shaping of a leaky
+    /// bucket, and equivalent to the generic cell rate algorithm (GCRA).
+    ///
+    /// Unlike a queuing bucket, a meter can be refunded.
+    ///
+    /// # Arguments
+    ///
+    /// * `leak_rate` - The rate at which the bucket leaks events per interval.
+    /// * `capacity` - The maximum capacity of the bucket.
+    /// * `leak_interval` - The interval at which the bucket leaks events. If `None`, defaults to 1 second.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::LeakyBucket;
+    ///
+    /// let bucket = LeakyBucket::meter(1, 2, Some(Duration::from_secs(1)));
+    ///
+    /// assert!(bucket.allow_n(2));
+    /// assert!(!bucket.allow());
+    /// ```
+    pub fn meter(leak_rate: u64, capacity: u64, leak_interval: Option<Duration>) -> Self {
+        Self::meter_from_clock(leak_rate, capacity, leak_interval, SharedClock::std())
+    }
+
+    /// Creates a new `LeakyBucket` used as a meter, reading the time from `clock`.
+    ///
+    /// See [`LeakyBucket::meter`].
+    pub fn meter_with_clock(
+        leak_rate: u64,
+        capacity: u64,
+        leak_interval: Option<Duration>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self::meter_from_clock(leak_rate, capacity, leak_interval, SharedClock::new(clock))
+    }
+
+    /// Creates a new `LeakyBucket` shaping traffic of weighted units, e.g. bytes or
+    /// packets, used as a meter.
+    ///
+    /// The bucket holds up to `capacity` units, the buffer of the shaper, and drains
+    /// `units_per_sec` units per second. Each piece of traffic is admitted by its
+    /// weight with [`LeakyBucket::allow_weight`], which tells how long to hold it
+    /// so that the traffic leaves at the drain rate.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::LeakyBucket;
+    ///
+    /// // 1 MB/s, buffering up to 64 KB
+    /// let shaper = LeakyBucket::shaper(1_000_000, 64 * 1024);
+    ///
+    /// assert_eq!(shaper.allow_weight(1500).unwrap(), Duration::ZERO);
+    /// // the second packet leaves once the first one has drained
+    /// assert!(shaper.allow_weight(1500).unwrap() > Duration::ZERO);
+    /// ```
+    pub fn shaper(units_per_sec: u64, capacity: u64) -> Self {
+        Self::meter(units_per_sec, capacity, Some(Duration::from_secs(1)))
+    }
+
+    /// Creates a new `LeakyBucket` shaping traffic of weighted units, reading the
+    /// time from `clock`.
+    ///
+    /// See [`LeakyBucket::shaper`].
+    pub fn shaper_with_clock(units_per_sec: u64, capacity: u64, clock: Arc<dyn Clock>) -> Self {
+        Self::meter_with_clock(units_per_sec, capacity, Some(Duration::from_secs(1)), clock)
+    }
+
+    fn meter_from_clock(
+        leak_rate: u64,
+        capacity: u64,
+        leak_interval: Option<Duration>,
+        mut clock: SharedClock,
+    ) -> Self {
+        let mode = Mode::Meter {
+            drained_at: ticks(clock.now()),
+            clock,
+        };
         Self {
             inner: Arc::new(Mutex::new(LeakyBucketInner::new(
                 leak_rate,
                 capacity,
                 leak_interval,
+                mode,
             ))),
         }
     }
 
+    /// Creates a new `LeakyBucket` allowing `quota`.
+    ///
+    /// The bucket holds [`Quota::burst`] events and leaks one event every
+    /// [`Quota::replenish_interval`].
+    pub fn from_quota(quota: Quota) -> Self {
+        Self::new(1, quota.burst(), Some(quota.replenish_interval()))
+    }
+
+    /// Updates the parameters of the bucket without losing its current state.
+    ///
+    /// Events that are already in the bucket stay there, and the new leak rate and
+    /// interval take effect from the next leak onwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `leak_rate` - The rate at which the bucket leaks events per second.
+    /// * `capacity` - The maximum capacity of the bucket.
+    /// * `leak_interval` - The interval at which the bucket leaks events. If `None`, defaults to 1 second.
+    pub fn reconfigure(&self, leak_rate: u64, capacity: u64, leak_interval: Option<Duration>) {
+        let mut inner = self.inner.lock_unpoisoned();
+        let old_emission = inner.emission_interval();
+        let new_emission =
+            emission_interval(leak_rate, leak_interval.unwrap_or(Duration::from_secs(1)));
+        if let Mode::Meter { drained_at, clock } = &mut inner.mode {
+            // keep the events of the meter, draining them at the new rate
+            let now = ticks(clock.now());
+            let level = drained_at.saturating_sub(now);
+            *drained_at = now + level.saturating_mul(new_emission) / old_emission;
+        }
+        inner.leak_rate = leak_rate;
+        inner.capacity = capacity;
+        inner.leak_interval = leak_interval.unwrap_or(Duration::from_secs(1));
+    }
+
+    /// Changes how the leak thread of a queuing bucket waits between leaks, from the
+    /// next leak onwards.
+    ///
+    /// The default [`WaitStrategy::Sleep`] leaks late by the lateness of the
+    /// scheduler, which matters for leak intervals below a millisecond; see
+    /// [`WaitStrategy::Hybrid`] for accurate pacing. A meter does not wait, and
+    /// ignores the strategy.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::{LeakyBucket, WaitStrategy};
+    ///
+    /// let bucket = LeakyBucket::new(1, 10, Some(Duration::from_micros(200)));
+    /// bucket.set_wait_strategy(WaitStrategy::Hybrid { spin: Duration::from_micros(100) });
+    ///
+    /// assert!(bucket.allow());
+    /// ```
+    pub fn set_wait_strategy(&self, wait: WaitStrategy) {
+        self.inner.lock_unpoisoned().wait = wait;
+    }
+
     /// Attempts to allow an event through the bucket.
     ///
     /// If the bucket has not reached its capacity and an event can be allowed,
     /// this method will return `true`. Otherwise, it returns `false`.
     ///
-    /// This method blocks until the bucket's state is updated to reflect the allowance.
+    /// Unless the bucket is a [meter](LeakyBucket::meter), this method blocks until the
+    /// bucket's state is updated to reflect the allowance.
     ///
     /// # Returns
     ///
     /// Returns `true` if the event is allowed, `false` otherwise.
     pub fn allow(&self) -> bool {
-        if !self.try_allow() {
-            return false;
+        self.allow_n(1)
+    }
+
+    /// Attempts to allow `n` events through the bucket.
+    ///
+    /// The `n` events are admitted into the bucket together, or not at all. Once
+    /// admitted, this method blocks until all of them have leaked out of the bucket,
+    /// unless the bucket is a [meter](LeakyBucket::meter).
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of events to allow.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the events are allowed, `false` otherwise.
+    pub fn allow_n(&self, n: u64) -> bool {
+        self.acquire(n).is_ok()
+    }
+
+    /// Attempts to allow an event through the bucket at the time `now`.
+    ///
+    /// This is a convenience method for `allow_n_at(1, now)`.
+    pub fn allow_at(&self, now: Duration) -> bool {
+        self.allow_n_at(1, now)
+    }
+
+    /// Attempts to allow `n` events through a [meter](LeakyBucket::meter) at the time
+    /// `now`, instead of the time read from its clock.
+    ///
+    /// `now` is on the timeline of the clock, see [`TokenBucket::allow_n_at`](crate::TokenBucket::allow_n_at).
+    /// A queuing bucket leaks in real time on its own thread, so it ignores `now`
+    /// and blocks like [`LeakyBucket::allow_n`].
+    pub fn allow_n_at(&self, n: u64, now: Duration) -> bool {
+        match self.try_meter(u128::from(n) * COST_SCALE, Some(At::Clamped(now))) {
+            Some(metered) => metered.is_ok(),
+            None => self.allow_n(n),
+        }
+    }
+
+    /// Like [`allow_n_at`](Self::allow_n_at), but fails with
+    /// [`Error::ClockWentBackwards`] if `now` is older than the latest time seen by
+    /// the meter, e.g. to detect the out-of-order entries of a replayed log.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::RateLimited`] if the events are denied, or [`Error::ClockWentBackwards`].
+    /// A queuing bucket fails like [`LeakyBucket::allow_n_timeout`] without timeout.
+    pub fn try_allow_n_at(&self, n: u64, now: Duration) -> Result<(), Error> {
+        match self.try_meter(u128::from(n) * COST_SCALE, Some(At::Strict(now))) {
+            Some(metered) => metered,
+            None => self.acquire(n),
         }
+    }
 
-        let rx = self.create_notify();
+    /// Attempts to allow an event through the bucket, waiting at most `timeout`.
+    ///
+    /// This is a convenience method that is equivalent to calling `allow_n_timeout(1, timeout)`.
+    pub fn allow_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        self.allow_n_timeout(1, timeout)
+    }
 
-        let _ = rx.recv();
-        self.leak();
-        true
+    /// Attempts to allow `n` events through the bucket, waiting at most `timeout` for
+    /// them to leak out.
+    ///
+    /// Unlike [`LeakyBucket::allow_n`], this does not block forever when the leak
+    /// thread cannot keep up. On timeout the events that have not leaked yet are
+    /// taken out of the bucket again.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of events to allow.
+    /// * `timeout` - The longest time to wait for the events to leak out.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the events have leaked out, [`Error::QueueFull`] if they do not
+    /// fit in the bucket ([`Error::RateLimited`] for a meter), [`Error::Timeout`] if they have not leaked out within
+    /// `timeout`, or [`Error::Disconnected`] if the leak thread has stopped.
+    pub fn allow_n_timeout(&self, n: u64, timeout: Duration) -> Result<(), Error> {
+        if let Some(metered) = self.try_meter(u128::from(n) * COST_SCALE, None) {
+            return metered;
+        }
+        let deadline = Instant::now().checked_add(timeout);
+        let mut admitted = self.admit(n)?;
+        while admitted.remaining > 0 {
+            let rx = self.create_notify().ok_or(Error::Disconnected)?;
+            match deadline {
+                Some(deadline) => rx.recv_deadline(deadline).map_err(|e| match e {
+                    oneshot::RecvTimeoutError::Timeout => Error::Timeout,
+                    oneshot::RecvTimeoutError::Disconnected => Error::Disconnected,
+                })?,
+                None => rx.recv().map_err(|_| Error::Disconnected)?,
+            }
+            admitted.leak();
+        }
+        Ok(())
     }
 
-    /// Attempts to allow an event through the bucket without blocking.
+    /// Attempts to allow `n` events through the bucket without blocking the thread.
+    ///
+    /// The returned future resolves once the events have leaked out, and works with
+    /// any async runtime. It is cancellation safe: dropping it, e.g. because it lost
+    /// a race against the runtime's timeout, takes the events that have not leaked
+    /// yet out of the bucket again.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of events to allow.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the events have leaked out, [`Error::QueueFull`] if they do not
+    /// fit in the bucket ([`Error::RateLimited`] for a meter), or [`Error::Disconnected`]
+    /// if the leak thread has stopped.
+    pub async fn allow_n_async(&self, n: u64) -> Result<(), Error> {
+        if let Some(metered) = self.try_meter(u128::from(n) * COST_SCALE, None) {
+            return metered;
+        }
+        let mut admitted = self.admit(n)?;
+        while admitted.remaining > 0 {
+            let rx = self.create_notify().ok_or(Error::Disconnected)?;
+            rx.await.map_err(|_| Error::Disconnected)?;
+            admitted.leak();
+        }
+        Ok(())
+    }
+
+    /// Admits traffic weighing `weight` units, e.g. the bytes of a packet, into the
+    /// bucket.
+    ///
+    /// A [meter](LeakyBucket::meter), such as a [shaper](LeakyBucket::shaper), does
+    /// not block: the units queue behind those already in the bucket, and the
+    /// returned delay is how long to hold the traffic before sending it, for it to
+    /// leave at the drain rate. A queuing bucket blocks like
+    /// [`LeakyBucket::allow_n`] until the units have leaked out, and returns zero.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::TooLarge`] if `weight` exceeds the capacity of the bucket,
+    /// [`Error::QueueFull`] if the units do not fit in the bucket now, or
+    /// [`Error::Disconnected`] if the leak thread of a queuing bucket has stopped.
+    pub fn allow_weight(&self, weight: u64) -> Result<Duration, Error> {
+        let mut inner = self.inner.lock_unpoisoned();
+        if weight > inner.capacity {
+            return Err(Error::TooLarge);
+        }
+        let (capacity, emission) = (inner.capacity, inner.emission_interval());
+        let Mode::Meter { drained_at, clock } = &mut inner.mode else {
+            drop(inner);
+            return self.acquire(weight).map(|()| Duration::ZERO);
+        };
+
+        let now = ticks(clock.now());
+        let backlog = (*drained_at).max(now);
+        let drained = backlog.saturating_add(u128::from(weight).saturating_mul(emission));
+        if drained - now > u128::from(capacity).saturating_mul(emission) {
+            return Err(Error::QueueFull);
+        }
+        *drained_at = drained;
+        let delay = (backlog - now).div_ceil(COST_SCALE);
+        Ok(u64::try_from(delay).map_or(Duration::MAX, Duration::from_nanos))
+    }
+
+    /// Attempts to allow events costing `cost` in total, which may be fractional.
+    ///
+    /// A [meter](LeakyBucket::meter) accounts for fractions exactly: an event
+    /// costing `0.5` takes half as long to leak as a whole one. A queuing bucket
+    /// rounds `cost` up to whole events, and blocks like [`LeakyBucket::allow_n`].
+    /// A cost that is not positive is free.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the events are allowed, `false` otherwise.
+    pub fn allow_cost(&self, cost: f64) -> bool {
+        match self.try_meter(cost_units(cost), None) {
+            Some(metered) => metered.is_ok(),
+            None => self.allow_n(whole_cost(cost)),
+        }
+    }
+
+    /// Estimates how long to wait until `n` events fit into the bucket.
+    ///
+    /// The estimate assumes no other event enters the bucket in the meantime. It
+    /// does not include the time the events then spend in the bucket before they
+    /// leak, which [`LeakyBucket::allow_n`] blocks for.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of events to add.
+    ///
+    /// # Returns
     ///
-    /// This method checks if the event can be allowed immediately without blocking
+    /// `Duration::ZERO` if the events fit now, an estimate of the time until enough
+    /// events have leaked otherwise, or `Duration::MAX` if `n` exceeds the capacity
+    /// and will never fit.
+    pub fn next_available(&self, n: u64) -> Duration {
+        let mut inner = self.inner.lock_unpoisoned();
+        let (capacity, emission) = (inner.capacity, inner.emission_interval());
+
+        if let Mode::Meter { drained_at, clock } = &mut inner.mode {
+            if n > capacity {
+                return Duration::MAX;
+            }
+            let now = ticks(clock.now());
+            let wait = ((*drained_at)
+                .max(now)
+                .saturating_add(u128::from(n).saturating_mul(emission)))
+            .saturating_sub(u128::from(capacity).saturating_mul(emission))
+            .saturating_sub(now);
+            return u64::try_from(wait.div_ceil(COST_SCALE))
+                .map_or(Duration::MAX, Duration::from_nanos);
+        }
+
+        if inner.current_level.saturating_add(n) <= inner.capacity {
+            return Duration::ZERO;
+        }
+        if n > inner.capacity || inner.leak_rate == 0 {
+            return Duration::MAX;
+        }
+
+        let excess = inner.current_level + n - inner.capacity;
+        u32::try_from(excess.div_ceil(inner.leak_rate))
+            .map_or(Duration::MAX, |r| inner.leak_interval.saturating_mul(r))
+    }
+
+    /// Admits `n` events into the bucket and waits for them to leak out.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the events have leaked out, [`Error::QueueFull`] if they do not
+    /// fit in the bucket ([`Error::RateLimited`] for a meter), or [`Error::Disconnected`]
+    /// if the leak thread has stopped.
+    pub(crate) fn acquire(&self, n: u64) -> Result<(), Error> {
+        if let Some(metered) = self.try_meter(u128::from(n) * COST_SCALE, None) {
+            return metered;
+        }
+        let mut admitted = self.admit(n)?;
+        while admitted.remaining > 0 {
+            let rx = self.create_notify().ok_or(Error::Disconnected)?;
+            rx.recv().map_err(|_| Error::Disconnected)?;
+            admitted.leak();
+        }
+        Ok(())
+    }
+
+    /// Meters events costing `units` units of [`COST_SCALE`] at the time `at`, or the
+    /// time of the clock if `None`, if the bucket is a meter.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the bucket queues events, otherwise `Ok(())` if the events are
+    /// allowed or [`Error::RateLimited`] if they do not fit in the bucket.
+    fn try_meter(&self, units: u128, at: Option<At>) -> Option<Result<(), Error>> {
+        let mut inner = self.inner.lock_unpoisoned();
+        let capacity = inner.capacity;
+        let emission = inner.emission_interval();
+        let Mode::Meter { drained_at, clock } = &mut inner.mode else {
+            return None;
+        };
+
+        let now = ticks(match at.map(|at| clock.observe_at(at)) {
+            Some(Ok(now)) => now,
+            Some(Err(e)) => return Some(Err(e)),
+            None => clock.now(),
+        });
+        let drained = (*drained_at)
+            .max(now)
+            .saturating_add(leak_time(units, emission));
+        if drained - now > u128::from(capacity).saturating_mul(emission) {
+            return Some(Err(Error::RateLimited));
+        }
+        *drained_at = drained;
+        Some(Ok(()))
+    }
+
+    /// Admits `n` events into the bucket, without waiting for them to leak.
+    ///
+    /// # Returns
+    ///
+    /// A guard tracking the events until they leak, or [`Error::QueueFull`] if they
+    /// do not fit in the bucket.
+    fn admit(&self, n: u64) -> Result<Admitted<'_>, Error> {
+        if !self.try_allow(n) {
+            return Err(Error::QueueFull);
+        }
+        Ok(Admitted {
+            bucket: self,
+            remaining: n,
+        })
+    }
+
+    /// Attempts to allow `n` events through the bucket without blocking.
+    ///
+    /// This method checks if the events can be allowed immediately without blocking
     /// and updates the bucket's state accordingly.
     ///
     /// # Returns
     ///
-    /// Returns `true` if the event is allowed, `false` otherwise.
-    fn try_allow(&self) -> bool {
-        let mut inner = self.inner.lock().expect("Failed to lock leaky bucket");
-        inner.try_allow()
+    /// Returns `true` if the events are allowed, `false` otherwise.
+    fn try_allow(&self, n: u64) -> bool {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.try_allow(n)
     }
 
     /// Creates a notification channel for the bucket.
@@ -101,17 +582,21 @@ impl LeakyBucket {
     ///
     /// # Returns
     ///
-    /// Returns a `oneshot::Receiver` that will receive the notification.
-    fn create_notify(&self) -> oneshot::Receiver<()> {
-        let inner = self.inner.lock().expect("Failed to lock leaky bucket");
+    /// Returns a `oneshot::Receiver` that will receive the notification, or `None`
+    /// if the leak thread has stopped.
+    fn create_notify(&self) -> Option<oneshot::Receiver<()>> {
+        let inner = self.inner.lock_unpoisoned();
+        let Mode::Queue {
+            queue: Some(queue), ..
+        } = &inner.mode
+        else {
+            return None;
+        };
 
         let (tx, rx) = oneshot::channel();
-        inner
-            .queue
-            .send(tx)
-            .expect("Failed to send to leaky bucket");
+        queue.send(tx).ok()?;
 
-        rx
+        Some(rx)
     }
 
     /// Updates the bucket's state to reflect that an event has been allowed.
@@ -119,9 +604,132 @@ impl LeakyBucket {
     /// This method leaks the bucket to reflect the passage of time and allows
     /// an event through the bucket.
     fn leak(&self) {
-        let mut inner = self.inner.lock().expect("Failed to lock leaky bucket");
-        inner.leak();
+        self.release(1);
+    }
+
+    /// Takes `n` events out of the bucket.
+    fn release(&self, n: u64) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.current_level = inner.current_level.saturating_sub(n);
+    }
+
+    /// Gives back `n` events previously allowed by [`LeakyBucket::allow_n`].
+    ///
+    /// A [meter](LeakyBucket::meter) takes the events out of the bucket again. A
+    /// queuing bucket does nothing: by the time `allow_n` returns, the events have
+    /// leaked out of the bucket and there is nothing left to give back.
+    pub fn refund(&self, n: u64) {
+        let mut inner = self.inner.lock_unpoisoned();
+        let emission = inner.emission_interval();
+        if let Mode::Meter { drained_at, .. } = &mut inner.mode {
+            *drained_at = drained_at.saturating_sub(u128::from(n).saturating_mul(emission));
+        }
+    }
+
+    /// Stops the leak thread of a queuing bucket, waiting at most `timeout` for it to
+    /// finish.
+    ///
+    /// Events still waiting in the bucket, and those allowed from then on, fail with
+    /// [`Error::Disconnected`], or are denied. Dropping the last clone of the bucket
+    /// stops the thread too. A meter has no thread, and is not affected.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Timeout`] if the thread has not finished within `timeout`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::{Error, LeakyBucket};
+    ///
+    /// let bucket = LeakyBucket::new(1, 5, Some(Duration::from_secs(3600)));
+    /// bucket.shutdown(Duration::from_secs(1)).unwrap();
+    ///
+    /// assert!(matches!(
+    ///     bucket.allow_timeout(Duration::from_secs(1)),
+    ///     Err(Error::Disconnected)
+    /// ));
+    /// ```
+    pub fn shutdown(&self, timeout: Duration) -> Result<(), Error> {
+        // the leak thread locks the bucket, so it is waited for without the lock
+        let worker = match &mut self.inner.lock_unpoisoned().mode {
+            Mode::Queue { queue, worker } => {
+                queue.take();
+                worker.take()
+            }
+            Mode::Meter { .. } => None,
+        };
+        worker.map_or(Ok(()), |worker| worker.shutdown(timeout))
+    }
+
+    /// Returns whether the bucket is a [meter](LeakyBucket::meter).
+    pub(crate) fn is_meter(&self) -> bool {
+        matches!(self.inner.lock_unpoisoned().mode, Mode::Meter { .. })
+    }
+
+    /// Estimates the memory held by this limiter, in bytes, leaving out the stack
+    /// of its leak thread.
+    pub(crate) fn mem_size(&self) -> usize {
+        arc_size::<Mutex<LeakyBucketInner>>()
+    }
+}
+
+/// Events admitted into a [`LeakyBucket`] that have not leaked out yet.
+///
+/// Dropping it before they all leaked, because waiting failed or was cancelled,
+/// takes the remaining events out of the bucket, as they will never leak.
+struct Admitted<'a> {
+    bucket: &'a LeakyBucket,
+    remaining: u64,
+}
+
+impl Admitted<'_> {
+    /// Lets one event leak out of the bucket.
+    fn leak(&mut self) {
+        self.bucket.leak();
+        self.remaining -= 1;
+    }
+}
+
+impl Drop for Admitted<'_> {
+    fn drop(&mut self) {
+        if self.remaining > 0 {
+            self.bucket.release(self.remaining);
+        }
+    }
+}
+
+/// Returns `time` in the ticks a meter counts time in, [`COST_SCALE`] per
+/// nanosecond.
+///
+/// Counting in nanoseconds would round the time a single unit takes to leak, and
+/// cap the leak rate at a unit per nanosecond, while a shaper draining the bytes of
+/// a 10 Gbps link leaks more than one per nanosecond.
+fn ticks(time: Duration) -> u128 {
+    time.as_nanos() * COST_SCALE
+}
+
+/// Returns the ticks it takes events costing `units` units of [`COST_SCALE`] to
+/// leak, a single event taking `emission` ticks.
+fn leak_time(units: u128, emission: u128) -> u128 {
+    let whole = (units / COST_SCALE).saturating_mul(emission);
+    whole.saturating_add(
+        (units % COST_SCALE)
+            .saturating_mul(emission)
+            .div_ceil(COST_SCALE),
+    )
+}
+
+/// Returns the ticks it takes a single event to leak at `leak_rate` events per
+/// `leak_interval`, at least one.
+///
+/// A bucket that does not leak takes longer than any clock will count.
+fn emission_interval(leak_rate: u64, leak_interval: Duration) -> u128 {
+    if leak_rate == 0 {
+        return ticks(Duration::from_nanos(u64::MAX));
     }
+    (ticks(leak_interval) / u128::from(leak_rate)).max(1)
 }
 
 impl LeakyBucketInner {
@@ -132,87 +740,95 @@ impl LeakyBucketInner {
     /// * `leak_rate` - The rate at which the bucket leaks events per second.
     /// * `capacity` - The maximum capacity of the bucket.
     /// * `leak_interval` - The interval at which the bucket leaks events. If `None`, defaults to 1 second.
+    /// * `mode` - How the bucket lets events leak.
     ///
     /// # Returns
     ///
     /// Returns a new `LeakyBucketInner` instance.
-    fn new(leak_rate: u64, capacity: u64, leak_interval: Option<Duration>) -> Self {
-        let (tx, rx) = mpsc::channel();
-
-        let res = Self {
+    fn new(leak_rate: u64, capacity: u64, leak_interval: Option<Duration>, mode: Mode) -> Self {
+        Self {
             capacity,
             current_level: 0,
             leak_rate,
             leak_interval: leak_interval.unwrap_or(Duration::from_secs(1)),
-            last_leaktime: Instant::now(),
-            queue: tx,
-        };
-
-        let mut res_clone = res.clone();
-        thread::spawn(move || {
-            res_clone.start(rx);
-        });
+            wait: WaitStrategy::default(),
+            mode,
+        }
+    }
 
-        res
+    /// Returns the ticks it takes a single event to leak.
+    fn emission_interval(&self) -> u128 {
+        emission_interval(self.leak_rate, self.leak_interval)
     }
 
     /// Starts the leak process in a separate thread.
     ///
     /// This method continuously leaks events from the bucket based on the configured
     /// leak rate and interval. It listens for notifications and adjusts the bucket's state
-    /// accordingly.
+    /// accordingly. The leak rate and interval are re-read on every round, so changes
+    /// made through [`LeakyBucket::reconfigure`] are picked up without restarting the thread.
+    ///
+    /// The process stops once the bucket has been dropped, or shut down.
     ///
     /// # Arguments
     ///
+    /// * `inner` - A weak reference to the state of the bucket.
     /// * `rx` - A receiver for one-shot notifications indicating when an event can be allowed.
-    fn start(&mut self, rx: mpsc::Receiver<oneshot::Sender<()>>) {
+    /// * `stop` - The signal to stop, which also interrupts the wait for the next leak.
+    fn start(
+        inner: Weak<Mutex<LeakyBucketInner>>,
+        rx: mpsc::Receiver<oneshot::Sender<()>>,
+        stop: &Stop,
+    ) {
+        let mut last_leaktime = Instant::now();
         loop {
-            let now = Instant::now();
-            let wait_time = self.leak_interval.saturating_sub(now - self.last_leaktime);
-            if wait_time > Duration::ZERO {
-                thread::sleep(wait_time);
+            let Some(inner) = inner.upgrade() else {
+                return;
+            };
+            let (leak_rate, leak_interval, wait) = {
+                let inner = inner.lock_unpoisoned();
+                (inner.leak_rate, inner.leak_interval, inner.wait)
+            };
+            drop(inner);
+
+            if !stop.wait(wait, leak_interval.saturating_sub(last_leaktime.elapsed())) {
+                return;
             }
-            self.last_leaktime = Instant::now();
-            for _ in 0..self.leak_rate {
-                if let Ok(tx) = rx.recv() {
-                    let _ = tx.send(());
+            last_leaktime = Instant::now();
+            for _ in 0..leak_rate {
+                match rx.recv() {
+                    Ok(tx) => {
+                        let _ = tx.send(());
+                    }
+                    // all senders are gone, the bucket has been dropped or shut down
+                    Err(_) => return,
                 }
             }
         }
     }
 
-    /// Attempts to allow an event through the bucket.
+    /// Attempts to allow `n` events through the bucket.
     ///
-    /// This method increases the current level of the bucket if it is below capacity,
-    /// indicating that an event has been allowed.
+    /// This method increases the current level of the bucket if the `n` events fit
+    /// below capacity, indicating that the events have been allowed.
     ///
     /// # Returns
     ///
-    /// Returns `true` if the event is allowed, `false` otherwise.
-    fn try_allow(&mut self) -> bool {
-        if self.current_level >= self.capacity {
+    /// Returns `true` if the events are allowed, `false` otherwise.
+    fn try_allow(&mut self, n: u64) -> bool {
+        if self.current_level.saturating_add(n) > self.capacity {
             false
         } else {
-            self.current_level += 1;
+            self.current_level += n;
             true
         }
     }
-
-    /// Leaks the bucket to reflect the passage of time.
-    ///
-    /// This method decreases the current level of the bucket if it is above zero,
-    /// indicating that an event has leaked out of the bucket.
-    fn leak(&mut self) {
-        if self.current_level > 0 {
-            self.current_level -= 1;
-        }
-    }
 }
 
 #[cfg(test)]
 mod tests {
     use chrono::Utc;
-    use std::thread::sleep;
+    use std::thread::{self, sleep};
 
     use super::*;
 
@@ -236,4 +852,133 @@ mod tests {
         assert!(!bucket.allow());
         sleep(Duration::from_millis(6));
     }
+
+    #[test]
+    fn leaky_bucket_should_shut_down_its_leak_thread() {
+        let bucket = LeakyBucket::new(1, 5, Some(Duration::from_secs(3600)));
+        let waiter = {
+            let bucket = bucket.clone();
+            thread::spawn(move || bucket.allow_timeout(Duration::from_secs(10)))
+        };
+        sleep(Duration::from_millis(20));
+
+        // the leak thread is woken up, not waited for until the next leak
+        let start = Instant::now();
+        assert!(bucket.shutdown(Duration::from_secs(1)).is_ok());
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(matches!(waiter.join().unwrap(), Err(Error::Disconnected)));
+        assert!(!bucket.allow());
+        assert!(bucket.shutdown(Duration::ZERO).is_ok());
+
+        // a meter has no thread to stop
+        let meter = LeakyBucket::meter(1, 1, None);
+        assert!(meter.shutdown(Duration::ZERO).is_ok());
+        assert!(meter.allow());
+    }
+
+    #[test]
+    fn leaky_bucket_should_time_out_and_release_events() {
+        let bucket = LeakyBucket::new(1, 2, Some(Duration::from_millis(1)));
+        assert!(bucket.allow_timeout(Duration::from_secs(1)).is_ok());
+
+        // nothing leaks before the next interval
+        let bucket = LeakyBucket::new(1, 2, Some(Duration::from_secs(60)));
+        assert!(matches!(
+            bucket.allow_n_timeout(2, Duration::from_millis(20)),
+            Err(Error::Timeout)
+        ));
+        assert!(matches!(
+            bucket.allow_n_timeout(3, Duration::ZERO),
+            Err(Error::QueueFull)
+        ));
+        assert_eq!(bucket.next_available(2), Duration::ZERO);
+    }
+
+    #[cfg(not(loom))]
+    #[tokio::test]
+    async fn leaky_bucket_should_release_events_of_cancelled_futures() {
+        let bucket = LeakyBucket::new(1, 2, Some(Duration::from_millis(1)));
+        assert!(bucket.allow_n_async(2).await.is_ok());
+
+        let bucket = LeakyBucket::new(1, 2, Some(Duration::from_secs(60)));
+        let wait = tokio::time::timeout(Duration::from_millis(20), bucket.allow_n_async(2));
+        assert!(wait.await.is_err());
+        assert_eq!(bucket.next_available(2), Duration::ZERO);
+    }
+
+    #[test]
+    fn leaky_bucket_meter_should_answer_without_queuing() {
+        let clock = Arc::new(crate::ManualClock::new());
+        let bucket =
+            LeakyBucket::meter_with_clock(2, 4, Some(Duration::from_secs(1)), clock.clone());
+
+        assert!(bucket.allow_n(4));
+        assert!(!bucket.allow());
+        assert_eq!(bucket.next_available(1), Duration::from_millis(500));
+        assert_eq!(bucket.next_available(5), Duration::MAX);
+
+        // the level drains continuously
+        clock.advance(Duration::from_millis(500));
+        assert!(bucket.allow());
+        assert!(!bucket.allow());
+
+        bucket.refund(2);
+        assert!(bucket.allow_n_timeout(2, Duration::ZERO).is_ok());
+        assert!(matches!(
+            bucket.allow_timeout(Duration::ZERO),
+            Err(Error::RateLimited)
+        ));
+
+        // a slower leak keeps the events in the bucket
+        bucket.reconfigure(1, 4, Some(Duration::from_secs(1)));
+        assert_eq!(bucket.next_available(1), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn leaky_bucket_shaper_should_delay_weighted_units() {
+        let clock = Arc::new(crate::ManualClock::new());
+        let shaper = LeakyBucket::shaper_with_clock(1000, 3000, clock.clone());
+
+        // packets leave one after the other at 1000 units per second
+        assert_eq!(shaper.allow_weight(1000).unwrap(), Duration::ZERO);
+        assert_eq!(shaper.allow_weight(500).unwrap(), Duration::from_secs(1));
+        assert_eq!(
+            shaper.allow_weight(1500).unwrap(),
+            Duration::from_millis(1500)
+        );
+        assert!(matches!(shaper.allow_weight(1), Err(Error::QueueFull)));
+        assert!(matches!(shaper.allow_weight(3001), Err(Error::TooLarge)));
+
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(
+            shaper.allow_weight(250).unwrap(),
+            Duration::from_millis(2750)
+        );
+
+        // more than a unit per nanosecond, e.g. the bytes of a 10 Gbps link
+        let shaper = LeakyBucket::shaper_with_clock(1_250_000_000, 64 * 1024, clock.clone());
+        assert_eq!(shaper.allow_weight(1500).unwrap(), Duration::ZERO);
+        assert_eq!(
+            shaper.allow_weight(1500).unwrap(),
+            Duration::from_nanos(1200)
+        );
+        clock.advance(Duration::from_nanos(2400));
+        assert_eq!(shaper.allow_weight(1500).unwrap(), Duration::ZERO);
+    }
+
+    #[test]
+    fn leaky_bucket_meter_should_allow_fractional_costs() {
+        let clock = Arc::new(crate::ManualClock::new());
+        let bucket =
+            LeakyBucket::meter_with_clock(1, 1, Some(Duration::from_secs(1)), clock.clone());
+
+        assert!(bucket.allow_cost(0.25));
+        assert!(bucket.allow_cost(0.75));
+        assert!(!bucket.allow_cost(0.1));
+
+        // a tenth of a request leaks in a tenth of the interval
+        clock.advance(Duration::from_millis(100));
+        assert!(bucket.allow_cost(0.1));
+        assert!(!bucket.allow_cost(0.001));
+    }
 }
@@ -1,11 +1,192 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+// without any algorithm, the helpers the algorithms share are left unused
+#![cfg_attr(
+    not(any(
+        feature = "calendar-window",
+        feature = "fixed-window",
+        feature = "leaky-bucket",
+        feature = "sliding-log",
+        feature = "sliding-window",
+        feature = "token-bucket"
+    )),
+    allow(dead_code, unused_imports, unused_variables, unreachable_patterns)
+)]
+
+extern crate alloc;
+
+#[cfg(feature = "leaky-bucket")]
+mod adaptive;
+#[cfg(feature = "std")]
+mod anomaly;
+#[cfg(feature = "async")]
+mod async_keyed;
+#[cfg(feature = "calendar-window")]
+mod calendar_window;
+mod clock;
+mod compose;
+#[cfg(feature = "tokio")]
+mod concurrency;
+#[cfg(feature = "std")]
+mod config;
+#[cfg(feature = "std")]
+mod connection;
+mod cost;
+#[cfg(feature = "std")]
+mod count_min;
+#[cfg(any(feature = "fixed-window", feature = "std"))]
+mod counter_window;
+#[cfg(feature = "std")]
+pub mod distributed;
+mod error;
+#[cfg(feature = "std")]
+mod fair_queue;
+#[cfg(feature = "std")]
+mod fair_share;
+#[cfg(feature = "fixed-window")]
 mod fixed_window;
+#[cfg(feature = "std")]
+mod heavy_hitters;
+#[cfg(feature = "std")]
+mod journal;
+#[cfg(feature = "std")]
+mod key_store;
+#[cfg(feature = "std")]
+mod keyed;
+#[cfg(feature = "leaky-bucket")]
 mod leaky_bucket;
+mod limiter;
+#[cfg(feature = "sysinfo")]
+mod load;
+mod multi_dim;
+mod observer;
+#[cfg(feature = "otel")]
+mod otel;
+#[cfg(feature = "async")]
+mod pacing;
+#[cfg(feature = "std")]
+mod penalty_box;
+mod queue_guard;
+mod quota;
+#[cfg(feature = "std")]
+mod quota_manager;
+#[cfg(feature = "std")]
+mod rate_limited_queue;
+#[cfg(feature = "std")]
+mod rate_meter;
+#[cfg(feature = "std")]
+mod registry;
+mod retry_budget;
+#[cfg(feature = "async")]
+mod runtime;
+#[cfg(all(feature = "std", target_has_atomic = "64"))]
+pub mod simulate;
+#[cfg(feature = "sliding-window")]
 mod sliding_window_count;
+#[cfg(feature = "sliding-log")]
 mod sliding_window_log;
+mod soft_limit;
+mod sync;
+#[cfg(all(feature = "std", feature = "token-bucket"))]
+mod throttled;
+#[cfg(feature = "std")]
+mod tiered;
+#[cfg(feature = "token-bucket")]
 mod token_bucket;
+mod unlimited;
+#[cfg(feature = "std")]
+mod wait;
+mod window;
+#[cfg(feature = "std")]
+mod worker;
 
-pub use fixed_window::FixedWindow;
+#[cfg(feature = "leaky-bucket")]
+pub use adaptive::{AdaptiveClientLimiter, AdaptivePolicy, Feedback};
+#[cfg(feature = "std")]
+pub use anomaly::{AnomalyDetector, AnomalyEvent, Metric, Rates};
+#[cfg(feature = "async")]
+pub use async_keyed::AsyncKeyedLimiter;
+#[cfg(feature = "calendar-window")]
+pub use calendar_window::{CalendarPeriod, CalendarWindow};
+pub use clock::Clock;
+#[cfg(target_has_atomic = "64")]
+pub use clock::ManualClock;
+#[cfg(feature = "std")]
+pub use clock::{StdClock, SystemClock};
+pub use compose::{And, Or};
+#[cfg(feature = "tokio")]
+pub use concurrency::{ConcurrencyLimiter, OwnedPermit, Permit};
+#[cfg(feature = "std")]
+pub use config::{ConfigError, LimiterConfig, RegistryConfig};
+#[cfg(feature = "std")]
+pub use connection::{ConnectionGuard, ConnectionLimiter};
+pub use cost::RequestCost;
+#[cfg(feature = "std")]
+pub use count_min::CountMinLimiter;
+#[cfg(feature = "macros")]
+pub use devkit_rl_macros::rate_limited;
+pub use error::Error;
+#[cfg(feature = "std")]
+pub use fair_queue::FairQueue;
+#[cfg(feature = "std")]
+pub use fair_share::{FairShareFlow, FairShareLimiter};
+#[cfg(feature = "fixed-window")]
+pub use fixed_window::{FixedWindow, WindowStats};
+#[cfg(feature = "std")]
+pub use heavy_hitters::{HeavyHitter, HeavyHitters};
+#[cfg(feature = "std")]
+pub use journal::{Decision, Journal, Journaled, Outcome};
+#[cfg(feature = "std")]
+pub use key_store::{HashMapStore, KeyEntry, KeyStore, ShardedStore};
+#[cfg(feature = "std")]
+pub use keyed::{KeyHandle, KeyedBy, KeyedLimiter, KeyedLimiterStats};
+#[cfg(feature = "leaky-bucket")]
 pub use leaky_bucket::LeakyBucket;
-pub use sliding_window_count::SlidingWindowCount;
-pub use sliding_window_log::SlidingWindowLog;
-pub use token_bucket::TokenBucket;
+#[cfg(feature = "std")]
+pub use limiter::Limiter;
+pub use limiter::RateLimiter;
+#[cfg(feature = "sysinfo")]
+pub use load::{LoadSampler, LoadSignal, ProcessUsage};
+pub use multi_dim::MultiDimLimiter;
+pub use observer::{Hooks, Observed, Observer};
+#[cfg(feature = "otel")]
+pub use otel::OtelObserver;
+#[cfg(feature = "async")]
+pub use pacing::{Cost, PacedSink, PacedStream, PerByte, PerItem, PerRequest};
+#[cfg(feature = "std")]
+pub use penalty_box::{BanEvent, PenaltyBox, PenaltyPolicy};
+pub use queue_guard::{QueueGuard, QueuePermit};
+pub use quota::Quota;
+#[cfg(feature = "std")]
+pub use quota_manager::{QuotaManager, TenantQuota};
+#[cfg(feature = "std")]
+pub use rate_limited_queue::{QueueProducer, RateLimitedIter, RateLimitedQueue};
+#[cfg(feature = "std")]
+pub use rate_meter::{Horizon, RateMeter};
+#[cfg(feature = "std")]
+pub use registry::{limiter, LimiterRegistry};
+pub use retry_budget::RetryBudget;
+#[cfg(feature = "async-std")]
+pub use runtime::AsyncStdRuntime;
+#[cfg(feature = "smol")]
+pub use runtime::SmolRuntime;
+#[cfg(feature = "tokio")]
+pub use runtime::TokioRuntime;
+#[cfg(feature = "async")]
+pub use runtime::{AsyncRuntime, DefaultRuntime, Sleep};
+#[cfg(feature = "sliding-window")]
+pub use sliding_window_count::{SlidingWindowCount, WindowBucket};
+#[cfg(feature = "sliding-log")]
+pub use sliding_window_log::{LogOverflow, SlidingWindowLog};
+pub use soft_limit::{Admission, SoftLimited};
+#[cfg(all(feature = "std", feature = "token-bucket"))]
+pub use throttled::{ThrottledReader, ThrottledWriter};
+#[cfg(feature = "std")]
+pub use tiered::{Tier, TieredLimiter};
+#[cfg(feature = "token-bucket")]
+pub use token_bucket::{Refill, TokenBucket};
+pub use unlimited::Unlimited;
+#[cfg(feature = "std")]
+pub use wait::WaitStrategy;
+pub use window::BucketRing;
+#[cfg(feature = "std")]
+pub use worker::{tasks, TaskInfo, Worker};
@@ -0,0 +1,1475 @@
+use core::time::Duration;
+
+#[cfg(feature = "calendar-window")]
+use crate::CalendarWindow;
+#[cfg(feature = "fixed-window")]
+use crate::FixedWindow;
+#[cfg(feature = "leaky-bucket")]
+use crate::LeakyBucket;
+#[cfg(feature = "sliding-window")]
+use crate::SlidingWindowCount;
+#[cfg(feature = "sliding-log")]
+use crate::SlidingWindowLog;
+#[cfg(feature = "token-bucket")]
+use crate::TokenBucket;
+use crate::{And, Error, Or, RequestCost, Unlimited};
+#[cfg(feature = "async")]
+use crate::{AsyncRuntime, DefaultRuntime};
+#[cfg(feature = "std")]
+use crate::{LimiterConfig, WaitStrategy};
+
+/// The common interface shared by every rate limiter in this crate.
+///
+/// Each algorithm also exposes `allow` and `allow_n` as inherent methods, so the
+/// trait only needs to be imported when limiters are used generically, e.g. behind
+/// a `dyn RateLimiter` or through a [`LimiterRegistry`](crate::LimiterRegistry).
+///
+/// All limiters agree on the edge cases: a limiter with a capacity or size of 0
+/// denies every request, [`Unlimited`] allows every request, and `n` may be as
+/// large as `u64::MAX` without overflowing.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{RateLimiter, TokenBucket, Unlimited};
+///
+/// let limiters: Vec<Box<dyn RateLimiter>> = vec![
+///     Box::new(TokenBucket::new(10, 1, None)),
+///     Box::new(Unlimited::new()),
+/// ];
+///
+/// assert!(limiters.iter().all(|l| l.allow()));
+/// ```
+pub trait RateLimiter: Send + Sync {
+    /// Attempts to allow a single request.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the request is allowed, `false` otherwise.
+    fn allow(&self) -> bool {
+        self.allow_n(1)
+    }
+
+    /// Attempts to allow `n` requests at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of requests to allow.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the requests are allowed, `false` otherwise.
+    fn allow_n(&self, n: u64) -> bool;
+
+    /// Attempts to allow `request`, taking its [cost](RequestCost::cost).
+    ///
+    /// # Returns
+    ///
+    /// `true` if the request is allowed, `false` otherwise.
+    fn allow_for<R: RequestCost + ?Sized>(&self, request: &R) -> bool
+    where
+        Self: Sized,
+    {
+        self.allow_n(request.cost())
+    }
+
+    /// Attempts to allow requests costing `cost` in total, which may be fractional.
+    ///
+    /// This is how a request is charged a fraction of the limit, e.g. a cheap read
+    /// costing a tenth of a write. The token bucket and the leaky bucket meter
+    /// account for fractions exactly; the other limiters count whole requests and
+    /// round `cost` up. A cost that is not positive is free.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the requests are allowed, `false` otherwise.
+    fn allow_cost(&self, cost: f64) -> bool {
+        self.allow_n(whole_cost(cost))
+    }
+
+    /// Estimates how long to wait until `n` requests would be allowed.
+    ///
+    /// This lets a denied caller schedule its retry precisely, e.g. in a
+    /// `Retry-After` header. The estimate assumes no other request is made in the
+    /// meantime, so the retry may still be denied under contention.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of requests to allow.
+    ///
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if the requests would be allowed now, or `Duration::MAX` if
+    /// they exceed the capacity of the limiter and will never be allowed.
+    fn next_available(&self, n: u64) -> Duration;
+
+    /// Attempts to allow `n` requests, reporting why they were not allowed.
+    ///
+    /// This never panics, which makes it suitable for callers that must not fail,
+    /// and it distinguishes a request that exceeds the limit from a limiter that
+    /// cannot make a decision, e.g. because its backend is unavailable.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of requests to allow.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the requests are allowed, [`Error::RateLimited`] if they exceed the
+    /// limit, [`Error::TooLarge`] if they exceed what the limiter ever allows at once,
+    /// [`Error::QueueFull`] if they exceed the queue of a queuing limiter, or another
+    /// [`Error`] if the limiter failed.
+    fn try_check(&self, n: u64) -> Result<(), Error> {
+        if self.allow_n(n) {
+            Ok(())
+        } else if self.next_available(n) == Duration::MAX {
+            Err(Error::TooLarge)
+        } else {
+            Err(Error::RateLimited)
+        }
+    }
+
+    /// Attempts to allow `request`, taking its [cost](RequestCost::cost), and
+    /// reporting why it was not allowed.
+    ///
+    /// See [`RateLimiter::try_check`].
+    fn try_check_for<R: RequestCost + ?Sized>(&self, request: &R) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        self.try_check(request.cost())
+    }
+
+    /// Returns how many requests would be allowed now, e.g. for a
+    /// `RateLimit-Remaining` header, or `None` for limiters that cannot tell.
+    fn remaining(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns when the current window of a window based limiter started, by the
+    /// clock of the limiter, or `None` for limiters without windows.
+    ///
+    /// A change of the returned value means that a new window has started, which is
+    /// what [`Observer::on_window_reset`](crate::Observer::on_window_reset) reports.
+    fn window_start(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Gives back `n` requests previously allowed, e.g. because a later check
+    /// denied the same request.
+    ///
+    /// Limiters that cannot give requests back, e.g. a queuing leaky bucket whose
+    /// requests have leaked, ignore it. See [`TokenBucket::refund`] and the `refund`
+    /// methods of the other algorithms.
+    fn refund(&self, n: u64) {
+        let _ = n;
+    }
+
+    /// Combines this limiter with `other`, allowing requests only if both allow
+    /// them.
+    ///
+    /// Requests denied by `other` are refunded to this limiter, so that they are
+    /// accounted to neither. Combined with [`KeyedLimiter::keyed_by`](crate::KeyedLimiter::keyed_by),
+    /// this builds a global limit on top of a limit per key.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use devkit_rl::{LimiterConfig, KeyedLimiter, RateLimiter, TokenBucket};
+    ///
+    /// let global = TokenBucket::new(3, 3, None);
+    /// let per_ip = KeyedLimiter::new(LimiterConfig::TokenBucket {
+    ///     capacity: 2,
+    ///     refill_rate: 2,
+    ///     refill_interval_ms: None,
+    ///     initial_tokens: None,
+    /// });
+    /// let limiter = |ip| (&global).and(per_ip.keyed_by(ip));
+    ///
+    /// assert!(limiter("10.0.0.1").allow_n(2));
+    /// // denied by the limit of the key, without using up the global limit
+    /// assert!(!limiter("10.0.0.1").allow());
+    /// assert!(limiter("10.0.0.2").allow());
+    /// assert!(!limiter("10.0.0.3").allow());
+    /// ```
+    fn and<B: RateLimiter>(self, other: B) -> And<Self, B>
+    where
+        Self: Sized,
+    {
+        And::new(self, other)
+    }
+
+    /// Combines this limiter with `other`, allowing requests if either allows
+    /// them.
+    ///
+    /// `other` is only checked if this limiter denies the requests, which makes it
+    /// an overflow allowance, e.g. a daily burst budget on top of a steady rate.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::{FixedWindow, RateLimiter, TokenBucket};
+    ///
+    /// let steady = TokenBucket::new(1, 1, None);
+    /// let burst = FixedWindow::new(2, Some(Duration::from_secs(86400)));
+    /// let limiter = steady.or(burst);
+    ///
+    /// assert!(limiter.allow());
+    /// // beyond the steady rate, from the burst budget
+    /// assert!(limiter.allow_n(2));
+    /// assert!(!limiter.allow());
+    /// ```
+    fn or<B: RateLimiter>(self, other: B) -> Or<Self, B>
+    where
+        Self: Sized,
+    {
+        Or::new(self, other)
+    }
+}
+
+impl<L: RateLimiter + ?Sized> RateLimiter for &L {
+    fn allow_n(&self, n: u64) -> bool {
+        (**self).allow_n(n)
+    }
+
+    fn allow_cost(&self, cost: f64) -> bool {
+        (**self).allow_cost(cost)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        (**self).next_available(n)
+    }
+
+    fn try_check(&self, n: u64) -> Result<(), Error> {
+        (**self).try_check(n)
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        (**self).remaining()
+    }
+
+    fn window_start(&self) -> Option<Duration> {
+        (**self).window_start()
+    }
+
+    fn refund(&self, n: u64) {
+        (**self).refund(n);
+    }
+}
+
+/// The units a request is divided into by the limiters accounting for fractional
+/// costs, see [`RateLimiter::allow_cost`].
+#[cfg(any(feature = "leaky-bucket", feature = "token-bucket"))]
+pub(crate) const COST_SCALE: u128 = 1_000_000_000;
+
+/// Converts `cost` into units of [`COST_SCALE`], rounding up. Costs that are not
+/// positive are free.
+#[cfg(any(feature = "leaky-bucket", feature = "token-bucket"))]
+pub(crate) fn cost_units(cost: f64) -> u128 {
+    let units = cost * COST_SCALE as f64;
+    let whole = units as u128;
+    if (whole as f64) < units {
+        whole + 1
+    } else {
+        whole
+    }
+}
+
+/// Rounds `cost` up to whole requests. Costs that are not positive are free.
+pub(crate) fn whole_cost(cost: f64) -> u64 {
+    let whole = cost as u64;
+    if (whole as f64) < cost {
+        whole.saturating_add(1)
+    } else {
+        whole
+    }
+}
+
+/// The shortest time to wait before trying a limiter again, so that a limiter
+/// rounding its estimate down, or denying a request it estimated to be available,
+/// e.g. under contention, does not make its callers spin.
+#[cfg(feature = "std")]
+pub(crate) const MIN_WAIT: Duration = Duration::from_millis(1);
+
+/// Returns how long to wait before trying a limiter again, from its estimate of
+/// [`RateLimiter::next_available`].
+///
+/// # Errors
+///
+/// [`Error::TooLarge`] if the limiter will never allow the requests.
+#[cfg(feature = "std")]
+pub(crate) fn retry_after(next_available: Duration) -> Result<Duration, Error> {
+    match next_available {
+        Duration::MAX => Err(Error::TooLarge),
+        wait => Ok(wait.max(MIN_WAIT)),
+    }
+}
+
+/// Returns a random duration below `max`, or zero if `max` is zero.
+#[cfg(feature = "std")]
+fn random_jitter(max: Duration) -> Duration {
+    use std::hash::BuildHasher;
+
+    let nanos = u64::try_from(max.as_nanos()).unwrap_or(u64::MAX);
+    if nanos == 0 {
+        return Duration::ZERO;
+    }
+    // every `RandomState` is seeded differently, which is random enough for jitter
+    let random = std::collections::hash_map::RandomState::new().hash_one(nanos);
+    Duration::from_nanos(random % nanos)
+}
+
+/// A rate limiter of any of the algorithms provided by this crate.
+///
+/// `Limiter` is what a [`LimiterRegistry`](crate::LimiterRegistry) hands out. It is
+/// cheap to clone, and every clone shares the state of the underlying limiter.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub enum Limiter {
+    #[cfg(feature = "token-bucket")]
+    TokenBucket(TokenBucket),
+    #[cfg(feature = "leaky-bucket")]
+    LeakyBucket(LeakyBucket),
+    #[cfg(feature = "fixed-window")]
+    FixedWindow(FixedWindow),
+    #[cfg(feature = "sliding-log")]
+    SlidingWindowLog(SlidingWindowLog),
+    #[cfg(feature = "sliding-window")]
+    SlidingWindowCount(SlidingWindowCount),
+    #[cfg(feature = "calendar-window")]
+    CalendarWindow(CalendarWindow),
+    Unlimited(Unlimited),
+}
+
+#[cfg(feature = "std")]
+impl Limiter {
+    /// Applies `config` to this limiter in place, keeping its current state.
+    ///
+    /// Every handle sharing this limiter observes the new parameters immediately.
+    /// A limiter cannot switch algorithms in place, so nothing is changed if `config`
+    /// describes a different algorithm than the one of this limiter. The same goes
+    /// for a leaky bucket switching between queuing and metering.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the configuration was applied, `false` if the algorithms differ.
+    pub fn reconfigure(&self, config: &LimiterConfig) -> bool {
+        match (self, *config) {
+            #[cfg(feature = "token-bucket")]
+            (
+                Limiter::TokenBucket(l),
+                LimiterConfig::TokenBucket {
+                    capacity,
+                    refill_rate,
+                    refill_interval_ms,
+                    ..
+                },
+            ) => l.reconfigure(
+                capacity,
+                refill_rate,
+                refill_interval_ms.map(Duration::from_millis),
+            ),
+            #[cfg(feature = "leaky-bucket")]
+            (
+                Limiter::LeakyBucket(l),
+                LimiterConfig::LeakyBucket {
+                    leak_rate,
+                    capacity,
+                    leak_interval_ms,
+                    meter,
+                },
+            ) if l.is_meter() == meter => l.reconfigure(
+                leak_rate,
+                capacity,
+                leak_interval_ms.map(Duration::from_millis),
+            ),
+            #[cfg(feature = "fixed-window")]
+            (
+                Limiter::FixedWindow(l),
+                LimiterConfig::FixedWindow {
+                    size,
+                    interval_ms,
+                    smoothing,
+                },
+            ) => {
+                l.reconfigure(size, interval_ms.map(Duration::from_millis));
+                l.set_smoothing(smoothing);
+            }
+            #[cfg(feature = "sliding-log")]
+            (
+                Limiter::SlidingWindowLog(l),
+                LimiterConfig::SlidingWindowLog {
+                    size,
+                    interval_ms,
+                    max_entries,
+                    overflow,
+                },
+            ) => {
+                l.set_max_entries(
+                    max_entries.unwrap_or(SlidingWindowLog::DEFAULT_MAX_ENTRIES),
+                    overflow,
+                );
+                l.reconfigure(size, interval_ms.map(Duration::from_millis));
+            }
+            #[cfg(feature = "sliding-window")]
+            (
+                Limiter::SlidingWindowCount(l),
+                LimiterConfig::SlidingWindowCount {
+                    size,
+                    interval_ms,
+                    bucket_count,
+                },
+            ) => l.reconfigure(
+                size,
+                Duration::from_millis(interval_ms.unwrap_or(1000)),
+                bucket_count,
+            ),
+            #[cfg(feature = "calendar-window")]
+            (
+                Limiter::CalendarWindow(l),
+                LimiterConfig::CalendarWindow {
+                    size,
+                    period,
+                    utc_offset_secs,
+                },
+            ) => l.reconfigure(size, period, utc_offset_secs),
+            (Limiter::Unlimited(_), LimiterConfig::Unlimited) => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// Gives back `n` requests previously allowed by this limiter.
+    ///
+    /// See [`TokenBucket::refund`] and the `refund` methods of the other algorithms.
+    pub fn refund(&self, n: u64) {
+        match self {
+            #[cfg(feature = "token-bucket")]
+            Limiter::TokenBucket(l) => l.refund(n),
+            #[cfg(feature = "leaky-bucket")]
+            Limiter::LeakyBucket(l) => l.refund(n),
+            #[cfg(feature = "fixed-window")]
+            Limiter::FixedWindow(l) => l.refund(n),
+            #[cfg(feature = "sliding-log")]
+            Limiter::SlidingWindowLog(l) => l.refund(n),
+            #[cfg(feature = "sliding-window")]
+            Limiter::SlidingWindowCount(l) => l.refund(n),
+            #[cfg(feature = "calendar-window")]
+            Limiter::CalendarWindow(l) => l.refund(n),
+            Limiter::Unlimited(l) => l.refund(n),
+        }
+    }
+
+    /// Attempts to allow a single request at the time `now`.
+    ///
+    /// This is a convenience method for `allow_n_at(1, now)`.
+    pub fn allow_at(&self, now: Duration) -> bool {
+        self.allow_n_at(1, now)
+    }
+
+    /// Attempts to allow `n` requests at the time `now`, instead of the time read
+    /// from the clock of the limiter, e.g. to replay a log.
+    ///
+    /// See [`TokenBucket::allow_n_at`] and the `allow_n_at` methods of the other
+    /// algorithms.
+    pub fn allow_n_at(&self, n: u64, now: Duration) -> bool {
+        match self {
+            #[cfg(feature = "token-bucket")]
+            Limiter::TokenBucket(l) => l.allow_n_at(n, now),
+            #[cfg(feature = "leaky-bucket")]
+            Limiter::LeakyBucket(l) => l.allow_n_at(n, now),
+            #[cfg(feature = "fixed-window")]
+            Limiter::FixedWindow(l) => l.allow_n_at(n, now),
+            #[cfg(feature = "sliding-log")]
+            Limiter::SlidingWindowLog(l) => l.allow_n_at(n, now),
+            #[cfg(feature = "sliding-window")]
+            Limiter::SlidingWindowCount(l) => l.allow_n_at(n, now),
+            #[cfg(feature = "calendar-window")]
+            Limiter::CalendarWindow(l) => l.allow_n_at(n, now),
+            Limiter::Unlimited(l) => l.allow_n(n),
+        }
+    }
+
+    /// Like [`Limiter::allow_n_at`], but fails with [`Error::ClockWentBackwards`]
+    /// if `now` is older than the latest time seen by the limiter.
+    ///
+    /// See [`TokenBucket::try_allow_n_at`] and the `try_allow_n_at` methods of the
+    /// other algorithms.
+    pub fn try_allow_n_at(&self, n: u64, now: Duration) -> Result<(), Error> {
+        match self {
+            #[cfg(feature = "token-bucket")]
+            Limiter::TokenBucket(l) => l.try_allow_n_at(n, now),
+            #[cfg(feature = "leaky-bucket")]
+            Limiter::LeakyBucket(l) => l.try_allow_n_at(n, now),
+            #[cfg(feature = "fixed-window")]
+            Limiter::FixedWindow(l) => l.try_allow_n_at(n, now),
+            #[cfg(feature = "sliding-log")]
+            Limiter::SlidingWindowLog(l) => l.try_allow_n_at(n, now),
+            #[cfg(feature = "sliding-window")]
+            Limiter::SlidingWindowCount(l) => l.try_allow_n_at(n, now),
+            #[cfg(feature = "calendar-window")]
+            Limiter::CalendarWindow(l) => l.try_allow_n_at(n, now),
+            Limiter::Unlimited(l) => l.try_check(n),
+        }
+    }
+
+    /// Attempts to allow `n` requests, waiting at most `timeout` for them.
+    ///
+    /// Only the leaky bucket waits for its requests, see [`LeakyBucket::allow_n_timeout`].
+    /// The other algorithms decide right away, like [`RateLimiter::try_check`].
+    #[cfg_attr(not(feature = "leaky-bucket"), allow(unused_variables))]
+    pub fn allow_n_timeout(&self, n: u64, timeout: Duration) -> Result<(), Error> {
+        match self {
+            #[cfg(feature = "leaky-bucket")]
+            Limiter::LeakyBucket(l) => l.allow_n_timeout(n, timeout),
+            _ => self.try_check(n),
+        }
+    }
+
+    /// Allows `n` requests, blocking the thread until the limiter lets them through.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the requests are allowed, [`Error::TooLarge`] if they can never
+    /// be at once, e.g. because `n` exceeds the capacity of the limiter, or
+    /// [`Error::Disconnected`] if the leak thread of a leaky bucket has stopped.
+    pub fn wait(&self, n: u64) -> Result<(), Error> {
+        self.wait_jittered(n, Duration::ZERO)
+    }
+
+    /// Allows `n` requests like [`Limiter::wait`], in as many rounds as it takes if
+    /// `n` exceeds what the limiter allows at once.
+    ///
+    /// The requests are split into batches of the most the limiter ever allows at
+    /// once, e.g. its capacity, each waited for in turn: a transfer of 10 MB through
+    /// a bucket of 1 MB goes through over 10 refills rather than never. The batches
+    /// allowed stay spent if a later one fails.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once all the requests are allowed, [`Error::TooLarge`] if the limiter
+    /// never allows even one, or the error of [`Limiter::wait`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::{Error, Limiter, TokenBucket};
+    ///
+    /// let limiter = Limiter::TokenBucket(TokenBucket::new(2, 2, Some(Duration::from_millis(10))));
+    ///
+    /// assert!(matches!(limiter.wait(5), Err(Error::TooLarge)));
+    /// assert!(limiter.wait_all(5).is_ok());
+    /// ```
+    pub fn wait_all(&self, n: u64) -> Result<(), Error> {
+        let batch = self.max_batch(n)?;
+        let mut left = n;
+        while left > 0 {
+            let take = left.min(batch);
+            self.wait(take)?;
+            left -= take;
+        }
+        Ok(())
+    }
+
+    /// Allows `n` requests like [`Limiter::wait`], adding a random delay of up to
+    /// `jitter` to every wait.
+    ///
+    /// Without jitter, all the callers waiting for a window limiter wake up at the
+    /// same window boundary, and hit the limiter, and whatever it protects, in a
+    /// burst. The jitter spreads them out. Requests allowed right away are not
+    /// delayed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::{Limiter, TokenBucket};
+    ///
+    /// let limiter = Limiter::TokenBucket(TokenBucket::new(1, 1, Some(Duration::from_millis(10))));
+    ///
+    /// assert!(limiter.wait_jittered(1, Duration::from_millis(5)).is_ok());
+    /// assert!(limiter.wait_jittered(1, Duration::from_millis(5)).is_ok());
+    /// ```
+    pub fn wait_jittered(&self, n: u64, jitter: Duration) -> Result<(), Error> {
+        self.wait_with(n, jitter, WaitStrategy::Sleep)
+    }
+
+    /// Allows `n` requests like [`Limiter::wait_jittered`], waiting with `strategy`.
+    ///
+    /// Sleeping wakes up late by the lateness of the scheduler, which matters for
+    /// limits pacing requests less than a millisecond apart, see [`WaitStrategy`].
+    /// A queuing leaky bucket waits for its leak thread instead, whose strategy is
+    /// set by [`LeakyBucket::set_wait_strategy`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::{Limiter, TokenBucket, WaitStrategy};
+    ///
+    /// let limiter = Limiter::TokenBucket(TokenBucket::new(1, 1, Some(Duration::from_micros(100))));
+    /// let strategy = WaitStrategy::Hybrid { spin: Duration::from_micros(50) };
+    ///
+    /// for _ in 0..10 {
+    ///     assert!(limiter.wait_with(1, Duration::ZERO, strategy).is_ok());
+    /// }
+    /// ```
+    pub fn wait_with(&self, n: u64, jitter: Duration, strategy: WaitStrategy) -> Result<(), Error> {
+        loop {
+            match self {
+                #[cfg(feature = "leaky-bucket")]
+                Limiter::LeakyBucket(l) => match l.allow_n_timeout(n, Duration::MAX) {
+                    Err(Error::QueueFull | Error::RateLimited) => {}
+                    done => return done,
+                },
+                _ if self.allow_n(n) => return Ok(()),
+                _ => {}
+            }
+            strategy.wait(retry_after(self.next_available(n))? + random_jitter(jitter));
+        }
+    }
+
+    /// Allows `n` requests if the estimated wait for them is at most `budget`,
+    /// blocking the thread until they are, and denies them right away otherwise.
+    ///
+    /// Unlike [`Limiter::allow_n_timeout`], which waits out its timeout before
+    /// failing, this fails fast, which suits interactive endpoints preferring an
+    /// immediate error to a long queue. The estimate is that of
+    /// [`RateLimiter::next_available`], checked again after every wait, since other
+    /// callers may take the quota first.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the requests are allowed, [`Error::OverBudget`] with the
+    /// estimated wait if it exceeds the budget left, [`Error::Timeout`] if a leaky
+    /// bucket queues them for longer than the budget, [`Error::TooLarge`] if they can
+    /// never be at once, or [`Error::Disconnected`] if the leak thread of a leaky
+    /// bucket has stopped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::{Error, Limiter, TokenBucket};
+    ///
+    /// let limiter = Limiter::TokenBucket(TokenBucket::new(1, 1, Some(Duration::from_secs(1))));
+    ///
+    /// assert!(limiter.admit_if_wait_below(1, Duration::ZERO).is_ok());
+    /// assert!(matches!(
+    ///     limiter.admit_if_wait_below(1, Duration::from_millis(100)),
+    ///     Err(Error::OverBudget(wait)) if wait > Duration::from_millis(100)
+    /// ));
+    /// ```
+    pub fn admit_if_wait_below(&self, n: u64, budget: Duration) -> Result<(), Error> {
+        let start = std::time::Instant::now();
+        loop {
+            let left = budget.saturating_sub(start.elapsed());
+            self.within_budget(n, left)?;
+            match self {
+                #[cfg(feature = "leaky-bucket")]
+                Limiter::LeakyBucket(l) => match l.allow_n_timeout(n, left) {
+                    Err(Error::QueueFull | Error::RateLimited) => {}
+                    done => return done,
+                },
+                _ if self.allow_n(n) => return Ok(()),
+                _ => {}
+            }
+            WaitStrategy::Sleep.wait(retry_after(self.next_available(n))?.min(left));
+        }
+    }
+
+    /// Allows `n` requests, waiting asynchronously until the limiter lets them through.
+    ///
+    /// This is the async counterpart of [`Limiter::wait`]: the task sleeps on the
+    /// timer of the [`DefaultRuntime`] instead of blocking the thread.
+    #[cfg(feature = "async")]
+    pub async fn wait_async(&self, n: u64) -> Result<(), Error> {
+        self.wait_async_jittered(n, Duration::ZERO).await
+    }
+
+    /// Allows `n` requests like [`Limiter::wait_async`], in as many rounds as it
+    /// takes if `n` exceeds what the limiter allows at once, see
+    /// [`Limiter::wait_all`].
+    ///
+    /// The batches allowed stay spent if the future is dropped before the last one.
+    #[cfg(feature = "async")]
+    pub async fn wait_async_all(&self, n: u64) -> Result<(), Error> {
+        let batch = self.max_batch(n)?;
+        let mut left = n;
+        while left > 0 {
+            let take = left.min(batch);
+            self.wait_async(take).await?;
+            left -= take;
+        }
+        Ok(())
+    }
+
+    /// Allows `n` requests like [`Limiter::wait_async`], adding a random delay of up
+    /// to `jitter` to every wait, see [`Limiter::wait_jittered`].
+    #[cfg(feature = "async")]
+    pub async fn wait_async_jittered(&self, n: u64, jitter: Duration) -> Result<(), Error> {
+        self.wait_async_with(n, jitter, &DefaultRuntime).await
+    }
+
+    /// Allows `n` requests like [`Limiter::wait_async_jittered`], sleeping on the
+    /// timer of `runtime`, see [`AsyncRuntime`].
+    #[cfg(feature = "async")]
+    pub async fn wait_async_with(
+        &self,
+        n: u64,
+        jitter: Duration,
+        runtime: &(impl AsyncRuntime + ?Sized),
+    ) -> Result<(), Error> {
+        loop {
+            match self {
+                #[cfg(feature = "leaky-bucket")]
+                Limiter::LeakyBucket(l) => match l.allow_n_async(n).await {
+                    Err(Error::QueueFull | Error::RateLimited) => {}
+                    done => return done,
+                },
+                _ if self.allow_n(n) => return Ok(()),
+                _ => {}
+            }
+            runtime
+                .sleep(retry_after(self.next_available(n))? + random_jitter(jitter))
+                .await;
+        }
+    }
+
+    /// Allows `n` requests like [`Limiter::admit_if_wait_below`], waiting
+    /// asynchronously on the timer of the [`DefaultRuntime`].
+    ///
+    /// A leaky bucket waiting for the requests to leak out past the budget takes
+    /// them back out of the bucket, and fails with [`Error::Timeout`].
+    #[cfg(feature = "async")]
+    pub async fn admit_if_wait_below_async(&self, n: u64, budget: Duration) -> Result<(), Error> {
+        let start = std::time::Instant::now();
+        loop {
+            let left = budget.saturating_sub(start.elapsed());
+            self.within_budget(n, left)?;
+            match self {
+                #[cfg(feature = "leaky-bucket")]
+                Limiter::LeakyBucket(l) => {
+                    let mut allow = Box::pin(l.allow_n_async(n));
+                    match crate::runtime::timeout(&DefaultRuntime, left, &mut allow).await {
+                        Some(Err(Error::QueueFull | Error::RateLimited)) => {}
+                        Some(done) => return done,
+                        None => return Err(Error::Timeout),
+                    }
+                }
+                _ if self.allow_n(n) => return Ok(()),
+                _ => {}
+            }
+            DefaultRuntime
+                .sleep(retry_after(self.next_available(n))?.min(left))
+                .await;
+        }
+    }
+
+    /// Checks that the estimated wait for `n` requests is at most `budget`.
+    fn within_budget(&self, n: u64, budget: Duration) -> Result<(), Error> {
+        match self.next_available(n) {
+            Duration::MAX => Err(Error::TooLarge),
+            wait if wait > budget => Err(Error::OverBudget(wait)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the most requests up to `n` the limiter ever allows at once.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::TooLarge`] if it never allows a single request.
+    fn max_batch(&self, n: u64) -> Result<u64, Error> {
+        if self.next_available(n) != Duration::MAX {
+            return Ok(n);
+        }
+        // bisect between a batch the limiter allows and one it never does
+        let (mut fits, mut exceeds) = (0, n);
+        while exceeds - fits > 1 {
+            let mid = fits + (exceeds - fits) / 2;
+            if self.next_available(mid) == Duration::MAX {
+                exceeds = mid;
+            } else {
+                fits = mid;
+            }
+        }
+        match fits {
+            0 => Err(Error::TooLarge),
+            fits => Ok(fits),
+        }
+    }
+
+    /// Estimates the memory held by the state of this limiter, in bytes.
+    pub(crate) fn mem_size(&self) -> usize {
+        match self {
+            #[cfg(feature = "token-bucket")]
+            Limiter::TokenBucket(l) => l.mem_size(),
+            #[cfg(feature = "leaky-bucket")]
+            Limiter::LeakyBucket(l) => l.mem_size(),
+            #[cfg(feature = "fixed-window")]
+            Limiter::FixedWindow(l) => l.mem_size(),
+            #[cfg(feature = "sliding-log")]
+            Limiter::SlidingWindowLog(l) => l.mem_size(),
+            #[cfg(feature = "sliding-window")]
+            Limiter::SlidingWindowCount(l) => l.mem_size(),
+            #[cfg(feature = "calendar-window")]
+            Limiter::CalendarWindow(l) => l.mem_size(),
+            Limiter::Unlimited(_) => 0,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl RateLimiter for Limiter {
+    fn allow_n(&self, n: u64) -> bool {
+        match self {
+            #[cfg(feature = "token-bucket")]
+            Limiter::TokenBucket(l) => l.allow_n(n),
+            #[cfg(feature = "leaky-bucket")]
+            Limiter::LeakyBucket(l) => l.allow_n(n),
+            #[cfg(feature = "fixed-window")]
+            Limiter::FixedWindow(l) => l.allow_n(n),
+            #[cfg(feature = "sliding-log")]
+            Limiter::SlidingWindowLog(l) => l.allow_n(n),
+            #[cfg(feature = "sliding-window")]
+            Limiter::SlidingWindowCount(l) => l.allow_n(n),
+            #[cfg(feature = "calendar-window")]
+            Limiter::CalendarWindow(l) => l.allow_n(n),
+            Limiter::Unlimited(l) => l.allow_n(n),
+        }
+    }
+
+    fn allow_cost(&self, cost: f64) -> bool {
+        match self {
+            #[cfg(feature = "token-bucket")]
+            Limiter::TokenBucket(l) => l.allow_cost(cost),
+            #[cfg(feature = "leaky-bucket")]
+            Limiter::LeakyBucket(l) => l.allow_cost(cost),
+            _ => self.allow_n(whole_cost(cost)),
+        }
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        match self {
+            #[cfg(feature = "token-bucket")]
+            Limiter::TokenBucket(l) => l.next_available(n),
+            #[cfg(feature = "leaky-bucket")]
+            Limiter::LeakyBucket(l) => l.next_available(n),
+            #[cfg(feature = "fixed-window")]
+            Limiter::FixedWindow(l) => l.next_available(n),
+            #[cfg(feature = "sliding-log")]
+            Limiter::SlidingWindowLog(l) => l.next_available(n),
+            #[cfg(feature = "sliding-window")]
+            Limiter::SlidingWindowCount(l) => l.next_available(n),
+            #[cfg(feature = "calendar-window")]
+            Limiter::CalendarWindow(l) => l.next_available(n),
+            Limiter::Unlimited(l) => l.next_available(n),
+        }
+    }
+
+    fn try_check(&self, n: u64) -> Result<(), Error> {
+        match self {
+            #[cfg(feature = "token-bucket")]
+            Limiter::TokenBucket(l) => l.try_check(n),
+            #[cfg(feature = "leaky-bucket")]
+            Limiter::LeakyBucket(l) => l.try_check(n),
+            #[cfg(feature = "fixed-window")]
+            Limiter::FixedWindow(l) => l.try_check(n),
+            #[cfg(feature = "sliding-log")]
+            Limiter::SlidingWindowLog(l) => l.try_check(n),
+            #[cfg(feature = "sliding-window")]
+            Limiter::SlidingWindowCount(l) => l.try_check(n),
+            #[cfg(feature = "calendar-window")]
+            Limiter::CalendarWindow(l) => l.try_check(n),
+            Limiter::Unlimited(l) => l.try_check(n),
+        }
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        match self {
+            #[cfg(feature = "token-bucket")]
+            Limiter::TokenBucket(l) => Some(l.remaining()),
+            #[cfg(feature = "fixed-window")]
+            Limiter::FixedWindow(l) => Some(l.remaining()),
+            #[cfg(feature = "sliding-log")]
+            Limiter::SlidingWindowLog(l) => Some(l.remaining()),
+            #[cfg(feature = "sliding-window")]
+            Limiter::SlidingWindowCount(l) => Some(l.remaining()),
+            #[cfg(feature = "calendar-window")]
+            Limiter::CalendarWindow(l) => Some(l.remaining()),
+            #[cfg(feature = "leaky-bucket")]
+            Limiter::LeakyBucket(_) => None,
+            Limiter::Unlimited(_) => None,
+        }
+    }
+
+    fn window_start(&self) -> Option<Duration> {
+        match self {
+            #[cfg(feature = "fixed-window")]
+            Limiter::FixedWindow(l) => Some(l.window_start()),
+            #[cfg(feature = "calendar-window")]
+            Limiter::CalendarWindow(l) => Some(l.window_start()),
+            _ => None,
+        }
+    }
+
+    fn refund(&self, n: u64) {
+        Limiter::refund(self, n);
+    }
+}
+
+#[cfg(feature = "token-bucket")]
+impl RateLimiter for TokenBucket {
+    fn allow_n(&self, n: u64) -> bool {
+        TokenBucket::allow_n(self, n)
+    }
+
+    fn allow_cost(&self, cost: f64) -> bool {
+        TokenBucket::allow_cost(self, cost)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        TokenBucket::next_available(self, n)
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        Some(TokenBucket::remaining(self))
+    }
+
+    fn refund(&self, n: u64) {
+        TokenBucket::refund(self, n);
+    }
+}
+
+#[cfg(feature = "leaky-bucket")]
+impl RateLimiter for LeakyBucket {
+    fn allow_n(&self, n: u64) -> bool {
+        LeakyBucket::allow_n(self, n)
+    }
+
+    fn allow_cost(&self, cost: f64) -> bool {
+        LeakyBucket::allow_cost(self, cost)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        LeakyBucket::next_available(self, n)
+    }
+
+    fn try_check(&self, n: u64) -> Result<(), Error> {
+        self.acquire(n).map_err(|e| match e {
+            Error::RateLimited | Error::QueueFull if self.next_available(n) == Duration::MAX => {
+                Error::TooLarge
+            }
+            e => e,
+        })
+    }
+
+    fn refund(&self, n: u64) {
+        LeakyBucket::refund(self, n);
+    }
+}
+
+#[cfg(feature = "fixed-window")]
+impl RateLimiter for FixedWindow {
+    fn allow_n(&self, n: u64) -> bool {
+        FixedWindow::allow_n(self, n)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        FixedWindow::next_available(self, n)
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        Some(FixedWindow::remaining(self))
+    }
+
+    fn window_start(&self) -> Option<Duration> {
+        Some(FixedWindow::window_start(self))
+    }
+
+    fn refund(&self, n: u64) {
+        FixedWindow::refund(self, n);
+    }
+}
+
+#[cfg(feature = "calendar-window")]
+impl RateLimiter for CalendarWindow {
+    fn allow_n(&self, n: u64) -> bool {
+        CalendarWindow::allow_n(self, n)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        CalendarWindow::next_available(self, n)
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        Some(CalendarWindow::remaining(self))
+    }
+
+    fn window_start(&self) -> Option<Duration> {
+        Some(CalendarWindow::window_start(self))
+    }
+
+    fn refund(&self, n: u64) {
+        CalendarWindow::refund(self, n);
+    }
+}
+
+#[cfg(feature = "sliding-log")]
+impl RateLimiter for SlidingWindowLog {
+    fn allow_n(&self, n: u64) -> bool {
+        SlidingWindowLog::allow_n(self, n)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        SlidingWindowLog::next_available(self, n)
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        Some(SlidingWindowLog::remaining(self))
+    }
+
+    fn refund(&self, n: u64) {
+        SlidingWindowLog::refund(self, n);
+    }
+}
+
+#[cfg(feature = "sliding-window")]
+impl RateLimiter for SlidingWindowCount {
+    fn allow_n(&self, n: u64) -> bool {
+        SlidingWindowCount::allow_n(self, n)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        SlidingWindowCount::next_available(self, n)
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        Some(SlidingWindowCount::remaining(self))
+    }
+
+    fn refund(&self, n: u64) {
+        SlidingWindowCount::refund(self, n);
+    }
+}
+
+impl RateLimiter for Unlimited {
+    fn allow_n(&self, n: u64) -> bool {
+        Unlimited::allow_n(self, n)
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        Unlimited::next_available(self, n)
+    }
+
+    fn refund(&self, n: u64) {
+        Unlimited::refund(self, n);
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "std",
+    any(
+        feature = "token-bucket",
+        feature = "leaky-bucket",
+        feature = "fixed-window",
+        feature = "sliding-log",
+        feature = "sliding-window"
+    )
+))]
+mod tests {
+    use alloc::sync::Arc;
+
+    use super::*;
+    #[cfg(feature = "calendar-window")]
+    use crate::CalendarPeriod;
+    #[cfg(feature = "sliding-log")]
+    use crate::LogOverflow;
+    use crate::{Clock, ManualClock};
+
+    #[cfg(feature = "token-bucket")]
+    #[test]
+    fn try_check_should_report_rate_limited() {
+        let limiter = Limiter::TokenBucket(TokenBucket::new(1, 1, Some(Duration::from_secs(60))));
+
+        assert!(limiter.try_check(1).is_ok());
+        assert!(matches!(limiter.try_check(1), Err(Error::RateLimited)));
+        assert!(matches!(limiter.try_check(2), Err(Error::TooLarge)));
+    }
+
+    #[cfg(any(
+        feature = "token-bucket",
+        feature = "fixed-window",
+        feature = "sliding-log",
+        feature = "sliding-window"
+    ))]
+    #[test]
+    fn next_available_should_estimate_retry_time() {
+        const INTERVAL: Duration = Duration::from_secs(60);
+
+        let limiters = [
+            #[cfg(feature = "token-bucket")]
+            Limiter::TokenBucket(TokenBucket::new(2, 1, Some(INTERVAL))),
+            #[cfg(feature = "fixed-window")]
+            Limiter::FixedWindow(FixedWindow::new(2, Some(INTERVAL))),
+            #[cfg(feature = "fixed-window")]
+            Limiter::FixedWindow(FixedWindow::with_smoothing(2, Some(INTERVAL), true)),
+            #[cfg(feature = "sliding-log")]
+            Limiter::SlidingWindowLog(SlidingWindowLog::new(2, Some(INTERVAL))),
+            #[cfg(feature = "sliding-window")]
+            Limiter::SlidingWindowCount(SlidingWindowCount::new(2, INTERVAL, 10)),
+        ];
+        for limiter in limiters {
+            assert_eq!(limiter.next_available(2), Duration::ZERO, "{limiter:?}");
+            assert!(limiter.allow_n(2));
+
+            let wait = limiter.next_available(1);
+            assert!(
+                wait > Duration::ZERO && wait <= INTERVAL * 2,
+                "{limiter:?}: {wait:?}"
+            );
+            assert_eq!(limiter.next_available(3), Duration::MAX, "{limiter:?}");
+        }
+
+        // the token bucket refills one token per interval
+        #[cfg(feature = "token-bucket")]
+        {
+            let bucket = TokenBucket::new(2, 1, Some(INTERVAL));
+            assert!(bucket.allow_n(2));
+            assert!(bucket.next_available(2) > INTERVAL);
+        }
+
+        assert_eq!(Unlimited::new().next_available(u64::MAX), Duration::ZERO);
+    }
+
+    #[cfg(any(
+        feature = "token-bucket",
+        feature = "leaky-bucket",
+        feature = "fixed-window",
+        feature = "sliding-log",
+        feature = "sliding-window"
+    ))]
+    #[test]
+    fn allow_n_at_should_replay_timestamps() {
+        const INTERVAL: Duration = Duration::from_secs(60);
+        const START: Duration = Duration::from_secs(1000);
+        const NEXT_DAY: Duration = Duration::from_secs(1000 + 86_400);
+
+        // the clock never moves: only the timestamps given do
+        let clock: Arc<dyn Clock> = Arc::new(ManualClock::new());
+        let limiters = [
+            #[cfg(feature = "token-bucket")]
+            Limiter::TokenBucket(TokenBucket::with_clock(2, 1, Some(INTERVAL), clock.clone())),
+            #[cfg(feature = "leaky-bucket")]
+            Limiter::LeakyBucket(LeakyBucket::meter_with_clock(
+                1,
+                2,
+                Some(INTERVAL),
+                clock.clone(),
+            )),
+            #[cfg(feature = "fixed-window")]
+            Limiter::FixedWindow(FixedWindow::with_clock(
+                2,
+                Some(INTERVAL),
+                false,
+                clock.clone(),
+            )),
+            #[cfg(feature = "sliding-log")]
+            Limiter::SlidingWindowLog(SlidingWindowLog::with_clock(
+                2,
+                Some(INTERVAL),
+                clock.clone(),
+            )),
+            #[cfg(feature = "sliding-window")]
+            Limiter::SlidingWindowCount(SlidingWindowCount::with_clock(
+                2,
+                INTERVAL,
+                10,
+                clock.clone(),
+            )),
+            #[cfg(feature = "calendar-window")]
+            Limiter::CalendarWindow(CalendarWindow::with_clock(
+                2,
+                CalendarPeriod::Daily,
+                0,
+                clock.clone(),
+            )),
+        ];
+        for limiter in limiters {
+            assert!(limiter.allow_n_at(2, START), "{limiter:?}");
+            assert!(
+                !limiter.allow_at(START + Duration::from_secs(10)),
+                "{limiter:?}"
+            );
+            // an older timestamp counts as the latest one seen
+            assert!(!limiter.allow_at(Duration::ZERO), "{limiter:?}");
+            assert!(limiter.allow_n_at(2, NEXT_DAY), "{limiter:?}");
+            assert!(!limiter.allow(), "{limiter:?}");
+            assert!(matches!(
+                limiter.try_allow_n_at(1, START),
+                Err(Error::ClockWentBackwards)
+            ));
+            assert!(matches!(
+                limiter.try_allow_n_at(1, NEXT_DAY),
+                Err(Error::RateLimited)
+            ));
+        }
+    }
+
+    #[cfg(any(
+        feature = "token-bucket",
+        feature = "leaky-bucket",
+        feature = "fixed-window",
+        feature = "sliding-log",
+        feature = "sliding-window"
+    ))]
+    #[test]
+    fn zero_capacity_should_deny_and_unlimited_should_allow() {
+        let zero = [
+            #[cfg(feature = "token-bucket")]
+            LimiterConfig::TokenBucket {
+                capacity: 0,
+                refill_rate: 1,
+                refill_interval_ms: None,
+                initial_tokens: None,
+            },
+            #[cfg(feature = "leaky-bucket")]
+            LimiterConfig::LeakyBucket {
+                leak_rate: 1,
+                capacity: 0,
+                leak_interval_ms: None,
+                meter: false,
+            },
+            #[cfg(feature = "fixed-window")]
+            LimiterConfig::FixedWindow {
+                size: 0,
+                interval_ms: None,
+                smoothing: true,
+            },
+            #[cfg(feature = "sliding-log")]
+            LimiterConfig::SlidingWindowLog {
+                size: 0,
+                interval_ms: None,
+                max_entries: None,
+                overflow: LogOverflow::Reject,
+            },
+            #[cfg(feature = "sliding-window")]
+            LimiterConfig::SlidingWindowCount {
+                size: 0,
+                interval_ms: None,
+                bucket_count: 10,
+            },
+        ];
+        for config in zero {
+            let limiter = config.build();
+            assert!(!limiter.allow(), "{config:?}");
+            assert!(!limiter.allow_n(u64::MAX), "{config:?}");
+        }
+
+        let unlimited = LimiterConfig::Unlimited.build();
+        assert!(unlimited.allow_n(u64::MAX));
+        assert!(unlimited.reconfigure(&LimiterConfig::Unlimited));
+    }
+
+    #[cfg(feature = "token-bucket")]
+    #[test]
+    fn wait_should_block_until_allowed() {
+        const INTERVAL: Duration = Duration::from_millis(20);
+
+        let limiter = Limiter::TokenBucket(TokenBucket::new(1, 1, Some(INTERVAL)));
+        let start = std::time::Instant::now();
+        assert!(limiter.wait(1).is_ok());
+        assert!(limiter.wait(1).is_ok());
+        assert!(start.elapsed() >= INTERVAL / 2);
+        assert!(matches!(limiter.wait(2), Err(Error::TooLarge)));
+
+        #[cfg(feature = "leaky-bucket")]
+        {
+            let limiter = Limiter::LeakyBucket(LeakyBucket::new(1, 1, Some(INTERVAL)));
+            assert!(limiter.wait(1).is_ok());
+            assert!(limiter.wait(1).is_ok());
+            assert!(matches!(limiter.wait(2), Err(Error::TooLarge)));
+        }
+    }
+
+    #[cfg(feature = "token-bucket")]
+    #[test]
+    fn admit_if_wait_below_should_fail_fast_over_the_budget() {
+        const INTERVAL: Duration = Duration::from_millis(20);
+
+        let limiter = Limiter::TokenBucket(TokenBucket::new(1, 1, Some(INTERVAL)));
+        assert!(limiter.admit_if_wait_below(1, Duration::ZERO).is_ok());
+        let start = std::time::Instant::now();
+        assert!(matches!(
+            limiter.admit_if_wait_below(1, INTERVAL / 10),
+            Err(Error::OverBudget(wait)) if wait > INTERVAL / 10
+        ));
+        assert!(start.elapsed() < INTERVAL / 2);
+
+        // within the budget, the request waits for its token
+        assert!(limiter.admit_if_wait_below(1, INTERVAL * 5).is_ok());
+        assert!(start.elapsed() >= INTERVAL / 2);
+        assert!(matches!(
+            limiter.admit_if_wait_below(2, Duration::MAX),
+            Err(Error::TooLarge)
+        ));
+
+        // a leaky bucket queues the events for longer than the budget
+        #[cfg(feature = "leaky-bucket")]
+        {
+            let limiter = Limiter::LeakyBucket(LeakyBucket::new(1, 5, Some(INTERVAL)));
+            assert!(matches!(
+                limiter.admit_if_wait_below(5, INTERVAL),
+                Err(Error::Timeout)
+            ));
+            assert_eq!(limiter.next_available(4), Duration::ZERO);
+        }
+    }
+
+    #[cfg(all(feature = "tokio", feature = "token-bucket"))]
+    #[tokio::test]
+    async fn admit_if_wait_below_async_should_bound_the_wait_by_the_budget() {
+        const INTERVAL: Duration = Duration::from_millis(20);
+
+        let limiter = Limiter::TokenBucket(TokenBucket::new(1, 1, Some(INTERVAL)));
+        assert!(limiter
+            .admit_if_wait_below_async(1, Duration::ZERO)
+            .await
+            .is_ok());
+        let start = std::time::Instant::now();
+        assert!(matches!(
+            limiter.admit_if_wait_below_async(1, INTERVAL / 10).await,
+            Err(Error::OverBudget(wait)) if wait > INTERVAL / 10
+        ));
+        assert!(start.elapsed() < INTERVAL / 2);
+        assert!(limiter
+            .admit_if_wait_below_async(1, INTERVAL * 5)
+            .await
+            .is_ok());
+        assert!(start.elapsed() >= INTERVAL / 2);
+
+        // the bucket has room for 5 events, which take 5 intervals to leak out
+        #[cfg(feature = "leaky-bucket")]
+        {
+            let limiter = Limiter::LeakyBucket(LeakyBucket::new(1, 5, Some(INTERVAL)));
+            let start = std::time::Instant::now();
+            assert!(matches!(
+                limiter.admit_if_wait_below_async(5, INTERVAL).await,
+                Err(Error::Timeout)
+            ));
+            assert!(start.elapsed() < INTERVAL * 4);
+            // the events still queued were taken back out of the bucket
+            assert_eq!(limiter.next_available(4), Duration::ZERO);
+            assert!(limiter
+                .admit_if_wait_below_async(1, INTERVAL * 5)
+                .await
+                .is_ok());
+        }
+    }
+
+    #[cfg(feature = "token-bucket")]
+    #[test]
+    fn wait_all_should_split_requests_exceeding_the_capacity() {
+        const INTERVAL: Duration = Duration::from_millis(10);
+
+        // 2 tokens right away, then 2 and 1 over the next two refills
+        let limiter = Limiter::TokenBucket(TokenBucket::new(2, 2, Some(INTERVAL)));
+        let start = std::time::Instant::now();
+        assert!(matches!(limiter.try_check(5), Err(Error::TooLarge)));
+        assert!(limiter.wait_all(5).is_ok());
+        assert!(start.elapsed() >= INTERVAL * 2);
+        assert!(limiter.wait_all(0).is_ok());
+
+        let empty = Limiter::TokenBucket(TokenBucket::new(0, 1, Some(INTERVAL)));
+        assert!(matches!(empty.wait_all(1), Err(Error::TooLarge)));
+    }
+
+    #[cfg(all(feature = "async", feature = "token-bucket"))]
+    #[test]
+    fn wait_async_with_should_sleep_on_the_given_runtime() {
+        // a runtime whose sleeps advance the clock of the limiter
+        let clock = Arc::new(ManualClock::new());
+        let runtime = {
+            let clock = clock.clone();
+            move |wait| -> crate::Sleep {
+                clock.advance(wait);
+                Box::pin(core::future::ready(()))
+            }
+        };
+        let limiter = Limiter::TokenBucket(TokenBucket::with_clock(
+            1,
+            1,
+            Some(Duration::from_secs(1)),
+            clock.clone(),
+        ));
+
+        futures::executor::block_on(async {
+            assert!(limiter
+                .wait_async_with(1, Duration::ZERO, &runtime)
+                .await
+                .is_ok());
+            assert!(limiter
+                .wait_async_with(1, Duration::ZERO, &runtime)
+                .await
+                .is_ok());
+        });
+        assert_eq!(clock.now(), Duration::from_secs(1));
+    }
+
+    #[cfg(all(feature = "tokio", feature = "token-bucket"))]
+    #[tokio::test(start_paused = true)]
+    async fn wait_async_should_sleep_until_allowed() {
+        let start = tokio::time::Instant::now();
+        let clock: std::sync::Arc<dyn crate::Clock> = std::sync::Arc::new(move || start.elapsed());
+        let limiter = Limiter::TokenBucket(TokenBucket::with_clock(
+            1,
+            1,
+            Some(Duration::from_secs(1)),
+            clock,
+        ));
+
+        assert!(limiter.wait_async(1).await.is_ok());
+        assert!(limiter.wait_async(1).await.is_ok());
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        assert!(matches!(limiter.wait_async(2).await, Err(Error::TooLarge)));
+
+        // one token per refill
+        assert!(limiter.wait_async_all(3).await.is_ok());
+        assert_eq!(start.elapsed(), Duration::from_secs(4));
+    }
+
+    #[cfg(all(feature = "tokio", feature = "fixed-window"))]
+    #[tokio::test(start_paused = true)]
+    async fn wait_async_jittered_should_spread_waiters() {
+        const JITTER: Duration = Duration::from_millis(100);
+
+        let start = tokio::time::Instant::now();
+        let clock: std::sync::Arc<dyn crate::Clock> = std::sync::Arc::new(move || start.elapsed());
+        let limiter = Limiter::FixedWindow(FixedWindow::with_clock(
+            100,
+            Some(Duration::from_secs(1)),
+            false,
+            clock,
+        ));
+        assert!(limiter.allow_n(100));
+
+        // the waiters wake up after the window boundary, within the jitter
+        let admitted = futures::future::join_all((0..100).map(|_| async {
+            limiter.wait_async_jittered(1, JITTER).await.unwrap();
+            start.elapsed()
+        }))
+        .await;
+        assert!(admitted
+            .iter()
+            .all(|at| (Duration::from_secs(1)..=Duration::from_secs(1) + JITTER).contains(at)));
+        let mut distinct = admitted.clone();
+        distinct.dedup();
+        assert!(distinct.len() > 10);
+
+        assert_eq!(random_jitter(Duration::ZERO), Duration::ZERO);
+    }
+}
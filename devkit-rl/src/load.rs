@@ -0,0 +1,271 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "leaky-bucket")]
+use crate::Feedback;
+use crate::{
+    sync::atomic::{AtomicU64, Ordering},
+    Worker,
+};
+
+/// The clock ticks per second of the cpu times in `/proc`, fixed by the Linux ABI.
+#[cfg(target_os = "linux")]
+const TICKS_PER_SECOND: u64 = 100;
+
+/// The resources used by the current process at some point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessUsage {
+    /// The cpu time used since the process started, in user and kernel mode.
+    pub cpu_time: Duration,
+    /// The resident memory of the process.
+    pub rss_bytes: u64,
+}
+
+impl ProcessUsage {
+    /// Reads the resources used by the current process.
+    ///
+    /// # Returns
+    ///
+    /// `None` if they cannot be read: they are read from `/proc/self`, which only
+    /// exists on Linux.
+    pub fn read() -> Option<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+            let status = std::fs::read_to_string("/proc/self/status").ok()?;
+            Some(Self {
+                cpu_time: parse_cpu_time(&stat)?,
+                rss_bytes: parse_rss(&status)?,
+            })
+        }
+        #[cfg(not(target_os = "linux"))]
+        None
+    }
+}
+
+/// Parses the `utime` and `stime` fields of `/proc/self/stat`.
+#[cfg(target_os = "linux")]
+fn parse_cpu_time(stat: &str) -> Option<Duration> {
+    // the command name may hold spaces and parentheses, the fields follow the last
+    // parenthesis, starting with the third one
+    let mut fields = stat.get(stat.rfind(')')? + 1..)?.split_whitespace();
+    let utime: u64 = fields.nth(11)?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    Some(Duration::from_millis(
+        (utime + stime) * 1000 / TICKS_PER_SECOND,
+    ))
+}
+
+/// Parses the `VmRSS` line of `/proc/self/status`.
+#[cfg(target_os = "linux")]
+fn parse_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line["VmRSS:".len()..]
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+/// The load of the process, shared between a [`LoadSampler`] publishing it and the
+/// limiters shedding work from it.
+///
+/// The load is a ratio of the resources used to those allowed: the process is
+/// overloaded from `1.0` on. Clones of a signal share their load.
+#[derive(Debug, Clone, Default)]
+pub struct LoadSignal {
+    /// The bits of the load, an `f64`.
+    load: Arc<AtomicU64>,
+}
+
+impl LoadSignal {
+    /// Creates a new `LoadSignal`, with no load.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the last load published.
+    pub fn load(&self) -> f64 {
+        f64::from_bits(self.load.load(Ordering::Relaxed))
+    }
+
+    /// Publishes a new load, e.g. from a custom measurement.
+    pub fn set(&self, load: f64) {
+        self.load.store(load.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the process uses at least the resources allowed.
+    pub fn is_overloaded(&self) -> bool {
+        self.load() >= 1.0
+    }
+
+    /// Returns the load as feedback for an [`AdaptiveClientLimiter`], which backs
+    /// off while the process is overloaded.
+    ///
+    /// [`AdaptiveClientLimiter`]: crate::AdaptiveClientLimiter
+    #[cfg(feature = "leaky-bucket")]
+    pub fn feedback(&self) -> Feedback {
+        if self.is_overloaded() {
+            Feedback::Throttled { retry_after: None }
+        } else {
+            Feedback::Accepted
+        }
+    }
+}
+
+/// A watchdog sampling the cpu and memory used by the process, and publishing its
+/// load to a [`LoadSignal`].
+///
+/// The load is the largest of the cores used over the last interval divided by
+/// the cores allowed, and of the resident memory divided by the memory allowed,
+/// if limited. A [`QueueGuard`](crate::QueueGuard) sheds the work entering it
+/// while the process is overloaded, and an
+/// [`AdaptiveClientLimiter`](crate::AdaptiveClientLimiter) backs off when fed
+/// [`LoadSignal::feedback`].
+///
+/// The usage is read from `/proc/self`: on other platforms than Linux, no load is
+/// ever published.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_rl::{LoadSampler, LoadSignal, QueueGuard};
+///
+/// let signal = LoadSignal::new();
+/// let sampler = LoadSampler::new(2.0)
+///     .with_rss_limit(512 << 20)
+///     .with_interval(Duration::from_millis(500))
+///     .spawn(signal.clone());
+/// let guard = QueueGuard::new(Duration::from_millis(100)).with_load_signal(signal);
+///
+/// assert!(sampler.shutdown(Duration::from_secs(1)).is_ok());
+/// ```
+#[derive(Debug, Clone)]
+pub struct LoadSampler {
+    /// The cores the process may use.
+    cpu_limit: f64,
+    /// The resident memory the process may use, if limited.
+    rss_limit: Option<u64>,
+    /// How often the usage is sampled.
+    interval: Duration,
+}
+
+impl LoadSampler {
+    /// Creates a new `LoadSampler` sampling the usage every second.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu_limit` - The cores the process may use, e.g. `0.5` or the cpu quota
+    ///   of its container.
+    pub fn new(cpu_limit: f64) -> Self {
+        Self {
+            cpu_limit,
+            rss_limit: None,
+            interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Limits the resident memory of the process to `bytes`.
+    pub fn with_rss_limit(mut self, bytes: u64) -> Self {
+        self.rss_limit = Some(bytes);
+        self
+    }
+
+    /// Samples the usage every `interval`, at least a millisecond.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval.max(Duration::from_millis(1));
+        self
+    }
+
+    /// Computes the load of the process between two samples, `elapsed` apart.
+    pub fn load(&self, previous: &ProcessUsage, current: &ProcessUsage, elapsed: Duration) -> f64 {
+        let mut load = 0.0;
+        if !elapsed.is_zero() && self.cpu_limit > 0.0 {
+            let cores = current
+                .cpu_time
+                .saturating_sub(previous.cpu_time)
+                .as_secs_f64()
+                / elapsed.as_secs_f64();
+            load = cores / self.cpu_limit;
+        }
+        if let Some(limit) = self.rss_limit.filter(|limit| *limit > 0) {
+            load = f64::max(load, current.rss_bytes as f64 / limit as f64);
+        }
+        load
+    }
+
+    /// Spawns a thread sampling the usage and publishing the load to `signal`,
+    /// until the returned worker is stopped.
+    pub fn spawn(self, signal: LoadSignal) -> Worker {
+        Worker::spawn("load-sampler", move |stop| {
+            let mut previous = ProcessUsage::read().map(|usage| (usage, Instant::now()));
+            while stop.sleep(self.interval) {
+                let Some(current) = ProcessUsage::read() else {
+                    continue;
+                };
+                let now = Instant::now();
+                if let Some((usage, at)) = &previous {
+                    signal.set(self.load(usage, &current, now.duration_since(*at)));
+                }
+                previous = Some((current, now));
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(cpu_ms: u64, rss_bytes: u64) -> ProcessUsage {
+        ProcessUsage {
+            cpu_time: Duration::from_millis(cpu_ms),
+            rss_bytes,
+        }
+    }
+
+    #[test]
+    fn load_sampler_should_take_the_most_used_resource() {
+        let sampler = LoadSampler::new(2.0).with_rss_limit(1000);
+        let second = Duration::from_secs(1);
+
+        // one core of two, and a quarter of the memory
+        assert_eq!(sampler.load(&usage(0, 0), &usage(1000, 250), second), 0.5);
+        // a quarter core, and 1.5 times the memory
+        assert_eq!(
+            sampler.load(&usage(1000, 0), &usage(1500, 1500), second * 2),
+            1.5
+        );
+        assert_eq!(
+            sampler.load(&usage(0, 0), &usage(0, 0), Duration::ZERO),
+            0.0
+        );
+        assert_eq!(
+            LoadSampler::new(1.0).load(&usage(0, 0), &usage(2000, 1 << 40), second),
+            2.0
+        );
+
+        let signal = LoadSignal::new();
+        assert!(!signal.is_overloaded());
+        signal.set(1.5);
+        assert!(signal.clone().is_overloaded());
+        signal.set(-1.0);
+        assert_eq!(signal.load(), 0.0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn process_usage_should_read_proc() {
+        let usage = ProcessUsage::read().unwrap();
+        assert!(usage.rss_bytes > 0);
+
+        let stat = "42 (a (b) c) S 1 2 3 4 5 6 7 8 9 10 150 25 0 0";
+        assert_eq!(parse_cpu_time(stat), Some(Duration::from_millis(1750)));
+        assert_eq!(parse_rss("Name:\ta\nVmRSS:\t  2048 kB\n"), Some(2 << 20));
+    }
+}
@@ -0,0 +1,284 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::time::Duration;
+
+use crate::{
+    clock::{whole_periods, SharedClock},
+    sync::{Mutex, MutexExt},
+    Clock,
+};
+
+/// A token bucket metering several resources at once, e.g. requests, bytes and
+/// compute units.
+///
+/// Each dimension is a token bucket of its own, with its own capacity and refill
+/// rate. A request states its cost in every dimension, and is admitted only if all
+/// of them have the budget for it: it then consumes from all of them, or from none
+/// of them if any is short. This is how cloud APIs meter, e.g. 100 requests and
+/// 1 MiB per second, where neither limit alone bounds the load.
+///
+/// The costs are given in the order the dimensions were added; a missing cost is
+/// 0, so a plain request may only give its first costs.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_rl::MultiDimLimiter;
+///
+/// let limiter = MultiDimLimiter::new()
+///     .with_dimension("requests", 10, 10, Some(Duration::from_secs(1)))
+///     .with_dimension("bytes", 4096, 4096, Some(Duration::from_secs(1)));
+///
+/// assert!(limiter.allow(&[1, 3000]));
+/// // plenty of requests left, but not enough bytes: nothing is consumed
+/// assert_eq!(limiter.check(&[1, 2000]), Err("bytes"));
+/// assert_eq!(limiter.available("requests"), Some(9));
+/// assert!(limiter.allow(&[1, 1000]));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MultiDimLimiter {
+    inner: Arc<Mutex<MultiDimLimiterInner>>,
+}
+
+#[derive(Debug)]
+struct MultiDimLimiterInner {
+    dimensions: Vec<Dimension>,
+    clock: SharedClock,
+}
+
+#[derive(Debug)]
+struct Dimension {
+    name: &'static str,
+    tokens: u64,
+    capacity: u64,
+    refill_rate: u64,
+    refill_interval: Duration,
+    last_refill_time: Duration,
+}
+
+impl MultiDimLimiter {
+    /// Creates a new `MultiDimLimiter` without dimensions, see
+    /// [`MultiDimLimiter::with_dimension`].
+    #[cfg(feature = "std")]
+    pub fn new() -> Self {
+        Self::from_clock(SharedClock::std())
+    }
+
+    /// Creates a new `MultiDimLimiter` without dimensions, reading the time from
+    /// `clock`.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self::from_clock(SharedClock::new(clock))
+    }
+
+    fn from_clock(clock: SharedClock) -> Self {
+        let inner = MultiDimLimiterInner {
+            dimensions: Vec::new(),
+            clock,
+        };
+
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// Adds a dimension, initially full, after the existing ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the dimension, reported when it denies a request.
+    /// * `capacity` - Maximum number of tokens of the dimension.
+    /// * `refill_rate` - Number of tokens to refill per interval.
+    /// * `refill_interval` - Interval between refills (optional, defaults to 1 second).
+    pub fn with_dimension(
+        self,
+        name: &'static str,
+        capacity: u64,
+        refill_rate: u64,
+        refill_interval: Option<Duration>,
+    ) -> Self {
+        {
+            let mut inner = self.inner.lock_unpoisoned();
+            let now = inner.clock.now();
+            inner.dimensions.push(Dimension {
+                name,
+                tokens: capacity,
+                capacity,
+                refill_rate,
+                refill_interval: refill_interval.unwrap_or(Duration::from_secs(1)),
+                last_refill_time: now,
+            });
+        }
+        self
+    }
+
+    /// Attempts to allow a request costing `costs`, one per dimension.
+    ///
+    /// # Returns
+    ///
+    /// `true` if every dimension has the budget for the request, `false` otherwise.
+    pub fn allow(&self, costs: &[u64]) -> bool {
+        self.check(costs).is_ok()
+    }
+
+    /// Attempts to allow a request costing `costs`, reporting which dimension denied
+    /// it.
+    ///
+    /// # Arguments
+    ///
+    /// * `costs` - The cost of the request in each dimension, in the order they
+    ///   were added.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the request is allowed, or the name of the first dimension short
+    /// of budget. A denied request consumes from no dimension.
+    pub fn check(&self, costs: &[u64]) -> Result<(), &'static str> {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.clock.now();
+
+        for (dimension, &cost) in inner.dimensions.iter_mut().zip(costs) {
+            dimension.advance_to(now);
+            if cost > dimension.tokens {
+                return Err(dimension.name);
+            }
+        }
+        for (dimension, &cost) in inner.dimensions.iter_mut().zip(costs) {
+            dimension.tokens -= cost;
+        }
+        Ok(())
+    }
+
+    /// Estimates how long to wait until a request costing `costs` would be allowed.
+    ///
+    /// The estimate assumes no other request is made in the meantime.
+    ///
+    /// # Returns
+    ///
+    /// The longest wait of the dimensions, or `Duration::MAX` if a cost exceeds the
+    /// capacity of its dimension and will never be allowed.
+    pub fn next_available(&self, costs: &[u64]) -> Duration {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.clock.now();
+
+        inner
+            .dimensions
+            .iter_mut()
+            .zip(costs)
+            .map(|(dimension, &cost)| {
+                dimension.advance_to(now);
+                dimension.next_available(cost, now)
+            })
+            .max()
+            .unwrap_or(Duration::ZERO)
+    }
+
+    /// Gives back `costs` previously allowed by [`MultiDimLimiter::allow`].
+    ///
+    /// This rolls back an admission that turned out not to be used, e.g. because
+    /// the request failed before doing any work. Refunding never fills a dimension
+    /// beyond its capacity.
+    pub fn refund(&self, costs: &[u64]) {
+        let mut inner = self.inner.lock_unpoisoned();
+
+        for (dimension, &cost) in inner.dimensions.iter_mut().zip(costs) {
+            dimension.tokens = dimension
+                .tokens
+                .saturating_add(cost)
+                .min(dimension.capacity);
+        }
+    }
+
+    /// Returns the tokens available in the dimension `name`, if it exists.
+    pub fn available(&self, name: &str) -> Option<u64> {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.clock.now();
+
+        let dimension = inner.dimensions.iter_mut().find(|d| d.name == name)?;
+        dimension.advance_to(now);
+        Some(dimension.tokens)
+    }
+
+    /// Returns the names of the dimensions, in the order the costs are given.
+    pub fn dimensions(&self) -> Vec<&'static str> {
+        let inner = self.inner.lock_unpoisoned();
+        inner.dimensions.iter().map(|d| d.name).collect()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for MultiDimLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dimension {
+    /// Refills the tokens of the intervals elapsed until `now`.
+    fn advance_to(&mut self, now: Duration) {
+        let elapsed = now.saturating_sub(self.last_refill_time);
+        if elapsed < self.refill_interval {
+            return;
+        }
+
+        let (interval_count, into_interval) = whole_periods(elapsed, self.refill_interval);
+        let tokens_to_add = interval_count.saturating_mul(self.refill_rate);
+        self.tokens = self.tokens.saturating_add(tokens_to_add).min(self.capacity);
+        self.last_refill_time = now - into_interval;
+    }
+
+    /// Estimates how long to wait from `now` until `cost` tokens are available.
+    fn next_available(&self, cost: u64, now: Duration) -> Duration {
+        if cost <= self.tokens {
+            return Duration::ZERO;
+        }
+        if cost > self.capacity || self.refill_rate == 0 {
+            return Duration::MAX;
+        }
+
+        let refills = (cost - self.tokens).div_ceil(self.refill_rate);
+        let wait = u32::try_from(refills)
+            .map_or(Duration::MAX, |r| self.refill_interval.saturating_mul(r));
+        let ready_at = self.last_refill_time.saturating_add(wait);
+        ready_at.saturating_sub(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+
+    #[test]
+    fn multi_dim_limiter_should_admit_only_if_all_dimensions_allow() {
+        const INTERVAL: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(ManualClock::new());
+        let limiter = MultiDimLimiter::with_clock(clock.clone())
+            .with_dimension("requests", 3, 3, Some(INTERVAL))
+            .with_dimension("bytes", 1000, 500, Some(INTERVAL))
+            .with_dimension("compute", 10, 10, Some(INTERVAL));
+        assert_eq!(limiter.dimensions(), ["requests", "bytes", "compute"]);
+
+        assert_eq!(limiter.check(&[1, 800, 2]), Ok(()));
+        // bytes are short: requests and compute are rolled back untouched
+        assert_eq!(limiter.check(&[1, 300, 2]), Err("bytes"));
+        assert_eq!(limiter.available("requests"), Some(2));
+        assert_eq!(limiter.available("compute"), Some(8));
+        assert_eq!(limiter.next_available(&[1, 300]), INTERVAL);
+        assert_eq!(limiter.next_available(&[1, 2000]), Duration::MAX);
+
+        // missing costs are free
+        assert!(limiter.allow(&[2]));
+        assert_eq!(limiter.check(&[1]), Err("requests"));
+
+        clock.advance(INTERVAL);
+        assert_eq!(limiter.available("bytes"), Some(700));
+        assert!(limiter.allow(&[1, 300, 10]));
+        assert_eq!(limiter.check(&[1, 0, 1]), Err("compute"));
+
+        limiter.refund(&[1, 300, 10]);
+        assert_eq!(limiter.available("requests"), Some(3));
+        assert_eq!(limiter.available("bytes"), Some(700));
+        assert_eq!(limiter.available("unknown"), None);
+    }
+}
@@ -0,0 +1,320 @@
+use alloc::{boxed::Box, sync::Arc};
+use core::{fmt, time::Duration};
+
+use crate::{
+    limiter::whole_cost,
+    sync::{Mutex, MutexExt},
+    Error, RateLimiter,
+};
+
+/// Callbacks on the decisions of a rate limiter.
+///
+/// Observers let applications log, alert on, or act upon the decisions of a limiter
+/// as they are made, e.g. ban a key that keeps exceeding its limit, instead of
+/// polling the limiter. Every method does nothing by default, so an observer only
+/// implements the events it is interested in. [`Hooks`] implements this trait with
+/// closures.
+///
+/// `K` is the key a decision is made for, `()` for limiters without keys. Observers
+/// are called on the thread making the request, after the limiter has made its
+/// decision, so they should return quickly.
+///
+/// Observers are attached with [`Observed`] or [`KeyedLimiter::with_observer`](crate::KeyedLimiter::with_observer).
+pub trait Observer<K: ?Sized = ()>: Send + Sync {
+    /// Called when `n` requests for `key` are allowed.
+    fn on_allowed(&self, _key: &K, _n: u64) {}
+
+    /// Called when `n` requests for `key` are denied, whatever the reason.
+    fn on_denied(&self, _key: &K, _n: u64) {}
+
+    /// Called when a new window of a window based limiter has started for `key`.
+    ///
+    /// Windows start on access, so this is reported before the first decision made
+    /// in the new window.
+    fn on_window_reset(&self, _key: &K) {}
+
+    /// Called when `n` requests for `key` are denied because the queue of a queuing
+    /// limiter, e.g. a [`LeakyBucket`](crate::LeakyBucket), is full. This is reported
+    /// in addition to [`Observer::on_denied`].
+    fn on_queue_full(&self, _key: &K, _n: u64) {}
+}
+
+impl<K: ?Sized> fmt::Debug for dyn Observer<K> + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observer").finish_non_exhaustive()
+    }
+}
+
+type Callback<K> = Box<dyn Fn(&K, u64) + Send + Sync>;
+type KeyCallback<K> = Box<dyn Fn(&K) + Send + Sync>;
+
+/// An [`Observer`] calling closures.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::{
+///     atomic::{AtomicU64, Ordering},
+///     Arc,
+/// };
+/// use devkit_rl::{Hooks, Observed, RateLimiter, TokenBucket};
+///
+/// let denied = Arc::new(AtomicU64::new(0));
+/// let hooks = Hooks::new().on_denied({
+///     let denied = denied.clone();
+///     move |_, n| {
+///         denied.fetch_add(n, Ordering::Relaxed);
+///     }
+/// });
+/// let limiter = Observed::new(TokenBucket::new(1, 1, None), Arc::new(hooks));
+///
+/// assert!(limiter.allow());
+/// assert!(!limiter.allow());
+/// assert_eq!(denied.load(Ordering::Relaxed), 1);
+/// ```
+pub struct Hooks<K: ?Sized = ()> {
+    allowed: Option<Callback<K>>,
+    denied: Option<Callback<K>>,
+    window_reset: Option<KeyCallback<K>>,
+    queue_full: Option<Callback<K>>,
+}
+
+impl<K: ?Sized> Hooks<K> {
+    /// Creates a new `Hooks` without any closure.
+    pub fn new() -> Self {
+        Self {
+            allowed: None,
+            denied: None,
+            window_reset: None,
+            queue_full: None,
+        }
+    }
+
+    /// Calls `f` with the key and the number of requests when requests are allowed.
+    pub fn on_allowed(mut self, f: impl Fn(&K, u64) + Send + Sync + 'static) -> Self {
+        self.allowed = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the key and the number of requests when requests are denied.
+    pub fn on_denied(mut self, f: impl Fn(&K, u64) + Send + Sync + 'static) -> Self {
+        self.denied = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the key when a new window starts.
+    pub fn on_window_reset(mut self, f: impl Fn(&K) + Send + Sync + 'static) -> Self {
+        self.window_reset = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the key and the number of requests when requests are denied
+    /// because a queue is full.
+    pub fn on_queue_full(mut self, f: impl Fn(&K, u64) + Send + Sync + 'static) -> Self {
+        self.queue_full = Some(Box::new(f));
+        self
+    }
+}
+
+impl<K: ?Sized> Default for Hooks<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: ?Sized> fmt::Debug for Hooks<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_allowed", &self.allowed.is_some())
+            .field("on_denied", &self.denied.is_some())
+            .field("on_window_reset", &self.window_reset.is_some())
+            .field("on_queue_full", &self.queue_full.is_some())
+            .finish()
+    }
+}
+
+impl<K: ?Sized> Observer<K> for Hooks<K> {
+    fn on_allowed(&self, key: &K, n: u64) {
+        if let Some(f) = &self.allowed {
+            f(key, n);
+        }
+    }
+
+    fn on_denied(&self, key: &K, n: u64) {
+        if let Some(f) = &self.denied {
+            f(key, n);
+        }
+    }
+
+    fn on_window_reset(&self, key: &K) {
+        if let Some(f) = &self.window_reset {
+            f(key);
+        }
+    }
+
+    fn on_queue_full(&self, key: &K, n: u64) {
+        if let Some(f) = &self.queue_full {
+            f(key, n);
+        }
+    }
+}
+
+/// Reports the outcome of a decision on `n` requests for `key` to `observer`.
+pub(crate) fn notify<K: ?Sized>(
+    observer: &dyn Observer<K>,
+    key: &K,
+    n: u64,
+    result: &Result<(), Error>,
+) {
+    match result {
+        Ok(()) => observer.on_allowed(key, n),
+        Err(e) => {
+            if matches!(e, Error::QueueFull) {
+                observer.on_queue_full(key, n);
+            }
+            observer.on_denied(key, n);
+        }
+    }
+}
+
+/// A rate limiter reporting its decisions to an [`Observer`].
+///
+/// `Observed` wraps any [`RateLimiter`] and implements it in turn, so it can be
+/// used wherever the wrapped limiter was. See [`Hooks`] for an example.
+pub struct Observed<L> {
+    limiter: L,
+    observer: Arc<dyn Observer>,
+    /// The start of the window seen by the last decision.
+    window: Mutex<Option<Duration>>,
+}
+
+impl<L: RateLimiter> Observed<L> {
+    /// Creates a new `Observed` reporting the decisions of `limiter` to `observer`.
+    pub fn new(limiter: L, observer: Arc<dyn Observer>) -> Self {
+        let window = limiter.window_start();
+        Self {
+            limiter,
+            observer,
+            window: Mutex::new(window),
+        }
+    }
+
+    /// Returns the wrapped limiter.
+    ///
+    /// Decisions made on it directly are not reported.
+    pub fn limiter(&self) -> &L {
+        &self.limiter
+    }
+
+    /// Reports a new window of the limiter, if one has started since the last decision.
+    fn check_window(&self) {
+        let start = self.limiter.window_start();
+        let reset = {
+            let mut window = self.window.lock_unpoisoned();
+            core::mem::replace(&mut *window, start) != start
+        };
+        if reset {
+            self.observer.on_window_reset(&());
+        }
+    }
+}
+
+impl<L: RateLimiter> RateLimiter for Observed<L> {
+    fn allow_n(&self, n: u64) -> bool {
+        self.try_check(n).is_ok()
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        self.limiter.next_available(n)
+    }
+
+    fn allow_cost(&self, cost: f64) -> bool {
+        self.check_window();
+        let allowed = self.limiter.allow_cost(cost);
+        let result = if allowed {
+            Ok(())
+        } else {
+            Err(Error::RateLimited)
+        };
+        notify(&*self.observer, &(), whole_cost(cost), &result);
+        allowed
+    }
+
+    fn try_check(&self, n: u64) -> Result<(), Error> {
+        self.check_window();
+        let result = self.limiter.try_check(n);
+        notify(&*self.observer, &(), n, &result);
+        result
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        self.limiter.remaining()
+    }
+
+    fn window_start(&self) -> Option<Duration> {
+        self.limiter.window_start()
+    }
+
+    fn refund(&self, n: u64) {
+        self.limiter.refund(n);
+    }
+}
+
+impl<L: fmt::Debug> fmt::Debug for Observed<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observed")
+            .field("limiter", &self.limiter)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "fixed-window"))]
+mod tests {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+    use crate::{FixedWindow, ManualClock};
+
+    #[derive(Default)]
+    struct Counts {
+        allowed: AtomicU64,
+        denied: AtomicU64,
+        resets: AtomicU64,
+    }
+
+    impl Observer for Counts {
+        fn on_allowed(&self, _key: &(), n: u64) {
+            self.allowed.fetch_add(n, Ordering::Relaxed);
+        }
+
+        fn on_denied(&self, _key: &(), n: u64) {
+            self.denied.fetch_add(n, Ordering::Relaxed);
+        }
+
+        fn on_window_reset(&self, _key: &()) {
+            self.resets.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn observed_should_report_decisions_and_window_resets() {
+        const INTERVAL: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(ManualClock::new());
+        let counts = Arc::new(Counts::default());
+        let limiter = Observed::new(
+            FixedWindow::with_clock(2, Some(INTERVAL), false, clock.clone()),
+            counts.clone(),
+        );
+
+        assert!(limiter.allow_n(2));
+        assert!(!limiter.allow());
+        assert_eq!(counts.resets.load(Ordering::Relaxed), 0);
+
+        clock.advance(INTERVAL);
+        assert!(limiter.allow());
+        assert_eq!(counts.allowed.load(Ordering::Relaxed), 3);
+        assert_eq!(counts.denied.load(Ordering::Relaxed), 1);
+        assert_eq!(counts.resets.load(Ordering::Relaxed), 1);
+    }
+}
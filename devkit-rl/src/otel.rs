@@ -0,0 +1,224 @@
+//! OpenTelemetry metrics and span attributes for the decisions of the limiters.
+
+use std::time::Duration;
+
+use opentelemetry::{
+    global,
+    metrics::{Counter, Histogram, Meter},
+    trace::get_active_span,
+    InstrumentationScope, KeyValue,
+};
+
+use crate::Observer;
+
+/// The attribute naming the limiter, on every metric and span.
+const LIMITER: &str = "devkit.rl.limiter";
+/// The span attribute holding the last decision, `allowed` or `denied`.
+const DECISION: &str = "devkit.rl.decision";
+/// The span attribute holding the last wait, in milliseconds.
+const WAIT_MS: &str = "devkit.rl.wait_ms";
+
+/// An [`Observer`] recording the decisions of a limiter as OpenTelemetry metrics.
+///
+/// It records the following instruments, attributed with the name of the limiter as
+/// `devkit.rl.limiter`:
+///
+/// * `devkit.rl.allowed` - a counter of the requests allowed.
+/// * `devkit.rl.denied` - a counter of the requests denied.
+/// * `devkit.rl.wait_ms` - a histogram of the time spent waiting for a limiter, see
+///   [`OtelObserver::record_wait`].
+///
+/// Every decision is also set on the active span, as the `devkit.rl.limiter` and
+/// `devkit.rl.decision` attributes, so that rate limiting shows up in the existing
+/// traces. The keys of a [`KeyedLimiter`](crate::KeyedLimiter) are left out, so that
+/// abusive clients cannot blow up the cardinality of the metrics.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+/// use devkit_rl::{FixedWindow, Observed, OtelObserver, RateLimiter};
+///
+/// // reports to the meter provider installed with `opentelemetry::global`
+/// let observer = Arc::new(OtelObserver::new("search-api"));
+/// let limiter = Observed::new(FixedWindow::new(1, None), observer);
+///
+/// assert!(limiter.allow());
+/// assert!(!limiter.allow());
+/// ```
+#[derive(Debug, Clone)]
+pub struct OtelObserver {
+    attributes: [KeyValue; 1],
+    allowed: Counter<u64>,
+    denied: Counter<u64>,
+    wait: Histogram<f64>,
+}
+
+impl OtelObserver {
+    /// Creates a new `OtelObserver` recording to the global meter provider.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the limiter, recorded as `devkit.rl.limiter`.
+    pub fn new(name: impl Into<String>) -> Self {
+        let scope = InstrumentationScope::builder(env!("CARGO_PKG_NAME"))
+            .with_version(env!("CARGO_PKG_VERSION"))
+            .build();
+        Self::with_meter(name, &global::meter_with_scope(scope))
+    }
+
+    /// Creates a new `OtelObserver` recording with `meter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the limiter, recorded as `devkit.rl.limiter`.
+    /// * `meter` - The meter creating the instruments.
+    pub fn with_meter(name: impl Into<String>, meter: &Meter) -> Self {
+        Self {
+            attributes: [KeyValue::new(LIMITER, name.into())],
+            allowed: meter
+                .u64_counter("devkit.rl.allowed")
+                .with_description("The requests allowed by the rate limiter.")
+                .with_unit("{request}")
+                .build(),
+            denied: meter
+                .u64_counter("devkit.rl.denied")
+                .with_description("The requests denied by the rate limiter.")
+                .with_unit("{request}")
+                .build(),
+            wait: meter
+                .f64_histogram("devkit.rl.wait_ms")
+                .with_description("The time spent waiting for the rate limiter.")
+                .with_unit("ms")
+                .build(),
+        }
+    }
+
+    /// Records that a caller waited `wait` for the limiter, e.g. in
+    /// [`Limiter::wait`](crate::Limiter::wait), and sets it on the active span.
+    pub fn record_wait(&self, wait: Duration) {
+        let ms = wait.as_secs_f64() * 1000.0;
+        self.wait.record(ms, &self.attributes);
+        get_active_span(|span| {
+            span.set_attributes([self.attributes[0].clone(), KeyValue::new(WAIT_MS, ms)]);
+        });
+    }
+
+    fn tag_span(&self, decision: &'static str) {
+        get_active_span(|span| {
+            span.set_attributes([
+                self.attributes[0].clone(),
+                KeyValue::new(DECISION, decision),
+            ]);
+        });
+    }
+}
+
+impl<K: ?Sized> Observer<K> for OtelObserver {
+    fn on_allowed(&self, _key: &K, n: u64) {
+        self.allowed.add(n, &self.attributes);
+        self.tag_span("allowed");
+    }
+
+    fn on_denied(&self, _key: &K, n: u64) {
+        self.denied.add(n, &self.attributes);
+        self.tag_span("denied");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use opentelemetry::{
+        metrics::MeterProvider,
+        trace::{Tracer, TracerProvider},
+        Value,
+    };
+    use opentelemetry_sdk::{
+        metrics::{
+            data::{AggregatedMetrics, MetricData},
+            InMemoryMetricExporter, PeriodicReader, SdkMeterProvider,
+        },
+        trace::{InMemorySpanExporter, SdkTracerProvider},
+    };
+
+    use super::*;
+    use crate::{FixedWindow, Observed, RateLimiter};
+
+    #[test]
+    fn otel_observer_should_record_decisions() {
+        let exporter = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+        let observer = Arc::new(OtelObserver::with_meter(
+            "search-api",
+            &provider.meter("test"),
+        ));
+        let limiter = Observed::new(FixedWindow::new(3, None), observer.clone());
+
+        assert!(limiter.allow_n(2));
+        assert!(!limiter.allow_n(2));
+        assert!(limiter.allow());
+        observer.record_wait(Duration::from_millis(250));
+        provider.force_flush().unwrap();
+
+        let metrics = exporter.get_finished_metrics().unwrap();
+        let metrics: Vec<_> = metrics
+            .iter()
+            .flat_map(|r| r.scope_metrics())
+            .flat_map(|s| s.metrics())
+            .collect();
+        let sum = |name: &str| {
+            let metric = metrics.iter().find(|m| m.name() == name).unwrap();
+            let AggregatedMetrics::U64(MetricData::Sum(sum)) = metric.data() else {
+                panic!("{name} is not a counter");
+            };
+            let point = sum.data_points().next().unwrap();
+            assert!(point
+                .attributes()
+                .any(|kv| kv.key.as_str() == LIMITER && kv.value.as_str() == "search-api"));
+            point.value()
+        };
+        assert_eq!(sum("devkit.rl.allowed"), 3);
+        assert_eq!(sum("devkit.rl.denied"), 2);
+
+        let metric = metrics
+            .iter()
+            .find(|m| m.name() == "devkit.rl.wait_ms")
+            .unwrap();
+        assert_eq!(metric.unit(), "ms");
+        let AggregatedMetrics::F64(MetricData::Histogram(histogram)) = metric.data() else {
+            panic!("devkit.rl.wait_ms is not a histogram");
+        };
+        let point = histogram.data_points().next().unwrap();
+        assert_eq!((point.count(), point.sum()), (1, 250.0));
+    }
+
+    #[test]
+    fn otel_observer_should_tag_the_active_span() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let tracer = provider.tracer("test");
+        let observer = Arc::new(OtelObserver::new("search-api"));
+        let limiter = Observed::new(FixedWindow::new(1, None), observer);
+
+        tracer.in_span("first", |_| assert!(limiter.allow()));
+        tracer.in_span("second", |_| assert!(!limiter.allow()));
+        provider.force_flush().unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let decision = |name: &str| {
+            let span = spans.iter().find(|s| s.name == name).unwrap();
+            span.attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == DECISION)
+                .map(|kv| kv.value.clone())
+        };
+        assert_eq!(decision("first"), Some(Value::from("allowed")));
+        assert_eq!(decision("second"), Some(Value::from("denied")));
+    }
+}
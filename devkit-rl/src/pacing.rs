@@ -0,0 +1,481 @@
+use core::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use std::{fmt, sync::Arc};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+
+use crate::{
+    limiter::retry_after, AsyncRuntime, DefaultRuntime, Error, RateLimiter, RequestCost, Sleep,
+};
+
+/// How much of a limiter an item passing through a [`PacedSink`] or a [`PacedStream`] uses.
+pub trait Cost<T> {
+    /// Returns the number of requests `item` counts for.
+    fn cost(&self, item: &T) -> u64;
+}
+
+/// Counts every item as one request, to pace messages.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PerItem;
+
+impl<T> Cost<T> for PerItem {
+    fn cost(&self, _item: &T) -> u64 {
+        1
+    }
+}
+
+/// Counts every byte of an item as one request, to pace bandwidth.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PerByte;
+
+impl<T: AsRef<[u8]>> Cost<T> for PerByte {
+    fn cost(&self, item: &T) -> u64 {
+        item.as_ref().len() as u64
+    }
+}
+
+/// Counts every item for its own [`RequestCost`], to pace typed requests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PerRequest;
+
+impl<T: RequestCost> Cost<T> for PerRequest {
+    fn cost(&self, item: &T) -> u64 {
+        item.cost()
+    }
+}
+
+impl<T, F> Cost<T> for F
+where
+    F: Fn(&T) -> u64,
+{
+    fn cost(&self, item: &T) -> u64 {
+        self(item)
+    }
+}
+
+/// Waits on the timer of a runtime until a limiter allows a request.
+pub(crate) struct Pacer<L> {
+    limiter: L,
+    runtime: Arc<dyn AsyncRuntime>,
+    sleep: Option<Sleep>,
+}
+
+impl<L> Pacer<L> {
+    pub(crate) fn new(limiter: L) -> Self {
+        Self {
+            limiter,
+            runtime: Arc::new(DefaultRuntime),
+            sleep: None,
+        }
+    }
+}
+
+impl<L: RateLimiter> Pacer<L> {
+    /// Polls until the limiter allows `n` requests.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` once the requests are allowed, or [`Error::RateLimited`] if they
+    /// exceed the capacity of the limiter and will never be allowed.
+    pub(crate) fn poll_acquire(&mut self, cx: &mut Context<'_>, n: u64) -> Poll<Result<(), Error>> {
+        loop {
+            if let Some(sleep) = &mut self.sleep {
+                ready!(sleep.as_mut().poll(cx));
+                self.sleep = None;
+            }
+            if self.limiter.allow_n(n) {
+                return Poll::Ready(Ok(()));
+            }
+            let Ok(wait) = retry_after(self.limiter.next_available(n)) else {
+                return Poll::Ready(Err(Error::RateLimited));
+            };
+            self.sleep = Some(self.runtime.sleep(wait));
+        }
+    }
+}
+
+/// A [`Sink`] whose writes are paced by a rate limiter.
+///
+/// Every item is held until the limiter allows its [`Cost`], and `poll_ready`,
+/// `poll_flush` and `poll_close` return `Poll::Pending` in the meantime. Wrapping
+/// the sink of a tokio-util codec, e.g. a `FramedWrite`, shapes the rate of the
+/// messages of a websocket or TCP protocol, or its bandwidth with [`PerByte`].
+/// The sink waits on the timer of the [`DefaultRuntime`], or of the one given to
+/// [`PacedSink::with_runtime`].
+///
+/// An item costing more than the capacity of the limiter is dropped, and the sink
+/// fails with [`Error::RateLimited`], which is why the error of the wrapped sink must
+/// convert from [`Error`]. `std::io::Error`, the error of the codecs, does.
+///
+/// # Example
+///
+/// ```
+/// use std::io;
+/// use devkit_rl::{PacedSink, Quota, TokenBucket};
+/// use futures::{channel::mpsc, SinkExt};
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> io::Result<()> {
+/// let (tx, mut rx) = mpsc::unbounded();
+/// let tx = tx.sink_map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe));
+/// let bucket = TokenBucket::from_quota(Quota::per_second(1000).allow_burst(2));
+/// let mut sink = PacedSink::new(tx, bucket);
+///
+/// for message in ["a", "b", "c"] {
+///     // the third message waits for a token
+///     sink.send(message).await?;
+/// }
+/// assert_eq!(rx.try_next().unwrap(), Some("a"));
+/// # Ok(())
+/// # }
+/// ```
+pub struct PacedSink<S, L, T, C = PerItem> {
+    sink: S,
+    pacer: Pacer<L>,
+    cost: C,
+    /// The item waiting for the limiter, with its cost.
+    pending: Option<(T, u64)>,
+    /// Whether the limiter allowed the pending item, which waits for the sink.
+    admitted: bool,
+}
+
+impl<S, L, T> PacedSink<S, L, T> {
+    /// Creates a new `PacedSink` counting every item as one request.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - The sink to pace.
+    /// * `limiter` - The limiter pacing the items.
+    pub fn new(sink: S, limiter: L) -> Self {
+        Self::with_cost(sink, limiter, PerItem)
+    }
+}
+
+impl<S, L, T> PacedSink<S, L, T, PerByte> {
+    /// Creates a new `PacedSink` counting every byte of an item as one request.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - The sink to pace.
+    /// * `limiter` - The limiter pacing the bytes.
+    pub fn bytes(sink: S, limiter: L) -> Self {
+        Self::with_cost(sink, limiter, PerByte)
+    }
+}
+
+impl<S, L, T, C> PacedSink<S, L, T, C> {
+    /// Creates a new `PacedSink` counting every item as `cost` requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - The sink to pace.
+    /// * `limiter` - The limiter pacing the items.
+    /// * `cost` - The number of requests an item counts for, e.g. a closure.
+    pub fn with_cost(sink: S, limiter: L, cost: C) -> Self {
+        Self {
+            sink,
+            pacer: Pacer::new(limiter),
+            cost,
+            pending: None,
+            admitted: false,
+        }
+    }
+
+    /// Waits on the timer of `runtime` instead of the [`DefaultRuntime`].
+    pub fn with_runtime(mut self, runtime: Arc<dyn AsyncRuntime>) -> Self {
+        self.pacer.runtime = runtime;
+        self
+    }
+
+    /// Returns a reference to the wrapped sink.
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    /// Returns a reference to the limiter.
+    pub fn limiter(&self) -> &L {
+        &self.pacer.limiter
+    }
+
+    /// Returns the wrapped sink and the limiter, dropping the item waiting for the
+    /// limiter, if any.
+    pub fn into_inner(self) -> (S, L) {
+        (self.sink, self.pacer.limiter)
+    }
+}
+
+impl<S, L, T, C> PacedSink<S, L, T, C>
+where
+    S: Sink<T> + Unpin,
+    S::Error: From<Error>,
+    L: RateLimiter,
+{
+    /// Hands the pending item over to the sink once the limiter allows it.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        let Some((_, cost)) = self.pending else {
+            return Poll::Ready(Ok(()));
+        };
+        if !self.admitted {
+            if let Err(e) = ready!(self.pacer.poll_acquire(cx, cost)) {
+                self.pending = None;
+                return Poll::Ready(Err(e.into()));
+            }
+            self.admitted = true;
+        }
+        ready!(Pin::new(&mut self.sink).poll_ready(cx))?;
+
+        self.admitted = false;
+        match self.pending.take() {
+            Some((item, _)) => Poll::Ready(Pin::new(&mut self.sink).start_send(item)),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl<S, L, T, C> Sink<T> for PacedSink<S, L, T, C>
+where
+    S: Sink<T> + Unpin,
+    S::Error: From<Error>,
+    L: RateLimiter,
+    C: Cost<T>,
+{
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_drain(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        let cost = this.cost.cost(&item);
+        this.pending = Some((item, cost));
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.sink).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.sink).poll_close(cx)
+    }
+}
+
+// the wrapped sink is never pinned by `PacedSink`, which is why it must be `Unpin`
+impl<S: Unpin, L, T, C> Unpin for PacedSink<S, L, T, C> {}
+
+impl<S: fmt::Debug, L: fmt::Debug, T, C> fmt::Debug for PacedSink<S, L, T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PacedSink")
+            .field("sink", &self.sink)
+            .field("limiter", &self.pacer.limiter)
+            .field("pending", &self.pending.is_some())
+            .finish()
+    }
+}
+
+/// A [`Stream`] of results whose items are paced by a rate limiter.
+///
+/// Every item is held until the limiter allows its [`Cost`], and `poll_next`
+/// returns `Poll::Pending` in the meantime. This is the read side of a
+/// [`PacedSink`], e.g. for the `FramedRead` of a tokio-util codec. Errors of the
+/// wrapped stream are passed through without waiting.
+///
+/// An item costing more than the capacity of the limiter is dropped, and
+/// [`Error::RateLimited`] is yielded in its place.
+pub struct PacedStream<S, L, T, C = PerItem> {
+    stream: S,
+    pacer: Pacer<L>,
+    cost: C,
+    /// The item waiting for the limiter, with its cost.
+    pending: Option<(T, u64)>,
+}
+
+impl<S, L, T> PacedStream<S, L, T> {
+    /// Creates a new `PacedStream` counting every item as one request.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream to pace.
+    /// * `limiter` - The limiter pacing the items.
+    pub fn new(stream: S, limiter: L) -> Self {
+        Self::with_cost(stream, limiter, PerItem)
+    }
+}
+
+impl<S, L, T> PacedStream<S, L, T, PerByte> {
+    /// Creates a new `PacedStream` counting every byte of an item as one request.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream to pace.
+    /// * `limiter` - The limiter pacing the bytes.
+    pub fn bytes(stream: S, limiter: L) -> Self {
+        Self::with_cost(stream, limiter, PerByte)
+    }
+}
+
+impl<S, L, T, C> PacedStream<S, L, T, C> {
+    /// Creates a new `PacedStream` counting every item as `cost` requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream to pace.
+    /// * `limiter` - The limiter pacing the items.
+    /// * `cost` - The number of requests an item counts for, e.g. a closure.
+    pub fn with_cost(stream: S, limiter: L, cost: C) -> Self {
+        Self {
+            stream,
+            pacer: Pacer::new(limiter),
+            cost,
+            pending: None,
+        }
+    }
+
+    /// Waits on the timer of `runtime` instead of the [`DefaultRuntime`].
+    pub fn with_runtime(mut self, runtime: Arc<dyn AsyncRuntime>) -> Self {
+        self.pacer.runtime = runtime;
+        self
+    }
+
+    /// Returns a reference to the wrapped stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a reference to the limiter.
+    pub fn limiter(&self) -> &L {
+        &self.pacer.limiter
+    }
+
+    /// Returns the wrapped stream and the limiter, dropping the item waiting for
+    /// the limiter, if any.
+    pub fn into_inner(self) -> (S, L) {
+        (self.stream, self.pacer.limiter)
+    }
+}
+
+impl<S, L, T, E, C> Stream for PacedStream<S, L, T, C>
+where
+    S: Stream<Item = Result<T, E>> + Unpin,
+    E: From<Error>,
+    L: RateLimiter,
+    C: Cost<T>,
+{
+    type Item = Result<T, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let cost = match this.pending {
+            Some((_, cost)) => cost,
+            None => match ready!(Pin::new(&mut this.stream).poll_next(cx)) {
+                Some(Ok(item)) => {
+                    let cost = this.cost.cost(&item);
+                    this.pending = Some((item, cost));
+                    cost
+                }
+                other => return Poll::Ready(other),
+            },
+        };
+
+        let admitted = ready!(this.pacer.poll_acquire(cx, cost));
+        let item = this.pending.take().map(|(item, _)| item);
+        Poll::Ready(match admitted {
+            Ok(()) => item.map(Ok),
+            Err(e) => Some(Err(e.into())),
+        })
+    }
+}
+
+impl<S: Unpin, L, T, C> Unpin for PacedStream<S, L, T, C> {}
+
+impl<S: fmt::Debug, L: fmt::Debug, T, C> fmt::Debug for PacedStream<S, L, T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PacedStream")
+            .field("stream", &self.stream)
+            .field("limiter", &self.pacer.limiter)
+            .field("pending", &self.pending.is_some())
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{io, sync::Arc, time::Duration};
+
+    use futures::{channel::mpsc, stream, SinkExt, StreamExt};
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::{Clock, TokenBucket};
+
+    /// Reads the time of the tokio runtime, which tests pause.
+    fn tokio_clock() -> Arc<dyn Clock> {
+        let start = Instant::now();
+        Arc::new(move || start.elapsed())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn paced_sink_should_pace_items_and_bytes() {
+        let (tx, mut rx) = mpsc::unbounded();
+        let tx = tx.sink_map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe));
+        let bucket = TokenBucket::with_clock(2, 1, Some(Duration::from_millis(100)), tokio_clock());
+        let mut sink = PacedSink::new(tx, bucket);
+
+        let start = Instant::now();
+        for i in 0..5 {
+            sink.send(i).await.unwrap();
+        }
+        assert_eq!(start.elapsed(), Duration::from_millis(300));
+        assert_eq!(
+            rx.by_ref().take(5).collect::<Vec<_>>().await,
+            [0, 1, 2, 3, 4]
+        );
+
+        // 10 bytes per 100ms
+        let (tx, rx) = mpsc::unbounded();
+        let tx = tx.sink_map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe));
+        let bucket =
+            TokenBucket::with_clock(10, 10, Some(Duration::from_millis(100)), tokio_clock());
+        let mut sink = PacedSink::bytes(tx, bucket);
+
+        let start = Instant::now();
+        sink.send("hello").await.unwrap();
+        sink.send("world").await.unwrap();
+        sink.send("!").await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+
+        // an item exceeding the capacity of the bucket can never be sent
+        let e = sink.send("too large to fit").await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Other);
+        sink.send("ok").await.unwrap();
+
+        let (tx, _) = sink.into_inner();
+        drop(tx);
+        assert_eq!(rx.collect::<Vec<_>>().await, ["hello", "world", "!", "ok"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn paced_stream_should_pace_items() {
+        let items = stream::iter([Ok("a"), Err(io::ErrorKind::InvalidData.into()), Ok("bb")]);
+        let bucket = TokenBucket::with_clock(1, 1, Some(Duration::from_millis(100)), tokio_clock());
+        let mut stream = PacedStream::with_cost(items, bucket, |item: &&str| item.len() as u64);
+
+        let start = Instant::now();
+        assert_eq!(stream.next().await.unwrap().unwrap(), "a");
+        assert!(stream.next().await.unwrap().is_err());
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // "bb" exceeds the capacity of the bucket
+        let e: io::Error = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Other);
+        assert!(stream.next().await.is_none());
+    }
+}
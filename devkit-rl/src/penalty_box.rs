@@ -0,0 +1,377 @@
+use std::{collections::HashMap, hash::Hash, sync::Arc, time::Duration};
+
+use crate::{
+    clock::SharedClock,
+    sync::{Mutex, MutexExt},
+    Clock, KeyedLimiter,
+};
+
+/// When a [`PenaltyBox`] bans a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PenaltyPolicy {
+    /// The denials a key may get within `window` without being banned.
+    pub max_denials: u64,
+    /// The period over which denials are counted, starting at the first denial.
+    pub window: Duration,
+    /// How long a banned key stays banned.
+    pub cooldown: Duration,
+}
+
+/// A change of the ban of a key, reported to [`PenaltyBox::on_ban`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BanEvent {
+    /// The key has been banned for `cooldown`, after `denials` denials within the
+    /// window of the policy, or by [`PenaltyBox::ban`].
+    Banned { denials: u64, cooldown: Duration },
+    /// The cooldown of the key has ended. This is reported on the first request
+    /// for the key after the end of the ban.
+    Expired,
+    /// The ban of the key has been lifted by [`PenaltyBox::unban`].
+    Unbanned,
+}
+
+type BanHook<K> = Arc<dyn Fn(&K, &BanEvent) + Send + Sync>;
+
+/// A keyed limiter banning the keys that keep exceeding their limit.
+///
+/// A key denied more than [`PenaltyPolicy::max_denials`] times within the window
+/// of the policy is banned: all its requests are denied for the cooldown, without
+/// reaching its limiter. This keeps a client hammering the service from getting
+/// through every time its limit refills.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_rl::{KeyedLimiter, LimiterConfig, PenaltyBox, PenaltyPolicy};
+///
+/// let limiter = KeyedLimiter::new(LimiterConfig::TokenBucket {
+///     capacity: 1,
+///     refill_rate: 1,
+///     refill_interval_ms: None,
+///     initial_tokens: None,
+/// });
+/// let penalty_box = PenaltyBox::new(
+///     limiter,
+///     PenaltyPolicy {
+///         max_denials: 2,
+///         window: Duration::from_secs(10),
+///         cooldown: Duration::from_secs(60),
+///     },
+/// )
+/// .on_ban(|key, event| println!("{key}: {event:?}"));
+///
+/// assert!(penalty_box.allow(&"10.0.0.1"));
+/// for _ in 0..3 {
+///     assert!(!penalty_box.allow(&"10.0.0.1"));
+/// }
+/// assert!(penalty_box.banned_until(&"10.0.0.1").is_some());
+///
+/// assert!(penalty_box.unban(&"10.0.0.1"));
+/// ```
+#[derive(Clone)]
+pub struct PenaltyBox<K> {
+    limiter: KeyedLimiter<K>,
+    inner: Arc<Mutex<PenaltyBoxInner<K>>>,
+    hook: Option<BanHook<K>>,
+}
+
+struct PenaltyBoxInner<K> {
+    policy: PenaltyPolicy,
+    /// The keys denied recently, and the banned keys.
+    offenders: HashMap<K, Offender>,
+    /// When the offenders that are neither counting nor banned are next dropped.
+    next_purge: Duration,
+    /// The source of time.
+    clock: SharedClock,
+}
+
+#[derive(Debug)]
+struct Offender {
+    /// The denials since `window_start`.
+    denials: u64,
+    /// The time of the first denial counted.
+    window_start: Duration,
+    /// The end of the ban of the key, if it is banned.
+    banned_until: Option<Duration>,
+}
+
+impl<K: Hash + Eq + Clone> PenaltyBox<K> {
+    /// Creates a new `PenaltyBox` banning the keys of `limiter` according to `policy`.
+    pub fn new(limiter: KeyedLimiter<K>, policy: PenaltyPolicy) -> Self {
+        Self::from_clock(limiter, policy, SharedClock::std())
+    }
+
+    /// Creates a new `PenaltyBox` reading the time of the bans from `clock`.
+    pub fn with_clock(
+        limiter: KeyedLimiter<K>,
+        policy: PenaltyPolicy,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self::from_clock(limiter, policy, SharedClock::new(clock))
+    }
+
+    fn from_clock(limiter: KeyedLimiter<K>, policy: PenaltyPolicy, mut clock: SharedClock) -> Self {
+        Self {
+            limiter,
+            inner: Arc::new(Mutex::new(PenaltyBoxInner {
+                policy,
+                offenders: HashMap::new(),
+                next_purge: clock.now(),
+                clock,
+            })),
+            hook: None,
+        }
+    }
+
+    /// Calls `f` whenever a key is banned, or its ban ends.
+    ///
+    /// `f` is called after the decision has been made, outside of any lock, so it
+    /// may call back into the penalty box.
+    pub fn on_ban(mut self, f: impl Fn(&K, &BanEvent) + Send + Sync + 'static) -> Self {
+        self.hook = Some(Arc::new(f));
+        self
+    }
+
+    /// Attempts to allow a single request for `key`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if `key` is not banned and its limiter allows the request.
+    pub fn allow(&self, key: &K) -> bool {
+        self.allow_n(key, 1)
+    }
+
+    /// Attempts to allow `n` requests for `key`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if `key` is not banned and its limiter allows the requests. A denial
+    /// counts towards the ban of `key`.
+    pub fn allow_n(&self, key: &K, n: u64) -> bool {
+        let (banned, expired) = self.inner.lock_unpoisoned().check_ban(key);
+        if expired {
+            self.report(key, &BanEvent::Expired);
+        }
+        if banned {
+            return false;
+        }
+
+        if self.limiter.allow_n(key, n) {
+            return true;
+        }
+        if let Some(event) = self.inner.lock_unpoisoned().record_denial(key) {
+            self.report(key, &event);
+        }
+        false
+    }
+
+    /// Bans `key` for `cooldown`, whatever its denials.
+    ///
+    /// A key that is already banned is banned until the later of both ends.
+    pub fn ban(&self, key: &K, cooldown: Duration) {
+        let event = {
+            let mut inner = self.inner.lock_unpoisoned();
+            let now = inner.clock.now();
+            let offender = inner.offender(key, now);
+            let until = now.saturating_add(cooldown);
+            offender.banned_until = offender.banned_until.max(Some(until));
+            BanEvent::Banned {
+                denials: offender.denials,
+                cooldown,
+            }
+        };
+        self.report(key, &event);
+    }
+
+    /// Lifts the ban of `key`, and forgets its denials.
+    ///
+    /// # Returns
+    ///
+    /// `true` if `key` was banned.
+    pub fn unban(&self, key: &K) -> bool {
+        let banned = {
+            let mut inner = self.inner.lock_unpoisoned();
+            let now = inner.clock.now();
+            inner
+                .offenders
+                .remove(key)
+                .and_then(|o| o.banned_until)
+                .is_some_and(|until| until > now)
+        };
+        if banned {
+            self.report(key, &BanEvent::Unbanned);
+        }
+        banned
+    }
+
+    /// Returns how long `key` stays banned, or `None` if it is not banned.
+    pub fn banned_until(&self, key: &K) -> Option<Duration> {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.clock.now();
+        let until = inner.offenders.get(key)?.banned_until?;
+        (until > now).then(|| until - now)
+    }
+
+    /// Returns the keyed limiter the requests of keys that are not banned go to.
+    pub fn limiter(&self) -> &KeyedLimiter<K> {
+        &self.limiter
+    }
+
+    fn report(&self, key: &K, event: &BanEvent) {
+        if let Some(hook) = &self.hook {
+            hook(key, event);
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> PenaltyBoxInner<K> {
+    /// Returns whether `key` is banned, and whether its ban has just expired.
+    fn check_ban(&mut self, key: &K) -> (bool, bool) {
+        let now = self.clock.now();
+        let Some(offender) = self.offenders.get_mut(key) else {
+            return (false, false);
+        };
+        match offender.banned_until {
+            Some(until) if until > now => (true, false),
+            Some(_) => {
+                self.offenders.remove(key);
+                (false, true)
+            }
+            None => (false, false),
+        }
+    }
+
+    /// Counts a denial of `key`, banning it if it exceeds the policy.
+    ///
+    /// # Returns
+    ///
+    /// The ban event, if `key` has been banned.
+    fn record_denial(&mut self, key: &K) -> Option<BanEvent> {
+        let now = self.clock.now();
+        let policy = self.policy;
+        let offender = self.offender(key, now);
+        if now.saturating_sub(offender.window_start) >= policy.window {
+            offender.denials = 0;
+            offender.window_start = now;
+        }
+        offender.denials += 1;
+        if offender.denials <= policy.max_denials || offender.banned_until.is_some() {
+            return None;
+        }
+
+        offender.banned_until = Some(now.saturating_add(policy.cooldown));
+        Some(BanEvent::Banned {
+            denials: offender.denials,
+            cooldown: policy.cooldown,
+        })
+    }
+
+    /// Returns the record of `key`, creating it if needed.
+    ///
+    /// The records that no longer count denials and are not banned are dropped
+    /// first, at most once per window.
+    fn offender(&mut self, key: &K, now: Duration) -> &mut Offender {
+        if now >= self.next_purge {
+            let window = self.policy.window;
+            self.offenders.retain(|_, o| {
+                o.banned_until.is_some_and(|until| until > now)
+                    || now.saturating_sub(o.window_start) < window
+            });
+            self.next_purge = now.saturating_add(window);
+        }
+
+        self.offenders.entry(key.clone()).or_insert(Offender {
+            denials: 0,
+            window_start: now,
+            banned_until: None,
+        })
+    }
+}
+
+impl<K> std::fmt::Debug for PenaltyBox<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inner = self.inner.lock_unpoisoned();
+        f.debug_struct("PenaltyBox")
+            .field("policy", &inner.policy)
+            .field("offenders", &inner.offenders.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "token-bucket"))]
+mod tests {
+    use std::sync::Mutex as StdMutex;
+
+    use super::*;
+    use crate::{LimiterConfig, ManualClock};
+
+    #[test]
+    fn penalty_box_should_ban_repeat_offenders_for_cooldown() {
+        const WINDOW: Duration = Duration::from_secs(10);
+        const COOLDOWN: Duration = Duration::from_secs(60);
+
+        let clock = Arc::new(ManualClock::new());
+        let limiter = KeyedLimiter::new(LimiterConfig::TokenBucket {
+            capacity: 1,
+            refill_rate: 1,
+            refill_interval_ms: Some(3_600_000),
+            initial_tokens: None,
+        });
+        let events = Arc::new(StdMutex::new(Vec::new()));
+        let penalty_box = PenaltyBox::with_clock(
+            limiter,
+            PenaltyPolicy {
+                max_denials: 1,
+                window: WINDOW,
+                cooldown: COOLDOWN,
+            },
+            clock.clone(),
+        )
+        .on_ban({
+            let events = events.clone();
+            move |key: &&str, event| events.lock().unwrap().push((*key, *event))
+        });
+
+        // denials outside of the window do not add up
+        assert!(penalty_box.allow(&"a"));
+        assert!(!penalty_box.allow(&"a"));
+        clock.advance(WINDOW);
+        assert!(!penalty_box.allow(&"a"));
+        assert_eq!(penalty_box.banned_until(&"a"), None);
+
+        // a second denial within the window bans the key, even once its limit refills
+        assert!(!penalty_box.allow(&"a"));
+        assert_eq!(penalty_box.banned_until(&"a"), Some(COOLDOWN));
+        penalty_box.limiter().remove(&"a");
+        assert!(!penalty_box.allow(&"a"));
+
+        clock.advance(COOLDOWN);
+        assert!(penalty_box.allow(&"a"));
+
+        penalty_box.ban(&"b", COOLDOWN);
+        assert!(!penalty_box.allow(&"b"));
+        assert!(penalty_box.unban(&"b"));
+        assert!(!penalty_box.unban(&"b"));
+        assert!(penalty_box.allow(&"b"));
+
+        let banned = BanEvent::Banned {
+            denials: 2,
+            cooldown: COOLDOWN,
+        };
+        let manual = BanEvent::Banned {
+            denials: 0,
+            cooldown: COOLDOWN,
+        };
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                ("a", banned),
+                ("a", BanEvent::Expired),
+                ("b", manual),
+                ("b", BanEvent::Unbanned)
+            ]
+        );
+    }
+}
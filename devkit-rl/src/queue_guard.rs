@@ -0,0 +1,310 @@
+use alloc::sync::Arc;
+use core::time::Duration;
+
+#[cfg(feature = "sysinfo")]
+use crate::LoadSignal;
+use crate::{
+    clock::SharedClock,
+    sync::{Mutex, MutexExt},
+    Clock, Error,
+};
+
+/// The weight of the newest throughput sample in the moving average.
+const ALPHA: f64 = 0.3;
+
+/// An admission controller rejecting work that would wait longer than a target.
+///
+/// Rate and concurrency limits are set ahead of time, and have to be retuned as the
+/// cost of the work changes. A guard instead measures how fast the work in flight
+/// completes, and estimates by Little's law how long new work would wait behind it:
+/// `in_flight / throughput`. Work is rejected while that estimate exceeds the
+/// target, e.g. the latency SLO of the service, so that it fails fast instead of
+/// timing out after having used resources.
+///
+/// The throughput is measured over windows of `window`, and smoothed over the last
+/// windows. Until a first window has completed work, nothing is rejected. Work
+/// enters with [`QueueGuard::try_enter`] and completes when the returned
+/// [`QueuePermit`] is dropped. Clones of a guard share their state.
+///
+/// # Example
+///
+/// ```
+/// use std::{sync::Arc, time::Duration};
+/// use devkit_rl::{ManualClock, QueueGuard};
+///
+/// let clock = Arc::new(ManualClock::new());
+/// let guard = QueueGuard::with_clock(Duration::from_millis(400), Duration::from_secs(1), clock.clone());
+///
+/// // 10 jobs per second complete
+/// for _ in 0..10 {
+///     drop(guard.try_enter().unwrap());
+///     clock.advance(Duration::from_millis(100));
+/// }
+/// assert_eq!(guard.throughput(), Some(10.0));
+///
+/// // 5 jobs in flight take 500ms to drain, a sixth one would wait too long
+/// let jobs: Vec<_> = (0..5).map(|_| guard.try_enter().unwrap()).collect();
+/// assert_eq!(guard.estimated_delay(), Duration::from_millis(500));
+/// assert!(guard.try_enter().is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct QueueGuard {
+    inner: Arc<Mutex<QueueGuardInner>>,
+}
+
+#[derive(Debug)]
+struct QueueGuardInner {
+    /// The longest estimated wait of admitted work.
+    target: Duration,
+    /// The duration of a throughput measurement.
+    window: Duration,
+    /// The time when the current measurement started.
+    window_start: Duration,
+    /// The work completed in the current measurement.
+    completed: u64,
+    /// The work in flight.
+    in_flight: u64,
+    /// The smoothed completions per second, once measured.
+    throughput: Option<f64>,
+    /// The smoothed service time of the work, once measured.
+    service_time: Option<Duration>,
+    /// The source of time.
+    clock: SharedClock,
+    /// The load of the process, shedding all work while overloaded.
+    #[cfg(feature = "sysinfo")]
+    load: Option<LoadSignal>,
+}
+
+/// Work admitted by a [`QueueGuard`], completed when dropped.
+#[derive(Debug)]
+#[must_use = "the work completes as soon as the permit is dropped"]
+pub struct QueuePermit {
+    inner: Arc<Mutex<QueueGuardInner>>,
+    entered_at: Duration,
+}
+
+impl QueueGuard {
+    /// Creates a new `QueueGuard` measuring the throughput every second.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The longest estimated wait of admitted work.
+    #[cfg(feature = "std")]
+    pub fn new(target: Duration) -> Self {
+        Self::from_clock(target, Duration::from_secs(1), SharedClock::std())
+    }
+
+    /// Creates a new `QueueGuard` reading the time from `clock`.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The longest estimated wait of admitted work.
+    /// * `window` - The duration of a throughput measurement, at least a nanosecond.
+    /// * `clock` - The source of time of the guard.
+    pub fn with_clock(target: Duration, window: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self::from_clock(target, window, SharedClock::new(clock))
+    }
+
+    fn from_clock(target: Duration, window: Duration, mut clock: SharedClock) -> Self {
+        let inner = QueueGuardInner {
+            target,
+            window: window.max(Duration::from_nanos(1)),
+            window_start: clock.now(),
+            completed: 0,
+            in_flight: 0,
+            throughput: None,
+            service_time: None,
+            clock,
+            #[cfg(feature = "sysinfo")]
+            load: None,
+        };
+
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// Sheds all the work entering the guard while `signal` reports the process
+    /// overloaded, e.g. from a [`LoadSampler`](crate::LoadSampler).
+    #[cfg(feature = "sysinfo")]
+    pub fn with_load_signal(self, signal: LoadSignal) -> Self {
+        self.inner.lock_unpoisoned().load = Some(signal);
+        self
+    }
+
+    /// Admits work if its estimated wait does not exceed the target.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::QueueFull`] if the work in flight would delay it too long, or the
+    /// process is overloaded.
+    pub fn try_enter(&self) -> Result<QueuePermit, Error> {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.clock.now();
+        inner.measure(now);
+
+        if inner.estimated_delay() > inner.target || inner.is_overloaded() {
+            return Err(Error::QueueFull);
+        }
+        inner.in_flight += 1;
+        Ok(QueuePermit {
+            inner: self.inner.clone(),
+            entered_at: now,
+        })
+    }
+
+    /// Estimates how long new work would wait behind the work in flight.
+    ///
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if nothing is in flight or the throughput is not measured
+    /// yet, or `Duration::MAX` if the work in flight has stopped completing.
+    pub fn estimated_delay(&self) -> Duration {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.clock.now();
+        inner.measure(now);
+        inner.estimated_delay()
+    }
+
+    /// Returns the number of permits alive.
+    pub fn in_flight(&self) -> u64 {
+        self.inner.lock_unpoisoned().in_flight
+    }
+
+    /// Returns the smoothed completions per second, once measured.
+    pub fn throughput(&self) -> Option<f64> {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.clock.now();
+        inner.measure(now);
+        inner.throughput
+    }
+
+    /// Returns the smoothed time from admission to completion, once measured.
+    pub fn service_time(&self) -> Option<Duration> {
+        self.inner.lock_unpoisoned().service_time
+    }
+
+    /// Changes the longest estimated wait of admitted work.
+    pub fn set_target(&self, target: Duration) {
+        self.inner.lock_unpoisoned().target = target;
+    }
+}
+
+impl QueueGuardInner {
+    /// Returns `true` if the load signal reports the process overloaded.
+    fn is_overloaded(&self) -> bool {
+        #[cfg(feature = "sysinfo")]
+        return self.load.as_ref().is_some_and(LoadSignal::is_overloaded);
+        #[cfg(not(feature = "sysinfo"))]
+        false
+    }
+
+    /// Ends the current measurement if it lasted a window, folding its throughput
+    /// into the average.
+    fn measure(&mut self, now: Duration) {
+        let elapsed = now.saturating_sub(self.window_start);
+        if elapsed < self.window {
+            return;
+        }
+        // an idle guard says nothing about how fast work completes
+        if self.completed > 0 || self.in_flight > 0 {
+            let sample = self.completed as f64 / elapsed.as_secs_f64();
+            self.throughput = Some(match self.throughput {
+                Some(average) => average + ALPHA * (sample - average),
+                None => sample,
+            });
+        }
+        self.completed = 0;
+        self.window_start = now;
+    }
+
+    /// Estimates by Little's law how long the work in flight takes to drain.
+    fn estimated_delay(&self) -> Duration {
+        if self.in_flight == 0 {
+            return Duration::ZERO;
+        }
+        match self.throughput {
+            None => Duration::ZERO,
+            Some(throughput) => {
+                let delay = self.in_flight as f64 / throughput;
+                Duration::try_from_secs_f64(delay).unwrap_or(Duration::MAX)
+            }
+        }
+    }
+}
+
+impl Drop for QueuePermit {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.clock.now();
+        inner.measure(now);
+
+        inner.in_flight = inner.in_flight.saturating_sub(1);
+        inner.completed += 1;
+        let took = now.saturating_sub(self.entered_at);
+        inner.service_time = Some(match inner.service_time {
+            Some(average) => average.mul_f64(1.0 - ALPHA) + took.mul_f64(ALPHA),
+            None => took,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::ManualClock;
+
+    #[test]
+    fn queue_guard_should_follow_the_throughput() {
+        const WINDOW: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(ManualClock::new());
+        let guard = QueueGuard::with_clock(Duration::from_secs(1), WINDOW, clock.clone());
+
+        // unmeasured, everything is admitted
+        let permits: Vec<_> = (0..20).map(|_| guard.try_enter().unwrap()).collect();
+        assert_eq!(guard.in_flight(), 20);
+        assert_eq!(guard.estimated_delay(), Duration::ZERO);
+
+        // 5 jobs complete in the first second, 15 jobs take 3s to drain
+        let mut permits = permits.into_iter();
+        permits.by_ref().take(5).for_each(drop);
+        clock.advance(WINDOW);
+        assert_eq!(guard.throughput(), Some(5.0));
+        assert_eq!(guard.service_time(), Some(Duration::ZERO));
+        assert_eq!(guard.estimated_delay(), Duration::from_secs(3));
+        assert!(matches!(guard.try_enter(), Err(Error::QueueFull)));
+
+        // a stalled second drags the throughput down
+        clock.advance(WINDOW);
+        assert_eq!(guard.throughput(), Some(3.5));
+
+        // the backlog drains, new work is admitted again
+        permits.by_ref().take(12).for_each(drop);
+        assert_eq!(guard.in_flight(), 3);
+        assert!(guard.try_enter().is_ok());
+        assert!(guard.service_time() > Some(Duration::ZERO));
+
+        guard.set_target(Duration::from_millis(500));
+        assert!(guard.try_enter().is_err());
+        drop(permits);
+        assert_eq!(guard.estimated_delay(), Duration::ZERO);
+    }
+
+    #[cfg(feature = "sysinfo")]
+    #[test]
+    fn queue_guard_should_shed_work_while_the_process_is_overloaded() {
+        let clock = Arc::new(ManualClock::new());
+        let signal = LoadSignal::new();
+        let guard = QueueGuard::with_clock(Duration::from_secs(1), Duration::from_secs(1), clock)
+            .with_load_signal(signal.clone());
+
+        assert!(guard.try_enter().is_ok());
+        signal.set(1.2);
+        assert!(matches!(guard.try_enter(), Err(Error::QueueFull)));
+        signal.set(0.8);
+        assert!(guard.try_enter().is_ok());
+    }
+}
@@ -0,0 +1,172 @@
+use core::time::Duration;
+
+/// How many requests a limiter allows over time, and how many of them at once.
+///
+/// A quota replaces the positional arguments of the limiter constructors, whose
+/// order differs between algorithms, with named constructors. Every algorithm can
+/// be created from a quota with its `from_quota` constructor.
+///
+/// The burst defaults to the number of requests per period, so a limiter created
+/// from `Quota::per_second(30)` allows 30 requests at once and then 30 more every
+/// second.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_rl::{Quota, TokenBucket};
+///
+/// let quota = Quota::per_minute(100).allow_burst(20);
+/// assert_eq!(quota.replenish_interval(), Duration::from_millis(600));
+///
+/// let bucket = TokenBucket::from_quota(quota);
+/// assert!(bucket.allow_n(20));
+/// assert!(!bucket.allow());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Quota {
+    count: u64,
+    period: Duration,
+    burst: u64,
+}
+
+impl Quota {
+    /// Creates a quota of `count` requests per `period`.
+    ///
+    /// A quota of 0 requests denies every request.
+    pub const fn new(count: u64, period: Duration) -> Self {
+        Self {
+            count,
+            period,
+            burst: count,
+        }
+    }
+
+    /// Creates a quota of `rate` requests per second, which may be fractional.
+    ///
+    /// Very low rates are first-class: `Quota::with_rate(1.0 / 600.0)` allows one
+    /// request every 10 minutes. The quota is stored as one request per
+    /// `1 / rate` seconds, with a burst of the whole requests per second, and at
+    /// least 1. A rate that is not positive denies every request.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::Quota;
+    ///
+    /// let quota = Quota::with_rate(0.5);
+    /// assert_eq!(quota.replenish_interval(), Duration::from_secs(2));
+    /// assert_eq!(quota.burst(), 1);
+    /// ```
+    pub fn with_rate(rate: f64) -> Self {
+        if rate.is_nan() || rate <= 0.0 {
+            return Self::new(0, Duration::from_secs(1));
+        }
+        let period = Duration::try_from_secs_f64(1.0 / rate).unwrap_or(Duration::MAX);
+        Self::new(1, period).allow_burst((rate as u64).max(1))
+    }
+
+    /// Creates a quota of `count` requests per second.
+    pub const fn per_second(count: u64) -> Self {
+        Self::new(count, Duration::from_secs(1))
+    }
+
+    /// Creates a quota of `count` requests per minute.
+    pub const fn per_minute(count: u64) -> Self {
+        Self::new(count, Duration::from_secs(60))
+    }
+
+    /// Creates a quota of `count` requests per hour.
+    pub const fn per_hour(count: u64) -> Self {
+        Self::new(count, Duration::from_secs(60 * 60))
+    }
+
+    /// Sets how many requests are allowed at once.
+    pub const fn allow_burst(mut self, burst: u64) -> Self {
+        self.burst = burst;
+        self
+    }
+
+    /// Returns the number of requests allowed per period.
+    pub const fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the period over which [`Quota::count`] requests are allowed.
+    pub const fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Returns the number of requests allowed at once.
+    pub const fn burst(&self) -> u64 {
+        self.burst
+    }
+
+    /// Returns the time it takes for a single request to be allowed again, at
+    /// least one nanosecond.
+    pub fn replenish_interval(&self) -> Duration {
+        self.scale_period(1)
+    }
+
+    /// Returns the time it takes for a full burst to be allowed again.
+    ///
+    /// Windowed limiters allow [`Quota::burst`] requests per this period, which keeps
+    /// both the rate and the burst of the quota.
+    pub fn burst_period(&self) -> Duration {
+        self.scale_period(self.burst)
+    }
+
+    /// Returns `period * n / count`, at least one nanosecond.
+    fn scale_period(&self, n: u64) -> Duration {
+        if self.count == 0 {
+            return self.period;
+        }
+        let nanos = (self.period.as_nanos() * u128::from(n) / u128::from(self.count)).max(1);
+        Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quota_with_rate_should_allow_fractions() {
+        let quota = Quota::with_rate(1.0 / 600.0);
+        assert_eq!(quota.replenish_interval(), Duration::from_secs(600));
+        assert_eq!(quota.burst(), 1);
+
+        let quota = Quota::with_rate(30.0);
+        assert_eq!(quota.burst(), 30);
+        assert_eq!(quota.burst_period().as_millis(), 999);
+
+        assert_eq!(Quota::with_rate(0.0).count(), 0);
+        assert_eq!(Quota::with_rate(f64::NAN).count(), 0);
+    }
+
+    #[test]
+    fn quota_should_keep_rate_and_burst() {
+        let quota = Quota::per_minute(100).allow_burst(20);
+        assert_eq!(quota.count(), 100);
+        assert_eq!(quota.burst(), 20);
+        assert_eq!(quota.burst_period(), Duration::from_secs(12));
+        assert_eq!(Quota::per_second(30).burst_period(), Duration::from_secs(1));
+        assert_eq!(
+            Quota::new(3, Duration::from_nanos(1)).replenish_interval(),
+            Duration::from_nanos(1)
+        );
+
+        #[cfg(feature = "fixed-window")]
+        {
+            let window = crate::FixedWindow::from_quota(quota);
+            assert!(window.allow_n(20));
+            assert!(!window.allow());
+        }
+        #[cfg(feature = "sliding-window")]
+        {
+            let window = crate::SlidingWindowCount::from_quota(Quota::per_hour(0), 10);
+            assert!(!window.allow());
+        }
+    }
+}
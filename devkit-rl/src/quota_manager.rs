@@ -0,0 +1,336 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use crate::{
+    clock::{whole_periods, SharedClock},
+    sync::{Mutex, MutexExt},
+    Clock,
+};
+
+/// The share of one tenant of a [`QuotaManager`].
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::TenantQuota;
+///
+/// // 10 requests are always available, up to 50 when siblings leave room
+/// let quota = TenantQuota::guaranteed(10).borrow_up_to(50);
+/// assert_eq!(quota.ceiling(), 50);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TenantQuota {
+    guaranteed: u64,
+    ceiling: u64,
+}
+
+impl TenantQuota {
+    /// Creates a share of `guaranteed` requests per window, without borrowing.
+    pub const fn guaranteed(guaranteed: u64) -> Self {
+        Self {
+            guaranteed,
+            ceiling: guaranteed,
+        }
+    }
+
+    /// Allows the tenant to borrow unused capacity, up to `ceiling` requests per
+    /// window in total. A ceiling below the guarantee is raised to it.
+    pub const fn borrow_up_to(mut self, ceiling: u64) -> Self {
+        self.ceiling = if ceiling > self.guaranteed {
+            ceiling
+        } else {
+            self.guaranteed
+        };
+        self
+    }
+
+    /// Allows the tenant to borrow all unused capacity.
+    pub const fn borrow_unlimited(self) -> Self {
+        self.borrow_up_to(u64::MAX)
+    }
+
+    /// Returns the requests per window always available to the tenant.
+    pub const fn guaranteed_requests(&self) -> u64 {
+        self.guaranteed
+    }
+
+    /// Returns the most requests per window the tenant may use.
+    pub const fn ceiling(&self) -> u64 {
+        self.ceiling
+    }
+}
+
+/// A fixed window limit partitioned among named tenants.
+///
+/// This is the shape of per-plan limits on a SaaS platform: every tenant is
+/// guaranteed a minimum number of requests per window, and the parent limit caps
+/// all tenants together. The guarantees of all tenants never exceed the parent
+/// limit, so a tenant always gets its minimum, however busy its siblings are.
+///
+/// Tenants allowed to borrow may go beyond their minimum, up to their ceiling, with
+/// capacity that is neither used by nor guaranteed to their siblings.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_rl::{QuotaManager, TenantQuota};
+///
+/// let manager = QuotaManager::new(100, Some(Duration::from_secs(1)));
+/// assert!(manager.add_tenant("enterprise", TenantQuota::guaranteed(60).borrow_unlimited()));
+/// assert!(manager.add_tenant("free", TenantQuota::guaranteed(10)));
+///
+/// // the 30 requests no tenant is guaranteed can be borrowed
+/// assert!(manager.allow_n("enterprise", 90));
+/// assert!(!manager.allow("enterprise"));
+/// assert!(manager.allow_n("free", 10));
+/// ```
+#[derive(Debug, Clone)]
+pub struct QuotaManager {
+    inner: Arc<Mutex<QuotaManagerInner>>,
+}
+
+#[derive(Debug)]
+struct QuotaManagerInner {
+    /// The requests allowed per window for all tenants together.
+    size: u64,
+    /// Duration of each window.
+    interval: Duration,
+    /// The time when the current window ends.
+    next_win_time: Duration,
+    tenants: HashMap<String, Tenant>,
+    /// The sum over all tenants of the larger of their usage and their guarantee.
+    committed: u64,
+    /// The source of time.
+    clock: SharedClock,
+}
+
+#[derive(Debug)]
+struct Tenant {
+    quota: TenantQuota,
+    /// The requests allowed in the current window.
+    used: u64,
+}
+
+impl Tenant {
+    /// Returns the capacity of the parent limit taken by this tenant.
+    fn committed(&self) -> u64 {
+        self.used.max(self.quota.guaranteed)
+    }
+}
+
+impl QuotaManager {
+    /// Creates a new `QuotaManager` without tenants.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The requests allowed per window for all tenants together.
+    /// * `interval` - Optional duration of the time window. Defaults to 1 second if not provided.
+    pub fn new(size: u64, interval: Option<Duration>) -> Self {
+        Self::from_clock(size, interval, SharedClock::std())
+    }
+
+    /// Creates a new `QuotaManager` without tenants, reading the time from `clock`.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The requests allowed per window for all tenants together.
+    /// * `interval` - Optional duration of the time window. Defaults to 1 second if not provided.
+    /// * `clock` - The source of time of the window.
+    pub fn with_clock(size: u64, interval: Option<Duration>, clock: Arc<dyn Clock>) -> Self {
+        Self::from_clock(size, interval, SharedClock::new(clock))
+    }
+
+    fn from_clock(size: u64, interval: Option<Duration>, mut clock: SharedClock) -> Self {
+        let interval = interval.unwrap_or(Duration::from_secs(1));
+        Self {
+            inner: Arc::new(Mutex::new(QuotaManagerInner {
+                size,
+                interval,
+                next_win_time: clock.now().saturating_add(interval),
+                tenants: HashMap::new(),
+                committed: 0,
+                clock,
+            })),
+        }
+    }
+
+    /// Adds a tenant, or changes the share of an existing one.
+    ///
+    /// An existing tenant keeps the requests it used in the current window.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the guarantees of all tenants would exceed the parent limit, or
+    /// the capacity already used by the other tenants, in which case nothing is changed.
+    pub fn add_tenant(&self, name: impl Into<String>, quota: TenantQuota) -> bool {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_window();
+
+        let name = name.into();
+        let (old, used) = inner
+            .tenants
+            .get(&name)
+            .map_or((0, 0), |t| (t.committed(), t.used));
+        let tenant = Tenant { quota, used };
+        let committed = (inner.committed - old).checked_add(tenant.committed());
+        match committed {
+            Some(committed) if committed <= inner.size => inner.committed = committed,
+            _ => return false,
+        }
+        inner.tenants.insert(name, tenant);
+        true
+    }
+
+    /// Removes a tenant, returning whether it existed.
+    ///
+    /// The capacity it held becomes available to its siblings right away.
+    pub fn remove_tenant(&self, name: &str) -> bool {
+        let mut inner = self.inner.lock_unpoisoned();
+        let Some(tenant) = inner.tenants.remove(name) else {
+            return false;
+        };
+        inner.committed -= tenant.committed();
+        true
+    }
+
+    /// Attempts to allow a single request for `tenant`.
+    ///
+    /// This is a convenience method that is equivalent to calling `allow_n(tenant, 1)`.
+    pub fn allow(&self, tenant: &str) -> bool {
+        self.allow_n(tenant, 1)
+    }
+
+    /// Attempts to allow `n` requests for `tenant`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the requests are within the share of `tenant`, `false` if they are
+    /// not, or if there is no such tenant.
+    pub fn allow_n(&self, tenant: &str, n: u64) -> bool {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_window();
+
+        let (size, committed) = (inner.size, inner.committed);
+        let Some(tenant) = inner.tenants.get_mut(tenant) else {
+            return false;
+        };
+        let used = tenant.used.saturating_add(n);
+        let extra = used.max(tenant.quota.guaranteed) - tenant.committed();
+        if used > tenant.quota.ceiling || committed.saturating_add(extra) > size {
+            return false;
+        }
+        tenant.used = used;
+        inner.committed += extra;
+        true
+    }
+
+    /// Estimates how long to wait until `n` requests for `tenant` are allowed.
+    ///
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if the requests are allowed now, the time until the next
+    /// window otherwise, or `Duration::MAX` if `n` exceeds the ceiling of `tenant`,
+    /// or there is no such tenant.
+    pub fn next_available(&self, tenant: &str, n: u64) -> Duration {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_window();
+
+        let Some(t) = inner.tenants.get(tenant) else {
+            return Duration::MAX;
+        };
+        let used = t.used.saturating_add(n);
+        let extra = used.max(t.quota.guaranteed) - t.committed();
+        if used <= t.quota.ceiling && inner.committed.saturating_add(extra) <= inner.size {
+            return Duration::ZERO;
+        }
+        // in the next window, the tenant can borrow all capacity not guaranteed to
+        // its siblings
+        let reserved: u64 = inner.tenants.values().map(|t| t.quota.guaranteed).sum();
+        let available = t
+            .quota
+            .ceiling
+            .min(inner.size - (reserved - t.quota.guaranteed));
+        if n > available {
+            return Duration::MAX;
+        }
+        inner.next_win_time.saturating_sub(inner.clock.now())
+    }
+
+    /// Returns the requests used by `tenant` in the current window, or `None` if
+    /// there is no such tenant.
+    pub fn usage(&self, tenant: &str) -> Option<u64> {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_window();
+        inner.tenants.get(tenant).map(|t| t.used)
+    }
+}
+
+impl QuotaManagerInner {
+    /// Starts a new window if the current one has ended.
+    fn update_window(&mut self) {
+        let now = self.clock.now();
+        if now < self.next_win_time {
+            return;
+        }
+
+        // the window containing `now` ends at the next whole interval
+        let (_, into_window) = whole_periods(now - self.next_win_time, self.interval);
+        self.next_win_time = (now - into_window).saturating_add(self.interval);
+        self.committed = 0;
+        for tenant in self.tenants.values_mut() {
+            tenant.used = 0;
+            self.committed += tenant.quota.guaranteed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+
+    #[test]
+    fn quota_manager_should_keep_guarantees_while_borrowing() {
+        const INTERVAL: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(ManualClock::new());
+        let manager = QuotaManager::with_clock(10, Some(INTERVAL), clock.clone());
+        assert!(manager.add_tenant("a", TenantQuota::guaranteed(4).borrow_up_to(8)));
+        assert!(manager.add_tenant("b", TenantQuota::guaranteed(3).borrow_unlimited()));
+        assert!(!manager.add_tenant("c", TenantQuota::guaranteed(4)));
+        assert!(manager.add_tenant("c", TenantQuota::guaranteed(2)));
+
+        // a borrows the single request nobody is guaranteed, and stops there
+        assert!(manager.allow_n("a", 5));
+        assert!(!manager.allow("a"));
+        assert_eq!(manager.next_available("a", 1), INTERVAL);
+        assert_eq!(manager.next_available("a", 9), Duration::MAX);
+
+        // the guarantees of b and c hold
+        assert!(manager.allow_n("b", 3));
+        assert!(!manager.allow("b"));
+        assert!(manager.allow_n("c", 2));
+        assert!(!manager.allow("unknown"));
+
+        // removing c frees its share for borrowing
+        assert!(manager.remove_tenant("c"));
+        assert!(manager.allow_n("b", 2));
+        assert_eq!(manager.usage("b"), Some(5));
+
+        clock.advance(INTERVAL);
+        assert_eq!(manager.usage("b"), Some(0));
+        assert!(manager.allow_n("a", 7));
+        assert!(!manager.allow("a"));
+    }
+
+    #[test]
+    fn quota_manager_should_refuse_guarantees_overflowing() {
+        let manager = QuotaManager::new(u64::MAX, None);
+        assert!(manager.add_tenant("a", TenantQuota::guaranteed(u64::MAX)));
+        assert!(!manager.add_tenant("b", TenantQuota::guaranteed(1)));
+        assert!(manager.add_tenant("a", TenantQuota::guaranteed(u64::MAX - 1)));
+        assert!(manager.add_tenant("b", TenantQuota::guaranteed(1)));
+        assert!(!manager.add_tenant("c", TenantQuota::guaranteed(u64::MAX)));
+        assert_eq!(manager.usage("c"), None);
+    }
+}
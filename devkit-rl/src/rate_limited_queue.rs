@@ -0,0 +1,270 @@
+use std::{
+    fmt,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError},
+    thread,
+    time::Duration,
+};
+
+use crate::{limiter::retry_after, worker::Stop, RateLimiter, Worker};
+
+/// How often a consumer thread waiting for items checks whether it is stopped.
+const STOP_POLL: Duration = Duration::from_millis(10);
+
+/// A bounded queue of work delivered to its consumer at the pace of a rate limiter,
+/// e.g. background jobs calling a rate-limited API.
+///
+/// Any number of [producers](RateLimitedQueue::producer) push items into the queue,
+/// and block while it is full, so that a burst of work slows the producers down
+/// rather than growing the queue. A single consumer takes the items out in the
+/// order they were pushed, each once the limiter allows a request: by iterating
+/// over the queue, or on a background thread with
+/// [`RateLimitedQueue::spawn_consumer`]. The consumer waits for the limiter before
+/// taking an item, so that the items waiting are all in the queue, at most its
+/// capacity.
+///
+/// The consumer stops once every producer has been dropped and the queue is
+/// drained, or if the limiter never allows a request, e.g. with a capacity of 0.
+///
+/// # Example
+///
+/// ```
+/// use std::{thread, time::Duration};
+/// use devkit_rl::{RateLimitedQueue, TokenBucket};
+///
+/// let queue = RateLimitedQueue::new(TokenBucket::new(1, 1, Some(Duration::from_millis(10))), 16);
+///
+/// let producer = queue.producer();
+/// thread::spawn(move || {
+///     for job in 0..3 {
+///         producer.push(job).unwrap();
+///     }
+/// });
+///
+/// // one job every 10ms, in order
+/// let jobs: Vec<_> = queue.into_iter().collect();
+/// assert_eq!(jobs, [0, 1, 2]);
+/// ```
+pub struct RateLimitedQueue<T, L> {
+    limiter: L,
+    tx: SyncSender<T>,
+    rx: Receiver<T>,
+}
+
+/// A producer pushing items into a [`RateLimitedQueue`].
+///
+/// Producers are cheap to clone, and may be moved to other threads.
+pub struct QueueProducer<T> {
+    tx: SyncSender<T>,
+}
+
+/// An iterator taking the items out of a [`RateLimitedQueue`] at the pace of its
+/// limiter, blocking the thread in between.
+pub struct RateLimitedIter<T, L> {
+    limiter: L,
+    rx: Receiver<T>,
+}
+
+impl<T, L: RateLimiter> RateLimitedQueue<T, L> {
+    /// Creates a new `RateLimitedQueue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `limiter` - The limiter pacing the delivery of the items, one request each.
+    /// * `capacity` - The most items queued at once. With a capacity of 0, a push
+    ///   waits for the consumer to take the item.
+    pub fn new(limiter: L, capacity: usize) -> Self {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        Self { limiter, tx, rx }
+    }
+
+    /// Returns a new producer of the queue.
+    pub fn producer(&self) -> QueueProducer<T> {
+        QueueProducer {
+            tx: self.tx.clone(),
+        }
+    }
+
+    /// Returns the limiter pacing the queue.
+    pub fn limiter(&self) -> &L {
+        &self.limiter
+    }
+
+    /// Delivers the items to `f` at the pace of the limiter, on a background thread.
+    ///
+    /// The thread runs until every producer has been dropped and the queue is
+    /// drained, or until the returned [`Worker`] is shut down or dropped, which
+    /// drops the items left in the queue.
+    pub fn spawn_consumer(self, mut f: impl FnMut(T) + Send + 'static) -> Worker
+    where
+        T: Send + 'static,
+        L: Send + 'static,
+    {
+        let Self { limiter, rx, .. } = self;
+        Worker::spawn("queue-consumer", move |stop| {
+            while pace(&limiter, Some(stop)) {
+                let item = loop {
+                    match rx.recv_timeout(STOP_POLL) {
+                        Ok(item) => break item,
+                        Err(RecvTimeoutError::Timeout) if !stop.is_stopped() => {}
+                        Err(_) => return,
+                    }
+                };
+                f(item);
+            }
+        })
+    }
+}
+
+impl<T, L: RateLimiter> IntoIterator for RateLimitedQueue<T, L> {
+    type Item = T;
+    type IntoIter = RateLimitedIter<T, L>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        RateLimitedIter {
+            limiter: self.limiter,
+            rx: self.rx,
+        }
+    }
+}
+
+impl<T, L: RateLimiter> Iterator for RateLimitedIter<T, L> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if !pace(&self.limiter, None) {
+            return None;
+        }
+        self.rx.recv().ok()
+    }
+}
+
+impl<T> QueueProducer<T> {
+    /// Pushes `item` into the queue, blocking the thread while the queue is full.
+    ///
+    /// # Errors
+    ///
+    /// The item back if the consumer has stopped.
+    pub fn push(&self, item: T) -> Result<(), T> {
+        self.tx.send(item).map_err(|e| e.0)
+    }
+
+    /// Pushes `item` into the queue if it is not full.
+    ///
+    /// # Errors
+    ///
+    /// The item back if the queue is full, or the consumer has stopped.
+    pub fn try_push(&self, item: T) -> Result<(), T> {
+        self.tx.try_send(item).map_err(|e| match e {
+            TrySendError::Full(item) | TrySendError::Disconnected(item) => item,
+        })
+    }
+}
+
+/// Waits until `limiter` allows a request, or until `stop` is set.
+///
+/// # Returns
+///
+/// `false` if the limiter never allows a request, or the thread should stop.
+fn pace(limiter: &impl RateLimiter, stop: Option<&Stop>) -> bool {
+    while !limiter.allow() {
+        let Ok(wait) = retry_after(limiter.next_available(1)) else {
+            return false;
+        };
+        match stop {
+            Some(stop) if !stop.sleep(wait) => return false,
+            Some(_) => {}
+            None => thread::sleep(wait),
+        }
+    }
+    true
+}
+
+impl<T> Clone for QueueProducer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<T, L: fmt::Debug> fmt::Debug for RateLimitedQueue<T, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitedQueue")
+            .field("limiter", &self.limiter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for QueueProducer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueueProducer").finish_non_exhaustive()
+    }
+}
+
+impl<T, L: fmt::Debug> fmt::Debug for RateLimitedIter<T, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitedIter")
+            .field("limiter", &self.limiter)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, feature = "token-bucket"))]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::TokenBucket;
+
+    const INTERVAL: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn rate_limited_queue_should_deliver_in_order_at_the_pace_of_the_limiter() {
+        let queue = RateLimitedQueue::new(TokenBucket::new(1, 1, Some(INTERVAL)), 2);
+        let producer = queue.producer();
+        assert!(producer.try_push(1).is_ok());
+        assert!(producer.clone().try_push(2).is_ok());
+        // the queue is full
+        assert_eq!(producer.try_push(3), Err(3));
+
+        let pusher = thread::spawn(move || (3..6).try_for_each(|item| producer.push(item)));
+        let start = Instant::now();
+        let items: Vec<_> = queue.into_iter().collect();
+        assert_eq!(items, [1, 2, 3, 4, 5]);
+        assert!(start.elapsed() >= INTERVAL * 3);
+        assert!(pusher.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn rate_limited_queue_should_deliver_to_a_consumer_thread() {
+        let queue = RateLimitedQueue::new(TokenBucket::new(2, 2, Some(INTERVAL)), 4);
+        let producer = queue.producer();
+        let (tx, rx) = mpsc::channel();
+        let consumer = queue.spawn_consumer(move |item| tx.send(item).unwrap());
+
+        for item in 0..4 {
+            producer.push(item).unwrap();
+        }
+        let items: Vec<_> = rx.iter().take(4).collect();
+        assert_eq!(items, [0, 1, 2, 3]);
+
+        // the consumer stops once the producers are gone
+        drop(producer);
+        assert!(consumer.shutdown(Duration::from_secs(1)).is_ok());
+
+        // a consumer waiting for the limiter leaves the items in the queue
+        let queue = RateLimitedQueue::new(TokenBucket::new(1, 1, Some(INTERVAL * 1000)), 1);
+        assert!(queue.limiter().allow());
+        let producer = queue.producer();
+        let consumer = queue.spawn_consumer(|_| {});
+        assert!(producer.try_push(1).is_ok());
+        thread::sleep(INTERVAL);
+        assert_eq!(producer.try_push(2), Err(2));
+        assert!(consumer.shutdown(Duration::from_secs(1)).is_ok());
+
+        // a queue whose limiter never allows a request delivers nothing
+        let queue = RateLimitedQueue::new(TokenBucket::new(0, 1, Some(INTERVAL)), 1);
+        queue.producer().push(1).unwrap();
+        assert_eq!(queue.into_iter().next(), None);
+    }
+}
@@ -0,0 +1,330 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    clock::{whole_periods, SharedClock},
+    sync::{Mutex, MutexExt},
+    Clock, Observer,
+};
+
+/// How often a [`RateMeter`] created with [`RateMeter::new`] updates its averages.
+const DEFAULT_TICK: Duration = Duration::from_secs(5);
+
+/// How far back an average of a [`RateMeter`] reaches, like the 1, 5 and 15 minute
+/// load averages of Unix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Horizon {
+    /// The last minute or so.
+    OneMinute,
+    /// The last 5 minutes or so.
+    FiveMinutes,
+    /// The last 15 minutes or so.
+    FifteenMinutes,
+}
+
+impl Horizon {
+    const ALL: [Horizon; 3] = [
+        Horizon::OneMinute,
+        Horizon::FiveMinutes,
+        Horizon::FifteenMinutes,
+    ];
+
+    /// Returns the time after which the weight of an event has decayed by a factor
+    /// of `e`.
+    pub fn duration(self) -> Duration {
+        match self {
+            Horizon::OneMinute => Duration::from_secs(60),
+            Horizon::FiveMinutes => Duration::from_secs(5 * 60),
+            Horizon::FifteenMinutes => Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+/// A meter measuring the rate of events and their latency, as exponentially
+/// decaying moving averages over the last 1, 5 and 15 minutes.
+///
+/// Limiters enforce rates; a meter measures them, e.g. the throughput a service
+/// actually sustains, to feed an adaptive limit, or the latency of its responses,
+/// to shed load once it degrades. Events are counted with [`RateMeter::mark`], or
+/// timed with [`RateMeter::observe`], and the averages are updated every `tick`,
+/// each event weighing less as it ages: after its [`Horizon`], its weight has
+/// decayed by a factor of `e`. Until a first tick has ended, the rates are 0 and
+/// the latencies unknown.
+///
+/// The meter is an [`Observer`] counting the requests allowed, so that it measures
+/// the throughput of a limiter. Clones of a meter share their state.
+///
+/// # Example
+///
+/// ```
+/// use std::{sync::Arc, time::Duration};
+/// use devkit_rl::{Horizon, ManualClock, RateMeter};
+///
+/// let clock = Arc::new(ManualClock::new());
+/// let meter = RateMeter::with_clock(Duration::from_secs(5), clock.clone());
+///
+/// // 10 responses per second, taking 200ms each
+/// for _ in 0..12 {
+///     for _ in 0..50 {
+///         meter.observe(Duration::from_millis(200));
+///     }
+///     clock.advance(Duration::from_secs(5));
+/// }
+/// assert_eq!(meter.rate(Horizon::OneMinute), 10.0);
+///
+/// // shed load once the responses slow down
+/// let slo = Duration::from_millis(500);
+/// assert!(meter.latency(Horizon::OneMinute) < Some(slo));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateMeter {
+    inner: Arc<Mutex<RateMeterInner>>,
+}
+
+#[derive(Debug)]
+struct RateMeterInner {
+    /// How often the averages are updated.
+    tick: Duration,
+    /// The time when the meter was created.
+    start: Duration,
+    /// The time when the current tick started.
+    tick_start: Duration,
+    /// The events marked since the meter was created.
+    count: u64,
+    /// The events marked in the current tick.
+    marked: u64,
+    /// The total latency, in seconds, and the number of the events timed in the
+    /// current tick.
+    latency: (f64, u64),
+    /// The events per second, by horizon, once a tick has ended.
+    rates: [Option<f64>; 3],
+    /// The latency in seconds, by horizon, once a tick with timed events has ended.
+    latencies: [Option<f64>; 3],
+    /// The source of time.
+    clock: SharedClock,
+}
+
+impl RateMeter {
+    /// Creates a new `RateMeter` updating its averages every 5 seconds.
+    pub fn new() -> Self {
+        Self::from_clock(DEFAULT_TICK, SharedClock::std())
+    }
+
+    /// Creates a new `RateMeter` reading the time from `clock`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tick` - How often the averages are updated, at least a nanosecond. The
+    ///   shorter, the sooner the averages follow a change of rate.
+    /// * `clock` - The source of time of the meter.
+    pub fn with_clock(tick: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self::from_clock(tick, SharedClock::new(clock))
+    }
+
+    fn from_clock(tick: Duration, mut clock: SharedClock) -> Self {
+        let now = clock.now();
+        Self {
+            inner: Arc::new(Mutex::new(RateMeterInner {
+                tick: tick.max(Duration::from_nanos(1)),
+                start: now,
+                tick_start: now,
+                count: 0,
+                marked: 0,
+                latency: (0.0, 0),
+                rates: [None; 3],
+                latencies: [None; 3],
+                clock,
+            })),
+        }
+    }
+
+    /// Marks an event.
+    pub fn mark(&self) {
+        self.mark_n(1);
+    }
+
+    /// Marks `n` events.
+    pub fn mark_n(&self, n: u64) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.advance();
+        inner.count = inner.count.saturating_add(n);
+        inner.marked = inner.marked.saturating_add(n);
+    }
+
+    /// Marks an event which took `latency`, e.g. a response and its response time.
+    pub fn observe(&self, latency: Duration) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.advance();
+        inner.count = inner.count.saturating_add(1);
+        inner.marked = inner.marked.saturating_add(1);
+        inner.latency.0 += latency.as_secs_f64();
+        inner.latency.1 += 1;
+    }
+
+    /// Returns the events marked since the meter was created.
+    pub fn count(&self) -> u64 {
+        self.inner.lock_unpoisoned().count
+    }
+
+    /// Returns the events per second since the meter was created.
+    pub fn mean_rate(&self) -> f64 {
+        let mut inner = self.inner.lock_unpoisoned();
+        let elapsed = inner.clock.now().saturating_sub(inner.start);
+        if elapsed.is_zero() {
+            return 0.0;
+        }
+        inner.count as f64 / elapsed.as_secs_f64()
+    }
+
+    /// Returns the moving average of the events per second over `horizon`, 0 until
+    /// a first tick has ended.
+    pub fn rate(&self, horizon: Horizon) -> f64 {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.advance();
+        inner.rates[horizon as usize].unwrap_or(0.0)
+    }
+
+    /// Returns the moving average of the latency of the timed events over
+    /// `horizon`, once a tick with timed events has ended.
+    pub fn latency(&self, horizon: Horizon) -> Option<Duration> {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.advance();
+        let latency = inner.latencies[horizon as usize]?;
+        Some(Duration::try_from_secs_f64(latency).unwrap_or(Duration::MAX))
+    }
+}
+
+impl Default for RateMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateMeterInner {
+    /// Ends the ticks elapsed until now, folding them into the averages.
+    fn advance(&mut self) {
+        let now = self.clock.now();
+        let elapsed = now.saturating_sub(self.tick_start);
+        if elapsed < self.tick {
+            return;
+        }
+        let (ticks, into_tick) = whole_periods(elapsed, self.tick);
+
+        let rate = self.marked as f64 / self.tick.as_secs_f64();
+        let (total, timed) = self.latency;
+        let latency = (timed > 0).then(|| total / timed as f64);
+        // the ticks after the first one saw no events
+        let idle = ticks.saturating_sub(1) as f64 * self.tick.as_secs_f64();
+        for (i, horizon) in Horizon::ALL.into_iter().enumerate() {
+            let horizon = horizon.duration().as_secs_f64();
+            let alpha = 1.0 - (-self.tick.as_secs_f64() / horizon).exp();
+            let rate = self.rates[i].map_or(rate, |average| ewma(alpha, average, rate));
+            self.rates[i] = Some(rate * (-idle / horizon).exp());
+            if let Some(latency) = latency {
+                self.latencies[i] = Some(
+                    self.latencies[i].map_or(latency, |average| ewma(alpha, average, latency)),
+                );
+            }
+        }
+
+        self.marked = 0;
+        self.latency = (0.0, 0);
+        self.tick_start = now - into_tick;
+    }
+}
+
+/// Folds `sample` into the moving average `average`, `sample` weighing `alpha`.
+fn ewma(alpha: f64, average: f64, sample: f64) -> f64 {
+    average + alpha * (sample - average)
+}
+
+impl<K: ?Sized> Observer<K> for RateMeter {
+    fn on_allowed(&self, _key: &K, n: u64) {
+        self.mark_n(n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+
+    const TICK: Duration = Duration::from_secs(5);
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn rate_meter_should_decay_by_horizon() {
+        let clock = Arc::new(ManualClock::new());
+        let meter = RateMeter::with_clock(TICK, clock.clone());
+
+        meter.mark_n(50);
+        assert_eq!(meter.rate(Horizon::OneMinute), 0.0);
+        clock.advance(TICK);
+        for horizon in Horizon::ALL {
+            assert_eq!(meter.rate(horizon), 10.0);
+        }
+
+        // an idle minute divides the 1 minute rate by e
+        clock.advance(Duration::from_secs(60));
+        assert_close(meter.rate(Horizon::OneMinute), 10.0 * (-1.0f64).exp());
+        assert_close(meter.rate(Horizon::FiveMinutes), 10.0 * (-0.2f64).exp());
+        assert_close(
+            meter.rate(Horizon::FifteenMinutes),
+            10.0 * (-1.0f64 / 15.0).exp(),
+        );
+        assert_eq!(meter.count(), 50);
+        assert_close(meter.mean_rate(), 50.0 / 65.0);
+        assert_eq!(meter.latency(Horizon::OneMinute), None);
+    }
+
+    #[test]
+    fn rate_meter_should_average_latencies_of_timed_events() {
+        let clock = Arc::new(ManualClock::new());
+        let meter = RateMeter::with_clock(TICK, clock.clone());
+
+        meter.observe(Duration::from_millis(100));
+        meter.observe(Duration::from_millis(300));
+        clock.advance(TICK);
+        assert_eq!(
+            meter.latency(Horizon::OneMinute),
+            Some(Duration::from_millis(200))
+        );
+
+        // untimed events and idle ticks leave the latencies as they are
+        meter.mark_n(10);
+        clock.advance(TICK * 3);
+        assert_eq!(
+            meter.latency(Horizon::FifteenMinutes),
+            Some(Duration::from_millis(200))
+        );
+
+        meter.observe(Duration::from_millis(800));
+        clock.advance(TICK);
+        let alpha = 1.0 - (-5.0f64 / 60.0).exp();
+        let latency = meter.latency(Horizon::OneMinute).unwrap();
+        assert_close(latency.as_secs_f64(), 0.2 + alpha * 0.6);
+        assert!(meter.latency(Horizon::FiveMinutes).unwrap() < latency);
+    }
+
+    #[cfg(feature = "token-bucket")]
+    #[test]
+    fn rate_meter_should_measure_the_throughput_of_a_limiter() {
+        use crate::{Observed, RateLimiter, TokenBucket};
+
+        let clock = Arc::new(ManualClock::new());
+        let meter = RateMeter::with_clock(TICK, clock.clone());
+        let limiter = Observed::new(
+            TokenBucket::with_clock(10, 1, None, clock.clone()),
+            Arc::new(meter.clone()),
+        );
+
+        for _ in 0..20 {
+            limiter.allow();
+        }
+        clock.advance(TICK);
+        assert_eq!(meter.count(), 10);
+        assert_eq!(meter.rate(Horizon::OneMinute), 2.0);
+    }
+}
@@ -0,0 +1,417 @@
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc, OnceLock},
+    thread,
+};
+
+#[cfg(any(feature = "json", feature = "toml", feature = "yaml"))]
+use crate::ConfigError;
+use crate::{
+    sync::{RwLock, RwLockExt},
+    worker::spawn_task,
+    Limiter, LimiterConfig, RegistryConfig,
+};
+
+/// The process-wide registry, see [`LimiterRegistry::global`].
+static GLOBAL: OnceLock<LimiterRegistry> = OnceLock::new();
+
+/// A collection of named rate limiters that can be looked up at runtime.
+///
+/// A registry is usually built from a [`RegistryConfig`], so that a service can
+/// describe all of its rate policies declaratively. Limiters are shared: every
+/// lookup of the same name returns a handle to the same limiter state. Limiters
+/// registered from a configuration are built on their first lookup, so unused
+/// policies cost nothing, e.g. no leak thread for a queuing leaky bucket.
+///
+/// The registry is cheap to clone and safe to share across threads.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{LimiterConfig, LimiterRegistry, RateLimiter};
+///
+/// let registry = LimiterRegistry::new();
+/// registry.insert(
+///     "search-api",
+///     LimiterConfig::TokenBucket {
+///         capacity: 1,
+///         refill_rate: 1,
+///         refill_interval_ms: None,
+///         initial_tokens: None,
+///     }
+///     .build(),
+/// );
+///
+/// let limiter = registry.get("search-api").unwrap();
+/// assert!(limiter.allow());
+/// assert!(!registry.get("search-api").unwrap().allow());
+/// assert!(registry.get("unknown").is_none());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LimiterRegistry {
+    inner: Arc<RwLock<HashMap<String, Entry>>>,
+}
+
+/// A registered limiter.
+#[derive(Debug)]
+enum Entry {
+    /// A limiter registered as is.
+    Built(Limiter),
+    /// A limiter registered by configuration, built on its first lookup.
+    Lazy {
+        config: LimiterConfig,
+        limiter: OnceLock<Limiter>,
+    },
+}
+
+impl Entry {
+    fn lazy(config: LimiterConfig) -> Self {
+        Entry::Lazy {
+            config,
+            limiter: OnceLock::new(),
+        }
+    }
+
+    /// Returns the limiter, building it if needed.
+    fn limiter(&self) -> &Limiter {
+        match self {
+            Entry::Built(limiter) => limiter,
+            Entry::Lazy { config, limiter } => limiter.get_or_init(|| config.build()),
+        }
+    }
+
+    /// Returns the limiter if it has been built.
+    fn built(&self) -> Option<&Limiter> {
+        match self {
+            Entry::Built(limiter) => Some(limiter),
+            Entry::Lazy { limiter, .. } => limiter.get(),
+        }
+    }
+
+    /// Returns the limiter if it has been built, without building it.
+    fn into_built(self) -> Option<Limiter> {
+        match self {
+            Entry::Built(limiter) => Some(limiter),
+            Entry::Lazy { limiter, .. } => limiter.into_inner(),
+        }
+    }
+}
+
+impl LimiterRegistry {
+    /// Creates a new, empty `LimiterRegistry`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the process-wide registry.
+    ///
+    /// The global registry lets libraries deep in the call stack share the limiters
+    /// of the application by name, instead of having them passed down through every
+    /// layer. It starts empty: the application registers its policies at startup,
+    /// e.g. with [`LimiterRegistry::reload`], and libraries look them up with
+    /// [`limiter`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use devkit_rl::{LimiterConfig, LimiterRegistry, RateLimiter};
+    ///
+    /// // at startup
+    /// LimiterRegistry::global().register(
+    ///     "search-api",
+    ///     LimiterConfig::TokenBucket {
+    ///         capacity: 1,
+    ///         refill_rate: 1,
+    ///         refill_interval_ms: None,
+    ///         initial_tokens: None,
+    ///     },
+    /// );
+    ///
+    /// // anywhere else
+    /// let limiter = devkit_rl::limiter("search-api").unwrap();
+    /// assert!(limiter.allow());
+    /// assert!(!devkit_rl::limiter("search-api").unwrap().allow());
+    /// ```
+    pub fn global() -> &'static LimiterRegistry {
+        GLOBAL.get_or_init(LimiterRegistry::new)
+    }
+
+    /// Creates a new `LimiterRegistry` containing a limiter for every entry of `config`.
+    ///
+    /// The limiters are built on their first lookup.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The named limiter configurations.
+    pub fn from_config(config: &RegistryConfig) -> Self {
+        let limiters = config
+            .limiters
+            .iter()
+            .map(|(name, c)| (name.clone(), Entry::lazy(*c)))
+            .collect();
+
+        Self {
+            inner: Arc::new(RwLock::new(limiters)),
+        }
+    }
+
+    /// Creates a new `LimiterRegistry` from a JSON configuration document.
+    #[cfg(feature = "json")]
+    pub fn from_json(s: &str) -> Result<Self, ConfigError> {
+        RegistryConfig::from_json(s).map(|c| Self::from_config(&c))
+    }
+
+    /// Creates a new `LimiterRegistry` from a TOML configuration document.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(s: &str) -> Result<Self, ConfigError> {
+        RegistryConfig::from_toml(s).map(|c| Self::from_config(&c))
+    }
+
+    /// Creates a new `LimiterRegistry` from a YAML configuration document.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(s: &str) -> Result<Self, ConfigError> {
+        RegistryConfig::from_yaml(s).map(|c| Self::from_config(&c))
+    }
+
+    /// Applies a new configuration to the registry.
+    ///
+    /// Limiters whose algorithm is unchanged are reconfigured in place, so their
+    /// current state (tokens, window counts, logs) survives the reload and handles
+    /// obtained earlier observe the new limits. Limiters that switched algorithm or
+    /// are new get fresh state, built on their next lookup, and limiters missing from
+    /// `config` are removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The new named limiter configurations.
+    pub fn reload(&self, config: &RegistryConfig) {
+        let mut limiters = self.inner.write_unpoisoned();
+
+        limiters.retain(|name, _| config.limiters.contains_key(name));
+        for (name, c) in &config.limiters {
+            match limiters.get(name).and_then(Entry::built) {
+                Some(limiter) if limiter.reconfigure(c) => {}
+                _ => {
+                    limiters.insert(name.clone(), Entry::lazy(*c));
+                }
+            }
+        }
+    }
+
+    /// Reloads the registry every time a new configuration is received from `rx`.
+    ///
+    /// This lets a config management system push new limits without recreating the
+    /// registry; see [`LimiterRegistry::reload`] for how state is preserved. The
+    /// background thread stops once every sender of `rx` has been dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `rx` - The channel new configurations are pushed to.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the background thread applying the configurations.
+    pub fn watch(&self, rx: mpsc::Receiver<RegistryConfig>) -> thread::JoinHandle<()> {
+        let registry = self.clone();
+        spawn_task("registry-watch", move || {
+            for config in rx {
+                registry.reload(&config);
+            }
+        })
+    }
+
+    /// Looks up a limiter by name.
+    ///
+    /// # Returns
+    ///
+    /// A handle sharing state with the registered limiter, or `None` if no limiter
+    /// is registered under `name`.
+    pub fn get(&self, name: &str) -> Option<Limiter> {
+        self.inner
+            .read_unpoisoned()
+            .get(name)
+            .map(|e| e.limiter().clone())
+    }
+
+    /// Registers `limiter` under `name`, returning the limiter previously registered
+    /// under that name, if it had been built.
+    pub fn insert(&self, name: impl Into<String>, limiter: Limiter) -> Option<Limiter> {
+        self.inner
+            .write_unpoisoned()
+            .insert(name.into(), Entry::Built(limiter))
+            .and_then(Entry::into_built)
+    }
+
+    /// Registers a limiter configured by `config` under `name`, replacing the limiter
+    /// previously registered under that name, if any.
+    ///
+    /// The limiter is built on its first lookup.
+    pub fn register(&self, name: impl Into<String>, config: LimiterConfig) {
+        self.inner
+            .write_unpoisoned()
+            .insert(name.into(), Entry::lazy(config));
+    }
+
+    /// Removes the limiter registered under `name`, returning it if it had been built.
+    pub fn remove(&self, name: &str) -> Option<Limiter> {
+        self.inner
+            .write_unpoisoned()
+            .remove(name)
+            .and_then(Entry::into_built)
+    }
+
+    /// Returns the names of all registered limiters.
+    pub fn names(&self) -> Vec<String> {
+        self.inner.read_unpoisoned().keys().cloned().collect()
+    }
+}
+
+/// Looks up a limiter of the process-wide registry by name.
+///
+/// This is a shorthand for `LimiterRegistry::global().get(name)`, see
+/// [`LimiterRegistry::global`].
+pub fn limiter(name: &str) -> Option<Limiter> {
+    LimiterRegistry::global().get(name)
+}
+
+#[cfg(all(test, feature = "fixed-window"))]
+mod tests {
+    #[cfg(feature = "sliding-log")]
+    use crate::LogOverflow;
+    use crate::RateLimiter;
+
+    use super::*;
+
+    #[cfg(feature = "sliding-log")]
+    #[test]
+    fn limiter_registry_should_work() {
+        let mut config = RegistryConfig::default();
+        config.limiters.insert(
+            "api".to_string(),
+            LimiterConfig::FixedWindow {
+                size: 2,
+                interval_ms: Some(60_000),
+                smoothing: false,
+            },
+        );
+        config.limiters.insert(
+            "login".to_string(),
+            LimiterConfig::SlidingWindowLog {
+                size: 1,
+                interval_ms: Some(60_000),
+                max_entries: None,
+                overflow: LogOverflow::default(),
+            },
+        );
+
+        let registry = LimiterRegistry::from_config(&config);
+        let mut names = registry.names();
+        names.sort();
+        assert_eq!(names, ["api", "login"]);
+
+        // every lookup shares the same state
+        assert!(registry.get("api").unwrap().allow());
+        assert!(registry.get("api").unwrap().allow());
+        assert!(!registry.get("api").unwrap().allow());
+
+        assert!(matches!(
+            registry.get("login"),
+            Some(Limiter::SlidingWindowLog(_))
+        ));
+        assert!(registry.remove("login").is_some());
+        assert!(registry.get("login").is_none());
+    }
+
+    #[cfg(feature = "token-bucket")]
+    #[test]
+    fn limiter_registry_reload_should_keep_state() {
+        let fixed_window = |size| LimiterConfig::FixedWindow {
+            size,
+            interval_ms: Some(60_000),
+            smoothing: false,
+        };
+
+        let mut config = RegistryConfig::default();
+        config.limiters.insert("api".to_string(), fixed_window(2));
+        config.limiters.insert("old".to_string(), fixed_window(2));
+        let registry = LimiterRegistry::from_config(&config);
+
+        let api = registry.get("api").unwrap();
+        assert!(api.allow());
+        assert!(api.allow());
+        assert!(!api.allow());
+
+        // raising the limit keeps the 2 requests already counted in the window
+        let (tx, rx) = mpsc::channel();
+        let watcher = registry.watch(rx);
+        let mut config = RegistryConfig::default();
+        config.limiters.insert("api".to_string(), fixed_window(3));
+        config.limiters.insert(
+            "new".to_string(),
+            LimiterConfig::TokenBucket {
+                capacity: 1,
+                refill_rate: 1,
+                refill_interval_ms: None,
+                initial_tokens: None,
+            },
+        );
+        tx.send(config).unwrap();
+        drop(tx);
+        watcher.join().unwrap();
+
+        assert!(api.allow());
+        assert!(!api.allow());
+        assert!(registry.get("old").is_none());
+        assert!(matches!(registry.get("new"), Some(Limiter::TokenBucket(_))));
+    }
+
+    #[test]
+    fn global_registry_should_build_limiters_lazily() {
+        let config = LimiterConfig::FixedWindow {
+            size: 1,
+            interval_ms: Some(60_000),
+            smoothing: false,
+        };
+        LimiterRegistry::global().register("global-test", config);
+
+        let is_built = |name: &str| {
+            LimiterRegistry::global()
+                .inner
+                .read_unpoisoned()
+                .get(name)
+                .and_then(Entry::built)
+                .is_some()
+        };
+        assert!(!is_built("global-test"));
+
+        // every lookup shares the limiter built by the first one
+        let handles: Vec<_> = (0..4)
+            .map(|_| thread::spawn(|| limiter("global-test").unwrap().allow()))
+            .collect();
+        let allowed = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|allowed| *allowed)
+            .count();
+        assert_eq!(allowed, 1);
+        assert!(is_built("global-test"));
+        assert!(limiter("global-unknown").is_none());
+
+        // an unused limiter is removed without being built
+        LimiterRegistry::global().register("global-unused", config);
+        assert!(LimiterRegistry::global().remove("global-unused").is_none());
+        assert!(LimiterRegistry::global().remove("global-test").is_some());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn limiter_registry_from_json_should_work() {
+        let registry = LimiterRegistry::from_json(
+            r#"{ "limiters": { "api": { "algorithm": "token_bucket", "capacity": 1, "refill_rate": 1 } } }"#,
+        )
+        .unwrap();
+
+        assert!(registry.get("api").unwrap().allow());
+        assert!(!registry.get("api").unwrap().allow());
+    }
+}
@@ -0,0 +1,207 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::time::Duration;
+
+use crate::{
+    clock::SharedClock,
+    sync::{Mutex, MutexExt},
+    Clock,
+};
+
+/// The number of slots the deposits of a [`RetryBudget`] are kept in.
+const SLOTS: usize = 10;
+
+/// The credits a single retry costs.
+const WITHDRAW_AMOUNT: i64 = 1000;
+
+/// A budget limiting retries to a share of the requests, like the retry budgets of
+/// Finagle and linkerd.
+///
+/// Retrying failed requests is how clients recover from transient errors, and also
+/// how they turn an overloaded service into an outage, as every failure adds
+/// another request. A budget allows retries only while they stay below
+/// `retry_percent` of the requests of the last `ttl`, plus a reserve of
+/// `min_per_sec` retries per second for clients that send few requests.
+///
+/// Every request deposits into the budget with [`RetryBudget::deposit`], and every
+/// retry withdraws from it with [`RetryBudget::withdraw`]. Deposits and withdrawals
+/// expire after `ttl`. Clones of a budget share their state.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_rl::RetryBudget;
+///
+/// // retries may add 20% to the requests of the last 10 seconds, and 1 per second
+/// let budget = RetryBudget::new(Duration::from_secs(10), 1, 0.2);
+/// for _ in 0..100 {
+///     budget.deposit();
+/// }
+///
+/// // 10 retries from the reserve and 20 from the deposits
+/// assert_eq!((0..40).filter(|_| budget.withdraw()).count(), 30);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    inner: Arc<Mutex<RetryBudgetInner>>,
+}
+
+#[derive(Debug)]
+struct RetryBudgetInner {
+    /// The credits deposited minus the credits withdrawn in each slot.
+    slots: Vec<i64>,
+    /// The sum of all slots, kept up to date incrementally.
+    balance: i64,
+    /// The index of the current slot.
+    index: usize,
+    /// Duration of each slot.
+    slot_interval: Duration,
+    /// The time when the current slot started.
+    slot_start: Duration,
+    /// The credits a request deposits.
+    deposit_amount: i64,
+    /// The credits that can be withdrawn in addition to the deposits.
+    reserve: i64,
+    /// The source of time.
+    clock: SharedClock,
+}
+
+impl RetryBudget {
+    /// Creates a new `RetryBudget`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - How long deposits count towards the budget.
+    /// * `min_per_sec` - The retries per second allowed regardless of the deposits.
+    /// * `retry_percent` - The retries allowed per deposit, e.g. `0.2` for one retry
+    ///   every five requests. Clamped to `0.0..=1000.0`.
+    #[cfg(feature = "std")]
+    pub fn new(ttl: Duration, min_per_sec: u32, retry_percent: f32) -> Self {
+        Self::from_clock(ttl, min_per_sec, retry_percent, SharedClock::std())
+    }
+
+    /// Creates a new `RetryBudget` reading the time from `clock`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - How long deposits count towards the budget.
+    /// * `min_per_sec` - The retries per second allowed regardless of the deposits.
+    /// * `retry_percent` - The retries allowed per deposit, clamped to `0.0..=1000.0`.
+    /// * `clock` - The source of time of the budget.
+    pub fn with_clock(
+        ttl: Duration,
+        min_per_sec: u32,
+        retry_percent: f32,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self::from_clock(ttl, min_per_sec, retry_percent, SharedClock::new(clock))
+    }
+
+    fn from_clock(
+        ttl: Duration,
+        min_per_sec: u32,
+        retry_percent: f32,
+        mut clock: SharedClock,
+    ) -> Self {
+        let deposit_amount = (retry_percent.clamp(0.0, 1000.0) * WITHDRAW_AMOUNT as f32) as i64;
+        // `min_per_sec` retries per second over `ttl`, in thousandths of a retry
+        let reserve = i64::try_from(u128::from(min_per_sec) * ttl.as_millis()).unwrap_or(i64::MAX);
+        let slot_nanos = (ttl.as_nanos() / SLOTS as u128).max(1);
+
+        Self {
+            inner: Arc::new(Mutex::new(RetryBudgetInner {
+                slots: vec![0; SLOTS],
+                balance: 0,
+                index: 0,
+                slot_interval: Duration::from_nanos(u64::try_from(slot_nanos).unwrap_or(u64::MAX)),
+                slot_start: clock.now(),
+                deposit_amount,
+                reserve,
+                clock,
+            })),
+        }
+    }
+
+    /// Records a request, earning credits for `retry_percent` of a retry.
+    pub fn deposit(&self) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_slots();
+        let amount = inner.deposit_amount;
+        inner.add(amount);
+    }
+
+    /// Attempts to spend the credits of a single retry.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the retry is within the budget, `false` if it should not be made.
+    pub fn withdraw(&self) -> bool {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_slots();
+        if inner.balance.saturating_add(inner.reserve) < WITHDRAW_AMOUNT {
+            return false;
+        }
+        inner.add(-WITHDRAW_AMOUNT);
+        true
+    }
+}
+
+impl RetryBudgetInner {
+    /// Clears the slots that have expired since the last update.
+    fn update_slots(&mut self) {
+        let now = self.clock.now();
+        let elapsed = now.saturating_sub(self.slot_start);
+        let passed = elapsed.as_nanos() / self.slot_interval.as_nanos();
+
+        for i in 1..=passed.min(SLOTS as u128) as usize {
+            let idx = (self.index + i) % SLOTS;
+            self.balance -= self.slots[idx];
+            self.slots[idx] = 0;
+        }
+
+        self.index = ((self.index as u128 + passed) % SLOTS as u128) as usize;
+        let into_slot = elapsed.as_nanos() % self.slot_interval.as_nanos();
+        self.slot_start = now - Duration::from_nanos(into_slot as u64);
+    }
+
+    /// Adds `amount` credits to the current slot.
+    fn add(&mut self, amount: i64) {
+        self.slots[self.index] = self.slots[self.index].saturating_add(amount);
+        self.balance = self.balance.saturating_add(amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManualClock;
+
+    #[test]
+    fn retry_budget_should_expire_deposits() {
+        const TTL: Duration = Duration::from_secs(10);
+
+        let clock = Arc::new(ManualClock::new());
+        let budget = RetryBudget::with_clock(TTL, 0, 0.5, clock.clone());
+        assert!(!budget.withdraw());
+
+        for _ in 0..4 {
+            budget.deposit();
+        }
+        assert!(budget.withdraw());
+        assert!(budget.withdraw());
+        assert!(!budget.withdraw());
+
+        // the withdrawals expire together with the deposits
+        clock.advance(TTL);
+        budget.deposit();
+        budget.deposit();
+        assert!(budget.withdraw());
+        assert!(!budget.withdraw());
+
+        // the reserve is always there
+        let budget = RetryBudget::with_clock(TTL, 1, 0.0, clock.clone());
+        assert_eq!((0..20).filter(|_| budget.withdraw()).count(), 10);
+        clock.advance(TTL);
+        assert!(budget.withdraw());
+    }
+}
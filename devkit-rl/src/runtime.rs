@@ -0,0 +1,197 @@
+use core::{
+    future::{poll_fn, Future},
+    pin::Pin,
+    task::Poll,
+    time::Duration,
+};
+
+/// A future completing once a wait is over, returned by an [`AsyncRuntime`].
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// The timer of an async runtime, on which the async limiters wait.
+///
+/// The limiters only need to sleep, so they work with any executor given a timer:
+/// [`DefaultRuntime`] picks the timer of the enabled runtime, [`TokioRuntime`],
+/// [`AsyncStdRuntime`] or [`SmolRuntime`], and any function returning a [`Sleep`]
+/// is a runtime too, e.g. for the timer of an embedded executor.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_rl::{AsyncRuntime, DefaultRuntime, Limiter, Sleep, TokenBucket};
+///
+/// // any function returning a sleep is a runtime
+/// let runtime = |duration: Duration| -> Sleep {
+///     println!("waiting {duration:?}");
+///     DefaultRuntime.sleep(duration)
+/// };
+/// let limiter = Limiter::TokenBucket(TokenBucket::new(1, 1, Some(Duration::from_millis(10))));
+///
+/// futures::executor::block_on(async {
+///     assert!(limiter.wait_async_with(1, Duration::ZERO, &runtime).await.is_ok());
+///     assert!(limiter.wait_async_with(1, Duration::ZERO, &runtime).await.is_ok());
+/// });
+/// ```
+///
+/// [`TokioRuntime`]: crate::TokioRuntime
+/// [`AsyncStdRuntime`]: crate::AsyncStdRuntime
+/// [`SmolRuntime`]: crate::SmolRuntime
+pub trait AsyncRuntime: Send + Sync {
+    /// Returns a future completing after `duration`.
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+impl<F> AsyncRuntime for F
+where
+    F: Fn(Duration) -> Sleep + Send + Sync,
+{
+    fn sleep(&self, duration: Duration) -> Sleep {
+        self(duration)
+    }
+}
+
+/// The timer of the runtime the limiters run on, unless another one is given.
+///
+/// Within a tokio runtime, with the `tokio` feature, the limiters wait on the tokio
+/// timer. Elsewhere, they wait on the timer of async-std with the `async-std`
+/// feature, or of smol with the `smol` feature, both of which work with any
+/// executor. Without any of them, every wait is timed by a thread of its own, which
+/// suits occasional waits only: enable the feature of the runtime, or give its timer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultRuntime;
+
+impl AsyncRuntime for DefaultRuntime {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        #[cfg(feature = "tokio")]
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return TokioRuntime.sleep(duration);
+        }
+        #[cfg(feature = "async-std")]
+        return AsyncStdRuntime.sleep(duration);
+        #[cfg(all(feature = "smol", not(feature = "async-std")))]
+        return SmolRuntime.sleep(duration);
+        #[cfg(not(any(feature = "async-std", feature = "smol")))]
+        thread_sleep(duration)
+    }
+}
+
+/// The timer of tokio, which must be awaited within a tokio runtime.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioRuntime;
+
+#[cfg(feature = "tokio")]
+impl AsyncRuntime for TokioRuntime {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// The timer of async-std, which works with any executor.
+#[cfg(feature = "async-std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStdRuntime;
+
+#[cfg(feature = "async-std")]
+impl AsyncRuntime for AsyncStdRuntime {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+/// The timer of smol, from `async-io`, which works with any executor.
+#[cfg(feature = "smol")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmolRuntime;
+
+#[cfg(feature = "smol")]
+impl AsyncRuntime for SmolRuntime {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        let timer = async_io::Timer::after(duration);
+        Box::pin(async move {
+            timer.await;
+        })
+    }
+}
+
+/// Returns a future completing after `duration`, timed by a thread of its own.
+#[cfg(not(any(feature = "async-std", feature = "smol")))]
+fn thread_sleep(duration: Duration) -> Sleep {
+    let (tx, rx) = oneshot::channel();
+    crate::worker::spawn_task("timer", move || {
+        std::thread::sleep(duration);
+        let _ = tx.send(());
+    });
+    Box::pin(async move {
+        let _ = rx.await;
+    })
+}
+
+/// Awaits `future` for at most `duration` on the timer of `runtime`.
+///
+/// # Returns
+///
+/// The output of `future`, or `None` if the time ran out first.
+pub(crate) async fn timeout<F>(
+    runtime: &(impl AsyncRuntime + ?Sized),
+    duration: Duration,
+    future: &mut F,
+) -> Option<F::Output>
+where
+    F: Future + Unpin,
+{
+    let mut sleep = runtime.sleep(duration);
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = Pin::new(&mut *future).poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        sleep.as_mut().poll(cx).map(|()| None)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use futures::executor::block_on;
+
+    use super::*;
+
+    const WAIT: Duration = Duration::from_millis(20);
+
+    fn assert_sleeps(runtime: &dyn AsyncRuntime) {
+        let start = Instant::now();
+        block_on(runtime.sleep(WAIT));
+        assert!(start.elapsed() >= WAIT);
+    }
+
+    #[test]
+    fn runtimes_should_sleep_on_any_executor() {
+        assert_sleeps(&DefaultRuntime);
+        #[cfg(feature = "async-std")]
+        assert_sleeps(&AsyncStdRuntime);
+        #[cfg(feature = "smol")]
+        assert_sleeps(&SmolRuntime);
+        #[cfg(not(any(feature = "async-std", feature = "smol")))]
+        assert_sleeps(&thread_sleep);
+
+        // the future wins the race, or the time runs out
+        let mut ready = core::future::ready(1);
+        assert_eq!(
+            block_on(timeout(&DefaultRuntime, WAIT, &mut ready)),
+            Some(1)
+        );
+        let mut pending = core::future::pending::<()>();
+        assert_eq!(block_on(timeout(&DefaultRuntime, WAIT, &mut pending)), None);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn default_runtime_should_sleep_on_tokio_within_tokio() {
+        let start = tokio::time::Instant::now();
+        DefaultRuntime.sleep(Duration::from_secs(3600)).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(3600));
+    }
+}
@@ -0,0 +1,353 @@
+//! Deterministic simulation of the limiters, to compare them on the same traffic.
+//!
+//! A [`Trace`] of arrivals, synthetic or recorded, is replayed through a limiter
+//! reading the time from a [`ManualClock`], so that a simulation of an hour of
+//! traffic runs in milliseconds and always gives the same [`Report`]. Comparing the
+//! reports of several algorithms on the traffic of a service shows which one admits
+//! what, and how bursty the admitted traffic is.
+//!
+//! Queuing limiters, i.e. the queuing leaky bucket, block on the real time and
+//! cannot be simulated; the leaky bucket meter can.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use devkit_rl::{simulate::{self, Trace}, TokenBucket};
+//!
+//! // 5 bursts of 20 requests, one per second
+//! let trace = Trace::bursts(20, Duration::from_secs(1), Duration::from_secs(5));
+//! let resolution = Duration::from_millis(100);
+//!
+//! let bucket = simulate::run(&trace, resolution, |clock| {
+//!     TokenBucket::with_clock(10, 1, Some(Duration::from_millis(100)), clock)
+//! });
+//! // a deeper bucket lets the first burst through whole
+//! let deep = simulate::run(&trace, resolution, |clock| {
+//!     TokenBucket::with_clock(20, 10, Some(Duration::from_secs(1)), clock)
+//! });
+//!
+//! assert_eq!(bucket.admitted, 50);
+//! assert_eq!(deep.admitted, 60);
+//! ```
+
+use std::{sync::Arc, time::Duration};
+
+use crate::{Clock, ManualClock, RateLimiter};
+
+/// A request arriving at a limiter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arrival {
+    /// When the request arrives, since the start of the trace.
+    pub at: Duration,
+    /// The number of requests it counts for, see [`RateLimiter::allow_n`].
+    pub weight: u64,
+}
+
+/// The arrivals replayed by a simulation, in chronological order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    arrivals: Vec<Arrival>,
+}
+
+impl Trace {
+    /// Creates a trace from recorded arrivals, which are sorted by time.
+    pub fn new(mut arrivals: Vec<Arrival>) -> Self {
+        arrivals.sort_by_key(|a| a.at);
+        Self { arrivals }
+    }
+
+    /// Creates a trace of requests of weight 1 arriving at `timestamps`.
+    pub fn from_timestamps(timestamps: impl IntoIterator<Item = Duration>) -> Self {
+        Self::new(
+            timestamps
+                .into_iter()
+                .map(|at| Arrival { at, weight: 1 })
+                .collect(),
+        )
+    }
+
+    /// Creates a trace of `rate` requests per second, evenly spaced, for `duration`.
+    pub fn constant(rate: f64, duration: Duration) -> Self {
+        if rate.is_nan() || rate <= 0.0 {
+            return Self::default();
+        }
+        let gap = 1.0 / rate;
+        let count = (duration.as_secs_f64() * rate).ceil() as u64;
+        Self::from_timestamps((0..count).map(|i| Duration::from_secs_f64(i as f64 * gap)))
+    }
+
+    /// Creates a trace of `size` simultaneous requests every `period`, for `duration`.
+    pub fn bursts(size: u64, period: Duration, duration: Duration) -> Self {
+        let period = period.max(Duration::from_nanos(1));
+        let count = duration.as_nanos().div_ceil(period.as_nanos());
+        let starts = (0..count).map(|i| period.saturating_mul(i as u32));
+        Self::from_timestamps(starts.flat_map(|at| (0..size).map(move |_| at)))
+    }
+
+    /// Creates a trace of requests arriving at random, `rate` per second on average,
+    /// for `duration`.
+    ///
+    /// The arrivals are a Poisson process, the usual model of independent clients.
+    /// The same `seed` always gives the same trace.
+    pub fn poisson(rate: f64, duration: Duration, seed: u64) -> Self {
+        if rate.is_nan() || rate <= 0.0 {
+            return Self::default();
+        }
+        let mut rng = SplitMix64(seed);
+        let mut at = 0.0;
+        let end = duration.as_secs_f64();
+        let mut timestamps = Vec::new();
+        loop {
+            // exponentially distributed gaps between arrivals
+            at += -rng.next_unit().ln() / rate;
+            if at >= end {
+                break;
+            }
+            timestamps.push(Duration::from_secs_f64(at));
+        }
+        Self::from_timestamps(timestamps)
+    }
+
+    /// Returns the arrivals, in chronological order.
+    pub fn arrivals(&self) -> &[Arrival] {
+        &self.arrivals
+    }
+
+    /// Returns the total weight of the arrivals.
+    pub fn total(&self) -> u64 {
+        self.arrivals.iter().map(|a| a.weight).sum()
+    }
+}
+
+/// The requests admitted and denied during an interval of a simulation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Interval {
+    /// When the interval starts, since the start of the trace.
+    pub start: Duration,
+    /// The weight of the requests admitted.
+    pub admitted: u64,
+    /// The weight of the requests denied.
+    pub denied: u64,
+}
+
+/// The outcome of replaying a [`Trace`] through a limiter.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    /// The weight of the requests admitted.
+    pub admitted: u64,
+    /// The weight of the requests denied.
+    pub denied: u64,
+    /// The admitted and denied requests over time, one entry per resolution
+    /// interval, from the start of the trace to its last arrival.
+    pub series: Vec<Interval>,
+}
+
+impl Report {
+    /// Returns the share of the requests admitted, between 0.0 and 1.0, or 1.0 for
+    /// an empty trace.
+    pub fn admit_ratio(&self) -> f64 {
+        let total = self.admitted + self.denied;
+        if total == 0 {
+            return 1.0;
+        }
+        self.admitted as f64 / total as f64
+    }
+
+    /// Returns the most requests admitted during an interval.
+    pub fn peak_admitted(&self) -> u64 {
+        self.series.iter().map(|i| i.admitted).max().unwrap_or(0)
+    }
+
+    /// Returns the mean of the requests admitted per interval.
+    pub fn mean_admitted(&self) -> f64 {
+        if self.series.is_empty() {
+            return 0.0;
+        }
+        self.admitted as f64 / self.series.len() as f64
+    }
+
+    /// Returns how bursty the admitted traffic is, as the coefficient of variation
+    /// of the requests admitted per interval.
+    ///
+    /// It is 0.0 for a perfectly smooth traffic, and grows as the admitted requests
+    /// concentrate in fewer intervals.
+    pub fn burstiness(&self) -> f64 {
+        let mean = self.mean_admitted();
+        if mean == 0.0 {
+            return 0.0;
+        }
+        let variance = self
+            .series
+            .iter()
+            .map(|i| (i.admitted as f64 - mean).powi(2))
+            .sum::<f64>()
+            / self.series.len() as f64;
+        variance.sqrt() / mean
+    }
+}
+
+/// Replays `trace` through the limiter built by `build`.
+///
+/// # Arguments
+///
+/// * `trace` - The arrivals to replay.
+/// * `resolution` - The length of the intervals of [`Report::series`], at least a nanosecond.
+/// * `build` - Builds the limiter on the clock of the simulation, which starts at 0
+///   and is moved to every arrival in turn.
+pub fn run<L, F>(trace: &Trace, resolution: Duration, build: F) -> Report
+where
+    L: RateLimiter,
+    F: FnOnce(Arc<dyn Clock>) -> L,
+{
+    let resolution = resolution.max(Duration::from_nanos(1));
+    let clock = Arc::new(ManualClock::new());
+    let limiter = build(clock.clone());
+
+    let mut report = Report::default();
+    let mut now = Duration::ZERO;
+    for arrival in trace.arrivals() {
+        clock.advance(arrival.at - now);
+        now = arrival.at;
+
+        let index = (now.as_nanos() / resolution.as_nanos()) as usize;
+        while report.series.len() <= index {
+            let start = resolution.saturating_mul(report.series.len() as u32);
+            report.series.push(Interval {
+                start,
+                ..Interval::default()
+            });
+        }
+
+        let interval = &mut report.series[index];
+        if limiter.allow_n(arrival.weight) {
+            interval.admitted += arrival.weight;
+            report.admitted += arrival.weight;
+        } else {
+            interval.denied += arrival.weight;
+            report.denied += arrival.weight;
+        }
+    }
+    report
+}
+
+/// A small, seedable pseudo-random generator, enough to draw traces.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `(0, 1]`.
+    fn next_unit(&mut self) -> f64 {
+        ((self.next() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(all(
+        feature = "leaky-bucket",
+        feature = "sliding-log",
+        feature = "sliding-window",
+        feature = "token-bucket"
+    ))]
+    use crate::{LeakyBucket, SlidingWindowCount, SlidingWindowLog, TokenBucket};
+
+    #[test]
+    fn traces_should_be_generated_deterministically() {
+        let trace = Trace::constant(4.0, Duration::from_secs(1));
+        assert_eq!(trace.arrivals().len(), 4);
+        assert_eq!(trace.arrivals()[1].at, Duration::from_millis(250));
+
+        let trace = Trace::bursts(3, Duration::from_secs(1), Duration::from_millis(2500));
+        assert_eq!(trace.total(), 9);
+        assert_eq!(trace.arrivals()[8].at, Duration::from_secs(2));
+
+        let trace = Trace::poisson(100.0, Duration::from_secs(10), 42);
+        assert_eq!(trace, Trace::poisson(100.0, Duration::from_secs(10), 42));
+        assert_ne!(trace, Trace::poisson(100.0, Duration::from_secs(10), 43));
+        assert!((900..1100).contains(&trace.arrivals().len()));
+
+        let trace = Trace::new(vec![
+            Arrival {
+                at: Duration::from_secs(2),
+                weight: 5,
+            },
+            Arrival {
+                at: Duration::from_secs(1),
+                weight: 1,
+            },
+        ]);
+        assert_eq!(trace.arrivals()[0].weight, 1);
+        assert_eq!(
+            Trace::constant(0.0, Duration::from_secs(1)),
+            Trace::default()
+        );
+    }
+
+    #[cfg(all(
+        feature = "leaky-bucket",
+        feature = "sliding-log",
+        feature = "sliding-window",
+        feature = "token-bucket"
+    ))]
+    #[test]
+    fn simulation_should_compare_algorithms() {
+        const RESOLUTION: Duration = Duration::from_millis(500);
+
+        // bursts of 20 requests every 2 seconds, for a limit of 10 requests per second
+        let trace = Trace::bursts(20, Duration::from_secs(2), Duration::from_secs(10));
+        let second = Some(Duration::from_secs(1));
+
+        let bucket = run(&trace, RESOLUTION, |clock| {
+            TokenBucket::with_clock(10, 10, second, clock)
+        });
+        assert_eq!((bucket.admitted, bucket.denied), (50, 50));
+        assert_eq!(bucket.series.len(), 17);
+        assert_eq!(bucket.peak_admitted(), 10);
+        assert_eq!(
+            bucket.series[4],
+            Interval {
+                start: Duration::from_secs(2),
+                admitted: 10,
+                denied: 10,
+            }
+        );
+        assert_eq!(bucket.admit_ratio(), 0.5);
+
+        let log = run(&trace, RESOLUTION, |clock| {
+            SlidingWindowLog::with_clock(10, second, clock)
+        });
+        let count = run(&trace, RESOLUTION, |clock| {
+            SlidingWindowCount::with_clock(10, Duration::from_secs(1), 10, clock)
+        });
+        assert_eq!(log.admitted, 50);
+        assert_eq!(count.admitted, 50);
+
+        // a meter admitting one request every 100ms smooths the bursts out
+        let meter = run(&trace, RESOLUTION, |clock| {
+            LeakyBucket::meter_with_clock(1, 1, Some(Duration::from_millis(100)), clock)
+        });
+        assert_eq!(meter.admitted, 5);
+        assert!(meter.burstiness() > 0.0);
+        assert!((bucket.burstiness() - meter.burstiness()).abs() < 1e-9);
+
+        // evenly spaced requests under the limit are all admitted, smoothly
+        let trace = Trace::constant(5.0, Duration::from_secs(10));
+        let smooth = run(&trace, Duration::from_secs(1), |clock| {
+            TokenBucket::with_clock(10, 10, second, clock)
+        });
+        assert_eq!(smooth.admit_ratio(), 1.0);
+        assert_eq!(smooth.burstiness(), 0.0);
+
+        assert_eq!(Report::default().admit_ratio(), 1.0);
+        assert_eq!(Report::default().burstiness(), 0.0);
+    }
+}
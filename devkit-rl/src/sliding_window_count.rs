@@ -1,7 +1,13 @@
-use std::{
-    sync::{Arc, Mutex},
-    time::{Duration, Instant},
+use alloc::{sync::Arc, vec::Vec};
+use core::time::Duration;
+
+use crate::{
+    clock::{At, SharedClock},
+    sync::{Mutex, MutexExt},
+    BucketRing, Clock, Error,
 };
+#[cfg(feature = "std")]
+use crate::{sync::arc_size, Quota};
 
 /// A sliding window rate limiter based on counting requests over a specified time window.
 ///
@@ -28,48 +34,258 @@ pub struct SlidingWindowCount {
     inner: Arc<Mutex<SlidingWindowCountInner>>,
 }
 
+/// The requests counted in a bucket of a [`SlidingWindowCount`].
+///
+/// The boundaries are timestamps of the clock of the limiter, like
+/// [`Clock::now`]. A bucket holds the requests allowed from `start`, inclusive, to
+/// `end`, exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowBucket {
+    /// When the bucket started, or 0 if before the clock started.
+    pub start: Duration,
+    /// When the bucket ends, which is in the future for the current bucket.
+    pub end: Duration,
+    /// The number of requests allowed in the bucket.
+    pub count: u64,
+}
+
 /// Inner structure that holds the state of the sliding window.
 ///
 /// This structure tracks the number of requests in each bucket, the total size of the window,
 /// and the interval for each bucket.
 #[derive(Debug)]
 struct SlidingWindowCountInner {
-    /// Vector to store request counts for each bucket.
-    buckets: Vec<u64>,
+    /// The request counts of each bucket.
+    buckets: BucketRing<u64>,
+    /// The sum of all buckets, kept up to date incrementally.
+    total: u64,
     /// Maximum number of requests allowed within the window.
     win_size: u64,
-    /// Duration of each bucket.
-    bucket_interval: Duration,
-    /// The time when the buckets were last updated.
-    last_update: Instant,
-    /// The index of the most recently updated bucket.
-    last_index: usize,
+    /// The source of time.
+    clock: SharedClock,
 }
 
 impl SlidingWindowCount {
+    /// The shortest bucket interval [`SlidingWindowCount::validate`] accepts.
+    ///
+    /// Clocks do not tick every nanosecond everywhere: Windows counts in steps of
+    /// 100ns, and virtualized hosts are often coarser. Buckets shorter than a tick
+    /// are skipped over without ever counting a request, and only cost memory.
+    pub const MIN_BUCKET_INTERVAL: Duration = Duration::from_micros(1);
+
     /// Creates a new `SlidingWindowCount` rate limiter.
     ///
     /// # Arguments
     ///
     /// * `win_size` - The maximum number of requests allowed within the sliding window.
     /// * `interval` - The total duration of the sliding window.
-    /// * `bucket_count` - The number of buckets to divide the sliding window into.
+    /// * `bucket_count` - The number of buckets to divide the sliding window into. A count
+    ///   of 0 is treated as 1, and buckets never get shorter than a nanosecond.
     ///
     /// # Returns
     ///
     /// A new `SlidingWindowCount` instance.
+    #[cfg(feature = "std")]
     pub fn new(win_size: u64, interval: Duration, bucket_count: u64) -> Self {
+        Self::from_clock(win_size, interval, bucket_count, false, SharedClock::std())
+    }
+
+    /// Creates a new `SlidingWindowCount` rate limiter whose buckets are aligned to
+    /// the unix epoch.
+    ///
+    /// The window reads the time from a [`SystemClock`](crate::SystemClock), and its
+    /// buckets start at whole multiples of the bucket interval since the unix epoch,
+    /// so that windows created in different processes slide together, and
+    /// [`SlidingWindowCount::histogram`] returns unix times.
+    ///
+    /// # Arguments
+    ///
+    /// * `win_size` - The maximum number of requests allowed within the sliding window.
+    /// * `interval` - The total duration of the sliding window.
+    /// * `bucket_count` - The number of buckets to divide the sliding window into.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::SlidingWindowCount;
+    ///
+    /// let swc = SlidingWindowCount::epoch_aligned(10, Duration::from_secs(60), 6);
+    ///
+    /// assert!(swc.allow());
+    /// assert_eq!(swc.histogram()[5].start.as_secs() % 10, 0);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn epoch_aligned(win_size: u64, interval: Duration, bucket_count: u64) -> Self {
+        let clock = SharedClock::new(Arc::new(crate::SystemClock::new()));
+        Self::from_clock(win_size, interval, bucket_count, true, clock)
+    }
+
+    /// Creates a new `SlidingWindowCount` rate limiter allowing `quota`.
+    ///
+    /// The window allows [`Quota::burst`] requests and lasts [`Quota::burst_period`].
+    ///
+    /// # Arguments
+    ///
+    /// * `quota` - The requests to allow.
+    /// * `bucket_count` - The number of buckets to divide the sliding window into.
+    #[cfg(feature = "std")]
+    pub fn from_quota(quota: Quota, bucket_count: u64) -> Self {
+        Self::new(quota.burst(), quota.burst_period(), bucket_count)
+    }
+
+    /// Creates a new `SlidingWindowCount` rate limiter with as many buckets as it
+    /// takes to stay within `target_error` of an exact sliding window.
+    ///
+    /// See [`SlidingWindowCount::bucket_count_for`] for how the bucket count is picked.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidConfig`] if `target_error` is not within (0, 1], or if it
+    /// takes buckets shorter than [`SlidingWindowCount::MIN_BUCKET_INTERVAL`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::SlidingWindowCount;
+    ///
+    /// // within 5% of an exact window of one minute: 20 buckets of 3 seconds
+    /// let swc = SlidingWindowCount::with_resolution(100, Duration::from_secs(60), 0.05).unwrap();
+    /// assert_eq!(swc.histogram().len(), 20);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn with_resolution(
+        win_size: u64,
+        interval: Duration,
+        target_error: f64,
+    ) -> Result<Self, Error> {
+        let bucket_count = Self::bucket_count_for(interval, target_error)?;
+        Ok(Self::new(win_size, interval, bucket_count))
+    }
+
+    /// Returns the number of buckets that keeps a window of `interval` within
+    /// `target_error` of an exact sliding window.
+    ///
+    /// The requests of a bucket leave the window together, when the whole bucket
+    /// does, up to a bucket interval earlier than they would leave an exact sliding
+    /// window: the window really spans between `interval` less one bucket interval
+    /// and `interval`. That is a relative error of `1 / bucket_count` on the length
+    /// of the window, so the bucket count is `ceil(1 / target_error)`.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidConfig`] if `target_error` is not within (0, 1], or if it
+    /// takes buckets shorter than [`SlidingWindowCount::MIN_BUCKET_INTERVAL`].
+    pub fn bucket_count_for(interval: Duration, target_error: f64) -> Result<u64, Error> {
+        if !(target_error > 0.0 && target_error <= 1.0) {
+            return Err(Error::InvalidConfig("target_error must be within (0, 1]"));
+        }
+        // `ceil` is not in `core`: round up by hand, saturating for tiny errors
+        let exact = 1.0 / target_error;
+        let mut bucket_count = exact as u64;
+        if (bucket_count as f64) < exact {
+            bucket_count = bucket_count.saturating_add(1);
+        }
+        Self::validate(interval, bucket_count)?;
+        Ok(bucket_count)
+    }
+
+    /// Checks that a window of `interval` divided into `bucket_count` buckets counts
+    /// every request.
+    ///
+    /// [`SlidingWindowCount::new`] accepts any parameters, and makes the best of the
+    /// invalid ones, e.g. by using 1 bucket instead of 0. This reports them instead.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidConfig`] if `bucket_count` is zero, or if the buckets are
+    /// shorter than [`SlidingWindowCount::MIN_BUCKET_INTERVAL`].
+    pub fn validate(interval: Duration, bucket_count: u64) -> Result<(), Error> {
+        if bucket_count == 0 {
+            return Err(Error::InvalidConfig("bucket_count must not be zero"));
+        }
+        if interval.as_nanos() / u128::from(bucket_count) < Self::MIN_BUCKET_INTERVAL.as_nanos() {
+            return Err(Error::InvalidConfig(
+                "bucket interval must not be shorter than the clock resolution",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Creates a new `SlidingWindowCount` rate limiter reading the time from `clock`.
+    ///
+    /// This is how a sliding window count is created without the `std` feature, and
+    /// how tests control the passing of time.
+    ///
+    /// # Arguments
+    ///
+    /// * `win_size` - The maximum number of requests allowed within the sliding window.
+    /// * `interval` - The total duration of the sliding window.
+    /// * `bucket_count` - The number of buckets to divide the sliding window into.
+    /// * `clock` - The source of time of the window.
+    pub fn with_clock(
+        win_size: u64,
+        interval: Duration,
+        bucket_count: u64,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self::from_clock(
+            win_size,
+            interval,
+            bucket_count,
+            false,
+            SharedClock::new(clock),
+        )
+    }
+
+    /// Creates the window, its buckets starting at whole multiples of the bucket
+    /// interval since the origin of `clock` if `aligned`, or since now otherwise.
+    fn from_clock(
+        win_size: u64,
+        interval: Duration,
+        bucket_count: u64,
+        aligned: bool,
+        mut clock: SharedClock,
+    ) -> Self {
+        let now = clock.now();
+        let origin = if aligned { Duration::ZERO } else { now };
+        let mut buckets = BucketRing::new(interval, bucket_count, origin);
+        buckets.advance(now, |_| {});
+        let inner = SlidingWindowCountInner {
+            buckets,
+            total: 0,
+            win_size,
+            clock,
+        };
+
         Self {
-            inner: Arc::new(Mutex::new(SlidingWindowCountInner {
-                buckets: vec![0; bucket_count as usize],
-                win_size,
-                bucket_interval: interval.div_f64(bucket_count as f64),
-                last_update: Instant::now(),
-                last_index: 0,
-            })),
+            inner: Arc::new(Mutex::new(inner)),
         }
     }
 
+    /// Updates the parameters of the rate limiter without losing its current state.
+    ///
+    /// If the number of buckets is unchanged, every bucket keeps its count. Otherwise the
+    /// requests of the current window are moved into the newest bucket, so they keep
+    /// counting against the limit for a full window. From then on, the buckets start
+    /// at whole multiples of the new bucket interval since the current bucket started.
+    ///
+    /// # Arguments
+    ///
+    /// * `win_size` - The maximum number of requests allowed within the sliding window.
+    /// * `interval` - The total duration of the sliding window.
+    /// * `bucket_count` - The number of buckets to divide the sliding window into.
+    pub fn reconfigure(&self, win_size: u64, interval: Duration, bucket_count: u64) {
+        let mut inner = self.inner.lock_unpoisoned();
+
+        inner.update_buckets();
+        inner
+            .buckets
+            .reconfigure(interval, bucket_count, |newest, count| *newest += count);
+        inner.win_size = win_size;
+    }
+
     /// Attempts to allow a single request.
     ///
     /// This is a convenience method that is equivalent to calling `allow_n(1)`.
@@ -91,67 +307,216 @@ impl SlidingWindowCount {
     ///
     /// `true` if the requests are allowed, `false` if they exceed the limit.
     pub fn allow_n(&self, n: u64) -> bool {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock_unpoisoned();
 
         // Update the buckets based on the current time.
         inner.update_buckets();
 
-        // Check if adding the new requests would exceed the window size.
-        if inner.total_count() + n <= inner.win_size {
-            inner.add_requests(n);
-            true
+        inner.try_accept(n)
+    }
+
+    /// Checks if a single request is allowed at the time `now`.
+    ///
+    /// This is a convenience method for `allow_n_at(1, now)`.
+    pub fn allow_at(&self, now: Duration) -> bool {
+        self.allow_n_at(1, now)
+    }
+
+    /// Checks if `n` requests are allowed at the time `now`, instead of the time read
+    /// from the clock of the window.
+    ///
+    /// `now` is on the timeline of the clock, see [`TokenBucket::allow_n_at`](crate::TokenBucket::allow_n_at).
+    pub fn allow_n_at(&self, n: u64, now: Duration) -> bool {
+        self.allow_n_at_checked(n, At::Clamped(now))
+            .unwrap_or(false)
+    }
+
+    /// Like [`allow_n_at`](Self::allow_n_at), but fails with
+    /// [`Error::ClockWentBackwards`] if `now` is older than the latest time seen by
+    /// the window, e.g. to detect the out-of-order entries of a replayed log.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::RateLimited`] if the requests are denied, or [`Error::ClockWentBackwards`].
+    pub fn try_allow_n_at(&self, n: u64, now: Duration) -> Result<(), Error> {
+        if self.allow_n_at_checked(n, At::Strict(now))? {
+            Ok(())
         } else {
-            false
+            Err(Error::RateLimited)
         }
     }
-}
 
-impl SlidingWindowCountInner {
-    /// Updates the state of the buckets to account for the time that has passed since the last update.
+    /// Takes `n` requests at the time `at`.
+    fn allow_n_at_checked(&self, n: u64, at: At) -> Result<bool, Error> {
+        let mut inner = self.inner.lock_unpoisoned();
+
+        let now = inner.clock.observe_at(at)?;
+        inner.update_buckets_to(now);
+
+        Ok(inner.try_accept(n))
+    }
+
+    /// Estimates how long to wait until `n` requests are allowed.
+    ///
+    /// The estimate assumes no other request is made in the meantime.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of requests to allow.
     ///
-    /// This function calculates how many buckets have passed and clears the old buckets that
-    /// are outside of the current window.
-    fn update_buckets(&mut self) {
-        let now = Instant::now();
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if the requests are allowed now, the time until enough buckets
+    /// have been cleared otherwise, or `Duration::MAX` if `n` exceeds the size of the
+    /// window and will never be allowed.
+    pub fn next_available(&self, n: u64) -> Duration {
+        let mut inner = self.inner.lock_unpoisoned();
+
+        let now = inner.update_buckets();
 
-        // Calculate how many buckets have passed since the last update.
-        let bucket_passed = self.bucket_passed(now);
+        let excess = inner
+            .total_count()
+            .saturating_add(n)
+            .saturating_sub(inner.win_size);
+        if excess == 0 {
+            return Duration::ZERO;
+        }
+        if n > inner.win_size {
+            return Duration::MAX;
+        }
 
-        // Clear the contents of the passed buckets.
-        for i in 0..bucket_passed {
-            let idx = (i + self.last_index) % self.buckets.len();
-            self.buckets[idx] = 0;
+        // the oldest bucket is cleared when the next one starts, then one more per bucket
+        // interval, ending with the current bucket
+        let len = inner.buckets.len();
+        let interval = inner.buckets.bucket_interval();
+        let next_bucket = inner.buckets.current_end().saturating_sub(now);
+        let mut freed = 0;
+        for (i, count) in inner.buckets.iter().enumerate() {
+            freed += count;
+            if freed >= excess {
+                return next_bucket.saturating_add(interval.saturating_mul(i as u32));
+            }
         }
+        next_bucket.saturating_add(interval.saturating_mul(len as u32 - 1))
+    }
+
+    /// Returns how many requests the sliding window still allows now.
+    pub fn remaining(&self) -> u64 {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.update_buckets();
+        inner.win_size.saturating_sub(inner.total_count())
+    }
+
+    /// Returns the requests counted in every bucket of the current window, oldest first.
+    ///
+    /// This shows how the requests are distributed inside the window, e.g. a burst
+    /// at its start, where the total only shows how full the window is.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::{sync::Arc, time::Duration};
+    /// use devkit_rl::{ManualClock, SlidingWindowCount};
+    ///
+    /// let clock = Arc::new(ManualClock::new());
+    /// clock.advance(Duration::from_secs(10));
+    /// let swc = SlidingWindowCount::with_clock(10, Duration::from_secs(3), 3, clock.clone());
+    ///
+    /// swc.allow_n(4);
+    /// clock.advance(Duration::from_secs(2));
+    /// swc.allow();
+    ///
+    /// let counts: Vec<_> = swc.histogram().iter().map(|b| b.count).collect();
+    /// assert_eq!(counts, [4, 0, 1]);
+    /// assert_eq!(swc.histogram()[2].start, Duration::from_secs(12));
+    /// ```
+    pub fn histogram(&self) -> Vec<WindowBucket> {
+        let mut inner = self.inner.lock_unpoisoned();
+
+        inner.update_buckets();
 
-        // Update the index and time for the most recent bucket.
-        self.last_index = (self.last_index + bucket_passed) % self.buckets.len();
-        self.last_update = now;
+        let len = inner.buckets.len();
+        let interval = inner.buckets.bucket_interval();
+        let current_end = inner.buckets.current_end();
+        inner
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| {
+                // the newest bucket is the current one
+                let age = u32::try_from(len - 1 - i).unwrap_or(u32::MAX);
+                let end = current_end.saturating_sub(interval.saturating_mul(age));
+                WindowBucket {
+                    start: end.saturating_sub(interval),
+                    end,
+                    count,
+                }
+            })
+            .collect()
     }
 
-    /// Calculates how many buckets have passed since the last update.
+    /// Gives back `n` requests previously allowed by [`SlidingWindowCount::allow_n`].
+    ///
+    /// This undoes an admission that turned out not to be used, e.g. because
+    /// another limiter denied the same request. Refunding more than was allowed
+    /// never takes the current bucket below 0.
     ///
     /// # Arguments
     ///
-    /// * `now` - The current timestamp.
+    /// * `n` - The number of requests to give back.
+    pub fn refund(&self, n: u64) {
+        let mut inner = self.inner.lock_unpoisoned();
+        let n = n.min(*inner.buckets.current());
+        *inner.buckets.current_mut() -= n;
+        inner.total -= n;
+    }
+
+    /// Estimates the memory held by this limiter, in bytes.
+    #[cfg(feature = "std")]
+    pub(crate) fn mem_size(&self) -> usize {
+        let buckets = self.inner.lock_unpoisoned().buckets.len();
+        arc_size::<Mutex<SlidingWindowCountInner>>() + buckets * size_of::<u64>()
+    }
+}
+
+impl SlidingWindowCountInner {
+    /// Updates the state of the buckets to account for the time that has passed since the last update.
+    ///
+    /// Buckets start at whole multiples of the bucket interval after the origin, so
+    /// which bucket holds a request is a function of its time alone, no matter how
+    /// often or how late the buckets are updated, e.g. after the process was
+    /// suspended. Every bucket started since the current one is cleared, as it still
+    /// holds the requests of a previous window.
     ///
     /// # Returns
     ///
-    /// The number of buckets that have passed since `last_update`.
-    fn bucket_passed(&self, now: Instant) -> usize {
-        let elapsed = now - self.last_update;
-        let count = elapsed.div_duration_f64(self.bucket_interval) as usize;
+    /// The current timestamp.
+    fn update_buckets(&mut self) -> Duration {
+        let now = self.clock.now();
+        self.update_buckets_to(now);
+        now
+    }
+
+    /// Updates the state of the buckets to `now`, see [`SlidingWindowCountInner::update_buckets`].
+    fn update_buckets_to(&mut self, now: Duration) {
+        let total = &mut self.total;
+        self.buckets.advance(now, |count| *total -= count);
+    }
 
-        // If more buckets have passed than the total number of buckets, clear all buckets.
-        if count > self.buckets.len() {
-            self.buckets.len()
+    /// Adds `n` requests to the current bucket if they fit in the window.
+    fn try_accept(&mut self, n: u64) -> bool {
+        // Check if adding the new requests would exceed the window size.
+        if self.total_count().saturating_add(n) <= self.win_size {
+            self.add_requests(n);
+            true
         } else {
-            count
+            false
         }
     }
 
     /// Returns the total number of requests in the current sliding window.
     fn total_count(&self) -> u64 {
-        self.buckets.iter().sum()
+        self.total
     }
 
     /// Adds the specified number of requests to the current bucket.
@@ -160,11 +525,12 @@ impl SlidingWindowCountInner {
     ///
     /// * `n` - The number of requests to add.
     fn add_requests(&mut self, n: u64) {
-        self.buckets[self.last_index] += n;
+        *self.buckets.current_mut() += n;
+        self.total += n;
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
@@ -183,16 +549,256 @@ mod tests {
 
         // No more requests should be allowed in the current window.
         assert!(!swc.allow());
-        assert_eq!(SIZE, swc.inner.lock().unwrap().total_count());
+        assert_eq!(SIZE, swc.inner.lock_unpoisoned().total_count());
 
-        // After sleeping for half of the window interval, some older tokens should be removed,
-        // allowing new requests.
-        std::thread::sleep(WINDOW_INTERVAL / 2);
+        // After sleeping for the window interval, the bucket of the first requests has slid
+        // out of the window, allowing new requests.
+        std::thread::sleep(WINDOW_INTERVAL);
         assert!(swc.allow());
 
         // After sleeping for a long time, all buckets should be cleared, allowing new requests.
         std::thread::sleep(WINDOW_INTERVAL * 2);
         assert!(swc.allow());
-        assert_eq!(1, swc.inner.lock().unwrap().total_count());
+        assert_eq!(1, swc.inner.lock_unpoisoned().total_count());
+    }
+
+    #[test]
+    fn sliding_window_count_running_total_should_match_buckets() {
+        const BUCKET_COUNT: u64 = 4;
+        const WINDOW_INTERVAL: Duration = Duration::from_millis(8);
+
+        let swc = SlidingWindowCount::new(100, WINDOW_INTERVAL, BUCKET_COUNT);
+
+        for _ in 0..BUCKET_COUNT * 3 {
+            assert!(swc.allow_n(3));
+            std::thread::sleep(WINDOW_INTERVAL / BUCKET_COUNT as u32);
+
+            let inner = swc.inner.lock_unpoisoned();
+            assert_eq!(inner.total_count(), inner.buckets.iter().sum::<u64>());
+        }
+    }
+
+    #[test]
+    fn sliding_window_count_should_guard_degenerate_buckets() {
+        let clock = Arc::new(crate::ManualClock::new());
+
+        // no buckets behaves like a single bucket
+        let swc = SlidingWindowCount::with_clock(1, Duration::from_secs(1), 0, clock.clone());
+        assert!(swc.allow());
+        assert!(!swc.allow());
+        assert_eq!(swc.next_available(1), Duration::from_secs(1));
+        swc.reconfigure(2, Duration::from_secs(1), 0);
+        assert!(swc.allow());
+
+        // buckets shorter than a nanosecond last a nanosecond
+        let swc = SlidingWindowCount::with_clock(1, Duration::from_nanos(3), 10, clock.clone());
+        assert!(swc.allow());
+        assert!(!swc.allow());
+        clock.advance(Duration::from_nanos(10));
+        assert!(swc.allow());
+    }
+
+    #[test]
+    fn sliding_window_count_should_pick_bucket_count_from_target_error() {
+        const SECOND: Duration = Duration::from_secs(1);
+
+        assert_eq!(
+            SlidingWindowCount::bucket_count_for(SECOND, 0.1).unwrap(),
+            10
+        );
+        assert_eq!(
+            SlidingWindowCount::bucket_count_for(SECOND, 0.03).unwrap(),
+            34
+        );
+        assert_eq!(
+            SlidingWindowCount::bucket_count_for(SECOND, 1.0).unwrap(),
+            1
+        );
+        for target_error in [0.0, -0.1, 1.5, f64::NAN] {
+            assert!(matches!(
+                SlidingWindowCount::bucket_count_for(SECOND, target_error),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+
+        // buckets shorter than the clock resolution never count a request
+        assert!(SlidingWindowCount::validate(Duration::from_millis(1), 1_000).is_ok());
+        assert!(matches!(
+            SlidingWindowCount::validate(Duration::from_millis(1), 1_001),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            SlidingWindowCount::bucket_count_for(Duration::from_micros(10), 0.01),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(SlidingWindowCount::with_resolution(10, Duration::from_nanos(100), 0.5).is_err());
+
+        // a request leaves the window at most a bucket interval, the target error, early
+        let clock = Arc::new(crate::ManualClock::new());
+        let bucket_count = SlidingWindowCount::bucket_count_for(SECOND, 0.1).unwrap();
+        let swc = SlidingWindowCount::with_clock(1, SECOND, bucket_count, clock.clone());
+        clock.advance(Duration::from_millis(99));
+        assert!(swc.allow());
+        clock.advance(Duration::from_millis(900));
+        assert!(!swc.allow());
+        assert_eq!(swc.next_available(1), Duration::from_millis(1));
+        clock.advance(Duration::from_millis(1));
+        assert!(swc.allow());
+    }
+
+    #[test]
+    fn sliding_window_count_should_rotate_on_frequent_updates() {
+        let clock = Arc::new(crate::ManualClock::new());
+        let swc = SlidingWindowCount::with_clock(1, Duration::from_millis(10), 10, clock.clone());
+
+        // updates more often than once per bucket must not hold the window still
+        assert!(swc.allow());
+        for _ in 0..20 {
+            clock.advance(Duration::from_micros(600));
+            swc.allow();
+        }
+        clock.advance(Duration::from_millis(10));
+        assert!(swc.allow());
+    }
+
+    #[test]
+    fn sliding_window_count_histogram_should_follow_the_window() {
+        let clock = Arc::new(crate::ManualClock::new());
+        let swc = SlidingWindowCount::with_clock(100, Duration::from_secs(4), 4, clock.clone());
+
+        assert!(swc.allow_n(2));
+        clock.advance(Duration::from_millis(1500));
+        assert!(swc.allow_n(3));
+
+        // buckets before the clock started start at 0
+        let secs = |s| Duration::from_secs(s);
+        let bucket = |start, end, count| WindowBucket {
+            start: secs(start),
+            end: secs(end),
+            count,
+        };
+        assert_eq!(
+            swc.histogram(),
+            [
+                bucket(0, 0, 0),
+                bucket(0, 0, 0),
+                bucket(0, 1, 2),
+                bucket(1, 2, 3),
+            ]
+        );
+
+        // the oldest buckets slide out of the window
+        clock.advance(secs(3));
+        assert_eq!(
+            swc.histogram(),
+            [
+                bucket(1, 2, 3),
+                bucket(2, 3, 0),
+                bucket(3, 4, 0),
+                bucket(4, 5, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn sliding_window_count_should_keep_buckets_aligned_across_long_pauses() {
+        const BUCKET: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(crate::ManualClock::new());
+        clock.advance(Duration::from_millis(300));
+        let swc = SlidingWindowCount::with_clock(10, BUCKET * 4, 4, clock.clone());
+
+        // a suspend of thousands of windows, ending mid-bucket
+        assert!(swc.allow_n(10));
+        clock.advance(BUCKET * 40_000 + Duration::from_millis(700));
+        let current = *swc.histogram().last().unwrap();
+        assert_eq!(current.start, Duration::from_millis(300) + BUCKET * 40_000);
+        assert_eq!(current.end - current.start, BUCKET);
+        assert!(swc.allow_n(10));
+
+        // bursts of updates after the pause do not move the boundaries
+        let starts: Vec<_> = swc.histogram().iter().map(|b| b.start).collect();
+        clock.advance(Duration::from_millis(299));
+        assert!(!swc.allow());
+        assert_eq!(swc.next_available(1), Duration::from_millis(1) + BUCKET * 3);
+        let now: Vec<_> = swc.histogram().iter().map(|b| b.start).collect();
+        assert_eq!(starts, now);
+
+        // a pause ending right on a boundary clears the whole window
+        clock.advance(Duration::from_millis(1) + BUCKET * 3);
+        assert!(swc.allow_n(10));
+    }
+
+    #[test]
+    fn sliding_window_count_epoch_aligned_should_index_buckets_by_time() {
+        const BUCKET: Duration = Duration::from_secs(10);
+
+        let clock = Arc::new(crate::ManualClock::new());
+        clock.advance(Duration::from_secs(1_000_005));
+        let aligned = |clock: &Arc<crate::ManualClock>| {
+            SlidingWindowCount::from_clock(1, BUCKET * 3, 3, true, SharedClock::new(clock.clone()))
+        };
+
+        // windows created at different times share their bucket boundaries
+        let a = aligned(&clock);
+        clock.advance(Duration::from_secs(1_234));
+        let b = aligned(&clock);
+        assert_eq!(a.histogram(), b.histogram());
+        assert_eq!(b.histogram()[2].start, Duration::from_secs(1_001_230));
+
+        // the request counts until its bucket leaves the window, 21s later
+        assert!(a.allow());
+        clock.advance(BUCKET * 2);
+        assert!(!a.allow());
+        clock.advance(Duration::from_secs(1));
+        assert!(a.allow());
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn sliding_window_count_should_hold_invariants(
+            win_size in 1u64..20,
+            bucket_count in 1u64..8,
+            bucket_nanos in 1u64..50,
+            steps in proptest::collection::vec((0u64..200, 1u64..5), 1..200),
+        ) {
+            let clock = Arc::new(crate::ManualClock::new());
+            let interval = Duration::from_nanos(bucket_nanos * bucket_count);
+            let swc = SlidingWindowCount::with_clock(win_size, interval, bucket_count, clock.clone());
+
+            let mut now = 0;
+            let mut allowed: Vec<(u64, u64)> = Vec::new();
+            for (advance, n) in steps {
+                clock.advance(Duration::from_nanos(advance));
+                now += advance;
+
+                let wait = swc.next_available(n);
+                let window_empty = allowed
+                    .iter()
+                    .all(|&(at, _)| now - at >= bucket_nanos * bucket_count);
+                let ok = swc.allow_n(n);
+                proptest::prop_assert_eq!(ok, wait == Duration::ZERO);
+
+                if n <= win_size && window_empty {
+                    // an empty window admits anything up to its size
+                    proptest::prop_assert!(ok);
+                }
+                if ok {
+                    allowed.push((now, n));
+                }
+
+                // any span of all but one bucket holds at most `win_size` requests
+                let span = bucket_nanos * (bucket_count - 1);
+                let recent: u64 = allowed
+                    .iter()
+                    .filter(|&&(at, _)| now - at < span)
+                    .map(|&(_, n)| n)
+                    .sum();
+                proptest::prop_assert!(recent <= win_size);
+
+                let inner = swc.inner.lock_unpoisoned();
+                proptest::prop_assert_eq!(inner.total_count(), inner.buckets.iter().sum::<u64>());
+            }
+        }
     }
 }
@@ -1,7 +1,16 @@
-use std::{
-    sync::{Arc, Mutex},
-    time::{Duration, Instant},
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
 };
+use core::time::Duration;
+
+use crate::{
+    clock::{At, SharedClock},
+    sync::{Mutex, MutexExt},
+    Clock, Error,
+};
+#[cfg(feature = "std")]
+use crate::{sync::arc_size, Quota};
 
 /// A rate limiter that uses a sliding window log algorithm.
 ///
@@ -9,6 +18,11 @@ use std::{
 /// logged with a timestamp, and the rate limiter ensures that the number of requests
 /// in a specified time window does not exceed the allowed limit.
 ///
+/// Requests made at the same instant share a single log entry, and the log is
+/// allocated up front for `size` entries, so allowing requests never allocates. The
+/// number of entries is capped, see [`SlidingWindowLog::with_max_entries`], so that a
+/// huge size does not make the log huge as well.
+///
 /// # Example
 ///
 /// ```
@@ -26,6 +40,27 @@ pub struct SlidingWindowLog {
     inner: Arc<Mutex<SlidingWindowLogInner>>,
 }
 
+/// What a [`SlidingWindowLog`] does with a request that needs a new log entry once
+/// the log holds its maximum number of entries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "std",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum LogOverflow {
+    /// Denies the request until old entries leave the window.
+    Reject,
+    /// Counts the request in the newest entry, which moves to the current time.
+    ///
+    /// The log then degrades to counting requests over coarser time slots, like a
+    /// [`SlidingWindowCount`](crate::SlidingWindowCount). Requests in a merged entry
+    /// leave the window late, so requests may be denied a little early, but never
+    /// more than `size` are allowed per window.
+    #[default]
+    Degrade,
+}
+
 /// Inner structure for `SlidingWindowLog`.
 ///
 /// This structure contains the main logic for managing the rate limiter,
@@ -36,11 +71,36 @@ struct SlidingWindowLogInner {
     size: u64,
     /// The duration of the sliding window.
     interval: Duration,
-    /// A vector storing the timestamps of requests.
-    logs: Vec<Instant>,
+    /// The timestamps of the requests in the window, oldest first, each with the
+    /// number of requests made at that time.
+    logs: VecDeque<(Duration, u64)>,
+    /// The number of requests in the log.
+    count: u64,
+    /// The maximum number of entries in the log.
+    max_entries: usize,
+    /// What to do with requests once the log holds `max_entries` entries.
+    overflow: LogOverflow,
+    /// The IDs of the requests admitted in the window, if deduplicating.
+    dedup: Option<Dedup>,
+    /// The source of time.
+    clock: SharedClock,
+}
+
+/// The IDs of the requests admitted by [`SlidingWindowLog::allow_with_id`].
+#[derive(Debug)]
+struct Dedup {
+    /// The maximum number of IDs remembered.
+    max_ids: usize,
+    /// The time each ID was admitted at.
+    ids: BTreeMap<Arc<str>, Duration>,
+    /// The IDs in the order they were admitted, oldest first.
+    order: VecDeque<(Duration, Arc<str>)>,
 }
 
 impl SlidingWindowLog {
+    /// The maximum number of log entries of a new `SlidingWindowLog`.
+    pub const DEFAULT_MAX_ENTRIES: usize = 1 << 16;
+
     /// Creates a new `SlidingWindowLog` rate limiter.
     ///
     /// # Arguments
@@ -51,16 +111,155 @@ impl SlidingWindowLog {
     /// # Returns
     ///
     /// A new `SlidingWindowLog` instance.
+    #[cfg(feature = "std")]
     pub fn new(size: u64, interval: Option<Duration>) -> Self {
+        Self::with_max_entries(
+            size,
+            interval,
+            Self::DEFAULT_MAX_ENTRIES,
+            LogOverflow::default(),
+        )
+    }
+
+    /// Creates a new `SlidingWindowLog` rate limiter allowing `quota`.
+    ///
+    /// The window allows [`Quota::burst`] requests and lasts [`Quota::burst_period`].
+    #[cfg(feature = "std")]
+    pub fn from_quota(quota: Quota) -> Self {
+        Self::new(quota.burst(), Some(quota.burst_period()))
+    }
+
+    /// Creates a new `SlidingWindowLog` rate limiter keeping at most `max_entries`
+    /// entries in its log.
+    ///
+    /// The log takes one entry per distinct request time in the window, so a
+    /// limiter allowing millions of requests per window would store millions of
+    /// entries. The cap bounds its memory, and `overflow` decides what happens to
+    /// requests once the log is full. A cap of at least `size` never comes into play.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The maximum number of requests allowed within the time window.
+    /// * `interval` - The duration of the sliding window. Defaults to 1 second if not provided.
+    /// * `max_entries` - The maximum number of entries in the log, at least 1.
+    /// * `overflow` - What to do with requests once the log is full.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::{LogOverflow, SlidingWindowLog};
+    ///
+    /// let rl = SlidingWindowLog::with_max_entries(
+    ///     10_000_000,
+    ///     Some(Duration::from_secs(60)),
+    ///     4096,
+    ///     LogOverflow::Degrade,
+    /// );
+    ///
+    /// assert!(rl.allow());
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn with_max_entries(
+        size: u64,
+        interval: Option<Duration>,
+        max_entries: usize,
+        overflow: LogOverflow,
+    ) -> Self {
+        Self::from_clock(size, interval, max_entries, overflow, SharedClock::std())
+    }
+
+    /// Creates a new `SlidingWindowLog` rate limiter reading the time from `clock`.
+    ///
+    /// This is how a sliding window log is created without the `std` feature, and how
+    /// tests control the passing of time.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The maximum number of requests allowed within the time window.
+    /// * `interval` - The duration of the sliding window. Defaults to 1 second if not provided.
+    /// * `clock` - The source of time of the window.
+    pub fn with_clock(size: u64, interval: Option<Duration>, clock: Arc<dyn Clock>) -> Self {
+        Self::from_clock(
+            size,
+            interval,
+            Self::DEFAULT_MAX_ENTRIES,
+            LogOverflow::default(),
+            SharedClock::new(clock),
+        )
+    }
+
+    fn from_clock(
+        size: u64,
+        interval: Option<Duration>,
+        max_entries: usize,
+        overflow: LogOverflow,
+        clock: SharedClock,
+    ) -> Self {
+        let max_entries = max_entries.max(1);
         Self {
             inner: Arc::new(Mutex::new(SlidingWindowLogInner {
                 size,
                 interval: interval.unwrap_or(Duration::from_secs(1)),
-                logs: Vec::with_capacity(size as usize),
+                logs: VecDeque::with_capacity(max_entries.min(size as usize)),
+                count: 0,
+                max_entries,
+                overflow,
+                dedup: None,
+                clock,
             })),
         }
     }
 
+    /// Changes the maximum number of entries in the log and what to do once it is full.
+    ///
+    /// Entries already logged are kept until they leave the window. See
+    /// [`SlidingWindowLog::with_max_entries`] for details.
+    pub fn set_max_entries(&self, max_entries: usize, overflow: LogOverflow) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.max_entries = max_entries.max(1);
+        inner.overflow = overflow;
+        inner.fit_capacity();
+    }
+
+    /// Enables admitting the duplicates of a request for free, remembering up to
+    /// `max_ids` request IDs, or disables it with 0.
+    ///
+    /// See [`SlidingWindowLog::allow_with_id`]. Disabling it forgets the IDs.
+    pub fn set_dedup(&self, max_ids: usize) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.dedup = match inner.dedup.take() {
+            _ if max_ids == 0 => None,
+            Some(mut dedup) => {
+                dedup.max_ids = max_ids;
+                dedup.shrink_to(max_ids);
+                Some(dedup)
+            }
+            None => Some(Dedup {
+                max_ids,
+                ids: BTreeMap::new(),
+                order: VecDeque::new(),
+            }),
+        };
+    }
+
+    /// Updates the parameters of the rate limiter without losing its current state.
+    ///
+    /// Requests already logged keep counting against the new size and interval. A
+    /// larger size grows the log ahead of time, up to its maximum number of entries,
+    /// to keep `allow_n` allocation-free.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The maximum number of requests allowed within the time window.
+    /// * `interval` - The duration of the sliding window. Defaults to 1 second if not provided.
+    pub fn reconfigure(&self, size: u64, interval: Option<Duration>) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.size = size;
+        inner.interval = interval.unwrap_or(Duration::from_secs(1));
+        inner.fit_capacity();
+    }
+
     /// Attempts to allow a single request.
     ///
     /// This is a convenience method that is equivalent to calling `allow_n(1)`.
@@ -82,29 +281,203 @@ impl SlidingWindowLog {
     ///
     /// `true` if the requests are allowed, `false` if they exceed the limit.
     pub fn allow_n(&self, n: u64) -> bool {
-        let mut inner = self
-            .inner
-            .lock()
-            .expect("Failed to lock sliding window log");
+        let mut inner = self.inner.lock_unpoisoned();
 
-        let now = Instant::now();
+        let now = inner.clock.now();
+        inner.allow_n_at(n, now)
+    }
 
-        // First attempt to accept the requests based on current logs.
-        if inner.try_accept(n, now) {
-            return true;
+    /// Attempts to allow a request identified by `id`, admitting it for free if a
+    /// request with the same ID was already admitted in the window.
+    ///
+    /// This makes admissions idempotent, e.g. when a webhook or an event is
+    /// redelivered, so that retries do not burn the quota. An ID is remembered for
+    /// one interval after its first admission, and the oldest IDs are forgotten once
+    /// more than the maximum set by [`SlidingWindowLog::set_dedup`] are remembered,
+    /// their duplicates then counting again. Without deduplication, this is
+    /// [`SlidingWindowLog::allow`].
+    ///
+    /// Remembering an ID allocates.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::SlidingWindowLog;
+    ///
+    /// let rl = SlidingWindowLog::new(2, Some(Duration::from_secs(60)));
+    /// rl.set_dedup(1024);
+    ///
+    /// assert!(rl.allow_with_id("evt_1"));
+    /// assert!(rl.allow_with_id("evt_1")); // redelivered, not counted
+    /// assert!(rl.allow_with_id("evt_2"));
+    /// assert!(!rl.allow_with_id("evt_3"));
+    /// assert!(rl.allow_with_id("evt_2"));
+    /// ```
+    pub fn allow_with_id(&self, id: &str) -> bool {
+        let mut inner = self.inner.lock_unpoisoned();
+
+        let now = inner.clock.now();
+        inner.allow_with_id_at(id, now)
+    }
+
+    /// Checks if a single request is allowed at the time `now`.
+    ///
+    /// This is a convenience method for `allow_n_at(1, now)`.
+    pub fn allow_at(&self, now: Duration) -> bool {
+        self.allow_n_at(1, now)
+    }
+
+    /// Checks if `n` requests are allowed at the time `now`, instead of the time read
+    /// from the clock of the window.
+    ///
+    /// `now` is on the timeline of the clock, see [`TokenBucket::allow_n_at`](crate::TokenBucket::allow_n_at).
+    pub fn allow_n_at(&self, n: u64, now: Duration) -> bool {
+        self.allow_n_at_checked(n, At::Clamped(now))
+            .unwrap_or(false)
+    }
+
+    /// Like [`allow_n_at`](Self::allow_n_at), but fails with
+    /// [`Error::ClockWentBackwards`] if `now` is older than the latest time seen by
+    /// the window, e.g. to detect the out-of-order entries of a replayed log.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::RateLimited`] if the requests are denied, or [`Error::ClockWentBackwards`].
+    pub fn try_allow_n_at(&self, n: u64, now: Duration) -> Result<(), Error> {
+        if self.allow_n_at_checked(n, At::Strict(now))? {
+            Ok(())
+        } else {
+            Err(Error::RateLimited)
+        }
+    }
+
+    /// Takes `n` requests at the time `at`.
+    fn allow_n_at_checked(&self, n: u64, at: At) -> Result<bool, Error> {
+        let mut inner = self.inner.lock_unpoisoned();
+
+        let now = inner.clock.observe_at(at)?;
+        Ok(inner.allow_n_at(n, now))
+    }
+
+    /// Estimates how long to wait until `n` requests are allowed.
+    ///
+    /// The estimate assumes no other request is made in the meantime.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of requests to allow.
+    ///
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if the requests are allowed now, the time until enough logged
+    /// requests have left the window otherwise, or `Duration::MAX` if `n` exceeds the
+    /// size of the window and will never be allowed.
+    pub fn next_available(&self, n: u64) -> Duration {
+        let mut inner = self.inner.lock_unpoisoned();
+
+        let now = inner.clock.now();
+        inner.remove_expired(now);
+
+        let excess = inner.count.saturating_add(n).saturating_sub(inner.size);
+        if excess == 0 {
+            return Duration::ZERO;
+        }
+        if n > inner.size {
+            return Duration::MAX;
         }
 
-        // Remove outdated logs outside the sliding window.
-        let interval = inner.interval;
-        let threshold = now - interval;
-        inner.remove_older_than(&threshold);
+        // the logs are in time order, the oldest `excess` requests have to leave the window
+        let mut left = 0;
+        let leaves_at = inner
+            .logs
+            .iter()
+            .find(|(_, count)| {
+                left += count;
+                left >= excess
+            })
+            .map_or(now, |(time, _)| time.saturating_add(inner.interval));
+        leaves_at.saturating_sub(now)
+    }
 
-        // Try again after cleaning up.
-        inner.try_accept(n, now)
+    /// Returns how many requests the sliding window still allows now.
+    pub fn remaining(&self) -> u64 {
+        let mut inner = self.inner.lock_unpoisoned();
+        let now = inner.clock.now();
+        inner.remove_expired(now);
+        inner.size.saturating_sub(inner.count)
+    }
+
+    /// Gives back `n` requests previously allowed by [`SlidingWindowLog::allow_n`].
+    ///
+    /// This undoes an admission that turned out not to be used, e.g. because
+    /// another limiter denied the same request. Refunding more than was allowed
+    /// never takes the log below empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of requests to give back.
+    pub fn refund(&self, n: u64) {
+        let mut inner = self.inner.lock_unpoisoned();
+        // the refunded requests are the most recent ones
+        let mut n = n.min(inner.count);
+        inner.count -= n;
+        while n > 0 {
+            let Some((_, count)) = inner.logs.back_mut() else {
+                break;
+            };
+            let taken = n.min(*count);
+            *count -= taken;
+            n -= taken;
+            if *count == 0 {
+                inner.logs.pop_back();
+            }
+        }
+    }
+
+    /// Estimates the memory held by this limiter, in bytes.
+    #[cfg(feature = "std")]
+    pub(crate) fn mem_size(&self) -> usize {
+        let logs = self.inner.lock_unpoisoned().logs.capacity();
+        arc_size::<Mutex<SlidingWindowLogInner>>() + logs * size_of::<(Duration, u64)>()
     }
 }
 
 impl SlidingWindowLogInner {
+    /// Logs `n` requests at `now` if they fit in the window.
+    ///
+    /// The entries that left the window are always removed first, so that the
+    /// decision depends on the window alone: neither on the requests that left it
+    /// without being pruned yet, nor on an entry being merged into while a free one
+    /// was about to be.
+    fn allow_n_at(&mut self, n: u64, now: Duration) -> bool {
+        self.remove_expired(now);
+        self.try_accept(n, now)
+    }
+
+    /// Logs a request identified by `id` at `now`, unless a request with the same
+    /// ID is already in the window.
+    fn allow_with_id_at(&mut self, id: &str, now: Duration) -> bool {
+        let interval = self.interval;
+        let Some(dedup) = &mut self.dedup else {
+            return self.allow_n_at(1, now);
+        };
+        if let Some(threshold) = now.checked_sub(interval) {
+            dedup.remove_until(threshold);
+        }
+        if dedup.ids.contains_key(id) {
+            return true;
+        }
+
+        if !self.allow_n_at(1, now) {
+            return false;
+        }
+        if let Some(dedup) = &mut self.dedup {
+            dedup.insert(id, now);
+        }
+        true
+    }
+
     /// Tries to accept `n` requests at the current time.
     ///
     /// # Arguments
@@ -115,36 +488,97 @@ impl SlidingWindowLogInner {
     /// # Returns
     ///
     /// `true` if the requests are accepted, `false` if they exceed the size limit.
-    fn try_accept(&mut self, n: u64, now: Instant) -> bool {
-        if self.logs.len() as u64 + n <= self.size {
-            self.append(n, now);
-            true
+    fn try_accept(&mut self, n: u64, now: Duration) -> bool {
+        if self.count.saturating_add(n) > self.size {
+            return false;
+        }
+        if n == 0 {
+            return true;
+        }
+
+        let full = self.logs.len() >= self.max_entries;
+        match self.logs.back_mut() {
+            Some((time, count)) if *time == now => *count += n,
+            Some((time, count)) if full => match self.overflow {
+                LogOverflow::Reject => return false,
+                LogOverflow::Degrade => {
+                    *time = now;
+                    *count += n;
+                }
+            },
+            _ => self.logs.push_back((now, n)),
+        }
+        self.count += n;
+        true
+    }
+
+    /// Sizes the log for the entries it may hold, so that logging requests does not
+    /// allocate.
+    fn fit_capacity(&mut self) {
+        let capacity = self.max_entries.min(self.size as usize);
+        if self.logs.capacity() < capacity {
+            let additional = capacity - self.logs.len();
+            self.logs.reserve(additional);
         } else {
-            false
+            self.logs.shrink_to(capacity);
         }
     }
 
-    /// Appends `n` requests to the log at the given timestamp.
+    /// Removes all log entries that have left the window, made at least one interval
+    /// before `now`.
     ///
     /// # Arguments
     ///
-    /// * `n` - The number of requests to log.
     /// * `now` - The current timestamp.
-    fn append(&mut self, n: u64, now: Instant) {
-        self.logs.append(&mut vec![now; n as usize]);
+    fn remove_expired(&mut self, now: Duration) {
+        let Some(threshold) = now.checked_sub(self.interval) else {
+            return;
+        };
+        while let Some(&(time, count)) = self.logs.front() {
+            if time > threshold {
+                break;
+            }
+            self.count -= count;
+            self.logs.pop_front();
+        }
     }
+}
 
-    /// Removes all log entries older than the provided threshold.
-    ///
-    /// # Arguments
-    ///
-    /// * `threshold` - The timestamp representing the start of the valid time window.
-    fn remove_older_than(&mut self, threshold: &Instant) {
-        self.logs.retain(|t| t >= threshold);
+impl Dedup {
+    /// Remembers `id` as admitted at `now`, forgetting the oldest ID if needed.
+    fn insert(&mut self, id: &str, now: Duration) {
+        let id: Arc<str> = Arc::from(id);
+        self.ids.insert(id.clone(), now);
+        self.order.push_back((now, id));
+        self.shrink_to(self.max_ids);
+    }
+
+    /// Forgets the oldest IDs until at most `max_ids` are remembered.
+    fn shrink_to(&mut self, max_ids: usize) {
+        while self.order.len() > max_ids {
+            self.pop_front();
+        }
+    }
+
+    /// Forgets the IDs admitted at or before `threshold`.
+    fn remove_until(&mut self, threshold: Duration) {
+        while self
+            .order
+            .front()
+            .is_some_and(|(time, _)| *time <= threshold)
+        {
+            self.pop_front();
+        }
+    }
+
+    fn pop_front(&mut self) {
+        if let Some((_, id)) = self.order.pop_front() {
+            self.ids.remove(&id);
+        }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
@@ -171,4 +605,165 @@ mod tests {
         std::thread::sleep(INTERVAL / 2);
         assert!(rl.allow());
     }
+
+    #[test]
+    fn sliding_window_log_should_count_requests_sharing_an_entry() {
+        const INTERVAL: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(crate::ManualClock::new());
+        let rl = SlidingWindowLog::with_clock(5, Some(INTERVAL), clock.clone());
+
+        assert!(rl.allow_n(2));
+        clock.advance(INTERVAL / 2);
+        assert!(rl.allow_n(3));
+        assert!(!rl.allow());
+        assert_eq!(rl.inner.lock_unpoisoned().logs.len(), 2);
+
+        // the refund reaches into the older entry
+        rl.refund(4);
+        assert!(rl.allow_n(4));
+        assert_eq!(rl.next_available(1), INTERVAL / 2);
+        assert_eq!(rl.next_available(2), INTERVAL);
+
+        clock.advance(INTERVAL / 2);
+        assert!(rl.allow());
+        assert!(!rl.allow());
+    }
+
+    #[test]
+    fn sliding_window_log_should_bound_its_entries() {
+        const INTERVAL: Duration = Duration::from_secs(1);
+        const TICK: Duration = Duration::from_millis(100);
+
+        let clock = Arc::new(crate::ManualClock::new());
+        let rl = SlidingWindowLog::with_clock(1_000_000, Some(INTERVAL), clock.clone());
+        rl.set_max_entries(3, LogOverflow::Reject);
+        assert!(rl.inner.lock_unpoisoned().logs.capacity() < 1_000);
+
+        for _ in 0..3 {
+            assert!(rl.allow_n(10));
+            clock.advance(TICK);
+        }
+        // a request at a new time needs a fourth entry
+        assert!(!rl.allow());
+
+        rl.set_max_entries(3, LogOverflow::Degrade);
+        assert!(rl.allow());
+        clock.advance(TICK);
+        assert!(rl.allow_n(5));
+        assert_eq!(rl.inner.lock_unpoisoned().logs.len(), 3);
+
+        // the merged requests leave the window with the newest of them
+        clock.advance(INTERVAL - TICK * 3);
+        assert_eq!(rl.next_available(1_000_000 - 15), TICK * 3);
+        clock.advance(TICK * 3);
+        assert!(rl.allow_n(1_000_000));
+    }
+
+    #[test]
+    fn sliding_window_log_should_prune_before_merging_entries() {
+        const INTERVAL: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(crate::ManualClock::new());
+        let rl = SlidingWindowLog::with_clock(10, Some(INTERVAL), clock.clone());
+        rl.set_max_entries(2, LogOverflow::Degrade);
+
+        assert!(rl.allow());
+        clock.advance(INTERVAL / 2);
+        assert!(rl.allow());
+
+        // the oldest entry left the window: its place is taken rather than the
+        // request merged into the entry before
+        clock.advance(INTERVAL * 6 / 10);
+        assert!(rl.allow());
+        assert_eq!(rl.inner.lock_unpoisoned().logs.len(), 2);
+
+        clock.advance(INTERVAL * 4 / 10);
+        assert_eq!(rl.remaining(), 9);
+    }
+
+    #[test]
+    fn sliding_window_log_should_never_exceed_its_size_under_contention() {
+        use std::{
+            sync::atomic::{AtomicU64, Ordering},
+            thread,
+        };
+
+        const SIZE: u64 = 20;
+        const INTERVAL: Duration = Duration::from_millis(100);
+
+        for overflow in [LogOverflow::Reject, LogOverflow::Degrade] {
+            let rl = SlidingWindowLog::new(SIZE, Some(INTERVAL));
+            rl.set_max_entries(4, overflow);
+            let ticks = AtomicU64::new(0);
+
+            // every thread takes the next time of a shared timeline, admissions
+            // racing each other at the same and at close times
+            let admitted: Vec<Duration> = thread::scope(|s| {
+                let workers: Vec<_> = (0..8)
+                    .map(|_| {
+                        s.spawn(|| {
+                            let mut admitted = Vec::new();
+                            for _ in 0..2_000 {
+                                let now =
+                                    Duration::from_millis(ticks.fetch_add(1, Ordering::SeqCst) / 8);
+                                let n = 1 + now.as_millis() as u64 % 3;
+                                if rl.try_allow_n_at(n, now).is_ok() {
+                                    admitted.extend((0..n).map(|_| now));
+                                }
+                            }
+                            admitted
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .flat_map(|w| w.join().unwrap())
+                    .collect()
+            });
+            assert!(!admitted.is_empty());
+
+            let mut admitted = admitted;
+            admitted.sort();
+            let mut end = 0;
+            for (start, &time) in admitted.iter().enumerate() {
+                while end < admitted.len() && admitted[end] < time + INTERVAL {
+                    end += 1;
+                }
+                assert!((end - start) as u64 <= SIZE, "{overflow:?} at {time:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn sliding_window_log_should_admit_duplicate_ids_for_free() {
+        const INTERVAL: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(crate::ManualClock::new());
+        let rl = SlidingWindowLog::with_clock(3, Some(INTERVAL), clock.clone());
+
+        // without deduplication, every request counts
+        assert!(rl.allow_with_id("a"));
+        assert!(rl.allow_with_id("a"));
+        rl.refund(2);
+
+        rl.set_dedup(2);
+        assert!(rl.allow_with_id("a"));
+        clock.advance(INTERVAL / 2);
+        assert!(rl.allow_with_id("b"));
+        assert!(rl.allow_with_id("a"));
+        assert!(rl.allow_with_id("b"));
+        assert_eq!(rl.next_available(2), INTERVAL / 2);
+
+        // "a" is forgotten to remember "c", its duplicate counts again
+        assert!(rl.allow_with_id("c"));
+        assert!(!rl.allow_with_id("a"));
+        assert!(rl.allow_with_id("c"));
+
+        // "a" leaves the window, and is counted again once
+        clock.advance(INTERVAL / 2);
+        assert!(rl.allow_with_id("a"));
+        assert!(rl.allow_with_id("a"));
+        assert!(!rl.allow());
+    }
 }
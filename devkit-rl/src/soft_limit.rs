@@ -0,0 +1,169 @@
+use core::time::Duration;
+
+use crate::{Error, RateLimiter};
+
+/// The admission of requests by a [`SoftLimited`] limiter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Admission {
+    /// The requests are within the soft limit, and served normally.
+    Admitted,
+    /// The requests exceed the soft limit but not the hard one: they are allowed,
+    /// and should be served in a degraded way, e.g. from a cache or at a lower
+    /// quality.
+    Degraded,
+    /// The requests exceed the hard limit, and are denied.
+    Denied,
+}
+
+impl Admission {
+    /// Returns whether the requests are allowed, degraded or not.
+    pub fn is_allowed(&self) -> bool {
+        *self != Admission::Denied
+    }
+
+    /// Returns whether the requests are allowed, but should be degraded.
+    pub fn is_degraded(&self) -> bool {
+        *self == Admission::Degraded
+    }
+}
+
+/// A rate limiter with a soft limit below its hard limit, degrading requests
+/// gracefully rather than denying them all at once.
+///
+/// Every request is checked against the hard limiter first, which denies it when
+/// over the hard limit. The requests it allows are then checked against the soft
+/// limiter: those within the soft limit are [admitted](Admission::Admitted), the
+/// others are [degraded](Admission::Degraded). The soft limiter only counts the
+/// requests it admits, so that under a sustained load between the two limits, the
+/// soft limit is served normally and the rest degraded.
+///
+/// Both limiters may be of any algorithm, and are usually the same algorithm with
+/// a lower limit for the soft one. As a [`RateLimiter`], a `SoftLimited` allows
+/// the requests its hard limit allows, degraded or not.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{Admission, SoftLimited, TokenBucket};
+///
+/// let limiter = SoftLimited::new(TokenBucket::new(2, 2, None), TokenBucket::new(3, 3, None));
+///
+/// assert_eq!(limiter.check_n(2), Admission::Admitted);
+/// assert_eq!(limiter.check(), Admission::Degraded);
+/// assert_eq!(limiter.check(), Admission::Denied);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SoftLimited<L> {
+    soft: L,
+    hard: L,
+}
+
+impl<L: RateLimiter> SoftLimited<L> {
+    /// Creates a new `SoftLimited` limiter.
+    ///
+    /// # Arguments
+    ///
+    /// * `soft` - The limiter beyond which requests are degraded.
+    /// * `hard` - The limiter beyond which requests are denied.
+    pub fn new(soft: L, hard: L) -> Self {
+        Self { soft, hard }
+    }
+
+    /// Checks a single request.
+    ///
+    /// This is a convenience method for `check_n(1)`.
+    pub fn check(&self) -> Admission {
+        self.check_n(1)
+    }
+
+    /// Checks `n` requests against both limits.
+    ///
+    /// # Returns
+    ///
+    /// [`Admission::Admitted`] if the requests are within the soft limit,
+    /// [`Admission::Degraded`] if they are only within the hard limit, or
+    /// [`Admission::Denied`] otherwise.
+    pub fn check_n(&self, n: u64) -> Admission {
+        if !self.hard.allow_n(n) {
+            Admission::Denied
+        } else if self.soft.allow_n(n) {
+            Admission::Admitted
+        } else {
+            Admission::Degraded
+        }
+    }
+
+    /// Returns the limiter beyond which requests are degraded.
+    pub fn soft(&self) -> &L {
+        &self.soft
+    }
+
+    /// Returns the limiter beyond which requests are denied.
+    pub fn hard(&self) -> &L {
+        &self.hard
+    }
+}
+
+impl<L: RateLimiter> RateLimiter for SoftLimited<L> {
+    fn allow_n(&self, n: u64) -> bool {
+        self.check_n(n).is_allowed()
+    }
+
+    fn next_available(&self, n: u64) -> Duration {
+        self.hard.next_available(n)
+    }
+
+    fn try_check(&self, n: u64) -> Result<(), Error> {
+        self.hard.try_check(n)?;
+        self.soft.allow_n(n);
+        Ok(())
+    }
+
+    fn remaining(&self) -> Option<u64> {
+        self.hard.remaining()
+    }
+
+    fn window_start(&self) -> Option<Duration> {
+        self.hard.window_start()
+    }
+
+    /// Gives back `n` requests to the hard limiter, as whether the soft limiter
+    /// counted them is not recorded.
+    fn refund(&self, n: u64) {
+        self.hard.refund(n);
+    }
+}
+
+#[cfg(all(test, feature = "std", feature = "fixed-window"))]
+mod tests {
+    use alloc::sync::Arc;
+
+    use super::*;
+    use crate::{FixedWindow, ManualClock};
+
+    #[test]
+    fn soft_limited_should_degrade_between_the_limits() {
+        const INTERVAL: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(ManualClock::new());
+        let limiter = SoftLimited::new(
+            FixedWindow::with_clock(5, Some(INTERVAL), false, clock.clone()),
+            FixedWindow::with_clock(8, Some(INTERVAL), false, clock.clone()),
+        );
+
+        let admissions: Vec<_> = (0..10).map(|_| limiter.check()).collect();
+        assert!(admissions[..5].iter().all(|a| *a == Admission::Admitted));
+        assert!(admissions[5..8].iter().all(Admission::is_degraded));
+        assert!(admissions[8..].iter().all(|a| !a.is_allowed()));
+        assert_eq!(limiter.hard().remaining(), 0);
+
+        // the soft limit is served normally again in the next window
+        clock.advance(INTERVAL);
+        assert_eq!(limiter.check_n(4), Admission::Admitted);
+        assert_eq!(limiter.check_n(2), Admission::Degraded);
+        assert!(matches!(limiter.try_check(3), Err(Error::RateLimited)));
+        assert!(limiter.allow_n(2));
+        assert_eq!(RateLimiter::remaining(&limiter), Some(0));
+        assert_eq!(limiter.next_available(1), INTERVAL);
+    }
+}
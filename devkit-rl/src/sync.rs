@@ -0,0 +1,228 @@
+//! Lock helpers shared by the rate limiters.
+//!
+//! A panic while holding a lock poisons it, and every later `lock().expect(..)`
+//! would panic as well, turning one failure into a process-wide outage. The state
+//! guarded by the locks in this crate is made of plain counters and timestamps that
+//! stay usable after an interrupted update, so the helpers here recover the guard
+//! of a poisoned lock instead of propagating the panic.
+//!
+//! Without the `std` feature there is no OS mutex, so [`Mutex`] is a minimal spin
+//! lock instead. The critical sections of the limiters are a few arithmetic
+//! operations long, which is the case spin locks are made for. With the
+//! `parking_lot` feature, the locks of `parking_lot` are used, which are smaller and
+//! faster under contention, and never poisoned.
+//!
+//! Tests built with `--cfg loom` use the locks and atomics of `loom` instead, which
+//! explores every interleaving of the threads of a test.
+
+#[cfg(all(loom, test))]
+pub(crate) use loom::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(all(feature = "parking_lot", not(all(loom, test))))]
+pub(crate) use parking_lot::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(any(all(loom, test), all(feature = "std", not(feature = "parking_lot"))))]
+use std::sync::PoisonError;
+#[cfg(all(feature = "std", not(feature = "parking_lot"), not(all(loom, test))))]
+pub(crate) use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// The atomics of the crate, which are those of `loom` in loom tests.
+pub(crate) mod atomic {
+    #[cfg(all(loom, test))]
+    pub(crate) use loom::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+    #[cfg(all(feature = "std", not(all(loom, test))))]
+    pub(crate) use core::sync::atomic::AtomicU64;
+    #[cfg(not(all(loom, test)))]
+    pub(crate) use core::sync::atomic::{AtomicBool, Ordering};
+}
+
+/// Extension methods acquiring a [`Mutex`] even if it has been poisoned.
+pub(crate) trait MutexExt<T> {
+    /// Acquires the mutex, recovering the guard if a previous holder panicked.
+    fn lock_unpoisoned(&self) -> MutexGuard<'_, T>;
+}
+
+#[cfg(any(all(loom, test), all(feature = "std", not(feature = "parking_lot"))))]
+impl<T> MutexExt<T> for Mutex<T> {
+    fn lock_unpoisoned(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(all(feature = "parking_lot", not(all(loom, test))))]
+impl<T> MutexExt<T> for Mutex<T> {
+    fn lock_unpoisoned(&self) -> MutexGuard<'_, T> {
+        self.lock()
+    }
+}
+
+/// Extension methods acquiring a [`RwLock`] even if it has been poisoned.
+#[cfg(feature = "std")]
+pub(crate) trait RwLockExt<T> {
+    /// Acquires shared read access, recovering the guard if a writer panicked.
+    fn read_unpoisoned(&self) -> RwLockReadGuard<'_, T>;
+
+    /// Acquires exclusive write access, recovering the guard if a writer panicked.
+    fn write_unpoisoned(&self) -> RwLockWriteGuard<'_, T>;
+}
+
+#[cfg(any(all(loom, test), all(feature = "std", not(feature = "parking_lot"))))]
+impl<T> RwLockExt<T> for RwLock<T> {
+    fn read_unpoisoned(&self) -> RwLockReadGuard<'_, T> {
+        self.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_unpoisoned(&self) -> RwLockWriteGuard<'_, T> {
+        self.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(all(feature = "parking_lot", not(all(loom, test))))]
+impl<T> RwLockExt<T> for RwLock<T> {
+    fn read_unpoisoned(&self) -> RwLockReadGuard<'_, T> {
+        self.read()
+    }
+
+    fn write_unpoisoned(&self) -> RwLockWriteGuard<'_, T> {
+        self.write()
+    }
+}
+
+/// Returns the size of the allocation behind an `Arc<T>`, its reference counts
+/// included.
+#[cfg(feature = "std")]
+pub(crate) fn arc_size<T>() -> usize {
+    2 * size_of::<usize>() + size_of::<T>()
+}
+
+#[cfg(all(not(feature = "std"), not(all(loom, test))))]
+pub(crate) use spin::{Mutex, MutexGuard};
+
+#[cfg(any(not(feature = "std"), all(loom, test)))]
+mod spin {
+    use core::{
+        cell::UnsafeCell,
+        fmt,
+        ops::{Deref, DerefMut},
+    };
+
+    use super::atomic::{AtomicBool, Ordering};
+    #[cfg(not(feature = "std"))]
+    use super::MutexExt;
+
+    /// A spin lock, standing in for `std::sync::Mutex` without the standard library.
+    ///
+    /// It cannot be poisoned: a panic without `std` aborts or halts.
+    pub(crate) struct Mutex<T> {
+        locked: AtomicBool,
+        value: UnsafeCell<T>,
+    }
+
+    // SAFETY: access to `value` is serialized by `locked`.
+    unsafe impl<T: Send> Send for Mutex<T> {}
+    // SAFETY: access to `value` is serialized by `locked`.
+    unsafe impl<T: Send> Sync for Mutex<T> {}
+
+    impl<T> Mutex<T> {
+        pub(crate) fn new(value: T) -> Self {
+            Self {
+                locked: AtomicBool::new(false),
+                value: UnsafeCell::new(value),
+            }
+        }
+
+        /// Acquires the lock, spinning until it is free.
+        pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+            while self
+                .locked
+                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                #[cfg(not(all(loom, test)))]
+                core::hint::spin_loop();
+                #[cfg(all(loom, test))]
+                loom::thread::yield_now();
+            }
+            MutexGuard { mutex: self }
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    impl<T> MutexExt<T> for Mutex<T> {
+        fn lock_unpoisoned(&self) -> MutexGuard<'_, T> {
+            self.lock()
+        }
+    }
+
+    impl<T: fmt::Debug> fmt::Debug for Mutex<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Mutex")
+                .field("value", &*self.lock())
+                .finish()
+        }
+    }
+
+    /// The guard of a locked [`Mutex`], unlocking it when dropped.
+    pub(crate) struct MutexGuard<'a, T> {
+        mutex: &'a Mutex<T>,
+    }
+
+    impl<T> Deref for MutexGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            // SAFETY: the guard holds the lock.
+            unsafe { &*self.mutex.value.get() }
+        }
+    }
+
+    impl<T> DerefMut for MutexGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            // SAFETY: the guard holds the lock.
+            unsafe { &mut *self.mutex.value.get() }
+        }
+    }
+
+    impl<T> Drop for MutexGuard<'_, T> {
+        fn drop(&mut self) {
+            self.mutex.locked.store(false, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(all(test, loom))]
+mod tests {
+    use loom::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use super::spin;
+
+    #[test]
+    fn loom_spin_mutex_should_publish_updates() {
+        loom::model(|| {
+            // the counter is updated in two relaxed steps, so only the lock keeps
+            // an update from being lost or reading a stale value
+            let lock = Arc::new(spin::Mutex::new(()));
+            let counter = Arc::new(AtomicU64::new(0));
+            let increment = {
+                let (lock, counter) = (lock.clone(), counter.clone());
+                move || {
+                    let _guard = lock.lock();
+                    let value = counter.load(Ordering::Relaxed);
+                    counter.store(value + 1, Ordering::Relaxed);
+                }
+            };
+
+            let other = thread::spawn(increment.clone());
+            increment();
+            other.join().unwrap();
+
+            let _guard = lock.lock();
+            assert_eq!(counter.load(Ordering::Relaxed), 2);
+        });
+    }
+}
@@ -0,0 +1,484 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    thread,
+    time::Duration,
+};
+#[cfg(feature = "async")]
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{limiter::retry_after, Quota, TokenBucket};
+#[cfg(feature = "async")]
+use crate::{AsyncRuntime, DefaultRuntime, Sleep};
+
+/// Hands out the bytes allowed by a token bucket.
+struct Throttle {
+    bucket: TokenBucket,
+    #[cfg(feature = "async")]
+    runtime: Arc<dyn AsyncRuntime>,
+    #[cfg(feature = "async")]
+    sleep: Option<Sleep>,
+}
+
+impl Throttle {
+    fn new(bucket: TokenBucket) -> Self {
+        Self {
+            bucket,
+            #[cfg(feature = "async")]
+            runtime: Arc::new(DefaultRuntime),
+            #[cfg(feature = "async")]
+            sleep: None,
+        }
+    }
+
+    /// Blocks the thread until at least one byte is allowed.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes allowed, up to `len`, or an error if the bucket never
+    /// allows any byte.
+    fn acquire(&self, len: usize) -> io::Result<usize> {
+        loop {
+            let granted = self.bucket.allow_up_to(len as u64);
+            if granted > 0 {
+                return Ok(granted as usize);
+            }
+            thread::sleep(self.wait()?);
+        }
+    }
+
+    /// Polls until at least one byte is allowed.
+    ///
+    /// See [`Throttle::acquire`].
+    #[cfg(feature = "async")]
+    fn poll_acquire(&mut self, cx: &mut Context<'_>, len: usize) -> Poll<io::Result<usize>> {
+        loop {
+            if let Some(sleep) = &mut self.sleep {
+                ready!(sleep.as_mut().poll(cx));
+                self.sleep = None;
+            }
+            let granted = self.bucket.allow_up_to(len as u64);
+            if granted > 0 {
+                return Poll::Ready(Ok(granted as usize));
+            }
+            let wait = self.wait()?;
+            self.sleep = Some(self.runtime.sleep(wait));
+        }
+    }
+
+    /// Returns how long to wait for the next byte.
+    fn wait(&self) -> io::Result<Duration> {
+        retry_after(self.bucket.next_available(1)).map_err(Into::into)
+    }
+
+    /// Gives back the bytes allowed but not transferred.
+    fn refund(&self, granted: usize, transferred: usize) {
+        if granted > transferred {
+            self.bucket.refund((granted - transferred) as u64);
+        }
+    }
+}
+
+impl fmt::Debug for Throttle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Throttle")
+            .field("bucket", &self.bucket)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A reader limited to a number of bytes per second.
+///
+/// Every read transfers the bytes available in a token bucket holding one token per
+/// byte, which may be fewer than asked: a read of 64 KiB through a reader limited to
+/// 1 KiB per second returns 1 KiB per second. A read blocks the thread until at
+/// least one byte is available. With the `async` feature, the reader also wraps the
+/// `AsyncRead` types of `futures`, and of tokio with the `tokio` feature, and waits
+/// on the timer of the [`DefaultRuntime`] instead, or of the one given to
+/// [`ThrottledReader::with_runtime`].
+///
+/// # Example
+///
+/// ```
+/// use std::io::Read;
+/// use devkit_rl::{Quota, ThrottledReader};
+///
+/// // 1 MiB per second, in bursts of up to 64 KiB
+/// let quota = Quota::per_second(1 << 20).allow_burst(64 << 10);
+/// let mut reader = ThrottledReader::new(&[0u8; 100_000][..], quota);
+///
+/// let mut buf = vec![0; 100_000];
+/// assert_eq!(reader.read(&mut buf).unwrap(), 64 << 10);
+/// ```
+#[derive(Debug)]
+pub struct ThrottledReader<R> {
+    inner: R,
+    throttle: Throttle,
+}
+
+impl<R> ThrottledReader<R> {
+    /// Creates a new `ThrottledReader`.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The reader to limit.
+    /// * `quota` - The bytes allowed per period, and at once.
+    pub fn new(inner: R, quota: Quota) -> Self {
+        Self::with_bucket(inner, TokenBucket::from_quota(quota))
+    }
+
+    /// Creates a new `ThrottledReader` taking one token of `bucket` per byte.
+    ///
+    /// Readers and writers sharing clones of a bucket share its bandwidth.
+    pub fn with_bucket(inner: R, bucket: TokenBucket) -> Self {
+        Self {
+            inner,
+            throttle: Throttle::new(bucket),
+        }
+    }
+
+    /// Waits on the timer of `runtime` when used asynchronously, instead of the
+    /// [`DefaultRuntime`].
+    #[cfg(feature = "async")]
+    pub fn with_runtime(mut self, runtime: Arc<dyn AsyncRuntime>) -> Self {
+        self.throttle.runtime = runtime;
+        self
+    }
+
+    /// Returns a reference to the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for ThrottledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return self.inner.read(buf);
+        }
+        let granted = self.throttle.acquire(buf.len())?;
+        let result = self.inner.read(&mut buf[..granted]);
+        self.throttle
+            .refund(granted, *result.as_ref().unwrap_or(&0));
+        result
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead + Unpin> AsyncRead for ThrottledReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+        let granted = ready!(this.throttle.poll_acquire(cx, buf.remaining()))?;
+
+        let mut limited = ReadBuf::new(buf.initialize_unfilled_to(granted));
+        let result = Pin::new(&mut this.inner).poll_read(cx, &mut limited);
+        let read = limited.filled().len();
+        this.throttle.refund(granted, read);
+        if let Poll::Ready(Ok(())) = result {
+            buf.advance(read);
+        }
+        result
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: futures_io::AsyncRead + Unpin> futures_io::AsyncRead for ThrottledReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+        let granted = ready!(this.throttle.poll_acquire(cx, buf.len()))?;
+
+        let result = Pin::new(&mut this.inner).poll_read(cx, &mut buf[..granted]);
+        let read = match result {
+            Poll::Ready(Ok(n)) => n,
+            _ => 0,
+        };
+        this.throttle.refund(granted, read);
+        result
+    }
+}
+
+/// A writer limited to a number of bytes per second.
+///
+/// Every write transfers the bytes available in a token bucket holding one token per
+/// byte, which may be fewer than given, as [`Write::write`] allows. A write blocks
+/// the thread until at least one byte is available. With the `async` feature, the
+/// writer also wraps the `AsyncWrite` types of `futures`, and of tokio with the
+/// `tokio` feature, and waits on the timer of the [`DefaultRuntime`] instead, or of
+/// the one given to [`ThrottledWriter::with_runtime`].
+///
+/// # Example
+///
+/// ```
+/// use std::io::Write;
+/// use devkit_rl::{Quota, ThrottledWriter};
+///
+/// let mut writer = ThrottledWriter::new(Vec::new(), Quota::per_second(1 << 20));
+/// writer.write_all(b"hello").unwrap();
+/// assert_eq!(writer.get_ref(), b"hello");
+/// ```
+#[derive(Debug)]
+pub struct ThrottledWriter<W> {
+    inner: W,
+    throttle: Throttle,
+}
+
+impl<W> ThrottledWriter<W> {
+    /// Creates a new `ThrottledWriter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The writer to limit.
+    /// * `quota` - The bytes allowed per period, and at once.
+    pub fn new(inner: W, quota: Quota) -> Self {
+        Self::with_bucket(inner, TokenBucket::from_quota(quota))
+    }
+
+    /// Creates a new `ThrottledWriter` taking one token of `bucket` per byte.
+    ///
+    /// Readers and writers sharing clones of a bucket share its bandwidth.
+    pub fn with_bucket(inner: W, bucket: TokenBucket) -> Self {
+        Self {
+            inner,
+            throttle: Throttle::new(bucket),
+        }
+    }
+
+    /// Waits on the timer of `runtime` when used asynchronously, instead of the
+    /// [`DefaultRuntime`].
+    #[cfg(feature = "async")]
+    pub fn with_runtime(mut self, runtime: Arc<dyn AsyncRuntime>) -> Self {
+        self.throttle.runtime = runtime;
+        self
+    }
+
+    /// Returns a reference to the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Returns the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for ThrottledWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return self.inner.write(buf);
+        }
+        let granted = self.throttle.acquire(buf.len())?;
+        let result = self.inner.write(&buf[..granted]);
+        self.throttle
+            .refund(granted, *result.as_ref().unwrap_or(&0));
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: AsyncWrite + Unpin> AsyncWrite for ThrottledWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+        let granted = ready!(this.throttle.poll_acquire(cx, buf.len()))?;
+
+        let result = Pin::new(&mut this.inner).poll_write(cx, &buf[..granted]);
+        let written = match result {
+            Poll::Ready(Ok(n)) => n,
+            _ => 0,
+        };
+        this.throttle.refund(granted, written);
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<W: futures_io::AsyncWrite + Unpin> futures_io::AsyncWrite for ThrottledWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+        let granted = ready!(this.throttle.poll_acquire(cx, buf.len()))?;
+
+        let result = Pin::new(&mut this.inner).poll_write(cx, &buf[..granted]);
+        let written = match result {
+            Poll::Ready(Ok(n)) => n,
+            _ => 0,
+        };
+        this.throttle.refund(granted, written);
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Instant};
+
+    use super::*;
+    use crate::ManualClock;
+
+    #[test]
+    fn throttled_reader_should_partially_fill_reads() {
+        let clock = Arc::new(ManualClock::new());
+        let bucket = TokenBucket::with_clock(10, 10, Some(Duration::from_secs(1)), clock.clone());
+        let mut reader = ThrottledReader::with_bucket(&[7u8; 25][..], bucket.clone());
+
+        let mut buf = [0; 16];
+        assert_eq!(reader.read(&mut buf).unwrap(), 10);
+        assert_eq!(buf[..10], [7; 10]);
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(reader.read(&mut buf[..4]).unwrap(), 4);
+        assert_eq!(reader.read(&mut buf).unwrap(), 6);
+
+        // the bytes left unread are given back
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(reader.read(&mut buf).unwrap(), 5);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        assert_eq!(bucket.allow_up_to(100), 5);
+
+        // a bucket without bandwidth fails instead of blocking forever
+        let bucket = TokenBucket::with_clock(0, 0, None, clock);
+        let mut writer = ThrottledWriter::with_bucket(Vec::new(), bucket);
+        assert!(writer.write(b"x").is_err());
+    }
+
+    #[test]
+    fn throttled_writer_should_block_until_bandwidth_is_available() {
+        // 1000 bytes per second, in bursts of 100
+        let mut writer = ThrottledWriter::new(Vec::new(), Quota::per_second(1000).allow_burst(100));
+
+        let start = Instant::now();
+        writer.write_all(&[1; 300]).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(190));
+        assert_eq!(writer.into_inner(), [1; 300]);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn throttled_io_should_wait_on_the_given_runtime() {
+        use futures::{executor::block_on, AsyncReadExt, AsyncWriteExt};
+
+        use crate::Clock;
+
+        // a runtime whose sleeps advance the clock of the bucket
+        let clock = Arc::new(ManualClock::new());
+        let runtime: Arc<dyn AsyncRuntime> = Arc::new({
+            let clock = clock.clone();
+            move |wait| -> Sleep {
+                clock.advance(wait);
+                Box::pin(std::future::ready(()))
+            }
+        });
+        let bucket =
+            TokenBucket::with_clock(10, 10, Some(Duration::from_millis(100)), clock.clone());
+
+        let mut reader = ThrottledReader::with_bucket(&[3u8; 35][..], bucket.clone())
+            .with_runtime(runtime.clone());
+        let mut data = Vec::new();
+        block_on(AsyncReadExt::read_to_end(&mut reader, &mut data)).unwrap();
+        assert_eq!(data, [3; 35]);
+        assert_eq!(clock.now(), Duration::from_millis(300));
+
+        let mut writer = ThrottledWriter::with_bucket(Vec::new(), bucket).with_runtime(runtime);
+        block_on(AsyncWriteExt::write_all(&mut writer, &data)).unwrap();
+        block_on(AsyncWriteExt::close(&mut writer)).unwrap();
+        assert_eq!(writer.get_ref().len(), 35);
+        assert_eq!(clock.now(), Duration::from_millis(600));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn throttled_io_should_wait_on_tokio() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        /// Reads the time of the tokio runtime, which the test pauses.
+        fn bucket() -> TokenBucket {
+            let start = tokio::time::Instant::now();
+            let clock = Arc::new(move || start.elapsed());
+            TokenBucket::with_clock(10, 10, Some(Duration::from_millis(100)), clock)
+        }
+
+        let start = tokio::time::Instant::now();
+        let mut reader = ThrottledReader::with_bucket(&[3u8; 35][..], bucket());
+        let mut data = Vec::new();
+        AsyncReadExt::read_to_end(&mut reader, &mut data)
+            .await
+            .unwrap();
+        assert_eq!(data, [3; 35]);
+        assert_eq!(start.elapsed(), Duration::from_millis(300));
+
+        let start = tokio::time::Instant::now();
+        let mut writer = ThrottledWriter::with_bucket(Vec::new(), bucket());
+        AsyncWriteExt::write_all(&mut writer, &data).await.unwrap();
+        AsyncWriteExt::flush(&mut writer).await.unwrap();
+        assert_eq!(writer.get_ref().len(), 35);
+        assert_eq!(start.elapsed(), Duration::from_millis(300));
+    }
+}
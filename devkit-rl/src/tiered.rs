@@ -0,0 +1,178 @@
+use std::{hash::Hash, time::Duration};
+
+use crate::{KeyedLimiter, Limiter, LimiterConfig, RateLimiter};
+
+/// The level of a [`TieredLimiter`] that denied a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    /// The limit of the key the request is accounted to.
+    Key,
+    /// The limit shared by all keys.
+    Global,
+}
+
+/// A rate limiter enforcing a global limit on top of a limit per key.
+///
+/// This is the most common production topology: every client gets its own limit,
+/// and all clients together are limited so that the service is not overloaded
+/// when many of them are busy at once.
+///
+/// A request is allowed only if both levels allow it, and is accounted to both or
+/// to neither. The key is checked first, so a client over its own limit does not
+/// use up the global limit. If the global limit then denies the request, it is
+/// refunded to the key, see [`Limiter::refund`]. A queuing leaky bucket cannot be
+/// refunded, which makes it a poor fit for the key level, unlike a
+/// [meter](crate::LeakyBucket::meter).
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::{LimiterConfig, Tier, TieredLimiter};
+///
+/// let limiter = TieredLimiter::new(
+///     LimiterConfig::TokenBucket {
+///         capacity: 3,
+///         refill_rate: 3,
+///         refill_interval_ms: None,
+///         initial_tokens: None,
+///     },
+///     LimiterConfig::TokenBucket {
+///         capacity: 2,
+///         refill_rate: 2,
+///         refill_interval_ms: None,
+///         initial_tokens: None,
+///     },
+/// );
+///
+/// assert!(limiter.allow_n(&"10.0.0.1", 2));
+/// assert_eq!(limiter.check_n(&"10.0.0.1", 1), Err(Tier::Key));
+/// assert!(limiter.allow(&"10.0.0.2"));
+/// assert_eq!(limiter.check_n(&"10.0.0.3", 1), Err(Tier::Global));
+/// ```
+#[derive(Debug, Clone)]
+pub struct TieredLimiter<K> {
+    global: Limiter,
+    per_key: KeyedLimiter<K>,
+}
+
+impl<K: Hash + Eq + Clone> TieredLimiter<K> {
+    /// Creates a new `TieredLimiter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `global` - The configuration of the limit shared by all keys.
+    /// * `per_key` - The configuration of the limit of each key.
+    pub fn new(global: LimiterConfig, per_key: LimiterConfig) -> Self {
+        Self::with_limiters(global.build(), KeyedLimiter::new(per_key))
+    }
+
+    /// Creates a new `TieredLimiter` from existing limiters.
+    ///
+    /// This allows sharing the global limiter with other parts of the application,
+    /// or using a keyed limiter that evicts idle keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `global` - The limiter shared by all keys.
+    /// * `per_key` - The limiter of each key.
+    pub fn with_limiters(global: Limiter, per_key: KeyedLimiter<K>) -> Self {
+        Self { global, per_key }
+    }
+
+    /// Attempts to allow a single request for `key`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if both the limit of `key` and the global limit allow the request.
+    pub fn allow(&self, key: &K) -> bool {
+        self.allow_n(key, 1)
+    }
+
+    /// Attempts to allow `n` requests for `key`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if both the limit of `key` and the global limit allow the requests.
+    pub fn allow_n(&self, key: &K, n: u64) -> bool {
+        self.check_n(key, n).is_ok()
+    }
+
+    /// Attempts to allow `n` requests for `key`, reporting which level denied them.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key the requests are accounted to.
+    /// * `n` - The number of requests to allow.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the requests are allowed, or the [`Tier`] that denied them. Denied
+    /// requests are not accounted to any level.
+    pub fn check_n(&self, key: &K, n: u64) -> Result<(), Tier> {
+        if !self.per_key.allow_n(key, n) {
+            return Err(Tier::Key);
+        }
+        if !self.global.allow_n(n) {
+            self.per_key.refund(key, n);
+            return Err(Tier::Global);
+        }
+        Ok(())
+    }
+
+    /// Estimates how long to wait until `n` requests for `key` would be allowed by
+    /// both levels.
+    ///
+    /// See [`RateLimiter::next_available`].
+    pub fn next_available(&self, key: &K, n: u64) -> Duration {
+        self.per_key
+            .next_available(key, n)
+            .max(self.global.next_available(n))
+    }
+
+    /// Returns the limiter shared by all keys.
+    pub fn global(&self) -> &Limiter {
+        &self.global
+    }
+
+    /// Returns the limiter of each key.
+    pub fn per_key(&self) -> &KeyedLimiter<K> {
+        &self.per_key
+    }
+}
+
+#[cfg(all(test, feature = "sliding-log", feature = "token-bucket"))]
+mod tests {
+    use super::*;
+    use crate::LogOverflow;
+
+    #[test]
+    fn tiered_limiter_should_roll_back_key_when_global_denies() {
+        let limiter = TieredLimiter::new(
+            LimiterConfig::TokenBucket {
+                capacity: 2,
+                refill_rate: 1,
+                refill_interval_ms: Some(60_000),
+                initial_tokens: None,
+            },
+            LimiterConfig::SlidingWindowLog {
+                size: 2,
+                interval_ms: Some(60_000),
+                max_entries: None,
+                overflow: LogOverflow::default(),
+            },
+        );
+
+        assert!(limiter.allow(&"a"));
+        assert!(limiter.allow(&"b"));
+
+        // the global limit is exhausted, "c" keeps its own limit untouched
+        assert_eq!(limiter.check_n(&"c", 2), Err(Tier::Global));
+        assert_eq!(limiter.per_key().next_available(&"c", 2), Duration::ZERO);
+        assert!(limiter.next_available(&"c", 1) > Duration::ZERO);
+
+        // once the global limit has room again, "c" gets its full limit
+        limiter.global().refund(2);
+        assert!(limiter.allow_n(&"c", 2));
+        assert_eq!(limiter.check_n(&"c", 1), Err(Tier::Key));
+    }
+}
@@ -1,7 +1,14 @@
-use std::{
-    sync::{Arc, Mutex},
-    time::{Duration, Instant},
+use alloc::sync::Arc;
+use core::time::Duration;
+
+use crate::{
+    clock::{whole_periods, At, SharedClock},
+    limiter::{cost_units, COST_SCALE},
+    sync::{Mutex, MutexExt},
+    Clock, Error,
 };
+#[cfg(feature = "std")]
+use crate::{sync::arc_size, Quota};
 
 /// A thread-safe token bucket rate limiter.
 ///
@@ -25,13 +32,45 @@ pub struct TokenBucket {
     inner: Arc<Mutex<TokenBucketInner>>,
 }
 
+/// How a [`TokenBucket`] adds tokens as time passes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "std",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Refill {
+    /// Adds `refill_rate` tokens at the end of every whole refill interval.
+    ///
+    /// With a 1 second interval, a request 900ms after the bucket ran out gets
+    /// nothing, and the tokens of the interval come back all at once.
+    #[default]
+    Intervals,
+    /// Adds tokens continuously, `elapsed * refill_rate / refill_interval`, keeping
+    /// the fractions of a token.
+    ///
+    /// With 10 tokens per second, a token comes back every 100ms, which spreads the
+    /// requests of a drained bucket over the interval instead of bunching them at
+    /// its end. The tokens added over a whole interval are the same in both modes.
+    Continuous,
+}
+
 #[derive(Debug)]
 struct TokenBucketInner {
     tokens: u64,
+    /// The part of a token already spent by fractional costs, in units of
+    /// [`COST_SCALE`], so that `tokens` are available but for this part.
+    spent: u64,
     capacity: u64,
     refill_rate: u64,
     refill_interval: Duration,
-    last_refill_time: Instant,
+    last_refill_time: Duration,
+    refill: Refill,
+    /// The time since `last_refill_time` not yet turned into a whole unit of
+    /// [`COST_SCALE`] by a continuous refill, in nanoseconds times units per
+    /// interval, i.e. less than one unit.
+    carry: u128,
+    clock: SharedClock,
 }
 
 impl TokenBucket {
@@ -54,13 +93,94 @@ impl TokenBucket {
     ///
     /// let bucket = TokenBucket::new(100, 10, Some(Duration::from_secs(1)));
     /// ```
+    #[cfg(feature = "std")]
     pub fn new(capacity: u64, refill_rate: u64, refill_interval: Option<Duration>) -> Self {
+        Self::from_clock(capacity, refill_rate, refill_interval, SharedClock::std())
+    }
+
+    /// Creates a new `TokenBucket` allowing `quota`.
+    ///
+    /// The bucket holds [`Quota::burst`] tokens and gets one token back every
+    /// [`Quota::replenish_interval`].
+    #[cfg(feature = "std")]
+    pub fn from_quota(quota: Quota) -> Self {
+        Self::new(quota.burst(), 1, Some(quota.replenish_interval()))
+    }
+
+    /// Creates a new `TokenBucket` starting with `initial_tokens` instead of full.
+    ///
+    /// A bucket starting full lets every new client burst up to its capacity right
+    /// away. Starting partially filled softens that burst, and starting empty paces
+    /// the requests from the first one on, at the refill rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of tokens in the bucket.
+    /// * `refill_rate` - Number of tokens to refill per interval.
+    /// * `refill_interval` - Interval between refills (optional, defaults to 1 second).
+    /// * `initial_tokens` - The tokens in the bucket when it is created, at most `capacity`.
+    ///
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use devkit_rl::TokenBucket;
+    ///
+    /// let bucket = TokenBucket::with_initial_tokens(100, 10, Some(Duration::from_secs(1)), 0);
+    /// assert!(!bucket.allow()); // nothing to spend before the first refill
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn with_initial_tokens(
+        capacity: u64,
+        refill_rate: u64,
+        refill_interval: Option<Duration>,
+        initial_tokens: u64,
+    ) -> Self {
+        let bucket = Self::new(capacity, refill_rate, refill_interval);
+        bucket.set_tokens(initial_tokens);
+        bucket
+    }
+
+    /// Creates a new `TokenBucket` reading the time from `clock`.
+    ///
+    /// This is how a token bucket is created without the `std` feature, and how tests
+    /// control the passing of time.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of tokens in the bucket.
+    /// * `refill_rate` - Number of tokens to refill per interval.
+    /// * `refill_interval` - Interval between refills (optional, defaults to 1 second).
+    /// * `clock` - The source of time of the bucket.
+    pub fn with_clock(
+        capacity: u64,
+        refill_rate: u64,
+        refill_interval: Option<Duration>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self::from_clock(
+            capacity,
+            refill_rate,
+            refill_interval,
+            SharedClock::new(clock),
+        )
+    }
+
+    fn from_clock(
+        capacity: u64,
+        refill_rate: u64,
+        refill_interval: Option<Duration>,
+        mut clock: SharedClock,
+    ) -> Self {
         let inner = TokenBucketInner {
             tokens: capacity, // initially fill the bucket to capacity
+            spent: 0,
             capacity,
             refill_rate,
             refill_interval: refill_interval.unwrap_or(Duration::from_secs(1)), // default to 1 second
-            last_refill_time: Instant::now(),
+            last_refill_time: clock.now(),
+            refill: Refill::default(),
+            carry: 0,
+            clock,
         };
 
         Self {
@@ -68,6 +188,67 @@ impl TokenBucket {
         }
     }
 
+    /// Sets the tokens in the bucket, at most its capacity.
+    ///
+    /// This is how a bucket reading the time from a clock starts partially filled
+    /// or empty, see [`TokenBucket::with_initial_tokens`]. Refills go on from the
+    /// tokens set.
+    pub fn set_tokens(&self, tokens: u64) {
+        let mut inner = self.inner.lock_unpoisoned();
+        inner.advance();
+        let tokens = tokens.min(inner.capacity);
+        inner.set_available(u128::from(tokens) * COST_SCALE);
+    }
+
+    /// Sets how the bucket adds tokens, whole intervals at a time by default.
+    ///
+    /// # Example
+    /// ```
+    /// use std::{sync::Arc, time::Duration};
+    /// use devkit_rl::{ManualClock, Refill, TokenBucket};
+    ///
+    /// let clock = Arc::new(ManualClock::new());
+    /// let bucket = TokenBucket::with_clock(10, 10, Some(Duration::from_secs(1)), clock.clone())
+    ///     .with_refill(Refill::Continuous);
+    /// assert!(bucket.allow_n(10));
+    ///
+    /// clock.advance(Duration::from_millis(900));
+    /// assert_eq!(bucket.remaining(), 9);
+    /// ```
+    pub fn with_refill(self, refill: Refill) -> Self {
+        {
+            let mut inner = self.inner.lock_unpoisoned();
+            inner.advance();
+            inner.refill = refill;
+            inner.carry = 0;
+        }
+        self
+    }
+
+    /// Updates the parameters of the bucket without losing its current state.
+    ///
+    /// Tokens already in the bucket are kept, but never exceed the new capacity.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of tokens in the bucket.
+    /// * `refill_rate` - Number of tokens to refill per interval.
+    /// * `refill_interval` - Interval between refills (optional, defaults to 1 second).
+    pub fn reconfigure(&self, capacity: u64, refill_rate: u64, refill_interval: Option<Duration>) {
+        let mut inner = self.inner.lock_unpoisoned();
+
+        // account for the time passed under the old parameters first
+        inner.advance();
+
+        inner.capacity = capacity;
+        inner.refill_rate = refill_rate;
+        inner.refill_interval = refill_interval.unwrap_or(Duration::from_secs(1));
+        inner.carry = 0;
+        if inner.tokens > capacity {
+            inner.set_available(u128::from(capacity) * COST_SCALE);
+        }
+    }
+
     /// Attempts to consume 1 token from the bucket.
     ///
     /// Returns `true` if the token was successfully consumed, or `false` if there are not enough tokens available.
@@ -99,51 +280,282 @@ impl TokenBucket {
     /// assert!(bucket.allow_n(5));
     /// ```
     pub fn allow_n(&self, n: u64) -> bool {
-        let mut inner = self.inner.lock().expect("Failed to lock token bucket");
+        self.consume(u128::from(n) * COST_SCALE)
+    }
+
+    /// Attempts to consume a single token at the time `now`.
+    ///
+    /// This is a convenience method for `allow_n_at(1, now)`.
+    pub fn allow_at(&self, now: Duration) -> bool {
+        self.allow_n_at(1, now)
+    }
+
+    /// Attempts to consume `n` tokens at the time `now`, instead of the time read
+    /// from the clock of the bucket.
+    ///
+    /// `now` is on the timeline of the clock, e.g. the time since the unix epoch for
+    /// a [`SystemClock`](crate::SystemClock). This replays logs, or drives
+    /// simulations and tests, without a mock clock. A time older than the latest one
+    /// seen by the bucket counts as that latest time.
+    ///
+    /// # Example
+    /// ```
+    /// use std::{sync::Arc, time::Duration};
+    /// use devkit_rl::{ManualClock, TokenBucket};
+    ///
+    /// let bucket = TokenBucket::with_clock(1, 1, None, Arc::new(ManualClock::new()));
+    /// assert!(bucket.allow_n_at(1, Duration::from_secs(10)));
+    /// assert!(!bucket.allow_n_at(1, Duration::from_millis(10_500)));
+    /// assert!(bucket.allow_n_at(1, Duration::from_secs(11)));
+    /// ```
+    pub fn allow_n_at(&self, n: u64, now: Duration) -> bool {
+        self.allow_n_at_checked(n, At::Clamped(now))
+            .unwrap_or(false)
+    }
 
+    /// Like [`allow_n_at`](Self::allow_n_at), but fails with
+    /// [`Error::ClockWentBackwards`] if `now` is older than the latest time seen by
+    /// the bucket, e.g. to detect the out-of-order entries of a replayed log.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::RateLimited`] if the tokens are denied, or [`Error::ClockWentBackwards`].
+    pub fn try_allow_n_at(&self, n: u64, now: Duration) -> Result<(), Error> {
+        if self.allow_n_at_checked(n, At::Strict(now))? {
+            Ok(())
+        } else {
+            Err(Error::RateLimited)
+        }
+    }
+
+    /// Takes `n` tokens at the time `at`.
+    fn allow_n_at_checked(&self, n: u64, at: At) -> Result<bool, Error> {
+        let mut inner = self.inner.lock_unpoisoned();
+
+        let now = inner.clock.observe_at(at)?;
+        inner.advance_to(now);
+
+        Ok(inner.take(u128::from(n) * COST_SCALE))
+    }
+
+    /// Attempts to consume `cost` tokens from the bucket, which may be fractional.
+    ///
+    /// A cost of `0.1` takes a tenth of a token, so ten such requests are allowed
+    /// per token. A cost that is not positive is free.
+    ///
+    /// # Example
+    /// ```
+    /// use devkit_rl::TokenBucket;
+    ///
+    /// let bucket = TokenBucket::new(1, 1, None);
+    /// assert!(bucket.allow_cost(0.5));
+    /// assert!(bucket.allow_cost(0.5));
+    /// assert!(!bucket.allow_cost(0.5));
+    /// ```
+    pub fn allow_cost(&self, cost: f64) -> bool {
+        self.consume(cost_units(cost))
+    }
+
+    /// Consumes as many whole tokens as are available, up to `max`.
+    ///
+    /// This partially fills requests the bucket cannot serve at once, e.g. the
+    /// bytes of a read or a write that exceeds the available bandwidth.
+    ///
+    /// # Returns
+    ///
+    /// The number of tokens consumed, 0 if none is available.
+    ///
+    /// # Example
+    /// ```
+    /// use devkit_rl::TokenBucket;
+    ///
+    /// let bucket = TokenBucket::new(10, 10, None);
+    /// assert_eq!(bucket.allow_up_to(4), 4);
+    /// assert_eq!(bucket.allow_up_to(100), 6);
+    /// assert_eq!(bucket.allow_up_to(100), 0);
+    /// ```
+    pub fn allow_up_to(&self, max: u64) -> u64 {
+        let mut inner = self.inner.lock_unpoisoned();
+
+        inner.advance();
+
+        let available = inner.available();
+        let n = (available / COST_SCALE).min(u128::from(max));
+        inner.set_available(available - n * COST_SCALE);
+        n as u64
+    }
+
+    /// Consumes `units` units of [`COST_SCALE`] if they are available.
+    fn consume(&self, units: u128) -> bool {
+        let mut inner = self.inner.lock_unpoisoned();
+
+        inner.advance();
+
+        inner.take(units)
+    }
+
+    /// Estimates how long to wait until `n` tokens can be consumed.
+    ///
+    /// The estimate assumes no other request consumes tokens in the meantime.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of tokens to consume.
+    ///
+    /// # Returns
+    ///
+    /// `Duration::ZERO` if the tokens are available now, the time until the refill
+    /// that makes them available otherwise, or `Duration::MAX` if `n` exceeds the
+    /// capacity and will never be available.
+    pub fn next_available(&self, n: u64) -> Duration {
+        let mut inner = self.inner.lock_unpoisoned();
+
+        inner.advance();
+
+        let units = u128::from(n) * COST_SCALE;
+        let available = inner.available();
+        if units <= available {
+            return Duration::ZERO;
+        }
+        if n > inner.capacity || inner.refill_rate == 0 {
+            return Duration::MAX;
+        }
+
+        let per_interval = u128::from(inner.refill_rate) * COST_SCALE;
+        let wait = match inner.refill {
+            Refill::Intervals => {
+                let refills = (units - available).div_ceil(per_interval);
+                u32::try_from(refills)
+                    .map_or(Duration::MAX, |r| inner.refill_interval.saturating_mul(r))
+            }
+            Refill::Continuous => {
+                let needed = (units - available)
+                    .saturating_mul(inner.refill_interval.as_nanos())
+                    .saturating_sub(inner.carry);
+                u64::try_from(needed.div_ceil(per_interval))
+                    .map_or(Duration::MAX, Duration::from_nanos)
+            }
+        };
+        let ready_at = inner.last_refill_time.saturating_add(wait);
+        ready_at.saturating_sub(inner.clock.now())
+    }
+
+    /// Returns the whole tokens left in the bucket, i.e. how many requests would be
+    /// allowed now.
+    pub fn remaining(&self) -> u64 {
+        let mut inner = self.inner.lock_unpoisoned();
         inner.advance();
+        let whole = inner.available() / COST_SCALE;
+        u64::try_from(whole).unwrap_or(u64::MAX)
+    }
+
+    /// Gives back `n` requests previously allowed by [`TokenBucket::allow_n`].
+    ///
+    /// This undoes an admission that turned out not to be used, e.g. because
+    /// another limiter denied the same request. Refunding more than was allowed
+    /// never fills the bucket beyond its capacity.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of requests to give back.
+    pub fn refund(&self, n: u64) {
+        let mut inner = self.inner.lock_unpoisoned();
+        let full = u128::from(inner.capacity) * COST_SCALE;
+        let available = inner.available().saturating_add(u128::from(n) * COST_SCALE);
+        inner.set_available(available.min(full));
+    }
+
+    /// Estimates the memory held by this limiter, in bytes.
+    #[cfg(feature = "std")]
+    pub(crate) fn mem_size(&self) -> usize {
+        arc_size::<Mutex<TokenBucketInner>>()
+    }
+}
+
+impl TokenBucketInner {
+    /// Returns the tokens available, in units of [`COST_SCALE`].
+    fn available(&self) -> u128 {
+        u128::from(self.tokens) * COST_SCALE - u128::from(self.spent)
+    }
 
-        if n > inner.tokens {
+    /// Takes `units` units of [`COST_SCALE`] if they are available.
+    fn take(&mut self, units: u128) -> bool {
+        let available = self.available();
+        if units > available {
             false
         } else {
-            inner.tokens -= n;
+            self.set_available(available - units);
             true
         }
     }
-}
 
-impl TokenBucketInner {
+    /// Sets the tokens available to `units` units of [`COST_SCALE`].
+    fn set_available(&mut self, units: u128) {
+        let whole = units.div_ceil(COST_SCALE);
+        self.tokens = u64::try_from(whole).unwrap_or(u64::MAX);
+        self.spent = (whole * COST_SCALE - units) as u64;
+    }
+
     /// Advances the token bucket, adding tokens based on the elapsed time since the last refill.
     ///
     /// This method checks how much time has passed since the last token refill and adds tokens
     /// to the bucket accordingly, ensuring that the number of tokens in the bucket does not
     /// exceed its capacity.
     fn advance(&mut self) {
-        let now = Instant::now();
-        let elapsed = now - self.last_refill_time;
+        let now = self.clock.now();
+        self.advance_to(now);
+    }
+
+    /// Advances the token bucket to `now`, see [`TokenBucketInner::advance`].
+    fn advance_to(&mut self, now: Duration) {
+        let elapsed = now.saturating_sub(self.last_refill_time);
+
+        if self.refill == Refill::Continuous {
+            self.accrue(elapsed);
+            self.last_refill_time = self.last_refill_time.max(now);
+            return;
+        }
 
         if elapsed < self.refill_interval {
             return;
         }
 
-        let interval_count = elapsed.div_duration_f64(self.refill_interval) as u64;
-        let tokens_to_add = interval_count * self.refill_rate;
+        let (interval_count, into_interval) = whole_periods(elapsed, self.refill_interval);
+        let tokens_to_add = interval_count.saturating_mul(self.refill_rate);
         self.tokens = self.tokens.saturating_add(tokens_to_add);
-        self.tokens = self.tokens.min(self.capacity);
+        if self.tokens >= self.capacity {
+            // a full bucket has no part of a token spent
+            self.tokens = self.capacity;
+            self.spent = 0;
+        }
+
+        // keep the refills aligned, the time into the current interval counts towards the next
+        self.last_refill_time = now - into_interval;
+    }
 
-        let passed_time = self
-            .refill_interval
-            .checked_mul(interval_count as u32)
-            .expect("Failed to calculate passed time");
+    /// Adds the tokens accrued continuously over `elapsed`, carrying the part of a
+    /// unit of [`COST_SCALE`] left over to the next refill.
+    fn accrue(&mut self, elapsed: Duration) {
+        let interval = self.refill_interval.as_nanos().max(1);
+        let accrued = elapsed
+            .as_nanos()
+            .saturating_mul(u128::from(self.refill_rate) * COST_SCALE)
+            .saturating_add(self.carry);
+        self.carry = accrued % interval;
 
-        self.last_refill_time = self
-            .last_refill_time
-            .checked_add(passed_time)
-            .expect("Failed to update last refill time");
+        let full = u128::from(self.capacity) * COST_SCALE;
+        let available = self.available().saturating_add(accrued / interval);
+        if available >= full {
+            // a full bucket accrues nothing more
+            self.set_available(full);
+            self.carry = 0;
+        } else {
+            self.set_available(available);
+        }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
@@ -185,6 +597,140 @@ mod tests {
         // and tokens should be replenished.
         std::thread::sleep(INTERVAL * 11);
         assert!(bucket.allow());
-        assert_eq!(bucket.inner.lock().unwrap().tokens, CAPACITY - 1);
+        assert_eq!(bucket.inner.lock_unpoisoned().tokens, CAPACITY - 1);
+    }
+
+    #[test]
+    fn token_bucket_should_allow_fractional_costs() {
+        let clock = Arc::new(crate::ManualClock::new());
+        let bucket = TokenBucket::with_clock(1, 1, Some(Duration::from_secs(1)), clock.clone());
+
+        assert!(bucket.allow_cost(0.4));
+        assert!(bucket.allow_cost(0.6));
+        assert!(!bucket.allow_cost(0.1));
+        assert_eq!(bucket.next_available(1), Duration::from_secs(1));
+
+        clock.advance(Duration::from_secs(1));
+        assert!(bucket.allow_cost(0.5));
+        assert!(!bucket.allow());
+        assert!(bucket.allow_cost(0.5));
+    }
+
+    #[test]
+    fn token_bucket_should_start_with_initial_tokens() {
+        const INTERVAL: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(crate::ManualClock::new());
+        let bucket = TokenBucket::with_clock(10, 2, Some(INTERVAL), clock.clone());
+
+        // an empty bucket paces from the first request on
+        bucket.set_tokens(0);
+        assert!(!bucket.allow());
+        assert_eq!(bucket.next_available(1), INTERVAL);
+        clock.advance(INTERVAL);
+        assert!(bucket.allow_n(2));
+        assert!(!bucket.allow());
+
+        // a partial bucket allows a smaller first burst, and never more than capacity
+        bucket.set_tokens(3);
+        assert!(bucket.allow_n(3));
+        assert!(!bucket.allow());
+        bucket.set_tokens(100);
+        assert!(bucket.allow_n(10));
+        assert!(!bucket.allow());
+    }
+
+    #[test]
+    fn token_bucket_should_refill_continuously() {
+        const INTERVAL: Duration = Duration::from_secs(1);
+
+        let clock = Arc::new(crate::ManualClock::new());
+        let stepped = TokenBucket::with_clock(10, 10, Some(INTERVAL), clock.clone());
+        let smooth = TokenBucket::with_clock(10, 10, Some(INTERVAL), clock.clone())
+            .with_refill(Refill::Continuous);
+        assert!(stepped.allow_n(10));
+        assert!(smooth.allow_n(10));
+
+        // 900ms after running out, only the continuous bucket has tokens back
+        clock.advance(Duration::from_millis(900));
+        assert!(!stepped.allow());
+        assert_eq!(stepped.next_available(1), Duration::from_millis(100));
+        assert_eq!(smooth.remaining(), 9);
+        assert!(smooth.allow_n(9));
+        assert_eq!(smooth.next_available(1), Duration::from_millis(100));
+
+        // a token every 100ms, the same 10 per interval as the stepped bucket
+        clock.advance(Duration::from_millis(100));
+        assert!(stepped.allow_n(10));
+        assert!(smooth.allow());
+        assert!(!smooth.allow());
+        for _ in 0..10 {
+            clock.advance(Duration::from_millis(100));
+            assert!(smooth.allow());
+            assert!(!smooth.allow());
+        }
+    }
+
+    #[test]
+    fn token_bucket_should_carry_fractions_of_a_continuous_refill() {
+        let clock = Arc::new(crate::ManualClock::new());
+        let bucket = TokenBucket::with_clock(1, 1, Some(Duration::from_secs(3)), clock.clone())
+            .with_refill(Refill::Continuous);
+        assert!(bucket.allow());
+
+        // a third of a token per second adds up to a whole token, nothing is lost
+        for _ in 0..2 {
+            clock.advance(Duration::from_secs(1));
+            assert!(!bucket.allow());
+        }
+        assert_eq!(bucket.next_available(1), Duration::from_secs(1));
+        clock.advance(Duration::from_secs(1));
+        assert!(bucket.allow());
+
+        // many small steps accrue as much as one long one
+        for _ in 0..3_000 {
+            clock.advance(Duration::from_millis(1));
+        }
+        assert!(bucket.allow_cost(1.0));
+        assert!(!bucket.allow_cost(0.001));
+    }
+
+    #[cfg(loom)]
+    #[test]
+    fn loom_token_bucket_should_not_spend_a_token_twice() {
+        loom::model(|| {
+            let bucket = TokenBucket::with_clock(
+                1,
+                1,
+                Some(Duration::from_secs(60)),
+                Arc::new(|| Duration::ZERO),
+            );
+            let other = {
+                let bucket = bucket.clone();
+                loom::thread::spawn(move || bucket.allow_cost(0.6))
+            };
+            let allowed = bucket.allow_cost(0.6);
+            assert!(allowed ^ other.join().unwrap());
+        });
+    }
+
+    // the locks of parking_lot and loom are never poisoned
+    #[cfg(not(any(feature = "parking_lot", loom)))]
+    #[test]
+    fn token_bucket_should_recover_from_poisoned_lock() {
+        let bucket = TokenBucket::new(2, 1, Some(Duration::from_secs(60)));
+
+        // panic while holding the lock, which poisons it
+        let poisoner = bucket.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.inner.lock_unpoisoned();
+            panic!("poison the token bucket");
+        })
+        .join();
+        assert!(bucket.inner.is_poisoned());
+
+        assert!(bucket.allow());
+        assert!(bucket.allow());
+        assert!(!bucket.allow());
     }
 }
@@ -0,0 +1,46 @@
+use core::time::Duration;
+
+/// A rate limiter that allows every request.
+///
+/// It lets applications turn rate limiting off, e.g. per environment through a
+/// [`LimiterConfig::Unlimited`](crate::LimiterConfig::Unlimited) entry, without
+/// branching around the limiter in their code. It holds no state.
+///
+/// The opposite, a limiter that denies every request, is any limiter with a
+/// capacity or size of 0.
+///
+/// # Example
+///
+/// ```
+/// use devkit_rl::Unlimited;
+///
+/// let limiter = Unlimited::new();
+/// assert!(limiter.allow_n(u64::MAX));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Unlimited;
+
+impl Unlimited {
+    /// Creates a new `Unlimited` rate limiter.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Allows a single request.
+    pub fn allow(&self) -> bool {
+        true
+    }
+
+    /// Allows `n` requests, whatever `n` is.
+    pub fn allow_n(&self, _n: u64) -> bool {
+        true
+    }
+
+    /// Returns `Duration::ZERO`, since requests never have to wait.
+    pub fn next_available(&self, _n: u64) -> Duration {
+        Duration::ZERO
+    }
+
+    /// Gives back `n` requests, which does nothing as nothing is counted.
+    pub fn refund(&self, _n: u64) {}
+}
@@ -0,0 +1,159 @@
+use std::{
+    hint, thread,
+    time::{Duration, Instant},
+};
+
+/// How a blocking limiter waits for its next deadline.
+///
+/// [`thread::sleep`] is cheap, but the scheduler commonly wakes the thread tens of
+/// microseconds to a millisecond late, which dwarfs pacing intervals below a
+/// millisecond. Spinning hits the deadline to the microsecond, at the cost of a
+/// busy core. [`WaitStrategy::Hybrid`] combines both: it parks the thread until
+/// shortly before the deadline, then spins the rest of the way, which is accurate
+/// while only spinning for the margin.
+///
+/// The strategy is used by the leak thread of a queuing [`LeakyBucket`](crate::LeakyBucket),
+/// see [`LeakyBucket::set_wait_strategy`](crate::LeakyBucket::set_wait_strategy),
+/// and by [`Limiter::wait_with`](crate::Limiter::wait_with).
+///
+/// # Example
+///
+/// ```
+/// use std::time::{Duration, Instant};
+/// use devkit_rl::WaitStrategy;
+///
+/// let start = Instant::now();
+/// WaitStrategy::Hybrid { spin: Duration::from_micros(100) }.wait(Duration::from_micros(300));
+/// assert!(start.elapsed() >= Duration::from_micros(300));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WaitStrategy {
+    /// Sleeps until the deadline, precise to the scheduler.
+    #[default]
+    Sleep,
+    /// Spins until the deadline, hinting the processor that the thread is busy
+    /// waiting.
+    Spin,
+    /// Yields the rest of the time slice to other threads until the deadline, which
+    /// is precise while letting them run, but still keeps a core busy when idle.
+    Yield,
+    /// Parks until `spin` before the deadline, then spins until it.
+    Hybrid {
+        /// How long before the deadline to stop parking, typically the lateness of
+        /// the scheduler, e.g. 100µs.
+        spin: Duration,
+    },
+}
+
+impl WaitStrategy {
+    /// Waits for `duration`.
+    pub fn wait(&self, duration: Duration) {
+        match Instant::now().checked_add(duration) {
+            Some(deadline) => self.wait_until(deadline),
+            // too far to tell apart from forever
+            None => loop {
+                thread::park();
+            },
+        }
+    }
+
+    /// Waits until `deadline`, returning right away if it has passed.
+    pub fn wait_until(&self, deadline: Instant) {
+        match *self {
+            WaitStrategy::Sleep => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left > Duration::ZERO {
+                    thread::sleep(left);
+                }
+            }
+            WaitStrategy::Spin => spin_until(deadline, hint::spin_loop),
+            WaitStrategy::Yield => spin_until(deadline, thread::yield_now),
+            WaitStrategy::Hybrid { spin } => {
+                // parking may wake up spuriously, or be unparked, so check the time again
+                loop {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left <= spin {
+                        break;
+                    }
+                    thread::park_timeout(left - spin);
+                }
+                spin_until(deadline, hint::spin_loop);
+            }
+        }
+    }
+
+    /// Waits for `duration`, returning early once `stopped` returns `true`, which
+    /// is checked every time the thread wakes up.
+    ///
+    /// Sleeping parks the thread instead, so that unparking it wakes it up to check.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the wait was stopped.
+    pub(crate) fn wait_unless(&self, duration: Duration, stopped: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now().checked_add(duration);
+        let spin = match *self {
+            WaitStrategy::Sleep => Duration::ZERO,
+            WaitStrategy::Spin | WaitStrategy::Yield => Duration::MAX,
+            WaitStrategy::Hybrid { spin } => spin,
+        };
+        loop {
+            if stopped() {
+                return false;
+            }
+            let left = deadline.map_or(Duration::MAX, |d| {
+                d.saturating_duration_since(Instant::now())
+            });
+            if left.is_zero() {
+                return true;
+            }
+            if left > spin {
+                thread::park_timeout(left - spin);
+            } else if *self == WaitStrategy::Yield {
+                thread::yield_now();
+            } else {
+                hint::spin_loop();
+            }
+        }
+    }
+}
+
+/// Calls `pause` until `deadline`.
+fn spin_until(deadline: Instant, pause: impl Fn()) {
+    while Instant::now() < deadline {
+        pause();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wait_strategies_should_not_return_before_the_deadline() {
+        const WAIT: Duration = Duration::from_micros(200);
+
+        for strategy in [
+            WaitStrategy::Sleep,
+            WaitStrategy::Spin,
+            WaitStrategy::Yield,
+            WaitStrategy::Hybrid {
+                spin: Duration::from_micros(50),
+            },
+        ] {
+            let start = Instant::now();
+            strategy.wait(WAIT);
+            assert!(start.elapsed() >= WAIT, "{strategy:?}");
+
+            // a deadline in the past does not wait
+            strategy.wait_until(start);
+        }
+
+        // spinning waits are accurate, give or take a preemption
+        let start = Instant::now();
+        for _ in 0..10 {
+            WaitStrategy::Spin.wait(WAIT);
+        }
+        assert!(start.elapsed() < WAIT * 10 + Duration::from_millis(50));
+    }
+}
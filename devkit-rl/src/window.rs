@@ -0,0 +1,181 @@
+use alloc::vec::Vec;
+use core::{mem, time::Duration};
+
+/// A ring of buckets dividing a sliding window of time, the machinery under
+/// [`SlidingWindowCount`](crate::SlidingWindowCount), for other crates to keep
+/// rolling statistics the same way, e.g. the outcomes of the calls of a circuit
+/// breaker.
+///
+/// Bucket `i` starts `i` bucket intervals after the origin, so which bucket holds
+/// an event is a function of its time alone, no matter how often or how late the
+/// ring is advanced. The ring holds the current bucket and those before it, up to
+/// the length of the window.
+///
+/// The ring does not read the time: it is given the time by its owner, which
+/// keeps it free of locks and clocks.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_rl::BucketRing;
+///
+/// let mut ring = BucketRing::<u64>::new(Duration::from_secs(3), 3, Duration::ZERO);
+/// *ring.current_mut() += 4;
+///
+/// ring.advance(Duration::from_secs(2), |_| {});
+/// *ring.current_mut() += 1;
+/// assert_eq!(ring.iter().copied().collect::<Vec<_>>(), [4, 0, 1]);
+///
+/// let mut expired = 0;
+/// ring.advance(Duration::from_secs(3), |count| expired += count);
+/// assert_eq!(expired, 4);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BucketRing<T> {
+    buckets: Vec<T>,
+    /// The duration of each bucket.
+    interval: Duration,
+    /// The time when the bucket numbered 0 started.
+    origin: Duration,
+    /// The number of the current bucket, held by `buckets[current % len]`.
+    current: u64,
+}
+
+impl<T: Default> BucketRing<T> {
+    /// Creates a new `BucketRing` of empty buckets, its buckets starting at whole
+    /// multiples of the bucket interval since `origin`, and its current bucket
+    /// holding `origin`.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - The total duration of the window.
+    /// * `bucket_count` - The number of buckets to divide the window into. A count
+    ///   of 0 is treated as 1, and buckets never get shorter than a nanosecond.
+    /// * `origin` - When the bucket numbered 0 starts, e.g. now, or 0 to align the
+    ///   buckets to the origin of the clock.
+    pub fn new(window: Duration, bucket_count: u64, origin: Duration) -> Self {
+        let bucket_count = bucket_count.max(1);
+        Self {
+            buckets: (0..bucket_count).map(|_| T::default()).collect(),
+            interval: bucket_interval(window, bucket_count),
+            origin,
+            current: 0,
+        }
+    }
+
+    /// Moves the current bucket to the one holding `now`.
+    ///
+    /// Every bucket started since the current one is cleared, oldest first, as it
+    /// still holds the events of a previous window: `expire` is called with its
+    /// contents. A `now` older than the current bucket is ignored.
+    pub fn advance(&mut self, now: Duration, mut expire: impl FnMut(T)) {
+        let bucket = self.bucket_at(now);
+        let passed = bucket.saturating_sub(self.current);
+
+        let len = self.buckets.len() as u64;
+        for i in 1..=passed.min(len) {
+            let slot = self.slot(self.current + i);
+            expire(mem::take(&mut self.buckets[slot]));
+        }
+
+        self.current = self.current.max(bucket);
+    }
+
+    /// Changes the duration of the window and the number of buckets, keeping the
+    /// events of the window.
+    ///
+    /// If the number of buckets is unchanged, every bucket keeps its contents.
+    /// Otherwise the buckets are folded into the newest one with `merge`, so they
+    /// keep counting for a full window. From then on, the buckets start at whole
+    /// multiples of the new bucket interval since the current bucket started.
+    pub fn reconfigure(
+        &mut self,
+        window: Duration,
+        bucket_count: u64,
+        mut merge: impl FnMut(&mut T, T),
+    ) {
+        let bucket_count = bucket_count.max(1);
+
+        // the buckets are numbered from the current one from now on
+        let current = self.slot(self.current);
+        self.origin = self.bucket_start(self.current);
+        self.current = 0;
+        if self.buckets.len() == bucket_count as usize {
+            self.buckets.rotate_left(current);
+        } else {
+            let mut newest = T::default();
+            for bucket in self.buckets.drain(..) {
+                merge(&mut newest, bucket);
+            }
+            self.buckets = (1..bucket_count).map(|_| T::default()).collect();
+            self.buckets.insert(0, newest);
+        }
+        self.interval = bucket_interval(window, bucket_count);
+    }
+}
+
+impl<T> BucketRing<T> {
+    /// Returns the current bucket.
+    pub fn current(&self) -> &T {
+        &self.buckets[self.slot(self.current)]
+    }
+
+    /// Returns the current bucket, to record events in.
+    pub fn current_mut(&mut self) -> &mut T {
+        let slot = self.slot(self.current);
+        &mut self.buckets[slot]
+    }
+
+    /// Returns the buckets of the window, oldest first, ending with the current one.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + '_ {
+        let len = self.buckets.len() as u64;
+        (1..=len).map(move |i| &self.buckets[self.slot(self.current + i)])
+    }
+
+    /// Returns the number of buckets.
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Returns `false`: a ring always has a bucket.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Returns the duration of each bucket.
+    pub fn bucket_interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns the time when the current bucket ends, and the next one starts.
+    pub fn current_end(&self) -> Duration {
+        self.bucket_start(self.current.saturating_add(1))
+    }
+
+    /// Returns the number of the bucket containing `now`.
+    fn bucket_at(&self, now: Duration) -> u64 {
+        let elapsed = now.saturating_sub(self.origin);
+        let bucket = elapsed.as_nanos() / self.interval.as_nanos();
+        u64::try_from(bucket).unwrap_or(u64::MAX)
+    }
+
+    /// Returns the time when the bucket numbered `bucket` starts.
+    fn bucket_start(&self, bucket: u64) -> Duration {
+        let offset = self.interval.as_nanos() * u128::from(bucket);
+        let offset = u64::try_from(offset).map_or(Duration::MAX, Duration::from_nanos);
+        self.origin.saturating_add(offset)
+    }
+
+    /// Returns the index in `buckets` holding the bucket numbered `bucket`.
+    fn slot(&self, bucket: u64) -> usize {
+        (bucket % self.buckets.len() as u64) as usize
+    }
+}
+
+/// Returns the duration of each of `bucket_count` buckets dividing `window`,
+/// at least one nanosecond.
+fn bucket_interval(window: Duration, bucket_count: u64) -> Duration {
+    let nanos = (window.as_nanos() / u128::from(bucket_count)).max(1);
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
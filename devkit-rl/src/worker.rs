@@ -0,0 +1,256 @@
+use std::{
+    sync::{mpsc, Arc, OnceLock},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexExt,
+    },
+    Error, WaitStrategy,
+};
+
+/// How long dropping a [`Worker`] waits for its thread to stop.
+const DROP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The internal tasks running, see [`tasks`].
+static TASKS: OnceLock<Mutex<Vec<TaskInfo>>> = OnceLock::new();
+
+/// The id of the next task.
+static NEXT_TASK_ID: core::sync::atomic::AtomicU64 = core::sync::atomic::AtomicU64::new(0);
+
+/// An internal task of the limiters, running on its own thread, see [`tasks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskInfo {
+    /// The id of the task, unique within the process.
+    pub id: u64,
+    /// What the task does, e.g. `sweeper`; its thread is named `devkit-rl-{name}`.
+    pub name: &'static str,
+    /// When the task was spawned.
+    pub started_at: Instant,
+}
+
+/// Lists the internal tasks running in the process, oldest first.
+///
+/// The limiters run a few tasks on threads of their own: the leak thread of a
+/// queuing leaky bucket, the sweepers of keyed limiters, gossip, lease renewals,
+/// timers, and so on. Each thread is named `devkit-rl-{name}` after its task, so
+/// that it is identifiable in debuggers and profilers, and runs in a
+/// `devkit_rl.task` span with the `tracing` feature.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_rl::{tasks, KeyedLimiter, LimiterConfig};
+///
+/// let limiter: KeyedLimiter<u64> =
+///     KeyedLimiter::with_idle_ttl(LimiterConfig::Unlimited, Duration::from_secs(60));
+/// let sweeper = limiter.spawn_sweeper(Duration::from_secs(10));
+///
+/// assert!(tasks().iter().any(|task| task.name == "sweeper"));
+/// ```
+pub fn tasks() -> Vec<TaskInfo> {
+    task_list().lock_unpoisoned().clone()
+}
+
+fn task_list() -> &'static Mutex<Vec<TaskInfo>> {
+    TASKS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// The registration of a running task, listed by [`tasks`] until dropped.
+struct Task {
+    id: u64,
+}
+
+impl Task {
+    fn register(name: &'static str) -> Self {
+        let id = NEXT_TASK_ID.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+        task_list().lock_unpoisoned().push(TaskInfo {
+            id,
+            name,
+            started_at: Instant::now(),
+        });
+        Self { id }
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        task_list()
+            .lock_unpoisoned()
+            .retain(|task| task.id != self.id);
+    }
+}
+
+/// Spawns a thread running the task `name`, listed by [`tasks`] until it returns.
+pub(crate) fn spawn_task<T: Send + 'static>(
+    name: &'static str,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> JoinHandle<T> {
+    // registered before the thread starts, so that it is listed as soon as spawned
+    let task = Task::register(name);
+    thread::Builder::new()
+        .name(format!("devkit-rl-{name}"))
+        .spawn(move || {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("devkit_rl.task", task = name, id = task.id).entered();
+            let _task = task;
+            f()
+        })
+        .expect("failed to spawn thread")
+}
+
+/// The handle of a background thread of a limiter, e.g. the sweeper of a
+/// [`KeyedLimiter`](crate::KeyedLimiter).
+///
+/// The thread runs until it is stopped with [`Worker::shutdown`], or the handle is
+/// dropped, which stops it too and waits up to a second for it to finish. The
+/// thread is woken up to stop: it does not sleep until its next tick first.
+/// [`Worker::detach`] lets the thread run on instead, until the limiter it works
+/// for is dropped.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use devkit_rl::{KeyedLimiter, LimiterConfig};
+///
+/// let limiter: KeyedLimiter<u64> =
+///     KeyedLimiter::with_idle_ttl(LimiterConfig::Unlimited, Duration::from_secs(60));
+/// let sweeper = limiter.spawn_sweeper(Duration::from_secs(10));
+///
+/// assert!(sweeper.shutdown(Duration::from_secs(1)).is_ok());
+/// ```
+#[derive(Debug)]
+#[must_use = "dropping the worker stops its thread"]
+pub struct Worker {
+    stop: Arc<Stop>,
+    handle: Option<JoinHandle<()>>,
+    /// Disconnected once the thread has finished.
+    done: mpsc::Receiver<()>,
+}
+
+/// The stop signal of a [`Worker`], given to its thread.
+#[derive(Debug, Default)]
+pub(crate) struct Stop {
+    stopped: AtomicBool,
+}
+
+impl Worker {
+    /// Spawns a thread running the task `name`, `f`, which should return soon after
+    /// `stop` is set.
+    pub(crate) fn spawn(name: &'static str, f: impl FnOnce(&Stop) + Send + 'static) -> Self {
+        let stop = Arc::new(Stop::default());
+        let (done_tx, done) = mpsc::channel::<()>();
+
+        let signal = stop.clone();
+        let handle = spawn_task(name, move || {
+            // disconnects the channel when the thread finishes, panicking or not
+            let _done = done_tx;
+            f(&signal);
+        });
+
+        Self {
+            stop,
+            handle: Some(handle),
+            done,
+        }
+    }
+
+    /// Stops the thread and waits for it to finish, at most `timeout`.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Timeout`] if the thread has not finished within `timeout`, e.g. in
+    /// the middle of a slow call to a remote store. It still stops once done with
+    /// it, but is not waited for any longer.
+    pub fn shutdown(mut self, timeout: Duration) -> Result<(), Error> {
+        self.stop(timeout)
+    }
+
+    /// Returns `true` if the thread has finished.
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Lets the thread run on without the handle, until the limiter it works for is
+    /// dropped.
+    pub fn detach(mut self) {
+        self.handle.take();
+    }
+
+    fn stop(&mut self, timeout: Duration) -> Result<(), Error> {
+        let Some(handle) = self.handle.take() else {
+            return Ok(());
+        };
+        self.stop.stopped.store(true, Ordering::Release);
+        handle.thread().unpark();
+
+        // the last handle of a limiter may be dropped by its own thread
+        if handle.thread().id() == thread::current().id() {
+            return Ok(());
+        }
+        match self.done.recv_timeout(timeout) {
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                let _ = handle.join();
+                Ok(())
+            }
+            _ => Err(Error::Timeout),
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let _ = self.stop(DROP_TIMEOUT);
+    }
+}
+
+impl Stop {
+    /// Returns `true` if the thread should stop.
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// Sleeps for `duration`, waking up early if the thread should stop.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the thread should stop.
+    pub(crate) fn sleep(&self, duration: Duration) -> bool {
+        self.wait(WaitStrategy::Sleep, duration)
+    }
+
+    /// Waits for `duration` with `strategy`, waking up early if the thread should
+    /// stop.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the thread should stop.
+    pub(crate) fn wait(&self, strategy: WaitStrategy, duration: Duration) -> bool {
+        strategy.wait_unless(duration, || self.is_stopped())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worker_should_be_listed_as_a_task_while_running() {
+        let (tx, rx) = mpsc::channel();
+        let worker = Worker::spawn("test", move |stop| {
+            let _ = tx.send(thread::current().name().map(str::to_owned));
+            while stop.sleep(Duration::from_millis(1)) {}
+        });
+        assert_eq!(rx.recv().unwrap().as_deref(), Some("devkit-rl-test"));
+        let running: Vec<_> = tasks().into_iter().filter(|t| t.name == "test").collect();
+        assert_eq!(running.len(), 1);
+
+        assert!(worker.shutdown(Duration::from_secs(1)).is_ok());
+        assert!(tasks().iter().all(|t| t.id != running[0].id));
+    }
+}
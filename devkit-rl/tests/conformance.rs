@@ -0,0 +1,322 @@
+//! Runs every algorithm through the same scenarios on a manual clock, and checks
+//! the invariants they all share, and those of each algorithm.
+//!
+//! Every limiter allows [`LIMIT`] requests per [`PERIOD`], in bursts of at most
+//! [`LIMIT`]. The algorithms differ in how they spread the requests over time,
+//! which [`Case::max_in`] bounds.
+
+use std::{sync::Arc, time::Duration};
+
+use devkit_rl::{
+    Error, FixedWindow, LeakyBucket, Limiter, ManualClock, RateLimiter, Refill, SlidingWindowCount,
+    SlidingWindowLog, TokenBucket,
+};
+use proptest::prelude::*;
+
+const LIMIT: u64 = 10;
+const PERIOD: Duration = Duration::from_secs(1);
+const BUCKET_COUNT: u64 = 10;
+
+/// A limiter under test, with the bound of the requests it allows over time.
+struct Case {
+    name: &'static str,
+    limiter: Limiter,
+    clock: Arc<ManualClock>,
+    /// The most requests the limiter allows at times within any span of this
+    /// length, both ends included.
+    max_in: fn(Duration) -> u64,
+}
+
+/// The number of whole `period`s in `span`.
+fn periods(span: Duration, period: Duration) -> u64 {
+    (span.as_nanos() / period.as_nanos()) as u64
+}
+
+/// Returns a limiter of every algorithm, each on its own clock.
+fn cases() -> Vec<Case> {
+    let case = |name, max_in: fn(Duration) -> u64, build: fn(Arc<ManualClock>) -> Limiter| {
+        let clock = Arc::new(ManualClock::new());
+        Case {
+            name,
+            limiter: build(clock.clone()),
+            clock,
+            max_in,
+        }
+    };
+    vec![
+        // a full bucket, then a refill per period, at a boundary of the span at
+        // worst
+        case(
+            "token bucket",
+            |span| LIMIT * (2 + periods(span, PERIOD)),
+            |clock| {
+                Limiter::TokenBucket(TokenBucket::with_clock(LIMIT, LIMIT, Some(PERIOD), clock))
+            },
+        ),
+        // a full bucket, then the tokens accrued over the span
+        case(
+            "continuous token bucket",
+            |span| LIMIT + periods(span * LIMIT as u32, PERIOD),
+            |clock| {
+                Limiter::TokenBucket(
+                    TokenBucket::with_clock(LIMIT, LIMIT, Some(PERIOD), clock)
+                        .with_refill(Refill::Continuous),
+                )
+            },
+        ),
+        case(
+            "leaky bucket meter",
+            |span| LIMIT + periods(span * LIMIT as u32, PERIOD),
+            |clock| {
+                Limiter::LeakyBucket(LeakyBucket::meter_with_clock(
+                    LIMIT,
+                    LIMIT,
+                    Some(PERIOD),
+                    clock,
+                ))
+            },
+        ),
+        // the span overlaps one more window than it covers, twice the limit at a
+        // boundary
+        case(
+            "fixed window",
+            |span| LIMIT * (2 + periods(span, PERIOD)),
+            |clock| {
+                Limiter::FixedWindow(FixedWindow::with_clock(LIMIT, Some(PERIOD), false, clock))
+            },
+        ),
+        case(
+            "smoothed fixed window",
+            |span| LIMIT * (2 + periods(span, PERIOD)),
+            |clock| Limiter::FixedWindow(FixedWindow::with_clock(LIMIT, Some(PERIOD), true, clock)),
+        ),
+        // exact: any span shorter than the window holds at most the limit
+        case(
+            "sliding window log",
+            |span| LIMIT * (1 + periods(span, PERIOD)),
+            |clock| {
+                Limiter::SlidingWindowLog(SlidingWindowLog::with_clock(LIMIT, Some(PERIOD), clock))
+            },
+        ),
+        // exact but for the last bucket, which leaves the window early
+        case(
+            "sliding window count",
+            |span| LIMIT * (1 + periods(span, PERIOD - PERIOD / BUCKET_COUNT as u32)),
+            |clock| {
+                Limiter::SlidingWindowCount(SlidingWindowCount::with_clock(
+                    LIMIT,
+                    PERIOD,
+                    BUCKET_COUNT,
+                    clock,
+                ))
+            },
+        ),
+    ]
+}
+
+/// Requests to make: how long to wait before each, and how many.
+type Trace = Vec<(Duration, u64)>;
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+/// Steady load at half the limit.
+fn steady() -> Trace {
+    (0..100).map(|_| (PERIOD / LIMIT as u32 * 2, 1)).collect()
+}
+
+/// Bursts of three times the limit, a few periods apart.
+fn bursts() -> Trace {
+    (0..5)
+        .flat_map(|_| {
+            let mut burst = vec![(PERIOD * 3, 1)];
+            burst.extend((1..LIMIT * 3).map(|_| (Duration::ZERO, 1)));
+            burst
+        })
+        .collect()
+}
+
+/// Bursts after idle gaps far longer than a period.
+fn idle_gaps() -> Trace {
+    (0..3)
+        .flat_map(|_| [(Duration::from_secs(3600), LIMIT), (ms(1), 1)])
+        .collect()
+}
+
+/// The limit just before a window boundary, and again just after it.
+fn boundary_attack() -> Trace {
+    let before = PERIOD - ms(1);
+    let mut trace = vec![(before, 1)];
+    trace.extend((1..LIMIT).map(|_| (Duration::ZERO, 1)));
+    trace.push((ms(1), 1));
+    trace.extend((1..LIMIT).map(|_| (Duration::ZERO, 1)));
+    trace
+}
+
+/// Plays `trace` against `case`, returning the time and count of the requests
+/// allowed.
+fn play(case: &Case, trace: &Trace) -> Vec<(Duration, u64)> {
+    let mut now = Duration::ZERO;
+    let mut allowed = Vec::new();
+    for &(wait, n) in trace {
+        case.clock.advance(wait);
+        now += wait;
+        if case.limiter.allow_n(n) {
+            allowed.push((now, n));
+        }
+    }
+    allowed
+}
+
+/// Checks that `allowed` stays within the bound of `case` over spans of several
+/// lengths, each starting at an allowed request.
+fn check_bounds(case: &Case, allowed: &[(Duration, u64)]) -> Result<(), String> {
+    let spans = [ms(0), ms(1), ms(500), ms(999), ms(1000), ms(1500), ms(5000)];
+    for &(start, _) in allowed {
+        for span in spans {
+            let count: u64 = allowed
+                .iter()
+                .filter(|&&(at, _)| at >= start && at <= start + span)
+                .map(|&(_, n)| n)
+                .sum();
+            let max = (case.max_in)(span);
+            if count > max {
+                return Err(format!(
+                    "{}: {count} requests allowed within {span:?} of {start:?}, at most {max}",
+                    case.name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn total(allowed: &[(Duration, u64)]) -> u64 {
+    allowed.iter().map(|&(_, n)| n).sum()
+}
+
+#[test]
+fn every_limiter_should_stay_within_its_bound_in_every_scenario() {
+    let scenarios = [
+        ("steady", steady()),
+        ("bursts", bursts()),
+        ("idle gaps", idle_gaps()),
+        ("boundary attack", boundary_attack()),
+    ];
+    for (scenario, trace) in &scenarios {
+        for case in cases() {
+            let allowed = play(&case, trace);
+            check_bounds(&case, &allowed).unwrap_or_else(|e| panic!("{scenario}: {e}"));
+        }
+    }
+}
+
+#[test]
+fn every_limiter_should_allow_load_below_its_limit() {
+    for case in cases() {
+        let trace = steady();
+        assert_eq!(
+            total(&play(&case, &trace)),
+            trace.len() as u64,
+            "{}",
+            case.name
+        );
+    }
+}
+
+#[test]
+fn every_limiter_should_allow_a_full_burst_after_an_idle_gap() {
+    for case in cases() {
+        for _ in 0..3 {
+            case.clock.advance(PERIOD * 3);
+            assert!(case.limiter.allow_n(LIMIT), "{}", case.name);
+            assert!(!case.limiter.allow(), "{}", case.name);
+        }
+    }
+}
+
+#[test]
+fn every_limiter_should_allow_once_its_estimate_has_passed() {
+    for case in cases() {
+        for _ in 0..LIMIT * 3 {
+            while case.limiter.allow() {}
+            // a smoothed window still weighs in the previous one
+            let wait = case.limiter.next_available(1);
+            assert!(
+                wait > Duration::ZERO && wait <= PERIOD * 2,
+                "{}: {wait:?}",
+                case.name
+            );
+            case.clock.advance(wait);
+            assert!(case.limiter.allow(), "{}", case.name);
+        }
+    }
+}
+
+#[test]
+fn every_limiter_should_report_requests_it_never_allows() {
+    for case in cases() {
+        assert_eq!(
+            case.limiter.next_available(LIMIT + 1),
+            Duration::MAX,
+            "{}",
+            case.name
+        );
+        assert!(
+            matches!(case.limiter.try_check(LIMIT + 1), Err(Error::TooLarge)),
+            "{}",
+            case.name
+        );
+        assert!(case.limiter.allow_n(LIMIT), "{}", case.name);
+    }
+}
+
+#[test]
+fn algorithms_should_differ_at_a_window_boundary() {
+    let allowed_of = |name: &str| {
+        let case = cases().into_iter().find(|c| c.name == name).unwrap();
+        total(&play(&case, &boundary_attack()))
+    };
+
+    // a fixed window resets at the boundary, a sliding log does not
+    assert_eq!(allowed_of("fixed window"), LIMIT * 2);
+    assert_eq!(allowed_of("sliding window log"), LIMIT);
+    assert_eq!(allowed_of("sliding window count"), LIMIT);
+    // a token bucket refills a whole period at once, a continuous one a token
+    assert_eq!(allowed_of("token bucket"), LIMIT * 2);
+    assert_eq!(allowed_of("continuous token bucket"), LIMIT);
+}
+
+proptest! {
+    #[test]
+    fn every_limiter_should_hold_its_invariants_on_random_traces(
+        steps in proptest::collection::vec((0u64..1500, 0u64..=LIMIT + 2), 1..200),
+    ) {
+        let trace: Trace = steps.into_iter().map(|(wait, n)| (ms(wait), n)).collect();
+        for case in cases() {
+            let mut allowed = Vec::new();
+            let mut now = Duration::ZERO;
+            for &(wait, n) in &trace {
+                case.clock.advance(wait);
+                now += wait;
+
+                let remaining = case.limiter.remaining();
+                prop_assert!(remaining.is_none_or(|r| r <= LIMIT), "{}", case.name);
+                let estimate = case.limiter.next_available(n);
+                let ok = case.limiter.allow_n(n);
+                // the estimate agrees with the decision
+                prop_assert_eq!(ok, estimate == Duration::ZERO, "{}", case.name);
+                prop_assert_eq!(n > LIMIT, estimate == Duration::MAX, "{}", case.name);
+                if let (Some(remaining), false) = (remaining, ok) {
+                    prop_assert!(n > remaining, "{}", case.name);
+                }
+                if ok && n > 0 {
+                    allowed.push((now, n));
+                }
+            }
+            let checked = check_bounds(&case, &allowed);
+            prop_assert!(checked.is_ok(), "{}", checked.unwrap_err());
+        }
+    }
+}
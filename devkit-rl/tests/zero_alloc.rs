@@ -0,0 +1,132 @@
+//! Checks that deciding on requests never allocates.
+//!
+//! The global allocator of this test binary counts the allocations of every
+//! thread, so that tests running in parallel do not disturb each other.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    sync::Arc,
+    time::Duration,
+};
+
+use devkit_rl::{
+    FixedWindow, Hooks, KeyedLimiter, LeakyBucket, Limiter, LimiterConfig, ManualClock, Observed,
+    RateLimiter, SlidingWindowCount, SlidingWindowLog, TokenBucket,
+};
+
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|a| a.set(a.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Returns the allocations made by `f` on the current thread.
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+/// Asserts that `limiter` allows and denies requests without allocating, while its
+/// windows go by.
+fn assert_allocation_free(limiter: &(impl RateLimiter + std::fmt::Debug), clock: &ManualClock) {
+    let allocs = allocations(|| {
+        for _ in 0..1_000 {
+            limiter.allow();
+            limiter.allow_n(3);
+            limiter.allow_cost(0.5);
+            clock.advance(Duration::from_millis(7));
+        }
+    });
+    assert_eq!(allocs, 0, "{limiter:?} allocated on the hot path");
+}
+
+#[test]
+fn allow_n_should_not_allocate() {
+    const INTERVAL: Option<Duration> = Some(Duration::from_millis(100));
+
+    let clock = Arc::new(ManualClock::new());
+    let limiters = [
+        Limiter::TokenBucket(TokenBucket::with_clock(10, 2, INTERVAL, clock.clone())),
+        Limiter::LeakyBucket(LeakyBucket::meter_with_clock(
+            10,
+            20,
+            INTERVAL,
+            clock.clone(),
+        )),
+        Limiter::FixedWindow(FixedWindow::with_clock(50, INTERVAL, false, clock.clone())),
+        Limiter::SlidingWindowLog(SlidingWindowLog::with_clock(50, INTERVAL, clock.clone())),
+        Limiter::SlidingWindowCount(SlidingWindowCount::with_clock(
+            50,
+            Duration::from_millis(100),
+            10,
+            clock.clone(),
+        )),
+    ];
+
+    for limiter in &limiters {
+        assert_allocation_free(limiter, &clock);
+        let observed = Observed::new(limiter.clone(), Arc::new(Hooks::new()));
+        assert_allocation_free(&observed, &clock);
+    }
+}
+
+#[test]
+fn keyed_limiter_should_not_allocate_for_known_keys() {
+    let limiter = KeyedLimiter::new(LimiterConfig::FixedWindow {
+        size: 10,
+        interval_ms: None,
+        smoothing: false,
+    });
+    let keys = ["10.0.0.1", "10.0.0.2", "10.0.0.3"];
+    for key in &keys {
+        limiter.allow(key);
+    }
+
+    let allocs = allocations(|| {
+        for _ in 0..1_000 {
+            for key in &keys {
+                limiter.allow_n(key, 2);
+            }
+        }
+    });
+    assert_eq!(allocs, 0);
+}
+
+#[test]
+fn keyed_limiter_should_look_up_string_keys_without_allocating() {
+    let limiter: KeyedLimiter<String> = KeyedLimiter::new(LimiterConfig::Unlimited);
+    let keys = ["tenant-a", "tenant-b"];
+    for key in keys {
+        limiter.allow(key);
+    }
+
+    let allocs = allocations(|| {
+        for _ in 0..1_000 {
+            for key in keys {
+                limiter.allow(key);
+            }
+        }
+    });
+    assert_eq!(allocs, 0);
+}
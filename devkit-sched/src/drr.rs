@@ -0,0 +1,196 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+};
+
+use crate::Scheduler;
+
+/// A deficit round robin scheduler, sharing by cost rather than by item.
+///
+/// The queues with items take turns. On its turn, a queue is credited its quantum
+/// and serves items while their cost fits in its credit; the credit left over is
+/// kept for its next turn. Over time each queue gets a share of the total cost
+/// proportional to its quantum, however large or small its items are, e.g. bytes
+/// of responses or tokens of LLM requests.
+///
+/// A queue whose next item costs more than its credit gives up its turn, so items
+/// far costlier than the quanta take many turns to be served: quanta around the
+/// typical cost of an item work best. A queue that empties loses its credit.
+///
+/// # Example
+///
+/// ```
+/// use devkit_sched::{DeficitRoundRobin, Scheduler};
+///
+/// let mut drr = DeficitRoundRobin::new(100);
+/// drr.push("bulk", "large", 100);
+/// drr.push("bulk", "large", 100);
+/// for _ in 0..4 {
+///     drr.push("interactive", "small", 25);
+/// }
+///
+/// // each turn, "interactive" serves as many small items as "bulk" serves large ones
+/// let order: Vec<_> = std::iter::from_fn(|| drr.pop()).map(|(_, item)| item).collect();
+/// assert_eq!(order, ["large", "small", "small", "small", "small", "large"]);
+/// ```
+#[derive(Debug)]
+pub struct DeficitRoundRobin<K, T> {
+    queues: HashMap<K, Queue<T>>,
+    /// The keys of the queues with items, the one taking its turn first.
+    ring: VecDeque<K>,
+    /// Whether the queue at the front of the ring was credited for its turn.
+    credited: bool,
+    quantum: u64,
+    len: usize,
+}
+
+#[derive(Debug)]
+struct Queue<T> {
+    quantum: Option<u64>,
+    deficit: u64,
+    items: VecDeque<(T, u64)>,
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self {
+            quantum: None,
+            deficit: 0,
+            items: VecDeque::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, T> DeficitRoundRobin<K, T> {
+    /// Creates a new `DeficitRoundRobin` crediting every queue `quantum` per turn,
+    /// unless set otherwise with [`DeficitRoundRobin::set_quantum`]. A quantum of 0 is
+    /// raised to 1.
+    pub fn new(quantum: u64) -> Self {
+        Self {
+            queues: HashMap::new(),
+            ring: VecDeque::new(),
+            credited: false,
+            quantum: quantum.max(1),
+            len: 0,
+        }
+    }
+
+    /// Sets the quantum of the queue `key`, e.g. twice the default quantum for a
+    /// queue that gets twice the share of the others.
+    pub fn set_quantum(&mut self, key: K, quantum: u64) {
+        self.queues.entry(key).or_default().quantum = Some(quantum.max(1));
+    }
+
+    /// Returns the quantum of the queue `key`.
+    pub fn quantum(&self, key: &K) -> u64 {
+        self.queues
+            .get(key)
+            .and_then(|queue| queue.quantum)
+            .unwrap_or(self.quantum)
+    }
+
+    /// Appends `item` costing `cost` to the queue `key`.
+    pub fn push(&mut self, key: K, item: T, cost: u64) {
+        let queue = self.queues.entry(key.clone()).or_default();
+        if queue.items.is_empty() {
+            self.ring.push_back(key);
+        }
+        queue.items.push_back((item, cost));
+        self.len += 1;
+    }
+
+    /// Returns the number of items in the queue `key`.
+    pub fn queue_len(&self, key: &K) -> usize {
+        self.queues.get(key).map_or(0, |queue| queue.items.len())
+    }
+}
+
+impl<K: Hash + Eq + Clone, T> Scheduler for DeficitRoundRobin<K, T> {
+    type Key = K;
+    type Item = T;
+
+    fn pop(&mut self) -> Option<(K, T)> {
+        loop {
+            let key = self.ring.front()?;
+            let queue = self.queues.get_mut(key)?;
+            if !self.credited {
+                let quantum = queue.quantum.unwrap_or(self.quantum);
+                queue.deficit = queue.deficit.saturating_add(quantum);
+                self.credited = true;
+            }
+
+            let cost = queue.items.front().map_or(0, |(_, cost)| *cost);
+            if cost > queue.deficit {
+                // the turn is over: the credit left is kept for the next one
+                self.ring.rotate_left(1);
+                self.credited = false;
+                continue;
+            }
+
+            queue.deficit -= cost;
+            let (item, _) = queue.items.pop_front()?;
+            self.len -= 1;
+            let key = if queue.items.is_empty() {
+                queue.deficit = 0;
+                self.credited = false;
+                self.ring.pop_front()?
+            } else {
+                key.clone()
+            };
+            return Some((key, item));
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deficit_round_robin_should_share_by_cost() {
+        let mut drr = DeficitRoundRobin::new(100);
+        drr.set_quantum("heavy", 200);
+        for _ in 0..10 {
+            drr.push("heavy", (), 100);
+            drr.push("light", (), 50);
+        }
+        assert_eq!(drr.quantum(&"heavy"), 200);
+        assert_eq!(drr.quantum(&"light"), 100);
+
+        // per turn, "heavy" serves 2 x 100 and "light" serves 2 x 50
+        let mut cost = HashMap::new();
+        for _ in 0..12 {
+            let (key, ()) = drr.pop().unwrap();
+            *cost.entry(key).or_insert(0) += if key == "heavy" { 100 } else { 50 };
+        }
+        assert_eq!(cost["heavy"], 600);
+        assert_eq!(cost["light"], 300);
+        assert_eq!(drr.len(), 8);
+    }
+
+    #[test]
+    fn deficit_round_robin_should_carry_credit_over_turns() {
+        let mut drr = DeficitRoundRobin::new(40);
+        drr.push("big", "big", 100);
+        drr.push("small", "s1", 40);
+        drr.push("small", "s2", 40);
+        drr.push("small", "s3", 40);
+
+        // "big" needs 3 turns to afford its item
+        let order: Vec<_> = std::iter::from_fn(|| drr.pop())
+            .map(|(_, item)| item)
+            .collect();
+        assert_eq!(order, ["s1", "s2", "big", "s3"]);
+        assert!(drr.is_empty());
+
+        // an emptied queue starts over without credit
+        drr.push("big", "again", 50);
+        drr.push("small", "s4", 10);
+        assert_eq!(drr.pop(), Some(("small", "s4")));
+        assert_eq!(drr.queue_len(&"big"), 1);
+    }
+}
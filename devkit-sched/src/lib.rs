@@ -0,0 +1,9 @@
+mod drr;
+mod priority;
+mod scheduler;
+mod wrr;
+
+pub use drr::DeficitRoundRobin;
+pub use priority::StrictPriority;
+pub use scheduler::Scheduler;
+pub use wrr::WeightedRoundRobin;
@@ -0,0 +1,123 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::Scheduler;
+
+/// A strict priority scheduler: the queue with the highest priority is always
+/// served first, items of the same priority in the order they were pushed.
+///
+/// A greater priority is served first, use [`std::cmp::Reverse`] for the opposite.
+/// A lower priority is only served once all the higher ones are empty, so a steady
+/// flow of urgent work starves everything else: pair it with a limiter on the
+/// urgent work, or use a [`WeightedRoundRobin`](crate::WeightedRoundRobin) to
+/// guarantee a share to every queue.
+///
+/// # Example
+///
+/// ```
+/// use devkit_sched::{Scheduler, StrictPriority};
+///
+/// let mut pending = StrictPriority::new();
+/// pending.push(0, "background");
+/// pending.push(9, "health check");
+/// pending.push(5, "request");
+///
+/// let order: Vec<_> = std::iter::from_fn(|| pending.pop()).map(|(_, item)| item).collect();
+/// assert_eq!(order, ["health check", "request", "background"]);
+/// ```
+#[derive(Debug)]
+pub struct StrictPriority<P, T> {
+    levels: BTreeMap<P, VecDeque<T>>,
+    len: usize,
+}
+
+impl<P, T> Default for StrictPriority<P, T> {
+    fn default() -> Self {
+        Self {
+            levels: BTreeMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<P: Ord + Clone, T> StrictPriority<P, T> {
+    /// Creates a new empty `StrictPriority`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `item` to the queue of `priority`.
+    pub fn push(&mut self, priority: P, item: T) {
+        self.levels.entry(priority).or_default().push_back(item);
+        self.len += 1;
+    }
+
+    /// Returns the number of items of `priority`.
+    pub fn queue_len(&self, priority: &P) -> usize {
+        self.levels.get(priority).map_or(0, VecDeque::len)
+    }
+
+    /// Returns the highest priority with items, if any.
+    pub fn peek_priority(&self) -> Option<&P> {
+        self.levels.keys().next_back()
+    }
+}
+
+impl<P: Ord + Clone, T> Scheduler for StrictPriority<P, T> {
+    type Key = P;
+    type Item = T;
+
+    fn pop(&mut self) -> Option<(P, T)> {
+        let mut level = self.levels.last_entry()?;
+        let item = level.get_mut().pop_front()?;
+        let priority = if level.get().is_empty() {
+            level.remove_entry().0
+        } else {
+            level.key().clone()
+        };
+        self.len -= 1;
+        Some((priority, item))
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Reverse;
+
+    use super::*;
+
+    #[test]
+    fn strict_priority_should_serve_highest_first_in_order() {
+        let mut pending = StrictPriority::new();
+        pending.push(1, "low 1");
+        pending.push(2, "high 1");
+        pending.push(1, "low 2");
+        pending.push(2, "high 2");
+        assert_eq!(pending.len(), 4);
+        assert_eq!(pending.peek_priority(), Some(&2));
+        assert_eq!(pending.queue_len(&1), 2);
+
+        assert_eq!(pending.pop(), Some((2, "high 1")));
+        assert_eq!(pending.pop(), Some((2, "high 2")));
+        // a higher priority pushed later still goes first
+        pending.push(3, "urgent");
+        assert_eq!(pending.pop(), Some((3, "urgent")));
+        assert_eq!(pending.pop(), Some((1, "low 1")));
+        assert_eq!(pending.pop(), Some((1, "low 2")));
+        assert_eq!(pending.pop(), None);
+        assert!(pending.is_empty());
+        assert_eq!(pending.peek_priority(), None);
+    }
+
+    #[test]
+    fn strict_priority_should_support_reverse_order() {
+        let mut pending = StrictPriority::new();
+        pending.push(Reverse(2), "second");
+        pending.push(Reverse(1), "first");
+        assert_eq!(pending.pop(), Some((Reverse(1), "first")));
+        assert_eq!(pending.pop(), Some((Reverse(2), "second")));
+    }
+}
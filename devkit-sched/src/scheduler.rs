@@ -0,0 +1,40 @@
+/// Picks the next item to serve among several queues.
+///
+/// Items are pushed into queues identified by keys, each scheduler with its own
+/// `push`, and the scheduler decides which queue is served next. Paired with a rate
+/// limiter, it decides *which* pending work to admit when tokens free up:
+///
+/// ```
+/// use devkit_sched::{Scheduler, WeightedRoundRobin};
+///
+/// let mut pending = WeightedRoundRobin::new();
+/// pending.set_weight("paid", 3);
+/// pending.push("free", "job 1");
+/// pending.push("paid", "job 2");
+///
+/// let mut tokens = 1;
+/// while !pending.is_empty() && tokens > 0 {
+///     let (tenant, job) = pending.pop().unwrap();
+///     assert_eq!((tenant, job), ("paid", "job 2"));
+///     tokens -= 1;
+/// }
+/// ```
+pub trait Scheduler {
+    /// The key identifying a queue.
+    type Key;
+    /// The items in the queues.
+    type Item;
+
+    /// Removes the next item to serve, together with the key of its queue.
+    ///
+    /// Returns `None` if all the queues are empty.
+    fn pop(&mut self) -> Option<(Self::Key, Self::Item)>;
+
+    /// Returns the number of items in all the queues.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if all the queues are empty.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+};
+
+use crate::Scheduler;
+
+/// A smooth weighted round robin scheduler.
+///
+/// A queue of weight 3 is served three times as often as a queue of weight 1, and
+/// the turns are interleaved rather than bunched: weights `a: 5, b: 1, c: 1` serve
+/// `a a b a c a a`, the sequence of nginx's upstream balancing. Only the queues with
+/// items take turns, so the share of an idle queue goes to the others.
+///
+/// Every item counts the same; see [`DeficitRoundRobin`](crate::DeficitRoundRobin)
+/// for items of different costs.
+///
+/// # Example
+///
+/// ```
+/// use devkit_sched::{Scheduler, WeightedRoundRobin};
+///
+/// let mut wrr = WeightedRoundRobin::new();
+/// wrr.set_weight("a", 2);
+/// for i in 0..3 {
+///     wrr.push("a", i);
+///     wrr.push("b", i);
+/// }
+///
+/// let keys: Vec<_> = std::iter::from_fn(|| wrr.pop()).map(|(key, _)| key).collect();
+/// assert_eq!(keys, ["a", "b", "a", "a", "b", "b"]);
+/// ```
+#[derive(Debug)]
+pub struct WeightedRoundRobin<K, T> {
+    queues: Vec<Queue<K, T>>,
+    index: HashMap<K, usize>,
+    len: usize,
+}
+
+#[derive(Debug)]
+struct Queue<K, T> {
+    key: K,
+    weight: u64,
+    /// The credit of the queue in the current cycle.
+    current: i128,
+    items: VecDeque<T>,
+}
+
+impl<K, T> Default for WeightedRoundRobin<K, T> {
+    fn default() -> Self {
+        Self {
+            queues: Vec::new(),
+            index: HashMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, T> WeightedRoundRobin<K, T> {
+    /// Creates a new `WeightedRoundRobin` without queues.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the weight of the queue `key`, creating it if needed. Queues have a
+    /// weight of 1 until set; a weight of 0 is raised to 1.
+    pub fn set_weight(&mut self, key: K, weight: u64) {
+        let i = self.queue(key);
+        self.queues[i].weight = weight.max(1);
+    }
+
+    /// Returns the weight of the queue `key`, if it exists.
+    pub fn weight(&self, key: &K) -> Option<u64> {
+        self.index.get(key).map(|&i| self.queues[i].weight)
+    }
+
+    /// Appends `item` to the queue `key`, creating it with a weight of 1 if needed.
+    pub fn push(&mut self, key: K, item: T) {
+        let i = self.queue(key);
+        self.queues[i].items.push_back(item);
+        self.len += 1;
+    }
+
+    /// Returns the number of items in the queue `key`.
+    pub fn queue_len(&self, key: &K) -> usize {
+        self.index
+            .get(key)
+            .map_or(0, |&i| self.queues[i].items.len())
+    }
+
+    /// Returns the index of the queue `key`, creating it if needed.
+    fn queue(&mut self, key: K) -> usize {
+        if let Some(&i) = self.index.get(&key) {
+            return i;
+        }
+        self.queues.push(Queue {
+            key: key.clone(),
+            weight: 1,
+            current: 0,
+            items: VecDeque::new(),
+        });
+        self.index.insert(key, self.queues.len() - 1);
+        self.queues.len() - 1
+    }
+}
+
+impl<K: Hash + Eq + Clone, T> Scheduler for WeightedRoundRobin<K, T> {
+    type Key = K;
+    type Item = T;
+
+    fn pop(&mut self) -> Option<(K, T)> {
+        // every queue with items earns its weight, the richest one is served and
+        // pays for the turn of all of them
+        let mut total = 0;
+        let mut best: Option<(usize, i128)> = None;
+        for (i, queue) in self.queues.iter_mut().enumerate() {
+            if queue.items.is_empty() {
+                continue;
+            }
+            queue.current += i128::from(queue.weight);
+            total += i128::from(queue.weight);
+            if best.is_none_or(|(_, current)| queue.current > current) {
+                best = Some((i, queue.current));
+            }
+        }
+        let queue = &mut self.queues[best?.0];
+        queue.current -= total;
+        let item = queue.items.pop_front()?;
+        if queue.items.is_empty() {
+            // an idle queue does not keep its credit for later
+            queue.current = 0;
+        }
+        self.len -= 1;
+        Some((queue.key.clone(), item))
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain<K: Hash + Eq + Clone, T>(wrr: &mut WeightedRoundRobin<K, T>) -> Vec<K> {
+        std::iter::from_fn(|| wrr.pop())
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    #[test]
+    fn weighted_round_robin_should_interleave_by_weight() {
+        let mut wrr = WeightedRoundRobin::new();
+        wrr.set_weight("a", 5);
+        for key in ["a", "b", "c"] {
+            for i in 0..5 {
+                wrr.push(key, i);
+            }
+        }
+        assert_eq!(wrr.len(), 15);
+        assert_eq!(wrr.weight(&"a"), Some(5));
+        assert_eq!(drain(&mut wrr)[..7], ["a", "a", "b", "a", "c", "a", "a"]);
+        assert!(wrr.is_empty());
+    }
+
+    #[test]
+    fn weighted_round_robin_should_skip_idle_queues() {
+        let mut wrr = WeightedRoundRobin::new();
+        wrr.set_weight("idle", 10);
+        wrr.push("busy", 1);
+        wrr.push("busy", 2);
+        assert_eq!(wrr.pop(), Some(("busy", 1)));
+
+        // an idle queue does not bank turns while it has no items
+        wrr.push("idle", 3);
+        wrr.push("busy", 4);
+        assert_eq!(wrr.queue_len(&"busy"), 2);
+        assert_eq!(drain(&mut wrr), ["idle", "busy", "busy"]);
+        assert_eq!(wrr.pop(), None);
+    }
+}
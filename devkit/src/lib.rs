@@ -0,0 +1,97 @@
+//! The devkit toolbox in one crate.
+//!
+//! Every subsystem is re-exported as a module behind the cargo feature of the
+//! same name, so an application depends on `devkit` alone and enables the pieces
+//! it uses. `rl`, `retry` and `cb` are enabled by default, `full` enables them
+//! all. The `algorithms` and `tokio` features are forwarded to the subsystems
+//! enabled, and `tower` enables the tower retry policy of `retry`.
+//!
+//! | feature    | crate             |
+//! |------------|-------------------|
+//! | `batch`    | `devkit-batch`    |
+//! | `bloom`    | `devkit-bloom`    |
+//! | `cache`    | `devkit-cache`    |
+//! | `cb`       | `devkit-cb`       |
+//! | `chash`    | `devkit-chash`    |
+//! | `debounce` | `devkit-debounce` |
+//! | `hedge`    | `devkit-hedge`    |
+//! | `id`       | `devkit-id`       |
+//! | `retry`    | `devkit-backoff`  |
+//! | `rl`       | `devkit-rl`       |
+//! | `sched`    | `devkit-sched`    |
+//!
+//! The [`prelude`] brings the main types and traits of the enabled subsystems
+//! into scope.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use devkit::prelude::*;
+//!
+//! let limiter = TokenBucket::new(10, 10, None);
+//! let breaker = CircuitBreaker::new(BreakerPolicy::default());
+//! let retry = Retry::new(ConstantBackoff::new(Duration::from_millis(1))).with_max_attempts(3);
+//!
+//! let result = retry.run(|| {
+//!     if !limiter.allow() {
+//!         return Err("rate limited");
+//!     }
+//!     let permit = breaker.try_acquire().ok_or("circuit open")?;
+//!     permit.success();
+//!     Ok(())
+//! });
+//! assert!(result.is_ok());
+//! ```
+
+#[cfg(feature = "retry")]
+pub use devkit_backoff as retry;
+#[cfg(feature = "batch")]
+pub use devkit_batch as batch;
+#[cfg(feature = "bloom")]
+pub use devkit_bloom as bloom;
+#[cfg(feature = "cache")]
+pub use devkit_cache as cache;
+#[cfg(feature = "cb")]
+pub use devkit_cb as cb;
+#[cfg(feature = "chash")]
+pub use devkit_chash as chash;
+#[cfg(feature = "debounce")]
+pub use devkit_debounce as debounce;
+#[cfg(feature = "hedge")]
+pub use devkit_hedge as hedge;
+#[cfg(feature = "id")]
+pub use devkit_id as id;
+#[cfg(feature = "rl")]
+pub use devkit_rl as rl;
+#[cfg(feature = "sched")]
+pub use devkit_sched as sched;
+
+/// The main types and traits of the enabled subsystems, to glob import.
+///
+/// The error types, whose names clash between subsystems, are left to their
+/// modules.
+pub mod prelude {
+    #[cfg(feature = "batch")]
+    pub use crate::batch::Batcher;
+    #[cfg(feature = "bloom")]
+    pub use crate::bloom::{BloomFilter, CountingBloomFilter, ScalableBloomFilter};
+    #[cfg(feature = "cache")]
+    pub use crate::cache::{Cache, CachedSingleFlight, LoadingCache, SingleFlight};
+    #[cfg(feature = "cb")]
+    pub use crate::cb::{BreakerPolicy, CallPermit, CircuitBreaker};
+    #[cfg(feature = "chash")]
+    pub use crate::chash::{HashRing, JumpHash};
+    #[cfg(feature = "debounce")]
+    pub use crate::debounce::{Debouncer, Throttler};
+    #[cfg(feature = "hedge")]
+    pub use crate::hedge::{HedgePolicy, Hedger};
+    #[cfg(feature = "id")]
+    pub use crate::id::{Ulid, UlidGenerator, UuidV7, UuidV7Generator};
+    #[cfg(feature = "retry")]
+    pub use crate::retry::{ConstantBackoff, ExponentialBackoff, Jitter, Retry, RetryError};
+    #[cfg(feature = "rl")]
+    pub use crate::rl::{KeyedLimiter, Limiter, Quota, RateLimiter, TokenBucket};
+    #[cfg(feature = "sched")]
+    pub use crate::sched::Scheduler;
+}